@@ -0,0 +1,505 @@
+//! Admin interface served over a Unix socket, letting operators inspect and manage a running
+//! server. Clients send a single line containing a command and its arguments, and the server
+//! responds with plain text before closing the connection. `watch` and `interact` instead keep
+//! the connection open, streaming the session until either side goes away.
+
+use std::{
+    fmt::Write as _,
+    fs::{DirBuilder, Permissions},
+    io::BufRead,
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use time::format_description::well_known::Rfc3339;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{unix::OwnedWriteHalf, UnixListener, UnixStream},
+    sync::broadcast::error::RecvError,
+};
+use tracing::{debug, info};
+
+use crate::{
+    config::Config,
+    state::{ActiveConnection, State, TakeoverGuard, TapEvent},
+};
+
+/// Longest command line we'll read from a client.
+const MAX_LINE_LENGTH: u64 = 4096;
+
+const HELP: &str = "\
+commands:
+  list           list open connections
+  show <id>      show details of a connection
+  kill <id>      close a connection
+  watch <id>     stream a connection's terminal as the client sees it
+  interact <id>  take over a connection's shell, sending your input to the client
+  config         dump the running config, with secrets redacted
+  stats          show how many audit logs are queued, dropped or spilled to disk
+  help           show this message
+
+connection ids can be shortened to any unique prefix
+";
+
+/// Accepts connections on the admin socket at `path`, replacing any socket left behind by a
+/// previous run.
+pub async fn serve(path: &Path, state: Arc<State>) -> anyhow::Result<()> {
+    let listener = bind(path)?;
+
+    info!(path = %path.display(), "Admin interface listening");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = handle(stream, &state).await {
+                debug!(%error, "Admin connection closed with error");
+            }
+        });
+    }
+}
+
+/// Binds the admin socket inside a directory only we can get into, moving it to `path` once
+/// its permissions have been tightened. Binding at `path` directly would leave the socket open
+/// to anyone the umask lets in until it's been chmod'ed.
+fn bind(path: &Path) -> anyhow::Result<UnixListener> {
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(format!(".{}", uuid::Uuid::new_v4()));
+    let staging = PathBuf::from(staging);
+
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .context("failed to create directory for admin socket")?;
+
+    let socket = staging.join("admin.sock");
+    let res = UnixListener::bind(&socket)
+        .context("failed to bind admin socket")
+        .and_then(|listener| {
+            // anyone able to connect can kill sessions and read the config
+            std::fs::set_permissions(&socket, Permissions::from_mode(0o600))?;
+            // replaces any socket left behind by a previous run
+            std::fs::rename(&socket, path).context("failed to move admin socket into place")?;
+            Ok(listener)
+        });
+
+    let _res = std::fs::remove_dir_all(&staging);
+
+    res
+}
+
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    state: &State,
+) -> Result<(), std::io::Error> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
+    let mut line = String::new();
+    (&mut reader)
+        .take(MAX_LINE_LENGTH)
+        .read_line(&mut line)
+        .await?;
+
+    match run_command(&line, state) {
+        Ok(Response::Text(response)) => writer.write_all(response.as_bytes()).await?,
+        Ok(Response::Watch(connection)) => {
+            watch(connection, None, &mut reader, &mut writer).await?;
+        }
+        Ok(Response::Interact(connection, takeover)) => {
+            watch(connection, Some(takeover), &mut reader, &mut writer).await?;
+        }
+        Err(error) => {
+            writer
+                .write_all(format!("error: {error}\n").as_bytes())
+                .await?
+        }
+    }
+
+    writer.shutdown().await
+}
+
+enum Response {
+    Text(String),
+    /// Stream the connection's terminal traffic until either side goes away.
+    Watch(Arc<ActiveConnection>),
+    /// As with `Watch`, but with the operator's input being sent to the client.
+    Interact(Arc<ActiveConnection>, TakeoverGuard),
+}
+
+fn run_command(line: &str, state: &State) -> anyhow::Result<Response> {
+    let mut args = line.split_whitespace();
+    let find = |id: &str| state.connections.find(id).map_err(|e| anyhow!(e));
+
+    let response = match (args.next(), args.next()) {
+        (Some("list"), None) => list(&state.connections.list()),
+        (Some("show"), Some(id)) => show(&find(id)?),
+        (Some("kill"), Some(id)) => {
+            let connection = find(id)?;
+            connection.kill.notify_one();
+            format!("killed {}\n", connection.id)
+        }
+        (Some("watch"), Some(id)) => return Ok(Response::Watch(find(id)?)),
+        (Some("interact"), Some(id)) => {
+            let connection = find(id)?;
+            let takeover = connection.takeover.begin().map_err(|e| anyhow!(e))?;
+            return Ok(Response::Interact(connection, takeover));
+        }
+        (Some("config"), None) => {
+            let mut out = serde_json::to_string_pretty(&*state.settings.load().config)?;
+            out.push('\n');
+            out
+        }
+        (Some("stats"), None) => stats(state),
+        (Some("help") | None, _) => HELP.to_string(),
+        (Some(command), _) => return Err(anyhow!("unknown command {command:?}, try `help`")),
+    };
+
+    Ok(Response::Text(response))
+}
+
+/// Copies the connection's terminal traffic to the operator as it happens. If the operator has
+/// taken over the session, anything they send is passed on to the client.
+async fn watch<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    connection: Arc<ActiveConnection>,
+    takeover: Option<TakeoverGuard>,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<(), std::io::Error> {
+    let mut events = connection.tap.subscribe();
+    let id = connection.id;
+
+    // we'd otherwise keep the tap open after the connection closes
+    drop(connection);
+
+    let header = if takeover.is_some() {
+        format!("interacting with {id}, the shell won't respond until you press ctrl-c\r\n")
+    } else {
+        format!("watching {id}, press ctrl-c to stop\r\n")
+    };
+    writer.write_all(header.as_bytes()).await?;
+    writer.flush().await?;
+
+    let mut buf = [0; 1024];
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                match event {
+                    Ok(TapEvent::Input(data) | TapEvent::Output(data)) => {
+                        writer.write_all(&data).await?;
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        writer
+                            .write_all(format!("\r\n[skipped {skipped} messages]\r\n").as_bytes())
+                            .await?;
+                    }
+                    Err(RecvError::Closed) => {
+                        writer.write_all(b"\r\n[connection closed]\r\n").await?;
+                        return Ok(());
+                    }
+                }
+
+                writer.flush().await?;
+            }
+            read = reader.read(&mut buf) => match (read, &takeover) {
+                // the operator has gone away
+                (Ok(0) | Err(_), _) => return Ok(()),
+                (Ok(n), Some(takeover)) => {
+                    if !takeover.send(&buf[..n]).await {
+                        writer.write_all(b"\r\n[connection closed]\r\n").await?;
+                        return Ok(());
+                    }
+                }
+                (Ok(_), None) => {}
+            },
+        }
+    }
+}
+
+fn list(connections: &[Arc<ActiveConnection>]) -> String {
+    let mut out = format!(
+        "{:<36}  {:<21}  {:<12}  {:>8}  {:>8}  {:>6}  CLIENT\n",
+        "ID", "PEER", "USER", "AGE", "IDLE", "EVENTS"
+    );
+
+    for connection in connections {
+        let stats = connection.stats.lock().clone();
+
+        writeln!(
+            out,
+            "{:<36}  {:<21}  {:<12}  {:>8}  {:>8}  {:>6}  {}",
+            connection.id,
+            connection.peer_address,
+            stats.username.as_deref().unwrap_or("-"),
+            format_duration(connection.start.elapsed()),
+            format_duration(stats.last_activity.elapsed()),
+            stats.events,
+            connection.handshake.get().map_or("-", |v| &*v.version),
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+fn show(connection: &ActiveConnection) -> String {
+    let stats = connection.stats.lock().clone();
+    let handshake = connection.handshake.get();
+
+    let mut out = String::new();
+    let mut field = |name: &str, value: &dyn std::fmt::Display| {
+        writeln!(out, "{:<12} {value}", format!("{name}:")).unwrap();
+    };
+
+    field("id", &connection.id);
+    field("peer", &connection.peer_address);
+    field(
+        "started",
+        &connection.started_at.format(&Rfc3339).unwrap_or_default(),
+    );
+    field("age", &format_duration(connection.start.elapsed()));
+    field("idle", &format_duration(stats.last_activity.elapsed()));
+    field("username", &stats.username.as_deref().unwrap_or("-"));
+    field("events", &stats.events);
+    field("client", &handshake.map_or("-", |v| &*v.version));
+    field("hassh", &handshake.map_or("-", |v| &*v.hassh));
+
+    out
+}
+
+fn stats(state: &State) -> String {
+    let mut out = String::new();
+    let mut field = |name: &str, value: &dyn std::fmt::Display| {
+        writeln!(out, "{:<12} {value}", format!("{name}:")).unwrap();
+    };
+
+    field("connections", &state.connections.list().len());
+    field("queued", &state.audit.depth());
+    field("dropped", &state.audit.dropped());
+    field("spilled", &state.audit.spilled());
+
+    out
+}
+
+/// Formats a duration as a compact `1h02m`, `3m04s` or `12s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m{s:02}s"),
+        (h, m, _) => format!("{h}h{m:02}m"),
+    }
+}
+
+/// Sends `args` as a command to the admin socket of a running server, copying the response
+/// to stdout. For `interact`, stdin is sent along too.
+pub async fn ctl(config: &Config, args: &[String]) -> anyhow::Result<()> {
+    let path = config
+        .admin_socket
+        .as_deref()
+        .ok_or_else(|| anyhow!("admin-socket isn't set in the config"))?;
+
+    let stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("failed to connect to {}", path.display()))?;
+    let (mut stream, mut writer) = stream.into_split();
+
+    writer
+        .write_all(format!("{}\n", args.join(" ")).as_bytes())
+        .await?;
+
+    // the write half has to stay open, closing it tells the server we've gone away
+    let _writer = if args.first().map_or(false, |v| v == "interact") {
+        tokio::spawn(forward_stdin(writer));
+        None
+    } else {
+        Some(writer)
+    };
+
+    // responses are streamed so `watch` shows output as it happens
+    let mut stdout = tokio::io::stdout();
+    let mut buf = vec![0; 8192];
+    let mut first = true;
+
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+
+        stdout.write_all(&buf[..n]).await?;
+        stdout.flush().await?;
+
+        if std::mem::take(&mut first) && buf[..n].starts_with(b"error: ") {
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Copies lines typed by the operator to the admin socket. Stdin is read on a thread of its own,
+/// as tokio's stdin would hold up the runtime shutting down until the operator pressed enter.
+async fn forward_stdin(mut writer: OwnedWriteHalf) {
+    let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
+
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut line = String::new();
+
+        while stdin.read_line(&mut line).map_or(false, |n| n > 0)
+            && send.send(std::mem::take(&mut line)).is_ok()
+        {}
+    });
+
+    while let Some(line) = recv.recv().await {
+        if writer.write_all(line.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        os::unix::fs::{FileTypeExt, PermissionsExt},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use test_case::test_case;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixStream,
+    };
+
+    use super::{bind, format_duration, handle};
+    use crate::state::{ActiveConnection, State};
+
+    #[test_case(12, "12s"; "seconds")]
+    #[test_case(184, "3m04s"; "minutes")]
+    #[test_case(3725, "1h02m"; "hours")]
+    fn formats_duration(secs: u64, expected: &str) {
+        assert_eq!(format_duration(Duration::from_secs(secs)), expected);
+    }
+
+    #[tokio::test]
+    async fn binds_socket_privately() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-admin-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("admin.sock");
+
+        // left behind by a previous run
+        std::fs::write(&path, b"").unwrap();
+
+        let _listener = bind(&path).unwrap();
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        UnixStream::connect(&path).await.unwrap();
+
+        // the staging directory is cleaned up
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    async fn request(state: &State, command: &str) -> String {
+        let (mut client, server) = tokio::io::duplex(4096);
+        client.write_all(command.as_bytes()).await.unwrap();
+        handle(server, state).await.unwrap();
+
+        let mut out = String::new();
+        client.read_to_string(&mut out).await.unwrap();
+        out
+    }
+
+    #[tokio::test]
+    async fn shows_stats() {
+        let state = State::default();
+        state.audit.push(crate::audit::AuditLog::default());
+
+        assert_eq!(
+            request(&state, "stats\n").await,
+            "connections: 0\nqueued:      1\ndropped:     0\nspilled:     0\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn kills_connection() {
+        let state = State::default();
+        let id = uuid::Uuid::from_bytes([1; 16]);
+        let connection = Arc::new(ActiveConnection::new(
+            id,
+            ([127, 0, 0, 1], 1234).into(),
+            time::OffsetDateTime::now_utc(),
+            std::time::Instant::now(),
+            Arc::default(),
+        ));
+        state.connections.insert(connection.clone());
+
+        let list = request(&state, "list\n").await;
+        assert!(list.contains(&format!("{id}  127.0.0.1:1234")), "{list}");
+
+        assert_eq!(
+            request(&state, "interact 0101\n").await,
+            "error: connection doesn't have an interactive shell\n"
+        );
+        assert_eq!(
+            request(&state, "kill 0202\n").await,
+            "error: no open connection matches 0202\n"
+        );
+        assert_eq!(
+            request(&state, "kill 0101\n").await,
+            format!("killed {id}\n")
+        );
+
+        // the permit is stored until the connection's task gets around to waiting on it
+        tokio::time::timeout(Duration::from_secs(1), connection.kill.notified())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn watches_connection() {
+        let state = Arc::new(State::default());
+        let id = uuid::Uuid::from_bytes([1; 16]);
+        let connection = Arc::new(ActiveConnection::new(
+            id,
+            ([127, 0, 0, 1], 1234).into(),
+            time::OffsetDateTime::now_utc(),
+            std::time::Instant::now(),
+            Arc::default(),
+        ));
+        state.connections.insert(connection.clone());
+
+        let (client, server) = tokio::io::duplex(4096);
+        let (mut reader, mut writer) = tokio::io::split(client);
+        writer.write_all(b"watch 0101\n").await.unwrap();
+
+        let handle = tokio::spawn({
+            let state = state.clone();
+            async move { handle(server, &state).await }
+        });
+
+        let mut header = vec![0; format!("watching {id}, press ctrl-c to stop\r\n").len()];
+        reader.read_exact(&mut header).await.unwrap();
+
+        connection.tap.output(b"$ ");
+        connection.tap.input(b"hunter2");
+        state.connections.remove(&id);
+        drop(connection);
+
+        let mut out = String::new();
+        reader.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "$ hunter2\r\n[connection closed]\r\n");
+
+        handle.await.unwrap().unwrap();
+    }
+}
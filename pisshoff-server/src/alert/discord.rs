@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::{
+    alert::{http_client, post_json, truncate, Alert, Notifier},
+    config::DiscordNotifierConfig,
+};
+
+/// Longest message Discord will accept.
+const MAX_CONTENT_LENGTH: usize = 2000;
+
+/// Posts alerts to a Discord channel through a [webhook].
+///
+/// [webhook]: https://discord.com/developers/docs/resources/webhook#execute-webhook
+pub struct DiscordNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl DiscordNotifier {
+    pub fn new(config: &DiscordNotifierConfig) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            client: http_client()?,
+            webhook_url: config.webhook_url.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    async fn notify(&self, alert: &Alert) -> Result<(), std::io::Error> {
+        let body = json!({
+            "content": truncate(alert.markdown("**"), MAX_CONTENT_LENGTH),
+            // the details are client-controlled, so mustn't be able to ping anyone
+            "allowed_mentions": { "parse": [] },
+        });
+
+        post_json(&self.client, &self.webhook_url, &body).await
+    }
+}
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::{
+    alert::{http_client, post_json, Alert, Notifier},
+    config::SlackNotifierConfig,
+};
+
+/// Posts alerts to a Slack channel through an [incoming webhook].
+///
+/// [incoming webhook]: https://api.slack.com/messaging/webhooks
+pub struct SlackNotifier {
+    client: reqwest::Client,
+    webhook_url: String,
+}
+
+impl SlackNotifier {
+    pub fn new(config: &SlackNotifierConfig) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            client: http_client()?,
+            webhook_url: config.webhook_url.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, alert: &Alert) -> Result<(), std::io::Error> {
+        let body = json!({
+            "text": escape(&alert.markdown("*")),
+            "unfurl_links": false,
+            "unfurl_media": false,
+        });
+
+        post_json(&self.client, &self.webhook_url, &body).await
+    }
+}
+
+/// Escapes the characters Slack treats as control characters, so clients can't smuggle
+/// mentions or links into the alert.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::escape;
+
+    #[test]
+    fn escapes_control_characters() {
+        assert_eq!(
+            escape("<!channel> a && b"),
+            "&lt;!channel&gt; a &amp;&amp; b"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::{
+    alert::{http_client, post_json, truncate, Alert, Notifier},
+    config::TelegramNotifierConfig,
+};
+
+/// Longest message Telegram will accept.
+const MAX_TEXT_LENGTH: usize = 4096;
+
+/// Sends alerts to a Telegram chat using the [bot API].
+///
+/// [bot API]: https://core.telegram.org/bots/api#sendmessage
+pub struct TelegramNotifier {
+    client: reqwest::Client,
+    url: String,
+    chat_id: String,
+}
+
+impl TelegramNotifier {
+    pub fn new(config: &TelegramNotifierConfig) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            client: http_client()?,
+            url: format!(
+                "https://api.telegram.org/bot{}/sendMessage",
+                config.bot_token
+            ),
+            chat_id: config.chat_id.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    async fn notify(&self, alert: &Alert) -> Result<(), std::io::Error> {
+        // sent as plain text, Telegram's Markdown is picky enough about escaping that any
+        // client-controlled details would likely get the message rejected
+        let body = json!({
+            "chat_id": self.chat_id,
+            "text": truncate(alert.summary(), MAX_TEXT_LENGTH),
+            "disable_web_page_preview": true,
+        });
+
+        post_json(&self.client, &self.url, &body).await
+    }
+}
@@ -0,0 +1,28 @@
+use async_trait::async_trait;
+
+use crate::{
+    alert::{http_client, post_json, Alert, Notifier},
+    config::WebhookNotifierConfig,
+};
+
+/// POSTs each alert as JSON to a configured URL.
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookNotifierConfig) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            client: http_client()?,
+            url: config.url.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, alert: &Alert) -> Result<(), std::io::Error> {
+        post_json(&self.client, &self.url, alert).await
+    }
+}
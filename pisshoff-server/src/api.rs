@@ -0,0 +1,559 @@
+//! Read-only HTTP API over the database written by the SQLite sink, so external tooling and
+//! dashboards can query sessions, credentials and payloads rather than grepping audit logs.
+//! Clients authenticate with one of the configured tokens as a bearer token.
+//!
+//! - `GET /api/sessions` lists sessions, most recent first, narrowed down by `sensor`, `tag`
+//!   or the peer's `ip`
+//! - `GET /api/sessions/{id}` returns a session along with its events
+//! - `GET /api/credentials` lists credentials by how often they were tried, narrowed down by
+//!   `username`
+//! - `GET /api/payloads` lists quarantined payloads, most recently seen first
+//!
+//! Lists are paged through with `limit` and `offset`, and can be limited to a time range with
+//! `since` and `until` as RFC 3339 timestamps.
+
+use std::{
+    collections::HashMap, convert::Infallible, path::Path, str::FromStr, sync::Arc, time::Duration,
+};
+
+use hyper::{
+    header::{self, HeaderValue},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::{audit::sqlite::format_timestamp, config::Config};
+
+/// Rows returned by a list if `limit` isn't given.
+const DEFAULT_LIMIT: u32 = 100;
+
+/// Most rows a list returns, however many are asked for.
+const MAX_LIMIT: u32 = 1000;
+
+/// Columns selected for each session, ending with its tags as a JSON array.
+const SESSION_COLUMNS: &str = "id, started_at, ended_at, duration_ms, peer_address, host, \
+     client_version, hassh, country, city, asn, org, reverse_dns, sensor, \
+     (SELECT json_group_array(tag) FROM tag WHERE tag.connection_id = connection.id)";
+
+/// Query parameters of a request.
+type Query = HashMap<String, String>;
+
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not found")
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(error: rusqlite::Error) -> Self {
+        // the details stay in our own logs
+        warn!(%error, "API query failed");
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to query database",
+        )
+    }
+}
+
+/// Serves the API on `listener` until the server shuts down, picking up changes to the tokens
+/// and database from `config`.
+pub async fn serve(
+    listener: std::net::TcpListener,
+    config: watch::Receiver<Arc<Config>>,
+) -> anyhow::Result<()> {
+    info!(address = %listener.local_addr()?, "API listening");
+
+    let make_service = make_service_fn(move |_| {
+        let config = config.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let config = config.borrow().clone();
+                async move { Ok::<_, Infallible>(respond(&request, &config).await) }
+            }))
+        }
+    });
+
+    hyper::Server::from_tcp(listener)?
+        .serve(make_service)
+        .await?;
+
+    Ok(())
+}
+
+async fn respond(request: &Request<Body>, config: &Config) -> Response<Body> {
+    match handle(request, config).await {
+        Ok(body) => json_response(StatusCode::OK, &body),
+        Err(error) => {
+            let mut response = json_response(error.status, &json!({ "error": error.message }));
+
+            if error.status == StatusCode::UNAUTHORIZED {
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            }
+
+            response
+        }
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Body> {
+    let mut body = serde_json::to_vec(body).unwrap_or_default();
+    body.push(b'\n');
+
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap_or_default()
+}
+
+async fn handle(request: &Request<Body>, config: &Config) -> Result<Value, ApiError> {
+    let Some(api) = &config.api else {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the API has been disabled",
+        ));
+    };
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    if !token.is_some_and(|token| api.authenticate(token)) {
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "missing or invalid token",
+        ));
+    }
+
+    if request.method() != Method::GET {
+        return Err(ApiError::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "only GET is supported",
+        ));
+    }
+
+    let path = request.uri().path().to_string();
+    let query = url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect::<Query>();
+    let database = api.database.clone();
+
+    // rusqlite is blocking, and queries over a large database can take a while
+    tokio::task::spawn_blocking(move || route(&open(&database)?, &path, &query))
+        .await
+        .map_err(|_| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "query panicked"))?
+}
+
+fn open(path: &Path) -> Result<Connection, ApiError> {
+    let connection = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|error| {
+        warn!(%error, path = %path.display(), "Failed to open database for the API");
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "database isn't available")
+    })?;
+
+    // wait for the sink to finish writing rather than failing
+    connection.busy_timeout(Duration::from_secs(5))?;
+
+    Ok(connection)
+}
+
+fn route(connection: &Connection, path: &str, query: &Query) -> Result<Value, ApiError> {
+    let Some(path) = path.trim_end_matches('/').strip_prefix("/api/") else {
+        return Err(ApiError::not_found());
+    };
+
+    match path {
+        "sessions" => sessions(connection, query),
+        "credentials" => credentials(connection, query),
+        "payloads" => payloads(connection, query),
+        _ => match path.strip_prefix("sessions/") {
+            Some(id) if !id.contains('/') => session(connection, id),
+            _ => Err(ApiError::not_found()),
+        },
+    }
+}
+
+/// Paging and time range shared by each list.
+struct Page {
+    since: Option<String>,
+    until: Option<String>,
+    limit: u32,
+    offset: u32,
+}
+
+impl Page {
+    fn from_query(query: &Query) -> Result<Self, ApiError> {
+        Ok(Self {
+            since: timestamp(query, "since")?,
+            until: timestamp(query, "until")?,
+            limit: number(query, "limit")?
+                .unwrap_or(DEFAULT_LIMIT)
+                .min(MAX_LIMIT),
+            offset: number(query, "offset")?.unwrap_or(0),
+        })
+    }
+}
+
+fn number<T: FromStr>(query: &Query, name: &str) -> Result<Option<T>, ApiError> {
+    query
+        .get(name)
+        .map(|v| {
+            v.parse()
+                .map_err(|_| ApiError::bad_request(format!("{name} must be a positive number")))
+        })
+        .transpose()
+}
+
+/// Parses an RFC 3339 timestamp into the format timestamps are stored in, so they compare
+/// correctly.
+fn timestamp(query: &Query, name: &str) -> Result<Option<String>, ApiError> {
+    query
+        .get(name)
+        .map(|v| {
+            OffsetDateTime::parse(v, &Rfc3339)
+                .map(format_timestamp)
+                .map_err(|_| ApiError::bad_request(format!("{name} must be an RFC 3339 timestamp")))
+        })
+        .transpose()
+}
+
+/// Timestamps are stored in UTC the way SQLite's date functions format them, which only needs
+/// a `T` and `Z` to be RFC 3339.
+fn to_rfc3339(ts: Option<String>) -> Option<String> {
+    ts.map(|v| format!("{}Z", v.replacen(' ', "T", 1)))
+}
+
+fn session_row(row: &Row<'_>) -> rusqlite::Result<Value> {
+    let tags: String = row.get(14)?;
+
+    Ok(json!({
+        "id": row.get::<_, String>(0)?,
+        "started_at": to_rfc3339(row.get(1)?),
+        "ended_at": to_rfc3339(row.get(2)?),
+        "duration_ms": row.get::<_, Option<i64>>(3)?,
+        "peer_address": row.get::<_, Option<String>>(4)?,
+        "host": row.get::<_, String>(5)?,
+        "client_version": row.get::<_, Option<String>>(6)?,
+        "hassh": row.get::<_, Option<String>>(7)?,
+        "country": row.get::<_, Option<String>>(8)?,
+        "city": row.get::<_, Option<String>>(9)?,
+        "asn": row.get::<_, Option<i64>>(10)?,
+        "org": row.get::<_, Option<String>>(11)?,
+        "reverse_dns": row.get::<_, Option<String>>(12)?,
+        "sensor": row.get::<_, Option<String>>(13)?,
+        "tags": serde_json::from_str::<Value>(&tags).unwrap_or_default(),
+    }))
+}
+
+fn sessions(connection: &Connection, query: &Query) -> Result<Value, ApiError> {
+    let page = Page::from_query(query)?;
+
+    let mut statement = connection.prepare(&format!(
+        "SELECT {SESSION_COLUMNS} FROM connection \
+         WHERE (?1 IS NULL OR started_at >= ?1) AND (?2 IS NULL OR started_at < ?2) \
+         AND (?3 IS NULL OR sensor = ?3) \
+         AND (?4 IS NULL OR id IN (SELECT connection_id FROM tag WHERE tag = ?4)) \
+         AND (?5 IS NULL OR peer_address LIKE ?5 || ':%' OR peer_address LIKE '[' || ?5 || ']:%') \
+         ORDER BY started_at DESC LIMIT ?6 OFFSET ?7"
+    ))?;
+
+    let sessions = statement
+        .query_map(
+            params![
+                page.since,
+                page.until,
+                query.get("sensor"),
+                query.get("tag"),
+                query.get("ip"),
+                page.limit,
+                page.offset,
+            ],
+            session_row,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Value::Array(sessions))
+}
+
+fn session(connection: &Connection, id: &str) -> Result<Value, ApiError> {
+    let Some(mut session) = connection
+        .query_row(
+            &format!("SELECT {SESSION_COLUMNS} FROM connection WHERE id = ?1"),
+            [id],
+            session_row,
+        )
+        .optional()?
+    else {
+        return Err(ApiError::not_found());
+    };
+
+    let mut statement = connection
+        .prepare("SELECT ts, offset_ms, data FROM event WHERE connection_id = ?1 ORDER BY id")?;
+
+    let events = statement
+        .query_map([id], |row| {
+            let data: String = row.get(2)?;
+
+            Ok(json!({
+                "ts": to_rfc3339(row.get(0)?),
+                "offset_ms": row.get::<_, i64>(1)?,
+                "action": serde_json::from_str::<Value>(&data).unwrap_or_default(),
+            }))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    session["events"] = Value::Array(events);
+
+    Ok(session)
+}
+
+fn credentials(connection: &Connection, query: &Query) -> Result<Value, ApiError> {
+    let page = Page::from_query(query)?;
+
+    let mut statement = connection.prepare(
+        "SELECT method, username, password, key_kind, key_fingerprint, COUNT(*) AS attempts, \
+         COUNT(DISTINCT connection_id), MIN(ts), MAX(ts) FROM credential \
+         WHERE (?1 IS NULL OR ts >= ?1) AND (?2 IS NULL OR ts < ?2) \
+         AND (?3 IS NULL OR username = ?3) \
+         GROUP BY method, username, password, key_kind, key_fingerprint \
+         ORDER BY attempts DESC, MAX(ts) DESC LIMIT ?4 OFFSET ?5",
+    )?;
+
+    let credentials = statement
+        .query_map(
+            params![
+                page.since,
+                page.until,
+                query.get("username"),
+                page.limit,
+                page.offset,
+            ],
+            |row| {
+                Ok(json!({
+                    "method": row.get::<_, String>(0)?,
+                    "username": row.get::<_, Option<String>>(1)?,
+                    "password": row.get::<_, Option<String>>(2)?,
+                    "key_kind": row.get::<_, Option<String>>(3)?,
+                    "key_fingerprint": row.get::<_, Option<String>>(4)?,
+                    "attempts": row.get::<_, i64>(5)?,
+                    "sessions": row.get::<_, i64>(6)?,
+                    "first_seen": to_rfc3339(row.get(7)?),
+                    "last_seen": to_rfc3339(row.get(8)?),
+                }))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Value::Array(credentials))
+}
+
+fn payloads(connection: &Connection, query: &Query) -> Result<Value, ApiError> {
+    let page = Page::from_query(query)?;
+
+    let mut statement = connection.prepare(
+        "SELECT json_extract(data, '$.sha256') AS sha256, MAX(json_extract(data, '$.size')), \
+         json_group_array(DISTINCT json_extract(data, '$.tool')), COUNT(DISTINCT connection_id), \
+         MIN(ts), MAX(ts) AS last_seen, MAX(json_extract(data, '$.virustotal.malicious')) \
+         FROM event WHERE type = 'quarantined' \
+         AND (?1 IS NULL OR ts >= ?1) AND (?2 IS NULL OR ts < ?2) \
+         GROUP BY sha256 ORDER BY last_seen DESC LIMIT ?3 OFFSET ?4",
+    )?;
+
+    let payloads = statement
+        .query_map(
+            params![page.since, page.until, page.limit, page.offset],
+            |row| {
+                let tools: String = row.get(2)?;
+
+                Ok(json!({
+                    "sha256": row.get::<_, String>(0)?,
+                    "size": row.get::<_, Option<i64>>(1)?,
+                    "tools": serde_json::from_str::<Value>(&tools).unwrap_or_default(),
+                    "sessions": row.get::<_, i64>(3)?,
+                    "first_seen": to_rfc3339(row.get(4)?),
+                    "last_seen": to_rfc3339(row.get(5)?),
+                    // missing if the payload was never looked up on VirusTotal
+                    "malicious": row.get::<_, Option<i64>>(6)?,
+                }))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Value::Array(payloads))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use hyper::{header, Body, Request, StatusCode};
+
+    use crate::{
+        api::{handle, open, route, Query},
+        audit::{
+            sqlite::SqliteSink, AuditLog, AuditLogAction, AuditSink, LoginAttemptEvent,
+            QuarantinedEvent,
+        },
+        config::{ApiConfig, Config, SqliteSinkConfig},
+    };
+
+    async fn database() -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pisshoff-api-{}.db", uuid::Uuid::new_v4()));
+        let mut sink = SqliteSink::open(&SqliteSinkConfig { path: path.clone() }).unwrap();
+
+        let mut log = AuditLog {
+            connection_id: uuid::Uuid::new_v4(),
+            peer_address: Some("203.0.113.7:51234".parse().unwrap()),
+            sensor: Some("sensor-1".into()),
+            ..AuditLog::default()
+        };
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: Box::from("root"),
+                password: Box::from("hunter2"),
+            },
+        ));
+        log.push_action(AuditLogAction::Quarantined(QuarantinedEvent {
+            sha256: Box::from("9f86d081884c7d65"),
+            size: 4,
+            tool: "wget".into(),
+            virustotal: None,
+        }));
+        log.tag("miner-recon");
+        log.finish();
+
+        sink.write(&log).await.unwrap();
+
+        path
+    }
+
+    fn query(pairs: &[(&str, &str)]) -> Query {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn queries_database() {
+        let path = database().await;
+        let connection = open(&path).unwrap();
+
+        let sessions = route(&connection, "/api/sessions", &query(&[])).unwrap();
+        assert_eq!(sessions.as_array().unwrap().len(), 1);
+        assert_eq!(sessions[0]["sensor"], "sensor-1");
+        assert_eq!(sessions[0]["tags"], serde_json::json!(["miner-recon"]));
+
+        let by_ip = route(
+            &connection,
+            "/api/sessions",
+            &query(&[("ip", "203.0.113.7")]),
+        )
+        .unwrap();
+        assert_eq!(by_ip.as_array().unwrap().len(), 1);
+
+        let by_tag = route(&connection, "/api/sessions", &query(&[("tag", "scanner")])).unwrap();
+        assert!(by_tag.as_array().unwrap().is_empty());
+
+        let id = sessions[0]["id"].as_str().unwrap();
+        let session = route(&connection, &format!("/api/sessions/{id}"), &query(&[])).unwrap();
+        assert_eq!(session["events"].as_array().unwrap().len(), 2);
+        assert_eq!(session["events"][0]["action"]["type"], "login-attempt");
+
+        let credentials = route(&connection, "/api/credentials", &query(&[])).unwrap();
+        assert_eq!(credentials[0]["password"], "hunter2");
+        assert_eq!(credentials[0]["attempts"], 1);
+
+        let payloads = route(&connection, "/api/payloads", &query(&[])).unwrap();
+        assert_eq!(payloads[0]["sha256"], "9f86d081884c7d65");
+        assert_eq!(payloads[0]["tools"], serde_json::json!(["wget"]));
+
+        let future = route(
+            &connection,
+            "/api/payloads",
+            &query(&[("since", "2999-01-01T00:00:00Z")]),
+        )
+        .unwrap();
+        assert!(future.as_array().unwrap().is_empty());
+
+        for (path, pairs, status) in [
+            ("/api/sessions/missing", &[][..], StatusCode::NOT_FOUND),
+            ("/api/unknown", &[][..], StatusCode::NOT_FOUND),
+            (
+                "/api/sessions",
+                &[("limit", "lots")][..],
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "/api/sessions",
+                &[("since", "yesterday")][..],
+                StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            let error = route(&connection, path, &query(pairs)).unwrap_err();
+            assert_eq!(error.status, status, "{path}");
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn requires_token() {
+        let path = database().await;
+        let config = Config {
+            api: Some(ApiConfig {
+                listen_address: "127.0.0.1:0".parse().unwrap(),
+                database: path.clone(),
+                tokens: vec!["hunter2".to_string()],
+            }),
+            ..Config::default()
+        };
+
+        for (token, status) in [
+            (None, StatusCode::UNAUTHORIZED),
+            (Some("Bearer hunter3"), StatusCode::UNAUTHORIZED),
+            (Some("Bearer hunter2"), StatusCode::OK),
+        ] {
+            let mut request = Request::builder().uri("/api/sessions?limit=1");
+
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, token);
+            }
+
+            let result = handle(&request.body(Body::empty()).unwrap(), &config).await;
+            assert_eq!(result.map_or_else(|e| e.status, |_| StatusCode::OK), status);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,78 +1,324 @@
-use std::{io::ErrorKind, sync::Arc, time::Duration};
+mod file;
+mod forward;
+mod hpfeeds;
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+#[cfg(feature = "nats")]
+mod nats;
+#[cfg(feature = "postgres")]
+mod postgres;
+pub mod queue;
+pub mod sqlite;
+mod stdout;
+mod syslog;
+mod webhook;
 
+#[cfg(any(feature = "kafka", feature = "mqtt", feature = "nats"))]
+use std::net::SocketAddr;
+use std::{io::ErrorKind, path::Path, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+pub use file::rotated_files;
 pub use pisshoff_types::audit::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use tokio::{
-    fs::OpenOptions,
-    io::{AsyncWriteExt, BufWriter},
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
     sync::{oneshot, watch},
     task::JoinHandle,
 };
-use tracing::{debug, info};
+use tokio_rustls::{
+    rustls::{self, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName},
+    TlsConnector,
+};
+use tracing::{debug, info, warn};
+#[cfg(any(feature = "kafka", feature = "mqtt", feature = "nats"))]
+use uuid::Uuid;
+
+use crate::{
+    alert::AlertSink,
+    audit::queue::AuditQueue,
+    config::{AuditSinkConfig, Config},
+};
+
+/// A destination for completed audit logs, multiple sinks can be configured at once and each
+/// will receive every log.
+#[async_trait]
+pub trait AuditSink: Send {
+    /// Writes a single connection's audit log to the sink.
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error>;
+
+    /// Flushes any logs the sink has buffered.
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+
+    /// Called when the server receives a SIGHUP but the sinks couldn't be recreated from the
+    /// reloaded config, allowing sinks to reopen any handles they hold.
+    async fn reload(&mut self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+}
+
+async fn open_sinks(config: &Config) -> Result<Vec<Box<dyn AuditSink>>, std::io::Error> {
+    let mut sinks: Vec<Box<dyn AuditSink>> = Vec::new();
+
+    for sink in config.audit_sinks().iter() {
+        if let Some(feature) = sink.missing_feature() {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                format!("pisshoff-server was built without the `{feature}` feature"),
+            ));
+        }
+
+        sinks.push(match sink {
+            AuditSinkConfig::File(config) => Box::new(file::FileSink::open(config).await?),
+            AuditSinkConfig::Forward(config) => Box::new(forward::ForwardSink::new(config)?),
+            AuditSinkConfig::Hpfeeds(config) => Box::new(hpfeeds::HpfeedsSink::new(config)),
+            #[cfg(feature = "kafka")]
+            AuditSinkConfig::Kafka(config) => Box::new(kafka::KafkaSink::new(config)?),
+            #[cfg(feature = "mqtt")]
+            AuditSinkConfig::Mqtt(config) => Box::new(mqtt::MqttSink::new(config)?),
+            #[cfg(feature = "nats")]
+            AuditSinkConfig::Nats(config) => Box::new(nats::NatsSink::new(config)?),
+            #[cfg(feature = "postgres")]
+            AuditSinkConfig::Postgres(config) => Box::new(postgres::PostgresSink::new(config)?),
+            AuditSinkConfig::Sqlite(config) => Box::new(sqlite::SqliteSink::open(config)?),
+            AuditSinkConfig::Stdout => Box::new(stdout::StdoutSink::default()),
+            AuditSinkConfig::Syslog(config) => Box::new(syslog::SyslogSink::new(config).await?),
+            AuditSinkConfig::Webhook(config) => Box::new(webhook::WebhookSink::new(config)?),
+            // sinks that weren't built in were turned away above
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        });
+    }
 
-use crate::config::Config;
+    if !config.alerts.is_empty() {
+        sinks.push(Box::new(AlertSink::new(config)?));
+    }
+
+    Ok(sinks)
+}
 
+/// Writes logs from `queue` to the configured sinks in the background, recreating the sinks each
+/// time a new config is sent.
 pub fn start_audit_writer(
-    config: Arc<Config>,
-    mut reload: watch::Receiver<()>,
+    mut config: watch::Receiver<Arc<Config>>,
     mut shutdown_recv: oneshot::Receiver<()>,
-) -> (
-    tokio::sync::mpsc::UnboundedSender<AuditLog>,
-    JoinHandle<Result<(), std::io::Error>>,
-) {
-    let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
-
-    let handle = tokio::spawn(async move {
-        let open_writer = || async {
-            let file = OpenOptions::default()
-                .create(true)
-                .append(true)
-                .open(&config.audit_output_file)
-                .await?;
-            Ok::<_, std::io::Error>(BufWriter::new(file))
-        };
-
-        let mut writer = open_writer().await?;
+    queue: Arc<AuditQueue>,
+) -> JoinHandle<Result<(), std::io::Error>> {
+    tokio::spawn(async move {
+        let initial_config = config.borrow_and_update().clone();
+        let mut sinks = open_sinks(&initial_config).await?;
+        let mut pending_flush = false;
         let mut shutdown = false;
 
         while !shutdown {
             tokio::select! {
-                log = recv.recv() => {
-                    match log {
-                        Some(log) => {
-                            let log = serde_json::to_vec(&log)
-                                .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
-                            writer.write_all(&log).await?;
-                            writer.write_all("\n".as_bytes()).await?;
-                        }
-                        None => {
-                            shutdown = true;
-                        }
+                log = queue.pop() => {
+                    for sink in &mut sinks {
+                        sink.write(&log).await?;
                     }
+
+                    pending_flush = true;
                 }
                 _ = &mut shutdown_recv => {
                     shutdown = true;
                 }
-                () = tokio::time::sleep(Duration::from_secs(5)), if !writer.buffer().is_empty() => {
-                    debug!("Flushing audits to disk");
-                    writer.flush().await?;
+                () = tokio::time::sleep(Duration::from_secs(5)), if pending_flush => {
+                    debug!("Flushing audits");
+
+                    for sink in &mut sinks {
+                        sink.flush().await?;
+                    }
+
+                    pending_flush = false;
                 }
-                Ok(()) = reload.changed() => {
-                    info!("Flushing audits to disk");
-                    writer.flush().await?;
+                Ok(()) = config.changed() => {
+                    info!("Reloading audit sinks");
+
+                    let new_config = config.borrow_and_update().clone();
+
+                    // anything still buffered would be lost when the old sinks are dropped
+                    for sink in &mut sinks {
+                        sink.flush().await?;
+                    }
+
+                    pending_flush = false;
 
-                    info!("Reopening handle to log file");
-                    writer = open_writer().await?;
+                    match open_sinks(&new_config).await {
+                        Ok(new_sinks) => {
+                            sinks = new_sinks;
+                            info!("Successfully reloaded audit sinks");
+                        }
+                        Err(error) => {
+                            warn!(%error, "Failed to open audit sinks from new config, keeping the old ones");
 
-                    info!("Successfully re-opened log file");
+                            for sink in &mut sinks {
+                                sink.reload().await?;
+                            }
+                        }
+                    }
                 }
                 else => break,
             }
         }
 
-        writer.flush().await?;
+        // logs that were still waiting would otherwise be lost
+        while let Some(log) = queue.try_pop() {
+            for sink in &mut sinks {
+                sink.write(&log).await?;
+            }
+        }
+
+        for sink in &mut sinks {
+            sink.flush().await?;
+        }
 
         Ok(())
-    });
+    })
+}
+
+/// Serialises a log, or something standing in for one, as a single line of JSON.
+fn to_json_line(log: &impl Serialize) -> Result<Vec<u8>, std::io::Error> {
+    let mut out = serde_json::to_vec(log).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+    out.push(b'\n');
+    Ok(out)
+}
+
+/// TLS config for connecting out to a sink, verifying the server against the CA certificates in
+/// the PEM file at `ca_file` or the bundled public roots if there isn't one.
+fn tls_config(ca_file: Option<&Path>) -> Result<Arc<ClientConfig>, std::io::Error> {
+    Ok(Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_store(ca_file)?)
+            .with_no_client_auth(),
+    ))
+}
+
+/// CA certificates to verify servers against, read from the PEM file at `ca_file` or the bundled
+/// public roots if there isn't one.
+pub fn root_store(ca_file: Option<&Path>) -> Result<RootCertStore, std::io::Error> {
+    let mut roots = RootCertStore::empty();
+
+    if let Some(path) = ca_file {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+
+        for certificate in rustls_pemfile::certs(&mut reader)? {
+            roots
+                .add(&rustls::Certificate(certificate))
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+        }
+    } else {
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+    }
+
+    Ok(roots)
+}
+
+/// A connection to a sink's server, either plain TCP or TLS over it.
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+type BoxedStream = Box<dyn Stream>;
+
+/// Connects to the server at `address`, given as `host:port`, over TLS if `tls` is set.
+async fn connect_stream(
+    address: &str,
+    tls: Option<&TlsConnector>,
+) -> Result<BoxedStream, std::io::Error> {
+    let stream = TcpStream::connect(address).await?;
+
+    match tls {
+        Some(tls) => upgrade_stream(stream, address, tls).await,
+        None => Ok(Box::new(stream)),
+    }
+}
+
+/// Starts a TLS session over `stream`, verifying the server's certificate against the host
+/// in `address`.
+async fn upgrade_stream(
+    stream: TcpStream,
+    address: &str,
+    tls: &TlsConnector,
+) -> Result<BoxedStream, std::io::Error> {
+    Ok(Box::new(tls.connect(server_name(address)?, stream).await?))
+}
+
+/// The name to verify the certificate of the server at `address`, given as `host:port`,
+/// against.
+fn server_name(address: &str) -> Result<ServerName, std::io::Error> {
+    let host = address
+        .rsplit_once(':')
+        .map_or(address, |(host, _)| host)
+        .trim_matches(['[', ']'].as_slice());
+
+    ServerName::try_from(host).map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// A single event published on its own, with enough of its connection to tell which it
+/// belonged to.
+#[cfg(any(feature = "kafka", feature = "mqtt", feature = "nats"))]
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    connection_id: Uuid,
+    host: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sensor: Option<&'a str>,
+    peer_address: Option<SocketAddr>,
+    #[serde(flatten)]
+    event: &'a AuditLogEvent,
+}
+
+#[cfg(any(feature = "kafka", feature = "mqtt", feature = "nats"))]
+impl<'a> EventRecord<'a> {
+    fn new(log: &'a AuditLog, event: &'a AuditLogEvent) -> Self {
+        Self {
+            connection_id: log.connection_id,
+            host: &log.host,
+            sensor: log.sensor.as_deref(),
+            peer_address: log.peer_address,
+            event,
+        }
+    }
+}
+
+/// Serialises `log` as a single message, or as a message per event along with the event's type,
+/// for the sinks publishing to a broker's topics.
+#[cfg(any(feature = "mqtt", feature = "nats"))]
+fn topic_messages(
+    log: &AuditLog,
+    per_event: bool,
+) -> Result<Vec<(Option<&'static str>, Vec<u8>)>, std::io::Error> {
+    if !per_event {
+        let payload =
+            serde_json::to_vec(log).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+        return Ok(vec![(None, payload)]);
+    }
+
+    log.events
+        .iter()
+        .map(|event| {
+            let payload = serde_json::to_vec(&EventRecord::new(log, event))
+                .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+            Ok((Some(<&'static str>::from(&event.action)), payload))
+        })
+        .collect()
+}
 
-    (send, handle)
+/// Hex-encoded SHA-256 digest of `data`, used to identify captured payloads.
+pub fn sha256_hex(data: &[u8]) -> Box<str> {
+    format!("{:x}", Sha256::digest(data)).into_boxed_str()
 }
@@ -0,0 +1,485 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use time::{macros::format_description, OffsetDateTime};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+use crate::{
+    audit::{to_json_line, AuditLog, AuditSink},
+    chain::Chain,
+    config::{FileCompression, FileSinkConfig},
+    encrypt::Encryption,
+};
+
+/// Appends logs to a file as JSON lines, rotating it once it grows too large or too old if
+/// configured to. The file is reopened on reload to play nicely with logrotate.
+pub struct FileSink {
+    config: FileSinkConfig,
+    writer: BufWriter<File>,
+    /// Hash chain running through the lines written, if enabled. It carries on across rotations
+    /// and reopens, so the first line of each file links back to the last line of the one before.
+    chain: Option<Chain>,
+    /// Recipients each line is encrypted to, if any are configured.
+    encryption: Option<Encryption>,
+    /// Bytes in the current file, including whatever was there when it was opened.
+    size: u64,
+    /// The rotation period the current file was started in.
+    period: u64,
+    /// Compression and pruning of the last file rotated out, which has to finish before the
+    /// next rotation so files are compressed and pruned in order.
+    compressing: Option<JoinHandle<()>>,
+}
+
+impl FileSink {
+    pub async fn open(config: &FileSinkConfig) -> Result<Self, std::io::Error> {
+        let chain = if config.hash_chain {
+            let config = config.clone();
+            Some(tokio::task::spawn_blocking(move || Chain::open(&config)).await??)
+        } else {
+            None
+        };
+
+        let encryption = if config.recipients.is_empty() {
+            None
+        } else {
+            Some(Encryption::new(&config.recipients)?)
+        };
+
+        let (writer, size, started) = open_writer(&config.path).await?;
+
+        Ok(Self {
+            period: period(started, config.rotate_interval),
+            config: config.clone(),
+            writer,
+            chain,
+            encryption,
+            size,
+            compressing: None,
+        })
+    }
+
+    /// Whether writing `len` more bytes at `now` should go into a fresh file.
+    fn needs_rotation(&self, len: u64, now: SystemTime) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+
+        let too_large = self
+            .config
+            .rotate_size
+            .map_or(false, |max| self.size + len > max);
+        let too_old = self.config.rotate_interval.is_some()
+            && period(now, self.config.rotate_interval) != self.period;
+
+        too_large || too_old
+    }
+
+    /// Moves the current file out of the way and starts a new one, compressing the old one
+    /// and pruning those past the limit in the background.
+    async fn rotate(&mut self, now: SystemTime) -> Result<(), std::io::Error> {
+        self.writer.flush().await?;
+
+        if self.config.fsync {
+            self.writer.get_ref().sync_all().await?;
+        }
+
+        if let Some(compressing) = self.compressing.take() {
+            let _res = compressing.await;
+        }
+
+        let rotated = rotated_path(&self.config.path, now);
+        tokio::fs::rename(&self.config.path, &rotated).await?;
+        info!(path = %rotated.display(), "Rotated audit log");
+
+        let (writer, size, _) = open_writer(&self.config.path).await?;
+        self.writer = writer;
+        self.size = size;
+        self.period = period(now, self.config.rotate_interval);
+
+        let config = self.config.clone();
+        self.compressing = Some(tokio::task::spawn_blocking(move || {
+            if let Err(error) = compress(&rotated, config.compression, config.fsync) {
+                warn!(%error, path = %rotated.display(), "Failed to compress rotated audit log");
+            }
+
+            if let Some(keep) = config.keep {
+                if let Err(error) = prune(&config.path, keep) {
+                    warn!(%error, "Failed to remove old audit logs");
+                }
+            }
+        }));
+
+        Ok(())
+    }
+}
+
+/// Opens the file at `path` for appending, along with its current size and when it was last
+/// written to, which is now if it's empty.
+async fn open_writer(path: &Path) -> Result<(BufWriter<File>, u64, SystemTime), std::io::Error> {
+    let file = OpenOptions::default()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+
+    let metadata = file.metadata().await?;
+    let modified = Some(metadata.modified()?)
+        .filter(|_| metadata.len() > 0)
+        .unwrap_or_else(SystemTime::now);
+
+    Ok((BufWriter::new(file), metadata.len(), modified))
+}
+
+/// Which rotation period `time` falls into, counted from the Unix epoch so a daily interval
+/// rotates at midnight UTC.
+fn period(time: SystemTime, interval: Option<u64>) -> u64 {
+    let Some(interval) = interval.filter(|v| *v > 0) else {
+        return 0;
+    };
+
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |v| v.as_secs() / interval)
+}
+
+/// Path to move `path` to when it's rotated at `now`, named by the time so rotated files sort
+/// oldest first. If the file's rotated more than once in a second, later ones are pushed a
+/// second along to keep the names unique.
+fn rotated_path(path: &Path, now: SystemTime) -> PathBuf {
+    let format = format_description!("[year][month][day]T[hour][minute][second]Z");
+    let mut time = OffsetDateTime::from(now);
+
+    loop {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".");
+        name.push(time.format(&format).unwrap_or_default());
+        let rotated = PathBuf::from(name);
+
+        let taken = [
+            FileCompression::None,
+            FileCompression::Gzip,
+            FileCompression::Zstd,
+        ]
+        .iter()
+        .any(|v| compressed_path(&rotated, *v).exists());
+
+        if !taken {
+            return rotated;
+        }
+
+        time += time::Duration::SECOND;
+    }
+}
+
+fn compressed_path(path: &Path, compression: FileCompression) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(compression.extension());
+    PathBuf::from(name)
+}
+
+/// Compresses the rotated file at `path`, replacing it with the compressed copy.
+fn compress(path: &Path, compression: FileCompression, fsync: bool) -> Result<(), std::io::Error> {
+    if compression == FileCompression::None {
+        return Ok(());
+    }
+
+    let output = compressed_path(path, compression);
+    let res = (|| {
+        let mut input = std::fs::File::open(path)?;
+        let file = std::fs::File::create(&output)?;
+
+        let file = match compression {
+            FileCompression::None => file,
+            FileCompression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                std::io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?
+            }
+            FileCompression::Zstd => {
+                let mut encoder = zstd::Encoder::new(file, 0)?;
+                std::io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?
+            }
+        };
+
+        if fsync {
+            file.sync_all()?;
+        }
+
+        Ok(())
+    })();
+
+    match res {
+        Ok(()) => std::fs::remove_file(path),
+        Err(error) => {
+            // leave the uncompressed file in place rather than a truncated copy
+            let _res = std::fs::remove_file(&output);
+            Err(error)
+        }
+    }
+}
+
+/// Removes all but the newest `keep` files rotated out of `path`.
+fn prune(path: &Path, keep: usize) -> Result<(), std::io::Error> {
+    let (directory, rotated) = rotated_names(path)?;
+
+    for name in &rotated[..rotated.len().saturating_sub(keep)] {
+        match std::fs::remove_file(directory.join(name)) {
+            Ok(()) => info!(name, "Removed old audit log"),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Files rotated out by the sink that have finished being compressed, oldest first.
+pub fn rotated_files(config: &FileSinkConfig) -> Result<Vec<PathBuf>, std::io::Error> {
+    let (directory, rotated) = rotated_names(&config.path)?;
+    let extension = config.compression.extension();
+
+    Ok(rotated
+        .iter()
+        .filter(|name| {
+            let Some(uncompressed) = name.strip_suffix(extension) else {
+                return false;
+            };
+
+            // the uncompressed file is only removed once its compressed copy is complete
+            extension.is_empty() || !rotated.iter().any(|v| v == uncompressed)
+        })
+        .map(|name| directory.join(name))
+        .collect())
+}
+
+/// Names of the files rotated out of `path` in the directory they're in, sorted oldest first.
+fn rotated_names(path: &Path) -> Result<(PathBuf, Vec<String>), std::io::Error> {
+    let (Some(directory), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok((PathBuf::new(), Vec::new()));
+    };
+    let directory = Some(directory)
+        .filter(|v| !v.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let prefix = format!("{}.", name.to_string_lossy());
+
+    let mut rotated = Vec::new();
+
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((PathBuf::new(), Vec::new())),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        // only files named by rotated_path, rather than anything sharing the prefix
+        let is_rotated = name
+            .strip_prefix(&prefix)
+            .map_or(false, |v| v.starts_with(|c: char| c.is_ascii_digit()));
+
+        if is_rotated {
+            rotated.push(name);
+        }
+    }
+
+    rotated.sort();
+
+    Ok((directory.to_path_buf(), rotated))
+}
+
+#[async_trait]
+impl AuditSink for FileSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        // lines are encrypted before they're chained, so the chain can be checked without
+        // decrypting them
+        let line = match (&mut self.chain, &self.encryption) {
+            (Some(chain), Some(encryption)) => chain.push(&encryption.seal(log)?)?,
+            (Some(chain), None) => chain.push(log)?,
+            (None, Some(encryption)) => to_json_line(&encryption.seal(log)?)?,
+            (None, None) => to_json_line(log)?,
+        };
+        let now = SystemTime::now();
+
+        if self.needs_rotation(line.len() as u64, now) {
+            // carry on writing to the current file rather than losing logs
+            if let Err(error) = self.rotate(now).await {
+                warn!(%error, "Failed to rotate audit log");
+            }
+        }
+
+        self.writer.write_all(&line).await?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.writer.flush().await
+    }
+
+    async fn reload(&mut self) -> Result<(), std::io::Error> {
+        info!("Flushing audits to disk");
+        self.writer.flush().await?;
+
+        info!("Reopening handle to log file");
+        let (writer, size, started) = open_writer(&self.config.path).await?;
+        self.writer = writer;
+        self.size = size;
+        self.period = period(started, self.config.rotate_interval);
+
+        info!("Successfully re-opened log file");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::{
+        audit::{
+            file::{rotated_files, FileSink},
+            sha256_hex, AuditLog, AuditSink,
+        },
+        chain::LinkHeader,
+        config::{FileCompression, FileSinkConfig},
+    };
+
+    fn rotated(directory: &Path) -> Vec<String> {
+        let mut names = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|v| v.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|v| v != "audit.jsonl")
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn rotates_by_size() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-rotate-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let mut sink = FileSink::open(&FileSinkConfig {
+            rotate_size: Some(1),
+            compression: FileCompression::Gzip,
+            keep: Some(2),
+            ..FileSinkConfig::new(directory.join("audit.jsonl"))
+        })
+        .await
+        .unwrap();
+
+        for _ in 0..4 {
+            sink.write(&AuditLog::default()).await.unwrap();
+        }
+        sink.flush().await.unwrap();
+        sink.compressing.take().unwrap().await.unwrap();
+
+        let rotated = rotated(&directory);
+        assert_eq!(rotated.len(), 2, "{rotated:?}");
+        assert!(rotated.iter().all(|v| v.ends_with(".gz")), "{rotated:?}");
+
+        let current = std::fs::read_to_string(directory.join("audit.jsonl")).unwrap();
+        assert_eq!(current.lines().count(), 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn lists_finished_rotations() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-rotate-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for name in [
+            "audit.jsonl",
+            "audit.jsonl.20230810T204609Z.gz",
+            // still being compressed
+            "audit.jsonl.20230811T204609Z",
+            "audit.jsonl.20230811T204609Z.gz",
+            "audit.jsonl.old",
+        ] {
+            std::fs::write(directory.join(name), "").unwrap();
+        }
+
+        let config = FileSinkConfig {
+            compression: FileCompression::Gzip,
+            ..FileSinkConfig::new(directory.join("audit.jsonl"))
+        };
+
+        assert_eq!(
+            rotated_files(&config).unwrap(),
+            [directory.join("audit.jsonl.20230810T204609Z.gz")]
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rotates_by_time() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-rotate-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let mut sink = FileSink::open(&FileSinkConfig {
+            rotate_interval: Some(3600),
+            ..FileSinkConfig::new(directory.join("audit.jsonl"))
+        })
+        .await
+        .unwrap();
+
+        let now = SystemTime::now();
+        assert!(!sink.needs_rotation(1, now));
+
+        sink.write(&AuditLog::default()).await.unwrap();
+        assert!(!sink.needs_rotation(1, now));
+        assert!(sink.needs_rotation(1, now + Duration::from_secs(3600)));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn chains_across_reopens() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-chain-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let config = FileSinkConfig {
+            hash_chain: true,
+            ..FileSinkConfig::new(directory.join("audit.jsonl"))
+        };
+
+        for _ in 0..2 {
+            let mut sink = FileSink::open(&config).await.unwrap();
+            sink.write(&AuditLog::default()).await.unwrap();
+            sink.flush().await.unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&config.path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        let second = serde_json::from_str::<LinkHeader>(lines[1]).unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(second.seq, 1);
+        assert_eq!(second.prev_hash, sha256_hex(lines[0].as_bytes()));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
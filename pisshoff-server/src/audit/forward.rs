@@ -0,0 +1,512 @@
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncRead, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_rustls::{rustls::ClientConfig, TlsConnector};
+use tracing::{info, warn};
+
+use crate::{
+    audit::{root_store, server_name, AuditLog, AuditSink, BoxedStream},
+    collector::{encode_line, load_key_pair, peer_fingerprint, read_line, Hello, Reply},
+    config::{fingerprint_matches, ForwardSinkConfig},
+};
+
+/// Number of logs to hold in memory whilst the collector is unavailable if no spool directory
+/// has been configured, the oldest are dropped once this is exceeded.
+const MAX_PENDING: usize = 1024;
+
+/// Most spooled logs to send before waiting for the collector to acknowledge them, so a long
+/// outage doesn't turn into a single enormous write.
+const SPOOL_BATCH_SIZE: usize = 512;
+
+/// File logs are appended to whilst the collector is unavailable, which is moved aside before
+/// it's sent so logs can carry on being spooled in the meantime.
+const SPOOL_FILE: &str = "spool.jsonl";
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for the collector to acknowledge what we've sent it.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Forwards logs to a collector, which writes them to its own sinks tagged with this sensor's
+/// ID, reconnecting with an exponential backoff if the collector goes away.
+///
+/// Logs are only let go of once the collector has acknowledged them, so they can be forwarded
+/// again if the connection drops before the acknowledgement arrives. Whilst the collector is
+/// unavailable they're spooled to disk if a spool directory has been configured, and sent
+/// oldest first once it's back.
+pub struct ForwardSink {
+    config: ForwardSinkConfig,
+    tls: Option<TlsConnector>,
+    connection: Option<Connection>,
+    /// Logs waiting to be acknowledged or spooled, already serialised.
+    pending: VecDeque<Vec<u8>>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+struct Connection {
+    stream: BufReader<BoxedStream>,
+    /// Sequence number to give the next record sent over the connection.
+    next_seq: u64,
+}
+
+impl ForwardSink {
+    pub fn new(config: &ForwardSinkConfig) -> Result<Self, std::io::Error> {
+        let tls = if config.tls {
+            Some(TlsConnector::from(client_tls_config(config)?))
+        } else {
+            None
+        };
+
+        if let Some(directory) = &config.spool_directory {
+            std::fs::create_dir_all(directory)?;
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            tls,
+            connection: None,
+            pending: VecDeque::new(),
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+        })
+    }
+
+    /// Forwards everything spooled and pending if we can, reconnecting to the collector if
+    /// needed, otherwise spooling whatever's pending.
+    async fn drain(&mut self) -> Result<(), std::io::Error> {
+        if self.connect().await && self.send_spooled().await? {
+            let mut pending = std::mem::take(&mut self.pending);
+            self.send(&mut pending).await;
+            self.pending = pending;
+        }
+
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let Some(directory) = &self.config.spool_directory else {
+            if self.pending.len() > MAX_PENDING {
+                warn!("Collector unavailable for too long, dropping oldest audit logs");
+                self.pending.drain(..self.pending.len() - MAX_PENDING);
+            }
+
+            return Ok(());
+        };
+
+        append_spool(&directory.join(SPOOL_FILE), self.pending.drain(..)).await
+    }
+
+    /// Sends logs spooled whilst the collector was unavailable, oldest first, returning whether
+    /// they were all acknowledged.
+    async fn send_spooled(&mut self) -> Result<bool, std::io::Error> {
+        let Some(directory) = self.config.spool_directory.clone() else {
+            return Ok(true);
+        };
+
+        for path in spooled_files(&directory).await? {
+            let mut logs = read_spool(&path).await?;
+
+            while !logs.is_empty() {
+                let mut batch = logs
+                    .drain(..logs.len().min(SPOOL_BATCH_SIZE))
+                    .collect::<VecDeque<_>>();
+
+                if !self.send(&mut batch).await {
+                    // keep whatever the collector didn't acknowledge for next time
+                    batch.extend(logs);
+                    write_spool(&path, batch).await?;
+                    return Ok(false);
+                }
+            }
+
+            tokio::fs::remove_file(&path).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Sends `logs` to the collector, removing them as they're acknowledged and returning
+    /// whether they all were.
+    async fn send(&mut self, logs: &mut VecDeque<Vec<u8>>) -> bool {
+        let Some(connection) = &mut self.connection else {
+            return false;
+        };
+
+        if logs.is_empty() {
+            return true;
+        }
+
+        let error = match tokio::time::timeout(REPLY_TIMEOUT, send_records(connection, logs)).await
+        {
+            Ok(Ok(())) => return true,
+            Ok(Err(error)) => error.to_string(),
+            Err(_) => "timed out waiting for acknowledgement".to_string(),
+        };
+
+        warn!(%error, "Lost connection to collector");
+        self.connection = None;
+        self.schedule_retry();
+
+        false
+    }
+
+    /// Connects to the collector if we aren't already and aren't backing off, returning whether
+    /// we're connected.
+    async fn connect(&mut self) -> bool {
+        if self.connection.is_none() {
+            if Instant::now() < self.next_attempt {
+                return false;
+            }
+
+            match tokio::time::timeout(CONNECT_TIMEOUT, connect(&self.config, self.tls.as_ref()))
+                .await
+            {
+                Ok(Ok(connection)) => {
+                    info!(address = %self.config.address, "Connected to collector");
+                    self.connection = Some(connection);
+                    self.backoff = MIN_BACKOFF;
+                }
+                Ok(Err(error)) => {
+                    warn!(%error, "Failed to connect to collector");
+                    self.schedule_retry();
+                }
+                Err(_) => {
+                    warn!("Timed out connecting to collector");
+                    self.schedule_retry();
+                }
+            }
+        }
+
+        self.connection.is_some()
+    }
+
+    fn schedule_retry(&mut self) {
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[async_trait]
+impl AuditSink for ForwardSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        let log = serde_json::to_vec(log).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        self.pending.push_back(log);
+        self.drain().await
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.drain().await
+    }
+}
+
+/// TLS config for connecting to the collector, presenting the sensor's client certificate if it
+/// has one.
+fn client_tls_config(config: &ForwardSinkConfig) -> Result<Arc<ClientConfig>, std::io::Error> {
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store(config.ca_file.as_deref())?);
+
+    let tls = match (&config.certificate, &config.private_key) {
+        (Some(certificate), Some(private_key)) => {
+            let (certificates, key) = load_key_pair(certificate, private_key)
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, format!("{e:#}")))?;
+
+            builder
+                .with_client_auth_cert(certificates, key)
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(tls))
+}
+
+/// Connects to the collector, checking its certificate is the one we've pinned if any, and
+/// identifies ourselves to it.
+async fn connect(
+    config: &ForwardSinkConfig,
+    tls: Option<&TlsConnector>,
+) -> Result<Connection, std::io::Error> {
+    let stream = TcpStream::connect(&config.address).await?;
+
+    let stream: BoxedStream = match tls {
+        Some(tls) => {
+            let stream = tls.connect(server_name(&config.address)?, stream).await?;
+
+            if let Some(pin) = &config.collector_fingerprint {
+                let fingerprint = peer_fingerprint(stream.get_ref().1.peer_certificates());
+
+                if !fingerprint.map_or(false, |v| fingerprint_matches(pin, &v)) {
+                    return Err(std::io::Error::new(
+                        ErrorKind::PermissionDenied,
+                        "collector's certificate doesn't match collector-fingerprint",
+                    ));
+                }
+            }
+
+            Box::new(stream)
+        }
+        None => Box::new(stream),
+    };
+
+    let mut stream = BufReader::new(stream);
+
+    let hello = Hello {
+        sensor: config.sensor_id.as_str().into(),
+        token: config.token.as_str().into(),
+    };
+
+    stream.write_all(&encode_line(&hello)?).await?;
+    stream.flush().await?;
+
+    match read_reply(&mut stream).await? {
+        Reply::Ok => Ok(Connection {
+            stream,
+            next_seq: 1,
+        }),
+        reply => Err(unexpected(reply)),
+    }
+}
+
+/// Sends every log in `pending` in a single write, removing them as the collector acknowledges
+/// them.
+async fn send_records(
+    connection: &mut Connection,
+    pending: &mut VecDeque<Vec<u8>>,
+) -> Result<(), std::io::Error> {
+    let first = connection.next_seq;
+    let mut out = Vec::new();
+
+    for (seq, log) in (first..).zip(pending.iter()) {
+        out.extend_from_slice(&record_line(seq, log));
+    }
+
+    connection.next_seq += pending.len() as u64;
+    let last = connection.next_seq - 1;
+
+    connection.stream.write_all(&out).await?;
+    connection.stream.flush().await?;
+
+    let mut acknowledged = first - 1;
+
+    while acknowledged < last {
+        match read_reply(&mut connection.stream).await? {
+            Reply::Ack(seq) if seq > acknowledged && seq <= last => {
+                pending.drain(..usize::try_from(seq - acknowledged).unwrap_or(usize::MAX));
+                acknowledged = seq;
+            }
+            reply => return Err(unexpected(reply)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the line sending `log` as a record, without deserialising and serialising it again.
+fn record_line(seq: u64, log: &[u8]) -> Vec<u8> {
+    let mut out = format!("{{\"seq\":{seq},\"log\":").into_bytes();
+    out.extend_from_slice(log);
+    out.extend_from_slice(b"}\n");
+    out
+}
+
+async fn read_reply(
+    stream: &mut BufReader<impl AsyncRead + Unpin>,
+) -> Result<Reply, std::io::Error> {
+    let line = read_line(stream).await?.ok_or(ErrorKind::UnexpectedEof)?;
+
+    serde_json::from_str(&line).map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+}
+
+fn unexpected(reply: Reply) -> std::io::Error {
+    match reply {
+        Reply::Error(message) => std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("collector turned us away: {message}"),
+        ),
+        reply => std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("unexpected reply from collector: {reply:?}"),
+        ),
+    }
+}
+
+/// Lists spooled logs waiting to be sent, oldest first, moving the file currently being
+/// appended to aside so it's included.
+async fn spooled_files(directory: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let current = directory.join(SPOOL_FILE);
+
+    if tokio::fs::try_exists(&current).await? {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        // the timestamp keeps the spool sorted oldest first
+        tokio::fs::rename(&current, directory.join(format!("{millis:020}.jsonl"))).await?;
+    }
+
+    let mut out = Vec::new();
+    let mut entries = tokio::fs::read_dir(directory).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if path.extension().map_or(false, |v| v == "jsonl") && path != current {
+            out.push(path);
+        }
+    }
+
+    out.sort();
+    Ok(out)
+}
+
+async fn read_spool(path: &Path) -> Result<VecDeque<Vec<u8>>, std::io::Error> {
+    Ok(tokio::fs::read(path)
+        .await?
+        .split(|v| *v == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(<[u8]>::to_vec)
+        .collect())
+}
+
+async fn append_spool(
+    path: &Path,
+    logs: impl IntoIterator<Item = Vec<u8>>,
+) -> Result<(), std::io::Error> {
+    tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?
+        .write_all(&spool_lines(logs))
+        .await
+}
+
+async fn write_spool(
+    path: &Path,
+    logs: impl IntoIterator<Item = Vec<u8>>,
+) -> Result<(), std::io::Error> {
+    tokio::fs::write(path, spool_lines(logs)).await
+}
+
+fn spool_lines(logs: impl IntoIterator<Item = Vec<u8>>) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for log in logs {
+        out.extend_from_slice(&log);
+        out.push(b'\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Instant};
+
+    use tokio::{net::TcpListener, sync::watch};
+
+    use super::{record_line, ForwardSink};
+    use crate::{
+        audit::{queue::AuditQueue, AuditLog, AuditSink},
+        collector::{self, Record},
+        config::{Config, ForwardSinkConfig},
+    };
+
+    #[test]
+    fn builds_records() {
+        let log = AuditLog {
+            host: "honeypot".into(),
+            ..AuditLog::default()
+        };
+
+        let line = record_line(3, &serde_json::to_vec(&log).unwrap());
+        assert_eq!(line.last(), Some(&b'\n'));
+
+        let record = serde_json::from_slice::<Record<AuditLog>>(&line).unwrap();
+        assert_eq!(record.seq, 3);
+        assert_eq!(record.log.host, "honeypot");
+    }
+
+    #[tokio::test]
+    async fn spools_until_collector_is_back() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-forward-{}", uuid::Uuid::new_v4()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let mut sink = ForwardSink::new(&ForwardSinkConfig {
+            address: address.clone(),
+            sensor_id: "sensor-1".to_string(),
+            token: "hunter2".to_string(),
+            tls: false,
+            ca_file: None,
+            collector_fingerprint: None,
+            certificate: None,
+            private_key: None,
+            spool_directory: Some(directory.clone()),
+        })
+        .unwrap();
+
+        // nothing's listening yet, so the logs are spooled
+        drop(listener);
+
+        for host in ["first", "second"] {
+            let log = AuditLog {
+                host: host.into(),
+                ..AuditLog::default()
+            };
+            sink.write(&log).await.unwrap();
+        }
+
+        assert!(sink.pending.is_empty());
+        assert!(directory.join("spool.jsonl").exists());
+
+        let config = Arc::new(
+            toml::from_str::<Config>(&format!(
+                r#"
+                [collector]
+                listen-address = "{address}"
+
+                [[collector.sensor]]
+                id = "sensor-1"
+                token = "hunter2"
+                "#
+            ))
+            .unwrap(),
+        );
+        let queue = Arc::new(AuditQueue::new(&config));
+        let (_send, recv) = watch::channel(config);
+        let listener = TcpListener::bind(&address).await.unwrap();
+        let collector = tokio::spawn(collector::serve(listener, None, recv, queue.clone()));
+
+        sink.next_attempt = Instant::now();
+        sink.flush().await.unwrap();
+
+        let hosts = std::iter::from_fn(|| queue.try_pop())
+            .map(|log| {
+                assert_eq!(log.sensor.as_deref(), Some("sensor-1"));
+                log.host.to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(hosts, ["first", "second"]);
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 0);
+
+        collector.abort();
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
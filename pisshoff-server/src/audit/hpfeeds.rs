@@ -0,0 +1,268 @@
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use sha1::{Digest, Sha1};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::{info, warn};
+
+use crate::{
+    audit::{AuditLog, AuditSink},
+    config::HpfeedsSinkConfig,
+};
+
+const OP_ERROR: u8 = 0;
+const OP_INFO: u8 = 1;
+const OP_AUTH: u8 = 2;
+const OP_PUBLISH: u8 = 3;
+
+/// Largest message we'll accept from the broker, `OP_INFO` messages are tiny.
+const MAX_MESSAGE_LENGTH: u32 = 64 * 1024;
+
+/// Number of logs to hold on to whilst the broker is unreachable, the oldest are dropped
+/// once this is exceeded.
+const MAX_PENDING: usize = 1024;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Publishes each log as JSON to a channel on an [hpfeeds] broker, reconnecting with an
+/// exponential backoff if the broker goes away.
+///
+/// [hpfeeds]: https://hpfeeds.org/
+pub struct HpfeedsSink {
+    config: HpfeedsSinkConfig,
+    stream: Option<TcpStream>,
+    pending: VecDeque<Vec<u8>>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+impl HpfeedsSink {
+    pub fn new(config: &HpfeedsSinkConfig) -> Self {
+        Self {
+            config: config.clone(),
+            stream: None,
+            pending: VecDeque::new(),
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+        }
+    }
+
+    /// Publishes as many pending logs as we can, reconnecting to the broker if needed.
+    async fn drain(&mut self) {
+        while let Some(payload) = self.pending.front() {
+            let message = publish_message(&self.config.ident, &self.config.channel, payload);
+
+            let res = match self.stream().await {
+                Some(stream) => stream.write_all(&message).await,
+                None => return,
+            };
+
+            if let Err(error) = res {
+                warn!(%error, "Lost connection to hpfeeds broker");
+                self.stream = None;
+                self.schedule_retry();
+                return;
+            }
+
+            self.pending.pop_front();
+        }
+    }
+
+    /// Returns the connection to the broker, establishing one if we don't already have one
+    /// and aren't backing off.
+    async fn stream(&mut self) -> Option<&mut TcpStream> {
+        if self.stream.is_none() {
+            if Instant::now() < self.next_attempt {
+                return None;
+            }
+
+            match tokio::time::timeout(CONNECT_TIMEOUT, connect(&self.config)).await {
+                Ok(Ok(stream)) => {
+                    info!(address = %self.config.address, "Connected to hpfeeds broker");
+                    self.stream = Some(stream);
+                    self.backoff = MIN_BACKOFF;
+                }
+                Ok(Err(error)) => {
+                    warn!(%error, "Failed to connect to hpfeeds broker");
+                    self.schedule_retry();
+                }
+                Err(_) => {
+                    warn!("Timed out connecting to hpfeeds broker");
+                    self.schedule_retry();
+                }
+            }
+        }
+
+        self.stream.as_mut()
+    }
+
+    fn schedule_retry(&mut self) {
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[async_trait]
+impl AuditSink for HpfeedsSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        let payload =
+            serde_json::to_vec(log).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        if self.pending.len() >= MAX_PENDING {
+            warn!("hpfeeds broker unavailable for too long, dropping oldest audit log");
+            self.pending.pop_front();
+        }
+
+        self.pending.push_back(payload);
+        self.drain().await;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.drain().await;
+
+        if let Some(stream) = &mut self.stream {
+            if let Err(error) = stream.flush().await {
+                warn!(%error, "Lost connection to hpfeeds broker");
+                self.stream = None;
+                self.schedule_retry();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Connects and authenticates to the broker.
+async fn connect(config: &HpfeedsSinkConfig) -> Result<TcpStream, std::io::Error> {
+    let mut stream = TcpStream::connect(&config.address).await?;
+
+    let (opcode, payload) = read_message(&mut stream).await?;
+    if opcode != OP_INFO {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("expected info message from broker, got opcode {opcode}"),
+        ));
+    }
+
+    let nonce = parse_info(&payload)?;
+    stream
+        .write_all(&auth_message(&config.ident, &config.secret, nonce))
+        .await?;
+
+    Ok(stream)
+}
+
+async fn read_message(stream: &mut TcpStream) -> Result<(u8, Vec<u8>), std::io::Error> {
+    let length = stream.read_u32().await?;
+
+    if !(5..=MAX_MESSAGE_LENGTH).contains(&length) {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid message length {length}"),
+        ));
+    }
+
+    let opcode = stream.read_u8().await?;
+    let mut payload = vec![0; length as usize - 5];
+    stream.read_exact(&mut payload).await?;
+
+    if opcode == OP_ERROR {
+        return Err(std::io::Error::new(
+            ErrorKind::Other,
+            format!("broker error: {}", String::from_utf8_lossy(&payload)),
+        ));
+    }
+
+    Ok((opcode, payload))
+}
+
+/// Picks the nonce out of an `OP_INFO` message, which is made up of the broker's name followed
+/// by the nonce.
+fn parse_info(payload: &[u8]) -> Result<&[u8], std::io::Error> {
+    let name_length = usize::from(*payload.first().unwrap_or(&0));
+
+    payload
+        .get(1 + name_length..)
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "truncated info message"))
+}
+
+fn auth_message(ident: &str, secret: &str, nonce: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(nonce);
+    hasher.update(secret.as_bytes());
+
+    let mut payload = Vec::new();
+    push_string(&mut payload, ident);
+    payload.extend_from_slice(&hasher.finalize());
+
+    message(OP_AUTH, &payload)
+}
+
+fn publish_message(ident: &str, channel: &str, data: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(data.len() + ident.len() + channel.len() + 2);
+    push_string(&mut payload, ident);
+    push_string(&mut payload, channel);
+    payload.extend_from_slice(data);
+
+    message(OP_PUBLISH, &payload)
+}
+
+/// Writes a string prefixed with its length as a single byte, truncating it if it's longer
+/// than a byte can represent.
+fn push_string(out: &mut Vec<u8>, s: &str) {
+    let s = &s.as_bytes()[..s.len().min(usize::from(u8::MAX))];
+    out.push(u8::try_from(s.len()).unwrap_or(u8::MAX));
+    out.extend_from_slice(s);
+}
+
+fn message(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let length = u32::try_from(payload.len() + 5).unwrap_or(u32::MAX);
+
+    let mut out = Vec::with_capacity(payload.len() + 5);
+    out.extend_from_slice(&length.to_be_bytes());
+    out.push(opcode);
+    out.extend_from_slice(payload);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{auth_message, parse_info, publish_message};
+
+    #[test]
+    fn encodes_publish() {
+        assert_eq!(
+            publish_message("ident", "chan", b"{}"),
+            b"\0\0\0\x12\x03\x05ident\x04chan{}"
+        );
+    }
+
+    #[test]
+    fn encodes_auth() {
+        let message = auth_message("ident", "secret", b"nonce");
+
+        assert_eq!(&message[..11], b"\0\0\0\x1f\x02\x05ident");
+        // sha1("noncesecret")
+        assert_eq!(
+            &message[11..],
+            b"\x5b\xbc\x30\x35\x54\xd5\xb3\xe9\x44\xf8\x8c\xc1\xcc\x76\xac\x6d\x4b\x75\x66\xfb"
+        );
+    }
+
+    #[test]
+    fn parses_info() {
+        assert_eq!(parse_info(b"\x06brokerNONCE").unwrap(), b"NONCE".as_slice());
+        assert!(parse_info(b"\x10broker").is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::ErrorKind,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rskafka::{
+    client::{
+        error::Error as KafkaError,
+        partition::{Compression, PartitionClient, UnknownTopicHandling},
+        ClientBuilder, Credentials, SaslConfig,
+    },
+    record::Record,
+};
+use serde::Serialize;
+use tokio_rustls::rustls::ClientConfig;
+use tracing::{info, warn};
+
+use crate::{
+    audit::{tls_config, AuditLog, AuditSink, EventRecord},
+    config::{KafkaMessages, KafkaSaslMechanism, KafkaSinkConfig},
+};
+
+/// Number of batches of records to hold on to whilst the brokers are unreachable, the oldest
+/// are dropped once this is exceeded.
+const MAX_PENDING: usize = 1024;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for the brokers to acknowledge a batch, the client otherwise retries
+/// retriable errors indefinitely.
+const PRODUCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Publishes logs to a Kafka topic, either a record per connection or a record per event,
+/// reconnecting with an exponential backoff if the brokers go away.
+///
+/// Records are keyed by connection ID and partitioned the same way as Kafka's own default
+/// partitioner, so every record for a connection lands on the same partition in order and
+/// consumers using the Java client's partitioner agree on where they are.
+pub struct KafkaSink {
+    config: KafkaSinkConfig,
+    tls: Option<Arc<ClientConfig>>,
+    partitions: Option<Vec<PartitionClient>>,
+    /// Records waiting to be published, batched up by the log they came from.
+    pending: VecDeque<Batch>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+/// Records for a single log, all of which share a key and so go to the same partition.
+struct Batch {
+    key: Vec<u8>,
+    records: Vec<Record>,
+}
+
+impl KafkaSink {
+    pub fn new(config: &KafkaSinkConfig) -> Result<Self, std::io::Error> {
+        let tls = if config.tls {
+            Some(tls_config(config.ca_file.as_deref())?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            tls,
+            partitions: None,
+            pending: VecDeque::new(),
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+        })
+    }
+
+    /// Splits `log` into the records to publish, leaving out any too large for the brokers to
+    /// accept and batching the rest so no single request goes over the limit either.
+    fn batches(&self, log: &AuditLog) -> Result<Vec<Batch>, std::io::Error> {
+        let values = match self.config.messages {
+            KafkaMessages::Connection => vec![(to_json(log)?, log.ts)],
+            KafkaMessages::Event => log
+                .events
+                .iter()
+                .map(|event| {
+                    let value = to_json(&EventRecord::new(log, event))?;
+
+                    Ok((value, event.ts.unwrap_or(log.ts)))
+                })
+                .collect::<Result<Vec<_>, std::io::Error>>()?,
+        };
+
+        let key = log.connection_id.to_string().into_bytes();
+        let mut batches = Vec::new();
+        let mut current = Batch {
+            key: key.clone(),
+            records: Vec::new(),
+        };
+        let mut current_size = 0;
+
+        for (value, ts) in values {
+            let size = key.len() + value.len();
+
+            if size > self.config.max_record_size {
+                warn!(
+                    connection_id = %log.connection_id,
+                    size,
+                    "Audit log record too large for Kafka, dropping it"
+                );
+                continue;
+            }
+
+            if current_size + size > self.config.max_record_size {
+                batches.push(std::mem::replace(
+                    &mut current,
+                    Batch {
+                        key: key.clone(),
+                        records: Vec::new(),
+                    },
+                ));
+                current_size = 0;
+            }
+
+            current_size += size;
+            current.records.push(Record {
+                key: Some(key.clone()),
+                value: Some(value),
+                headers: BTreeMap::new(),
+                timestamp: DateTime::<Utc>::from(SystemTime::from(ts)),
+            });
+        }
+
+        if !current.records.is_empty() {
+            batches.push(current);
+        }
+
+        Ok(batches)
+    }
+
+    /// Publishes as many pending batches as we can, reconnecting to the brokers if needed.
+    async fn drain(&mut self) {
+        while let Some(batch) = self.pending.front() {
+            let records = batch.records.clone();
+            let key = batch.key.clone();
+
+            let Some(partitions) = self.partitions().await else {
+                return;
+            };
+
+            let partition = &partitions[partition(&key, partitions.len())];
+            let res = tokio::time::timeout(
+                PRODUCE_TIMEOUT,
+                partition.produce(records, Compression::NoCompression),
+            )
+            .await;
+
+            match res {
+                Ok(Ok(_)) => {}
+                Ok(Err(error @ KafkaError::ServerError { .. })) => {
+                    // the brokers saw the batch and turned it down, retrying won't help
+                    warn!(%error, "Kafka rejected audit log records, dropping them");
+                }
+                Ok(Err(error)) => {
+                    warn!(%error, "Lost connection to Kafka");
+                    self.partitions = None;
+                    self.schedule_retry();
+                    return;
+                }
+                Err(_) => {
+                    warn!("Timed out publishing to Kafka");
+                    self.partitions = None;
+                    self.schedule_retry();
+                    return;
+                }
+            }
+
+            self.pending.pop_front();
+        }
+    }
+
+    /// Returns a client for each of the topic's partitions, connecting to the cluster if we
+    /// aren't already and aren't backing off.
+    async fn partitions(&mut self) -> Option<&[PartitionClient]> {
+        if self.partitions.is_none() {
+            if Instant::now() < self.next_attempt {
+                return None;
+            }
+
+            match tokio::time::timeout(CONNECT_TIMEOUT, connect(&self.config, self.tls.clone()))
+                .await
+            {
+                Ok(Ok(partitions)) => {
+                    info!(
+                        topic = %self.config.topic,
+                        partitions = partitions.len(),
+                        "Connected to Kafka"
+                    );
+                    self.partitions = Some(partitions);
+                    self.backoff = MIN_BACKOFF;
+                }
+                Ok(Err(error)) => {
+                    warn!(%error, "Failed to connect to Kafka");
+                    self.schedule_retry();
+                }
+                Err(_) => {
+                    warn!("Timed out connecting to Kafka");
+                    self.schedule_retry();
+                }
+            }
+        }
+
+        self.partitions.as_deref()
+    }
+
+    fn schedule_retry(&mut self) {
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[async_trait]
+impl AuditSink for KafkaSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        let batches = self.batches(log)?;
+
+        if batches.is_empty() {
+            return Ok(());
+        }
+
+        self.pending.extend(batches);
+
+        if self.pending.len() > MAX_PENDING {
+            warn!("Kafka unavailable for too long, dropping oldest audit log records");
+            self.pending.drain(..self.pending.len() - MAX_PENDING);
+        }
+
+        self.drain().await;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.drain().await;
+        Ok(())
+    }
+}
+
+/// Connects to the cluster and looks up the topic's partitions, returning a client for each of
+/// them ordered by partition number.
+async fn connect(
+    config: &KafkaSinkConfig,
+    tls: Option<Arc<ClientConfig>>,
+) -> Result<Vec<PartitionClient>, std::io::Error> {
+    let mut builder = ClientBuilder::new(config.brokers.clone());
+
+    if let Some(tls) = tls {
+        builder = builder.tls_config(tls);
+    }
+
+    if let Some(sasl) = &config.sasl {
+        let credentials = Credentials::new(sasl.username.clone(), sasl.password.clone());
+
+        builder = builder.sasl_config(match sasl.mechanism {
+            KafkaSaslMechanism::Plain => SaslConfig::Plain(credentials),
+            KafkaSaslMechanism::ScramSha256 => SaslConfig::ScramSha256(credentials),
+            KafkaSaslMechanism::ScramSha512 => SaslConfig::ScramSha512(credentials),
+        });
+    }
+
+    let client = builder.build().await.map_err(to_io_error)?;
+
+    let topic = client
+        .list_topics()
+        .await
+        .map_err(to_io_error)?
+        .into_iter()
+        .find(|v| v.name == config.topic)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("topic {} doesn't exist", config.topic),
+            )
+        })?;
+
+    if topic.partitions.is_empty() {
+        return Err(std::io::Error::new(
+            ErrorKind::NotFound,
+            format!("topic {} has no partitions", config.topic),
+        ));
+    }
+
+    let mut partitions = Vec::with_capacity(topic.partitions.len());
+
+    // partitions is a BTreeSet, so these come out in order
+    for partition in topic.partitions {
+        partitions.push(
+            client
+                .partition_client(config.topic.clone(), partition, UnknownTopicHandling::Error)
+                .await
+                .map_err(to_io_error)?,
+        );
+    }
+
+    Ok(partitions)
+}
+
+fn to_json(value: &impl Serialize) -> Result<Vec<u8>, std::io::Error> {
+    serde_json::to_vec(value).map_err(|e| std::io::Error::new(ErrorKind::Other, e))
+}
+
+fn to_io_error(error: KafkaError) -> std::io::Error {
+    std::io::Error::new(ErrorKind::Other, error)
+}
+
+/// Picks the partition for a record with `key` out of `count` of them, the same way as Kafka's
+/// default partitioner does.
+fn partition(key: &[u8], count: usize) -> usize {
+    usize::try_from(murmur2(key) & 0x7fff_ffff).unwrap_or_default() % count
+}
+
+/// The 32-bit MurmurHash2 variant Kafka partitions keys by, which differs from the reference
+/// implementation in its seed.
+#[allow(clippy::cast_possible_truncation)]
+fn murmur2(data: &[u8]) -> u32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+
+    let mut chunks = data.chunks_exact(4);
+
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+
+    let rest = chunks.remainder();
+
+    if rest.len() >= 3 {
+        h ^= u32::from(rest[2]) << 16;
+    }
+
+    if rest.len() >= 2 {
+        h ^= u32::from(rest[1]) << 8;
+    }
+
+    if let Some(first) = rest.first() {
+        h ^= u32::from(*first);
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+
+    h
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::{murmur2, partition};
+
+    // test vectors from Kafka's own `UtilsTest`
+    #[test_case("21", -973_932_308)]
+    #[test_case("foobar", -790_332_482)]
+    #[test_case("a-little-bit-long-string", -985_981_536)]
+    #[test_case("a-little-bit-longer-string", -1_486_304_829)]
+    #[test_case("lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8", -58_897_971)]
+    #[test_case("abc", 479_470_107)]
+    fn matches_kafka_murmur2(input: &str, expected: i32) {
+        assert_eq!(
+            murmur2(input.as_bytes()),
+            u32::from_ne_bytes(expected.to_ne_bytes())
+        );
+    }
+
+    #[test]
+    fn partitions_by_key() {
+        let key = b"464d87c9-e8fc-4d24-ab6f-34ee67b094f5";
+
+        assert_eq!(partition(key, 1), 0);
+        assert!((0..1000).all(|count| partition(key, count + 1) <= count));
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io::ErrorKind,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio_rustls::TlsConnector;
+use tracing::{info, warn};
+
+use crate::{
+    audit::{connect_stream, tls_config, topic_messages, AuditLog, AuditSink, BoxedStream},
+    config::{MqttMessages, MqttSinkConfig},
+};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PINGREQ: u8 = 0xc0;
+const PINGRESP: u8 = 0xd0;
+
+/// MQTT 3.1.1.
+const PROTOCOL_LEVEL: u8 = 4;
+const FLAG_USERNAME: u8 = 0x80;
+const FLAG_PASSWORD: u8 = 0x40;
+const FLAG_CLEAN_SESSION: u8 = 0x02;
+
+/// Seconds the broker should wait to hear from us before giving up on the connection, zero
+/// turns it off as we only talk to the broker when there's something to publish.
+const KEEP_ALIVE: u16 = 0;
+
+/// Largest remaining length a packet can have, as it's encoded in at most four bytes.
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+
+/// Largest packet we'll accept from the broker, acknowledgements are tiny.
+const MAX_INCOMING_LENGTH: usize = 64 * 1024;
+
+/// Number of messages to hold on to whilst the broker is unreachable, the oldest are dropped
+/// once this is exceeded.
+const MAX_PENDING: usize = 4096;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for the broker to confirm it's received what we've published.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Publishes logs to a topic on an MQTT 3.1.1 broker, or each event to a topic of its own
+/// beneath it, reconnecting with an exponential backoff if the broker goes away.
+///
+/// Messages are published in batches and only let go of once the broker has acknowledged
+/// them, either with a `PUBACK` for each at QoS 1 or a `PINGRESP` to a `PINGREQ` sent after
+/// them at QoS 0, so nothing is lost to a connection that died whilst idle.
+pub struct MqttSink {
+    config: MqttSinkConfig,
+    tls: Option<TlsConnector>,
+    stream: Option<BufReader<BoxedStream>>,
+    pending: VecDeque<Message>,
+    next_packet_id: u16,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+struct Message {
+    topic: String,
+    payload: Vec<u8>,
+}
+
+impl MqttSink {
+    pub fn new(config: &MqttSinkConfig) -> Result<Self, std::io::Error> {
+        if config.qos > 1 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("unsupported MQTT QoS {}, expected 0 or 1", config.qos),
+            ));
+        }
+
+        let tls = if config.tls {
+            Some(TlsConnector::from(tls_config(config.ca_file.as_deref())?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            tls,
+            stream: None,
+            pending: VecDeque::new(),
+            next_packet_id: 1,
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+        })
+    }
+
+    /// Publishes all pending messages if we can, reconnecting to the broker if needed.
+    async fn drain(&mut self) {
+        if self.pending.is_empty() || !self.connect().await {
+            return;
+        }
+
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+
+        let mut packets = Vec::new();
+        let mut unacked = HashSet::new();
+
+        for message in &self.pending {
+            let packet_id = (self.config.qos > 0).then(|| {
+                let id = self.next_packet_id;
+                // zero isn't a valid packet ID
+                self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+                unacked.insert(id);
+                id
+            });
+
+            packets.extend(publish_packet(&message.topic, &message.payload, packet_id));
+        }
+
+        if unacked.is_empty() {
+            packets.extend(packet(PINGREQ, &[]));
+        }
+
+        let res = tokio::time::timeout(REPLY_TIMEOUT, publish(stream, &packets, unacked)).await;
+
+        match res {
+            Ok(Ok(())) => self.pending.clear(),
+            Ok(Err(error)) => {
+                warn!(%error, "Lost connection to MQTT broker");
+                self.stream = None;
+                self.schedule_retry();
+            }
+            Err(_) => {
+                warn!("Timed out waiting for MQTT broker");
+                self.stream = None;
+                self.schedule_retry();
+            }
+        }
+    }
+
+    /// Connects to the broker if we aren't already and aren't backing off, returning whether
+    /// we're connected.
+    async fn connect(&mut self) -> bool {
+        if self.stream.is_none() {
+            if Instant::now() < self.next_attempt {
+                return false;
+            }
+
+            match tokio::time::timeout(CONNECT_TIMEOUT, connect(&self.config, self.tls.as_ref()))
+                .await
+            {
+                Ok(Ok(stream)) => {
+                    info!(address = %self.config.address, "Connected to MQTT broker");
+                    self.stream = Some(stream);
+                    self.backoff = MIN_BACKOFF;
+                }
+                Ok(Err(error)) => {
+                    warn!(%error, "Failed to connect to MQTT broker");
+                    self.schedule_retry();
+                }
+                Err(_) => {
+                    warn!("Timed out connecting to MQTT broker");
+                    self.schedule_retry();
+                }
+            }
+        }
+
+        self.stream.is_some()
+    }
+
+    fn schedule_retry(&mut self) {
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[async_trait]
+impl AuditSink for MqttSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        let per_event = self.config.messages == MqttMessages::Event;
+
+        for (event_type, payload) in topic_messages(log, per_event)? {
+            let topic = match event_type {
+                Some(event_type) => format!("{}/{event_type}", self.config.topic),
+                None => self.config.topic.clone(),
+            };
+
+            // room for the fixed header's extras, the topic's length and the packet ID
+            if topic.len() + payload.len() + 4 > MAX_REMAINING_LENGTH {
+                warn!(
+                    size = payload.len(),
+                    "Audit log message too large for MQTT, dropping it"
+                );
+                continue;
+            }
+
+            self.pending.push_back(Message { topic, payload });
+        }
+
+        if self.pending.len() > MAX_PENDING {
+            warn!("MQTT broker unavailable for too long, dropping oldest audit log messages");
+            self.pending.drain(..self.pending.len() - MAX_PENDING);
+        }
+
+        self.drain().await;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.drain().await;
+        Ok(())
+    }
+}
+
+/// Connects to the broker and waits for it to accept us.
+async fn connect(
+    config: &MqttSinkConfig,
+    tls: Option<&TlsConnector>,
+) -> Result<BufReader<BoxedStream>, std::io::Error> {
+    let mut stream = BufReader::new(connect_stream(&config.address, tls).await?);
+
+    stream.write_all(&connect_packet(config)).await?;
+    stream.flush().await?;
+
+    let (header, body) = read_packet(&mut stream).await?;
+
+    if header & 0xf0 != CONNACK {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "expected CONNACK from broker, got packet type {}",
+                header >> 4
+            ),
+        ));
+    }
+
+    match body.get(1) {
+        Some(0) => Ok(stream),
+        Some(code) => Err(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("broker refused connection: {}", connack_reason(*code)),
+        )),
+        None => Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "truncated CONNACK",
+        )),
+    }
+}
+
+fn connack_reason(code: u8) -> &'static str {
+    match code {
+        1 => "unacceptable protocol version",
+        2 => "client ID rejected",
+        3 => "server unavailable",
+        4 => "bad username or password",
+        5 => "not authorised",
+        _ => "unknown reason",
+    }
+}
+
+/// Writes out `packets`, then waits for the broker to acknowledge each packet ID in
+/// `unacked`, or to answer the `PINGREQ` at the end of them if there aren't any.
+async fn publish(
+    stream: &mut BufReader<BoxedStream>,
+    packets: &[u8],
+    mut unacked: HashSet<u16>,
+) -> Result<(), std::io::Error> {
+    let ping = unacked.is_empty();
+
+    stream.write_all(packets).await?;
+    stream.flush().await?;
+
+    loop {
+        let (header, body) = read_packet(stream).await?;
+
+        match header & 0xf0 {
+            PUBACK if !ping => {
+                if let [high, low, ..] = body[..] {
+                    unacked.remove(&u16::from_be_bytes([high, low]));
+                }
+
+                if unacked.is_empty() {
+                    return Ok(());
+                }
+            }
+            PINGRESP if ping => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Reads a packet from the broker, returning its first byte and the rest of it after its
+/// length.
+async fn read_packet(
+    stream: &mut BufReader<impl AsyncRead + Unpin>,
+) -> Result<(u8, Vec<u8>), std::io::Error> {
+    let header = stream.read_u8().await?;
+
+    let mut length = 0;
+
+    for i in 0..4 {
+        let byte = stream.read_u8().await?;
+        length |= usize::from(byte & 0x7f) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            break;
+        } else if i == 3 {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "invalid packet length",
+            ));
+        }
+    }
+
+    if length > MAX_INCOMING_LENGTH {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("packet from broker too long, {length} bytes"),
+        ));
+    }
+
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await?;
+
+    Ok((header, body))
+}
+
+fn connect_packet(config: &MqttSinkConfig) -> Vec<u8> {
+    let mut flags = FLAG_CLEAN_SESSION;
+    let mut payload = Vec::new();
+
+    push_string(
+        &mut payload,
+        config.client_id.as_deref().unwrap_or_default(),
+    );
+
+    if let Some(username) = &config.username {
+        flags |= FLAG_USERNAME;
+        push_string(&mut payload, username);
+
+        // a password can only be sent along with a username
+        if let Some(password) = &config.password {
+            flags |= FLAG_PASSWORD;
+            push_string(&mut payload, password);
+        }
+    }
+
+    let mut body = Vec::with_capacity(payload.len() + 10);
+    push_string(&mut body, "MQTT");
+    body.push(PROTOCOL_LEVEL);
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE.to_be_bytes());
+    body.extend_from_slice(&payload);
+
+    packet(CONNECT, &body)
+}
+
+/// A `PUBLISH` packet, at QoS 1 if it has a packet ID or QoS 0 if it doesn't.
+fn publish_packet(topic: &str, payload: &[u8], packet_id: Option<u16>) -> Vec<u8> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+    push_string(&mut body, topic);
+
+    let header = match packet_id {
+        Some(id) => {
+            body.extend_from_slice(&id.to_be_bytes());
+            PUBLISH | 0x02
+        }
+        None => PUBLISH,
+    };
+
+    body.extend_from_slice(payload);
+
+    packet(header, &body)
+}
+
+/// Writes a string prefixed with its length as two bytes, truncating it if it's longer than
+/// two bytes can represent.
+fn push_string(out: &mut Vec<u8>, s: &str) {
+    let s = &s.as_bytes()[..s.len().min(usize::from(u16::MAX))];
+    out.extend_from_slice(&u16::try_from(s.len()).unwrap_or(u16::MAX).to_be_bytes());
+    out.extend_from_slice(s);
+}
+
+/// Prefixes `body` with the packet's first byte and its length, which the caller makes sure
+/// fits in [`MAX_REMAINING_LENGTH`].
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 5);
+    out.push(header);
+
+    let mut length = body.len();
+
+    loop {
+        let mut byte = u8::try_from(length & 0x7f).unwrap_or_default();
+        length >>= 7;
+
+        if length > 0 {
+            byte |= 0x80;
+        }
+
+        out.push(byte);
+
+        if length == 0 {
+            break;
+        }
+    }
+
+    out.extend_from_slice(body);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{packet, publish_packet, read_packet, PINGREQ};
+
+    #[test]
+    fn encodes_publish() {
+        assert_eq!(publish_packet("a/b", b"{}", None), b"\x30\x07\x00\x03a/b{}");
+        assert_eq!(
+            publish_packet("a/b", b"{}", Some(10)),
+            b"\x32\x09\x00\x03a/b\x00\x0a{}"
+        );
+    }
+
+    #[tokio::test]
+    async fn round_trips_lengths() {
+        assert_eq!(packet(PINGREQ, &[]), b"\xc0\x00");
+
+        for length in [0, 127, 128, 16_383, 16_384, 60_000] {
+            let encoded = packet(0x30, &vec![1; length]);
+            let mut reader = tokio::io::BufReader::new(encoded.as_slice());
+
+            let (header, body) = read_packet(&mut reader).await.unwrap();
+            assert_eq!(header, 0x30);
+            assert_eq!(body.len(), length);
+        }
+    }
+}
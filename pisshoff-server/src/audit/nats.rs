@@ -0,0 +1,361 @@
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+use tracing::{info, warn};
+
+use crate::{
+    audit::{tls_config, topic_messages, upgrade_stream, AuditLog, AuditSink, BoxedStream},
+    config::{NatsMessages, NatsSinkConfig},
+};
+
+/// Number of messages to hold on to whilst the server is unreachable, the oldest are dropped
+/// once this is exceeded.
+const MAX_PENDING: usize = 4096;
+
+/// Longest line we'll accept from the server, `INFO` lines are the only long ones.
+const MAX_LINE_LENGTH: u64 = 64 * 1024;
+
+/// Largest payload the server accepts if it doesn't say otherwise.
+const DEFAULT_MAX_PAYLOAD: usize = 1024 * 1024;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for the server to confirm it's received what we've published.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Publishes logs to a subject on a [NATS] server, or each event to a subject of its own
+/// beneath it, reconnecting with an exponential backoff if the server goes away.
+///
+/// Messages are published in batches followed by a `PING`, and are only let go of once the
+/// server's `PONG` confirms it's read them, so nothing is lost to a connection that died
+/// whilst idle. Messages can be published again if the connection drops before the `PONG`
+/// arrives.
+///
+/// [NATS]: https://docs.nats.io/reference/reference-protocols/nats-protocol
+pub struct NatsSink {
+    config: NatsSinkConfig,
+    tls: Option<TlsConnector>,
+    connection: Option<Connection>,
+    pending: VecDeque<Message>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+struct Connection {
+    stream: BufReader<BoxedStream>,
+    /// Largest payload the server accepts, larger messages get the connection closed.
+    max_payload: usize,
+}
+
+struct Message {
+    subject: String,
+    payload: Vec<u8>,
+}
+
+/// The parts of the server's `INFO` we need.
+#[derive(Deserialize)]
+struct ServerInfo {
+    #[serde(default)]
+    tls_required: bool,
+    #[serde(default)]
+    max_payload: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ConnectOptions<'a> {
+    verbose: bool,
+    pedantic: bool,
+    tls_required: bool,
+    name: &'static str,
+    lang: &'static str,
+    version: &'static str,
+    protocol: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pass: Option<&'a str>,
+}
+
+impl NatsSink {
+    pub fn new(config: &NatsSinkConfig) -> Result<Self, std::io::Error> {
+        let tls = if config.tls {
+            Some(TlsConnector::from(tls_config(config.ca_file.as_deref())?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            tls,
+            connection: None,
+            pending: VecDeque::new(),
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+        })
+    }
+
+    /// Publishes all pending messages if we can, reconnecting to the server if needed.
+    async fn drain(&mut self) {
+        if self.pending.is_empty() || !self.connect().await {
+            return;
+        }
+
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+
+        let max_payload = connection.max_payload;
+        self.pending.retain(|message| {
+            let fits = message.payload.len() <= max_payload;
+
+            if !fits {
+                warn!(
+                    size = message.payload.len(),
+                    max_payload, "Audit log message too large for NATS server, dropping it"
+                );
+            }
+
+            fits
+        });
+
+        let res = tokio::time::timeout(REPLY_TIMEOUT, publish(connection, &self.pending)).await;
+
+        match res {
+            Ok(Ok(())) => self.pending.clear(),
+            Ok(Err(error)) => {
+                warn!(%error, "Lost connection to NATS server");
+                self.connection = None;
+                self.schedule_retry();
+            }
+            Err(_) => {
+                warn!("Timed out waiting for NATS server");
+                self.connection = None;
+                self.schedule_retry();
+            }
+        }
+    }
+
+    /// Connects to the server if we aren't already and aren't backing off, returning whether
+    /// we're connected.
+    async fn connect(&mut self) -> bool {
+        if self.connection.is_none() {
+            if Instant::now() < self.next_attempt {
+                return false;
+            }
+
+            match tokio::time::timeout(CONNECT_TIMEOUT, connect(&self.config, self.tls.as_ref()))
+                .await
+            {
+                Ok(Ok(connection)) => {
+                    info!(address = %self.config.address, "Connected to NATS server");
+                    self.connection = Some(connection);
+                    self.backoff = MIN_BACKOFF;
+                }
+                Ok(Err(error)) => {
+                    warn!(%error, "Failed to connect to NATS server");
+                    self.schedule_retry();
+                }
+                Err(_) => {
+                    warn!("Timed out connecting to NATS server");
+                    self.schedule_retry();
+                }
+            }
+        }
+
+        self.connection.is_some()
+    }
+
+    fn schedule_retry(&mut self) {
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[async_trait]
+impl AuditSink for NatsSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        let per_event = self.config.messages == NatsMessages::Event;
+
+        for (event_type, payload) in topic_messages(log, per_event)? {
+            let subject = match event_type {
+                Some(event_type) => format!("{}.{event_type}", self.config.subject),
+                None => self.config.subject.clone(),
+            };
+
+            self.pending.push_back(Message { subject, payload });
+        }
+
+        if self.pending.len() > MAX_PENDING {
+            warn!("NATS server unavailable for too long, dropping oldest audit log messages");
+            self.pending.drain(..self.pending.len() - MAX_PENDING);
+        }
+
+        self.drain().await;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.drain().await;
+        Ok(())
+    }
+}
+
+/// Connects and authenticates to the server, upgrading the connection to TLS once the server
+/// has sent its `INFO` if configured to.
+async fn connect(
+    config: &NatsSinkConfig,
+    tls: Option<&TlsConnector>,
+) -> Result<Connection, std::io::Error> {
+    let mut stream = BufReader::new(TcpStream::connect(&config.address).await?);
+
+    let line = read_line(&mut stream).await?;
+    let info = line
+        .strip_prefix("INFO ")
+        .ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("expected INFO from server, got {line:?}"),
+            )
+        })
+        .and_then(|info| {
+            serde_json::from_str::<ServerInfo>(info)
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+        })?;
+
+    // the server doesn't send anything else until we've started TLS or sent CONNECT, so
+    // nothing's lost from the buffer
+    let stream: BoxedStream = match tls {
+        Some(tls) => upgrade_stream(stream.into_inner(), &config.address, tls).await?,
+        None if info.tls_required => {
+            return Err(std::io::Error::new(
+                ErrorKind::PermissionDenied,
+                "server requires TLS",
+            ))
+        }
+        None => Box::new(stream.into_inner()),
+    };
+
+    let mut connection = Connection {
+        stream: BufReader::new(stream),
+        max_payload: info.max_payload.unwrap_or(DEFAULT_MAX_PAYLOAD),
+    };
+
+    let options = serde_json::to_string(&ConnectOptions {
+        verbose: false,
+        pedantic: false,
+        tls_required: tls.is_some(),
+        name: "pisshoff",
+        lang: "rust",
+        version: env!("CARGO_PKG_VERSION"),
+        protocol: 0,
+        auth_token: config.token.as_deref(),
+        user: config.username.as_deref(),
+        pass: config.password.as_deref(),
+    })
+    .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+    connection
+        .stream
+        .write_all(format!("CONNECT {options}\r\n").as_bytes())
+        .await?;
+
+    // a bad CONNECT gets an -ERR back before the PONG
+    publish(&mut connection, &VecDeque::new()).await?;
+
+    Ok(connection)
+}
+
+/// Publishes `messages` in a single write, then waits for the server to answer a `PING`.
+async fn publish(
+    connection: &mut Connection,
+    messages: &VecDeque<Message>,
+) -> Result<(), std::io::Error> {
+    let mut out = Vec::new();
+
+    for message in messages {
+        out.extend_from_slice(&pub_command(&message.subject, &message.payload));
+    }
+
+    out.extend_from_slice(b"PING\r\n");
+
+    let stream = &mut connection.stream;
+    stream.write_all(&out).await?;
+    stream.flush().await?;
+
+    loop {
+        let line = read_line(stream).await?;
+
+        match line.split_whitespace().next() {
+            Some("PONG") => return Ok(()),
+            Some("PING") => {
+                stream.write_all(b"PONG\r\n").await?;
+                stream.flush().await?;
+            }
+            Some("-ERR") => {
+                return Err(std::io::Error::new(
+                    ErrorKind::Other,
+                    format!("server error: {}", line.trim_start_matches("-ERR ")),
+                ))
+            }
+            // +OK, and any INFO the server sends as the cluster changes
+            _ => {}
+        }
+    }
+}
+
+/// Reads a line from the server, without its `\r\n`.
+async fn read_line(stream: &mut BufReader<impl AsyncRead + Unpin>) -> std::io::Result<String> {
+    let mut line = String::new();
+
+    if (&mut *stream)
+        .take(MAX_LINE_LENGTH)
+        .read_line(&mut line)
+        .await?
+        == 0
+    {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+
+    if !line.ends_with('\n') {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "line from server too long",
+        ));
+    }
+
+    Ok(line.trim_end().to_string())
+}
+
+fn pub_command(subject: &str, payload: &[u8]) -> Vec<u8> {
+    let mut out = format!("PUB {subject} {}\r\n", payload.len()).into_bytes();
+    out.extend_from_slice(payload);
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::pub_command;
+
+    #[test]
+    fn encodes_pub() {
+        assert_eq!(
+            pub_command("pisshoff.audit.exec-command", b"{}"),
+            b"PUB pisshoff.audit.exec-command 2\r\n{}\r\n"
+        );
+    }
+}
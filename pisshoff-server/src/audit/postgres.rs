@@ -0,0 +1,462 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io::ErrorKind,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, PoolError, RecyclingMethod, Runtime};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio_postgres::{config::SslMode, types::Json, NoTls};
+use tokio_postgres_rustls::MakeRustlsConnect;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    audit::{sqlite::Credential, tls_config, AuditLog, AuditLogAction, AuditSink},
+    config::PostgresSinkConfig,
+};
+
+/// The same tables as the SQLite sink, with Postgres' own types.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS connection (
+    id UUID PRIMARY KEY,
+    started_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ,
+    duration_ms BIGINT,
+    peer_address TEXT,
+    host TEXT NOT NULL,
+    client_version TEXT,
+    hassh TEXT,
+    country TEXT,
+    city TEXT,
+    asn BIGINT,
+    org TEXT,
+    reverse_dns TEXT,
+    sensor TEXT
+);
+
+CREATE INDEX IF NOT EXISTS connection_started_at ON connection (started_at);
+
+CREATE TABLE IF NOT EXISTS event (
+    id BIGSERIAL PRIMARY KEY,
+    connection_id UUID NOT NULL REFERENCES connection (id),
+    ts TIMESTAMPTZ,
+    offset_ms BIGINT NOT NULL,
+    type TEXT NOT NULL,
+    data JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS event_connection_id ON event (connection_id);
+CREATE INDEX IF NOT EXISTS event_type_ts ON event (type, ts);
+
+CREATE TABLE IF NOT EXISTS credential (
+    id BIGSERIAL PRIMARY KEY,
+    connection_id UUID NOT NULL REFERENCES connection (id),
+    ts TIMESTAMPTZ,
+    method TEXT NOT NULL,
+    username TEXT,
+    password TEXT,
+    key_kind TEXT,
+    key_fingerprint TEXT
+);
+
+CREATE INDEX IF NOT EXISTS credential_ts ON credential (ts);
+
+CREATE TABLE IF NOT EXISTS tag (
+    connection_id UUID NOT NULL REFERENCES connection (id),
+    tag TEXT NOT NULL,
+    PRIMARY KEY (connection_id, tag)
+);
+
+CREATE INDEX IF NOT EXISTS tag_tag ON tag (tag);
+";
+
+/// Key of the advisory lock held whilst creating the tables, as `CREATE TABLE IF NOT EXISTS`
+/// can still fail when a fleet of sensors all start at once.
+const SCHEMA_LOCK: i64 = 0x7069_7373_686f_6666;
+
+/// Number of logs to hold on to whilst the database is unreachable, the oldest are dropped once
+/// this is exceeded.
+const MAX_PENDING: usize = 65_536;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const INSERT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Writes logs into a PostgreSQL database for central analysis, normalised into the same
+/// tables as the SQLite sink. Logs are inserted in batches, with a statement per table per
+/// batch, spread over a pool of connections and retried with an exponential backoff if the
+/// database goes away.
+pub struct PostgresSink {
+    config: PostgresSinkConfig,
+    pool: Pool,
+    schema_created: bool,
+    /// Logs waiting to be inserted, already split into rows.
+    pending: VecDeque<Rows>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+impl PostgresSink {
+    pub fn new(config: &PostgresSinkConfig) -> Result<Self, std::io::Error> {
+        let mut pg_config = config
+            .url
+            .parse::<tokio_postgres::Config>()
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+        pg_config.connect_timeout(CONNECT_TIMEOUT);
+
+        let manager_config = ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        };
+
+        let manager = if config.tls {
+            pg_config.ssl_mode(SslMode::Require);
+            let tls = MakeRustlsConnect::new((*tls_config(config.ca_file.as_deref())?).clone());
+            Manager::from_config(pg_config, tls, manager_config)
+        } else {
+            Manager::from_config(pg_config, NoTls, manager_config)
+        };
+
+        let pool = Pool::builder(manager)
+            .max_size(config.pool_size.max(1))
+            .runtime(Runtime::Tokio1)
+            .build()
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        Ok(Self {
+            config: config.clone(),
+            pool,
+            schema_created: false,
+            pending: VecDeque::new(),
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+        })
+    }
+
+    /// Inserts as many pending logs as we can, creating the tables first if we haven't yet.
+    async fn drain(&mut self) {
+        while !self.pending.is_empty() {
+            if Instant::now() < self.next_attempt {
+                return;
+            }
+
+            if !self.schema_created {
+                match tokio::time::timeout(CONNECT_TIMEOUT, create_schema(&self.pool)).await {
+                    Ok(Ok(())) => {
+                        info!("Connected to Postgres");
+                        self.schema_created = true;
+                    }
+                    Ok(Err(error)) => {
+                        warn!(%error, "Failed to connect to Postgres");
+                        self.schedule_retry();
+                        return;
+                    }
+                    Err(_) => {
+                        warn!("Timed out connecting to Postgres");
+                        self.schedule_retry();
+                        return;
+                    }
+                }
+            }
+
+            // a batch for each connection in the pool, inserted side by side
+            let mut batches = Vec::new();
+
+            while batches.len() < self.config.pool_size.max(1) && !self.pending.is_empty() {
+                let count = self.pending.len().min(self.config.batch_size.max(1));
+                batches.push(self.pending.drain(..count).collect::<Vec<_>>());
+            }
+
+            let results = futures::future::join_all(
+                batches
+                    .iter()
+                    .map(|batch| tokio::time::timeout(INSERT_TIMEOUT, insert(&self.pool, batch))),
+            )
+            .await;
+
+            let mut failed = false;
+
+            // batches worth retrying go back on the front of the queue, last first so they stay
+            // in order
+            for (batch, result) in batches.into_iter().zip(results).rev() {
+                match result {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(error)) if !retriable(&error) => {
+                        warn!(%error, logs = batch.len(), "Postgres rejected audit logs, dropping them");
+                        continue;
+                    }
+                    Ok(Err(error)) => warn!(%error, "Failed to insert audit logs into Postgres"),
+                    Err(_) => warn!("Timed out inserting audit logs into Postgres"),
+                }
+
+                failed = true;
+
+                for rows in batch.into_iter().rev() {
+                    self.pending.push_front(rows);
+                }
+            }
+
+            if failed {
+                self.schedule_retry();
+                return;
+            }
+
+            self.backoff = MIN_BACKOFF;
+        }
+    }
+
+    fn schedule_retry(&mut self) {
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[async_trait]
+impl AuditSink for PostgresSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        self.pending.push_back(Rows::new(log)?);
+
+        if self.pending.len() > MAX_PENDING {
+            warn!("Postgres unavailable for too long, dropping oldest audit logs");
+            self.pending.drain(..self.pending.len() - MAX_PENDING);
+        }
+
+        if self.pending.len() >= self.config.batch_size {
+            self.drain().await;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.drain().await;
+        Ok(())
+    }
+}
+
+/// A log split into the rows it's stored as, each an object keyed by column name so a whole
+/// batch can be inserted at once with `jsonb_populate_recordset`.
+struct Rows {
+    connection_id: Uuid,
+    connection: Value,
+    events: Vec<Value>,
+    credentials: Vec<Value>,
+    tags: Vec<Value>,
+}
+
+impl Rows {
+    fn new(log: &AuditLog) -> Result<Self, std::io::Error> {
+        let connection_id = log.connection_id;
+        let geoip = log.geoip.as_ref();
+
+        let connection = json!({
+            "id": connection_id,
+            "started_at": format_timestamp(log.ts),
+            "ended_at": log.ended_at.and_then(format_timestamp),
+            "duration_ms": log.duration_ms,
+            "peer_address": log.peer_address.map(|v| v.to_string()),
+            "host": log.host,
+            "client_version": log.client_handshake.as_ref().map(|v| &v.version),
+            "hassh": log.client_handshake.as_ref().map(|v| &v.hassh),
+            "country": geoip.and_then(|v| v.country.as_deref()),
+            "city": geoip.and_then(|v| v.city.as_deref()),
+            "asn": geoip.and_then(|v| v.asn),
+            "org": geoip.and_then(|v| v.org.as_deref()),
+            "reverse_dns": log.reverse_dns,
+            "sensor": log.sensor,
+        });
+
+        let mut events = Vec::with_capacity(log.events.len());
+        let mut credentials = Vec::new();
+
+        for event in &log.events {
+            let ts = event.ts.and_then(format_timestamp);
+
+            events.push(json!({
+                "connection_id": connection_id,
+                "ts": ts,
+                "offset_ms": event.offset_ms,
+                "type": <&'static str>::from(&event.action),
+                "data": serde_json::to_value(&event.action)
+                    .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?,
+            }));
+
+            if let AuditLogAction::LoginAttempt(attempt) = &event.action {
+                let credential = Credential::new(attempt);
+
+                credentials.push(json!({
+                    "connection_id": connection_id,
+                    "ts": ts,
+                    "method": credential.method,
+                    "username": credential.username,
+                    "password": credential.password,
+                    "key_kind": credential.key_kind,
+                    "key_fingerprint": credential.key_fingerprint,
+                }));
+            }
+        }
+
+        let tags = log
+            .tags
+            .iter()
+            .map(|tag| json!({ "connection_id": connection_id, "tag": tag }))
+            .collect();
+
+        Ok(Self {
+            connection_id,
+            connection,
+            events,
+            credentials,
+            tags,
+        })
+    }
+}
+
+async fn create_schema(pool: &Pool) -> Result<(), PoolError> {
+    let mut client = pool.get().await?;
+    let tx = client.transaction().await?;
+
+    tx.execute("SELECT pg_advisory_xact_lock($1)", &[&SCHEMA_LOCK])
+        .await?;
+    tx.batch_execute(SCHEMA).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Inserts `batch` in a single transaction.
+async fn insert(pool: &Pool, batch: &[Rows]) -> Result<(), PoolError> {
+    let mut client = pool.get().await?;
+    let tx = client.transaction().await?;
+
+    let connections = batch.iter().map(|v| &v.connection).collect::<Vec<_>>();
+
+    // logs forwarded from sensors can be delivered more than once, so the rest of the rows are
+    // only inserted for connections that weren't already stored
+    let inserted = tx
+        .query(
+            "INSERT INTO connection \
+             SELECT * FROM jsonb_populate_recordset(NULL::connection, $1) \
+             ON CONFLICT (id) DO NOTHING RETURNING id",
+            &[&Json(&connections)],
+        )
+        .await?
+        .into_iter()
+        .map(|row| row.get::<_, Uuid>(0))
+        .collect::<HashSet<_>>();
+
+    let batch = batch
+        .iter()
+        .filter(|v| inserted.contains(&v.connection_id))
+        .collect::<Vec<_>>();
+
+    let events = batch.iter().flat_map(|v| &v.events).collect::<Vec<_>>();
+    let credentials = batch
+        .iter()
+        .flat_map(|v| &v.credentials)
+        .collect::<Vec<_>>();
+    let tags = batch.iter().flat_map(|v| &v.tags).collect::<Vec<_>>();
+
+    for (rows, statement) in [
+        (
+            events,
+            "INSERT INTO event (connection_id, ts, offset_ms, type, data) \
+             SELECT connection_id, ts, offset_ms, type, data \
+             FROM jsonb_populate_recordset(NULL::event, $1)",
+        ),
+        (
+            credentials,
+            "INSERT INTO credential (connection_id, ts, method, username, password, key_kind, \
+             key_fingerprint) \
+             SELECT connection_id, ts, method, username, password, key_kind, key_fingerprint \
+             FROM jsonb_populate_recordset(NULL::credential, $1)",
+        ),
+        (
+            tags,
+            "INSERT INTO tag (connection_id, tag) \
+             SELECT connection_id, tag FROM jsonb_populate_recordset(NULL::tag, $1)",
+        ),
+    ] {
+        if !rows.is_empty() {
+            tx.execute(statement, &[&Json(&rows)]).await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Whether a failed insert is worth retrying, rather than the database having understood the
+/// rows and turned them down, in which case it'd only turn them down again.
+fn retriable(error: &PoolError) -> bool {
+    let PoolError::Backend(error) = error else {
+        return true;
+    };
+
+    match error.as_db_error() {
+        // connection exceptions, transaction rollbacks, insufficient resources and operator
+        // intervention, such as the database restarting
+        Some(error) => matches!(&error.code().code()[..2], "08" | "40" | "53" | "57"),
+        None => true,
+    }
+}
+
+fn format_timestamp(ts: OffsetDateTime) -> Option<String> {
+    ts.format(&Rfc3339).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::Rows;
+    use crate::audit::{AuditLog, AuditLogAction, LoginAttemptEvent};
+
+    #[test]
+    fn splits_log_into_rows() {
+        let mut log = AuditLog {
+            connection_id: uuid::Uuid::new_v4(),
+            sensor: Some("sensor-1".into()),
+            ..AuditLog::default()
+        };
+        log.push_action(AuditLogAction::LoginAttempt(LoginAttemptEvent::PublicKey {
+            kind: "ssh-ed25519".into(),
+            fingerprint: Box::from("SHA256:abc"),
+        }));
+        log.tag("miner-recon");
+        log.finish();
+
+        let rows = Rows::new(&log).unwrap();
+        let connection_id = log.connection_id.to_string();
+
+        assert_eq!(rows.connection["id"], connection_id);
+        assert_eq!(rows.connection["sensor"], "sensor-1");
+        assert!(rows.connection["ended_at"].is_string());
+
+        assert_eq!(rows.events.len(), 1);
+        assert_eq!(rows.events[0]["type"], "login-attempt");
+        assert_eq!(rows.events[0]["data"]["kind"], "ssh-ed25519");
+
+        assert_eq!(
+            rows.credentials,
+            [json!({
+                "connection_id": connection_id,
+                "ts": rows.events[0]["ts"],
+                "method": "public-key",
+                "username": null,
+                "password": null,
+                "key_kind": "ssh-ed25519",
+                "key_fingerprint": "SHA256:abc",
+            })]
+        );
+
+        assert_eq!(
+            rows.tags,
+            [json!({ "connection_id": connection_id, "tag": "miner-recon" })]
+        );
+    }
+}
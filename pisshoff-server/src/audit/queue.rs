@@ -0,0 +1,355 @@
+//! Logs waiting for the writer, held in a bounded queue so a flood of connections can't grow
+//! it without limit. What happens once it's full is down to the configured overflow policy.
+
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::{
+    audit::{to_json_line, AuditLog},
+    config::{AuditQueueConfig, Config, OverflowPolicy},
+};
+
+/// Name of the file logs are spilled to within the spill directory.
+const SPILL_FILE: &str = "audit-spill.jsonl";
+
+pub struct AuditQueue {
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// Where logs that don't fit are written under [`OverflowPolicy::Spill`].
+    spill_path: Option<PathBuf>,
+    logs: Mutex<VecDeque<AuditLog>>,
+    /// Held whilst the spill file is being written or read back in, separately from `logs` so
+    /// the disk never holds up anything waiting on the queue.
+    spill_file: Mutex<()>,
+    /// Woken whenever a log is queued.
+    queued: Notify,
+    /// Woken whenever a log is taken off the queue.
+    space: Notify,
+    dropped: AtomicU64,
+    spilled: AtomicU64,
+}
+
+impl Default for AuditQueue {
+    fn default() -> Self {
+        Self::new(&Config::default())
+    }
+}
+
+impl AuditQueue {
+    pub fn new(config: &Config) -> Self {
+        let AuditQueueConfig {
+            capacity,
+            overflow,
+            spill_directory,
+        } = &config.audit_queue;
+
+        let spill_path = spill_directory
+            .as_ref()
+            .or(config.state_dir.as_ref())
+            .map(|v| v.join(SPILL_FILE));
+
+        Self {
+            capacity: (*capacity).max(1),
+            policy: *overflow,
+            spill_path,
+            logs: Mutex::new(VecDeque::new()),
+            spill_file: Mutex::new(()),
+            queued: Notify::new(),
+            space: Notify::new(),
+            dropped: AtomicU64::new(0),
+            spilled: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues `log` to be written, applying the overflow policy if the queue is full. This never
+    /// waits, so can be called from anywhere, including as a connection is dropped.
+    pub fn push(&self, log: AuditLog) {
+        if let (OverflowPolicy::Spill, Some(path)) = (self.policy, &self.spill_path) {
+            if self.depth() >= self.capacity {
+                let _file = self.spill_file.lock();
+
+                match spill(path, &log) {
+                    Ok(()) => {
+                        self.spilled.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+                    Err(error) => {
+                        warn!(%error, "Failed to spill audit log, dropping the oldest instead");
+                    }
+                }
+            }
+        }
+
+        let mut logs = self.logs.lock();
+
+        // under `Block` the accept loop stops taking connections until there's space again, so
+        // the queue only grinds to a halt rather than growing without limit
+        if logs.len() >= self.capacity && self.policy != OverflowPolicy::Block {
+            self.drop_oldest(&mut logs);
+        }
+
+        logs.push_back(log);
+        drop(logs);
+
+        self.queued.notify_one();
+    }
+
+    fn drop_oldest(&self, logs: &mut VecDeque<AuditLog>) {
+        if logs.pop_front().is_some() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(dropped, "Audit queue is full, dropped the oldest log");
+        }
+    }
+
+    /// Waits for the next log to write.
+    pub async fn pop(&self) -> AuditLog {
+        loop {
+            let queued = self.queued.notified();
+
+            if let Some(log) = self.try_pop() {
+                return log;
+            }
+
+            queued.await;
+        }
+    }
+
+    /// Takes the next log off the queue if there is one. Once the queue is empty, logs that
+    /// were spilled to disk are read back in.
+    pub fn try_pop(&self) -> Option<AuditLog> {
+        let mut log = self.logs.lock().pop_front();
+
+        if log.is_none() && self.spilled() > 0 {
+            let unspilled = self.unspill();
+            let mut logs = self.logs.lock();
+
+            // anything queued whilst we were reading came after the logs that were spilled
+            for unspilled in unspilled.into_iter().rev() {
+                logs.push_front(unspilled);
+            }
+
+            log = logs.pop_front();
+        }
+
+        if log.is_some() {
+            self.space.notify_waiters();
+        }
+
+        log
+    }
+
+    /// Waits until there's space in the queue, only ever waiting under
+    /// [`OverflowPolicy::Block`].
+    pub async fn wait_for_space(&self) {
+        loop {
+            let space = self.space.notified();
+
+            if self.has_space() {
+                return;
+            }
+
+            space.await;
+        }
+    }
+
+    /// Whether a log can be queued without waiting, which is always the case unless the
+    /// policy is [`OverflowPolicy::Block`].
+    pub fn has_space(&self) -> bool {
+        self.policy != OverflowPolicy::Block || self.depth() < self.capacity
+    }
+
+    /// Reads spilled logs back in, up to the queue's capacity. Any that don't fit are spilled
+    /// again. The spill file is left alone if it can't be read in full, to try again later.
+    fn unspill(&self) -> Vec<AuditLog> {
+        let Some(path) = &self.spill_path else {
+            return Vec::new();
+        };
+
+        let _file = self.spill_file.lock();
+
+        let lines = match std::fs::File::open(path)
+            .and_then(|file| BufReader::new(file).lines().collect::<Result<Vec<_>, _>>())
+        {
+            Ok(lines) => lines,
+            Err(error) => {
+                warn!(%error, "Failed to read spilled audit logs back in");
+                return Vec::new();
+            }
+        };
+
+        if let Err(error) = std::fs::remove_file(path) {
+            warn!(%error, "Failed to remove spilled audit logs after reading them back in");
+            return Vec::new();
+        }
+
+        self.spilled.store(0, Ordering::Relaxed);
+
+        let mut logs = Vec::new();
+
+        for line in lines {
+            let Ok(log) = serde_json::from_str::<AuditLog>(&line) else {
+                warn!("Skipping unreadable spilled audit log");
+                continue;
+            };
+
+            if logs.len() < self.capacity {
+                logs.push(log);
+            } else if spill(path, &log).is_ok() {
+                self.spilled.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        logs
+    }
+
+    /// Number of logs waiting to be written.
+    pub fn depth(&self) -> usize {
+        self.logs.lock().len()
+    }
+
+    /// Most logs that can be waiting before the overflow policy kicks in.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of logs dropped for want of space.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of logs currently spilled to disk.
+    pub fn spilled(&self) -> u64 {
+        self.spilled.load(Ordering::Relaxed)
+    }
+}
+
+fn spill(path: &Path, log: &AuditLog) -> Result<(), std::io::Error> {
+    let line = to_json_line(log)?;
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use crate::{
+        audit::{
+            queue::{AuditQueue, SPILL_FILE},
+            AuditLog,
+        },
+        config::{Config, OverflowPolicy},
+    };
+
+    fn queue(policy: OverflowPolicy, spill_directory: Option<&std::path::Path>) -> AuditQueue {
+        let mut config = Config::default();
+        config.audit_queue.capacity = 2;
+        config.audit_queue.overflow = policy;
+        config.audit_queue.spill_directory = spill_directory.map(ToOwned::to_owned);
+        AuditQueue::new(&config)
+    }
+
+    fn log(host: &'static str) -> AuditLog {
+        AuditLog {
+            host: host.into(),
+            ..AuditLog::default()
+        }
+    }
+
+    #[test]
+    fn drops_oldest() {
+        let queue = queue(OverflowPolicy::DropOldest, None);
+
+        for host in ["a", "b", "c"] {
+            queue.push(log(host));
+        }
+
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.try_pop().unwrap().host, "b");
+        assert_eq!(queue.try_pop().unwrap().host, "c");
+        assert!(queue.try_pop().is_none());
+    }
+
+    #[test]
+    fn spills() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-spill-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let queue = queue(OverflowPolicy::Spill, Some(&directory));
+
+        for host in ["a", "b", "c", "d"] {
+            queue.push(log(host));
+        }
+
+        assert_eq!(queue.spilled(), 2);
+        assert_eq!(queue.dropped(), 0);
+
+        let hosts = std::iter::from_fn(|| queue.try_pop())
+            .map(|v| v.host.into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(hosts, ["a", "b", "c", "d"]);
+        assert_eq!(queue.spilled(), 0);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn keeps_spilled_logs_that_cant_be_read() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-spill-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let queue = queue(OverflowPolicy::Spill, Some(&directory));
+
+        for host in ["a", "b", "c", "d"] {
+            queue.push(log(host));
+        }
+
+        // invalid UTF-8 fails the read partway through the file
+        let path = directory.join(SPILL_FILE);
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"\xff\xfe\n").unwrap();
+        drop(file);
+
+        assert_eq!(queue.try_pop().unwrap().host, "a");
+        assert_eq!(queue.try_pop().unwrap().host, "b");
+        assert!(queue.try_pop().is_none());
+
+        assert_eq!(queue.spilled(), 2);
+        assert!(std::fs::read(&path).unwrap().starts_with(b"{"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn blocks() {
+        let queue = queue(OverflowPolicy::Block, None);
+
+        for host in ["a", "b", "c"] {
+            queue.push(log(host));
+        }
+
+        assert_eq!(queue.depth(), 3, "nothing is lost when blocking");
+        assert!(futures::poll!(Box::pin(queue.wait_for_space())).is_pending());
+
+        queue.try_pop();
+        queue.try_pop();
+        queue.wait_for_space().await;
+    }
+}
@@ -0,0 +1,338 @@
+use std::io::ErrorKind;
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, Transaction};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    audit::{AuditLog, AuditLogAction, AuditSink, LoginAttemptEvent},
+    config::SqliteSinkConfig,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS connection (
+    id TEXT PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    duration_ms INTEGER,
+    peer_address TEXT,
+    host TEXT NOT NULL,
+    client_version TEXT,
+    hassh TEXT,
+    country TEXT,
+    city TEXT,
+    asn INTEGER,
+    org TEXT,
+    reverse_dns TEXT,
+    sensor TEXT
+);
+
+CREATE TABLE IF NOT EXISTS event (
+    id INTEGER PRIMARY KEY,
+    connection_id TEXT NOT NULL REFERENCES connection (id),
+    ts TEXT,
+    offset_ms INTEGER NOT NULL,
+    type TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS event_connection_id ON event (connection_id);
+CREATE INDEX IF NOT EXISTS event_type_ts ON event (type, ts);
+
+CREATE TABLE IF NOT EXISTS credential (
+    id INTEGER PRIMARY KEY,
+    connection_id TEXT NOT NULL REFERENCES connection (id),
+    ts TEXT,
+    method TEXT NOT NULL,
+    username TEXT,
+    password TEXT,
+    key_kind TEXT,
+    key_fingerprint TEXT
+);
+
+CREATE INDEX IF NOT EXISTS credential_ts ON credential (ts);
+
+CREATE TABLE IF NOT EXISTS tag (
+    connection_id TEXT NOT NULL REFERENCES connection (id),
+    tag TEXT NOT NULL,
+    PRIMARY KEY (connection_id, tag)
+);
+
+CREATE INDEX IF NOT EXISTS tag_tag ON tag (tag);
+";
+
+/// Writes logs into a SQLite database, normalised into `connection`, `event`, `credential` and
+/// `tag` tables so they can be queried directly. Each event's action is stored as JSON in
+/// `event.data`, which can be picked apart using SQLite's JSON functions.
+pub struct SqliteSink {
+    connection: Connection,
+}
+
+impl SqliteSink {
+    pub fn open(config: &SqliteSinkConfig) -> Result<Self, std::io::Error> {
+        Self::new(Connection::open(&config.path).map_err(to_io_error)?)
+    }
+
+    fn new(connection: Connection) -> Result<Self, std::io::Error> {
+        connection.execute_batch(SCHEMA).map_err(to_io_error)?;
+
+        // databases created before logs were forwarded between sensors don't have the column
+        let has_sensor: bool = connection
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('connection') WHERE name = 'sensor'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(to_io_error)?;
+
+        if !has_sensor {
+            connection
+                .execute_batch("ALTER TABLE connection ADD COLUMN sensor TEXT")
+                .map_err(to_io_error)?;
+        }
+
+        Ok(Self { connection })
+    }
+}
+
+#[async_trait]
+impl AuditSink for SqliteSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        // rusqlite is blocking, but writes to a local database are quick enough that handing
+        // the connection off to another thread isn't worth the hassle
+        tokio::task::block_in_place(|| insert(&mut self.connection, log))
+    }
+}
+
+fn insert(connection: &mut Connection, log: &AuditLog) -> Result<(), std::io::Error> {
+    let tx = connection.transaction().map_err(to_io_error)?;
+    let connection_id = log.connection_id.to_string();
+    let geoip = log.geoip.as_ref();
+
+    tx.execute(
+        "INSERT INTO connection (id, started_at, ended_at, duration_ms, peer_address, host, \
+         client_version, hassh, country, city, asn, org, reverse_dns, sensor) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+            connection_id,
+            format_timestamp(log.ts),
+            log.ended_at.map(format_timestamp),
+            log.duration_ms,
+            log.peer_address.map(|v| v.to_string()),
+            &*log.host,
+            log.client_handshake.as_ref().map(|v| &*v.version),
+            log.client_handshake.as_ref().map(|v| &*v.hassh),
+            geoip.and_then(|v| v.country.as_deref()),
+            geoip.and_then(|v| v.city.as_deref()),
+            geoip.and_then(|v| v.asn),
+            geoip.and_then(|v| v.org.as_deref()),
+            log.reverse_dns.as_deref(),
+            log.sensor.as_deref(),
+        ],
+    )
+    .map_err(to_io_error)?;
+
+    for tag in &log.tags {
+        tx.execute(
+            "INSERT INTO tag (connection_id, tag) VALUES (?1, ?2)",
+            params![connection_id, &**tag],
+        )
+        .map_err(to_io_error)?;
+    }
+
+    for event in &log.events {
+        let ts = event.ts.map(format_timestamp);
+        let data = serde_json::to_string(&event.action)
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        tx.execute(
+            "INSERT INTO event (connection_id, ts, offset_ms, type, data) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                connection_id,
+                ts,
+                event.offset_ms,
+                <&'static str>::from(&event.action),
+                data,
+            ],
+        )
+        .map_err(to_io_error)?;
+
+        if let AuditLogAction::LoginAttempt(attempt) = &event.action {
+            insert_credential(&tx, &connection_id, ts.as_deref(), attempt)?;
+        }
+    }
+
+    tx.commit().map_err(to_io_error)
+}
+
+/// A login attempt as it's stored in the `credential` table.
+pub(super) struct Credential<'a> {
+    pub method: &'static str,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    pub key_kind: Option<&'a str>,
+    pub key_fingerprint: Option<&'a str>,
+}
+
+impl<'a> Credential<'a> {
+    pub fn new(attempt: &'a LoginAttemptEvent) -> Self {
+        match attempt {
+            LoginAttemptEvent::UsernamePassword { username, password } => Self {
+                method: "password",
+                username: Some(&**username),
+                password: Some(&**password),
+                key_kind: None,
+                key_fingerprint: None,
+            },
+            LoginAttemptEvent::PublicKey { kind, fingerprint } => Self {
+                method: "public-key",
+                username: None,
+                password: None,
+                key_kind: Some(&**kind),
+                key_fingerprint: Some(&**fingerprint),
+            },
+            LoginAttemptEvent::KeyboardInteractive {
+                username,
+                responses,
+            } => Self {
+                method: "keyboard-interactive",
+                username: Some(&**username),
+                password: responses.first().map(|v| &**v),
+                key_kind: None,
+                key_fingerprint: None,
+            },
+        }
+    }
+}
+
+fn insert_credential(
+    tx: &Transaction<'_>,
+    connection_id: &str,
+    ts: Option<&str>,
+    attempt: &LoginAttemptEvent,
+) -> Result<(), std::io::Error> {
+    let Credential {
+        method,
+        username,
+        password,
+        key_kind,
+        key_fingerprint,
+    } = Credential::new(attempt);
+
+    tx.execute(
+        "INSERT INTO credential (connection_id, ts, method, username, password, key_kind, \
+         key_fingerprint) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            connection_id,
+            ts,
+            method,
+            username,
+            password,
+            key_kind,
+            key_fingerprint
+        ],
+    )
+    .map_err(to_io_error)?;
+
+    Ok(())
+}
+
+/// Formats timestamps the way SQLite's own date functions do, so they can be compared against
+/// ie. `datetime('now', '-7 days')`.
+pub fn format_timestamp(ts: OffsetDateTime) -> String {
+    ts.to_offset(time::UtcOffset::UTC)
+        .format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3]"
+        ))
+        .unwrap_or_default()
+}
+
+fn to_io_error(e: rusqlite::Error) -> std::io::Error {
+    std::io::Error::new(ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::Connection;
+
+    use super::{insert, SqliteSink};
+    use crate::audit::{AuditLog, AuditLogAction, ExecCommandEvent, LoginAttemptEvent};
+
+    #[test]
+    fn normalises_log() {
+        let SqliteSink { mut connection } =
+            SqliteSink::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        let mut log = AuditLog {
+            sensor: Some("sensor-1".into()),
+            ..AuditLog::default()
+        };
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: Box::from("root"),
+                password: Box::from("hunter2"),
+            },
+        ));
+        log.push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+            args: Box::from(vec!["uname -a".to_string()]),
+        }));
+        log.tag("miner-recon");
+        log.finish();
+
+        insert(&mut connection, &log).unwrap();
+
+        let password: String = connection
+            .query_row(
+                "SELECT password FROM credential WHERE username = 'root'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(password, "hunter2");
+
+        let command: String = connection
+            .query_row(
+                "SELECT json_extract(data, '$.args[0]') FROM event WHERE type = 'exec-command' \
+                 AND ts >= datetime('now', '-1 day')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(command, "uname -a");
+
+        let events: u32 = connection
+            .query_row("SELECT COUNT(*) FROM event", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(events, 2);
+
+        let tag: String = connection
+            .query_row("SELECT tag FROM tag", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tag, "miner-recon");
+
+        let sensor: String = connection
+            .query_row("SELECT sensor FROM connection", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(sensor, "sensor-1");
+    }
+
+    #[test]
+    fn adds_sensor_to_old_databases() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch("CREATE TABLE connection (id TEXT PRIMARY KEY)")
+            .unwrap();
+
+        let SqliteSink { connection } = SqliteSink::new(connection).unwrap();
+
+        let columns: u32 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('connection') WHERE name = 'sensor'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(columns, 1);
+    }
+}
@@ -0,0 +1,28 @@
+use async_trait::async_trait;
+use tokio::io::{AsyncWriteExt, BufWriter, Stdout};
+
+use crate::audit::{to_json_line, AuditLog, AuditSink};
+
+/// Writes logs to stdout as JSON lines, useful when running in a container.
+pub struct StdoutSink {
+    writer: BufWriter<Stdout>,
+}
+
+impl Default for StdoutSink {
+    fn default() -> Self {
+        Self {
+            writer: BufWriter::new(tokio::io::stdout()),
+        }
+    }
+}
+
+#[async_trait]
+impl AuditSink for StdoutSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        self.writer.write_all(&to_json_line(log)?).await
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.writer.flush().await
+    }
+}
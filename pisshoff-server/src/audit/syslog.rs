@@ -0,0 +1,309 @@
+use std::{fmt::Write as _, io::ErrorKind, net::SocketAddr};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{io::AsyncWriteExt, net::UdpSocket};
+use tokio_rustls::TlsConnector;
+use tracing::warn;
+
+use crate::{
+    audit::{connect_stream, tls_config, AuditLog, AuditSink, BoxedStream},
+    config::{SyslogMessages, SyslogSinkConfig, SyslogTransport},
+};
+
+/// Severity of every message we send, `info`.
+const SEVERITY: u8 = 6;
+const APP_NAME: &str = "pisshoff";
+/// SD-ID of our structured data, using the private enterprise number reserved for
+/// documentation by RFC 5612 as we don't have one of our own.
+const SD_ID: &str = "pisshoff@32473";
+
+/// Sends each log to a syslog server as RFC 5424 messages, either as a single message with
+/// the JSON-encoded log as its body or as a message per event with the event's fields mapped
+/// to structured data.
+pub struct SyslogSink {
+    config: SyslogSinkConfig,
+    priority: u8,
+    transport: Transport,
+}
+
+enum Transport {
+    Udp(UdpSocket),
+    /// A TCP or TLS stream, reconnected on the next write if the connection is lost.
+    Stream {
+        stream: Option<BoxedStream>,
+        tls: Option<TlsConnector>,
+    },
+}
+
+impl SyslogSink {
+    pub async fn new(config: &SyslogSinkConfig) -> Result<Self, std::io::Error> {
+        let transport = match config.transport {
+            SyslogTransport::Udp => {
+                let address = resolve(&config.address).await?;
+                let bind: SocketAddr = if address.is_ipv4() {
+                    ([0, 0, 0, 0], 0).into()
+                } else {
+                    ([0_u16; 8], 0).into()
+                };
+
+                let socket = UdpSocket::bind(bind).await?;
+                socket.connect(address).await?;
+
+                Transport::Udp(socket)
+            }
+            SyslogTransport::Tcp => Transport::Stream {
+                stream: None,
+                tls: None,
+            },
+            SyslogTransport::Tls => Transport::Stream {
+                stream: None,
+                tls: Some(TlsConnector::from(tls_config(config.ca_file.as_deref())?)),
+            },
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            priority: config.facility as u8 * 8 + SEVERITY,
+            transport,
+        })
+    }
+
+    async fn send(&mut self, message: &str) -> Result<(), std::io::Error> {
+        match &mut self.transport {
+            Transport::Udp(socket) => socket.send(message.as_bytes()).await.map(|_| ()),
+            Transport::Stream { stream, tls } => {
+                let writer = match stream {
+                    Some(writer) => writer,
+                    None => {
+                        stream.insert(connect_stream(&self.config.address, tls.as_ref()).await?)
+                    }
+                };
+
+                // octet-counted framing, as required by RFC 5425 and allowed by RFC 6587
+                let res = writer
+                    .write_all(format!("{} {message}", message.len()).as_bytes())
+                    .await;
+
+                if res.is_err() {
+                    *stream = None;
+                }
+
+                res
+            }
+        }
+    }
+}
+
+/// Formats `log` as one or more RFC 5424 messages, depending on the configured `mode`.
+fn format_messages(
+    log: &AuditLog,
+    priority: u8,
+    mode: SyslogMessages,
+    timestamp: &str,
+) -> Result<Vec<String>, std::io::Error> {
+    let host = if log.host.is_empty() { "-" } else { &log.host };
+    let header = format!("<{priority}>1 {timestamp} {host} {APP_NAME} -");
+
+    match mode {
+        SyslogMessages::Connection => {
+            let body =
+                serde_json::to_string(log).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+            let sd = structured_data(connection_params(log));
+
+            Ok(vec![format!("{header} connection {sd} {body}")])
+        }
+        SyslogMessages::Event => log
+            .events
+            .iter()
+            .map(|event| {
+                let action = serde_json::to_value(&event.action)
+                    .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+                let msg_id = <&'static str>::from(&event.action);
+
+                let mut params = connection_params(log);
+                params.push(("offset-ms", event.offset_ms.to_string()));
+
+                if let Value::Object(fields) = &action {
+                    params.extend(
+                        fields
+                            .iter()
+                            .filter(|(key, _)| key.as_str() != "type")
+                            .map(|(key, value)| (key.as_str(), param_value(value))),
+                    );
+                }
+
+                Ok(format!(
+                    "{header} {msg_id} {} {action}",
+                    structured_data(params)
+                ))
+            })
+            .collect(),
+    }
+}
+
+#[async_trait]
+impl AuditSink for SyslogSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        let timestamp = OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        for message in format_messages(log, self.priority, self.config.messages, &timestamp)? {
+            // syslog is best-effort, a missing collector shouldn't bring the honeypot down
+            if let Err(error) = self.send(&message).await {
+                warn!(%error, "Failed to send audit log to syslog");
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        if let Transport::Stream { stream, .. } = &mut self.transport {
+            if let Some(writer) = stream {
+                if let Err(error) = writer.flush().await {
+                    warn!(%error, "Failed to flush syslog stream");
+                    *stream = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn resolve(address: &str) -> Result<SocketAddr, std::io::Error> {
+    tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "failed to resolve syslog server"))
+}
+
+fn connection_params(log: &AuditLog) -> Vec<(&str, String)> {
+    let mut params = vec![("connection-id", log.connection_id.to_string())];
+
+    if let Some(peer_address) = log.peer_address {
+        params.push(("peer-address", peer_address.to_string()));
+    }
+
+    if let Some(duration_ms) = log.duration_ms {
+        params.push(("duration-ms", duration_ms.to_string()));
+    }
+
+    if let Some(handshake) = &log.client_handshake {
+        params.push(("client-version", handshake.version.to_string()));
+        params.push(("hassh", handshake.hassh.to_string()));
+    }
+
+    if let Some(geoip) = &log.geoip {
+        params.extend(geoip.country.as_ref().map(|v| ("country", v.to_string())));
+        params.extend(geoip.asn.map(|v| ("asn", v.to_string())));
+    }
+
+    if !log.tags.is_empty() {
+        params.push(("tags", log.tags.join(",")));
+    }
+
+    params.push(("events", log.events.len().to_string()));
+    params
+}
+
+fn param_value(value: &Value) -> String {
+    match value {
+        Value::String(v) => v.clone(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+/// Builds an SD-ELEMENT out of `params`, skipping any with names RFC 5424 doesn't allow.
+fn structured_data<'a>(params: impl IntoIterator<Item = (&'a str, String)>) -> String {
+    let mut out = format!("[{SD_ID}");
+
+    for (name, value) in params {
+        let valid_name = !name.is_empty()
+            && name.len() <= 32
+            && name
+                .bytes()
+                .all(|c| c.is_ascii_graphic() && !matches!(c, b'=' | b']' | b'"'));
+
+        if valid_name {
+            write!(out, " {name}=\"").unwrap();
+
+            for c in value.chars() {
+                if matches!(c, '"' | '\\' | ']') {
+                    out.push('\\');
+                }
+
+                out.push(c);
+            }
+
+            out.push('"');
+        }
+    }
+
+    out.push(']');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_messages, structured_data};
+    use crate::{
+        audit::{AuditLog, AuditLogAction, LoginAttemptEvent},
+        config::SyslogMessages,
+    };
+
+    #[test]
+    fn escapes_structured_data() {
+        assert_eq!(
+            structured_data([
+                ("username", "root".to_string()),
+                ("password", "a\"b]c\\d".to_string()),
+                ("bad name", "skipped".to_string()),
+            ]),
+            r#"[pisshoff@32473 username="root" password="a\"b\]c\\d"]"#
+        );
+    }
+
+    #[test]
+    fn message_per_event() {
+        let mut log = AuditLog::default();
+        log.host = "honeypot".into();
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: Box::from("root"),
+                password: Box::from("hunter2"),
+            },
+        ));
+
+        let messages =
+            format_messages(&log, 134, SyslogMessages::Event, "2023-01-01T00:00:00Z").unwrap();
+        assert_eq!(messages.len(), 1);
+
+        let (header, rest) = messages[0].split_once(" [").unwrap();
+        assert_eq!(
+            header,
+            "<134>1 2023-01-01T00:00:00Z honeypot pisshoff - login-attempt"
+        );
+
+        let (sd, body) = rest.split_once("] ").unwrap();
+        assert!(
+            sd.starts_with(&format!(
+                "pisshoff@32473 connection-id=\"{}\"",
+                log.connection_id
+            )),
+            "{sd}"
+        );
+        assert!(
+            sd.contains(" username=\"root\" password=\"hunter2\""),
+            "{sd}"
+        );
+
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["password"], "hunter2");
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    collections::VecDeque,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use reqwest::header::CONTENT_TYPE;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditLog, AuditSink},
+    config::WebhookSinkConfig,
+};
+
+/// Number of batches to hold in memory whilst the endpoint is unavailable if no spool directory
+/// has been configured, the oldest are dropped once this is exceeded.
+const MAX_PENDING: usize = 64;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// POSTs logs as JSON arrays to a configured URL, in batches of up to `batch-size` logs or
+/// whatever has built up by the time the audit writer flushes.
+///
+/// Batches that fail to send are spooled, either to disk or in memory, and retried in order with
+/// an exponential backoff once the endpoint is reachable again.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    config: WebhookSinkConfig,
+    batch: Vec<Vec<u8>>,
+    pending: VecDeque<Vec<u8>>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+impl WebhookSink {
+    pub fn new(config: &WebhookSinkConfig) -> Result<Self, std::io::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        if let Some(directory) = &config.spool_directory {
+            std::fs::create_dir_all(directory)?;
+        }
+
+        Ok(Self {
+            client,
+            config: config.clone(),
+            batch: Vec::new(),
+            pending: VecDeque::new(),
+            backoff: MIN_BACKOFF,
+            next_attempt: Instant::now(),
+        })
+    }
+
+    /// Sends the current batch, spooling it if it can't be sent right now.
+    async fn send_batch(&mut self) -> Result<(), std::io::Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let body = batch_body(&std::mem::take(&mut self.batch));
+
+        // anything spooled is older than this batch, so has to go first
+        if !self.drain().await? || !self.try_post(&body).await {
+            self.spool(body).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends spooled batches oldest first, returning whether the spool was emptied.
+    async fn drain(&mut self) -> Result<bool, std::io::Error> {
+        while let Some(body) = self.pending.front().cloned() {
+            if !self.try_post(&body).await {
+                return Ok(false);
+            }
+
+            self.pending.pop_front();
+        }
+
+        let Some(directory) = self.config.spool_directory.clone() else {
+            return Ok(true);
+        };
+
+        for path in spooled_batches(&directory).await? {
+            let body = tokio::fs::read(&path).await?;
+
+            if !self.try_post(&body).await {
+                return Ok(false);
+            }
+
+            tokio::fs::remove_file(&path).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// POSTs a batch to the endpoint, unless we're backing off after a previous failure.
+    async fn try_post(&mut self, body: &[u8]) -> bool {
+        if Instant::now() < self.next_attempt {
+            return false;
+        }
+
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_vec());
+
+        if let Some(token) = &self.config.bearer_token {
+            request = request.bearer_auth(token);
+        }
+
+        let res = request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+
+        // an unavailable endpoint shouldn't stop the other sinks from receiving logs
+        match res {
+            Ok(_) => {
+                if self.backoff > MIN_BACKOFF {
+                    info!("Webhook endpoint is available again");
+                }
+
+                self.backoff = MIN_BACKOFF;
+                true
+            }
+            Err(error) => {
+                warn!(%error, retry_in = ?self.backoff, "Failed to send audit logs to webhook");
+                self.next_attempt = Instant::now() + self.backoff;
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                false
+            }
+        }
+    }
+
+    async fn spool(&mut self, body: Vec<u8>) -> Result<(), std::io::Error> {
+        if let Some(directory) = &self.config.spool_directory {
+            let millis = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+
+            // the timestamp prefix keeps the spool sorted oldest first
+            let path = directory.join(format!("{millis:020}-{}.json", Uuid::new_v4()));
+            return tokio::fs::write(path, body).await;
+        }
+
+        if self.pending.len() >= MAX_PENDING {
+            warn!("Webhook endpoint unavailable for too long, dropping oldest batch of audit logs");
+            self.pending.pop_front();
+        }
+
+        self.pending.push_back(body);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditSink for WebhookSink {
+    async fn write(&mut self, log: &AuditLog) -> Result<(), std::io::Error> {
+        self.batch
+            .push(serde_json::to_vec(log).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?);
+
+        if self.batch.len() >= self.config.batch_size.max(1) {
+            self.send_batch().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        if self.batch.is_empty() {
+            self.drain().await?;
+            Ok(())
+        } else {
+            self.send_batch().await
+        }
+    }
+}
+
+/// Lists batches in the spool directory, oldest first.
+async fn spooled_batches(directory: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut out = Vec::new();
+    let mut entries = tokio::fs::read_dir(directory).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if path.extension().map_or(false, |v| v == "json") {
+            out.push(path);
+        }
+    }
+
+    out.sort();
+    Ok(out)
+}
+
+/// Joins already-serialised logs into a JSON array.
+fn batch_body(logs: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(logs.iter().map(|v| v.len() + 1).sum::<usize>() + 2);
+    out.push(b'[');
+
+    for (i, log) in logs.iter().enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+
+        out.extend_from_slice(log);
+    }
+
+    out.push(b']');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::batch_body;
+
+    #[test]
+    fn joins_batch() {
+        assert_eq!(batch_body(&[]), b"[]");
+        assert_eq!(
+            batch_body(&[b"{\"a\":1}".to_vec(), b"{\"b\":2}".to_vec()]),
+            b"[{\"a\":1},{\"b\":2}]"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Applies the `[auth]` section of the config to password logins.
+
+use crate::config::{AuthAction, AuthConfig};
+
+/// What the auth policy has to say about a login.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Accept,
+    Reject,
+    /// Accept the login with the given probability.
+    Probability(f64),
+}
+
+/// Finds the verdict of the first allowlist entry or rule matching the login, `attempt` being
+/// the number of passwords the client has tried on this connection, including this one.
+/// Returns `None` if nothing matched, leaving it down to `access-probability`.
+pub fn evaluate(
+    config: &AuthConfig,
+    username: &str,
+    password: &str,
+    attempt: usize,
+) -> Option<Verdict> {
+    if config
+        .allow
+        .iter()
+        .any(|v| v.username == username && v.password == password)
+    {
+        return Some(Verdict::Accept);
+    }
+
+    let rule = config.rules.iter().find(|rule| {
+        rule.username
+            .as_ref()
+            .map_or(true, |v| v.is_match(username))
+            && rule
+                .password
+                .as_ref()
+                .map_or(true, |v| v.is_match(password))
+    })?;
+
+    Some(match rule.action {
+        AuthAction::Accept(true) => Verdict::Accept,
+        AuthAction::Accept(false) => Verdict::Reject,
+        AuthAction::Probability(probability) => Verdict::Probability(probability),
+        AuthAction::Attempt(n) if attempt >= n => Verdict::Accept,
+        AuthAction::Attempt(_) => Verdict::Reject,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::{evaluate, Verdict};
+    use crate::config::Config;
+
+    const CONFIG: &str = r#"
+        [auth]
+        allow = [{ username = "pi", password = "raspberry" }]
+
+        [[auth.rules]]
+        username = "root"
+        password = "root|toor"
+        accept = false
+
+        [[auth.rules]]
+        username = "admin"
+        password = "admin"
+        accept = true
+
+        [[auth.rules]]
+        username = "ubuntu|oracle"
+        probability = 0.5
+
+        [[auth.rules]]
+        username = "git"
+        attempt = 3
+    "#;
+
+    #[test_case("pi", "raspberry", 1, Some(Verdict::Accept); "allowlist")]
+    #[test_case("pi", "raspberry1", 1, None; "allowlist is exact")]
+    #[test_case("root", "toor", 1, Some(Verdict::Reject); "rejected by rule")]
+    #[test_case("root", "hunter2", 1, None; "pattern matches whole password")]
+    #[test_case("admin", "admin", 1, Some(Verdict::Accept); "accepted by rule")]
+    #[test_case("oracle", "hunter2", 1, Some(Verdict::Probability(0.5)); "probability")]
+    #[test_case("git", "hunter2", 2, Some(Verdict::Reject); "before nth attempt")]
+    #[test_case("git", "hunter2", 3, Some(Verdict::Accept); "nth attempt")]
+    #[test_case("gitlab", "hunter2", 3, None; "pattern matches whole username")]
+    fn evaluates_rules(username: &str, password: &str, attempt: usize, expected: Option<Verdict>) {
+        let config = toml::from_str::<Config>(CONFIG).unwrap();
+
+        assert_eq!(
+            evaluate(&config.auth, username, password, attempt),
+            expected
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_pattern() {
+        let config = toml::from_str::<Config>("[[auth.rules]]\nusername = \"(\"\naccept = true");
+
+        assert!(config.is_err());
+    }
+}
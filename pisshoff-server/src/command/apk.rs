@@ -0,0 +1,182 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use pisshoff_types::audit::Package;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const REPOSITORIES: &[&str] = &[
+    "https://dl-cdn.alpinelinux.org/alpine/v3.18/main",
+    "https://dl-cdn.alpinelinux.org/alpine/v3.18/community",
+];
+
+const LOCKED: &str = "ERROR: Unable to lock database: Permission denied
+ERROR: Failed to open apk database: Permission denied
+";
+
+/// Packages the database is reported as holding before anything's installed.
+const INSTALLED: usize = 15;
+
+#[derive(Debug, Clone)]
+pub struct Apk {}
+
+#[async_trait]
+impl Command for Apk {
+    const NAME: &'static str = "apk";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut operands = params.iter().filter(|v| !v.starts_with('-'));
+    let root = connection.username() == "root";
+    let arch = connection.config().persona.machine.clone();
+
+    let Some(operation) = operands.next() else {
+        return (
+            "apk-tools 2.14.0, compiled for x86_64.\n\nusage: apk [<OPTIONS>...] COMMAND [<ARGUMENTS>...]\n"
+                .to_string(),
+            1,
+        );
+    };
+
+    let packages = operands
+        .map(|v| match v.split_once('=') {
+            Some((name, version)) => Package {
+                name: name.into(),
+                version: Some(version.into()),
+            },
+            None => Package {
+                name: v.as_str().into(),
+                version: None,
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+
+    match operation.as_str() {
+        "add" => {
+            let res = if root {
+                fetch(&mut out, &arch);
+                add(&mut out, connection, &packages);
+                (out, 0)
+            } else {
+                (LOCKED.to_string(), 99)
+            };
+
+            super::record_packages(connection, Apk::NAME, packages);
+            res
+        }
+        "update" | "upgrade" | "del" if !root => (LOCKED.to_string(), 99),
+        "update" => {
+            fetch(&mut out, &arch);
+            writeln!(out, "v3.18.4 [{}]", REPOSITORIES[0]).unwrap();
+            writeln!(out, "v3.18.4 [{}]", REPOSITORIES[1]).unwrap();
+            writeln!(out, "OK: 20072 distinct packages available").unwrap();
+            (out, 0)
+        }
+        "upgrade" | "del" => {
+            fetch(&mut out, &arch);
+            writeln!(out, "OK: 9 MiB in {INSTALLED} packages").unwrap();
+            (out, 0)
+        }
+        other => (format!("apk: unknown command '{other}'\n"), 1),
+    }
+}
+
+fn fetch(out: &mut String, arch: &str) {
+    for repository in REPOSITORIES {
+        writeln!(out, "fetch {repository}/{arch}/APKINDEX.tar.gz").unwrap();
+    }
+}
+
+fn add(out: &mut String, connection: &ConnectionState, packages: &[Package]) {
+    let mut total = 0;
+
+    for (i, package) in packages.iter().enumerate() {
+        let (version, size) = super::package_details(connection, &package.name);
+        let version = package
+            .version
+            .as_deref()
+            .map_or_else(|| format!("{version}-r0"), ToString::to_string);
+        total += size;
+
+        writeln!(
+            out,
+            "({}/{}) Installing {} ({version})",
+            i + 1,
+            packages.len(),
+            package.name
+        )
+        .unwrap();
+    }
+
+    if !packages.is_empty() {
+        out.push_str("Executing busybox-1.36.1-r2.trigger\n");
+    }
+
+    writeln!(
+        out,
+        "OK: {} MiB in {} packages",
+        9 + total * 3 / 1024,
+        INSTALLED + packages.len()
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::AuditLogAction;
+
+    use crate::{command::apk::execute, server::ConnectionState};
+
+    #[test]
+    fn records_install() {
+        let mut state = ConnectionState::mock();
+
+        let input = shlex::split("add --no-cache masscan xmrig=6.20.0-r0").unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, 0, "{out}");
+        assert!(
+            out.contains("(2/2) Installing xmrig (6.20.0-r0)\n"),
+            "{out}"
+        );
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PackageInstall(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.manager, "apk");
+        assert_eq!(&*event.packages[1].name, "xmrig");
+        assert_eq!(event.packages[1].version.as_deref(), Some("6.20.0-r0"));
+    }
+}
@@ -0,0 +1,328 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::Package;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "apt 2.4.10 (amd64)
+Usage: apt-get [options] command
+       apt-get [options] install|remove pkg1 [pkg2 ...]
+       apt-get [options] source pkg1 [pkg2 ...]
+
+apt-get is a command line interface for retrieval of packages
+and information about them from authenticated sources and
+for installation, upgrade and removal of packages together
+with their dependencies.
+
+Most used commands:
+  update - Retrieve new lists of packages
+  upgrade - Perform an upgrade
+  install - Install new packages (pkg is libc6 not libc6.deb)
+  reinstall - Reinstall packages (pkg is libc6 not libc6.deb)
+  remove - Remove packages
+  purge - Remove packages and config files
+  autoremove - Remove automatically all unused packages
+  dist-upgrade - Distribution upgrade, see apt-get(8)
+
+See apt-get(8) for more information about the available commands.
+                        This APT has Super Cow Powers.
+";
+
+const PACKAGE_LISTS: &str = "Reading package lists... Done
+Building dependency tree... Done
+Reading state information... Done
+";
+
+const LOCKED: &str =
+    "E: Could not open lock file /var/lib/dpkg/lock-frontend - open (13: Permission denied)
+E: Unable to acquire the dpkg frontend lock (/var/lib/dpkg/lock-frontend), are you root?
+";
+
+#[derive(Debug, Clone)]
+pub struct Apt {}
+
+#[async_trait]
+impl Command for Apt {
+    const NAME: &'static str = "apt-get";
+    const ALIASES: &'static [&'static str] = &["apt"];
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut operands = params.iter().filter(|v| !v.starts_with('-'));
+    let root = connection.username() == "root";
+
+    let Some(operation) = operands.next() else {
+        return (USAGE.to_string(), 1);
+    };
+
+    let packages = operands
+        .map(|v| match v.split_once('=') {
+            Some((name, version)) => Package {
+                name: name.into(),
+                version: Some(version.into()),
+            },
+            None => Package {
+                name: v.as_str().into(),
+                version: None,
+            },
+        })
+        .collect::<Vec<_>>();
+
+    match operation.as_str() {
+        "install" | "reinstall" => {
+            // worth knowing what they were after, even if they couldn't have installed it
+            let out = if root {
+                (install(connection, &packages), 0)
+            } else {
+                (LOCKED.to_string(), 100)
+            };
+
+            super::record_packages(connection, Apt::NAME, packages);
+            out
+        }
+        "update" if root => (update(connection), 0),
+        "update" => (
+            "Reading package lists... Done\nE: Could not open lock file /var/lib/apt/lists/lock - open (13: Permission denied)\nE: Unable to lock directory /var/lib/apt/lists/\n"
+                .to_string(),
+            100,
+        ),
+        "upgrade" | "dist-upgrade" | "full-upgrade" | "remove" | "purge" | "autoremove"
+            if !root =>
+        {
+            (LOCKED.to_string(), 100)
+        }
+        "upgrade" | "dist-upgrade" | "full-upgrade" => (
+            format!("{PACKAGE_LISTS}Calculating upgrade... Done\n0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.\n"),
+            0,
+        ),
+        "remove" | "purge" | "autoremove" => {
+            let mut out = PACKAGE_LISTS.to_string();
+
+            for package in &packages {
+                writeln!(out, "Package '{}' is not installed, so not removed", package.name)
+                    .unwrap();
+            }
+
+            out.push_str("0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.\n");
+            (out, 0)
+        }
+        other => (format!("E: Invalid operation {other}\n"), 100),
+    }
+}
+
+/// Where packages are fetched from, and the release they're fetched for.
+struct Archive {
+    mirror: &'static str,
+    codename: String,
+    section: &'static str,
+    arch: &'static str,
+}
+
+impl Archive {
+    /// Works out the archive from the persona's `/etc/os-release`, falling back to Debian's if
+    /// there isn't one.
+    fn new(connection: &mut ConnectionState) -> Self {
+        let arch = match connection.config().persona.machine.as_str() {
+            "aarch64" => "arm64",
+            "armv7l" => "armhf",
+            _ => "amd64",
+        };
+
+        let os_release = connection
+            .file_system()
+            .read(Path::new("/etc/os-release"))
+            .map(|v| String::from_utf8_lossy(v).into_owned())
+            .unwrap_or_default();
+        let field = |name: &str| {
+            os_release
+                .lines()
+                .find_map(|v| v.strip_prefix(name)?.strip_prefix('='))
+                .map(|v| v.trim_matches('"').to_string())
+        };
+
+        let codename = field("VERSION_CODENAME").unwrap_or_else(|| "bookworm".to_string());
+
+        if field("ID").as_deref() == Some("ubuntu") {
+            Self {
+                mirror: "http://archive.ubuntu.com/ubuntu",
+                codename,
+                section: "universe",
+                arch,
+            }
+        } else {
+            Self {
+                mirror: "http://deb.debian.org/debian",
+                codename,
+                section: "main",
+                arch,
+            }
+        }
+    }
+}
+
+fn update(connection: &mut ConnectionState) -> String {
+    let archive = Archive::new(connection);
+    let mut out = String::new();
+
+    for (i, suite) in ["", "-updates", "-security"].iter().enumerate() {
+        writeln!(
+            out,
+            "Hit:{} {} {}{suite} InRelease",
+            i + 1,
+            archive.mirror,
+            archive.codename
+        )
+        .unwrap();
+    }
+
+    out.push_str(PACKAGE_LISTS);
+    out.push_str("All packages are up to date.\n");
+    out
+}
+
+fn install(connection: &mut ConnectionState, packages: &[Package]) -> String {
+    let archive = Archive::new(connection);
+    let mut out = PACKAGE_LISTS.to_string();
+
+    if packages.is_empty() {
+        out.push_str("0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.\n");
+        return out;
+    }
+
+    let details = packages
+        .iter()
+        .map(|v| {
+            let (version, size) = super::package_details(connection, &v.name);
+            let version = v.version.as_deref().map_or(version, ToString::to_string);
+            (&*v.name, version, size)
+        })
+        .collect::<Vec<_>>();
+    let total = details.iter().map(|(_, _, size)| size).sum::<u64>();
+
+    out.push_str("The following NEW packages will be installed:\n ");
+    for (name, _, _) in &details {
+        write!(out, " {name}").unwrap();
+    }
+    writeln!(
+        out,
+        "\n0 upgraded, {} newly installed, 0 to remove and 0 not upgraded.",
+        details.len()
+    )
+    .unwrap();
+    writeln!(out, "Need to get {total} kB of archives.").unwrap();
+    writeln!(
+        out,
+        "After this operation, {} kB of additional disk space will be used.",
+        total * 3
+    )
+    .unwrap();
+
+    for (i, (name, version, size)) in details.iter().enumerate() {
+        writeln!(
+            out,
+            "Get:{} {} {}/{} {} {name} {} {version} [{size} kB]",
+            i + 1,
+            archive.mirror,
+            archive.codename,
+            archive.section,
+            archive.arch,
+            archive.arch,
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "Fetched {total} kB in 1s ({total} kB/s)").unwrap();
+
+    for (name, version, _) in &details {
+        writeln!(out, "Selecting previously unselected package {name}.").unwrap();
+        out.push_str("(Reading database ... 74562 files and directories currently installed.)\n");
+        writeln!(
+            out,
+            "Preparing to unpack .../{name}_{version}_{}.deb ...",
+            archive.arch
+        )
+        .unwrap();
+        writeln!(out, "Unpacking {name} ({version}) ...").unwrap();
+    }
+
+    for (name, version, _) in &details {
+        writeln!(out, "Setting up {name} ({version}) ...").unwrap();
+    }
+
+    out.push_str("Processing triggers for man-db (2.10.2-1) ...\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{command::apt::execute, server::ConnectionState};
+
+    #[test_case("root", "install -y masscan xmrig=6.20.0", 0; "root")]
+    #[test_case("ubuntu", "install masscan xmrig=6.20.0", 100; "not root")]
+    fn records_install(username: &str, input: &str, exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        state.switch_user(username, false);
+
+        let input = shlex::split(input).unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, exit_code, "{out}");
+
+        if exit_code == 0 {
+            assert!(out.contains("Unpacking masscan ("), "{out}");
+            assert!(out.contains("Setting up xmrig (6.20.0) ...\n"), "{out}");
+        }
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PackageInstall(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.manager, "apt-get");
+        assert_eq!(&*event.packages[0].name, "masscan");
+        assert_eq!(event.packages[0].version, None);
+        assert_eq!(&*event.packages[1].name, "xmrig");
+        assert_eq!(event.packages[1].version.as_deref(), Some("6.20.0"));
+    }
+
+    #[test_case("", 1; "usage")]
+    #[test_case("update", 0; "update")]
+    #[test_case("frobnicate", 100; "invalid")]
+    fn other_operations(input: &str, exit_code: u32) {
+        let input = shlex::split(input).unwrap();
+        let (_, code) = execute(&mut ConnectionState::mock(), &input);
+        assert_eq!(code, exit_code);
+    }
+}
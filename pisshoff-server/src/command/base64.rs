@@ -0,0 +1,178 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Decodes as leniently as coreutils does, which doesn't mind missing padding.
+const ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+#[derive(Debug, Clone)]
+pub struct Base64 {
+    decode: bool,
+    ignore_garbage: bool,
+    /// Column to wrap encoded output at, or 0 to not wrap it at all.
+    wrap: usize,
+}
+
+impl Base64 {
+    fn run(&self, input: &[u8]) -> (Vec<u8>, u32) {
+        if !self.decode {
+            let mut encoded = ENGINE.encode(input).into_bytes();
+
+            if self.wrap > 0 {
+                encoded = encoded
+                    .chunks(self.wrap)
+                    .collect::<Vec<_>>()
+                    .join(b"\n".as_slice());
+            }
+
+            if !encoded.is_empty() {
+                encoded.push(b'\n');
+            }
+
+            return (encoded, 0);
+        }
+
+        let input = input
+            .iter()
+            .copied()
+            .filter(|c| !c.is_ascii_whitespace())
+            .filter(|c| !self.ignore_garbage || c.is_ascii_alphanumeric() || b"+/=".contains(c))
+            .collect::<Vec<_>>();
+
+        match ENGINE.decode(input) {
+            Ok(decoded) => (decoded, 0),
+            Err(_) => (b"base64: invalid input\n".to_vec(), 1),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Base64 {
+    const NAME: &'static str = "base64";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut this = Self {
+            decode: false,
+            ignore_garbage: false,
+            wrap: 76,
+        };
+        let mut file = None;
+        let mut params = params.iter();
+
+        while let Some(param) = params.next() {
+            let wrap = match param.as_str() {
+                "-d" | "-D" | "--decode" => {
+                    this.decode = true;
+                    continue;
+                }
+                "-i" | "--ignore-garbage" => {
+                    this.ignore_garbage = true;
+                    continue;
+                }
+                "-w" | "--wrap" => params.next().map_or("", String::as_str),
+                "-" => continue,
+                v if v.len() > 2
+                    && v.starts_with('-')
+                    && v[1..].chars().all(|c| "dDi".contains(c)) =>
+                {
+                    this.decode |= v.contains(['d', 'D']);
+                    this.ignore_garbage |= v.contains('i');
+                    continue;
+                }
+                v => {
+                    if let Some(wrap) = v.strip_prefix("--wrap=").or(v.strip_prefix("-w")) {
+                        wrap
+                    } else {
+                        file = Some(v);
+                        continue;
+                    }
+                }
+            };
+
+            let Ok(wrap) = wrap.parse() else {
+                session.data(
+                    channel,
+                    format!("base64: invalid wrap size: '{wrap}'\n").into(),
+                );
+                return CommandResult::Exit(1);
+            };
+
+            this.wrap = wrap;
+        }
+
+        let Some(file) = file else {
+            return CommandResult::ReadStdin(this);
+        };
+
+        let (out, status) = match connection.file_system().read(Path::new(file)) {
+            Ok(content) => this.run(content),
+            Err(e) => (format!("base64: {file}: {e}\n").into_bytes(), 1),
+        };
+
+        session.data(channel, out.into());
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, status) = self.run(data);
+        session.data(channel, out.into());
+        CommandResult::Exit(status)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::command::base64::Base64;
+
+    #[test_case(false, false, 76, b"hello world", b"aGVsbG8gd29ybGQ=\n", 0; "encode")]
+    #[test_case(false, false, 4, b"hello", b"aGVs\nbG8=\n", 0; "wrapped")]
+    #[test_case(true, false, 76, b"aGVsbG8g\nd29ybGQ=\n", b"hello world", 0; "decode")]
+    #[test_case(true, false, 76, b"aGVsbG8", b"hello", 0; "missing padding")]
+    #[test_case(true, false, 76, b"aGVs!bG8=", b"base64: invalid input\n", 1; "invalid")]
+    #[test_case(true, true, 76, b"aGVs!bG8=", b"hello", 0; "ignore garbage")]
+    fn runs(
+        decode: bool,
+        ignore_garbage: bool,
+        wrap: usize,
+        input: &[u8],
+        expected: &[u8],
+        status: u32,
+    ) {
+        let this = Base64 {
+            decode,
+            ignore_garbage,
+            wrap,
+        };
+
+        assert_eq!(this.run(input), (expected.to_vec(), status));
+    }
+}
@@ -45,6 +45,8 @@ impl Cat {
 
 #[async_trait]
 impl Command for Cat {
+    const NAME: &'static str = "cat";
+
     async fn new<S: ThrusshSession + Send>(
         connection: &mut ConnectionState,
         params: &[String],
@@ -83,6 +85,7 @@ mod test {
 
     use crate::{
         command::{cat::Cat, Command, CommandResult},
+        file_system::{FileSystem, Tree},
         server::{
             test::{fake_channel_id, predicate::eq_string},
             ConnectionState, MockThrusshSession,
@@ -186,6 +189,35 @@ mod test {
         assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
     }
 
+    #[tokio::test]
+    async fn snapshot_files() {
+        let path =
+            std::env::temp_dir().join(format!("pisshoff-snapshot-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"etc": {"motd": "hello from the snapshot\n"}}"#).unwrap();
+        let snapshot = Tree::load_snapshot(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        *state.file_system() = FileSystem::new("root", Some(&snapshot));
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("hello from the snapshot\n"))
+            .returning(|_, _| ());
+
+        let out = Cat::new(
+            &mut state,
+            ["/etc/motd".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+
     #[tokio::test]
     async fn stdin() {
         let mut session = MockThrusshSession::default();
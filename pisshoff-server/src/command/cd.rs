@@ -0,0 +1,125 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Cd {}
+
+#[async_trait]
+impl Command for Cd {
+    const NAME: &'static str = "cd";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        if params.len() > 1 {
+            session.data(channel, "bash: cd: too many arguments\n".into());
+            return CommandResult::Exit(1);
+        }
+
+        let target = params.first().filter(|v| *v != "~").map(|v| {
+            v.strip_prefix("~/").map_or_else(
+                || Path::new(v).to_path_buf(),
+                |rest| connection.file_system().home().join(rest),
+            )
+        });
+
+        let previous = connection.file_system().pwd().display().to_string();
+
+        match connection.file_system().cd(target.as_deref()) {
+            Ok(()) => {
+                let pwd = connection.file_system().pwd().display().to_string();
+                connection.set_variable("OLDPWD", &previous);
+                connection.set_variable("PWD", &pwd);
+                CommandResult::Exit(0)
+            }
+            Err(e) => {
+                session.data(channel, format!("bash: cd: {}: {e}\n", params[0]).into());
+                CommandResult::Exit(1)
+            }
+        }
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use test_case::test_case;
+
+    use crate::{
+        command::{cd::Cd, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case(&[], "/root"; "no arguments")]
+    #[test_case(&["~"], "/root"; "home")]
+    #[test_case(&["~/a"], "/root/a"; "relative to home")]
+    #[test_case(&["a/../a"], "/root/a"; "relative")]
+    #[test_case(&["/tmp"], "/tmp"; "absolute")]
+    #[tokio::test]
+    async fn works(params: &[&str], expected: &str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("/root/a")).unwrap();
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+
+        let params = params.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let out = Cd::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(state.file_system().pwd(), Path::new(expected));
+    }
+
+    #[test_case("nope", "bash: cd: nope: No such file or directory\n"; "missing")]
+    #[test_case("file", "bash: cd: file: Not a directory\n"; "file")]
+    #[tokio::test]
+    async fn errors(param: &str, expected: &str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("file"), Box::default())
+            .unwrap();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(expected))
+            .returning(|_, _| ());
+
+        let out = Cd::new(
+            &mut state,
+            &[param.to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+        assert_eq!(state.file_system().pwd(), Path::new("/root"));
+    }
+}
@@ -0,0 +1,178 @@
+use std::{collections::BTreeSet, fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::FileOperation;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{record_file_operation, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "Usage: chattr [-pRVf] [-+=aAcCdDeijPsStTuFx] [-v version] files...\n";
+
+/// Attributes `chattr` knows how to set, of which only `i` and `a` have any effect here.
+const ATTRIBUTES: &str = "aAcCdDeFijmPsStTux";
+
+#[derive(Debug, Clone)]
+pub struct Chattr {}
+
+#[async_trait]
+impl Command for Chattr {
+    const NAME: &'static str = "chattr";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut changes = Vec::new();
+    let mut files = Vec::new();
+
+    for param in params {
+        match param.as_str() {
+            "-R" | "-V" | "-f" => {}
+            v if v.len() > 1
+                && v.starts_with(['+', '-', '='])
+                && v[1..].chars().all(|c| ATTRIBUTES.contains(c)) =>
+            {
+                changes.push(v);
+            }
+            v => files.push(v),
+        }
+    }
+
+    if changes.is_empty() {
+        return ("Must use '-v', =, - or +\n".to_string(), 1);
+    }
+
+    if files.is_empty() {
+        return (USAGE.to_string(), 1);
+    }
+
+    let mut out = String::new();
+    let mut status = 0;
+
+    for file in files {
+        let path = Path::new(file);
+
+        let Ok(mut attributes) = connection.file_system().attributes(path) else {
+            writeln!(
+                out,
+                "chattr: No such file or directory while trying to stat {file}"
+            )
+            .unwrap();
+            status = 1;
+            continue;
+        };
+
+        // only the superuser can touch the immutable or append-only flags, and nobody can
+        // change the attributes of a file they don't own
+        if connection.username() != "root" {
+            writeln!(
+                out,
+                "chattr: Operation not permitted while setting flags on {file}"
+            )
+            .unwrap();
+            status = 1;
+            continue;
+        }
+
+        for change in &changes {
+            let flags = change[1..].chars();
+
+            match &change[..1] {
+                "+" => attributes.extend(flags),
+                "-" => attributes.retain(|v| !change[1..].contains(*v)),
+                _ => attributes = flags.collect::<BTreeSet<_>>(),
+            }
+        }
+
+        let _res = connection.file_system().set_attributes(path, attributes);
+
+        record_file_operation(
+            connection,
+            path,
+            FileOperation::Chattr {
+                attributes: changes.join(" ").into(),
+            },
+        );
+    }
+
+    (out, status)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{command::chattr::execute, server::ConnectionState};
+
+    #[test]
+    fn immutable_files_cant_be_changed() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(
+                Path::new("authorized_keys"),
+                b"ssh-rsa AAAA\n".to_vec().into(),
+            )
+            .unwrap();
+
+        let input = shlex::split("+ia authorized_keys").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+        assert!(state
+            .file_system()
+            .write(Path::new("authorized_keys"), Box::default())
+            .is_err());
+        assert!(state
+            .file_system()
+            .remove(Path::new("authorized_keys"))
+            .is_err());
+
+        let input = shlex::split("-i authorized_keys").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+        assert!(state
+            .file_system()
+            .append(Path::new("authorized_keys"), b"ssh-rsa BBBB\n")
+            .is_ok());
+        assert!(state
+            .file_system()
+            .write(Path::new("authorized_keys"), Box::default())
+            .is_err());
+
+        let input = shlex::split("= authorized_keys").unwrap();
+        assert_eq!(
+            execute(&mut state, &input),
+            ("Must use '-v', =, - or +\n".to_string(), 1)
+        );
+    }
+
+    #[test_case("+i", "Usage: chattr [-pRVf] [-+=aAcCdDeijPsStTuFx] [-v version] files...\n"; "no files")]
+    #[test_case("-ia missing", "chattr: No such file or directory while trying to stat missing\n"; "missing")]
+    fn errors(input: &str, expected: &str) {
+        let mut state = ConnectionState::mock();
+        let input = shlex::split(input).unwrap();
+        assert_eq!(execute(&mut state, &input), (expected.to_string(), 1));
+    }
+}
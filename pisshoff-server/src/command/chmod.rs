@@ -0,0 +1,260 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::FileOperation;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{record_file_operation, Command, CommandResult},
+    file_system::Tree,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const TRY_HELP: &str = "Try 'chmod --help' for more information.\n";
+
+#[derive(Debug, Clone)]
+pub struct Chmod {}
+
+#[async_trait]
+impl Command for Chmod {
+    const NAME: &'static str = "chmod";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut recursive = false;
+    let mut mode = None;
+    let mut files = Vec::new();
+
+    for param in params {
+        match param.as_str() {
+            "-R" | "--recursive" => recursive = true,
+            // modes like `-x` look like options, but aren't any that chmod has
+            v if mode.is_none() && is_mode(v) => mode = Some(v),
+            v if v.starts_with('-') && v.len() > 1 => {}
+            v if mode.is_none() => mode = Some(v),
+            v => files.push(v),
+        }
+    }
+
+    let Some(mode) = mode else {
+        return (format!("chmod: missing operand\n{TRY_HELP}"), 1);
+    };
+
+    if files.is_empty() {
+        return (
+            format!("chmod: missing operand after '{mode}'\n{TRY_HELP}"),
+            1,
+        );
+    }
+
+    if parse_mode(mode, 0, false).is_none() {
+        return (format!("chmod: invalid mode: '{mode}'\n{TRY_HELP}"), 1);
+    }
+
+    let mut out = String::new();
+    let mut status = 0;
+
+    for file in files {
+        let path = Path::new(file);
+        let file_system = connection.file_system();
+
+        let targets = match file_system.walk(path) {
+            Ok(targets) if recursive => targets
+                .into_iter()
+                .map(|(v, tree)| {
+                    let target = if v.as_os_str().is_empty() {
+                        path.to_path_buf()
+                    } else {
+                        path.join(v)
+                    };
+
+                    (target, matches!(tree, Tree::Directory(_)))
+                })
+                .collect::<Vec<_>>(),
+            Ok(targets) => targets
+                .into_iter()
+                .take(1)
+                .map(|(_, tree)| (path.to_path_buf(), matches!(tree, Tree::Directory(_))))
+                .collect(),
+            Err(e) => {
+                writeln!(out, "chmod: cannot access '{file}': {e}").unwrap();
+                status = 1;
+                continue;
+            }
+        };
+
+        let mut applied = None;
+
+        for (target, directory) in targets {
+            let current = file_system.mode(&target).unwrap_or_default();
+            let new = parse_mode(mode, current, directory).unwrap_or(current);
+
+            if let Err(e) = file_system.set_mode(&target, new) {
+                writeln!(
+                    out,
+                    "chmod: changing permissions of '{}': {e}",
+                    target.display()
+                )
+                .unwrap();
+                status = 1;
+            } else if applied.is_none() {
+                applied = Some(new);
+            }
+        }
+
+        if let Some(applied) = applied {
+            record_file_operation(
+                connection,
+                path,
+                FileOperation::Chmod {
+                    mode: format!("{applied:04o}").into(),
+                },
+            );
+        }
+    }
+
+    (out, status)
+}
+
+/// Whether `v` is a symbolic mode rather than an option, as in `chmod -x file`.
+fn is_mode(v: &str) -> bool {
+    v.starts_with('-')
+        && v.len() > 1
+        && v.chars().all(|c| "rwxXstugoa+-=,".contains(c))
+        && v != "--"
+}
+
+/// Applies `spec` to the `current` mode of a file, or a directory if `directory` is set, which
+/// may be either octal or symbolic as in `u+x,go-w`.
+pub fn parse_mode(spec: &str, current: u32, directory: bool) -> Option<u32> {
+    if !spec.is_empty() && spec.chars().all(|c| c.is_digit(8)) {
+        return u32::from_str_radix(spec, 8).ok().filter(|v| *v <= 0o7777);
+    }
+
+    let mut mode = current;
+
+    for clause in spec.split(',') {
+        let op = clause.find(['+', '-', '='])?;
+        let (who, rest) = clause.split_at(op);
+
+        let mut mask = 0;
+        for c in who.chars() {
+            mask |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return None,
+            };
+        }
+
+        if who.is_empty() {
+            mask = 0o7777;
+        }
+
+        let mut bits = 0;
+        for c in rest[1..].chars() {
+            bits |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                'X' if directory || current & 0o111 != 0 => 0o111,
+                'X' => 0,
+                's' => 0o6000,
+                't' => 0o1000,
+                _ => return None,
+            };
+        }
+
+        let bits = bits & mask;
+
+        mode = match &rest[..1] {
+            "+" => mode | bits,
+            "-" => mode & !bits,
+            _ => (mode & !mask) | bits,
+        };
+    }
+
+    Some(mode)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pisshoff_types::audit::{AuditLogAction, FileOperation};
+    use test_case::test_case;
+
+    use crate::{
+        command::chmod::{execute, parse_mode},
+        server::ConnectionState,
+    };
+
+    #[test_case("755", 0o644, false, Some(0o755); "octal")]
+    #[test_case("+x", 0o644, false, Some(0o755); "add")]
+    #[test_case("u+x,go-r", 0o644, false, Some(0o700); "clauses")]
+    #[test_case("a=rX", 0o700, true, Some(0o555); "directory")]
+    #[test_case("g=", 0o775, false, Some(0o705); "clear")]
+    #[test_case("u+s", 0o755, false, Some(0o4755); "setuid")]
+    #[test_case("99", 0o644, false, None; "invalid octal")]
+    #[test_case("u+q", 0o644, false, None; "invalid symbolic")]
+    fn parses_modes(spec: &str, current: u32, directory: bool, expected: Option<u32>) {
+        assert_eq!(parse_mode(spec, current, directory), expected);
+    }
+
+    #[test]
+    fn makes_executable() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("x"), b"#!/bin/sh\n".to_vec().into())
+            .unwrap();
+
+        let input = shlex::split("+x x missing").unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(
+            out,
+            "chmod: cannot access 'missing': No such file or directory\n"
+        );
+        assert_eq!(code, 1);
+        assert_eq!(state.file_system().mode(Path::new("x")).unwrap(), 0o755);
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::FileOperation(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.path, "/root/x");
+        assert_eq!(
+            event.operation,
+            FileOperation::Chmod {
+                mode: "0755".into()
+            }
+        );
+    }
+}
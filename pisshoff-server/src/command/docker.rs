@@ -0,0 +1,500 @@
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    fmt::Write,
+    hash::{Hash, Hasher},
+};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, ContainerRequest, ContainerRequestEvent};
+use thrussh::ChannelId;
+use time::OffsetDateTime;
+
+use crate::{
+    command::{Command, CommandResult},
+    persona::Persona,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "
+Usage:  docker [OPTIONS] COMMAND
+
+A self-sufficient runtime for containers
+
+Common Commands:
+  run         Create and run a new container from an image
+  exec        Execute a command in a running container
+  ps          List containers
+  build       Build an image from a Dockerfile
+  pull        Download an image from a registry
+  push        Upload an image to a registry
+  images      List images
+
+Run 'docker COMMAND --help' for more information on a command.
+";
+
+const NOT_RUNNING: &str = "Cannot connect to the Docker daemon at unix:///var/run/docker.sock. Is the docker daemon running?\n";
+
+const PERMISSION_DENIED: &str = "permission denied while trying to connect to the Docker daemon socket at unix:///var/run/docker.sock: dial unix /var/run/docker.sock: connect: permission denied\n";
+
+/// Options to `docker run` that take a value as the following parameter.
+const RUN_WITH_VALUE: &[&str] = &[
+    "-e",
+    "--env",
+    "-p",
+    "--publish",
+    "-w",
+    "--workdir",
+    "-u",
+    "--user",
+    "-h",
+    "--hostname",
+    "-m",
+    "--memory",
+    "-l",
+    "--label",
+    "--name",
+    "--entrypoint",
+    "--network",
+    "--net",
+    "--restart",
+    "--cpus",
+    "--pid",
+    "--ipc",
+    "--cap-add",
+    "--device",
+];
+
+#[derive(Debug, Clone)]
+pub struct Docker {}
+
+#[async_trait]
+impl Command for Docker {
+    const NAME: &'static str = "docker";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let Some((operation, params)) = params.split_first() else {
+        return (USAGE.to_string(), 0);
+    };
+
+    let request = match operation.as_str() {
+        "-v" | "--version" => return ("Docker version 24.0.7, build afdd53b\n".to_string(), 0),
+        "ps" => None,
+        "pull" => match params.iter().find(|v| !v.starts_with('-')) {
+            Some(image) => Some(ContainerRequest::Pull {
+                image: image.as_str().into(),
+            }),
+            None => {
+                return (
+                    "\"docker pull\" requires exactly 1 argument.\nSee 'docker pull --help'.\n\nUsage:  docker pull [OPTIONS] NAME[:TAG|@DIGEST]\n\nDownload an image from a registry\n"
+                        .to_string(),
+                    1,
+                );
+            }
+        },
+        "run" => match parse_run(params) {
+            Some(request) => Some(request),
+            None => {
+                return (
+                    "\"docker run\" requires at least 1 argument.\nSee 'docker run --help'.\n\nUsage:  docker run [OPTIONS] IMAGE [COMMAND] [ARG...]\n\nCreate and run a new container from an image\n"
+                        .to_string(),
+                    1,
+                );
+            }
+        },
+        other => {
+            return (
+                format!("docker: '{other}' is not a docker command.\nSee 'docker --help'\n"),
+                1,
+            );
+        }
+    };
+
+    // worth knowing what they were after, even if the daemon wouldn't have let them
+    if let Some(request) = &request {
+        connection
+            .audit_log()
+            .push_action(AuditLogAction::ContainerRequest(ContainerRequestEvent {
+                tool: Cow::Borrowed(Docker::NAME),
+                request: request.clone(),
+            }));
+    }
+
+    let persona = &connection.config().persona;
+
+    if let Err(message) = connect(persona, connection.username()) {
+        return (message.to_string(), 1);
+    }
+
+    let out = match request {
+        None => {
+            let uptime = (OffsetDateTime::now_utc() - persona.boot_time())
+                .whole_seconds()
+                .unsigned_abs();
+            ps(persona, params, uptime)
+        }
+        Some(ContainerRequest::Pull { image }) => pull(&image),
+        Some(ContainerRequest::Run { image, .. }) => {
+            let mut out = String::new();
+
+            if !persona.containers.iter().any(|v| *v.image == *image) {
+                let (repository, tag) = reference(&image);
+                writeln!(out, "Unable to find image '{repository}:{tag}' locally").unwrap();
+                out.push_str(&pull(&image));
+            }
+
+            if params.iter().any(|v| is_detach(v)) {
+                writeln!(
+                    out,
+                    "{}",
+                    id(connection.rng(("docker", "run", params)).u64(..))
+                )
+                .unwrap();
+            }
+
+            out
+        }
+        Some(ContainerRequest::Apply { .. }) => unreachable!("docker doesn't apply manifests"),
+    };
+
+    (out, 0)
+}
+
+/// Checks the daemon is running and the user is allowed to talk to it, returning the error
+/// docker would print otherwise.
+fn connect(persona: &Persona, username: &str) -> Result<(), &'static str> {
+    if !persona
+        .daemons
+        .iter()
+        .any(|v| v.command.contains("dockerd"))
+    {
+        return Err(NOT_RUNNING);
+    }
+
+    if username != "root"
+        && !persona
+            .identity(username)
+            .groups
+            .iter()
+            .any(|(_, v)| *v == "docker")
+    {
+        return Err(PERMISSION_DENIED);
+    }
+
+    Ok(())
+}
+
+/// Parses the options to `docker run`, up to the image and the command that follows it.
+fn parse_run(params: &[String]) -> Option<ContainerRequest> {
+    let mut privileged = false;
+    let mut volumes = Vec::new();
+    let mut params = params.iter();
+
+    let image = loop {
+        let param = params.next()?;
+        let (option, attached) = match param.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option, Some(value)),
+            _ => (param.as_str(), None),
+        };
+
+        match option {
+            "--privileged" => privileged = true,
+            "-v" | "--volume" | "--mount" => {
+                if let Some(volume) = attached.or_else(|| params.next().map(String::as_str)) {
+                    volumes.push(volume.into());
+                }
+            }
+            v if RUN_WITH_VALUE.contains(&v) => {
+                if attached.is_none() {
+                    params.next();
+                }
+            }
+            v if v.starts_with('-') => {}
+            v => break v,
+        }
+    };
+
+    Some(ContainerRequest::Run {
+        image: image.into(),
+        command: params.map(|v| v.as_str().into()).collect(),
+        privileged,
+        volumes,
+    })
+}
+
+/// Whether `param` asks for the container to run in the background, bundled or not.
+fn is_detach(param: &str) -> bool {
+    param == "--detach"
+        || (param.starts_with('-') && !param.starts_with("--") && param.contains('d'))
+}
+
+/// Lists the persona's containers, all of which were started when the system booted `uptime`
+/// seconds ago.
+fn ps(persona: &Persona, params: &[String], uptime: u64) -> String {
+    if params
+        .iter()
+        .any(|v| v == "--quiet" || (v.starts_with('-') && !v.starts_with("--") && v.contains('q')))
+    {
+        return persona
+            .containers
+            .iter()
+            .map(|v| format!("{}\n", &id(("container", &v.name))[..12]))
+            .collect();
+    }
+
+    let age = human_duration(uptime);
+
+    let mut rows = vec![[
+        "CONTAINER ID".to_string(),
+        "IMAGE".to_string(),
+        "COMMAND".to_string(),
+        "CREATED".to_string(),
+        "STATUS".to_string(),
+        "PORTS".to_string(),
+        "NAMES".to_string(),
+    ]];
+
+    for container in &persona.containers {
+        let command = if container.command.chars().count() > 20 {
+            format!(
+                "{}\u{2026}",
+                container.command.chars().take(19).collect::<String>()
+            )
+        } else {
+            container.command.clone()
+        };
+
+        rows.push([
+            id(("container", &container.name))[..12].to_string(),
+            container.image.clone(),
+            format!("\"{command}\""),
+            format!("{age} ago"),
+            format!("Up {age}"),
+            container.ports.join(", "),
+            container.name.clone(),
+        ]);
+    }
+
+    let widths = (0..rows[0].len())
+        .map(|i| rows.iter().map(|v| v[i].chars().count()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+
+    for row in &rows {
+        let mut line = String::new();
+
+        for (column, width) in row.iter().zip(&widths) {
+            write!(line, "{column:<width$}   ").unwrap();
+        }
+
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+
+    out
+}
+
+fn pull(image: &str) -> String {
+    let (repository, tag) = reference(image);
+
+    // images from docker hub without a namespace are official ones
+    let path = match repository.split_once('/') {
+        Some((registry, path)) if registry.contains(['.', ':']) => path.to_string(),
+        Some(_) => repository.to_string(),
+        None => format!("library/{repository}"),
+    };
+
+    let mut out = String::new();
+
+    if !image.contains(':') {
+        out.push_str("Using default tag: latest\n");
+    }
+
+    writeln!(out, "{tag}: Pulling from {path}").unwrap();
+
+    for i in 0..rng(("layers", image)).usize(1..6) {
+        writeln!(out, "{}: Pull complete", &id(("layer", image, i))[..12]).unwrap();
+    }
+
+    writeln!(out, "Digest: sha256:{}", id(("digest", image))).unwrap();
+    writeln!(out, "Status: Downloaded newer image for {repository}:{tag}").unwrap();
+
+    if repository.contains('/') {
+        writeln!(out, "{repository}:{tag}").unwrap();
+    } else {
+        writeln!(out, "docker.io/library/{repository}:{tag}").unwrap();
+    }
+
+    out
+}
+
+/// Splits an image reference into its repository and tag, the tag defaulting to `latest`.
+fn reference(image: &str) -> (&str, &str) {
+    let name_start = image.rfind('/').map_or(0, |i| i + 1);
+
+    match image[name_start..].split_once(':') {
+        Some((_, tag)) => (&image[..image.len() - tag.len() - 1], tag),
+        None => (image, "latest"),
+    }
+}
+
+fn rng(seed: impl Hash) -> fastrand::Rng {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    fastrand::Rng::with_seed(hasher.finish())
+}
+
+/// Generates a 64 character hex id, as docker uses for containers, layers and digests, so the
+/// same container or layer always gets the same one.
+fn id(seed: impl Hash) -> String {
+    let rng = rng(seed);
+
+    (0..4).fold(String::new(), |mut out, _| {
+        write!(out, "{:016x}", rng.u64(..)).unwrap();
+        out
+    })
+}
+
+/// Formats a duration in seconds the way docker does in `ps`, ie. `2 weeks`.
+fn human_duration(seconds: u64) -> String {
+    let hours = (seconds + 1800) / 3600;
+
+    match seconds {
+        0 => "Less than a second".to_string(),
+        1 => "1 second".to_string(),
+        2..=59 => format!("{seconds} seconds"),
+        60..=119 => "About a minute".to_string(),
+        120..=3599 => format!("{} minutes", seconds / 60),
+        _ if hours == 1 => "About an hour".to_string(),
+        _ if hours < 48 => format!("{hours} hours"),
+        _ if hours < 24 * 7 * 2 => format!("{} days", hours / 24),
+        _ if hours < 24 * 30 * 2 => format!("{} weeks", hours / 24 / 7),
+        _ if hours < 24 * 365 * 2 => format!("{} months", hours / 24 / 30),
+        _ => format!("{} years", hours / 24 / 365),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::{AuditLogAction, ContainerRequest};
+    use test_case::test_case;
+
+    use crate::{
+        command::docker::{
+            connect, execute, human_duration, ps, reference, NOT_RUNNING, PERMISSION_DENIED,
+        },
+        persona::Persona,
+        server::ConnectionState,
+    };
+
+    #[test_case("redis", "redis", "latest"; "bare")]
+    #[test_case("alpine:3.18", "alpine", "3.18"; "tagged")]
+    #[test_case("localhost:5000/miner", "localhost:5000/miner", "latest"; "registry with port")]
+    fn parses_reference(image: &str, repository: &str, tag: &str) {
+        assert_eq!(reference(image), (repository, tag));
+    }
+
+    #[test_case(30, "30 seconds")]
+    #[test_case(4000, "About an hour")]
+    #[test_case(3_576_660, "5 weeks")]
+    fn formats_duration(seconds: u64, expected: &str) {
+        assert_eq!(human_duration(seconds), expected);
+    }
+
+    #[test_case("container", "root", Err(NOT_RUNNING); "no daemon")]
+    #[test_case("debian", "root", Ok(()); "root")]
+    #[test_case("debian", "admin", Ok(()); "docker group")]
+    #[test_case("ubuntu-22.04", "root", Err(NOT_RUNNING); "no daemon on ubuntu")]
+    fn connects(preset: &str, username: &str, expected: Result<(), &str>) {
+        let persona = toml::from_str::<Persona>(&format!("preset = \"{preset}\"")).unwrap();
+        assert_eq!(connect(&persona, username), expected);
+    }
+
+    #[test]
+    fn refuses_users_outside_docker_group() {
+        let mut persona = toml::from_str::<Persona>("preset = \"debian\"").unwrap();
+        persona.groups.retain(|v| v.name != "docker");
+        assert_eq!(connect(&persona, "admin"), Err(PERMISSION_DENIED));
+    }
+
+    #[test]
+    fn lists_persona_containers() {
+        let persona = toml::from_str::<Persona>("preset = \"debian\"").unwrap();
+        let out = ps(&persona, &[], 3_576_660);
+
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "CONTAINER ID   IMAGE       COMMAND                  CREATED       STATUS       PORTS                      NAMES"
+        );
+
+        let line = lines.next().unwrap();
+        assert!(
+            line.ends_with("   redis:7.2   \"docker-entrypoint.s\u{2026}\"   5 weeks ago   Up 5 weeks   127.0.0.1:6379->6379/tcp   redis"),
+            "{out}"
+        );
+        assert_eq!(lines.next(), None);
+
+        assert_eq!(
+            ps(&persona, &["-q".to_string()], 0),
+            format!("{}\n", &line[..12])
+        );
+    }
+
+    #[test]
+    fn records_run_without_daemon() {
+        let mut state = ConnectionState::mock();
+
+        let input =
+            shlex::split("run -d --rm --privileged -v /:/mnt --name x alpine chroot /mnt sh")
+                .unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, 1, "{out}");
+        assert!(
+            out.starts_with("Cannot connect to the Docker daemon"),
+            "{out}"
+        );
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::ContainerRequest(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.tool, "docker");
+        assert_eq!(
+            event.request,
+            ContainerRequest::Run {
+                image: "alpine".into(),
+                command: vec!["chroot".into(), "/mnt".into(), "sh".into()],
+                privileged: true,
+                volumes: vec!["/:/mnt".into()],
+            }
+        );
+    }
+}
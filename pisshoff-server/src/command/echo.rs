@@ -12,6 +12,8 @@ pub struct Echo {}
 
 #[async_trait]
 impl Command for Echo {
+    const NAME: &'static str = "echo";
+
     async fn new<S: ThrusshSession + Send>(
         _connection: &mut ConnectionState,
         params: &[String],
@@ -0,0 +1,223 @@
+use std::{borrow::Cow, fmt::Write};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult, ConcreteCommand},
+    server::{ConnectionState, Environment, ThrusshSession},
+};
+
+/// Runs a command with its own environment, which is swapped in whenever the command is
+/// called into so anything it changes stays with it.
+#[derive(Debug, Clone)]
+pub struct Env {
+    environment: Environment,
+    command: Box<ConcreteCommand>,
+}
+
+#[async_trait]
+impl Command for Env {
+    const NAME: &'static str = "env";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut environment = connection.environment().clone();
+        let mut i = 0;
+
+        while let Some(param) = params.get(i) {
+            match param.as_str() {
+                "-i" | "--ignore-environment" | "-" => environment.clear(),
+                "-u" | "--unset" => {
+                    i += 1;
+
+                    let Some(name) = params.get(i) else {
+                        session.data(
+                            channel,
+                            "env: option requires an argument -- 'u'\nTry 'env --help' for \
+                             more information.\n"
+                                .to_string()
+                                .into(),
+                        );
+                        return CommandResult::Exit(125);
+                    };
+
+                    environment.remove(name.as_bytes());
+                }
+                "-0" | "--null" => {}
+                v if v.starts_with('-') && v.len() > 1 => {
+                    session.data(
+                        channel,
+                        format!(
+                            "env: invalid option -- '{}'\nTry 'env --help' for more \
+                             information.\n",
+                            v.trim_start_matches('-')
+                        )
+                        .into(),
+                    );
+                    return CommandResult::Exit(125);
+                }
+                v if v.contains('=') => assign(&mut environment, std::slice::from_ref(param)),
+                _ => break,
+            }
+
+            i += 1;
+        }
+
+        let command = &params[i..];
+
+        if command.is_empty() {
+            session.data(channel, list(&environment).into());
+            return CommandResult::Exit(0);
+        }
+
+        Self::run(environment, command, connection, channel, session).await
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        std::mem::swap(connection.environment_mut(), &mut self.environment);
+        let res = self.command.stdin(connection, channel, data, session).await;
+        std::mem::swap(connection.environment_mut(), &mut self.environment);
+
+        match res {
+            CommandResult::ReadStdin(command) => CommandResult::ReadStdin(Self {
+                environment: self.environment,
+                command: Box::new(command),
+            }),
+            CommandResult::Exit(v) => CommandResult::Exit(v),
+            CommandResult::Close(v) => CommandResult::Close(v),
+        }
+    }
+}
+
+impl Env {
+    /// Starts running `params` within `environment`, leaving the session's own untouched.
+    pub async fn run<S: ThrusshSession + Send>(
+        mut environment: Environment,
+        params: &[String],
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let Some((exec, params)) = params.split_first() else {
+            return CommandResult::Exit(0);
+        };
+
+        std::mem::swap(connection.environment_mut(), &mut environment);
+        let res =
+            ConcreteCommand::new(connection, Some(exec.as_bytes()), params, channel, session).await;
+        std::mem::swap(connection.environment_mut(), &mut environment);
+
+        match res {
+            CommandResult::ReadStdin(command) => CommandResult::ReadStdin(Self {
+                environment,
+                command: Box::new(command),
+            }),
+            CommandResult::Exit(v) => CommandResult::Exit(v),
+            CommandResult::Close(v) => CommandResult::Close(v),
+        }
+    }
+}
+
+/// Applies `NAME=value` assignments to `environment`.
+pub fn assign(environment: &mut Environment, assignments: &[String]) {
+    for (name, value) in assignments.iter().filter_map(|v| v.split_once('=')) {
+        environment.insert(
+            Cow::Owned(name.as_bytes().to_vec()),
+            Cow::Owned(value.as_bytes().to_vec()),
+        );
+    }
+}
+
+/// The variables in `environment` sorted by name, leaving out special parameters such as `?`.
+pub fn variables(environment: &Environment) -> Vec<(String, String)> {
+    let mut variables = environment
+        .iter()
+        .map(|(k, v)| {
+            (
+                String::from_utf8_lossy(k).into_owned(),
+                String::from_utf8_lossy(v).into_owned(),
+            )
+        })
+        .filter(|(k, _)| super::is_name(k))
+        .collect::<Vec<_>>();
+    variables.sort();
+    variables
+}
+
+/// Lists the variables in `environment` as `NAME=value` lines, as `env` and `printenv` do.
+pub fn list(environment: &Environment) -> String {
+    let mut out = String::new();
+
+    for (name, value) in variables(environment) {
+        writeln!(out, "{name}={value}").unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+
+    use crate::{
+        command::{env::Env, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[tokio::test]
+    async fn lists() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_variable("HOME", "/root");
+        state.set_variable("C2", "http://203.0.113.5/x");
+        state.set_exit_status(1);
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string("C2=http://203.0.113.5/x\nHOME=/root\nLANG=C\n"),
+            )
+            .returning(|_, _| ());
+
+        let params = ["LANG=C".to_string()];
+        let out = Env::new(&mut state, &params, fake_channel_id(), &mut session).await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        // the assignment only applies to env itself
+        assert_eq!(state.environment().get(b"LANG".as_slice()), None);
+    }
+
+    #[tokio::test]
+    async fn runs_command() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_variable("HOME", "/root");
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("A=b\n"))
+            .returning(|_, _| ());
+
+        let params = shlex::split("-i A=b env").unwrap();
+        let out = Env::new(&mut state, &params, fake_channel_id(), &mut session).await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert!(state.environment().get(b"HOME".as_slice()).is_some());
+    }
+}
@@ -1,5 +1,3 @@
-use std::str::FromStr;
-
 use async_trait::async_trait;
 use thrussh::ChannelId;
 
@@ -13,19 +11,39 @@ pub struct Exit {}
 
 #[async_trait]
 impl Command for Exit {
+    const NAME: &'static str = "exit";
+    const ALIASES: &'static [&'static str] = &["logout"];
+
     async fn new<S: ThrusshSession + Send>(
-        _connection: &mut ConnectionState,
+        connection: &mut ConnectionState,
         params: &[String],
-        _channel: ChannelId,
-        _session: &mut S,
+        channel: ChannelId,
+        session: &mut S,
     ) -> CommandResult<Self> {
-        let exit_status = params
-            .first()
-            .map(String::as_str)
-            .map_or(Ok(0), u32::from_str)
-            .unwrap_or(2);
+        let [status] = params else {
+            if params.is_empty() {
+                // exiting without a status passes on that of the last command
+                return CommandResult::Close(connection.exit_status());
+            }
+
+            session.data(
+                channel,
+                "bash: exit: too many arguments\n".to_string().into(),
+            );
+            return CommandResult::Exit(1);
+        };
 
-        CommandResult::Close(exit_status)
+        // statuses wrap around as they're truncated to a byte, so `exit -1` exits with 255
+        match status.parse::<i64>() {
+            Ok(v) => CommandResult::Close(u32::try_from(v.rem_euclid(256)).unwrap_or_default()),
+            Err(_) => {
+                session.data(
+                    channel,
+                    format!("bash: exit: {status}: numeric argument required\n").into(),
+                );
+                CommandResult::Close(2)
+            }
+        }
     }
 
     async fn stdin<S: ThrusshSession + Send>(
@@ -41,19 +59,26 @@ impl Command for Exit {
 
 #[cfg(test)]
 mod test {
+    use mockall::predicate::always;
     use test_case::test_case;
 
     use crate::{
         command::{exit::Exit, Command, CommandResult},
-        server::{test::fake_channel_id, ConnectionState, MockThrusshSession},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
     };
 
     #[test_case(&[], 0; "no parameters")]
     #[test_case(&["3"], 3; "with parameter")]
+    #[test_case(&["300"], 44; "wraps")]
+    #[test_case(&["-1"], 255; "negative")]
     #[test_case(&["invalid"], 2; "invalid parameter")]
     #[tokio::test]
     async fn test(params: &[&str], expected_exit_code: u32) {
         let mut session = MockThrusshSession::default();
+        session.expect_data().returning(|_, _| ());
 
         let out = Exit::new(
             &mut ConnectionState::mock(),
@@ -72,4 +97,33 @@ mod test {
             "{out:?}"
         );
     }
+
+    #[tokio::test]
+    async fn last_status() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.set_exit_status(127);
+
+        let out = Exit::new(&mut state, &[], fake_channel_id(), &mut session).await;
+        assert!(matches!(out, CommandResult::Close(127)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn too_many_arguments() {
+        let mut session = MockThrusshSession::default();
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("bash: exit: too many arguments\n"))
+            .returning(|_, _| ());
+
+        let out = Exit::new(
+            &mut ConnectionState::mock(),
+            &["1".to_string(), "2".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
 }
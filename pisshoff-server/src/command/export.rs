@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{env, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Export {}
+
+#[async_trait]
+impl Command for Export {
+    const NAME: &'static str = "export";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut out = String::new();
+    let mut exit_code = 0;
+    let mut names = params.iter().filter(|v| !v.starts_with('-')).peekable();
+
+    // every variable we keep is exported, so there's nothing else to list
+    if names.peek().is_none() {
+        for (name, value) in env::variables(connection.environment()) {
+            writeln!(out, "declare -x {name}={value:?}").unwrap();
+        }
+
+        return (out, 0);
+    }
+
+    for param in names {
+        let (name, value) = param
+            .split_once('=')
+            .map_or((param.as_str(), None), |(name, value)| (name, Some(value)));
+
+        if !super::is_name(name) {
+            writeln!(out, "bash: export: `{param}': not a valid identifier").unwrap();
+            exit_code = 1;
+            continue;
+        }
+
+        if let Some(value) = value {
+            connection.set_variable(name, value);
+        }
+    }
+
+    (out, exit_code)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::export::execute, server::ConnectionState};
+
+    #[test_case("C2=http://203.0.113.5/x", "", 0; "assign")]
+    #[test_case("A", "", 0; "existing")]
+    #[test_case("", "declare -x A=\"b\"\n", 0; "list")]
+    #[test_case("-p", "declare -x A=\"b\"\n", 0; "list with option")]
+    #[test_case("1A=b", "bash: export: `1A=b': not a valid identifier\n", 1; "invalid")]
+    fn works(input: &str, expected: &str, exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        state.set_variable("A", "b");
+
+        let input = shlex::split(input).unwrap();
+        assert_eq!(
+            execute(&mut state, &input),
+            (expected.to_string(), exit_code)
+        );
+    }
+
+    #[test]
+    fn sets() {
+        let mut state = ConnectionState::mock();
+
+        let input = shlex::split("C2=http://203.0.113.5/x").unwrap();
+        execute(&mut state, &input);
+
+        assert_eq!(
+            state.environment().get(b"C2".as_slice()).map(|v| &v[..]),
+            Some(b"http://203.0.113.5/x".as_slice())
+        );
+    }
+}
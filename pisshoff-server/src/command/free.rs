@@ -0,0 +1,175 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    persona::{human_size, Persona},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Free {}
+
+#[async_trait]
+impl Command for Free {
+    const NAME: &'static str = "free";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(params, &connection.config().persona);
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Bytes,
+    Kibi,
+    Mebi,
+    Gibi,
+    Human,
+}
+
+impl Unit {
+    fn format(self, kibibytes: u64) -> String {
+        match self {
+            Self::Bytes => (kibibytes * 1024).to_string(),
+            Self::Kibi => kibibytes.to_string(),
+            Self::Mebi => (kibibytes / 1024).to_string(),
+            Self::Gibi => (kibibytes / 1024 / 1024).to_string(),
+            Self::Human if kibibytes == 0 => "0B".to_string(),
+            Self::Human => human_size(kibibytes, "i"),
+        }
+    }
+}
+
+fn execute(params: &[String], persona: &Persona) -> (String, u32) {
+    let mut unit = Unit::Kibi;
+    let mut total = false;
+
+    for param in super::argparse(params) {
+        unit = match param {
+            Arg::Short('b') | Arg::Long("bytes") => Unit::Bytes,
+            Arg::Short('k') | Arg::Long("kibi" | "kilo") => Unit::Kibi,
+            Arg::Short('m') | Arg::Long("mebi" | "mega") => Unit::Mebi,
+            Arg::Short('g') | Arg::Long("gibi" | "giga") => Unit::Gibi,
+            Arg::Short('h') | Arg::Long("human") => Unit::Human,
+            Arg::Short('t') | Arg::Long("total") => {
+                total = true;
+                continue;
+            }
+            Arg::Short('w' | 'l') | Arg::Long("wide" | "lohi") => continue,
+            Arg::Operand(v) => {
+                return (
+                    format!("free: extra operand '{v}'\nTry 'free --help' for more information.\n"),
+                    1,
+                );
+            }
+            Arg::Short(c) => {
+                return (
+                    format!(
+                        "free: invalid option -- '{c}'\nTry 'free --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!("free: unrecognized option '--{v}'\nTry 'free --help' for more information.\n"),
+                    1,
+                );
+            }
+        };
+    }
+
+    let memory = persona.memory_usage();
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "{:>20}{:>12}{:>12}{:>12}{:>12}{:>12}",
+        "total", "used", "free", "shared", "buff/cache", "available"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "{:<8}{:>12}{:>12}{:>12}{:>12}{:>12}{:>12}",
+        "Mem:",
+        unit.format(memory.total),
+        unit.format(memory.used),
+        unit.format(memory.free),
+        unit.format(memory.shared),
+        unit.format(memory.buff_cache),
+        unit.format(memory.available),
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "{:<8}{:>12}{:>12}{:>12}",
+        "Swap:",
+        unit.format(persona.swap),
+        unit.format(0),
+        unit.format(persona.swap),
+    )
+    .unwrap();
+
+    if total {
+        writeln!(
+            out,
+            "{:<8}{:>12}{:>12}{:>12}",
+            "Total:",
+            unit.format(memory.total + persona.swap),
+            unit.format(memory.used),
+            unit.format(memory.free + persona.swap),
+        )
+        .unwrap();
+    }
+
+    (out, 0)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{command::free::execute, persona::Persona};
+
+    #[test]
+    fn kibibytes() {
+        let (out, exit_code) = execute(&[], &Persona::default());
+
+        assert_eq!(
+            out,
+            "               total        used        free      shared  buff/cache   available
+Mem:         8148348     1222252     5377910       16296     1548186     6771277
+Swap:        2097148           0     2097148
+"
+        );
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test]
+    fn human() {
+        let (out, exit_code) = execute(&["-h".to_string()], &Persona::default());
+
+        assert!(out.contains("\nMem:           7.8Gi"), "{out}");
+        assert!(out.contains("\nSwap:          2.0Gi"), "{out}");
+        assert_eq!(exit_code, 0);
+    }
+}
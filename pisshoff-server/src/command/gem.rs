@@ -0,0 +1,210 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use pisshoff_types::audit::Package;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "RubyGems is a package management framework for Ruby.
+
+  Usage:
+    gem -h/--help
+    gem -v/--version
+    gem command [arguments...] [options...]
+
+  Examples:
+    gem install rake
+    gem list --local
+    gem build package.gemspec
+    gem help install
+";
+
+const VERSION: &str = "3.3.5\n";
+
+#[derive(Debug, Clone)]
+pub struct Gem {}
+
+#[async_trait]
+impl Command for Gem {
+    const NAME: &'static str = "gem";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let Some((operation, params)) = params.split_first() else {
+        return (USAGE.to_string(), 0);
+    };
+
+    match operation.as_str() {
+        "install" | "i" => install(connection, params),
+        "-v" | "--version" => (VERSION.to_string(), 0),
+        "list" => (
+            "\n*** LOCAL GEMS ***\n\nbundler (default: 2.3.5)\nrake (13.0.6)\n".to_string(),
+            0,
+        ),
+        "uninstall" => {
+            let mut out = String::new();
+
+            for gem in params.iter().filter(|v| !v.starts_with('-')) {
+                writeln!(out, "Gem '{gem}' is not installed").unwrap();
+            }
+
+            (out, 0)
+        }
+        other => (
+            format!("ERROR:  While executing gem ... (Gem::CommandLineError)\n    Unknown command {other}\n"),
+            1,
+        ),
+    }
+}
+
+fn install(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut packages = Vec::<Package>::new();
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            // applies to the gem before it
+            "-v" | "--version" => {
+                let version = params.next().map(|v| v.as_str().into());
+
+                if let Some(package) = packages.last_mut() {
+                    package.version = version;
+                }
+            }
+            "-i" | "--install-dir" | "-s" | "--source" | "-n" | "--bindir" => {
+                params.next();
+            }
+            v if v.starts_with('-') => {}
+            v => packages.push(match v.split_once(':') {
+                Some((name, version)) => Package {
+                    name: name.into(),
+                    version: Some(version.into()),
+                },
+                None => Package {
+                    name: v.into(),
+                    version: None,
+                },
+            }),
+        }
+    }
+
+    if packages.is_empty() {
+        return (
+            "ERROR:  While executing gem ... (Gem::CommandLineError)\n    Please specify at least one gem name (e.g. gem build GEMNAME)\n"
+                .to_string(),
+            1,
+        );
+    }
+
+    let mut out = String::new();
+
+    if connection.username() == "root" {
+        for package in &packages {
+            let version = package.version.as_deref().map_or_else(
+                || super::package_details(connection, &package.name).0,
+                ToString::to_string,
+            );
+            let name = &package.name;
+
+            writeln!(out, "Fetching {name}-{version}.gem").unwrap();
+            writeln!(out, "Successfully installed {name}-{version}").unwrap();
+            writeln!(out, "Parsing documentation for {name}-{version}").unwrap();
+            writeln!(out, "Installing ri documentation for {name}-{version}").unwrap();
+        }
+
+        writeln!(
+            out,
+            "Done installing documentation for {} after 0 seconds",
+            packages
+                .iter()
+                .map(|v| &*v.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "{} gem{} installed",
+            packages.len(),
+            if packages.len() == 1 { "" } else { "s" }
+        )
+        .unwrap();
+
+        super::record_packages(connection, Gem::NAME, packages);
+        (out, 0)
+    } else {
+        super::record_packages(connection, Gem::NAME, packages);
+        (
+            "ERROR:  While executing gem ... (Gem::FilePermissionError)\n    You don't have write permissions for the /var/lib/gems/3.0.0 directory.\n"
+                .to_string(),
+            1,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{command::gem::execute, server::ConnectionState};
+
+    #[test_case("root", 0; "root")]
+    #[test_case("ubuntu", 1; "not root")]
+    fn records_install(username: &str, exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        state.switch_user(username, false);
+
+        let input =
+            shlex::split("install --no-document net-ssh -v 7.2.0 bcrypt_pbkdf:1.1.0").unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, exit_code, "{out}");
+
+        if exit_code == 0 {
+            assert!(
+                out.contains("Successfully installed net-ssh-7.2.0\n"),
+                "{out}"
+            );
+            assert!(out.ends_with("\n2 gems installed\n"), "{out}");
+        }
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PackageInstall(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.manager, "gem");
+        assert_eq!(event.packages[0].version.as_deref(), Some("7.2.0"));
+        assert_eq!(&*event.packages[1].name, "bcrypt_pbkdf");
+        assert_eq!(event.packages[1].version.as_deref(), Some("1.1.0"));
+    }
+}
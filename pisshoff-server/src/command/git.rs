@@ -0,0 +1,300 @@
+use std::{borrow::Cow, fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, DownloadAttemptEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    file_system::Tree,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "usage: git [--version] [--help] [-C <path>] [-c <name>=<value>]
+           [--exec-path[=<path>]] [--html-path] [--man-path] [--info-path]
+           [-p | --paginate | -P | --no-pager] [--no-replace-objects] [--bare]
+           [--git-dir=<path>] [--work-tree=<path>] [--namespace=<name>]
+           <command> [<args>]
+
+These are common Git commands used in various situations:
+
+start a working area (see also: git help tutorial)
+   clone     Clone a repository into a new directory
+   init      Create an empty Git repository or reinitialize an existing one
+
+collaborate (see also: git help workflows)
+   fetch     Download objects and refs from another repository
+   pull      Fetch from and integrate with another repository or a local branch
+   push      Update remote refs along with associated objects
+";
+
+const NOT_A_REPOSITORY: &str =
+    "fatal: not a git repository (or any of the parent directories): .git\n";
+
+/// Options to `git clone` that take a value as the following parameter.
+const CLONE_WITH_VALUE: &[&str] = &[
+    "-b",
+    "--branch",
+    "-o",
+    "--origin",
+    "-c",
+    "--config",
+    "-j",
+    "--jobs",
+    "-u",
+    "--upload-pack",
+    "--depth",
+    "--reference",
+    "--separate-git-dir",
+    "--filter",
+    "--template",
+];
+
+#[derive(Debug, Clone)]
+pub struct Git {}
+
+#[async_trait]
+impl Command for Git {
+    const NAME: &'static str = "git";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut params = params.iter();
+
+    let operation = loop {
+        match params.next().map(String::as_str) {
+            None => return (USAGE.to_string(), 1),
+            Some("--version") => return ("git version 2.34.1\n".to_string(), 0),
+            Some("-C" | "-c") => {
+                params.next();
+            }
+            Some(v) if v.starts_with('-') => {}
+            Some(v) => break v,
+        }
+    };
+
+    let params = params.as_slice();
+
+    match operation {
+        "clone" => clone(connection, params),
+        "init" => {
+            let path = connection.file_system().canonicalize(Path::new(".git"));
+            let _res = connection.file_system().mkdirall(&path);
+            (
+                format!("Initialized empty Git repository in {}/\n", path.display()),
+                0,
+            )
+        }
+        "pull" | "fetch" | "push" | "status" | "log" | "checkout" | "submodule" => {
+            (NOT_A_REPOSITORY.to_string(), 128)
+        }
+        other => (
+            format!("git: '{other}' is not a git command. See 'git --help'.\n"),
+            1,
+        ),
+    }
+}
+
+fn clone(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut operands = Vec::new();
+    let mut quiet = false;
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            v if CLONE_WITH_VALUE.contains(&v) => {
+                params.next();
+            }
+            v if v.starts_with('-') => {}
+            v => operands.push(v),
+        }
+    }
+
+    let (url, directory) = match operands.as_slice() {
+        [url] => (*url, directory_for(url)),
+        [url, directory, ..] => (*url, (*directory).to_string()),
+        [] => {
+            return (
+                "fatal: You must specify a repository to clone.\n\nusage: git clone [<options>] [--] <repo> [<dir>]\n"
+                    .to_string(),
+                129,
+            );
+        }
+    };
+
+    if !is_remote(url) {
+        return (format!("fatal: repository '{url}' does not exist\n"), 128);
+    }
+
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::DownloadAttempt(DownloadAttemptEvent {
+            tool: Cow::Borrowed(Git::NAME),
+            url: Box::from(url),
+            output: Some(directory.as_str().into()),
+            sha256: None,
+            size: None,
+            error: None,
+        }));
+
+    let path = Path::new(&directory);
+
+    let exists = match connection.file_system().get(path) {
+        Ok(Tree::Directory(v)) => !v.is_empty(),
+        Ok(Tree::File(_)) => true,
+        Err(_) => false,
+    };
+
+    if exists {
+        return (
+            format!("fatal: destination path '{directory}' already exists and is not an empty directory.\n"),
+            128,
+        );
+    }
+
+    let _res = connection.file_system().mkdirall(&path.join(".git"));
+    let _res = connection.file_system().write(
+        &path.join("README.md"),
+        format!("# {directory}\n").into_bytes().into(),
+    );
+
+    if quiet {
+        return (String::new(), 0);
+    }
+
+    let mut out = format!("Cloning into '{directory}'...\n");
+
+    let rng = connection.rng(("git", url));
+    let objects = rng.u32(20..4000);
+    let compressed = objects * 2 / 3;
+    let deltas = objects / 2;
+    let reused = objects - rng.u32(0..objects / 4 + 1);
+    let size = f64::from(objects) * 1.7;
+
+    writeln!(out, "remote: Enumerating objects: {objects}, done.").unwrap();
+    writeln!(
+        out,
+        "remote: Counting objects: 100% ({objects}/{objects}), done."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "remote: Compressing objects: 100% ({compressed}/{compressed}), done."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "remote: Total {objects} (delta {deltas}), reused {reused} (delta {}), pack-reused 0",
+        deltas * 3 / 4
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "Receiving objects: 100% ({objects}/{objects}), {size:.2} KiB | 2.41 MiB/s, done."
+    )
+    .unwrap();
+    writeln!(out, "Resolving deltas: 100% ({deltas}/{deltas}), done.").unwrap();
+
+    (out, 0)
+}
+
+/// Whether `url` points at another machine, either as a URL or scp-like `user@host:path`.
+fn is_remote(url: &str) -> bool {
+    url.contains("://")
+        || url
+            .split_once(':')
+            .map_or(false, |(host, _)| !host.is_empty() && !host.contains('/'))
+}
+
+/// Works out the directory a repository is cloned into when one isn't given, the "humanish"
+/// part of the URL as git calls it.
+fn directory_for(url: &str) -> String {
+    let path = url.trim_end_matches('/');
+    let path = path.strip_suffix("/.git").unwrap_or(path);
+    let name = path.rsplit(['/', ':']).next().unwrap_or(path);
+
+    name.strip_suffix(".git").unwrap_or(name).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::git::{directory_for, execute, is_remote},
+        server::ConnectionState,
+    };
+
+    #[test_case("https://github.com/rapid7/metasploit-framework.git", "metasploit-framework"; "https")]
+    #[test_case("https://github.com/user/repo/", "repo"; "trailing slash")]
+    #[test_case("git@github.com:user/miner.git", "miner"; "scp-like")]
+    #[test_case("host:repo", "repo"; "scp-like without path")]
+    fn humanish_directory(url: &str, expected: &str) {
+        assert_eq!(directory_for(url), expected);
+    }
+
+    #[test_case("https://github.com/user/repo", true; "url")]
+    #[test_case("git@github.com:user/repo", true; "scp-like")]
+    #[test_case("./repo", false; "local")]
+    #[test_case("/tmp/a:b", false; "local with colon")]
+    fn remote(url: &str, expected: bool) {
+        assert_eq!(is_remote(url), expected);
+    }
+
+    #[test]
+    fn records_clone() {
+        let mut state = ConnectionState::mock();
+
+        let input = shlex::split("clone --depth 1 https://github.com/user/xmrig.git").unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, 0, "{out}");
+        assert!(out.starts_with("Cloning into 'xmrig'...\n"), "{out}");
+        assert!(out.contains("\nResolving deltas: 100% ("), "{out}");
+        assert!(state.file_system().get(Path::new("xmrig/.git")).is_ok());
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::DownloadAttempt(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.tool, "git");
+        assert_eq!(&*event.url, "https://github.com/user/xmrig.git");
+        assert_eq!(event.output.as_deref(), Some("xmrig"));
+
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, 128, "{out}");
+        assert_eq!(
+            out,
+            "fatal: destination path 'xmrig' already exists and is not an empty directory.\n"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use regex::{Regex, RegexBuilder};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str =
+    "Usage: grep [OPTION]... PATTERNS [FILE]...\nTry 'grep --help' for more information.\n";
+
+/// A basic `grep`, enough for recon pipelines like `lspci | grep -i vga` to behave. Basic and
+/// extended expressions are both treated as the latter.
+#[derive(Debug, Clone)]
+pub struct Grep {
+    pattern: Regex,
+    invert: bool,
+    count: bool,
+    quiet: bool,
+    line_number: bool,
+}
+
+impl Grep {
+    /// Writes out the lines of `content` that match, prefixed with `name` if there's more than
+    /// one file being searched. Returns whether any lines matched.
+    fn search(&self, content: &[u8], name: Option<&str>, out: &mut String) -> bool {
+        let content = String::from_utf8_lossy(content);
+        let mut matches = 0;
+
+        for (i, line) in content.lines().enumerate() {
+            if self.pattern.is_match(line) == self.invert {
+                continue;
+            }
+
+            matches += 1;
+
+            if self.quiet || self.count {
+                continue;
+            }
+
+            if let Some(name) = name {
+                write!(out, "{name}:").unwrap();
+            }
+
+            if self.line_number {
+                write!(out, "{}:", i + 1).unwrap();
+            }
+
+            writeln!(out, "{line}").unwrap();
+        }
+
+        if self.count && !self.quiet {
+            if let Some(name) = name {
+                write!(out, "{name}:").unwrap();
+            }
+
+            writeln!(out, "{matches}").unwrap();
+        }
+
+        matches > 0
+    }
+}
+
+#[async_trait]
+impl Command for Grep {
+    const NAME: &'static str = "grep";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (this, files) = match parse(params) {
+            Ok(v) => v,
+            Err(e) => {
+                session.data(channel, e.into());
+                return CommandResult::Exit(2);
+            }
+        };
+
+        if files.is_empty() {
+            return CommandResult::ReadStdin(this);
+        }
+
+        let mut out = String::new();
+        let mut matched = false;
+        let mut failed = false;
+
+        for file in &files {
+            let name = (files.len() > 1).then_some(file.as_str());
+
+            match connection.file_system().read(Path::new(file)) {
+                Ok(content) => matched |= this.search(content, name, &mut out),
+                Err(e) => {
+                    writeln!(out, "grep: {file}: {e}").unwrap();
+                    failed = true;
+                }
+            }
+        }
+
+        session.data(channel, out.into());
+
+        CommandResult::Exit(match (matched, failed) {
+            (true, _) => 0,
+            (false, true) => 2,
+            (false, false) => 1,
+        })
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut out = String::new();
+        let matched = self.search(data, None, &mut out);
+        session.data(channel, out.into());
+        CommandResult::Exit(u32::from(!matched))
+    }
+}
+
+/// Parses the options given to `grep`, returning the searcher along with the files to search.
+fn parse(params: &[String]) -> Result<(Grep, Vec<String>), String> {
+    let mut pattern = None;
+    let mut operands = Vec::new();
+    let mut ignore_case = false;
+    let mut fixed = false;
+    let mut invert = false;
+    let mut count = false;
+    let mut quiet = false;
+    let mut line_number = false;
+
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            "--" => operands.extend(params.by_ref().cloned()),
+            "--ignore-case" => ignore_case = true,
+            "--invert-match" => invert = true,
+            "--count" => count = true,
+            "--quiet" | "--silent" => quiet = true,
+            "--line-number" => line_number = true,
+            "--fixed-strings" => fixed = true,
+            "--extended-regexp" | "--basic-regexp" => {}
+            v if v.starts_with("--") => {
+                return Err(format!("grep: unrecognized option '{v}'\n{USAGE}"));
+            }
+            v if v.starts_with('-') && v.len() > 1 => {
+                for (i, flag) in v.char_indices().skip(1) {
+                    match flag {
+                        'i' | 'y' => ignore_case = true,
+                        'v' => invert = true,
+                        'c' => count = true,
+                        'q' | 's' => quiet = true,
+                        'n' => line_number = true,
+                        'F' => fixed = true,
+                        'E' | 'G' => {}
+                        'e' => {
+                            let rest = &v[i + 1..];
+                            pattern = if rest.is_empty() {
+                                params.next().cloned()
+                            } else {
+                                Some(rest.to_string())
+                            };
+                            break;
+                        }
+                        other => {
+                            return Err(format!("grep: invalid option -- '{other}'\n{USAGE}"));
+                        }
+                    }
+                }
+            }
+            v => operands.push(v.to_string()),
+        }
+    }
+
+    let pattern = match pattern {
+        Some(v) => v,
+        None if operands.is_empty() => return Err(USAGE.to_string()),
+        None => operands.remove(0),
+    };
+
+    let pattern = if fixed {
+        regex::escape(&pattern)
+    } else {
+        pattern
+    };
+
+    let pattern = RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|_| "grep: Invalid regular expression\n".to_string())?;
+
+    Ok((
+        Grep {
+            pattern,
+            invert,
+            count,
+            quiet,
+            line_number,
+        },
+        operands,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use test_case::test_case;
+
+    use crate::{
+        command::{grep::Grep, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    const LSPCI: &str = "00:01.1 IDE interface: Intel Corporation 82371SB PIIX3 IDE\n00:02.0 VGA compatible controller: Cirrus Logic GD 5446\n00:03.0 Ethernet controller: Red Hat, Inc. Virtio network device\n";
+
+    #[test_case("-i vga", "00:02.0 VGA compatible controller: Cirrus Logic GD 5446\n", 0; "ignore case")]
+    #[test_case("vga", "", 1; "case sensitive")]
+    #[test_case("-vc -e VGA", "2\n", 0; "combined flags")]
+    #[test_case("-n -E 'IDE|Ethernet'", "1:00:01.1 IDE interface: Intel Corporation 82371SB PIIX3 IDE\n3:00:03.0 Ethernet controller: Red Hat, Inc. Virtio network device\n", 0; "extended")]
+    #[test_case("-F Inc.", "00:03.0 Ethernet controller: Red Hat, Inc. Virtio network device\n", 0; "fixed")]
+    #[tokio::test]
+    async fn stdin(input: &str, expected: &str, exit_code: u32) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(expected))
+            .returning(|_, _| ());
+
+        let out = Grep::new(
+            &mut state,
+            shlex::split(input).unwrap().as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                LSPCI.as_bytes(),
+                &mut session,
+            )
+            .await;
+
+        assert!(
+            matches!(out, CommandResult::Exit(v) if v == exit_code),
+            "{out:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn files() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        state
+            .file_system()
+            .write(
+                Path::new("a"),
+                "root:x:0:0\nuser:x:1000:1000\n".as_bytes().into(),
+            )
+            .unwrap();
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string("a:root:x:0:0\ngrep: b: No such file or directory\n"),
+            )
+            .returning(|_, _| ());
+
+        let out = Grep::new(
+            &mut state,
+            ["^root".to_string(), "a".to_string(), "b".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+}
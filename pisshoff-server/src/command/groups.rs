@@ -0,0 +1,111 @@
+use async_trait::async_trait;
+use itertools::Itertools;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    persona::Persona,
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Groups {}
+
+#[async_trait]
+impl Command for Groups {
+    const NAME: &'static str = "groups";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(params, &connection.config().persona, connection.username());
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(params: &[String], persona: &Persona, username: &str) -> (String, u32) {
+    let mut users = Vec::new();
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Operand(v) => users.push(v),
+            Arg::Short(c) => {
+                return (
+                    format!(
+                        "groups: invalid option -- '{c}'\nTry 'groups --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!("groups: unrecognized option '--{v}'\nTry 'groups --help' for more information.\n"),
+                    1,
+                );
+            }
+        }
+    }
+
+    let names = |user: &str| {
+        persona
+            .identity(user)
+            .groups
+            .iter()
+            .map(|(_, name)| name)
+            .join(" ")
+    };
+
+    if users.is_empty() {
+        return (format!("{}\n", names(username)), 0);
+    }
+
+    let mut out = String::new();
+    let mut exit_code = 0;
+
+    for user in users {
+        // the only other user we know anything about is root
+        if user == username || user == "root" {
+            out.push_str(&format!("{user} : {}\n", names(user)));
+        } else {
+            out.push_str(&format!("groups: '{user}': no such user\n"));
+            exit_code = 1;
+        }
+    }
+
+    (out, exit_code)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::groups::execute, persona::Persona};
+
+    #[test_case("root", "", "root\n", 0; "root")]
+    #[test_case("ubuntu", "", "ubuntu adm cdrom sudo dip plugdev lxd\n", 0; "user")]
+    #[test_case("ubuntu", "ubuntu root", "ubuntu : ubuntu adm cdrom sudo dip plugdev lxd\nroot : root\n", 0; "operands")]
+    #[test_case("root", "nobody", "groups: 'nobody': no such user\n", 1; "unknown user")]
+    fn works(username: &str, input: &str, expected: &str, expected_exit_code: u32) {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(&input, &persona, username);
+
+        assert_eq!(output, expected);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct History {}
+
+#[async_trait]
+impl Command for History {
+    const NAME: &'static str = "history";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection.history(), params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(history: &mut Vec<String>, params: &[String]) -> (String, u32) {
+    let mut params = params.iter();
+    let mut count = None;
+
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            // clearing the history is a favourite of anybody covering their tracks, it's
+            // already been recorded in the audit log by the time we get here
+            "-c" => {
+                history.clear();
+                return (String::new(), 0);
+            }
+            "-d" => {
+                let Some(offset) = params.next() else {
+                    return (
+                        "bash: history: -d: option requires an argument\nhistory: usage: history \
+                         [-c] [-d offset] [n] or history -anrw [filename] or history -ps arg \
+                         [arg...]\n"
+                            .to_string(),
+                        2,
+                    );
+                };
+
+                return match offset.parse::<usize>() {
+                    Ok(i) if (1..=history.len()).contains(&i) => {
+                        history.remove(i - 1);
+                        (String::new(), 0)
+                    }
+                    _ => (
+                        format!("bash: history: {offset}: history position out of range\n"),
+                        1,
+                    ),
+                };
+            }
+            // reading and writing the history file is left to bash exiting, which never happens
+            "-a" | "-n" | "-r" | "-w" => return (String::new(), 0),
+            v if v.starts_with('-') && v.len() > 1 => {
+                return (
+                    format!(
+                        "bash: history: {v}: invalid option\nhistory: usage: history [-c] [-d \
+                         offset] [n] or history -anrw [filename] or history -ps arg [arg...]\n"
+                    ),
+                    2,
+                );
+            }
+            v => match v.parse::<usize>() {
+                Ok(v) if count.is_none() => count = Some(v),
+                Ok(_) => return ("bash: history: too many arguments\n".to_string(), 1),
+                Err(_) => {
+                    return (
+                        format!("bash: history: {v}: numeric argument required\n"),
+                        1,
+                    );
+                }
+            },
+        }
+    }
+
+    let skip = history.len() - count.unwrap_or(history.len()).min(history.len());
+    let mut out = String::new();
+
+    for (i, line) in history.iter().enumerate().skip(skip) {
+        writeln!(out, "{:>5}  {line}", i + 1).unwrap();
+    }
+
+    (out, 0)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::command::history::execute;
+
+    #[test_case("", "    1  ls\n    2  uname -a\n    3  history\n", 0; "all")]
+    #[test_case("2", "    2  uname -a\n    3  history\n", 0; "last")]
+    #[test_case("10", "    1  ls\n    2  uname -a\n    3  history\n", 0; "more than kept")]
+    #[test_case("x", "bash: history: x: numeric argument required\n", 1; "not numeric")]
+    #[test_case("-d 9", "bash: history: 9: history position out of range\n", 1; "out of range")]
+    fn works(input: &str, expected: &str, exit_code: u32) {
+        let mut history = vec![
+            "ls".to_string(),
+            "uname -a".to_string(),
+            "history".to_string(),
+        ];
+
+        let input = shlex::split(input).unwrap();
+        assert_eq!(
+            execute(&mut history, &input),
+            (expected.to_string(), exit_code)
+        );
+    }
+
+    #[test_case("-c", &[]; "clear")]
+    #[test_case("-d 2", &["ls", "history"]; "delete")]
+    fn edits(input: &str, expected: &[&str]) {
+        let mut history = vec![
+            "ls".to_string(),
+            "uname -a".to_string(),
+            "history".to_string(),
+        ];
+
+        let input = shlex::split(input).unwrap();
+        assert_eq!(execute(&mut history, &input), (String::new(), 0));
+        assert_eq!(history, expected);
+    }
+}
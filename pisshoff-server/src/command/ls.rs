@@ -13,6 +13,8 @@ pub struct Ls {}
 
 #[async_trait]
 impl Command for Ls {
+    const NAME: &'static str = "ls";
+
     async fn new<S: ThrusshSession + Send>(
         connection: &mut ConnectionState,
         params: &[String],
@@ -67,7 +69,8 @@ impl Command for Ls {
             session.data(channel, format!("{resp}\n").into());
         }
 
-        CommandResult::Exit(u32::from(error))
+        // GNU ls exits with 2 when it can't access one of its operands
+        CommandResult::Exit(if error { 2 } else { 0 })
     }
 
     async fn stdin<S: ThrusshSession + Send>(
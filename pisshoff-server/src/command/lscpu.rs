@@ -0,0 +1,97 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    persona::Persona,
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Lscpu {}
+
+#[async_trait]
+impl Command for Lscpu {
+    const NAME: &'static str = "lscpu";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        _params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        session.data(channel, execute(&connection.config().persona).into());
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(persona: &Persona) -> String {
+    let mut out = String::new();
+
+    let mut line = |indent: usize, key: &str, value: &dyn std::fmt::Display| {
+        let key = format!("{}{key}:", " ".repeat(indent));
+        let line = format!("{key:<25}{value}");
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    };
+
+    let (op_modes, address_sizes) = if persona.machine == "x86_64" {
+        ("32-bit, 64-bit", "46 bits physical, 48 bits virtual")
+    } else {
+        ("32-bit", "32 bits physical, 32 bits virtual")
+    };
+
+    let last_cpu = persona.cpu_count.saturating_sub(1);
+
+    line(0, "Architecture", &persona.machine);
+    line(2, "CPU op-mode(s)", &op_modes);
+    line(2, "Address sizes", &address_sizes);
+    line(2, "Byte Order", &"Little Endian");
+    line(0, "CPU(s)", &persona.cpu_count);
+    line(2, "On-line CPU(s) list", &format!("0-{last_cpu}"));
+    line(0, "Vendor ID", &persona.cpu_vendor);
+    line(2, "Model name", &persona.cpu_model);
+    line(4, "CPU family", &6);
+    line(4, "Model", &79);
+    line(4, "Thread(s) per core", &1);
+    line(4, "Core(s) per socket", &persona.cpu_count);
+    line(4, "Socket(s)", &1);
+    line(4, "Stepping", &1);
+    line(4, "BogoMIPS", &format!("{:.2}", persona.cpu_mhz * 2.0));
+    line(0, "Virtualization features", &"");
+    line(2, "Hypervisor vendor", &"KVM");
+    line(2, "Virtualization type", &"full");
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{command::lscpu::execute, persona::Persona};
+
+    #[test]
+    fn reports_persona() {
+        let out = execute(&Persona::default());
+
+        assert!(
+            out.starts_with("Architecture:            x86_64\n"),
+            "{out}"
+        );
+        assert!(out.contains("\nCPU(s):                  4\n"), "{out}");
+        assert!(
+            out.contains("\n  Model name:            Intel(R) Xeon(R) CPU E5-2680 v4 @ 2.40GHz\n"),
+            "{out}"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    persona::Gpu,
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Devices QEMU gives every virtual machine, which the persona's GPUs are passed through
+/// alongside.
+const DEVICES: &[(&str, &str)] = &[
+    (
+        "00:00.0",
+        "Host bridge: Intel Corporation 440FX - 82441FX PMC [Natoma] (rev 02)",
+    ),
+    (
+        "00:01.0",
+        "ISA bridge: Intel Corporation 82371SB PIIX3 ISA [Natoma/Triton II]",
+    ),
+    (
+        "00:01.1",
+        "IDE interface: Intel Corporation 82371SB PIIX3 IDE [Natoma/Triton II]",
+    ),
+    (
+        "00:01.3",
+        "Bridge: Intel Corporation 82371AB/EB/MB PIIX4 ACPI (rev 03)",
+    ),
+    ("00:02.0", "VGA compatible controller: Cirrus Logic GD 5446"),
+    (
+        "00:03.0",
+        "Ethernet controller: Red Hat, Inc. Virtio network device",
+    ),
+    (
+        "00:04.0",
+        "SCSI storage controller: Red Hat, Inc. Virtio block device",
+    ),
+];
+
+#[derive(Debug, Clone)]
+pub struct Lspci {}
+
+#[async_trait]
+impl Command for Lspci {
+    const NAME: &'static str = "lspci";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        _params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        // droppers look for a GPU before deciding which miner to fetch
+        connection.audit_log().tag("miner-recon");
+
+        let out = execute(&connection.config().persona.gpus);
+        session.data(channel, out.into());
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(gpus: &[Gpu]) -> String {
+    let mut out = String::new();
+
+    for (slot, device) in DEVICES {
+        writeln!(out, "{slot} {device}").unwrap();
+    }
+
+    for (i, gpu) in gpus.iter().enumerate() {
+        writeln!(out, "{} VGA compatible controller: {}", slot(i), gpu.device).unwrap();
+    }
+
+    out
+}
+
+/// PCI slot the `i`th GPU is attached to, after the devices every machine has.
+pub fn slot(i: usize) -> String {
+    format!("00:{:02x}.0", 5 + i)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{command::lspci::execute, persona::Gpu};
+
+    #[test]
+    fn lists_gpus() {
+        let gpus = [Gpu {
+            name: "NVIDIA GeForce RTX 3090".to_string(),
+            device: "NVIDIA Corporation GA102 [GeForce RTX 3090] (rev a1)".to_string(),
+            memory: 24576,
+            power_limit: 350,
+        }];
+
+        let out = execute(&gpus);
+        assert_eq!(out.lines().count(), 8);
+        assert!(out.ends_with(
+            "\n00:05.0 VGA compatible controller: NVIDIA Corporation GA102 [GeForce RTX 3090] (rev a1)\n"
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, MkdirEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{chmod, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const TRY_HELP: &str = "Try 'mkdir --help' for more information.\n";
+
+#[derive(Debug, Clone)]
+pub struct Mkdir {}
+
+#[async_trait]
+impl Command for Mkdir {
+    const NAME: &'static str = "mkdir";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut parents = false;
+    let mut verbose = false;
+    let mut mode = None;
+    let mut directories = Vec::new();
+
+    let mut params = params.iter();
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            "-p" | "--parents" => parents = true,
+            "-v" | "--verbose" => verbose = true,
+            "-pv" | "-vp" => (parents, verbose) = (true, true),
+            "-m" | "--mode" => mode = params.next().map(String::as_str),
+            v if v.starts_with("--mode=") => mode = v.strip_prefix("--mode="),
+            v if v.starts_with("-m") => mode = v.strip_prefix("-m"),
+            v => directories.push(v),
+        }
+    }
+
+    let mode = match mode.map(|v| (v, chmod::parse_mode(v, 0o755, true))) {
+        None => None,
+        Some((_, Some(mode))) => Some(mode),
+        Some((v, None)) => return (format!("mkdir: invalid mode '{v}'\n"), 1),
+    };
+
+    if directories.is_empty() {
+        return (format!("mkdir: missing operand\n{TRY_HELP}"), 1);
+    }
+
+    let mut out = String::new();
+    let mut status = 0;
+
+    for directory in directories {
+        let path = Path::new(directory);
+        let file_system = connection.file_system();
+        let existed = file_system.get(path).is_ok();
+
+        let res = if parents {
+            file_system.mkdirall(path)
+        } else {
+            file_system.mkdir(path)
+        };
+
+        if let Err(e) = res {
+            writeln!(out, "mkdir: cannot create directory '{directory}': {e}").unwrap();
+            status = 1;
+            continue;
+        }
+
+        if existed {
+            continue;
+        }
+
+        if let Some(mode) = mode {
+            let _res = file_system.set_mode(path, mode);
+        }
+
+        if verbose {
+            writeln!(out, "mkdir: created directory '{directory}'").unwrap();
+        }
+
+        let canonical = file_system.canonicalize(path);
+        connection
+            .audit_log()
+            .push_action(AuditLogAction::Mkdir(MkdirEvent {
+                path: canonical.to_string_lossy().into(),
+            }));
+    }
+
+    (out, status)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use crate::{command::mkdir::execute, server::ConnectionState};
+
+    #[test]
+    fn creates_directories() {
+        let mut state = ConnectionState::mock();
+
+        let input = shlex::split("-v -m 700 a").unwrap();
+        assert_eq!(
+            execute(&mut state, &input),
+            ("mkdir: created directory 'a'\n".to_string(), 0)
+        );
+        assert_eq!(state.file_system().mode(Path::new("a")).unwrap(), 0o700);
+
+        let input = shlex::split("-p a/b/c").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+        assert!(state.file_system().get(Path::new("a/b/c")).is_ok());
+    }
+
+    #[test_case("", "mkdir: missing operand\nTry 'mkdir --help' for more information.\n"; "no operands")]
+    #[test_case("a", "mkdir: cannot create directory 'a': File exists\n"; "exists")]
+    #[test_case("b/c", "mkdir: cannot create directory 'b/c': No such file or directory\n"; "no parent")]
+    #[test_case("-m z b", "mkdir: invalid mode 'z'\n"; "invalid mode")]
+    fn errors(input: &str, expected: &str) {
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("a")).unwrap();
+
+        let input = shlex::split(input).unwrap();
+        assert_eq!(execute(&mut state, &input), (expected.to_string(), 1));
+    }
+}
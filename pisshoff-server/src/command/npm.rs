@@ -0,0 +1,180 @@
+use async_trait::async_trait;
+use pisshoff_types::audit::Package;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "npm <command>
+
+Usage:
+
+npm install        install all the dependencies in your project
+npm install <foo>  add the <foo> dependency to your project
+npm test           run this project's tests
+npm run <foo>      run the script named <foo>
+npm <command> -h   quick help on <command>
+npm -l             display usage info for all commands
+npm help <term>    search for help on <term>
+
+npm@9.8.1 /usr/lib/node_modules/npm
+";
+
+const VERSION: &str = "9.8.1\n";
+
+#[derive(Debug, Clone)]
+pub struct Npm {}
+
+#[async_trait]
+impl Command for Npm {
+    const NAME: &'static str = "npm";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    if params.iter().any(|v| v == "-v" || v == "--version") {
+        return (VERSION.to_string(), 0);
+    }
+
+    let global = params.iter().any(|v| v == "-g" || v == "--global");
+    let mut operands = params.iter().filter(|v| !v.starts_with('-'));
+
+    let Some(operation) = operands.next() else {
+        return (USAGE.to_string(), 1);
+    };
+
+    match operation.as_str() {
+        "install" | "i" | "add" | "isntall" => {
+            let packages = operands.map(|v| parse_package(v)).collect::<Vec<_>>();
+
+            if packages.is_empty() {
+                return (
+                    "\nup to date, audited 1 package in 312ms\n\nfound 0 vulnerabilities\n"
+                        .to_string(),
+                    0,
+                );
+            }
+
+            if global && connection.username() != "root" {
+                super::record_packages(connection, Npm::NAME, packages);
+                return (
+                    "npm ERR! code EACCES\nnpm ERR! syscall mkdir\nnpm ERR! path /usr/lib/node_modules\nnpm ERR! errno -13\nnpm ERR! Error: EACCES: permission denied, mkdir '/usr/lib/node_modules'\n"
+                        .to_string(),
+                    243,
+                );
+            }
+
+            // each package pulls in a plausible number of dependencies of its own
+            let added = packages
+                .iter()
+                .map(|v| super::package_details(connection, &v.name).1 / 40 + 1)
+                .sum::<u64>();
+            let elapsed = connection.rng(("npm", added)).u8(1..9);
+
+            super::record_packages(connection, Npm::NAME, packages);
+
+            let out = if global {
+                format!("\nadded {added} packages in {elapsed}s\n")
+            } else {
+                format!(
+                    "\nadded {added} packages, and audited {} packages in {elapsed}s\n\nfound 0 vulnerabilities\n",
+                    added + 1
+                )
+            };
+
+            (out, 0)
+        }
+        "uninstall" | "remove" | "rm" | "un" => (
+            "\nup to date, audited 1 package in 198ms\n\nfound 0 vulnerabilities\n".to_string(),
+            0,
+        ),
+        "ls" | "list" => ("/root\n`-- (empty)\n\n".to_string(), 0),
+        other => (
+            format!("Unknown command: \"{other}\"\n\nTo see a list of supported npm commands, run:\n  npm help\n"),
+            1,
+        ),
+    }
+}
+
+/// Splits a package spec such as `@scope/name@1.0.0` on its last `@`, the leading one belonging
+/// to the scope.
+fn parse_package(spec: &str) -> Package {
+    match spec.rfind('@') {
+        Some(i) if i > 0 => Package {
+            name: spec[..i].into(),
+            version: Some(spec[i + 1..].into()),
+        },
+        _ => Package {
+            name: spec.into(),
+            version: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::npm::{execute, parse_package},
+        server::ConnectionState,
+    };
+
+    #[test_case("express", "express", None; "bare")]
+    #[test_case("express@4.18.2", "express", Some("4.18.2"); "versioned")]
+    #[test_case("@types/node", "@types/node", None; "scoped")]
+    #[test_case("@types/node@20", "@types/node", Some("20"); "scoped versioned")]
+    fn parses_packages(input: &str, name: &str, version: Option<&str>) {
+        let package = parse_package(input);
+
+        assert_eq!(&*package.name, name);
+        assert_eq!(package.version.as_deref(), version);
+    }
+
+    #[test]
+    fn records_install() {
+        let mut state = ConnectionState::mock();
+
+        let input = shlex::split("install --save ws@8.14.2 @scope/miner").unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, 0, "{out}");
+        assert!(out.ends_with("\n\nfound 0 vulnerabilities\n"), "{out}");
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PackageInstall(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.manager, "npm");
+        assert_eq!(&*event.packages[0].name, "ws");
+        assert_eq!(event.packages[0].version.as_deref(), Some("8.14.2"));
+        assert_eq!(&*event.packages[1].name, "@scope/miner");
+    }
+}
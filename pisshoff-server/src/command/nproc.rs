@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Nproc {}
+
+#[async_trait]
+impl Command for Nproc {
+    const NAME: &'static str = "nproc";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(params, connection.config().persona.cpu_count);
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(params: &[String], cpu_count: u32) -> (String, u32) {
+    let mut ignore = 0;
+    let mut awaiting_ignore = false;
+
+    for param in super::argparse(params) {
+        let ignore_value = match param {
+            Arg::Operand(v) if awaiting_ignore => v,
+            Arg::Long(v) if v.starts_with("ignore=") => &v["ignore=".len()..],
+            Arg::Long("ignore") => {
+                awaiting_ignore = true;
+                continue;
+            }
+            Arg::Long("all") => continue,
+            Arg::Operand(v) => {
+                return (
+                    format!(
+                        "nproc: extra operand '{v}'\nTry 'nproc --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Short(c) => {
+                return (
+                    format!("nproc: invalid option -- '{c}'\nTry 'nproc --help' for more information.\n"),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!("nproc: unrecognized option '--{v}'\nTry 'nproc --help' for more information.\n"),
+                    1,
+                );
+            }
+        };
+
+        awaiting_ignore = false;
+
+        let Ok(v) = ignore_value.parse::<u32>() else {
+            return (format!("nproc: invalid number: '{ignore_value}'\n"), 1);
+        };
+
+        ignore = v;
+    }
+
+    if awaiting_ignore {
+        return (
+            "nproc: option '--ignore' requires an argument\nTry 'nproc --help' for more information.\n"
+                .to_string(),
+            1,
+        );
+    }
+
+    (format!("{}\n", cpu_count.saturating_sub(ignore).max(1)), 0)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::command::nproc::execute;
+
+    #[test_case("", "4\n", 0; "none")]
+    #[test_case("--all", "4\n", 0; "all")]
+    #[test_case("--ignore=1", "3\n", 0; "ignore")]
+    #[test_case("--ignore 10", "1\n", 0; "ignore all")]
+    #[test_case("--ignore=x", "nproc: invalid number: 'x'\n", 1; "invalid ignore")]
+    #[test_case("-z", "nproc: invalid option -- 'z'\nTry 'nproc --help' for more information.\n", 1; "unknown short arg")]
+    fn works(input: &str, expected: &str, expected_exit_code: u32) {
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(&input, 4);
+
+        assert_eq!(output, expected);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Write,
+    hash::{Hash, Hasher},
+};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    command::{lspci, Command, CommandResult},
+    persona::Gpu,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const DRIVER_VERSION: &str = "535.129.03";
+
+const HEADER: &str =
+    "+---------------------------------------------------------------------------------------+
+| NVIDIA-SMI 535.129.03             Driver Version: 535.129.03   CUDA Version: 12.2     |
+|-----------------------------------------+----------------------+----------------------+
+| GPU  Name                 Persistence-M | Bus-Id        Disp.A | Volatile Uncorr. ECC |
+| Fan  Temp   Perf          Pwr:Usage/Cap |         Memory-Usage | GPU-Util  Compute M. |
+|                                         |                      |               MIG M. |
+|=========================================+======================+======================|
+";
+
+const PROCESSES: &str = "
++---------------------------------------------------------------------------------------+
+| Processes:                                                                            |
+|  GPU   GI   CI        PID   Type   Process name                            GPU Memory |
+|        ID   ID                                                             Usage      |
+|=======================================================================================|
+|  No running processes found                                                           |
++---------------------------------------------------------------------------------------+
+";
+
+#[derive(Debug, Clone)]
+pub struct NvidiaSmi {}
+
+#[async_trait]
+impl Command for NvidiaSmi {
+    const NAME: &'static str = "nvidia-smi";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        connection.audit_log().tag("miner-recon");
+
+        let gpus = nvidia(&connection.config().persona.gpus);
+
+        if gpus.is_empty() {
+            session.data(
+                channel,
+                format!("bash: {}: command not found\n", Self::NAME).into(),
+            );
+            return CommandResult::Exit(127);
+        }
+
+        let (out, exit_code) = execute(params, &gpus, OffsetDateTime::now_utc());
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+/// The persona's NVIDIA GPUs, along with the PCI slot each is attached to. The driver's only
+/// installed if there's at least one of them.
+fn nvidia(gpus: &[Gpu]) -> Vec<(String, &Gpu)> {
+    gpus.iter()
+        .enumerate()
+        .filter(|(_, v)| v.device.starts_with("NVIDIA"))
+        .map(|(i, v)| (format!("00000000:{}", lspci::slot(i)), v))
+        .collect()
+}
+
+fn execute(params: &[String], gpus: &[(String, &Gpu)], now: OffsetDateTime) -> (String, u32) {
+    let mut query = None;
+    let mut format = None;
+
+    for param in params {
+        if let Some(v) = param.strip_prefix("--query-gpu=") {
+            query = Some(v);
+        } else if let Some(v) = param.strip_prefix("--format=") {
+            format = Some(v);
+        } else if param == "-L" || param == "--list-gpus" {
+            let mut out = String::new();
+
+            for (i, (_, gpu)) in gpus.iter().enumerate() {
+                writeln!(out, "GPU {i}: {} (UUID: GPU-{})", gpu.name, uuid(gpu)).unwrap();
+            }
+
+            return (out, 0);
+        }
+    }
+
+    match (query, format) {
+        (Some(query), Some(format)) => self::query(gpus, query, format),
+        (Some(_), None) => (
+            "Please specify output format with --format=csv[,noheader][,nounits].\n".to_string(),
+            2,
+        ),
+        (None, _) => (table(gpus, now), 0),
+    }
+}
+
+fn table(gpus: &[(String, &Gpu)], now: OffsetDateTime) -> String {
+    let mut out = now
+        .format(format_description!(
+            "[weekday repr:short] [month repr:short] [day padding:space] [hour]:[minute]:[second] [year]"
+        ))
+        .unwrap();
+    out.push('\n');
+    out.push_str(HEADER);
+
+    for (i, (bus_id, gpu)) in gpus.iter().enumerate() {
+        let rng = rng(gpu);
+        let memory = format!("1MiB / {}MiB", gpu.memory);
+
+        writeln!(
+            out,
+            "|{}| {bus_id} Off |                  N/A |",
+            cell(&format!("   {i}  {}", gpu.name), "Off ", 41)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "|{}| {memory:>20} |      0%      Default |",
+            cell(
+                &format!("  0%   {}C    P8", rng.u8(30..45)),
+                &format!("{}W / {}W ", rng.u8(10..30), gpu.power_limit),
+                41
+            )
+        )
+        .unwrap();
+        out.push_str(
+            "|                                         |                      |                  N/A |\n",
+        );
+        out.push_str(
+            "+-----------------------------------------+----------------------+----------------------+\n",
+        );
+    }
+
+    out.push_str(PROCESSES);
+    out
+}
+
+/// Answers `--query-gpu`, as scripts do to pick a miner without having to parse the table.
+fn query(gpus: &[(String, &Gpu)], query: &str, format: &str) -> (String, u32) {
+    let header = !format.split(',').any(|v| v == "noheader");
+    let units = !format.split(',').any(|v| v == "nounits");
+    let fields = query.split(',').map(str::trim).collect::<Vec<_>>();
+
+    let mut out = String::new();
+
+    if header {
+        let names = fields
+            .iter()
+            .map(|v| match unit(v) {
+                Some(unit) if units => format!("{v} [{unit}]"),
+                _ => (*v).to_string(),
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", names.join(", ")).unwrap();
+    }
+
+    for (i, (bus_id, gpu)) in gpus.iter().enumerate() {
+        let mut values = Vec::with_capacity(fields.len());
+
+        for field in &fields {
+            let value = match *field {
+                "index" => i.to_string(),
+                "count" => gpus.len().to_string(),
+                "name" | "gpu_name" => gpu.name.clone(),
+                "uuid" | "gpu_uuid" => format!("GPU-{}", uuid(gpu)),
+                "driver_version" => DRIVER_VERSION.to_string(),
+                "pci.bus_id" | "gpu_bus_id" => bus_id.clone(),
+                "memory.total" => gpu.memory.to_string(),
+                "memory.used" => "1".to_string(),
+                "memory.free" => gpu.memory.saturating_sub(1).to_string(),
+                "utilization.gpu" => "0".to_string(),
+                "temperature.gpu" => rng(gpu).u8(30..45).to_string(),
+                "power.limit" => format!("{}.00", gpu.power_limit),
+                other => {
+                    return (
+                        format!("Field \"{other}\" is not a valid field to query.\n"),
+                        2,
+                    );
+                }
+            };
+
+            match unit(field) {
+                Some(unit) if units => values.push(format!("{value} {unit}")),
+                _ => values.push(value),
+            }
+        }
+
+        writeln!(out, "{}", values.join(", ")).unwrap();
+    }
+
+    (out, 0)
+}
+
+fn unit(field: &str) -> Option<&'static str> {
+    match field {
+        "memory.total" | "memory.used" | "memory.free" => Some("MiB"),
+        "utilization.gpu" => Some("%"),
+        "power.limit" => Some("W"),
+        _ => None,
+    }
+}
+
+/// Pads `left` and `right` apart to fill a cell of the table `width` characters wide.
+fn cell(left: &str, right: &str, width: usize) -> String {
+    format!("{left:<width$}{right}", width = width - right.len())
+}
+
+fn rng(gpu: &Gpu) -> fastrand::Rng {
+    let mut hasher = DefaultHasher::new();
+    gpu.name.hash(&mut hasher);
+    gpu.device.hash(&mut hasher);
+    fastrand::Rng::with_seed(hasher.finish())
+}
+
+fn uuid(gpu: &Gpu) -> uuid::Uuid {
+    let rng = rng(gpu);
+    uuid::Uuid::from_u64_pair(rng.u64(..), rng.u64(..))
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::{
+        command::nvidia_smi::{execute, nvidia},
+        persona::Gpu,
+    };
+
+    fn gpus() -> Vec<Gpu> {
+        vec![
+            Gpu {
+                name: "Intel UHD Graphics 630".to_string(),
+                device: "Intel Corporation CoffeeLake-S GT2 [UHD Graphics 630]".to_string(),
+                memory: 0,
+                power_limit: 0,
+            },
+            Gpu {
+                name: "NVIDIA GeForce RTX 3090".to_string(),
+                device: "NVIDIA Corporation GA102 [GeForce RTX 3090] (rev a1)".to_string(),
+                memory: 24576,
+                power_limit: 350,
+            },
+        ]
+    }
+
+    #[test]
+    fn renders_table() {
+        let gpus = gpus();
+        let gpus = nvidia(&gpus);
+        let (out, code) = execute(&[], &gpus, datetime!(2023-10-24 09:12:44 UTC));
+        assert_eq!(code, 0);
+
+        let mut lines = out.lines();
+        assert_eq!(lines.next(), Some("Tue Oct 24 09:12:44 2023"));
+
+        let lines = lines.collect::<Vec<_>>();
+        assert!(
+            lines
+                .iter()
+                .all(|v| v.is_empty() || v.chars().count() == 89),
+            "{out}"
+        );
+        assert_eq!(
+            lines[7],
+            "|   0  NVIDIA GeForce RTX 3090        Off | 00000000:00:06.0 Off |                  N/A |"
+        );
+        assert!(lines[8].ends_with("W / 350W |      1MiB / 24576MiB |      0%      Default |"));
+    }
+
+    #[test_case("--query-gpu=name,memory.total --format=csv", "name, memory.total [MiB]\nNVIDIA GeForce RTX 3090, 24576 MiB\n", 0; "csv")]
+    #[test_case("--query-gpu=count --format=csv,noheader", "1\n", 0; "noheader")]
+    #[test_case("--query-gpu=memory.free --format=csv,noheader,nounits", "24575\n", 0; "nounits")]
+    #[test_case("--query-gpu=hashrate --format=csv,noheader", "Field \"hashrate\" is not a valid field to query.\n", 2; "invalid")]
+    fn queries(input: &str, expected: &str, exit_code: u32) {
+        let gpus = gpus();
+        let gpus = nvidia(&gpus);
+        let input = shlex::split(input).unwrap();
+
+        let (out, code) = execute(&input, &gpus, datetime!(2023-10-24 09:12:44 UTC));
+        assert_eq!(out, expected);
+        assert_eq!(code, exit_code);
+    }
+}
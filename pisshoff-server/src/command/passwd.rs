@@ -0,0 +1,296 @@
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, PasswordChangeEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "Usage: passwd [options] [LOGIN]
+
+Options:
+  -a, --all                     report password status on all accounts
+  -d, --delete                  delete the password for the named account
+  -e, --expire                  force expire the password for the named account
+  -h, --help                    display this help message and exit
+  -l, --lock                    lock the password of the named account
+  -S, --status                  report password status on the named account
+  -u, --unlock                  unlock the password of the named account
+";
+
+#[derive(Debug, Clone)]
+pub struct Passwd {
+    user: String,
+    /// Whether the current password is asked for before the new one, as it is for everyone
+    /// but root.
+    current: bool,
+    /// Whether the new password is read once from stdin, as with CentOS' `--stdin`.
+    stdin: bool,
+    answers: Vec<String>,
+}
+
+#[async_trait]
+impl Command for Passwd {
+    const NAME: &'static str = "passwd";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut user = None;
+        let mut stdin = false;
+
+        for param in super::argparse(params) {
+            match param {
+                Arg::Long("stdin") => stdin = true,
+                Arg::Operand(v) if user.is_none() => user = Some(v),
+                Arg::Short(c) => {
+                    session.data(
+                        channel,
+                        format!("passwd: invalid option -- '{c}'\n{USAGE}").into(),
+                    );
+                    return CommandResult::Exit(2);
+                }
+                Arg::Long(v) => {
+                    session.data(
+                        channel,
+                        format!("passwd: unrecognized option '--{v}'\n{USAGE}").into(),
+                    );
+                    return CommandResult::Exit(2);
+                }
+                Arg::Operand(_) => {
+                    session.data(channel, USAGE.to_string().into());
+                    return CommandResult::Exit(2);
+                }
+            }
+        }
+
+        let root = connection.username() == "root";
+        let user = user.unwrap_or(connection.username()).to_string();
+
+        if !root && user != connection.username() {
+            session.data(
+                channel,
+                format!("passwd: You may not view or modify password information for {user}.\n")
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        let this = Self {
+            current: !root,
+            stdin,
+            user,
+            answers: Vec::new(),
+        };
+
+        if stdin {
+            session.data(
+                channel,
+                format!("Changing password for user {}.\n", this.user).into(),
+            );
+        } else {
+            if this.current {
+                session.data(
+                    channel,
+                    format!("Changing password for {}.\n", this.user).into(),
+                );
+            }
+
+            super::prompt_password(connection, this.prompt(), channel, session);
+        }
+
+        CommandResult::ReadStdin(this)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        mut data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        // piped input can answer several prompts at once
+        while !data.is_empty() {
+            let (answer, rest) = if self.stdin {
+                let (answer, rest) = match data.iter().position(|&c| c == b'\n') {
+                    Some(i) => (&data[..i], &data[i + 1..]),
+                    None => (data, [].as_slice()),
+                };
+                (String::from_utf8_lossy(answer).into_owned(), rest)
+            } else {
+                super::read_password(connection, data, channel, session)
+            };
+
+            self.answers.push(answer);
+            data = rest;
+
+            if self.answers.len() == self.prompts() {
+                return self.finish(connection, channel, session);
+            }
+
+            super::prompt_password(connection, self.prompt(), channel, session);
+        }
+
+        CommandResult::ReadStdin(self)
+    }
+}
+
+impl Passwd {
+    /// Number of prompts to be answered.
+    fn prompts(&self) -> usize {
+        if self.stdin {
+            1
+        } else {
+            2 + usize::from(self.current)
+        }
+    }
+
+    /// The prompt for the next answer.
+    fn prompt(&self) -> &'static str {
+        match self.answers.len() + usize::from(!self.current) {
+            0 => "Current password: ",
+            1 => "New password: ",
+            _ => "Retype new password: ",
+        }
+    }
+
+    fn finish<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut answers = self.answers.into_iter();
+
+        let current = if self.current { answers.next() } else { None };
+        let new = answers.next().unwrap_or_default();
+        let retyped = answers.next();
+
+        let matches = retyped.as_ref().map_or(true, |v| *v == new);
+
+        let event = PasswordChangeEvent {
+            username: connection.username().into(),
+            target: self.user.into_boxed_str(),
+            current: current.map(String::into_boxed_str),
+            new: new.into_boxed_str(),
+            retyped: retyped.map(String::into_boxed_str),
+        };
+        connection
+            .audit_log()
+            .push_action(AuditLogAction::PasswordChange(event));
+
+        if !matches {
+            session.data(
+                channel,
+                "Sorry, passwords do not match.\npasswd: Authentication token manipulation error\npasswd: password unchanged\n"
+                    .to_string()
+                    .into(),
+            );
+            return CommandResult::Exit(10);
+        }
+
+        let message = if self.stdin {
+            "passwd: all authentication tokens updated successfully.\n"
+        } else {
+            "passwd: password updated successfully\n"
+        };
+        session.data(channel, message.to_string().into());
+
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::{passwd::Passwd, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("root", &[], b"n3wpass\nn3wpass\n", None, Some("n3wpass"), 0; "root")]
+    #[test_case("ubuntu", &[], b"ubuntu\nn3wpass\nn3wpass\n", Some("ubuntu"), Some("n3wpass"), 0; "user")]
+    #[test_case("root", &[], b"n3wpass\nother\n", None, Some("other"), 10; "mismatch")]
+    #[test_case("root", &["--stdin", "root"], b"n3wpass\n", None, None, 0; "stdin")]
+    #[tokio::test]
+    async fn captures_passwords(
+        username: &str,
+        params: &[&str],
+        input: &[u8],
+        current: Option<&str>,
+        retyped: Option<&str>,
+        exit_code: u32,
+    ) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.switch_user(username, false);
+
+        session
+            .expect_data()
+            .with(always(), always())
+            .returning(|_, _| ());
+
+        let params = params.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let out = Passwd::new(&mut state, &params, fake_channel_id(), &mut session)
+            .await
+            .unwrap_stdin();
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), input, &mut session)
+            .await;
+        assert!(
+            matches!(out, CommandResult::Exit(v) if v == exit_code),
+            "{out:?}"
+        );
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PasswordChange(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.target, username);
+        assert_eq!(event.current.as_deref(), current);
+        assert_eq!(&*event.new, "n3wpass");
+        assert_eq!(event.retyped.as_deref(), retyped);
+    }
+
+    #[tokio::test]
+    async fn refuses_other_users() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.switch_user("ubuntu", false);
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string("passwd: You may not view or modify password information for root.\n"),
+            )
+            .returning(|_, _| ());
+
+        let out = Passwd::new(
+            &mut state,
+            ["root".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+}
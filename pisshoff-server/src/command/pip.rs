@@ -0,0 +1,280 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use pisshoff_types::audit::Package;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "
+Usage:
+  pip <command> [options]
+
+Commands:
+  install                     Install packages.
+  download                    Download packages.
+  uninstall                   Uninstall packages.
+  freeze                      Output installed packages in requirements format.
+  list                        List installed packages.
+  show                        Show information about installed packages.
+";
+
+const VERSION: &str = "pip 22.0.2 from /usr/lib/python3/dist-packages/pip (python 3.10)\n";
+
+/// Options to `pip install` that take a value as the following parameter.
+const WITH_VALUE: &[&str] = &[
+    "-i",
+    "--index-url",
+    "--extra-index-url",
+    "-t",
+    "--target",
+    "--prefix",
+    "--root",
+    "-c",
+    "--constraint",
+    "--trusted-host",
+];
+
+/// Operators a requirement's version specifier can start with, longest first so `==` isn't
+/// taken for `=`.
+const SPECIFIERS: &[&str] = &["===", "==", ">=", "<=", "~=", "!=", ">", "<"];
+
+#[derive(Debug, Clone)]
+pub struct Pip {}
+
+#[async_trait]
+impl Command for Pip {
+    const NAME: &'static str = "pip";
+    const ALIASES: &'static [&'static str] = &["pip3"];
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let Some((operation, params)) = params.split_first() else {
+        return (USAGE.to_string(), 0);
+    };
+
+    match operation.as_str() {
+        "install" | "download" => install(connection, params),
+        "-V" | "--version" => (VERSION.to_string(), 0),
+        "list" => (
+            "Package    Version\n---------- -------\npip        22.0.2\nsetuptools 59.6.0\nwheel      0.37.1\n"
+                .to_string(),
+            0,
+        ),
+        "freeze" => (String::new(), 0),
+        "uninstall" => {
+            let mut out = String::new();
+
+            for package in params.iter().filter(|v| !v.starts_with('-')) {
+                writeln!(out, "WARNING: Skipping {package} as it is not installed.").unwrap();
+            }
+
+            (out, 0)
+        }
+        other => (format!("ERROR: unknown command \"{other}\"\n"), 1),
+    }
+}
+
+fn install(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut requirements = Vec::new();
+    let mut user = false;
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            "-r" | "--requirement" => {
+                let Some(file) = params.next() else {
+                    return ("ERROR: -r option requires 1 argument.\n".to_string(), 2);
+                };
+
+                let Ok(content) = connection.file_system().read(Path::new(file)) else {
+                    return (
+                        format!("ERROR: Could not open requirements file: [Errno 2] No such file or directory: '{file}'\n"),
+                        1,
+                    );
+                };
+
+                requirements.extend(
+                    String::from_utf8_lossy(content)
+                        .lines()
+                        .map(|v| v.split('#').next().unwrap_or_default().trim())
+                        .filter(|v| !v.is_empty() && !v.starts_with('-'))
+                        .map(parse_requirement),
+                );
+            }
+            "--user" => user = true,
+            v if WITH_VALUE.contains(&v) => {
+                params.next();
+            }
+            v if v.starts_with('-') => {}
+            v => requirements.push(parse_requirement(v)),
+        }
+    }
+
+    if requirements.is_empty() {
+        return (
+            "ERROR: You must give at least one requirement to install (see \"pip help install\")\n"
+                .to_string(),
+            1,
+        );
+    }
+
+    let mut out = String::new();
+
+    if !user && connection.username() != "root" {
+        out.push_str(
+            "Defaulting to user installation because normal site-packages is not writeable\n",
+        );
+    }
+
+    let mut installed = Vec::with_capacity(requirements.len());
+
+    for requirement in &requirements {
+        let (version, size) = super::package_details(connection, &requirement.name);
+        let pinned = requirement
+            .version
+            .as_deref()
+            .filter(|v| v.starts_with(|c: char| c.is_ascii_digit()));
+        let version = pinned.map_or(version, ToString::to_string);
+        let file = requirement.name.replace('-', "_");
+
+        match (pinned, requirement.version.as_deref()) {
+            (Some(v), _) => writeln!(out, "Collecting {}=={v}", requirement.name),
+            (None, Some(v)) => writeln!(out, "Collecting {}{v}", requirement.name),
+            (None, None) => writeln!(out, "Collecting {}", requirement.name),
+        }
+        .unwrap();
+        writeln!(
+            out,
+            "  Downloading {file}-{version}-py3-none-any.whl ({size} kB)"
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "     \u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501}\u{2501} {size}.0/{size}.0 kB 3.1 MB/s eta 0:00:00"
+        )
+        .unwrap();
+
+        installed.push(format!("{}-{version}", requirement.name));
+    }
+
+    writeln!(
+        out,
+        "Installing collected packages: {}",
+        requirements
+            .iter()
+            .map(|v| &*v.name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+    .unwrap();
+    writeln!(out, "Successfully installed {}", installed.join(" ")).unwrap();
+
+    super::record_packages(connection, Pip::NAME, requirements);
+
+    (out, 0)
+}
+
+/// Splits a requirement such as `requests>=2.0` into the package and its version specifier, the
+/// latter including the operator unless it's an exact match.
+fn parse_requirement(requirement: &str) -> Package {
+    // environment markers, ie. `; python_version < "3.8"`
+    let requirement = requirement.split(';').next().unwrap_or_default().trim();
+
+    let split = requirement
+        .char_indices()
+        .find(|(i, _)| SPECIFIERS.iter().any(|v| requirement[*i..].starts_with(v)))
+        .map(|(i, _)| i);
+
+    let Some(i) = split else {
+        return Package {
+            name: requirement.into(),
+            version: None,
+        };
+    };
+
+    let version = requirement[i..].trim();
+
+    Package {
+        name: requirement[..i].trim().into(),
+        version: Some(version.strip_prefix("==").unwrap_or(version).trim().into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::pip::{execute, parse_requirement},
+        server::ConnectionState,
+    };
+
+    #[test_case("requests", "requests", None; "bare")]
+    #[test_case("requests==2.31.0", "requests", Some("2.31.0"); "exact")]
+    #[test_case("paramiko >= 3.0; python_version > \"3.6\"", "paramiko", Some(">= 3.0"); "range with marker")]
+    fn parses_requirements(input: &str, name: &str, version: Option<&str>) {
+        let package = parse_requirement(input);
+
+        assert_eq!(&*package.name, name);
+        assert_eq!(package.version.as_deref(), version);
+    }
+
+    #[test]
+    fn records_install() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(
+                Path::new("/tmp/requirements.txt"),
+                b"# deps\npycryptodome==3.19.0\n".to_vec().into(),
+            )
+            .unwrap();
+
+        let input = shlex::split("install -q paramiko -r /tmp/requirements.txt").unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, 0, "{out}");
+        assert!(out.contains("Successfully installed paramiko-"), "{out}");
+        assert!(out.ends_with(" pycryptodome-3.19.0\n"), "{out}");
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PackageInstall(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.manager, "pip");
+        assert_eq!(&*event.packages[1].name, "pycryptodome");
+        assert_eq!(event.packages[1].version.as_deref(), Some("3.19.0"));
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{env, Command, CommandResult},
+    server::{ConnectionState, Environment, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Printenv {}
+
+#[async_trait]
+impl Command for Printenv {
+    const NAME: &'static str = "printenv";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection.environment(), params);
+
+        if !out.is_empty() {
+            session.data(channel, out.into());
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(environment: &Environment, params: &[String]) -> (String, u32) {
+    let names = params
+        .iter()
+        .filter(|v| !v.starts_with('-'))
+        .collect::<Vec<_>>();
+
+    if names.is_empty() {
+        return (env::list(environment), 0);
+    }
+
+    let mut out = String::new();
+    let mut exit_code = 0;
+
+    // printenv exits with 1 if any of the variables asked for aren't set
+    for name in names {
+        match environment.get(name.as_bytes()) {
+            Some(value) if super::is_name(name) => {
+                writeln!(out, "{}", String::from_utf8_lossy(value)).unwrap();
+            }
+            _ => exit_code = 1,
+        }
+    }
+
+    (out, exit_code)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{command::printenv::execute, server::ConnectionState};
+
+    #[test_case("", "HOME=/root\nPATH=/usr/bin:/bin\n", 0; "all")]
+    #[test_case("HOME", "/root\n", 0; "one")]
+    #[test_case("PATH MISSING", "/usr/bin:/bin\n", 1; "missing")]
+    fn works(input: &str, expected: &str, exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        state.set_variable("HOME", "/root");
+        state.set_variable("PATH", "/usr/bin:/bin");
+
+        let input = shlex::split(input).unwrap();
+        assert_eq!(
+            execute(state.environment(), &input),
+            (expected.to_string(), exit_code)
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::{fmt::Write, time::Duration};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    persona::processes::Process,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "
+Usage:
+ ps [options]
+
+ Try 'ps --help <simple|list|output|threads|misc|all>'
+  or 'ps --help <s|l|o|t|m|a>'
+ for additional help text.
+
+For more details see ps(1).
+";
+
+#[derive(Debug, Clone)]
+pub struct Ps {}
+
+#[async_trait]
+impl Command for Ps {
+    const NAME: &'static str = "ps";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let now = OffsetDateTime::now_utc();
+        let processes = super::processes(connection, Self::NAME, params, now);
+
+        let (out, exit_code) = execute(
+            params,
+            &processes,
+            connection.config().persona.memory,
+            (connection.username(), connection.tty()),
+            now,
+        );
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// `ps`, `ps -e`
+    Default,
+    /// `ps -f`, `ps -ef`
+    Full,
+    /// `ps aux`
+    User,
+}
+
+fn execute(
+    params: &[String],
+    processes: &[Process],
+    memory: u64,
+    (username, tty): (&str, Option<&str>),
+    now: OffsetDateTime,
+) -> (String, u32) {
+    let mut format = Format::Default;
+    let mut all = false;
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('e' | 'A') => all = true,
+            Arg::Short('f') => format = Format::Full,
+            // BSD style options, given without a dash
+            Arg::Operand(v) if v.chars().all(|c| matches!(c, 'a' | 'u' | 'x' | 'w')) => {
+                all |= v.contains('a') || v.contains('x');
+                if v.contains('u') {
+                    format = Format::User;
+                }
+            }
+            Arg::Short('w' | 'l') => {}
+            Arg::Short(_) => return (format!("error: unsupported SysV option\n{USAGE}"), 1),
+            Arg::Long(_) => return (format!("error: unknown gnu long option\n{USAGE}"), 1),
+            Arg::Operand(_) => {
+                return (
+                    format!("error: unsupported option (BSD syntax)\n{USAGE}"),
+                    1,
+                )
+            }
+        }
+    }
+
+    // without any selection, ps only shows the user's processes on the same terminal
+    let processes = processes
+        .iter()
+        .filter(|v| all || (v.user == username && v.tty.as_deref() == tty))
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+
+    match format {
+        Format::Default => {
+            out.push_str("    PID TTY          TIME CMD\n");
+            for process in processes {
+                writeln!(
+                    out,
+                    "{:>7} {:<8} {:>8} {}",
+                    process.pid,
+                    tty_name(process),
+                    clock(process.time),
+                    process.comm(),
+                )
+                .unwrap();
+            }
+        }
+        Format::Full => {
+            out.push_str("UID          PID    PPID  C STIME TTY          TIME CMD\n");
+            for process in processes {
+                writeln!(
+                    out,
+                    "{:<8} {:>7} {:>7} {:>2} {:>5} {:<8} {:>8} {}",
+                    user_name(process),
+                    process.pid,
+                    process.ppid,
+                    0,
+                    start_time(process, now),
+                    tty_name(process),
+                    clock(process.time),
+                    process.command,
+                )
+                .unwrap();
+            }
+        }
+        Format::User => {
+            out.push_str(
+                "USER         PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND\n",
+            );
+            for process in processes {
+                #[allow(clippy::cast_precision_loss)]
+                let mem = process.rss as f64 * 100.0 / memory.max(1) as f64;
+
+                writeln!(
+                    out,
+                    "{:<8} {:>7} {:>4.1} {:>4.1} {:>6} {:>5} {:<8} {:<4} {:>5} {:>6} {}",
+                    user_name(process),
+                    process.pid,
+                    0.0,
+                    mem,
+                    process.vsz,
+                    process.rss,
+                    tty_name(process),
+                    process.stat,
+                    start_time(process, now),
+                    minutes(process.time),
+                    process.command,
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    (out, 0)
+}
+
+fn tty_name(process: &Process) -> &str {
+    process.tty.as_deref().unwrap_or("?")
+}
+
+/// Names longer than the column are cut short with a `+`, as procps does.
+fn user_name(process: &Process) -> String {
+    if process.user.len() > 8 {
+        format!("{}+", &process.user[..7])
+    } else {
+        process.user.clone()
+    }
+}
+
+/// Formats when the process started, as the time if it was today, the date if it was this
+/// year, or the year otherwise.
+pub fn start_time(process: &Process, now: OffsetDateTime) -> String {
+    let start = process.start;
+
+    let format = if start.date() == now.date() {
+        format_description!("[hour]:[minute]")
+    } else if start.year() == now.year() {
+        format_description!("[month repr:short][day]")
+    } else {
+        format_description!("[year]")
+    };
+
+    start.format(format).unwrap()
+}
+
+/// Formats CPU time as `HH:MM:SS`.
+fn clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Formats CPU time as `M:SS`.
+fn minutes(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::{
+        command::ps::execute,
+        persona::{
+            processes::{processes, Session},
+            Persona,
+        },
+    };
+
+    #[test_case("", "    PID TTY          TIME CMD", 3; "session only")]
+    #[test_case("-e", "    PID TTY          TIME CMD", 52; "every process")]
+    #[test_case("-ef", "UID          PID    PPID  C STIME TTY          TIME CMD", 52; "full format")]
+    #[test_case("aux", "USER         PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND", 52; "user format")]
+    fn works(input: &str, expected_header: &str, expected_lines: usize) {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let now = datetime!(2023-10-24 09:12:44 UTC);
+        let processes = processes(
+            &persona,
+            datetime!(2023-10-12 06:08:40 UTC),
+            &Session {
+                username: "ubuntu",
+                tty: Some("pts/0"),
+                login: datetime!(2023-10-24 09:10:00 UTC),
+                now,
+                command: "ps",
+            },
+        );
+
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(
+            &input,
+            &processes,
+            persona.memory,
+            ("ubuntu", Some("pts/0")),
+            now,
+        );
+
+        assert_eq!(exit_code, 0);
+        assert_eq!(output.lines().next(), Some(expected_header));
+        assert_eq!(output.lines().count(), expected_lines, "{output}");
+    }
+
+    #[test]
+    fn aux_columns_line_up() {
+        let persona = toml::from_str::<Persona>("preset = \"debian\"").unwrap();
+        let now = datetime!(2023-10-24 09:12:44 UTC);
+        let processes = processes(
+            &persona,
+            datetime!(2023-09-12 23:41:40 UTC),
+            &Session {
+                username: "root",
+                tty: None,
+                login: now,
+                now,
+                command: "ps aux",
+            },
+        );
+
+        let (output, _) = execute(
+            &["aux".to_string()],
+            &processes,
+            persona.memory,
+            ("root", None),
+            now,
+        );
+        let mut lines = output.lines();
+
+        assert_eq!(
+            lines.next().unwrap(),
+            "USER         PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND"
+        );
+        assert!(lines.next().unwrap().starts_with("root           1  0.0 "));
+        assert!(output.contains(" ?        I<   Sep12   0:"), "{output}");
+        assert!(output.contains("message+"), "{output}");
+        assert!(output.ends_with(" 09:12   0:00 ps aux\n"), "{output}");
+    }
+}
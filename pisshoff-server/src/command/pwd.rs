@@ -11,6 +11,8 @@ pub struct Pwd {}
 
 #[async_trait]
 impl Command for Pwd {
+    const NAME: &'static str = "pwd";
+
     async fn new<S: ThrusshSession + Send>(
         connection: &mut ConnectionState,
         _params: &[String],
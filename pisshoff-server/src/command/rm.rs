@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use pisshoff_types::audit::FileOperation;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{record_file_operation, Arg, Command, CommandResult},
+    file_system::LsError,
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Rm {}
+
+#[async_trait]
+impl Command for Rm {
+    const NAME: &'static str = "rm";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut recursive = false;
+        let mut force = false;
+        let mut files = Vec::new();
+
+        for param in super::argparse(params) {
+            match param {
+                Arg::Short('r' | 'R') | Arg::Long("recursive") => recursive = true,
+                Arg::Short('f') | Arg::Long("force") => force = true,
+                Arg::Operand(file) => files.push(file),
+                Arg::Short(_) | Arg::Long(_) => {}
+            }
+        }
+
+        if files.is_empty() && !force {
+            session.data(channel, "rm: missing operand\n".into());
+            return CommandResult::Exit(1);
+        }
+
+        let mut status = 0;
+
+        for file in files {
+            let path = Path::new(file);
+            let fs = connection.file_system();
+
+            let res = if recursive {
+                fs.remove_all(path)
+            } else {
+                fs.remove(path)
+            };
+
+            match res {
+                Ok(()) => record_file_operation(connection, path, FileOperation::Remove),
+                Err(LsError::NoSuchFileOrDirectory) if force => {}
+                Err(e) => {
+                    status = 1;
+                    session.data(channel, format!("rm: cannot remove '{file}': {e}\n").into());
+                }
+            }
+        }
+
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+
+    use crate::{
+        command::{rm::Rm, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[tokio::test]
+    async fn removes_files() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("a"), Box::default())
+            .unwrap();
+
+        let out = Rm::new(
+            &mut state,
+            &["a".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert!(state.file_system().get(Path::new("a")).is_err());
+    }
+
+    #[tokio::test]
+    async fn directory_requires_recursive() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("a/b")).unwrap();
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string("rm: cannot remove 'a': Is a directory\n"),
+            )
+            .returning(|_, _| ());
+
+        let out = Rm::new(
+            &mut state,
+            &["a".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+
+        let out = Rm::new(
+            &mut state,
+            &["-rf".to_string(), "a".to_string(), "missing".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert!(state.file_system().get(Path::new("a")).is_err());
+    }
+}
@@ -8,12 +8,15 @@ use nom::{
     combinator::{map, map_res},
     IResult,
 };
-use pisshoff_types::audit::{AuditLogAction, WriteFileEvent};
+use pisshoff_types::audit::{AuditLogAction, MkdirEvent, WriteFileEvent};
 use thrussh::ChannelId;
 use tracing::warn;
 
 use crate::{
-    command::{Arg, Command, CommandResult},
+    audit::sha256_hex,
+    command::{ssh::Login, Arg, Command, CommandResult},
+    file_system::Tree,
+    quarantine::{self, Origin},
     server::{ConnectionState, ThrusshSession},
 };
 
@@ -35,12 +38,20 @@ pub struct Scp {
 
 #[async_trait]
 impl Command for Scp {
+    const NAME: &'static str = "scp";
+
     async fn new<S: ThrusshSession + Send>(
-        _connection: &mut ConnectionState,
+        connection: &mut ConnectionState,
         params: &[String],
         channel: ChannelId,
         session: &mut S,
     ) -> CommandResult<Self> {
+        if let Some(login) = Login::scp(connection, params) {
+            return login
+                .begin(connection, channel, session)
+                .map(Self::outbound);
+        }
+
         let mut path = None;
         let mut transfer = false;
 
@@ -94,6 +105,11 @@ impl Command for Scp {
         let mut exit = false;
         while !self.pending_data.is_empty() && !exit {
             let next_state = match self.state {
+                State::Outbound(login) => {
+                    return login
+                        .read(connection, channel, data, session)
+                        .map(Self::outbound);
+                }
                 State::Waiting => {
                     match Receive::parse(&self.pending_data) {
                         Ok((rest, res)) => {
@@ -101,12 +117,39 @@ impl Command for Scp {
 
                             match res {
                                 Receive::FileCopy {
-                                    length, file_name, ..
+                                    mode,
+                                    length,
+                                    file_name,
                                 } => {
-                                    state = State::ReceivingFile(length, self.path.join(file_name));
+                                    // if the target is a directory (either because we were asked
+                                    // to copy into one or we're copying recursively) then the file
+                                    // keeps its own name, otherwise it takes the target's name
+                                    let path = if matches!(
+                                        connection.file_system().get(&self.path),
+                                        Ok(Tree::Directory(_))
+                                    ) {
+                                        self.path.join(file_name)
+                                    } else {
+                                        self.path.clone()
+                                    };
+
+                                    state = State::ReceivingFile(PendingFile {
+                                        length,
+                                        path,
+                                        file_name: file_name.to_string(),
+                                        mode: mode.to_string(),
+                                    });
                                 }
                                 Receive::DirectoryCopy { directory_name, .. } => {
                                     self.path.push(directory_name);
+
+                                    connection.audit_log().push_action(AuditLogAction::Mkdir(
+                                        MkdirEvent {
+                                            path: Box::from(self.path.to_string_lossy().as_ref()),
+                                        },
+                                    ));
+
+                                    let _res = connection.file_system().mkdirall(&self.path);
                                 }
                                 Receive::EndDirectory => {
                                     self.path.pop();
@@ -129,21 +172,35 @@ impl Command for Scp {
                         }
                     }
                 }
-                State::ReceivingFile(length, path) => {
-                    if self.pending_data.len() < length {
+                State::ReceivingFile(file) => {
+                    if self.pending_data.len() < file.length {
                         // keep waiting for more data...
                         exit = true;
-                        State::ReceivingFile(length, path)
+                        State::ReceivingFile(file)
                     } else {
                         // we've received the whole file, lets print and start waiting again
-                        let data = self.pending_data.split_to(length);
-
-                        connection
-                            .audit_log()
-                            .push_action(AuditLogAction::WriteFile(WriteFileEvent {
-                                path: Box::from(path.to_string_lossy().into_owned()),
-                                content: data.freeze(),
-                            }));
+                        let data = self.pending_data.split_to(file.length).freeze();
+                        let sha256 = sha256_hex(&data);
+
+                        let origin = Origin {
+                            tool: "scp",
+                            name: Some(file.file_name.as_str()),
+                            url: None,
+                        };
+                        quarantine::store(connection, &sha256, data.clone(), &origin);
+
+                        let _res = connection
+                            .file_system()
+                            .write(&file.path, data.to_vec().into_boxed_slice());
+
+                        connection.record_write(WriteFileEvent {
+                            path: Box::from(file.path.to_string_lossy().into_owned()),
+                            sha256,
+                            size: data.len(),
+                            original_name: Some(file.file_name.into_boxed_str()),
+                            mode: Some(file.mode.into_boxed_str()),
+                            content: data,
+                        });
 
                         State::AwaitingSeparator
                     }
@@ -168,13 +225,34 @@ impl Command for Scp {
     }
 }
 
+impl Scp {
+    /// Copies to or from another machine, rather than being our end of someone else's `scp`.
+    fn outbound(login: Login) -> Self {
+        Self {
+            path: PathBuf::new(),
+            pending_data: BytesMut::new(),
+            state: State::Outbound(login),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 enum State {
+    /// Logging in to another machine to copy to or from it.
+    Outbound(Login),
     Waiting,
-    ReceivingFile(usize, PathBuf),
+    ReceivingFile(PendingFile),
     AwaitingSeparator,
 }
 
+#[derive(Clone, Debug)]
+struct PendingFile {
+    length: usize,
+    path: PathBuf,
+    file_name: String,
+    mode: String,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 #[allow(dead_code)]
 enum Receive<'a> {
@@ -283,6 +361,8 @@ impl<'a> Receive<'a> {
 
 #[cfg(test)]
 mod test {
+    use std::path::Path;
+
     use insta::assert_debug_snapshot;
     use mockall::predicate::always;
 
@@ -347,6 +427,7 @@ mod test {
     async fn works() {
         let mut session = MockThrusshSession::default();
         let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("hello")).unwrap();
 
         session
             .expect_data()
@@ -378,4 +459,40 @@ mod test {
             assert_debug_snapshot!(state.audit_log());
         });
     }
+
+    #[tokio::test]
+    async fn file_target() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("/tmp")).unwrap();
+
+        session
+            .expect_data()
+            .with(always(), eq_string("\0"))
+            .returning(|_, _| ());
+
+        let out = Scp::new(
+            &mut state,
+            ["-t".to_string(), "/tmp/x".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let _out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"C0755 5 dropper.sh\nhello\0",
+                &mut session,
+            )
+            .await
+            .unwrap_stdin();
+
+        assert_eq!(
+            state.file_system().read(Path::new("/tmp/x")).unwrap(),
+            b"hello"
+        );
+    }
 }
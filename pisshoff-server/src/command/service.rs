@@ -0,0 +1,120 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{systemctl, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str =
+    "Usage: service < option > | --status-all | [ service_name [ command | --full-restart ] ]\n";
+
+/// `service`, which hands off to `systemctl` on systems booted with systemd, just as the real
+/// one does.
+#[derive(Debug, Clone)]
+pub struct Service {}
+
+#[async_trait]
+impl Command for Service {
+    const NAME: &'static str = "service";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let persona = &connection.config().persona;
+    let booted = systemctl::booted(persona);
+
+    match params {
+        [] => (USAGE.to_string(), 1),
+        [flag, ..] if flag == "--status-all" && booted => {
+            (status_all(&systemctl::daemons(persona)), 0)
+        }
+        [flag, ..] if flag == "--status-all" => (String::new(), 0),
+        // without systemd there'd have to be a script in /etc/init.d, which there never is
+        [name, ..] if !booted => (format!("{name}: unrecognized service\n"), 1),
+        [name, action, ..] => {
+            let action = match action.as_str() {
+                "force-reload" => "reload-or-restart",
+                "--full-restart" => "restart",
+                other => other,
+            };
+
+            let options = systemctl::parse(&[action.to_string(), name.to_string()]);
+            systemctl::execute(connection, &options)
+        }
+        [name] => (
+            format!(
+                "Usage: /etc/init.d/{name} {{start|stop|restart|reload|force-reload|status}}\n"
+            ),
+            1,
+        ),
+    }
+}
+
+/// Lists every service along with whether it's running, which the persona's daemons always
+/// are.
+fn status_all(daemons: &[String]) -> String {
+    let mut names = daemons
+        .iter()
+        .filter_map(|v| v.strip_suffix(".service"))
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+
+    let mut out = String::new();
+
+    for name in names {
+        writeln!(out, " [ + ]  {name}").unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::{
+        command::service::{execute, status_all},
+        server::ConnectionState,
+    };
+
+    #[test]
+    fn lists_daemons() {
+        let daemons = ["sshd.service".to_string(), "cron.service".to_string()];
+        assert_eq!(status_all(&daemons), " [ + ]  cron\n [ + ]  sshd\n");
+    }
+
+    #[test_case("nginx start", "nginx: unrecognized service\n", 1; "unrecognized")]
+    #[test_case("--status-all", "", 0; "status all")]
+    fn without_systemd(input: &str, expected: &str, exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        let input = shlex::split(input).unwrap();
+
+        assert_eq!(
+            execute(&mut state, &input),
+            (expected.to_string(), exit_code)
+        );
+    }
+}
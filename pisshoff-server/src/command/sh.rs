@@ -0,0 +1,437 @@
+//! Runs scripts dropped onto the file system through the emulator, so the behaviour of a
+//! dropper is recorded command by command rather than just its name. Positional parameters
+//! aren't passed through to the script.
+
+use std::{convert::Infallible, path::Path};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    file_system::LsError,
+    server::{ConnectionState, ThrusshSession},
+    subsystem::shell::run_script,
+};
+
+/// Directories binaries are usually installed to, programs in which are emulated by name if
+/// there's no script there.
+const BIN_DIRECTORIES: &[&str] = &[
+    "/bin",
+    "/sbin",
+    "/usr/bin",
+    "/usr/sbin",
+    "/usr/local/bin",
+    "/usr/local/sbin",
+];
+
+/// Interpreters a script can name in its shebang for us to run it.
+const SHELLS: &[&str] = &["sh", "bash", "dash", "ash", "busybox"];
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+#[derive(Debug, Clone)]
+pub struct Sh {}
+
+#[async_trait]
+impl Command for Sh {
+    const NAME: &'static str = "sh";
+    const ALIASES: &'static [&'static str] = &["dash", "ash"];
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        start(Self::NAME, connection, params, channel, session)
+            .await
+            .map(|()| Self {})
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        read(Self::NAME, connection, channel, data, session)
+            .await
+            .map(|()| Self {})
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bash {}
+
+#[async_trait]
+impl Command for Bash {
+    const NAME: &'static str = "bash";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        start(Self::NAME, connection, params, channel, session)
+            .await
+            .map(|()| Self {})
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        read(Self::NAME, connection, channel, data, session)
+            .await
+            .map(|()| Self {})
+    }
+}
+
+/// Runs the script given by `-c` or as the first operand, or reads one from stdin if there's
+/// neither.
+async fn start<S: ThrusshSession + Send>(
+    name: &str,
+    connection: &mut ConnectionState,
+    params: &[String],
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<()> {
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            // short options can be bundled with `-c`, as in `sh -ec 'curl x | sh'`
+            v if v.starts_with('-') && !v.starts_with("--") && v.contains('c') => {
+                let Some(command) = params.next() else {
+                    session.data(
+                        channel,
+                        format!("{name}: -c: option requires an argument\n").into(),
+                    );
+                    return CommandResult::Exit(2);
+                };
+
+                let res = run_script(
+                    &format!("{name}: -c"),
+                    command.as_bytes(),
+                    connection,
+                    channel,
+                    session,
+                )
+                .await;
+
+                return CommandResult::Exit(exit_status(res));
+            }
+            "-s" => break,
+            v if v.starts_with(['-', '+']) => {}
+            file => {
+                return CommandResult::Exit(
+                    run_file(name, file, connection, channel, session).await,
+                )
+            }
+        }
+    }
+
+    CommandResult::ReadStdin(())
+}
+
+/// Runs a script piped in on stdin, as in `curl x | sh`, waiting for more until it calls
+/// `exit`.
+async fn read<S: ThrusshSession + Send>(
+    name: &str,
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    data: &[u8],
+    session: &mut S,
+) -> CommandResult<()> {
+    match run_script(name, data, connection, channel, session).await {
+        CommandResult::Close(v) => CommandResult::Exit(v),
+        CommandResult::Exit(_) => CommandResult::ReadStdin(()),
+        CommandResult::ReadStdin(v) => match v {},
+    }
+}
+
+/// Runs the script at `file` as in `sh x.sh`, which unlike `./x.sh` doesn't need to be
+/// executable.
+async fn run_file<S: ThrusshSession + Send>(
+    name: &str,
+    file: &str,
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> u32 {
+    let (message, status) = match connection.file_system().read(Path::new(file)) {
+        Ok(content) if content.starts_with(ELF_MAGIC) => ("cannot execute binary file".into(), 126),
+        Ok(content) => {
+            let content = content.to_vec();
+            return exit_status(run_script(file, &content, connection, channel, session).await);
+        }
+        Err(e @ LsError::IsADirectory) => (e.to_string(), 126),
+        Err(e) => (e.to_string(), 127),
+    };
+
+    session.data(channel, format!("{name}: {file}: {message}\n").into());
+    status
+}
+
+/// Runs the program at `path`, as in `./x` or `/usr/bin/wget`. Scripts need to have been made
+/// executable, and are run as if they'd been passed to `sh`. Returns `None` if there's no
+/// script at the path and it's in one of the usual places for binaries, in which case it
+/// should be run as the command it's named after.
+pub async fn exec<S: ThrusshSession + Send>(
+    connection: &mut ConnectionState,
+    path: &str,
+    channel: ChannelId,
+    session: &mut S,
+) -> Option<u32> {
+    let file_system = connection.file_system();
+    let in_bin = file_system
+        .canonicalize(Path::new(path))
+        .parent()
+        .map_or(false, |v| BIN_DIRECTORIES.iter().any(|d| v == Path::new(d)));
+    let executable = file_system
+        .mode(Path::new(path))
+        .map_or(false, |v| v & 0o111 != 0);
+
+    let (error, status) = match file_system.read(Path::new(path)) {
+        Err(LsError::NoSuchFileOrDirectory) if in_bin => return None,
+        Err(e @ LsError::NoSuchFileOrDirectory) => (e.to_string(), 127),
+        Err(e) => (e.to_string(), 126),
+        // snapshots don't carry modes, so anything that came with the persona in these
+        // directories is only ever the real thing
+        Ok(content) if in_bin && (!executable || content.starts_with(ELF_MAGIC)) => return None,
+        Ok(_) if !executable => ("Permission denied".to_string(), 126),
+        // there's nothing to run binaries with, but they've already been recorded when they
+        // were written and failing would give the game away
+        Ok(content) if content.starts_with(ELF_MAGIC) || !is_shell_script(content) => {
+            return Some(0);
+        }
+        Ok(content) => {
+            let content = content.to_vec();
+            return Some(exit_status(
+                run_script(path, &content, connection, channel, session).await,
+            ));
+        }
+    };
+
+    session.data(channel, format!("bash: {path}: {error}\n").into());
+    Some(status)
+}
+
+/// Whether `content` is a script for a shell, which scripts without a shebang are assumed to
+/// be.
+fn is_shell_script(content: &[u8]) -> bool {
+    let Some(shebang) = content.strip_prefix(b"#!") else {
+        return true;
+    };
+
+    let line = String::from_utf8_lossy(shebang.split(|c| *c == b'\n').next().unwrap_or_default());
+    let mut words = line
+        .split_whitespace()
+        .map(|v| v.rsplit('/').next().unwrap_or(v));
+
+    let interpreter = match words.next() {
+        Some("env") => words.find(|v| !v.starts_with('-')),
+        v => v,
+    };
+
+    interpreter.map_or(false, |v| SHELLS.contains(&v))
+}
+
+/// Exit status of a script, which may have ended early by calling `exit`.
+fn exit_status(res: CommandResult<Infallible>) -> u32 {
+    match res {
+        CommandResult::Exit(v) | CommandResult::Close(v) => v,
+        CommandResult::ReadStdin(v) => match v {},
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            sh::{is_shell_script, Bash},
+            Command, CommandResult, ConcreteCommand,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[tokio::test]
+    async fn runs_scripts() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(
+                Path::new("x.sh"),
+                b"#!/bin/sh\n# fetch the miner\necho hi\nls /missing \\\n  || echo fallback\n"
+                    .to_vec()
+                    .into(),
+            )
+            .unwrap();
+
+        session.expect_redirected().returning(|| false);
+
+        for expected in [
+            "hi\n",
+            "ls: /missing: No such file or directory\n",
+            "fallback\n",
+        ] {
+            session
+                .expect_data()
+                .once()
+                .with(always(), eq_string(expected))
+                .returning(|_, _| ());
+        }
+
+        let out = Bash::new(
+            &mut state,
+            &["x.sh".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let commands = state
+            .audit_log()
+            .events
+            .iter()
+            .filter(|v| matches!(v.action, AuditLogAction::ExecCommand(_)))
+            .count();
+        assert_eq!(commands, 3);
+    }
+
+    #[tokio::test]
+    async fn exit_ends_script() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session.expect_redirected().returning(|| false);
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("a\n"))
+            .returning(|_, _| ());
+
+        let out = Bash::new(
+            &mut state,
+            &["-c".to_string(), "echo a; exit 3; echo b".to_string()],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(3)), "{out:?}");
+    }
+
+    #[test_case(b"./x", "bash: ./x: Permission denied\n", 126; "not executable")]
+    #[test_case(b"./missing", "bash: ./missing: No such file or directory\n", 127; "missing")]
+    #[test_case(b"./dir", "bash: ./dir: Is a directory\n", 126; "directory")]
+    #[test_case(b"/usr/bin/whoami", "root\n", 0; "emulated")]
+    #[tokio::test]
+    async fn runs_by_path(exec: &[u8], expected: &'static str, exit_code: u32) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.file_system().mkdirall(Path::new("dir")).unwrap();
+        state
+            .file_system()
+            .write(Path::new("x"), b"whoami\n".to_vec().into())
+            .unwrap();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(expected))
+            .returning(|_, _| ());
+
+        let out =
+            ConcreteCommand::new(&mut state, Some(exec), &[], fake_channel_id(), &mut session)
+                .await;
+        assert!(
+            matches!(out, CommandResult::Exit(v) if v == exit_code),
+            "{out:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn runs_executable_scripts() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("x"), b"whoami\n".to_vec().into())
+            .unwrap();
+        state.file_system().set_mode(Path::new("x"), 0o755).unwrap();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("root\n"))
+            .returning(|_, _| ());
+
+        let out = ConcreteCommand::new(
+            &mut state,
+            Some(b"./x".as_slice()),
+            &[],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+
+    #[test_case(b"sh", &["x"], "x: maximum nesting level exceeded (64)\n"; "sh")]
+    #[test_case(b"./x", &[], "./x: maximum nesting level exceeded (64)\n"; "path")]
+    #[tokio::test]
+    async fn stops_recursive_scripts(exec: &[u8], params: &[&str], expected: &'static str) {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        let script = format!("{} {}\n", String::from_utf8_lossy(exec), params.join(" "));
+        state
+            .file_system()
+            .write(Path::new("x"), script.into_bytes().into())
+            .unwrap();
+        state.file_system().set_mode(Path::new("x"), 0o755).unwrap();
+
+        session.expect_redirected().returning(|| false);
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(expected))
+            .returning(|_, _| ());
+
+        let params = params.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let out = ConcreteCommand::new(
+            &mut state,
+            Some(exec),
+            &params,
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(2)), "{out:?}");
+        assert_eq!(*state.script_depth(), 0);
+    }
+
+    #[test_case(b"echo hi\n", true; "no shebang")]
+    #[test_case(b"#!/bin/bash\necho hi\n", true; "bash")]
+    #[test_case(b"#!/usr/bin/env sh\necho hi\n", true; "env")]
+    #[test_case(b"#!/usr/bin/python3\nprint(1)\n", false; "python")]
+    fn detects_shell_scripts(content: &[u8], expected: bool) {
+        assert_eq!(is_shell_script(content), expected);
+    }
+}
@@ -0,0 +1,171 @@
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    command::{connect_out, Command, CommandResult, Target},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Where one of socat's two addresses leads.
+#[derive(Debug, PartialEq, Eq)]
+enum Address {
+    Connect {
+        host: String,
+        port: u16,
+    },
+    Listen,
+    /// A program to hand the connection to, as in `exec:'bash -li',pty`.
+    Program(String),
+    /// Anything else, such as `stdio` or a file.
+    Other,
+}
+
+impl Address {
+    fn parse(address: &str) -> Self {
+        let (kind, rest) = address.split_once([':', ',']).unwrap_or((address, ""));
+        let value = rest.split(',').next().unwrap_or_default();
+
+        match kind.to_ascii_lowercase().as_str() {
+            "tcp" | "tcp4" | "tcp6" | "tcp-connect" | "tcp4-connect" | "tcp6-connect"
+            | "openssl" | "openssl-connect" | "ssl" => {
+                let Some((host, port)) = value.rsplit_once(':') else {
+                    return Self::Other;
+                };
+
+                port.parse().map_or(Self::Other, |port| Self::Connect {
+                    host: host
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .to_string(),
+                    port,
+                })
+            }
+            "tcp-listen" | "tcp4-listen" | "tcp6-listen" | "tcp-l" | "openssl-listen" => {
+                Self::Listen
+            }
+            "exec" | "system" => Self::Program(value.to_string()),
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Socat {}
+
+#[async_trait]
+impl Command for Socat {
+    const NAME: &'static str = "socat";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let addresses = addresses(params);
+
+        let pid = super::processes(connection, Self::NAME, params, OffsetDateTime::now_utc())
+            .last()
+            .map_or(0, |v| v.pid);
+
+        let [first, second] = &addresses[..] else {
+            let out = format!(
+                "{} socat[{pid}] E exactly 2 addresses required (there are {}); use option \
+                 \"-h\" for help\n",
+                timestamp(),
+                addresses.len()
+            );
+            session.data(channel, out.into());
+            return CommandResult::Exit(1);
+        };
+
+        let ((host, port), program) = match (first, second) {
+            (Address::Listen, _) | (_, Address::Listen) => {
+                return CommandResult::ReadStdin(Self {});
+            }
+            (Address::Connect { host, port }, Address::Program(program))
+            | (Address::Program(program), Address::Connect { host, port }) => {
+                ((host.clone(), *port), Some(program.clone()))
+            }
+            (Address::Connect { host, port }, _) | (_, Address::Connect { host, port }) => {
+                ((host.clone(), *port), None)
+            }
+            _ => return CommandResult::Exit(0),
+        };
+
+        connect_out(
+            connection,
+            Self::NAME,
+            Target {
+                host: host.clone(),
+                port,
+                program,
+            },
+        )
+        .await;
+
+        let out = format!(
+            "{} socat[{pid}] E connect(5, AF=2 {host}:{port}, 16): Connection timed out\n",
+            timestamp()
+        );
+        session.data(channel, out.into());
+        CommandResult::Exit(1)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        // nobody's ever going to connect to the listener
+        CommandResult::ReadStdin(self)
+    }
+}
+
+/// Addresses given to socat, skipping over its options.
+fn addresses(params: &[String]) -> Vec<Address> {
+    let mut addresses = Vec::new();
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        match param.as_str() {
+            "-lf" | "-lp" => {
+                params.next();
+            }
+            "-" => addresses.push(Address::Other),
+            v if v.starts_with('-') => {}
+            v => addresses.push(Address::parse(v)),
+        }
+    }
+
+    addresses
+}
+
+/// Timestamp socat prefixes its log messages with.
+fn timestamp() -> String {
+    OffsetDateTime::now_utc()
+        .format(format_description!(
+            "[year]/[month]/[day] [hour]:[minute]:[second]"
+        ))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::command::socat::Address;
+
+    #[test_case("TCP:10.0.0.1:4444", Address::Connect { host: "10.0.0.1".to_string(), port: 4444 }; "tcp")]
+    #[test_case("openssl-connect:evil.example:443,verify=0", Address::Connect { host: "evil.example".to_string(), port: 443 }; "openssl")]
+    #[test_case("tcp6:[::1]:4444", Address::Connect { host: "::1".to_string(), port: 4444 }; "ipv6")]
+    #[test_case("exec:bash -li,pty,stderr,setsid", Address::Program("bash -li".to_string()); "exec")]
+    #[test_case("TCP-LISTEN:4444,reuseaddr,fork", Address::Listen; "listen")]
+    #[test_case("STDIO", Address::Other; "stdio")]
+    fn parses(input: &str, expected: Address) {
+        assert_eq!(Address::parse(input), expected);
+    }
+}
@@ -0,0 +1,253 @@
+use std::{fmt::Write, net::SocketAddr};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::OffsetDateTime;
+
+use crate::{
+    command::{netstat, Arg, Command, CommandResult},
+    persona::{
+        network::{Protocol, Socket},
+        processes::Process,
+        Network,
+    },
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Ss {}
+
+#[async_trait]
+impl Command for Ss {
+    const NAME: &'static str = "ss";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let processes = super::processes(connection, Self::NAME, params, OffsetDateTime::now_utc());
+        let peer = connection.audit_log().peer_address;
+
+        let (out, exit_code) = execute(
+            params,
+            &connection.config().persona.network,
+            &processes,
+            peer,
+            connection.username(),
+        );
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(
+    params: &[String],
+    network: &Network,
+    processes: &[Process],
+    peer: Option<SocketAddr>,
+    username: &str,
+) -> (String, u32) {
+    let mut listening = false;
+    let mut all = false;
+    let mut numeric = false;
+    let mut programs = false;
+    let mut protocols = Vec::new();
+
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('t') | Arg::Long("tcp") => protocols.push(Protocol::Tcp),
+            Arg::Short('u') | Arg::Long("udp") => protocols.push(Protocol::Udp),
+            Arg::Short('l') | Arg::Long("listening") => listening = true,
+            Arg::Short('a') | Arg::Long("all") => all = true,
+            Arg::Short('n') | Arg::Long("numeric") => numeric = true,
+            Arg::Short('p') | Arg::Long("processes") => programs = true,
+            Arg::Short('e' | 'o' | 'H' | '4' | '6') | Arg::Long("extended" | "options") => {}
+            Arg::Short(c) => {
+                return (
+                    format!("ss: invalid option -- '{c}'\nUsage: ss [ OPTIONS ]\n       ss [ OPTIONS ] [ FILTER ]\n"),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!("ss: unrecognized option '--{v}'\nUsage: ss [ OPTIONS ]\n       ss [ OPTIONS ] [ FILTER ]\n"),
+                    1,
+                );
+            }
+            Arg::Operand(v) => {
+                return (format!("ss: bad filter expression at \"{v}\"\n"), 1);
+            }
+        }
+    }
+
+    // the netid column is only shown when it could be more than one thing
+    let netid = protocols.len() != 1;
+    if protocols.is_empty() {
+        protocols = vec![Protocol::Tcp, Protocol::Udp];
+    }
+
+    // ss lists udp sockets before tcp ones
+    let mut sockets = network.sockets(peer);
+    sockets.sort_by_key(|v| v.protocol == Protocol::Tcp);
+
+    let rows = sockets
+        .iter()
+        .filter(|v| protocols.contains(&v.protocol))
+        .filter(|v| all || v.is_listening() == listening)
+        .map(|socket| {
+            // only root gets to see who owns everybody else's sockets
+            let owner = socket
+                .owner(processes)
+                .filter(|v| programs && (username == "root" || v.user == username));
+
+            Row::new(socket, owner, numeric)
+        })
+        .collect::<Vec<_>>();
+
+    let local_width = rows
+        .iter()
+        .map(|v| v.local.len())
+        .chain(std::iter::once("Local Address:Port".len()))
+        .max()
+        .unwrap_or_default();
+    let peer_width = rows
+        .iter()
+        .map(|v| v.peer.len())
+        .chain(std::iter::once("Peer Address:Port".len()))
+        .max()
+        .unwrap_or_default();
+
+    let mut out = String::new();
+
+    let header = format!(
+        "{}State  Recv-Q Send-Q {:<local_width$} {:<peer_width$} Process",
+        if netid { "Netid " } else { "" },
+        "Local Address:Port",
+        "Peer Address:Port",
+    );
+    writeln!(out, "{header}").unwrap();
+
+    for row in rows {
+        let mut line = String::new();
+
+        if netid {
+            write!(line, "{:<5} ", row.protocol).unwrap();
+        }
+
+        write!(
+            line,
+            "{:<6} {:<6} {:<6} {:<local_width$} {:<peer_width$} {}",
+            row.state,
+            0,
+            row.send_queue,
+            row.local,
+            row.peer,
+            row.process.unwrap_or_default(),
+        )
+        .unwrap();
+
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+
+    (out, 0)
+}
+
+struct Row {
+    protocol: &'static str,
+    state: &'static str,
+    send_queue: u32,
+    local: String,
+    peer: String,
+    process: Option<String>,
+}
+
+impl Row {
+    fn new(socket: &Socket, owner: Option<&Process>, numeric: bool) -> Self {
+        let wildcard = if socket.local.is_ipv4() {
+            "0.0.0.0:*"
+        } else {
+            "[::]:*"
+        };
+
+        Self {
+            protocol: match socket.protocol {
+                Protocol::Tcp => "tcp",
+                Protocol::Udp => "udp",
+            },
+            state: match (socket.protocol, socket.is_listening()) {
+                (Protocol::Tcp, true) => "LISTEN",
+                (Protocol::Udp, true) => "UNCONN",
+                (_, false) => "ESTAB",
+            },
+            // the backlog of listening sockets
+            send_queue: if socket.protocol == Protocol::Tcp && socket.is_listening() {
+                128
+            } else {
+                0
+            },
+            local: address(socket.local, numeric),
+            peer: socket
+                .remote
+                .map_or_else(|| wildcard.to_string(), |v| address(v, numeric)),
+            process: owner.map(|v| format!("users:((\"{}\",pid={},fd=3))", v.comm(), v.pid)),
+        }
+    }
+}
+
+fn address(addr: SocketAddr, numeric: bool) -> String {
+    let port = netstat::service(addr.port())
+        .filter(|_| !numeric)
+        .map_or_else(|| addr.port().to_string(), ToString::to_string);
+
+    match addr {
+        SocketAddr::V4(v) => format!("{}:{port}", v.ip()),
+        SocketAddr::V6(v) => format!("[{}]:{port}", v.ip()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use test_case::test_case;
+
+    use crate::{command::ss::execute, persona::Persona};
+
+    #[test_case("-tln", "State  Recv-Q Send-Q Local Address:Port Peer Address:Port Process
+LISTEN 0      128    127.0.0.53:53      0.0.0.0:*
+LISTEN 0      128    0.0.0.0:22         0.0.0.0:*
+LISTEN 0      128    0.0.0.0:80         0.0.0.0:*
+LISTEN 0      128    [::]:22            [::]:*
+LISTEN 0      128    [::]:80            [::]:*
+"; "listening")]
+    #[test_case("-un", "State  Recv-Q Send-Q Local Address:Port Peer Address:Port Process
+"; "no udp connections")]
+    #[test_case("", "Netid State  Recv-Q Send-Q Local Address:Port Peer Address:Port  Process
+tcp   ESTAB  0      0      10.0.0.12:http     10.0.0.5:51724
+tcp   ESTAB  0      0      10.0.0.12:http     10.0.0.5:51730
+tcp   ESTAB  0      0      10.0.0.12:ssh      198.51.100.7:40122
+"; "established")]
+    fn works(input: &str, expected: &str) {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7)), 40_122);
+
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(&input, &persona.network, &[], Some(peer), "root");
+
+        assert_eq!(output, expected);
+        assert_eq!(exit_code, 0);
+    }
+}
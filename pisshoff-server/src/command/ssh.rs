@@ -0,0 +1,515 @@
+//! Outbound `ssh`, which along with `scp` prompts for a password to the machine being logged in
+//! to before the connection times out, capturing where the client's headed next and the
+//! credentials they have for it.
+
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use pisshoff_types::audit::{AuditLogAction, LateralMovementEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "usage: ssh [-46AaCfGgKkMNnqsTtVvXxYy] [-B bind_interface] [-b bind_address]
+           [-c cipher_spec] [-D [bind_address:]port] [-E log_file]
+           [-e escape_char] [-F configfile] [-I pkcs11] [-i identity_file]
+           [-J destination] [-L address] [-l login_name] [-m mac_spec]
+           [-O ctl_cmd] [-o option] [-P tag] [-p port] [-R address]
+           [-S ctl_path] [-W host:port] [-w local_tun[:remote_tun]]
+           destination [command [argument ...]]\n";
+
+/// Short options `ssh` takes an argument to.
+const SSH_OPTIONS_WITH_ARGUMENTS: &str = "BbcDEeFIiJLlmOoPpRSWw";
+
+/// Short options `scp` takes an argument to.
+const SCP_OPTIONS_WITH_ARGUMENTS: &str = "cDFiJloPSX";
+
+#[derive(Debug, Clone)]
+pub struct Ssh(Login);
+
+#[async_trait]
+impl Command for Ssh {
+    const NAME: &'static str = "ssh";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let Some(login) = Login::ssh(connection, params) else {
+            session.data(channel, USAGE.to_string().into());
+            return CommandResult::Exit(255);
+        };
+
+        login.begin(connection, channel, session).map(Self)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        self.0.read(connection, channel, data, session).map(Self)
+    }
+}
+
+/// A login to another machine, as attempted by `ssh` and `scp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Login {
+    tool: &'static str,
+    host: String,
+    port: u16,
+    user: String,
+    command: Option<String>,
+    /// Whether the client's yet to accept the host key, which isn't asked if they've told us
+    /// not to check it.
+    verifying: bool,
+    /// Whether there's nobody to type a password, as with `-o BatchMode=yes`.
+    batch: bool,
+}
+
+impl Login {
+    /// Works out where `ssh` was asked to log in to, returning `None` if it wasn't given a
+    /// destination.
+    fn ssh(connection: &ConnectionState, params: &[String]) -> Option<Self> {
+        let mut this = Self {
+            tool: "ssh",
+            host: String::new(),
+            port: 22,
+            user: connection.username().to_string(),
+            command: None,
+            verifying: true,
+            batch: false,
+        };
+        let mut user = None;
+        let mut operands = Vec::new();
+        let mut params = params.iter();
+
+        while let Some(param) = params.next() {
+            if !operands.is_empty() {
+                // anything after the destination is the command to run there
+                operands.push(param.as_str());
+                continue;
+            }
+
+            let Some(options) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+                operands.push(param.as_str());
+                continue;
+            };
+
+            let Some((option, value)) =
+                option_value(options, SSH_OPTIONS_WITH_ARGUMENTS, &mut params)
+            else {
+                continue;
+            };
+
+            match option {
+                'l' => user = Some(value.to_string()),
+                'p' => this.port = value.parse().unwrap_or(this.port),
+                'o' => this.configure(value, &mut user),
+                _ => {}
+            }
+        }
+
+        let (destination, command) = operands.split_first()?;
+        let (destination, uri) = match destination.strip_prefix("ssh://") {
+            Some(destination) => (destination, true),
+            None => (*destination, false),
+        };
+        let (destination_user, host) = match destination.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, destination),
+        };
+
+        // only URIs can carry a port, otherwise the colon is part of an IPv6 address
+        let host = match host.rsplit_once(':') {
+            Some((host, port)) if uri => {
+                this.port = port.parse().unwrap_or(this.port);
+                host
+            }
+            _ => host,
+        };
+
+        this.host = host.to_string();
+        this.user = destination_user
+            .map(str::to_string)
+            .or(user)
+            .unwrap_or(this.user);
+        this.command = (!command.is_empty()).then(|| command.join(" "));
+
+        Some(this)
+    }
+
+    /// Works out where `scp` was asked to copy to or from, returning `None` if none of its
+    /// operands are on another machine, or it's being run on our end of someone else's `scp`.
+    pub fn scp(connection: &ConnectionState, params: &[String]) -> Option<Self> {
+        let sink_or_source = |v: &String| {
+            v.strip_prefix('-')
+                .map_or(false, |v| !v.starts_with('-') && v.contains(['t', 'f']))
+        };
+
+        if params.iter().any(sink_or_source) {
+            return None;
+        }
+
+        let mut this = Self {
+            tool: "scp",
+            host: String::new(),
+            port: 22,
+            user: connection.username().to_string(),
+            command: None,
+            verifying: true,
+            batch: false,
+        };
+        let mut user = None;
+        let mut operands = Vec::new();
+        let mut params = params.iter();
+
+        while let Some(param) = params.next() {
+            let Some(options) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+                operands.push(param.as_str());
+                continue;
+            };
+
+            let Some((option, value)) =
+                option_value(options, SCP_OPTIONS_WITH_ARGUMENTS, &mut params)
+            else {
+                continue;
+            };
+
+            match option {
+                'P' => this.port = value.parse().unwrap_or(this.port),
+                'o' => this.configure(value, &mut user),
+                _ => {}
+            }
+        }
+
+        let target = operands.pop()?;
+        let remote = |operand: &str| {
+            let (host, path) = operand.split_once(':')?;
+            (!host.is_empty() && !host.contains('/')).then_some((host.to_string(), path))
+        };
+
+        // copying to the other machine runs `scp -t` there, as we do here for our clients
+        let (destination, command) = if let Some((host, path)) = remote(target) {
+            (host, format!("scp -t {path}"))
+        } else {
+            let (host, path) = operands.iter().copied().find_map(remote)?;
+            (host, format!("scp -f {path}"))
+        };
+
+        let (destination_user, host) = match destination.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host.to_string()),
+            None => (None, destination),
+        };
+
+        this.host = host;
+        this.user = destination_user.or(user).unwrap_or(this.user);
+        this.command = Some(command);
+
+        Some(this)
+    }
+
+    /// Applies an `-o` option, as in `-o StrictHostKeyChecking=no`.
+    fn configure(&mut self, option: &str, user: &mut Option<String>) {
+        let (key, value) = option
+            .split_once(['=', ' '])
+            .map_or((option, ""), |(key, value)| (key, value.trim()));
+
+        match key.to_ascii_lowercase().as_str() {
+            "user" => *user = Some(value.to_string()),
+            "port" => self.port = value.parse().unwrap_or(self.port),
+            "stricthostkeychecking" => {
+                self.verifying = !matches!(
+                    value.to_ascii_lowercase().as_str(),
+                    "no" | "accept-new" | "off"
+                );
+            }
+            "batchmode" => self.batch = value.eq_ignore_ascii_case("yes"),
+            _ => {}
+        }
+    }
+
+    /// Host as it appears in `known_hosts`, which only includes the port if it isn't the usual
+    /// one.
+    fn known_host(&self) -> String {
+        if self.port == 22 {
+            self.host.clone()
+        } else {
+            format!("[{}]:{}", self.host, self.port)
+        }
+    }
+
+    /// Starts logging in, by asking the client to accept the host key if it needs to be
+    /// accepted or otherwise going straight to the password.
+    pub fn begin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        if self.batch {
+            session.data(
+                channel,
+                format!(
+                    "{}@{}: Permission denied (publickey,password).\n",
+                    self.user, self.host
+                )
+                .into(),
+            );
+            self.record(connection, Vec::new());
+            return CommandResult::Exit(self.failure_status());
+        }
+
+        if self.verifying {
+            // each machine keeps the same key for the length of the session
+            let mut key = [0; 32];
+            let rng = connection.rng(("ssh", &self.host));
+            key.fill_with(|| rng.u8(..));
+
+            session.data(
+                channel,
+                format!(
+                    "The authenticity of host '{host} ({ip})' can't be established.\nED25519 key \
+                     fingerprint is SHA256:{fingerprint}.\nThis key is not known by any other \
+                     names\nAre you sure you want to continue connecting \
+                     (yes/no/[fingerprint])? ",
+                    host = self.known_host(),
+                    ip = self.host,
+                    fingerprint = STANDARD_NO_PAD.encode(key),
+                )
+                .into(),
+            );
+        } else {
+            self.trust(channel, session);
+            self.prompt(connection, channel, session);
+        }
+
+        CommandResult::ReadStdin(self)
+    }
+
+    /// Reads the client's answer to whichever prompt they were last given.
+    pub fn read<S: ThrusshSession + Send>(
+        mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        if !self.verifying {
+            let (password, _) = super::read_password(connection, data, channel, session);
+            self.record(connection, vec![password.into()]);
+
+            let mut out = format!(
+                "Read from remote host {}: Connection timed out\n",
+                self.host
+            );
+            if self.tool == "scp" {
+                out.push_str("lost connection\n");
+            }
+
+            session.data(channel, out.into());
+            return CommandResult::Exit(self.failure_status());
+        }
+
+        let answer = String::from_utf8_lossy(data);
+        match answer.trim() {
+            "yes" => {
+                self.verifying = false;
+                self.trust(channel, session);
+                self.prompt(connection, channel, session);
+            }
+            "no" => {
+                session.data(
+                    channel,
+                    "Host key verification failed.\n".to_string().into(),
+                );
+                self.record(connection, Vec::new());
+                return CommandResult::Exit(self.failure_status());
+            }
+            _ => {
+                session.data(
+                    channel,
+                    "Please type 'yes', 'no' or the fingerprint: "
+                        .to_string()
+                        .into(),
+                );
+            }
+        }
+
+        CommandResult::ReadStdin(self)
+    }
+
+    fn trust<S: ThrusshSession + Send>(&self, channel: ChannelId, session: &mut S) {
+        session.data(
+            channel,
+            format!(
+                "Warning: Permanently added '{}' (ED25519) to the list of known hosts.\n",
+                self.known_host()
+            )
+            .into(),
+        );
+    }
+
+    fn prompt<S: ThrusshSession + Send>(
+        &self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) {
+        super::prompt_password(
+            connection,
+            &format!("{}@{}'s password: ", self.user, self.host),
+            channel,
+            session,
+        );
+    }
+
+    fn failure_status(&self) -> u32 {
+        if self.tool == "scp" {
+            1
+        } else {
+            255
+        }
+    }
+
+    fn record(&self, connection: &mut ConnectionState, passwords: Vec<Box<str>>) {
+        connection
+            .audit_log()
+            .push_action(AuditLogAction::LateralMovement(LateralMovementEvent {
+                tool: Cow::Borrowed(self.tool),
+                host: self.host.as_str().into(),
+                port: self.port,
+                username: self.user.as_str().into(),
+                passwords,
+                command: self.command.as_deref().map(Into::into),
+            }));
+    }
+}
+
+/// Reads the option at the start of a bundle of short `options` that takes an argument, along
+/// with its argument, which is either the rest of the bundle or the next parameter.
+fn option_value<'a>(
+    options: &'a str,
+    with_arguments: &str,
+    params: &mut impl Iterator<Item = &'a String>,
+) -> Option<(char, &'a str)> {
+    let (i, option) = options
+        .char_indices()
+        .find(|(_, c)| with_arguments.contains(*c))?;
+    let rest = &options[i + option.len_utf8()..];
+
+    if rest.is_empty() {
+        params.next().map(|v| (option, v.as_str()))
+    } else {
+        Some((option, rest))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            ssh::{Login, Ssh},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("admin@10.0.0.5", "10.0.0.5", 22, "admin", None; "user")]
+    #[test_case("-p 2222 -l pi 10.0.0.5 uname -a", "10.0.0.5", 2222, "pi", Some("uname -a"); "options")]
+    #[test_case("-o User=git -o Port=2200 gitlab", "gitlab", 2200, "git", None; "config")]
+    #[test_case("ssh://ubuntu@10.0.0.5:2022", "10.0.0.5", 2022, "ubuntu", None; "uri")]
+    #[test_case("10.0.0.5", "10.0.0.5", 22, "root", None; "current user")]
+    fn parses_ssh(input: &str, host: &str, port: u16, user: &str, command: Option<&str>) {
+        let state = ConnectionState::mock();
+        let input = shlex::split(input).unwrap();
+        let login = Login::ssh(&state, &input).unwrap();
+
+        assert_eq!(
+            (
+                login.host.as_str(),
+                login.port,
+                login.user.as_str(),
+                login.command.as_deref()
+            ),
+            (host, port, user, command)
+        );
+    }
+
+    #[test_case("-P 2222 xmrig admin@10.0.0.5:/tmp/", Some(("10.0.0.5", 2222, "admin", "scp -t /tmp/")); "upload")]
+    #[test_case("-r 10.0.0.5:.ssh .", Some(("10.0.0.5", 22, "root", "scp -f .ssh")); "download")]
+    #[test_case("a b", None; "local")]
+    #[test_case("-t /tmp", None; "sink")]
+    fn parses_scp(input: &str, expected: Option<(&str, u16, &str, &str)>) {
+        let state = ConnectionState::mock();
+        let input = shlex::split(input).unwrap();
+        let login = Login::scp(&state, &input);
+
+        assert_eq!(
+            login.as_ref().map(|v| (
+                v.host.as_str(),
+                v.port,
+                v.user.as_str(),
+                v.command.as_deref().unwrap_or_default()
+            )),
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn records_password() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        for expected in [
+            "Warning: Permanently added '10.0.0.5' (ED25519) to the list of known hosts.\n",
+            "admin@10.0.0.5's password: ",
+            "Read from remote host 10.0.0.5: Connection timed out\n",
+        ] {
+            session
+                .expect_data()
+                .once()
+                .with(always(), eq_string(expected))
+                .returning(|_, _| ());
+        }
+
+        let params = shlex::split("-o StrictHostKeyChecking=no admin@10.0.0.5").unwrap();
+        let out = Ssh::new(&mut state, &params, fake_channel_id(), &mut session).await;
+        let CommandResult::ReadStdin(out) = out else {
+            panic!("expected a password prompt, got {out:?}");
+        };
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), b"hunter2\n", &mut session)
+            .await;
+        assert!(matches!(out, CommandResult::Exit(255)), "{out:?}");
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::LateralMovement(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.tool, "ssh");
+        assert_eq!(&*event.username, "admin");
+        assert_eq!(event.passwords, vec![Box::<str>::from("hunter2")]);
+    }
+}
@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, PrivilegeEscalationEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult, RunAs},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub enum Su {
+    /// Waiting on the target user's password.
+    Password(Options),
+    Running(RunAs),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    user: String,
+    /// Whether to start a login shell, moving to the user's home directory.
+    login: bool,
+    /// Command to run as the user with `-c`, rather than switching to them.
+    command: Option<String>,
+}
+
+#[async_trait]
+impl Command for Su {
+    const NAME: &'static str = "su";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let options = match parse(params) {
+            Ok(options) => options,
+            Err(e) => {
+                session.data(channel, e.into());
+                return CommandResult::Exit(1);
+            }
+        };
+
+        // root can become anybody without being asked
+        if connection.username() == "root" {
+            return run(options, &[], connection, channel, session).await;
+        }
+
+        if connection.tty().is_none() {
+            session.data(
+                channel,
+                "su: must be run from a terminal\n".to_string().into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        super::prompt_password(connection, "Password: ", channel, session);
+        CommandResult::ReadStdin(Self::Password(options))
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        match self {
+            Self::Password(options) => {
+                let (password, rest) = super::read_password(connection, data, channel, session);
+
+                let event = PrivilegeEscalationEvent {
+                    tool: Cow::Borrowed(Self::NAME),
+                    username: connection.username().into(),
+                    target: options.user.as_str().into(),
+                    password: password.into_boxed_str(),
+                };
+                connection
+                    .audit_log()
+                    .push_action(AuditLogAction::PrivilegeEscalation(event));
+
+                run(options, rest, connection, channel, session).await
+            }
+            Self::Running(command) => command
+                .stdin(connection, channel, data, session)
+                .await
+                .map(Self::Running),
+        }
+    }
+}
+
+/// Switches to the user, or runs their command, once they've been authenticated.
+async fn run<S: ThrusshSession + Send>(
+    options: Options,
+    input: &[u8],
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<Su> {
+    let Some(command) = options.command else {
+        connection.switch_user(&options.user, options.login);
+        return CommandResult::Exit(0);
+    };
+
+    let Some(command) = shlex::split(&command) else {
+        session.data(
+            channel,
+            "bash: -c: line 1: unexpected EOF while looking for matching quote\n"
+                .to_string()
+                .into(),
+        );
+        return CommandResult::Exit(2);
+    };
+
+    RunAs::start(options.user, &command, input, connection, channel, session)
+        .await
+        .map(Su::Running)
+}
+
+fn parse(params: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut user = None;
+    let mut params = params.iter();
+
+    while let Some(param) = params.next() {
+        if let Some(command) = param.strip_prefix("--command=") {
+            options.command = Some(command.to_string());
+            continue;
+        }
+
+        match param.as_str() {
+            "-" | "-l" | "--login" => options.login = true,
+            "-m" | "-p" | "--preserve-environment" => {}
+            "-c" | "--command" => {
+                options.command = Some(params.next().cloned().ok_or_else(|| {
+                    "su: option requires an argument -- 'c'\nTry 'su --help' for more information.\n"
+                        .to_string()
+                })?);
+            }
+            "-s" | "--shell" => {
+                params.next();
+            }
+            v if v.starts_with("--") => {
+                return Err(format!(
+                    "su: unrecognized option '{v}'\nTry 'su --help' for more information.\n"
+                ));
+            }
+            v if v.starts_with('-') => {
+                return Err(format!(
+                    "su: invalid option -- '{}'\nTry 'su --help' for more information.\n",
+                    v.chars().nth(1).unwrap_or('-')
+                ));
+            }
+            v => {
+                // anything after the user is passed on to the shell, which we don't have
+                user.get_or_insert(v);
+            }
+        }
+    }
+
+    options.user = user.unwrap_or("root").to_string();
+    Ok(options)
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            su::{parse, Options, Su},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("", "root", false, None; "no arguments")]
+    #[test_case("-", "root", true, None; "login")]
+    #[test_case("- admin", "admin", true, None; "login as user")]
+    #[test_case("-c 'cat /etc/shadow' postgres", "postgres", false, Some("cat /etc/shadow"); "command")]
+    fn parses(input: &str, user: &str, login: bool, command: Option<&str>) {
+        let input = shlex::split(input).unwrap();
+
+        assert_eq!(
+            parse(&input).unwrap(),
+            Options {
+                user: user.to_string(),
+                login,
+                command: command.map(ToString::to_string),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn switches_user() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.switch_user("ubuntu", false);
+        state.set_tty("pts/0");
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("Password: "))
+            .returning(|_, _| ());
+
+        let out = Su::new(
+            &mut state,
+            ["-".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+        assert!(state.hides_input());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("\n"))
+            .returning(|_, _| ());
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), b"toor\n", &mut session)
+            .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        assert!(!state.hides_input());
+        assert_eq!(state.username(), "root");
+        assert_eq!(state.file_system().pwd().to_str(), Some("/root"));
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PrivilegeEscalation(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.tool, "su");
+        assert_eq!(&*event.username, "ubuntu");
+        assert_eq!(&*event.password, "toor");
+    }
+}
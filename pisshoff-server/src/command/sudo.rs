@@ -0,0 +1,331 @@
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use pisshoff_types::audit::{AuditLogAction, PrivilegeEscalationEvent};
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult, RunAs},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const USAGE: &str = "usage: sudo -h | -K | -k | -V
+usage: sudo -v [-ABknS] [-g group] [-h host] [-p prompt] [-u user]
+usage: sudo -l [-ABknS] [-g group] [-h host] [-p prompt] [-U user] [-u user] [command]
+usage: sudo [-ABbEHknPS] [-C num] [-D directory] [-g group] [-h host] [-p prompt] [-R directory] [-T timeout] [-u user] [VAR=value] [-i|-s] [<command>]
+usage: sudo -e [-ABknS] [-C num] [-D directory] [-g group] [-h host] [-p prompt] [-R directory] [-T timeout] [-u user] file ...
+";
+
+/// Short options that take a value, either attached or as the following parameter.
+const SHORT_WITH_VALUE: &[char] = &['u', 'g', 'p', 'C', 'D', 'R', 'T', 'U'];
+
+#[derive(Debug, Clone)]
+pub enum Sudo {
+    /// Waiting on the client's password before acting as `user`.
+    Password {
+        user: String,
+        action: Action,
+    },
+    Running(RunAs),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Start a shell as the user, `login` being set for `-i` rather than `-s`.
+    Shell {
+        login: bool,
+    },
+    Command(Vec<String>),
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options<'a> {
+    user: Option<&'a str>,
+    prompt: Option<&'a str>,
+    /// Read the password from stdin, rather than requiring a terminal.
+    stdin: bool,
+    non_interactive: bool,
+    shell: Option<bool>,
+    command: &'a [String],
+}
+
+#[async_trait]
+impl Command for Sudo {
+    const NAME: &'static str = "sudo";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let options = match parse(params) {
+            Ok(options) => options,
+            Err(e) => {
+                session.data(channel, e.into());
+                return CommandResult::Exit(1);
+            }
+        };
+
+        // variable assignments before the command are passed through to its environment
+        let command = options
+            .command
+            .iter()
+            .position(|v| !v.contains('='))
+            .map_or(&[][..], |i| &options.command[i..]);
+
+        let action = match (options.shell, command.is_empty()) {
+            (Some(login), true) => Action::Shell { login },
+            (_, false) => Action::Command(command.to_vec()),
+            (None, true) => {
+                session.data(channel, USAGE.to_string().into());
+                return CommandResult::Exit(1);
+            }
+        };
+
+        let user = options.user.unwrap_or("root").to_string();
+
+        // root never needs to give a password
+        if connection.username() == "root" {
+            return run(user, action, &[], connection, channel, session).await;
+        }
+
+        if options.non_interactive {
+            session.data(channel, "sudo: a password is required\n".to_string().into());
+            return CommandResult::Exit(1);
+        }
+
+        if connection.tty().is_none() && !options.stdin {
+            session.data(
+                channel,
+                "sudo: a terminal is required to read the password; either use the -S option to read from standard input or configure an askpass helper\nsudo: a password is required\n"
+                    .to_string()
+                    .into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        let prompt = options.prompt.map_or_else(
+            || format!("[sudo] password for {}: ", connection.username()),
+            // sudo expands `%p` and `%u`, which are the same thing for us
+            |v| {
+                v.replace("%p", connection.username())
+                    .replace("%u", connection.username())
+            },
+        );
+        super::prompt_password(connection, &prompt, channel, session);
+
+        CommandResult::ReadStdin(Self::Password { user, action })
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        match self {
+            Self::Password { user, action } => {
+                let (password, rest) = super::read_password(connection, data, channel, session);
+
+                let event = PrivilegeEscalationEvent {
+                    tool: Cow::Borrowed(Self::NAME),
+                    username: connection.username().into(),
+                    target: user.as_str().into(),
+                    password: password.into_boxed_str(),
+                };
+                connection
+                    .audit_log()
+                    .push_action(AuditLogAction::PrivilegeEscalation(event));
+
+                run(user, action, rest, connection, channel, session).await
+            }
+            Self::Running(command) => command
+                .stdin(connection, channel, data, session)
+                .await
+                .map(Self::Running),
+        }
+    }
+}
+
+/// Carries out `action` as `user` once they've been authenticated.
+async fn run<S: ThrusshSession + Send>(
+    user: String,
+    action: Action,
+    input: &[u8],
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<Sudo> {
+    match action {
+        Action::Shell { login } => {
+            connection.switch_user(&user, login);
+            CommandResult::Exit(0)
+        }
+        Action::Command(command) => {
+            RunAs::start(user, &command, input, connection, channel, session)
+                .await
+                .map(Sudo::Running)
+        }
+    }
+}
+
+/// Parses sudo's options, which end at the first operand since everything after is the command
+/// to run.
+fn parse(params: &[String]) -> Result<Options<'_>, String> {
+    let mut options = Options::default();
+    let mut i = 0;
+
+    while let Some(param) = params.get(i) {
+        i += 1;
+
+        if param == "--" {
+            break;
+        }
+
+        let Some(flags) = param.strip_prefix('-').filter(|v| !v.is_empty()) else {
+            i -= 1;
+            break;
+        };
+
+        for (j, flag) in flags.char_indices() {
+            if SHORT_WITH_VALUE.contains(&flag) {
+                let rest = &flags[j + flag.len_utf8()..];
+                let value = if rest.is_empty() {
+                    i += 1;
+                    params.get(i - 1).map(String::as_str).ok_or_else(|| {
+                        format!("sudo: option requires an argument -- '{flag}'\n{USAGE}")
+                    })?
+                } else {
+                    rest
+                };
+
+                match flag {
+                    'u' => options.user = Some(value),
+                    'p' => options.prompt = Some(value),
+                    _ => {}
+                }
+
+                break;
+            }
+
+            match flag {
+                'S' => options.stdin = true,
+                'n' => options.non_interactive = true,
+                'i' => options.shell = Some(true),
+                's' => options.shell = Some(false),
+                'E' | 'H' | 'k' | 'A' | 'b' | 'P' => {}
+                c => return Err(format!("sudo: invalid option -- '{c}'\n{USAGE}")),
+            }
+        }
+    }
+
+    options.command = &params[i..];
+    Ok(options)
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::predicate::always;
+    use pisshoff_types::audit::AuditLogAction;
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            sudo::{parse, Sudo},
+            Command, CommandResult,
+        },
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[test_case("whoami", None, false, None, &["whoami"]; "command")]
+    #[test_case("-S -p '' cat /etc/shadow", None, true, None, &["cat", "/etc/shadow"]; "stdin with prompt")]
+    #[test_case("-uwww-data -- id -u", Some("www-data"), false, None, &["id", "-u"]; "attached user")]
+    #[test_case("-i", None, false, Some(true), &[]; "login shell")]
+    #[test_case("-u admin -s", Some("admin"), false, Some(false), &[]; "shell as user")]
+    fn parses(input: &str, user: Option<&str>, stdin: bool, shell: Option<bool>, command: &[&str]) {
+        let input = shlex::split(input).unwrap();
+        let options = parse(&input).unwrap();
+
+        assert_eq!(options.user, user);
+        assert_eq!(options.stdin, stdin);
+        assert_eq!(options.shell, shell);
+        assert_eq!(options.command, command);
+    }
+
+    #[tokio::test]
+    async fn captures_password() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.switch_user("ubuntu", false);
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("[sudo] password for ubuntu: "))
+            .returning(|_, _| ());
+
+        let out = Sudo::new(
+            &mut state,
+            ["-S".to_string(), "whoami".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("root\n"))
+            .returning(|_, _| ());
+
+        let out = out
+            .stdin(&mut state, fake_channel_id(), b"hunter2\n", &mut session)
+            .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        // the user is only switched for the duration of the command
+        assert_eq!(state.username(), "ubuntu");
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PrivilegeEscalation(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.username, "ubuntu");
+        assert_eq!(&*event.target, "root");
+        assert_eq!(&*event.password, "hunter2");
+    }
+
+    #[tokio::test]
+    async fn requires_terminal() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state.switch_user("ubuntu", false);
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), always())
+            .returning(|_, _| ());
+
+        let out = Sudo::new(
+            &mut state,
+            ["whoami".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+}
@@ -0,0 +1,962 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pisshoff_types::audit::WriteFileEvent;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    audit::sha256_hex,
+    command::{Command, CommandResult},
+    persistence::{UNIT_DIRECTORIES, USER_UNIT_DIRECTORY},
+    persona::Persona,
+    server::{ConnectionState, ThrusshSession},
+};
+
+pub const NOT_BOOTED: &str =
+    "System has not been booted with systemd as init system (PID 1). Can't operate.
+Failed to connect to bus: Host is down
+";
+
+const NO_INSTALL: &str =
+    "The unit files have no installation config (WantedBy=, RequiredBy=, Also=,
+Alias= settings in the [Install] section, and DefaultInstance= for template
+units). This means they are not meant to be enabled using systemctl.
+";
+
+/// Suffixes of the unit types `systemctl` recognises without assuming a `.service`.
+const UNIT_SUFFIXES: &[&str] = &[
+    ".service", ".timer", ".socket", ".path", ".target", ".mount", ".slice",
+];
+
+/// Where units written by the administrator go, and where `enable` installs them.
+const SYSTEM_UNIT_DIRECTORY: &str = "/etc/systemd/system";
+
+/// `systemctl`, backed by the unit files in the virtual file system along with a unit for each
+/// of the persona's daemons. Whether a unit's been started is kept in the file system too, under
+/// `/run`, so it's consistent for the rest of the session.
+#[derive(Debug, Clone)]
+pub struct Systemctl {
+    /// Unit file or drop-in being written by `systemctl edit`.
+    path: PathBuf,
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    user: bool,
+    now: bool,
+    full: bool,
+    force: bool,
+    quiet: bool,
+    operation: Option<String>,
+    units: Vec<String>,
+}
+
+/// A unit `systemctl` knows about.
+#[derive(Debug)]
+struct Unit {
+    name: String,
+    /// The unit file, which units for the persona's daemons don't have in the file system.
+    path: Option<PathBuf>,
+    content: String,
+    /// Drop-ins extending the unit, along with their content.
+    drop_ins: Vec<(PathBuf, String)>,
+    /// Whether the unit is for one of the persona's daemons, which are enabled and were started
+    /// at boot.
+    daemon: bool,
+}
+
+impl Unit {
+    /// Every line of the unit file and its drop-ins, in the order systemd reads them.
+    fn lines(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.content.as_str())
+            .chain(self.drop_ins.iter().map(|(_, v)| v.as_str()))
+            .flat_map(str::lines)
+    }
+
+    /// Looks up the last value for `key` in the unit.
+    fn directive(&self, key: &str) -> Option<&str> {
+        self.lines()
+            .filter_map(|v| v.split_once('='))
+            .filter(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim())
+            .last()
+    }
+
+    fn description(&self) -> &str {
+        self.directive("Description").unwrap_or_else(|| {
+            self.name
+                .rsplit_once('.')
+                .map_or(self.name.as_str(), |(v, _)| v)
+        })
+    }
+
+    fn wanted_by(&self) -> Vec<&str> {
+        self.lines()
+            .filter_map(|v| v.split_once('='))
+            .filter(|(k, _)| matches!(k.trim(), "WantedBy" | "RequiredBy"))
+            .flat_map(|(_, v)| v.split_whitespace())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Command for Systemctl {
+    const NAME: &'static str = "systemctl";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let options = parse(params);
+
+        if options.operation.as_deref() != Some("edit") {
+            let (out, exit_code) = execute(connection, &options);
+            session.data(channel, out.into());
+            return CommandResult::Exit(exit_code);
+        }
+
+        if !booted(&connection.config().persona) {
+            session.data(channel, NOT_BOOTED.to_string().into());
+            return CommandResult::Exit(1);
+        }
+
+        let daemons = daemons(&connection.config().persona);
+
+        // there's no editor to speak of, so whatever's sent next is taken as the result of
+        // editing the unit
+        match edit(connection, &options, &daemons) {
+            Ok(path) => CommandResult::ReadStdin(Self { path }),
+            Err(e) => {
+                session.data(channel, e.into());
+                CommandResult::Exit(1)
+            }
+        }
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        connection.record_write(WriteFileEvent {
+            path: self.path.to_string_lossy().into_owned().into_boxed_str(),
+            sha256: sha256_hex(data),
+            size: data.len(),
+            original_name: None,
+            mode: None,
+            content: Bytes::copy_from_slice(data),
+        });
+
+        let file_system = connection.file_system();
+        let res = file_system
+            .mkdirall(self.path.parent().unwrap_or(Path::new("/")))
+            .and_then(|()| file_system.write(&self.path, data.into()));
+
+        if let Err(e) = res {
+            session.data(
+                channel,
+                format!("Failed to edit {}: {e}\n", self.path.display()).into(),
+            );
+            return CommandResult::Exit(1);
+        }
+
+        CommandResult::Exit(0)
+    }
+}
+
+pub fn parse(params: &[String]) -> Options {
+    let mut options = Options::default();
+
+    for param in params {
+        match param.as_str() {
+            "--user" => options.user = true,
+            "--system" => options.user = false,
+            "--now" => options.now = true,
+            "--full" => options.full = true,
+            "-f" | "--force" => options.force = true,
+            "-q" | "--quiet" => options.quiet = true,
+            v if v.starts_with('-') => {}
+            v if options.operation.is_none() => options.operation = Some(v.to_string()),
+            v => options.units.push(normalise(v)),
+        }
+    }
+
+    options
+}
+
+/// Runs everything but `edit`, which needs to read the new unit from stdin.
+pub fn execute(connection: &mut ConnectionState, options: &Options) -> (String, u32) {
+    let persona = &connection.config().persona;
+
+    if !booted(persona) {
+        return (NOT_BOOTED.to_string(), 1);
+    }
+
+    let daemons = daemons(persona);
+    run(connection, options, &daemons)
+}
+
+/// systemd's only running if the persona's process table has its journal daemon.
+pub fn booted(persona: &Persona) -> bool {
+    persona
+        .daemons
+        .iter()
+        .any(|v| v.command.contains("systemd-journald"))
+}
+
+/// Works out a unit for each of the persona's daemons, named after the program.
+pub fn daemons(persona: &Persona) -> Vec<String> {
+    let mut units = Vec::new();
+
+    for daemon in persona.daemons.iter().skip(1) {
+        let Some(program) = daemon
+            .command
+            .split_whitespace()
+            .next()
+            .filter(|v| !v.starts_with('['))
+        else {
+            continue;
+        };
+
+        let program = program.trim_start_matches('@').trim_end_matches(':');
+        let unit = normalise(program.rsplit('/').next().unwrap_or(program));
+
+        if !units.contains(&unit) {
+            units.push(unit);
+        }
+    }
+
+    units
+}
+
+/// Turns a unit given on the command line into its full name, as systemd assumes a `.service`
+/// if there's no suffix and takes the name of the file if given a path.
+fn normalise(unit: &str) -> String {
+    let unit = unit.rsplit('/').next().unwrap_or(unit);
+
+    if UNIT_SUFFIXES.iter().any(|v| unit.ends_with(v)) {
+        unit.to_string()
+    } else {
+        format!("{unit}.service")
+    }
+}
+
+fn run(connection: &mut ConnectionState, options: &Options, daemons: &[String]) -> (String, u32) {
+    let operation = options.operation.as_deref().unwrap_or("list-units");
+
+    match operation {
+        "list-units" => (list_units(connection, options, daemons), 0),
+        "daemon-reload" | "daemon-reexec" if !authorised(connection, options) => (
+            "Failed to reload daemon: Interactive authentication required.\n".to_string(),
+            1,
+        ),
+        "daemon-reload" | "daemon-reexec" => (String::new(), 0),
+        "start" | "stop" | "restart" | "reload" | "try-restart" | "reload-or-restart" | "kill" => {
+            control(connection, options, daemons, operation)
+        }
+        "enable" | "reenable" => enable(connection, options, daemons, true),
+        "disable" => enable(connection, options, daemons, false),
+        "status" => status(connection, options, daemons),
+        "is-active" => {
+            let mut out = String::new();
+            let mut status = 0;
+
+            for name in &options.units {
+                let active = find(connection, name, options.user, daemons)
+                    .and_then(|unit| active_since(connection, &unit, options.user))
+                    .is_some();
+
+                if !active {
+                    status = 3;
+                }
+
+                if !options.quiet {
+                    out.push_str(if active { "active\n" } else { "inactive\n" });
+                }
+            }
+
+            (out, status)
+        }
+        "is-enabled" => {
+            let mut out = String::new();
+            let mut status = 0;
+
+            for name in &options.units {
+                let Some(unit) = find(connection, name, options.user, daemons) else {
+                    writeln!(
+                        out,
+                        "Failed to get unit file state for {name}: No such file or directory"
+                    )
+                    .unwrap();
+                    status = 1;
+                    continue;
+                };
+
+                let state = enabled(connection, &unit, options.user);
+                if state != "enabled" {
+                    status = 1;
+                }
+
+                if !options.quiet {
+                    writeln!(out, "{state}").unwrap();
+                }
+            }
+
+            (out, status)
+        }
+        "cat" => cat(connection, options, daemons),
+        other => (format!("Unknown command verb {other}.\n"), 1),
+    }
+}
+
+/// Whether the user can manage the units they're asking about, which is always the case for
+/// their own instance. Anything else would need polkit to ask for a password, which it can't
+/// without an agent.
+fn authorised(connection: &ConnectionState, options: &Options) -> bool {
+    options.user || connection.username() == "root"
+}
+
+/// Directories units are loaded from, in order of precedence.
+fn unit_directories(connection: &mut ConnectionState, user: bool) -> Vec<PathBuf> {
+    if user {
+        vec![connection.file_system().home().join(USER_UNIT_DIRECTORY)]
+    } else {
+        UNIT_DIRECTORIES.iter().map(PathBuf::from).collect()
+    }
+}
+
+fn find(
+    connection: &mut ConnectionState,
+    name: &str,
+    user: bool,
+    daemons: &[String],
+) -> Option<Unit> {
+    let directories = unit_directories(connection, user);
+    let file_system = connection.file_system();
+
+    let (path, content) = directories
+        .iter()
+        .find_map(|v| {
+            let file = v.join(name);
+            let data = file_system.read(&file).ok()?;
+            Some((Some(file), String::from_utf8_lossy(data).into_owned()))
+        })
+        .unwrap_or_default();
+
+    let mut drop_ins = Vec::new();
+
+    for directory in &directories {
+        let directory = directory.join(format!("{name}.d"));
+        let Ok(files) = file_system.ls(Some(&directory)) else {
+            continue;
+        };
+
+        for file in files.iter().filter(|v| v.ends_with(".conf")) {
+            let file = directory.join(file);
+
+            if let Ok(data) = file_system.read(&file) {
+                drop_ins.push((file, String::from_utf8_lossy(data).into_owned()));
+            }
+        }
+    }
+
+    let daemon = !user && daemons.iter().any(|v| v == name);
+
+    if path.is_none() && drop_ins.is_empty() && !daemon {
+        return None;
+    }
+
+    Some(Unit {
+        name: name.to_string(),
+        path,
+        content,
+        drop_ins,
+        daemon,
+    })
+}
+
+/// File recording when the unit was started in this session, or that it's since been stopped.
+fn state_path(connection: &ConnectionState, unit: &str, user: bool) -> PathBuf {
+    let runtime = if user {
+        let uid = connection
+            .config()
+            .persona
+            .identity(connection.username())
+            .uid;
+        PathBuf::from(format!("/run/user/{uid}"))
+    } else {
+        PathBuf::from("/run")
+    };
+
+    runtime
+        .join("systemd/units")
+        .join(format!("invocation:{unit}"))
+}
+
+/// When the unit was started, if it's running.
+fn active_since(
+    connection: &mut ConnectionState,
+    unit: &Unit,
+    user: bool,
+) -> Option<OffsetDateTime> {
+    let path = state_path(connection, &unit.name, user);
+
+    match connection.file_system().read(&path) {
+        Ok(state) => std::str::from_utf8(state)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .and_then(|v| OffsetDateTime::from_unix_timestamp(v).ok()),
+        Err(_) if unit.daemon => Some(connection.config().persona.boot_time()),
+        Err(_) => None,
+    }
+}
+
+fn set_active(connection: &mut ConnectionState, unit: &str, user: bool, active: bool) {
+    let path = state_path(connection, unit, user);
+    let state = if active {
+        OffsetDateTime::now_utc().unix_timestamp().to_string()
+    } else {
+        "inactive".to_string()
+    };
+
+    let file_system = connection.file_system();
+    let _res = file_system
+        .mkdirall(path.parent().unwrap_or(Path::new("/")))
+        .and_then(|()| file_system.write(&path, state.into_bytes().into()));
+}
+
+/// Directory `enable` installs units into, which is where the wants of each target live.
+fn install_directory(connection: &mut ConnectionState, user: bool) -> PathBuf {
+    if user {
+        connection.file_system().home().join(USER_UNIT_DIRECTORY)
+    } else {
+        PathBuf::from(SYSTEM_UNIT_DIRECTORY)
+    }
+}
+
+fn enabled(connection: &mut ConnectionState, unit: &Unit, user: bool) -> &'static str {
+    let install = install_directory(connection, user);
+    let wanted_by = unit.wanted_by();
+
+    if unit.daemon
+        || wanted_by.iter().any(|target| {
+            connection
+                .file_system()
+                .read(&install.join(format!("{target}.wants")).join(&unit.name))
+                .is_ok()
+        })
+    {
+        "enabled"
+    } else if wanted_by.is_empty() {
+        "static"
+    } else {
+        "disabled"
+    }
+}
+
+fn control(
+    connection: &mut ConnectionState,
+    options: &Options,
+    daemons: &[String],
+    operation: &str,
+) -> (String, u32) {
+    if options.units.is_empty() {
+        return ("Too few arguments.\n".to_string(), 1);
+    }
+
+    let mut out = String::new();
+    let mut status = 0;
+
+    for name in &options.units {
+        if !authorised(connection, options) {
+            writeln!(
+                out,
+                "Failed to {operation} {name}: Interactive authentication required.\nSee system logs and 'systemctl status {name}' for details."
+            )
+            .unwrap();
+            status = 1;
+            continue;
+        }
+
+        let Some(unit) = find(connection, name, options.user, daemons) else {
+            writeln!(out, "Failed to {operation} {name}: Unit {name} not found.").unwrap();
+            status = 5;
+            continue;
+        };
+
+        match operation {
+            "start" | "restart" | "reload-or-restart" => {
+                set_active(connection, name, options.user, true);
+            }
+            "try-restart" if active_since(connection, &unit, options.user).is_some() => {
+                set_active(connection, name, options.user, true);
+            }
+            "stop" | "kill" => set_active(connection, name, options.user, false),
+            _ => {}
+        }
+    }
+
+    (out, status)
+}
+
+fn enable(
+    connection: &mut ConnectionState,
+    options: &Options,
+    daemons: &[String],
+    enable: bool,
+) -> (String, u32) {
+    let verb = if enable { "enable" } else { "disable" };
+
+    if options.units.is_empty() {
+        return ("Too few arguments.\n".to_string(), 1);
+    }
+
+    if !authorised(connection, options) {
+        return (
+            format!("Failed to {verb} unit: Interactive authentication required.\n"),
+            1,
+        );
+    }
+
+    let install = install_directory(connection, options.user);
+    let mut out = String::new();
+
+    for name in &options.units {
+        let Some(unit) = find(connection, name, options.user, daemons) else {
+            return (
+                format!("Failed to {verb} unit: Unit file {name} does not exist.\n"),
+                1,
+            );
+        };
+
+        let wanted_by = unit.wanted_by();
+
+        if enable && wanted_by.is_empty() && !unit.daemon {
+            out.push_str(NO_INSTALL);
+        }
+
+        // symlinks are copies, since that's all the file system can do
+        for target in wanted_by {
+            let wants = install.join(format!("{target}.wants"));
+            let link = wants.join(name);
+            let file_system = connection.file_system();
+
+            if enable {
+                let res = file_system
+                    .mkdirall(&wants)
+                    .and_then(|()| file_system.write(&link, unit.content.as_bytes().into()));
+
+                if res.is_ok() {
+                    let path = unit.path.as_deref().unwrap_or(&link);
+                    writeln!(
+                        out,
+                        "Created symlink {} → {}.",
+                        link.display(),
+                        path.display()
+                    )
+                    .unwrap();
+                }
+            } else if file_system.remove(&link).is_ok() {
+                writeln!(out, "Removed {}.", link.display()).unwrap();
+            }
+        }
+
+        if options.now {
+            set_active(connection, name, options.user, enable);
+        }
+    }
+
+    (out, 0)
+}
+
+fn status(
+    connection: &mut ConnectionState,
+    options: &Options,
+    daemons: &[String],
+) -> (String, u32) {
+    let mut out = String::new();
+    let mut status = 0;
+
+    for name in &options.units {
+        let Some(unit) = find(connection, name, options.user, daemons) else {
+            writeln!(out, "Unit {name} could not be found.").unwrap();
+            status = 4;
+            continue;
+        };
+
+        let since = active_since(connection, &unit, options.user);
+        let path = unit
+            .path
+            .clone()
+            .unwrap_or_else(|| Path::new("/lib/systemd/system").join(name));
+
+        writeln!(
+            out,
+            "{} {name} - {}",
+            if since.is_some() { '●' } else { '○' },
+            unit.description()
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "     Loaded: loaded ({}; {}; vendor preset: enabled)",
+            path.display(),
+            enabled(connection, &unit, options.user)
+        )
+        .unwrap();
+
+        let Some(since) = since else {
+            out.push_str("     Active: inactive (dead)\n");
+            status = 3;
+            continue;
+        };
+
+        let ago = (OffsetDateTime::now_utc() - since)
+            .whole_seconds()
+            .unsigned_abs();
+        let command = unit.directive("ExecStart").map_or_else(
+            || format!("/usr/sbin/{}", unit.description()),
+            str::to_string,
+        );
+        let program = command
+            .split_whitespace()
+            .next()
+            .and_then(|v| v.rsplit('/').next())
+            .unwrap_or_default();
+        let pid = connection.rng(("systemctl", name)).u32(400..30000);
+
+        writeln!(
+            out,
+            "     Active: active (running) since {} UTC; {} ago",
+            since
+                .format(format_description!(
+                    "[weekday repr:short] [year]-[month]-[day] [hour]:[minute]:[second]"
+                ))
+                .unwrap(),
+            elapsed(ago)
+        )
+        .unwrap();
+        writeln!(out, "   Main PID: {pid} ({program})").unwrap();
+        writeln!(out, "      Tasks: 1").unwrap();
+        writeln!(out, "     CGroup: /system.slice/{name}").unwrap();
+        writeln!(out, "             └─{pid} {command}").unwrap();
+    }
+
+    (out, status)
+}
+
+fn cat(connection: &mut ConnectionState, options: &Options, daemons: &[String]) -> (String, u32) {
+    let mut out = String::new();
+
+    for name in &options.units {
+        let Some(unit) = find(connection, name, options.user, daemons)
+            .filter(|v| v.path.is_some() || !v.drop_ins.is_empty())
+        else {
+            return (format!("No files found for {name}.\n"), 1);
+        };
+
+        let files = unit
+            .path
+            .iter()
+            .map(|v| (v, &unit.content))
+            .chain(unit.drop_ins.iter().map(|(path, content)| (path, content)));
+
+        for (path, content) in files {
+            writeln!(out, "# {}\n{content}", path.display()).unwrap();
+        }
+    }
+
+    (out, 0)
+}
+
+fn list_units(connection: &mut ConnectionState, options: &Options, daemons: &[String]) -> String {
+    let mut names = if options.user {
+        Vec::new()
+    } else {
+        daemons.to_vec()
+    };
+
+    for directory in unit_directories(connection, options.user) {
+        let Ok(files) = connection.file_system().ls(Some(&directory)) else {
+            continue;
+        };
+
+        names.extend(
+            files
+                .into_iter()
+                .filter(|v| UNIT_SUFFIXES.iter().any(|suffix| v.ends_with(suffix)))
+                .map(str::to_string),
+        );
+    }
+
+    names.sort();
+    names.dedup();
+
+    let mut units = Vec::new();
+
+    for name in &names {
+        let Some(unit) = find(connection, name, options.user, daemons) else {
+            continue;
+        };
+
+        if active_since(connection, &unit, options.user).is_some() {
+            units.push(unit);
+        }
+    }
+
+    let width = units.iter().map(|v| v.name.len()).max().unwrap_or(0).max(4) + 1;
+
+    let mut out = format!("  {:<width$}LOAD   ACTIVE SUB     DESCRIPTION\n", "UNIT");
+
+    for unit in &units {
+        writeln!(
+            out,
+            "  {:<width$}loaded active running {}",
+            unit.name,
+            unit.description()
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        out,
+        "
+LOAD   = Reflects whether the unit definition was properly loaded.
+ACTIVE = The high-level unit activation state, i.e. generalization of SUB.
+SUB    = The low-level unit activation state, values depend on unit type.
+{} loaded units listed.",
+        units.len()
+    )
+    .unwrap();
+
+    out
+}
+
+/// Writes out the unit's drop-in, or the unit itself with `--full`, to be written by `stdin`.
+fn edit(
+    connection: &mut ConnectionState,
+    options: &Options,
+    daemons: &[String],
+) -> Result<PathBuf, String> {
+    let [name] = options.units.as_slice() else {
+        return Err("Filenames not specified\n".to_string());
+    };
+
+    if !authorised(connection, options) {
+        return Err(format!(
+            "Failed to edit {name}: Interactive authentication required.\n"
+        ));
+    }
+
+    if !options.force && find(connection, name, options.user, daemons).is_none() {
+        return Err(format!(
+            "No files found for {name}.\nRun 'systemctl edit --force --full {name}' to create a new unit.\n"
+        ));
+    }
+
+    let install = install_directory(connection, options.user);
+
+    Ok(if options.full {
+        install.join(name)
+    } else {
+        install.join(format!("{name}.d")).join("override.conf")
+    })
+}
+
+/// Formats the time since a unit was started, as systemd does.
+fn elapsed(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{minutes}min {}s", seconds % 60),
+        3600..=86399 => format!("{hours}h {minutes}min"),
+        _ if days == 1 => format!("1 day {hours}h"),
+        _ => format!("{days} days"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, PersistenceMechanism};
+    use test_case::test_case;
+
+    use crate::{
+        command::{
+            systemctl::{daemons, elapsed, normalise, parse, run, Systemctl, NOT_BOOTED},
+            Command, CommandResult,
+        },
+        persona::Persona,
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    const UNIT: &str = "[Unit]\nDescription=Kernel worker\n\n[Service]\nExecStart=/usr/bin/.kworker -o pool:3333\n\n[Install]\nWantedBy=multi-user.target\n";
+
+    fn systemctl(state: &mut ConnectionState, input: &str) -> (String, u32) {
+        let input = shlex::split(input).unwrap();
+        run(state, &parse(&input), &["cron.service".to_string()])
+    }
+
+    #[test_case("sshd", "sshd.service"; "bare")]
+    #[test_case("kworker.timer", "kworker.timer"; "suffix")]
+    #[test_case("/etc/systemd/system/kworker.service", "kworker.service"; "path")]
+    fn normalises(input: &str, expected: &str) {
+        assert_eq!(normalise(input), expected);
+    }
+
+    #[test]
+    fn units_for_daemons() {
+        let persona = toml::from_str::<Persona>("preset = \"debian\"").unwrap();
+        let daemons = daemons(&persona);
+
+        assert!(daemons.contains(&"cron.service".to_string()), "{daemons:?}");
+        assert!(daemons.contains(&"sshd.service".to_string()), "{daemons:?}");
+        assert!(!daemons.iter().any(|v| v.starts_with('[')), "{daemons:?}");
+    }
+
+    #[test_case(45, "45s")]
+    #[test_case(125, "2min 5s")]
+    #[test_case(7260, "2h 1min")]
+    #[test_case(3_576_660, "41 days")]
+    fn formats_elapsed(seconds: u64, expected: &str) {
+        assert_eq!(elapsed(seconds), expected);
+    }
+
+    #[test]
+    fn enables_and_starts_written_unit() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .mkdirall(Path::new("/etc/systemd/system"))
+            .unwrap();
+        state
+            .file_system()
+            .write(
+                Path::new("/etc/systemd/system/kworker.service"),
+                UNIT.as_bytes().into(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            systemctl(&mut state, "is-enabled kworker"),
+            ("disabled\n".to_string(), 1)
+        );
+
+        let (out, code) = systemctl(&mut state, "enable --now kworker");
+        assert_eq!(code, 0, "{out}");
+        assert_eq!(out, "Created symlink /etc/systemd/system/multi-user.target.wants/kworker.service → /etc/systemd/system/kworker.service.\n");
+
+        assert_eq!(
+            systemctl(&mut state, "is-enabled kworker"),
+            ("enabled\n".to_string(), 0)
+        );
+        assert_eq!(
+            systemctl(&mut state, "is-active kworker"),
+            ("active\n".to_string(), 0)
+        );
+
+        let (out, code) = systemctl(&mut state, "status kworker.service");
+        assert_eq!(code, 0, "{out}");
+        assert!(out.starts_with("● kworker.service - Kernel worker\n     Loaded: loaded (/etc/systemd/system/kworker.service; enabled;"), "{out}");
+        assert!(out.ends_with(" /usr/bin/.kworker -o pool:3333\n"), "{out}");
+
+        let (out, code) = systemctl(&mut state, "list-units");
+        assert_eq!(code, 0, "{out}");
+        assert!(
+            out.contains("\n  kworker.service loaded active running Kernel worker\n"),
+            "{out}"
+        );
+
+        assert_eq!(systemctl(&mut state, "stop kworker"), (String::new(), 0));
+        assert_eq!(
+            systemctl(&mut state, "is-active kworker"),
+            ("inactive\n".to_string(), 3)
+        );
+    }
+
+    #[test_case("status nope", "Unit nope.service could not be found.\n", 4; "status")]
+    #[test_case("start nope", "Failed to start nope.service: Unit nope.service not found.\n", 5; "start")]
+    #[test_case("is-active cron", "active\n", 0; "daemon")]
+    #[test_case("frobnicate", "Unknown command verb frobnicate.\n", 1; "unknown verb")]
+    fn unknown_units(input: &str, expected: &str, exit_code: u32) {
+        let mut state = ConnectionState::mock();
+        assert_eq!(
+            systemctl(&mut state, input),
+            (expected.to_string(), exit_code)
+        );
+    }
+
+    #[tokio::test]
+    async fn not_booted() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string(NOT_BOOTED))
+            .returning(|_, _| ());
+
+        let out = Systemctl::new(
+            &mut state,
+            ["status".to_string(), "sshd".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn records_edited_unit() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        let out = Systemctl {
+            path: Path::new("/etc/systemd/system/cron.service.d/override.conf").to_path_buf(),
+        }
+        .stdin(
+            &mut state,
+            fake_channel_id(),
+            b"[Service]\nExecStartPost=/tmp/.x/run\n",
+            &mut session,
+        )
+        .await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let persistence = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PersistenceAttempt(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        let PersistenceMechanism::Systemd { unit, commands, .. } = &persistence.mechanism else {
+            panic!("expected systemd, got {:?}", persistence.mechanism);
+        };
+        assert_eq!(&**unit, "cron.service");
+        assert_eq!(commands, &[Box::from("/tmp/.x/run")]);
+        assert_eq!(
+            systemctl(&mut state, "cat cron").0,
+            "# /etc/systemd/system/cron.service.d/override.conf\n[Service]\nExecStartPost=/tmp/.x/run\n\n"
+        );
+    }
+}
@@ -0,0 +1,655 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pisshoff_types::audit::{AuditLogAction, FileOperation, FileOperationEvent, WriteFileEvent};
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    audit::sha256_hex,
+    command::{record_file_operation, Command, CommandResult},
+    file_system::Tree,
+    server::{ConnectionState, ThrusshSession},
+};
+
+const BLOCK: usize = 512;
+
+const NO_MODE: &str = "tar: You must specify one of the '-Acdtrux', '--delete' or '--test-label' \
+                       options\nTry 'tar --help' or 'tar --usage' for more information.\n";
+
+const NOT_AN_ARCHIVE: &str = "tar: This does not look like a tar archive\ntar: Exiting with \
+                              failure status due to previous errors\n";
+
+/// There's nothing to decompress gzip with, so compressed archives are treated as if they were
+/// cut short. The archive itself has already been recorded by whatever wrote it.
+const GZIP: &str = "\ngzip: stdin: unexpected end of file\ntar: Child returned status 1\ntar: \
+                    Error is not recoverable: exiting now\n";
+
+const LEADING_SLASH: &str = "tar: Removing leading `/' from member names\n";
+
+/// `tar`, which can create, list and extract uncompressed ustar archives within the virtual
+/// file system so payloads shipped as archives get recorded file by file.
+#[derive(Debug, Clone)]
+pub struct Tar {
+    options: Options,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Options {
+    /// One of `c`, `t` or `x`.
+    mode: Option<char>,
+    verbose: bool,
+    /// The archive to read from or write to, or stdin/stdout if unset or `-`.
+    file: Option<String>,
+    directory: Option<String>,
+    strip_components: usize,
+    members: Vec<String>,
+}
+
+impl Options {
+    fn archive(&self) -> Option<&str> {
+        self.file.as_deref().filter(|v| *v != "-")
+    }
+}
+
+/// A single file or directory read out of an archive.
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    name: String,
+    mode: u32,
+    mtime: i64,
+    /// Contents of the file, or `None` if the entry is a directory.
+    content: Option<Vec<u8>>,
+}
+
+#[async_trait]
+impl Command for Tar {
+    const NAME: &'static str = "tar";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let options = parse(params);
+
+        let (out, exit_code) = match options.mode {
+            Some('c') => create(connection, &options),
+            Some(_) if options.archive().is_none() => {
+                return CommandResult::ReadStdin(Self { options });
+            }
+            Some(_) => {
+                let archive = options.archive().unwrap_or_default();
+
+                match connection.file_system().read(Path::new(archive)) {
+                    Ok(data) => {
+                        let data = data.to_vec();
+                        extract(connection, &options, &data)
+                    }
+                    Err(e) => (
+                        format!(
+                            "tar: {archive}: Cannot open: {e}\ntar: Error is not recoverable: \
+                             exiting now\n"
+                        )
+                        .into_bytes(),
+                        2,
+                    ),
+                }
+            }
+            None => (NO_MODE.as_bytes().to_vec(), 2),
+        };
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = extract(connection, &self.options, data);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+}
+
+/// Parses both the traditional form of `tar xvf file.tar`, where the first argument is a bundle
+/// of options whose values follow it, and the usual dashed and long options.
+fn parse(params: &[String]) -> Options {
+    let mut options = Options::default();
+    let mut params = params.iter().enumerate();
+
+    while let Some((i, param)) = params.next() {
+        if let Some(long) = param.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+
+            match name {
+                "extract" | "get" => options.mode = Some('x'),
+                "create" => options.mode = Some('c'),
+                "list" => options.mode = Some('t'),
+                "verbose" => options.verbose = true,
+                "file" => options.file = value.or_else(|| params.next().map(|(_, v)| v.clone())),
+                "directory" => {
+                    options.directory = value.or_else(|| params.next().map(|(_, v)| v.clone()));
+                }
+                "strip-components" => {
+                    options.strip_components = value.and_then(|v| v.parse().ok()).unwrap_or(0);
+                }
+                _ => {}
+            }
+
+            continue;
+        }
+
+        let (flags, dashed) = match param.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => (flags, true),
+            _ if i == 0 => (param.as_str(), false),
+            _ => {
+                options.members.push(param.clone());
+                continue;
+            }
+        };
+
+        for (j, flag) in flags.char_indices() {
+            match flag {
+                'x' | 'c' | 't' => options.mode = Some(flag),
+                'v' => options.verbose = true,
+                'f' | 'C' => {
+                    // a dashed bundle can carry the value itself, as in `-xfpayload.tar`
+                    let rest = &flags[j + 1..];
+                    let value = if dashed && !rest.is_empty() {
+                        Some(rest.to_string())
+                    } else {
+                        params.next().map(|(_, v)| v.clone())
+                    };
+
+                    if flag == 'f' {
+                        options.file = value;
+                    } else {
+                        options.directory = value;
+                    }
+
+                    if dashed && !rest.is_empty() {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    options
+}
+
+/// Resolves `name` relative to the directory given by `-C`, if any.
+fn resolve(options: &Options, name: &str) -> PathBuf {
+    match &options.directory {
+        Some(directory) if !name.is_empty() => Path::new(directory).join(name),
+        Some(directory) => PathBuf::from(directory),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Records `operation` against the archive, which is `-` if it was read from stdin or written
+/// to stdout.
+fn record(connection: &mut ConnectionState, options: &Options, operation: FileOperation) {
+    match options.archive() {
+        Some(archive) => record_file_operation(connection, Path::new(archive), operation),
+        None => connection
+            .audit_log()
+            .push_action(AuditLogAction::FileOperation(FileOperationEvent {
+                path: "-".into(),
+                operation,
+            })),
+    }
+}
+
+fn extract(connection: &mut ConnectionState, options: &Options, data: &[u8]) -> (Vec<u8>, u32) {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        record(
+            connection,
+            options,
+            FileOperation::Extract { files: Vec::new() },
+        );
+        return (GZIP.as_bytes().to_vec(), 2);
+    }
+
+    let Some(entries) = read_archive(data) else {
+        record(
+            connection,
+            options,
+            FileOperation::Extract { files: Vec::new() },
+        );
+        return (NOT_AN_ARCHIVE.as_bytes().to_vec(), 2);
+    };
+
+    let mut out = String::new();
+
+    if entries.iter().any(|v| v.name.starts_with('/')) {
+        out.push_str(LEADING_SLASH);
+    }
+
+    if options.mode == Some('t') {
+        for entry in &entries {
+            list(&mut out, entry, options.verbose);
+        }
+
+        return (out.into_bytes(), 0);
+    }
+
+    let mut files = Vec::new();
+
+    for entry in entries {
+        let name = entry
+            .name
+            .trim_start_matches('/')
+            .split('/')
+            .skip(options.strip_components)
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if name.is_empty() {
+            continue;
+        }
+
+        if options.verbose {
+            writeln!(out, "{}", entry.name.trim_start_matches('/')).unwrap();
+        }
+
+        let path = resolve(options, &name);
+        let file_system = connection.file_system();
+
+        let Some(content) = entry.content else {
+            if file_system.mkdirall(&path).is_ok() {
+                let _res = file_system.set_mode(&path, entry.mode);
+            }
+
+            continue;
+        };
+
+        let canonical = file_system.canonicalize(&path);
+        let written = canonical
+            .parent()
+            .map_or(Ok(()), |v| file_system.mkdirall(v))
+            .and_then(|()| file_system.write(&path, content.clone().into()));
+
+        if let Err(e) = written {
+            writeln!(out, "tar: {name}: Cannot open: {e}").unwrap();
+            continue;
+        }
+
+        let _res = file_system.set_mode(&path, entry.mode);
+
+        connection.record_write(WriteFileEvent {
+            path: canonical.to_string_lossy().into(),
+            sha256: sha256_hex(&content),
+            size: content.len(),
+            original_name: None,
+            mode: Some(format!("{:04o}", entry.mode).into()),
+            content: Bytes::from(content),
+        });
+
+        files.push(canonical.to_string_lossy().into());
+    }
+
+    record(connection, options, FileOperation::Extract { files });
+
+    (out.into_bytes(), 0)
+}
+
+/// Writes out `entry` as `tar -t` would, with its permissions, owner, size and modification
+/// time if `verbose`.
+fn list(out: &mut String, entry: &Entry, verbose: bool) {
+    let name = entry.name.trim_start_matches('/');
+
+    if !verbose {
+        writeln!(out, "{name}").unwrap();
+        return;
+    }
+
+    let mut permissions = String::from(if entry.content.is_some() { "-" } else { "d" });
+    for (i, c) in "rwxrwxrwx".chars().enumerate() {
+        permissions.push(if entry.mode & (0o400 >> i) == 0 {
+            '-'
+        } else {
+            c
+        });
+    }
+
+    let mtime = OffsetDateTime::from_unix_timestamp(entry.mtime)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap_or_default();
+
+    writeln!(
+        out,
+        "{permissions} root/root {:>9} {mtime} {name}",
+        entry.content.as_ref().map_or(0, Vec::len)
+    )
+    .unwrap();
+}
+
+/// Reads each entry out of a ustar archive, or `None` if `data` isn't one.
+fn read_archive(data: &[u8]) -> Option<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut offset = 0;
+
+    while offset + BLOCK <= data.len() {
+        let header = &data[offset..offset + BLOCK];
+        offset += BLOCK;
+
+        if header.iter().all(|v| *v == 0) {
+            break;
+        }
+
+        if &header[257..262] != b"ustar" {
+            return None;
+        }
+
+        let size = usize::try_from(octal(&header[124..136])?).ok()?;
+        let content = data.get(offset..offset + size)?.to_vec();
+        offset += size.div_ceil(BLOCK) * BLOCK;
+
+        let name = match (string(&header[345..500]), string(&header[..100])) {
+            (prefix, name) if prefix.is_empty() => name,
+            (prefix, name) => format!("{prefix}/{name}"),
+        };
+        let name = long_name.take().unwrap_or(name);
+        let mode = u32::try_from(octal(&header[100..108])?).ok()? & 0o7777;
+        let mtime = i64::try_from(octal(&header[136..148]).unwrap_or(0)).unwrap_or(0);
+
+        match header[156] {
+            b'0' | 0 => entries.push(Entry {
+                name,
+                mode,
+                mtime,
+                content: Some(content),
+            }),
+            b'5' => entries.push(Entry {
+                name: name.trim_end_matches('/').to_string(),
+                mode,
+                mtime,
+                content: None,
+            }),
+            // GNU tar's extension for names too long to fit in the header, which holds the name
+            // of the entry following it
+            b'L' => long_name = Some(string(&content)),
+            // links and devices aren't something the virtual file system has
+            _ => {}
+        }
+    }
+
+    Some(entries)
+}
+
+/// Parses a NUL or space terminated octal number out of a header field.
+fn octal(field: &[u8]) -> Option<u64> {
+    let field = std::str::from_utf8(field).ok()?;
+    let field = field.trim_matches(|c: char| c == '\0' || c == ' ');
+
+    if field.is_empty() {
+        Some(0)
+    } else {
+        u64::from_str_radix(field, 8).ok()
+    }
+}
+
+/// Reads a NUL terminated string out of a header field.
+fn string(field: &[u8]) -> String {
+    let end = field.iter().position(|v| *v == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn create(connection: &mut ConnectionState, options: &Options) -> (Vec<u8>, u32) {
+    if options.members.is_empty() {
+        return (
+            b"tar: Cowardly refusing to create an empty archive\nTry 'tar --help' or 'tar \
+              --usage' for more information.\n"
+                .to_vec(),
+            2,
+        );
+    }
+
+    let mut messages = String::new();
+    let mut archive = Vec::new();
+    let mut files = Vec::new();
+    let mut status = 0;
+    let mtime = OffsetDateTime::now_utc().unix_timestamp().unsigned_abs();
+
+    if options.members.iter().any(|v| v.starts_with('/')) {
+        messages.push_str(LEADING_SLASH);
+    }
+
+    for member in &options.members {
+        let path = resolve(options, member);
+        let file_system = connection.file_system();
+
+        let Ok(walked) = file_system.walk(&path) else {
+            writeln!(
+                messages,
+                "tar: {member}: Cannot stat: No such file or directory"
+            )
+            .unwrap();
+            status = 2;
+            continue;
+        };
+
+        for (relative, tree) in walked {
+            let member = Path::new(member.trim_start_matches('/'));
+            let (full, name) = if relative.as_os_str().is_empty() {
+                (path.clone(), member.to_path_buf())
+            } else {
+                (path.join(&relative), member.join(&relative))
+            };
+            let name = name.to_string_lossy();
+            let mode = file_system.mode(&full).unwrap_or(0o644);
+
+            if options.verbose {
+                writeln!(messages, "{name}").unwrap();
+            }
+
+            match tree {
+                Tree::Directory(_) => {
+                    archive.extend(header(&format!("{name}/"), mode, 0, mtime, b'5'));
+                }
+                Tree::File(content) => {
+                    archive.extend(header(&name, mode, content.len(), mtime, b'0'));
+                    archive.extend_from_slice(content);
+                    archive.resize(archive.len().div_ceil(BLOCK) * BLOCK, 0);
+                    files.push(file_system.canonicalize(&full).to_string_lossy().into());
+                }
+            }
+        }
+    }
+
+    archive.resize(archive.len() + BLOCK * 2, 0);
+
+    let out = if let Some(target) = options.archive() {
+        if let Err(e) = connection
+            .file_system()
+            .write(Path::new(target), archive.into())
+        {
+            writeln!(messages, "tar: {target}: Cannot open: {e}").unwrap();
+            return (messages.into_bytes(), 2);
+        }
+
+        messages.into_bytes()
+    } else {
+        // the archive itself goes to stdout, so there's nowhere for the file names to go
+        archive
+    };
+
+    record(connection, options, FileOperation::Archive { files });
+
+    (out, status)
+}
+
+/// Builds a ustar header for a single entry.
+fn header(name: &str, mode: u32, size: usize, mtime: u64, typeflag: u8) -> [u8; BLOCK] {
+    fn put(header: &mut [u8], offset: usize, value: &[u8]) {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    }
+
+    let mut header = [0; BLOCK];
+
+    // names which won't fit are split between the name and prefix fields at a directory
+    let (prefix, name) = match name
+        .char_indices()
+        .rfind(|(i, c)| *c == '/' && name.len() - i - 1 <= 100)
+    {
+        Some((i, _)) if name.len() > 100 && i <= 155 => (&name[..i], &name[i + 1..]),
+        _ => ("", name),
+    };
+
+    put(&mut header, 0, &name.as_bytes()[..name.len().min(100)]);
+    put(&mut header, 100, format!("{mode:07o}\0").as_bytes());
+    put(&mut header, 108, b"0000000\0");
+    put(&mut header, 116, b"0000000\0");
+    put(&mut header, 124, format!("{size:011o}\0").as_bytes());
+    put(&mut header, 136, format!("{mtime:011o}\0").as_bytes());
+    put(&mut header, 148, b"        ");
+    header[156] = typeflag;
+    put(&mut header, 257, b"ustar\x0000");
+    put(&mut header, 265, b"root");
+    put(&mut header, 297, b"root");
+    put(&mut header, 345, prefix.as_bytes());
+
+    let checksum = header.iter().map(|v| u32::from(*v)).sum::<u32>();
+    put(&mut header, 148, format!("{checksum:06o}\0 ").as_bytes());
+
+    header
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pisshoff_types::audit::{AuditLogAction, FileOperation};
+    use test_case::test_case;
+
+    use crate::{
+        command::tar::{create, extract, parse, read_archive, Options},
+        server::ConnectionState,
+    };
+
+    #[test_case("xvf a.tar", Some('x'), true, Some("a.tar"), None; "traditional")]
+    #[test_case("-xzvf a.tgz -C /tmp", Some('x'), true, Some("a.tgz"), Some("/tmp"); "dashed")]
+    #[test_case("cfC a.tar dir", Some('c'), false, Some("a.tar"), Some("dir"); "values in order")]
+    #[test_case("--extract --file=a.tar --directory=b", Some('x'), false, Some("a.tar"), Some("b"); "long")]
+    #[test_case("-t", Some('t'), false, None, None; "stdin")]
+    fn parses(
+        input: &str,
+        mode: Option<char>,
+        verbose: bool,
+        file: Option<&str>,
+        directory: Option<&str>,
+    ) {
+        let input = shlex::split(input).unwrap();
+        let options = parse(&input);
+
+        assert_eq!(options.mode, mode);
+        assert_eq!(options.verbose, verbose);
+        assert_eq!(options.file.as_deref(), file);
+        assert_eq!(options.directory.as_deref(), directory);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .mkdirall(Path::new("payload/bin"))
+            .unwrap();
+        state
+            .file_system()
+            .write(Path::new("payload/bin/miner"), b"\x7fELF".to_vec().into())
+            .unwrap();
+        state
+            .file_system()
+            .set_mode(Path::new("payload/bin/miner"), 0o755)
+            .unwrap();
+
+        let input = shlex::split("cvf p.tar payload").unwrap();
+        let (out, code) = create(&mut state, &parse(&input));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "payload\npayload/bin\npayload/bin/miner\n"
+        );
+        assert_eq!(code, 0);
+
+        let archive = state
+            .file_system()
+            .read(Path::new("p.tar"))
+            .unwrap()
+            .to_vec();
+        assert_eq!(archive.len(), 512 * 6);
+        assert_eq!(read_archive(&archive).unwrap().len(), 3);
+
+        let options = Options {
+            mode: Some('x'),
+            directory: Some("/tmp".to_string()),
+            strip_components: 1,
+            ..Options::default()
+        };
+        let (out, code) = extract(&mut state, &options, &archive);
+        assert!(out.is_empty());
+        assert_eq!(code, 0);
+        assert_eq!(
+            state
+                .file_system()
+                .read(Path::new("/tmp/bin/miner"))
+                .unwrap(),
+            b"\x7fELF"
+        );
+        assert_eq!(
+            state
+                .file_system()
+                .mode(Path::new("/tmp/bin/miner"))
+                .unwrap(),
+            0o755
+        );
+
+        let extracted = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::FileOperation(v) => match &v.operation {
+                    FileOperation::Extract { files } => Some(files.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(extracted, vec!["/tmp/bin/miner".into()]);
+    }
+
+    #[test_case(b"not an archive at all", "tar: This does not look like a tar archive\ntar: Exiting with failure status due to previous errors\n"; "garbage")]
+    #[test_case(b"\x1f\x8b\x08\x00", "\ngzip: stdin: unexpected end of file\ntar: Child returned status 1\ntar: Error is not recoverable: exiting now\n"; "gzip")]
+    fn invalid_archive(data: &[u8], expected: &str) {
+        let mut state = ConnectionState::mock();
+        let options = Options {
+            mode: Some('x'),
+            ..Options::default()
+        };
+
+        let mut padded = data.to_vec();
+        padded.resize(1024, b'a');
+
+        let (out, code) = extract(&mut state, &options, &padded);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(code, 2);
+    }
+}
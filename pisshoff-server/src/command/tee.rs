@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pisshoff_types::audit::WriteFileEvent;
+use thrussh::ChannelId;
+
+use crate::{
+    audit::sha256_hex,
+    command::{Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Tee {
+    files: Vec<PathBuf>,
+}
+
+#[async_trait]
+impl Command for Tee {
+    const NAME: &'static str = "tee";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut append = false;
+        let mut files = Vec::new();
+
+        for param in super::argparse(params) {
+            match param {
+                Arg::Short('a') | Arg::Long("append") => append = true,
+                Arg::Short('i' | 'p') | Arg::Long("ignore-interrupts") => {}
+                Arg::Operand(file) => {
+                    files.push(connection.file_system().canonicalize(Path::new(file)))
+                }
+                Arg::Short(c) => {
+                    session.data(
+                        channel,
+                        format!("tee: invalid option -- '{c}'\nTry 'tee --help' for more information.\n")
+                            .into(),
+                    );
+                    return CommandResult::Exit(1);
+                }
+                Arg::Long(v) => {
+                    session.data(
+                        channel,
+                        format!("tee: unrecognized option '--{v}'\nTry 'tee --help' for more information.\n")
+                            .into(),
+                    );
+                    return CommandResult::Exit(1);
+                }
+            }
+        }
+
+        let mut opened = Vec::with_capacity(files.len());
+
+        // files are truncated as soon as tee starts, unless they're being appended to
+        for file in files {
+            let res = if append {
+                connection.file_system().append(&file, &[])
+            } else {
+                connection.file_system().write(&file, Box::default())
+            };
+
+            match res {
+                Ok(()) => opened.push(file),
+                Err(e) => session.data(channel, format!("tee: {}: {e}\n", file.display()).into()),
+            }
+        }
+
+        CommandResult::ReadStdin(Self { files: opened })
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        session.data(channel, data.to_vec().into());
+
+        let mut status = 0;
+
+        for file in self.files {
+            connection.record_write(WriteFileEvent {
+                path: file.to_string_lossy().into_owned().into_boxed_str(),
+                sha256: sha256_hex(data),
+                size: data.len(),
+                original_name: None,
+                mode: None,
+                content: Bytes::copy_from_slice(data),
+            });
+
+            if let Err(e) = connection.file_system().append(&file, data) {
+                status = 1;
+                session.data(channel, format!("tee: {}: {e}\n", file.display()).into());
+            }
+        }
+
+        CommandResult::Exit(status)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+    use pisshoff_types::audit::{AuditLogAction, PersistenceMechanism};
+
+    use crate::{
+        command::{tee::Tee, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[tokio::test]
+    async fn appends_keys() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .mkdirall(Path::new("/root/.ssh"))
+            .unwrap();
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("ssh-rsa AAAA mdrfckr\n"))
+            .returning(|_, _| ());
+
+        let out = Tee::new(
+            &mut state,
+            ["-a".to_string(), "/root/.ssh/authorized_keys".to_string()].as_slice(),
+            fake_channel_id(),
+            &mut session,
+        )
+        .await
+        .unwrap_stdin();
+
+        let out = out
+            .stdin(
+                &mut state,
+                fake_channel_id(),
+                b"ssh-rsa AAAA mdrfckr\n",
+                &mut session,
+            )
+            .await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(
+            state
+                .file_system()
+                .read(Path::new("/root/.ssh/authorized_keys"))
+                .unwrap(),
+            b"ssh-rsa AAAA mdrfckr\n"
+        );
+
+        let persistence = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PersistenceAttempt(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        let PersistenceMechanism::AuthorizedKeys { keys } = &persistence.mechanism else {
+            panic!("expected authorized keys, got {:?}", persistence.mechanism);
+        };
+        assert_eq!(&*persistence.path, "/root/.ssh/authorized_keys");
+        assert_eq!(keys[0].comment.as_deref(), Some("mdrfckr"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::{fmt::Write, time::Duration};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::OffsetDateTime;
+
+use crate::{
+    command::{uptime, Command, CommandResult},
+    persona::{processes::Process, Persona},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Prints a single batch mode iteration, as if `top -bn1` had been run, regardless of the
+/// parameters given, since there's no interactive display to draw.
+#[derive(Debug, Clone)]
+pub struct Top {}
+
+#[async_trait]
+impl Command for Top {
+    const NAME: &'static str = "top";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let now = OffsetDateTime::now_utc();
+        let processes = super::processes(connection, Self::NAME, params, now);
+
+        let persona = &connection.config().persona;
+        let out = execute(persona, &processes, persona.boot_time(), now);
+
+        session.data(channel, out.into());
+        CommandResult::Exit(0)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn execute(
+    persona: &Persona,
+    processes: &[Process],
+    boot: OffsetDateTime,
+    now: OffsetDateTime,
+) -> String {
+    let memory = persona.memory_usage();
+    let running = processes.iter().filter(|v| v.stat.starts_with('R')).count();
+    let mib = |kibibytes: u64| kibibytes as f64 / 1024.0;
+
+    let mut out = String::new();
+
+    writeln!(out, "top - {}", uptime::summary(boot, now)).unwrap();
+    writeln!(
+        out,
+        "Tasks: {:>3} total, {:>3} running, {:>3} sleeping, {:>3} stopped, {:>3} zombie",
+        processes.len(),
+        running,
+        processes.len() - running,
+        0,
+        0,
+    )
+    .unwrap();
+    out.push_str(
+        "%Cpu(s):  0.3 us,  0.2 sy,  0.0 ni, 99.5 id,  0.0 wa,  0.0 hi,  0.0 si,  0.0 st\n",
+    );
+    writeln!(
+        out,
+        "MiB Mem : {:>8.1} total, {:>8.1} free, {:>8.1} used, {:>8.1} buff/cache",
+        mib(memory.total),
+        mib(memory.free),
+        mib(memory.used),
+        mib(memory.buff_cache),
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "MiB Swap: {:>8.1} total, {:>8.1} free, {:>8.1} used. {:>8.1} avail Mem",
+        mib(persona.swap),
+        mib(persona.swap),
+        0.0,
+        mib(memory.available),
+    )
+    .unwrap();
+    out.push_str(
+        "\n    PID USER      PR  NI    VIRT    RES    SHR S  %CPU  %MEM     TIME+ COMMAND\n",
+    );
+
+    for process in processes {
+        let (priority, nice) = if process.is_kernel_thread() {
+            ("0", -20)
+        } else {
+            ("20", 0)
+        };
+
+        writeln!(
+            out,
+            "{:>7} {:<9} {:>2} {:>3} {:>7} {:>6} {:>6} {} {:>5.1} {:>5.1} {:>9} {}",
+            process.pid,
+            user_name(process),
+            priority,
+            nice,
+            process.vsz,
+            process.rss,
+            process.shared,
+            process.stat.chars().next().unwrap_or('S'),
+            0.0,
+            process.rss as f64 * 100.0 / memory.total.max(1) as f64,
+            cpu_time(process.time),
+            process.comm(),
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+/// Names longer than the column are cut short with a `+`, as procps does.
+fn user_name(process: &Process) -> String {
+    if process.user.len() > 8 {
+        format!("{}+", &process.user[..7])
+    } else {
+        process.user.clone()
+    }
+}
+
+/// Formats CPU time as `M:SS.hh`.
+fn cpu_time(time: Duration) -> String {
+    let hundredths = time.as_millis() / 10;
+    format!(
+        "{}:{:02}.{:02}",
+        hundredths / 6000,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use crate::{
+        command::top::execute,
+        persona::{
+            processes::{processes, Session},
+            Persona,
+        },
+    };
+
+    #[test]
+    fn works() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let boot = datetime!(2023-10-12 06:08:40 UTC);
+        let now = datetime!(2023-10-24 09:12:44 UTC);
+        let processes = processes(
+            &persona,
+            boot,
+            &Session {
+                username: "ubuntu",
+                tty: Some("pts/0"),
+                login: datetime!(2023-10-24 09:10:00 UTC),
+                now,
+                command: "top",
+            },
+        );
+
+        let output = execute(&persona, &processes, boot, now);
+        let mut lines = output.lines();
+
+        assert_eq!(
+            lines.next().unwrap(),
+            "top - 09:12:44 up 12 days,  3:04,  1 user,  load average: 0.08, 0.03, 0.01"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "Tasks:  51 total,   1 running,  50 sleeping,   0 stopped,   0 zombie"
+        );
+        assert!(lines.next().unwrap().starts_with("%Cpu(s):"));
+        assert!(lines.next().unwrap().starts_with("MiB Mem :"));
+        assert!(lines.next().unwrap().starts_with("MiB Swap:"));
+        assert_eq!(lines.next().unwrap(), "");
+        assert_eq!(
+            lines.next().unwrap(),
+            "    PID USER      PR  NI    VIRT    RES    SHR S  %CPU  %MEM     TIME+ COMMAND"
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("      1 root      20   0 "));
+        let last = lines.last().unwrap();
+        assert!(last.contains(" R   0.0 "), "{last}");
+        assert!(last.ends_with("   0:00.00 top"), "{last}");
+    }
+}
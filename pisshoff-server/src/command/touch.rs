@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Touch {}
+
+#[async_trait]
+impl Command for Touch {
+    const NAME: &'static str = "touch";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let files = super::argparse(params)
+            .filter_map(|v| match v {
+                Arg::Operand(file) => Some(file),
+                Arg::Short(_) | Arg::Long(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        if files.is_empty() {
+            session.data(channel, "touch: missing file operand\n".into());
+            return CommandResult::Exit(1);
+        }
+
+        let mut status = 0;
+
+        for file in files {
+            if let Err(e) = connection.file_system().touch(Path::new(file)) {
+                status = 1;
+                session.data(
+                    channel,
+                    format!("touch: cannot touch '{file}': {e}\n").into(),
+                );
+            }
+        }
+
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::predicate::always;
+
+    use crate::{
+        command::{touch::Touch, Command, CommandResult},
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession,
+        },
+    };
+
+    #[tokio::test]
+    async fn works() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .write(Path::new("existing"), Box::from(b"hello".as_slice()))
+            .unwrap();
+
+        session
+            .expect_data()
+            .once()
+            .with(
+                always(),
+                eq_string("touch: cannot touch 'missing/a': No such file or directory\n"),
+            )
+            .returning(|_, _| ());
+
+        let out = Touch::new(
+            &mut state,
+            &[
+                "new".to_string(),
+                "existing".to_string(),
+                "missing/a".to_string(),
+            ],
+            fake_channel_id(),
+            &mut session,
+        )
+        .await;
+
+        assert!(matches!(out, CommandResult::Exit(1)), "{out:?}");
+        assert_eq!(state.file_system().read(Path::new("new")).unwrap(), b"");
+        assert_eq!(
+            state.file_system().read(Path::new("existing")).unwrap(),
+            b"hello"
+        );
+    }
+}
@@ -4,6 +4,7 @@ use thrussh::ChannelId;
 
 use crate::{
     command::{Arg, Command, CommandResult},
+    persona::Persona,
     server::{ConnectionState, ThrusshSession},
 };
 
@@ -57,13 +58,15 @@ pub struct Uname {}
 
 #[async_trait]
 impl Command for Uname {
+    const NAME: &'static str = "uname";
+
     async fn new<S: ThrusshSession + Send>(
-        _connection: &mut ConnectionState,
+        connection: &mut ConnectionState,
         params: &[String],
         channel: ChannelId,
         session: &mut S,
     ) -> CommandResult<Self> {
-        let (out, exit_code) = execute(params);
+        let (out, exit_code) = execute(params, &connection.config().persona);
 
         session.data(channel, out.into());
         CommandResult::Exit(exit_code)
@@ -80,7 +83,7 @@ impl Command for Uname {
     }
 }
 
-pub fn execute(params: &[String]) -> (String, u32) {
+pub fn execute(params: &[String], persona: &Persona) -> (String, u32) {
     let mut to_print = ToPrint::empty();
     let mut filter_unknown = false;
 
@@ -148,19 +151,19 @@ pub fn execute(params: &[String]) -> (String, u32) {
     }
 
     if to_print.contains(ToPrint::NODE_NAME) {
-        write!("cd5079c0d642");
+        write!(&persona.hostname);
     }
 
     if to_print.contains(ToPrint::KERNEL_RELEASE) {
-        write!("5.15.49");
+        write!(&persona.kernel_release);
     }
 
     if to_print.contains(ToPrint::KERNEL_VERSION) {
-        write!("#1 SMP PREEMPT Tue Sep 13 07:51:32 UTC 2022");
+        write!(&persona.kernel_version);
     }
 
     if to_print.contains(ToPrint::MACHINE) {
-        write!("x86_64");
+        write!(&persona.machine);
     }
 
     if to_print.contains(ToPrint::PROCESSOR) && !filter_unknown {
@@ -184,7 +187,7 @@ pub fn execute(params: &[String]) -> (String, u32) {
 mod test {
     use test_case::test_case;
 
-    use crate::command::uname::execute;
+    use crate::{command::uname::execute, persona::Persona};
 
     #[test_case("", 0; "none")]
     #[test_case("-a", 0; "all")]
@@ -196,7 +199,7 @@ mod test {
     #[test_case("-sn oper", 1; "unknown operand")]
     fn snapshot(input: &str, expected_exit_code: u32) {
         let input_parsed = shlex::split(input).unwrap();
-        let (output, actual_exit_code) = execute(&input_parsed);
+        let (output, actual_exit_code) = execute(&input_parsed, &Persona::default());
 
         insta::assert_display_snapshot!(input, output);
         assert_eq!(actual_exit_code, expected_exit_code);
@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Unset {}
+
+#[async_trait]
+impl Command for Unset {
+    const NAME: &'static str = "unset";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut exit_code = 0;
+
+        for name in params.iter().filter(|v| !v.starts_with('-')) {
+            if super::is_name(name) {
+                connection.environment_mut().remove(name.as_bytes());
+            } else {
+                session.data(
+                    channel,
+                    format!("bash: unset: `{name}': not a valid identifier\n").into(),
+                );
+                exit_code = 1;
+            }
+        }
+
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
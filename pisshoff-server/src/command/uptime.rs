@@ -0,0 +1,166 @@
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// Load averages reported by `uptime` and `top`, the system is always just about idle.
+pub const LOAD_AVERAGE: &str = "0.08, 0.03, 0.01";
+
+#[derive(Debug, Clone)]
+pub struct Uptime {}
+
+#[async_trait]
+impl Command for Uptime {
+    const NAME: &'static str = "uptime";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let boot = connection.config().persona.boot_time();
+        let (out, exit_code) = execute(params, boot, OffsetDateTime::now_utc());
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(params: &[String], boot: OffsetDateTime, now: OffsetDateTime) -> (String, u32) {
+    for param in super::argparse(params) {
+        match param {
+            Arg::Short('p') | Arg::Long("pretty") => {
+                return (format!("{}\n", pretty(boot, now)), 0);
+            }
+            Arg::Short('s') | Arg::Long("since") => {
+                let since = boot
+                    .format(format_description!(
+                        "[year]-[month]-[day] [hour]:[minute]:[second]"
+                    ))
+                    .unwrap();
+                return (format!("{since}\n"), 0);
+            }
+            Arg::Short(c) => {
+                return (
+                    format!("uptime: invalid option -- '{c}'\nTry 'uptime --help' for more information.\n"),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!("uptime: unrecognized option '--{v}'\nTry 'uptime --help' for more information.\n"),
+                    1,
+                );
+            }
+            Arg::Operand(_) => {
+                return (
+                    "\nUsage:\n uptime [options]\n\nOptions:\n -p, --pretty   show uptime in pretty format\n -h, --help     display this help and exit\n -s, --since    system up since\n -V, --version  output version information and exit\n\nFor more details see uptime(1).\n"
+                        .to_string(),
+                    1,
+                );
+            }
+        }
+    }
+
+    (format!(" {}\n", summary(boot, now)), 0)
+}
+
+/// Formats the line shown by `uptime` and at the top of `top`, ie.
+/// `10:15:42 up 12 days,  3:04,  1 user,  load average: 0.08, 0.03, 0.01`.
+pub fn summary(boot: OffsetDateTime, now: OffsetDateTime) -> String {
+    let minutes = (now - boot).whole_minutes().max(0);
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+
+    let mut up = String::new();
+
+    if days > 0 {
+        up.push_str(&format!("{days} day{}, ", if days == 1 { "" } else { "s" }));
+    }
+
+    if hours > 0 {
+        up.push_str(&format!("{hours:2}:{minutes:02}, "));
+    } else {
+        up.push_str(&format!("{minutes} min, "));
+    }
+
+    let time = now
+        .format(format_description!("[hour]:[minute]:[second]"))
+        .unwrap();
+
+    format!("{time} up {up} 1 user,  load average: {LOAD_AVERAGE}")
+}
+
+fn pretty(boot: OffsetDateTime, now: OffsetDateTime) -> String {
+    let minutes = (now - boot).whole_minutes().max(0);
+    let units = [
+        ("week", minutes / 10080),
+        ("day", minutes / 1440 % 7),
+        ("hour", minutes / 60 % 24),
+        ("minute", minutes % 60),
+    ];
+
+    let parts = units
+        .iter()
+        .filter(|(_, value)| *value > 0)
+        .map(|(unit, value)| format!("{value} {unit}{}", if *value == 1 { "" } else { "s" }))
+        .collect::<Vec<_>>();
+
+    if parts.is_empty() {
+        "up 0 minutes".to_string()
+    } else {
+        format!("up {}", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::command::uptime::execute;
+
+    #[test_case("", " 09:12:44 up 12 days,  3:04,  1 user,  load average: 0.08, 0.03, 0.01\n", 0; "none")]
+    #[test_case("-p", "up 1 week, 5 days, 3 hours, 4 minutes\n", 0; "pretty")]
+    #[test_case("--since", "2023-10-12 06:08:40\n", 0; "since")]
+    #[test_case("-z", "uptime: invalid option -- 'z'\nTry 'uptime --help' for more information.\n", 1; "unknown short arg")]
+    fn works(input: &str, expected: &str, expected_exit_code: u32) {
+        let input = shlex::split(input).unwrap();
+        let (output, exit_code) = execute(
+            &input,
+            datetime!(2023-10-12 06:08:40 UTC),
+            datetime!(2023-10-24 09:12:44 UTC),
+        );
+
+        assert_eq!(output, expected);
+        assert_eq!(exit_code, expected_exit_code);
+    }
+
+    #[test]
+    fn just_booted() {
+        let (output, _) = execute(
+            &[],
+            datetime!(2023-10-24 09:10:00 UTC),
+            datetime!(2023-10-24 09:12:44 UTC),
+        );
+
+        assert_eq!(
+            output,
+            " 09:12:44 up 2 min,  1 user,  load average: 0.08, 0.03, 0.01\n"
+        );
+    }
+}
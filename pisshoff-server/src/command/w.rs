@@ -0,0 +1,151 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    command::{uptime, Arg, Command, CommandResult},
+    persona::logins::Login,
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct W {}
+
+#[async_trait]
+impl Command for W {
+    const NAME: &'static str = "w";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let logins = super::logins(connection);
+        let boot = connection.config().persona.boot_time();
+        let (out, exit_code) = execute(params, &logins, boot, OffsetDateTime::now_utc());
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(
+    params: &[String],
+    logins: &[Login],
+    boot: OffsetDateTime,
+    now: OffsetDateTime,
+) -> (String, u32) {
+    let mut header = true;
+    let mut users = Vec::new();
+
+    for arg in super::argparse(params) {
+        match arg {
+            Arg::Short('h') | Arg::Long("no-header") => header = false,
+            Arg::Short('s' | 'i' | 'f' | 'o' | 'u') | Arg::Long("short" | "ip-addr" | "from") => {}
+            Arg::Short(c) => {
+                return (
+                    format!(
+                        "w: invalid option -- '{c}'\n\nUsage:\n w [options] [user]\n\nFor more \
+                         details see w(1).\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!(
+                        "w: unrecognized option '--{v}'\n\nUsage:\n w [options] [user]\n\nFor \
+                         more details see w(1).\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Operand(v) => users.push(v),
+        }
+    }
+
+    let what = std::iter::once("w")
+        .chain(params.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut out = String::new();
+
+    if header {
+        writeln!(out, " {}", uptime::summary(boot, now)).unwrap();
+        out.push_str("USER     TTY      FROM             LOGIN@   IDLE   JCPU   PCPU WHAT\n");
+    }
+
+    for login in logins.iter().filter(|v| v.end.is_none()) {
+        if !users.is_empty() && !users.iter().any(|v| *v == login.user) {
+            continue;
+        }
+
+        let since = login
+            .start
+            .format(format_description!("[hour]:[minute]"))
+            .unwrap();
+
+        writeln!(
+            out,
+            "{:<8} {:<8} {:<16} {since:<8} 0.00s  0.02s  0.00s {what}",
+            login.user, login.tty, login.host,
+        )
+        .unwrap();
+    }
+
+    (out, 0)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::{command::w::execute, persona::logins::Login};
+
+    #[test_case("", " 09:12:44 up 12 days,  3:04,  1 user,  load average: 0.08, 0.03, 0.01\nUSER     TTY      FROM             LOGIN@   IDLE   JCPU   PCPU WHAT\nroot     pts/1    203.0.113.5      09:10    0.00s  0.02s  0.00s w\n", 0; "none")]
+    #[test_case("-h", "root     pts/1    203.0.113.5      09:10    0.00s  0.02s  0.00s w -h\n", 0; "no header")]
+    #[test_case("-h admin", "", 0; "other user")]
+    #[test_case("-z", "w: invalid option -- 'z'\n\nUsage:\n w [options] [user]\n\nFor more details see w(1).\n", 1; "unknown short arg")]
+    fn works(input: &str, expected: &str, exit_code: u32) {
+        let logins = [
+            Login {
+                user: "root".to_string(),
+                tty: "pts/1".to_string(),
+                host: "203.0.113.5".to_string(),
+                start: datetime!(2023-10-24 09:10:00 UTC),
+                end: None,
+            },
+            Login {
+                user: "root".to_string(),
+                tty: "pts/0".to_string(),
+                host: "192.168.1.20".to_string(),
+                start: datetime!(2023-10-12 21:02:00 UTC),
+                end: Some(datetime!(2023-10-14 09:30:00 UTC)),
+            },
+        ];
+
+        let input = shlex::split(input).unwrap();
+        let out = execute(
+            &input,
+            &logins,
+            datetime!(2023-10-12 06:08:40 UTC),
+            datetime!(2023-10-24 09:12:44 UTC),
+        );
+        assert_eq!(out, (expected.to_string(), exit_code));
+    }
+}
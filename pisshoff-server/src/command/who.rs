@@ -0,0 +1,189 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    command::{Arg, Command, CommandResult},
+    persona::logins::Login,
+    server::{ConnectionState, ThrusshSession},
+};
+
+#[derive(Debug, Clone)]
+pub struct Who {}
+
+#[async_trait]
+impl Command for Who {
+    const NAME: &'static str = "who";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let logins = super::logins(connection);
+        let boot = connection.config().persona.boot_time();
+        let (out, exit_code) = execute(params, &logins, boot);
+
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct Options {
+    boot: bool,
+    run_level: bool,
+    count: bool,
+    header: bool,
+    all: bool,
+    /// Only show the session attached to stdin, as in `who am i`.
+    me: bool,
+}
+
+fn execute(params: &[String], logins: &[Login], boot: OffsetDateTime) -> (String, u32) {
+    let mut options = Options::default();
+    let mut operands = 0;
+
+    for arg in super::argparse(params) {
+        match arg {
+            Arg::Short('b') | Arg::Long("boot") => options.boot = true,
+            Arg::Short('r') | Arg::Long("runlevel") => options.run_level = true,
+            Arg::Short('q') | Arg::Long("count") => options.count = true,
+            Arg::Short('H') | Arg::Long("heading") => options.header = true,
+            Arg::Short('m') => options.me = true,
+            Arg::Short('a') | Arg::Long("all") => {
+                options.boot = true;
+                options.run_level = true;
+                options.all = true;
+            }
+            Arg::Short('s' | 'u' | 'T' | 'w') | Arg::Long("short" | "users" | "mesg") => {}
+            Arg::Short(c) => {
+                return (
+                    format!(
+                        "who: invalid option -- '{c}'\nTry 'who --help' for more information.\n"
+                    ),
+                    1,
+                );
+            }
+            Arg::Long(v) => {
+                return (
+                    format!(
+                        "who: unrecognized option '--{v}'\nTry 'who --help' for more \
+                         information.\n"
+                    ),
+                    1,
+                );
+            }
+            // `who am i`, or any other two words, along with an optional file
+            Arg::Operand(_) => operands += 1,
+        }
+    }
+
+    match operands {
+        0 | 1 => {}
+        2 => options.me = true,
+        _ => {
+            return (
+                format!(
+                    "who: extra operand '{}'\nTry 'who --help' for more information.\n",
+                    params.last().map_or("", String::as_str)
+                ),
+                1,
+            );
+        }
+    }
+
+    let current = logins.iter().filter(|v| v.end.is_none());
+    let mut out = String::new();
+
+    if options.count {
+        let users = current.map(|v| v.user.as_str()).collect::<Vec<_>>();
+        writeln!(out, "{}\n# users={}", users.join(" "), users.len()).unwrap();
+        return (out, 0);
+    }
+
+    if options.header {
+        out.push_str("NAME     LINE         TIME             COMMENT\n");
+    }
+
+    if options.boot {
+        writeln!(out, "         system boot  {}", date(boot)).unwrap();
+    }
+
+    if options.run_level {
+        writeln!(out, "         run-level 5  {}", date(boot)).unwrap();
+    }
+
+    // asking for the boot time or run-level on their own leaves out the users
+    if options.all || options.me || !(options.boot || options.run_level) {
+        for login in current.take(if options.me { 1 } else { usize::MAX }) {
+            writeln!(
+                out,
+                "{:<8} {:<12} {} ({})",
+                login.user,
+                login.tty,
+                date(login.start),
+                login.host
+            )
+            .unwrap();
+        }
+    }
+
+    (out, 0)
+}
+
+fn date(v: OffsetDateTime) -> String {
+    v.format(format_description!("[year]-[month]-[day] [hour]:[minute]"))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+    use time::macros::datetime;
+
+    use crate::{command::who::execute, persona::logins::Login};
+
+    #[test_case("", "root     pts/1        2023-10-24 09:10 (203.0.113.5)\n", 0; "none")]
+    #[test_case("am i", "root     pts/1        2023-10-24 09:10 (203.0.113.5)\n", 0; "am i")]
+    #[test_case("-b", "         system boot  2023-10-12 06:08\n", 0; "boot")]
+    #[test_case("-q", "root\n# users=1\n", 0; "count")]
+    #[test_case("-H", "NAME     LINE         TIME             COMMENT\nroot     pts/1        2023-10-24 09:10 (203.0.113.5)\n", 0; "heading")]
+    #[test_case("a b c", "who: extra operand 'c'\nTry 'who --help' for more information.\n", 1; "extra operand")]
+    fn works(input: &str, expected: &str, exit_code: u32) {
+        let logins = [
+            Login {
+                user: "root".to_string(),
+                tty: "pts/1".to_string(),
+                host: "203.0.113.5".to_string(),
+                start: datetime!(2023-10-24 09:10:00 UTC),
+                end: None,
+            },
+            Login {
+                user: "root".to_string(),
+                tty: "pts/0".to_string(),
+                host: "192.168.1.20".to_string(),
+                start: datetime!(2023-10-12 21:02:00 UTC),
+                end: Some(datetime!(2023-10-14 09:30:00 UTC)),
+            },
+        ];
+
+        let input = shlex::split(input).unwrap();
+        let out = execute(&input, &logins, datetime!(2023-10-12 06:08:40 UTC));
+        assert_eq!(out, (expected.to_string(), exit_code));
+    }
+}
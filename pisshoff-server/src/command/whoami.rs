@@ -11,6 +11,8 @@ pub struct Whoami {}
 
 #[async_trait]
 impl Command for Whoami {
+    const NAME: &'static str = "whoami";
+
     async fn new<S: ThrusshSession + Send>(
         connection: &mut ConnectionState,
         _params: &[String],
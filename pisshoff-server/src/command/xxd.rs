@@ -0,0 +1,155 @@
+use std::{fmt::Write, path::Path};
+
+use async_trait::async_trait;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+/// `xxd`, which is mostly used to turn hex back into the payload it encodes with `xxd -r -p`.
+#[derive(Debug, Clone)]
+pub struct Xxd {
+    revert: bool,
+    /// Whether to use a plain hex dump, without offsets or the printable characters.
+    plain: bool,
+}
+
+impl Xxd {
+    fn run(&self, input: &[u8]) -> Vec<u8> {
+        match (self.revert, self.plain) {
+            (true, true) => from_hex(input),
+            (true, false) => String::from_utf8_lossy(input)
+                .lines()
+                // the hex is between the offset and the two spaces before the characters
+                .filter_map(|line| line.split_once(':'))
+                .flat_map(|(_, rest)| {
+                    from_hex(rest.split("  ").next().unwrap_or_default().as_bytes())
+                })
+                .collect(),
+            (false, true) => {
+                let mut out = String::new();
+
+                for line in input.chunks(30) {
+                    for byte in line {
+                        write!(out, "{byte:02x}").unwrap();
+                    }
+
+                    out.push('\n');
+                }
+
+                out.into_bytes()
+            }
+            (false, false) => dump(input).into_bytes(),
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Xxd {
+    const NAME: &'static str = "xxd";
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let mut this = Self {
+            revert: false,
+            plain: false,
+        };
+        let mut file = None;
+
+        for param in params {
+            match param.as_str() {
+                "-r" | "-revert" => this.revert = true,
+                "-p" | "-ps" | "-postscript" | "-plain" => this.plain = true,
+                "-rp" | "-pr" => (this.revert, this.plain) = (true, true),
+                "-" => {}
+                v if v.starts_with('-') => {}
+                v => file = file.or(Some(v)),
+            }
+        }
+
+        let Some(file) = file else {
+            return CommandResult::ReadStdin(this);
+        };
+
+        let (out, status) = match connection.file_system().read(Path::new(file)) {
+            Ok(content) => (this.run(content), 0),
+            Err(e) => (format!("xxd: {file}: {e}\n").into_bytes(), 2),
+        };
+
+        session.data(channel, out.into());
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        session.data(channel, self.run(data).into());
+        CommandResult::Exit(0)
+    }
+}
+
+/// Decodes pairs of hex digits, skipping over anything else as `xxd -r -p` does.
+fn from_hex(input: &[u8]) -> Vec<u8> {
+    let digits = input
+        .iter()
+        .filter_map(|c| char::from(*c).to_digit(16))
+        .collect::<Vec<_>>();
+
+    digits
+        .chunks_exact(2)
+        .map(|v| u8::try_from(v[0] << 4 | v[1]).unwrap_or_default())
+        .collect()
+}
+
+/// Renders the usual hex dump of 16 bytes a line, with each line's offset and printable
+/// characters.
+fn dump(input: &[u8]) -> String {
+    let mut out = String::new();
+
+    for (i, line) in input.chunks(16).enumerate() {
+        let hex = line
+            .chunks(2)
+            .map(|v| v.iter().map(|b| format!("{b:02x}")).collect::<String>())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let printable = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    char::from(b)
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+
+        writeln!(out, "{:08x}: {hex:<39}  {printable}", i * 16).unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::command::xxd::Xxd;
+
+    #[test_case(true, true, b"7768 6f61\nmi", b"whoa"; "revert plain")]
+    #[test_case(false, true, b"whoami\n", b"77686f616d690a\n"; "plain")]
+    #[test_case(false, false, b"whoami\n", b"00000000: 7768 6f61 6d69 0a                        whoami.\n"; "dump")]
+    #[test_case(true, false, b"00000000: 7768 6f61 6d69 0a                        whoami.\n", b"whoami\n"; "revert dump")]
+    fn runs(revert: bool, plain: bool, input: &[u8], expected: &[u8]) {
+        assert_eq!(Xxd { revert, plain }.run(input), expected);
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt::Write;
+
+use async_trait::async_trait;
+use pisshoff_types::audit::Package;
+use thrussh::ChannelId;
+
+use crate::{
+    command::{Command, CommandResult},
+    server::{ConnectionState, ThrusshSession},
+};
+
+const HEADER: &str = "Loaded plugins: fastestmirror
+Loading mirror speeds from cached hostfile
+ * base: mirror.centos.org
+ * epel: mirror.centos.org
+ * extras: mirror.centos.org
+ * updates: mirror.centos.org
+";
+
+const RULE: &str =
+    "================================================================================\n";
+
+#[derive(Debug, Clone)]
+pub struct Yum {}
+
+#[async_trait]
+impl Command for Yum {
+    const NAME: &'static str = "yum";
+    const ALIASES: &'static [&'static str] = &["dnf"];
+
+    async fn new<S: ThrusshSession + Send>(
+        connection: &mut ConnectionState,
+        params: &[String],
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        let (out, exit_code) = execute(connection, params);
+        session.data(channel, out.into());
+        CommandResult::Exit(exit_code)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        _connection: &mut ConnectionState,
+        _channel: ChannelId,
+        _data: &[u8],
+        _session: &mut S,
+    ) -> CommandResult<Self> {
+        CommandResult::Exit(0)
+    }
+}
+
+fn execute(connection: &mut ConnectionState, params: &[String]) -> (String, u32) {
+    let mut operands = params.iter().filter(|v| !v.starts_with('-'));
+
+    let Some(operation) = operands.next() else {
+        return (
+            "Loaded plugins: fastestmirror\nYou need to give some command\n".to_string(),
+            1,
+        );
+    };
+
+    // rpm doesn't have a separator between the name and version we could split on
+    let packages = operands
+        .map(|v| Package {
+            name: v.as_str().into(),
+            version: None,
+        })
+        .collect::<Vec<_>>();
+
+    let root = connection.username() == "root";
+
+    match operation.as_str() {
+        "install" | "reinstall" | "localinstall" => {
+            let out = if root {
+                (install(connection, &packages), 0)
+            } else {
+                (
+                    "Loaded plugins: fastestmirror\nYou need to be root to perform this command.\n"
+                        .to_string(),
+                    1,
+                )
+            };
+
+            super::record_packages(connection, Yum::NAME, packages);
+            out
+        }
+        _ if !root => (
+            "Loaded plugins: fastestmirror\nYou need to be root to perform this command.\n"
+                .to_string(),
+            1,
+        ),
+        "update" | "upgrade" | "makecache" | "clean" => {
+            (format!("{HEADER}No packages marked for update\n"), 0)
+        }
+        "remove" | "erase" => (
+            format!("Loaded plugins: fastestmirror\nNo Match for argument: {}\nNo Packages marked for removal\n", packages.first().map_or("", |v| &*v.name)),
+            0,
+        ),
+        other => (
+            format!("Loaded plugins: fastestmirror\nNo such command: {other}. Please use /usr/bin/yum --help\n"),
+            1,
+        ),
+    }
+}
+
+fn install(connection: &ConnectionState, packages: &[Package]) -> String {
+    let arch = match connection.config().persona.machine.as_str() {
+        "armv7l" => "armv7hl",
+        other => other,
+    };
+
+    let mut out = HEADER.to_string();
+
+    if packages.is_empty() {
+        out.push_str("Error: Need to pass a list of pkgs to install\n");
+        return out;
+    }
+
+    let details = packages
+        .iter()
+        .map(|v| {
+            let (version, size) = super::package_details(connection, &v.name);
+            (&*v.name, format!("{version}-1.el7"), size)
+        })
+        .collect::<Vec<_>>();
+    let total = details.iter().map(|(_, _, size)| size).sum::<u64>();
+
+    out.push_str("Resolving Dependencies\n--> Running transaction check\n");
+    for (name, version, _) in &details {
+        writeln!(
+            out,
+            "---> Package {name}.{arch} 0:{version} will be installed"
+        )
+        .unwrap();
+    }
+    out.push_str("--> Finished Dependency Resolution\n\nDependencies Resolved\n\n");
+
+    out.push_str(RULE);
+    out.push_str(
+        " Package          Arch            Version               Repository        Size\n",
+    );
+    out.push_str(RULE);
+    out.push_str("Installing:\n");
+    for (name, version, size) in &details {
+        writeln!(
+            out,
+            " {name:<16} {arch:<15} {version:<21} {:<11} {size:>6} k",
+            "epel"
+        )
+        .unwrap();
+    }
+
+    out.push_str("\nTransaction Summary\n");
+    out.push_str(RULE);
+    writeln!(
+        out,
+        "Install  {} Package{}\n",
+        details.len(),
+        if details.len() == 1 { "" } else { "s" }
+    )
+    .unwrap();
+    writeln!(out, "Total download size: {total} k").unwrap();
+    writeln!(out, "Installed size: {} k", total * 3).unwrap();
+    out.push_str("Downloading packages:\nRunning transaction check\nRunning transaction test\nTransaction test succeeded\nRunning transaction\n");
+
+    for verb in ["Installing", "Verifying "] {
+        for (i, (name, version, _)) in details.iter().enumerate() {
+            let package = format!("{name}-{version}.{arch}");
+            writeln!(out, "  {verb} : {package:<58} {}/{}", i + 1, details.len()).unwrap();
+        }
+    }
+
+    out.push_str("\nInstalled:\n");
+    for (name, version, _) in &details {
+        writeln!(out, "  {name}.{arch} 0:{version}").unwrap();
+    }
+    out.push_str("\nComplete!\n");
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::AuditLogAction;
+
+    use crate::{command::yum::execute, server::ConnectionState};
+
+    #[test]
+    fn records_install() {
+        let mut state = ConnectionState::mock();
+
+        let input = shlex::split("install -y xmrig python3-pip").unwrap();
+        let (out, code) = execute(&mut state, &input);
+        assert_eq!(code, 0, "{out}");
+        assert!(out.contains("Install  2 Packages\n"), "{out}");
+        assert!(out.ends_with("\nComplete!\n"), "{out}");
+
+        let event = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PackageInstall(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*event.manager, "yum");
+        assert_eq!(
+            event.packages.iter().map(|v| &*v.name).collect::<Vec<_>>(),
+            ["xmrig", "python3-pip"]
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Captures payloads requested via `wget` and `curl`. Every requested URL is recorded in the
+//! audit log and, if enabled, the payload is fetched into the quarantine directory so it can be
+//! analysed later on.
+
+use std::{
+    borrow::Cow,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use bytes::{Bytes, BytesMut};
+use pisshoff_types::audit::{AuditLogAction, DownloadAttemptEvent};
+use reqwest::{header::LOCATION, redirect::Policy, Url};
+use tracing::debug;
+
+use crate::{
+    audit::sha256_hex,
+    config::DownloadConfig,
+    quarantine::{self, Origin},
+    server::ConnectionState,
+};
+
+/// Maximum number of redirects we'll follow when fetching a payload.
+const MAX_REDIRECTS: usize = 5;
+
+/// Parses a URL passed to `wget` or `curl`, both of which assume `http://` if no scheme is
+/// given.
+pub fn parse_url(url: &str) -> Option<Url> {
+    let url = if url.contains("://") {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("http://{url}"))
+    }
+    .ok()?;
+
+    (matches!(url.scheme(), "http" | "https") && url.host_str().is_some()).then_some(url)
+}
+
+/// Records a request for `url` in the audit log, fetching the payload if enabled. Returns the
+/// payload if it was fetched successfully.
+pub async fn download(
+    connection: &mut ConnectionState,
+    tool: &'static str,
+    user_agent: &str,
+    url: &Url,
+    output: Option<&str>,
+) -> Option<Bytes> {
+    let config = connection.config().download.clone();
+
+    let (content, error) = if config.fetch {
+        match fetch(&config, user_agent, url.clone()).await {
+            Ok(content) => (Some(content), None),
+            Err(error) => {
+                debug!(%error, %url, "Failed to fetch payload");
+                (None, Some(error.to_string().into_boxed_str()))
+            }
+        }
+    } else {
+        (None, None)
+    };
+
+    let sha256 = content.as_deref().map(sha256_hex);
+
+    if let (Some(content), Some(sha256)) = (&content, &sha256) {
+        let origin = Origin {
+            tool,
+            name: output,
+            url: Some(url.as_str()),
+        };
+        quarantine::store(connection, sha256, content.clone(), &origin);
+    }
+
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::DownloadAttempt(DownloadAttemptEvent {
+            tool: Cow::Borrowed(tool),
+            url: Box::from(url.as_str()),
+            output: output.map(Box::from),
+            sha256,
+            size: content.as_ref().map(Bytes::len),
+            error,
+        }));
+
+    content
+}
+
+async fn fetch(config: &DownloadConfig, user_agent: &str, url: Url) -> anyhow::Result<Bytes> {
+    tokio::time::timeout(
+        Duration::from_secs(config.timeout),
+        fetch_inner(config.max_size, user_agent, url),
+    )
+    .await
+    .map_err(|_| anyhow!("timed out after {}s", config.timeout))?
+}
+
+async fn fetch_inner(max_size: usize, user_agent: &str, mut url: Url) -> anyhow::Result<Bytes> {
+    for _ in 0..=MAX_REDIRECTS {
+        let addr = resolve(&url).await?;
+
+        // redirects are followed manually so each hop can be resolved and checked, and the
+        // address we checked is pinned so the host can't be rebound between the check and
+        // the request
+        let mut client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .user_agent(user_agent);
+
+        if let Some(host) = url.domain() {
+            client = client.resolve(host, addr);
+        }
+
+        let mut response = client.build()?.get(url.clone()).send().await?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| anyhow!("redirect without a location"))?;
+            url = url.join(location)?;
+            continue;
+        }
+
+        if !response.status().is_success() {
+            bail!("server responded with {}", response.status());
+        }
+
+        if response
+            .content_length()
+            .map_or(false, |v| v > max_size as u64)
+        {
+            bail!("payload is larger than {max_size} bytes");
+        }
+
+        let mut content = BytesMut::new();
+
+        while let Some(chunk) = response.chunk().await? {
+            if content.len() + chunk.len() > max_size {
+                bail!("payload is larger than {max_size} bytes");
+            }
+
+            content.extend_from_slice(&chunk);
+        }
+
+        return Ok(content.freeze());
+    }
+
+    bail!("exceeded {MAX_REDIRECTS} redirects")
+}
+
+/// Resolves the host `url` points to, refusing anything that isn't publicly routable so we
+/// can't be used to reach services on our own network.
+async fn resolve(url: &Url) -> anyhow::Result<SocketAddr> {
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("unknown port"))?;
+    let host = url.host_str().ok_or_else(|| anyhow!("missing host"))?;
+
+    let addr = if let Ok(ip) = host.trim_matches(['[', ']'].as_slice()).parse::<IpAddr>() {
+        SocketAddr::new(ip, port)
+    } else {
+        tokio::net::lookup_host((host, port))
+            .await?
+            .next()
+            .ok_or_else(|| anyhow!("failed to resolve {host}"))?
+    };
+
+    if !is_public(addr.ip()) {
+        bail!("refusing to connect to non-public address {}", addr.ip());
+    }
+
+    Ok(addr)
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            // all of 0.0.0.0/8 reaches the local host on Linux, not just 0.0.0.0
+            let this_network = a == 0;
+            let shared = a == 100 && (b & 0xc0) == 64;
+            let benchmarking = a == 198 && (b & 0xfe) == 18;
+            // includes the broadcast address
+            let reserved = a >= 240;
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || this_network
+                || shared
+                || benchmarking
+                || reserved)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+
+            // NAT64 and 6to4 addresses are passed on to the IPv4 address they embed
+            let octets = ip.octets();
+            if octets[..12] == [0, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0] {
+                let [.., a, b, c, d] = octets;
+                return is_public(IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+            }
+            if octets[..2] == [0x20, 0x02] {
+                return is_public(IpAddr::V4(Ipv4Addr::new(
+                    octets[2], octets[3], octets[4], octets[5],
+                )));
+            }
+
+            let segment = ip.segments()[0];
+            let unique_local = (segment & 0xfe00) == 0xfc00;
+            let link_local = (segment & 0xffc0) == 0xfe80;
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || unique_local
+                || link_local)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use test_case::test_case;
+
+    use super::{is_public, parse_url};
+
+    #[test_case("http://example.com/x.sh", Some("http://example.com/x.sh"); "full url")]
+    #[test_case("example.com/x.sh", Some("http://example.com/x.sh"); "missing scheme")]
+    #[test_case("1.2.3.4:8080/bins/x86", Some("http://1.2.3.4:8080/bins/x86"); "ip with port")]
+    #[test_case("ftp://example.com/x.sh", None; "unsupported scheme")]
+    #[test_case("http://", None; "missing host")]
+    fn parses_url(input: &str, expected: Option<&str>) {
+        assert_eq!(
+            parse_url(input).as_ref().map(reqwest::Url::as_str),
+            expected
+        );
+    }
+
+    #[test_case("1.1.1.1", true; "public v4")]
+    #[test_case("127.0.0.1", false; "loopback v4")]
+    #[test_case("10.0.0.1", false; "private v4")]
+    #[test_case("169.254.169.254", false; "link local v4")]
+    #[test_case("100.64.0.1", false; "shared v4")]
+    #[test_case("0.0.0.0", false; "unspecified v4")]
+    #[test_case("0.1.2.3", false; "this network v4")]
+    #[test_case("198.18.0.1", false; "benchmarking v4")]
+    #[test_case("198.19.255.254", false; "end of benchmarking v4")]
+    #[test_case("198.20.0.1", true; "after benchmarking v4")]
+    #[test_case("240.0.0.1", false; "reserved v4")]
+    #[test_case("255.255.255.255", false; "broadcast v4")]
+    #[test_case("2606:4700::1111", true; "public v6")]
+    #[test_case("::1", false; "loopback v6")]
+    #[test_case("fd00::1", false; "unique local v6")]
+    #[test_case("::ffff:127.0.0.1", false; "mapped v4")]
+    #[test_case("64:ff9b::a00:1", false; "nat64 private v4")]
+    #[test_case("64:ff9b::7f00:1", false; "nat64 loopback v4")]
+    #[test_case("64:ff9b::101:101", true; "nat64 public v4")]
+    #[test_case("2002:a00:1::1", false; "6to4 private v4")]
+    #[test_case("2002:7f00:1::1", false; "6to4 loopback v4")]
+    #[test_case("2002:101:101::1", true; "6to4 public v4")]
+    fn public_addresses(ip: &str, expected: bool) {
+        assert_eq!(is_public(ip.parse::<IpAddr>().unwrap()), expected);
+    }
+}
@@ -0,0 +1,234 @@
+//! Encrypts the lines the file sink writes to [age] X25519 recipients, so credentials and
+//! payload details aren't left readable on an internet-facing host. Only the recipients' public
+//! keys are needed to write logs, the identities to read them back can be kept elsewhere.
+//!
+//! Each line is encrypted on its own, as an age file can't be appended to once it's finished,
+//! which keeps files readable up to the last line written however the server stops. Lines are
+//! written as `{"age": "..."}` with the age file base64-encoded, leaving room for the fields
+//! hash chaining adds so encrypted files can be verified without decrypting them.
+//!
+//! [age]: https://age-encryption.org
+
+use std::{
+    fs::File,
+    io::{BufRead, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use age::x25519;
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{audit::AuditLog, report};
+
+/// An encrypted audit log.
+#[derive(Serialize, Deserialize)]
+pub struct Sealed {
+    /// The log as JSON, encrypted as a binary age file and base64-encoded.
+    pub age: Box<str>,
+}
+
+/// Encrypts logs to the configured recipients.
+pub struct Encryption {
+    recipients: Vec<x25519::Recipient>,
+}
+
+impl Encryption {
+    /// Parses each of `recipients`, which are age public keys of the form `age1...`.
+    pub fn new(recipients: &[String]) -> Result<Self, std::io::Error> {
+        let recipients = recipients
+            .iter()
+            .map(|v| parse_recipient(v))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, e))?;
+
+        Ok(Self { recipients })
+    }
+
+    pub fn seal(&self, log: &AuditLog) -> Result<Sealed, std::io::Error> {
+        let json = serde_json::to_vec(log).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        let recipients = self
+            .recipients
+            .iter()
+            .map(|v| Box::new(v.clone()) as Box<dyn age::Recipient + Send>)
+            .collect();
+        let encryptor = age::Encryptor::with_recipients(recipients).ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidInput, "no recipients to encrypt to")
+        })?;
+
+        let mut encrypted = Vec::new();
+        let mut writer = encryptor.wrap_output(&mut encrypted)?;
+        writer.write_all(&json)?;
+        writer.finish()?;
+
+        Ok(Sealed {
+            age: STANDARD.encode(encrypted).into_boxed_str(),
+        })
+    }
+}
+
+/// Parses an age public key, describing what's wrong with it if it isn't one.
+pub fn parse_recipient(recipient: &str) -> Result<x25519::Recipient, String> {
+    recipient
+        .parse::<x25519::Recipient>()
+        .map_err(|e| format!("invalid age recipient {recipient:?}: {e}"))
+}
+
+/// Decrypts the audit logs in `paths`, each of which can be a file or a directory of them, with
+/// the age identities in the file at `identity`, writing them out to `output` or stdout if it
+/// isn't set. Lines that weren't encrypted are written out as they are.
+pub fn run(paths: &[PathBuf], identity: &Path, output: Option<&Path>) -> anyhow::Result<()> {
+    let identities = read_identities(identity)?;
+
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).with_context(|| {
+                format!("failed to create {}", path.display())
+            })?))
+        }
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let mut failed_lines = 0;
+
+    for path in paths {
+        for file in report::files(path)? {
+            let reader = report::open(&file)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            failed_lines += decrypt(reader, &identities, &mut out)
+                .with_context(|| format!("failed to decrypt {}", file.display()))?;
+        }
+    }
+
+    out.flush()?;
+
+    if failed_lines > 0 {
+        return Err(anyhow!(
+            "{failed_lines} lines couldn't be decrypted with the given identities"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Reads the identities out of an age identity file, as written by `age-keygen`.
+fn read_identities(path: &Path) -> anyhow::Result<Vec<x25519::Identity>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let identities = contents
+        .lines()
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && !v.starts_with('#'))
+        .map(|v| {
+            v.parse::<x25519::Identity>()
+                .map_err(|e| anyhow!("invalid identity in {}: {e}", path.display()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if identities.is_empty() {
+        return Err(anyhow!("no identities in {}", path.display()));
+    }
+
+    Ok(identities)
+}
+
+/// Writes out each line of `reader` decrypted, returning the number of encrypted lines that
+/// couldn't be decrypted, which are left out.
+fn decrypt(
+    reader: impl BufRead,
+    identities: &[x25519::Identity],
+    out: &mut impl Write,
+) -> std::io::Result<usize> {
+    let mut failed_lines = 0;
+
+    for line in reader.split(b'\n') {
+        let line = line?;
+
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        let Ok(sealed) = serde_json::from_slice::<Sealed>(&line) else {
+            out.write_all(&line)?;
+            out.write_all(b"\n")?;
+            continue;
+        };
+
+        match unseal(&sealed, identities) {
+            Some(log) => {
+                out.write_all(&log)?;
+                out.write_all(b"\n")?;
+            }
+            None => failed_lines += 1,
+        }
+    }
+
+    Ok(failed_lines)
+}
+
+/// Decrypts a sealed log back into its JSON, if one of `identities` is able to.
+fn unseal(sealed: &Sealed, identities: &[x25519::Identity]) -> Option<Vec<u8>> {
+    let encrypted = STANDARD.decode(sealed.age.as_bytes()).ok()?;
+
+    let age::Decryptor::Recipients(decryptor) = age::Decryptor::new(&encrypted[..]).ok()? else {
+        return None;
+    };
+
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|v| v as &dyn age::Identity))
+        .ok()?;
+
+    let mut log = Vec::new();
+    reader.read_to_end(&mut log).ok()?;
+    Some(log)
+}
+
+#[cfg(test)]
+mod test {
+    use age::x25519;
+
+    use super::{decrypt, Encryption};
+    use crate::audit::AuditLog;
+
+    #[test]
+    fn round_trips() {
+        let identity = x25519::Identity::generate();
+        let other = x25519::Identity::generate();
+
+        let encryption = Encryption::new(&[identity.to_public().to_string()]).unwrap();
+        let log = AuditLog {
+            host: "honeypot".into(),
+            ..AuditLog::default()
+        };
+
+        let sealed = serde_json::to_string(&encryption.seal(&log).unwrap()).unwrap();
+        assert!(!sealed.contains("honeypot"));
+
+        let input = format!("{sealed}\n{{\"plain\":true}}\n");
+
+        let mut out = Vec::new();
+        let failed = decrypt(input.as_bytes(), &[identity], &mut out).unwrap();
+        assert_eq!(failed, 0);
+
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "{\"plain\":true}");
+
+        let decrypted: AuditLog = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(decrypted.host, "honeypot");
+
+        // nothing comes out for identities the log wasn't encrypted to
+        let mut out = Vec::new();
+        let failed = decrypt(sealed.as_bytes(), &[other], &mut out).unwrap();
+        assert_eq!(failed, 1);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_recipients() {
+        assert!(Encryption::new(&["age1nope".to_string()]).is_err());
+    }
+}
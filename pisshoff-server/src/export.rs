@@ -0,0 +1,314 @@
+//! Flattens audit logs into a table of one row per event, with the connection's details
+//! repeated on each of its rows, so they can be loaded straight into pandas, DuckDB or a
+//! spreadsheet.
+
+#[cfg(feature = "parquet")]
+mod parquet_writer;
+
+use std::{
+    fs::File,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+#[cfg(feature = "parquet")]
+use self::parquet_writer::ParquetWriter;
+use crate::{
+    audit::{AuditLog, AuditLogAction, LoginAttemptEvent},
+    report,
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    /// Only available when built with the `parquet` feature.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// Writes a row for each event in the audit logs in `paths`, each of which can be a file or a
+/// directory of them, to `output` or stdout if it isn't set.
+pub fn run(paths: &[PathBuf], format: ExportFormat, output: Option<&Path>) -> anyhow::Result<()> {
+    let out: Box<dyn Write + Send> = match output {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+        ),
+        None => Box::new(std::io::stdout()),
+    };
+
+    let mut writer = match format {
+        ExportFormat::Csv => Writer::Csv(csv::Writer::from_writer(out)),
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => Writer::Parquet(ParquetWriter::new(out)?),
+    };
+
+    let mut skipped_lines = 0;
+
+    for path in paths {
+        for file in report::files(path)? {
+            let reader = report::open(&file)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            skipped_lines += export(reader, &mut writer)
+                .with_context(|| format!("failed to export {}", file.display()))?;
+        }
+    }
+
+    writer.finish()?;
+
+    if skipped_lines > 0 {
+        eprintln!("Skipped {skipped_lines} lines that couldn't be read as audit logs");
+    }
+
+    Ok(())
+}
+
+/// Writes out the rows for each audit log in `reader`, returning the number of lines that
+/// couldn't be read as one.
+fn export<W: Write + Send>(reader: impl BufRead, writer: &mut Writer<W>) -> anyhow::Result<usize> {
+    let mut skipped_lines = 0;
+
+    for line in reader.split(b'\n') {
+        let line = line?;
+
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        let Ok(log) = serde_json::from_slice::<AuditLog>(&line) else {
+            skipped_lines += 1;
+            continue;
+        };
+
+        for row in rows(&log) {
+            writer.write(row)?;
+        }
+    }
+
+    Ok(skipped_lines)
+}
+
+/// A single event, along with the connection it happened on.
+#[derive(Debug, Clone, Serialize)]
+struct Row {
+    connection_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    connection_ts: OffsetDateTime,
+    host: String,
+    protocol: &'static str,
+    peer_ip: Option<String>,
+    peer_port: Option<u16>,
+    country: Option<String>,
+    asn: Option<u32>,
+    org: Option<String>,
+    reverse_dns: Option<String>,
+    client_version: Option<String>,
+    duration_ms: Option<u64>,
+    /// Tags the connection was given, separated by commas.
+    tags: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    event_ts: Option<OffsetDateTime>,
+    offset_ms: Option<u64>,
+    channel: Option<u32>,
+    event_type: Option<&'static str>,
+    username: Option<String>,
+    password: Option<String>,
+    command: Option<String>,
+    /// The whole event as JSON, for the fields that don't have a column of their own.
+    details: Option<String>,
+}
+
+/// Flattens `log` into a row per event. Connections without any events still get a row, so
+/// they're counted.
+fn rows(log: &AuditLog) -> Vec<Row> {
+    let connection = Row {
+        connection_id: log.connection_id.to_string(),
+        connection_ts: log.ts,
+        host: log.host.to_string(),
+        protocol: log.protocol.into(),
+        peer_ip: log.peer_address.map(|v| v.ip().to_string()),
+        peer_port: log.peer_address.map(|v| v.port()),
+        country: log
+            .geoip
+            .as_ref()
+            .and_then(|v| v.country.as_deref().map(str::to_string)),
+        asn: log.geoip.as_ref().and_then(|v| v.asn),
+        org: log
+            .geoip
+            .as_ref()
+            .and_then(|v| v.org.as_deref().map(str::to_string)),
+        reverse_dns: log.reverse_dns.as_deref().map(str::to_string),
+        client_version: log.client_handshake.as_ref().map(|v| v.version.to_string()),
+        duration_ms: log.duration_ms,
+        tags: (!log.tags.is_empty()).then(|| log.tags.join(",")),
+        event_ts: None,
+        offset_ms: None,
+        channel: None,
+        event_type: None,
+        username: None,
+        password: None,
+        command: None,
+        details: None,
+    };
+
+    if log.events.is_empty() {
+        return vec![connection];
+    }
+
+    log.events
+        .iter()
+        .map(|event| {
+            let mut row = connection.clone();
+            row.event_ts = event.ts;
+            row.offset_ms = Some(event.offset_ms);
+            row.channel = event.channel;
+            row.event_type = Some((&event.action).into());
+            row.details = serde_json::to_string(&event.action).ok();
+
+            match &event.action {
+                AuditLogAction::LoginAttempt(LoginAttemptEvent::UsernamePassword {
+                    username,
+                    password,
+                }) => {
+                    row.username = Some(username.to_string());
+                    row.password = Some(password.to_string());
+                }
+                AuditLogAction::ExecCommand(command) => {
+                    row.command = Some(command.args.join(" "));
+                }
+                _ => {}
+            }
+
+            row
+        })
+        .collect()
+}
+
+enum Writer<W: Write + Send> {
+    Csv(csv::Writer<W>),
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetWriter<W>),
+}
+
+impl<W: Write + Send> Writer<W> {
+    fn write(&mut self, row: Row) -> anyhow::Result<()> {
+        match self {
+            Self::Csv(writer) => writer.serialize(row)?,
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer.write(row)?,
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Self::Csv(mut writer) => writer.flush()?,
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer.finish()?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use time::macros::datetime;
+
+    use super::{export, rows, Writer};
+    use crate::audit::{AuditLog, AuditLogAction, ExecCommandEvent, LoginAttemptEvent};
+
+    fn log() -> AuditLog {
+        let mut log = AuditLog {
+            connection_id: uuid::Uuid::from_bytes([
+                1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+            ]),
+            ts: datetime!(2023-06-01 14:30 UTC),
+            host: Cow::Borrowed("test"),
+            peer_address: Some(([127, 0, 0, 1], 1234).into()),
+            ..AuditLog::default()
+        };
+
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: Box::from("root"),
+                password: Box::from("hunter2"),
+            },
+        ));
+        log.push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+            args: Box::from(["uname -a".to_string()]),
+        }));
+
+        log
+    }
+
+    #[test]
+    fn flattens_events() {
+        let rows = rows(&log());
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].event_type, Some("login-attempt"));
+        assert_eq!(rows[0].username.as_deref(), Some("root"));
+        assert_eq!(rows[0].password.as_deref(), Some("hunter2"));
+        assert_eq!(rows[1].event_type, Some("exec-command"));
+        assert_eq!(rows[1].command.as_deref(), Some("uname -a"));
+        assert_eq!(rows[1].peer_ip.as_deref(), Some("127.0.0.1"));
+
+        assert_eq!(rows(&AuditLog::default()).len(), 1);
+    }
+
+    #[test]
+    fn writes_csv() {
+        let input = format!("{}\nnot json\n", serde_json::to_string(&log()).unwrap());
+
+        let mut out = Vec::new();
+        let mut writer = Writer::Csv(csv::Writer::from_writer(&mut out));
+        let skipped = export(input.as_bytes(), &mut writer).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(skipped, 1);
+
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("connection_id,connection_ts,host,"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("01020304-0506-0708-090a-0b0c0d0e0f10,2023-06-01T14:30:00Z,test,ssh,"));
+        assert_eq!(lines.count(), 1);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn writes_parquet() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        use super::ParquetWriter;
+
+        let mut out = Vec::new();
+        let mut writer = Writer::Parquet(ParquetWriter::new(&mut out).unwrap());
+
+        for row in rows(&log()) {
+            writer.write(row).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(out))
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows = reader.map(|batch| batch.unwrap().num_rows()).sum::<usize>();
+
+        assert_eq!(rows, 2);
+    }
+}
@@ -0,0 +1,133 @@
+//! Writes exported rows out as Parquet, batching them up into row groups.
+
+use std::{io::Write, sync::Arc};
+
+use arrow_array::{
+    ArrayRef, RecordBatch, StringArray, TimestampMillisecondArray, UInt16Array, UInt32Array,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::{
+    arrow::ArrowWriter, basic::Compression, errors::ParquetError,
+    file::properties::WriterProperties,
+};
+use time::OffsetDateTime;
+
+use super::Row;
+
+/// Number of rows buffered up before they're written out as a Parquet row group.
+const BATCH_SIZE: usize = 8192;
+
+/// Buffers rows up into batches to write out as Parquet row groups.
+pub struct ParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    rows: Vec<Row>,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(out: W) -> Result<Self, ParquetError> {
+        let timestamp = DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()));
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("connection_id", DataType::Utf8, false),
+            Field::new("connection_ts", timestamp.clone(), false),
+            Field::new("host", DataType::Utf8, false),
+            Field::new("protocol", DataType::Utf8, false),
+            Field::new("peer_ip", DataType::Utf8, true),
+            Field::new("peer_port", DataType::UInt16, true),
+            Field::new("country", DataType::Utf8, true),
+            Field::new("asn", DataType::UInt32, true),
+            Field::new("org", DataType::Utf8, true),
+            Field::new("reverse_dns", DataType::Utf8, true),
+            Field::new("client_version", DataType::Utf8, true),
+            Field::new("duration_ms", DataType::UInt64, true),
+            Field::new("tags", DataType::Utf8, true),
+            Field::new("event_ts", timestamp, true),
+            Field::new("offset_ms", DataType::UInt64, true),
+            Field::new("channel", DataType::UInt32, true),
+            Field::new("event_type", DataType::Utf8, true),
+            Field::new("username", DataType::Utf8, true),
+            Field::new("password", DataType::Utf8, true),
+            Field::new("command", DataType::Utf8, true),
+            Field::new("details", DataType::Utf8, true),
+        ]));
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+
+        Ok(Self {
+            writer: ArrowWriter::try_new(out, schema.clone(), Some(properties))?,
+            schema,
+            rows: Vec::with_capacity(BATCH_SIZE),
+        })
+    }
+
+    pub fn write(&mut self, row: Row) -> Result<(), ParquetError> {
+        self.rows.push(row);
+
+        if self.rows.len() >= BATCH_SIZE {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), ParquetError> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let rows = std::mem::take(&mut self.rows);
+
+        let columns: Vec<ArrayRef> = vec![
+            strings(&rows, |v| Some(v.connection_id.as_str())),
+            timestamps(&rows, |v| Some(v.connection_ts)),
+            strings(&rows, |v| Some(v.host.as_str())),
+            strings(&rows, |v| Some(v.protocol)),
+            strings(&rows, |v| v.peer_ip.as_deref()),
+            Arc::new(rows.iter().map(|v| v.peer_port).collect::<UInt16Array>()),
+            strings(&rows, |v| v.country.as_deref()),
+            Arc::new(rows.iter().map(|v| v.asn).collect::<UInt32Array>()),
+            strings(&rows, |v| v.org.as_deref()),
+            strings(&rows, |v| v.reverse_dns.as_deref()),
+            strings(&rows, |v| v.client_version.as_deref()),
+            Arc::new(rows.iter().map(|v| v.duration_ms).collect::<UInt64Array>()),
+            strings(&rows, |v| v.tags.as_deref()),
+            timestamps(&rows, |v| v.event_ts),
+            Arc::new(rows.iter().map(|v| v.offset_ms).collect::<UInt64Array>()),
+            Arc::new(rows.iter().map(|v| v.channel).collect::<UInt32Array>()),
+            strings(&rows, |v| v.event_type),
+            strings(&rows, |v| v.username.as_deref()),
+            strings(&rows, |v| v.password.as_deref()),
+            strings(&rows, |v| v.command.as_deref()),
+            strings(&rows, |v| v.details.as_deref()),
+        ];
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)
+    }
+
+    pub fn finish(mut self) -> Result<(), ParquetError> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+fn strings<'a>(rows: &'a [Row], f: impl Fn(&'a Row) -> Option<&'a str>) -> ArrayRef {
+    Arc::new(rows.iter().map(f).collect::<StringArray>())
+}
+
+fn timestamps(rows: &[Row], f: impl Fn(&Row) -> Option<OffsetDateTime>) -> ArrayRef {
+    let array = rows
+        .iter()
+        .map(|row| {
+            f(row)
+                .map(|ts| i64::try_from(ts.unix_timestamp_nanos() / 1_000_000).unwrap_or(i64::MAX))
+        })
+        .collect::<TimestampMillisecondArray>();
+
+    Arc::new(array.with_timezone("UTC"))
+}
@@ -1,36 +1,105 @@
 #![allow(dead_code)]
 
 use std::{
-    borrow::Cow,
-    collections::{btree_map::Entry, BTreeMap},
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
     fmt::{Display, Formatter},
-    path::{Path, PathBuf},
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
 };
 
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
 /// A fake file system, stored in memory only active for the current session.
 pub struct FileSystem {
     pwd: PathBuf,
     home: PathBuf,
     data: Tree,
+    /// Metadata of the paths the client has changed it for, keyed by their canonical path.
+    metadata: BTreeMap<PathBuf, Metadata>,
+}
+
+/// What `chmod` and `chattr` have been used to change about a file or directory.
+#[derive(Clone, Debug, Default)]
+struct Metadata {
+    mode: Option<u32>,
+    /// Attributes as set by `chattr`, ie. `i` if the file is immutable.
+    attributes: BTreeSet<char>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Tree {
     Directory(BTreeMap<String, Box<Tree>>),
     File(Box<[u8]>),
 }
 
+impl Tree {
+    /// Loads a snapshot of a file system to seed each session's file system with. Snapshots
+    /// are JSON documents in which objects are directories and strings are the contents of
+    /// files, ie. `{"etc": {"hostname": "web01\n"}}`. The top-level object is the root
+    /// directory.
+    pub fn load_snapshot(path: &Path) -> Result<Self, std::io::Error> {
+        let file = std::fs::read(path)?;
+        let value =
+            serde_json::from_slice(&file).map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        match Self::from_json(value) {
+            Some(v @ Self::Directory(_)) => Ok(v),
+            _ => Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "file system snapshot must be a JSON object of directories and files",
+            )),
+        }
+    }
+
+    fn from_json(value: Value) -> Option<Self> {
+        match value {
+            Value::Object(entries) => entries
+                .into_iter()
+                .map(|(name, value)| Some((name, Box::new(Self::from_json(value)?))))
+                .collect::<Option<_>>()
+                .map(Self::Directory),
+            Value::String(content) => Some(Self::File(content.into_bytes().into_boxed_slice())),
+            _ => None,
+        }
+    }
+
+    /// Writes a file to `path` within the tree, creating any missing parent directories.
+    /// Existing entries take precedence, so are left untouched.
+    pub fn insert_default(&mut self, path: &Path, content: &[u8]) {
+        let mut tree = self;
+        let mut components = names(path).peekable();
+
+        while let Some(name) = components.next() {
+            let Self::Directory(entries) = tree else {
+                return;
+            };
+
+            if components.peek().is_none() {
+                entries
+                    .entry(name.to_string())
+                    .or_insert_with(|| Box::new(Self::File(Box::from(content))));
+                return;
+            }
+
+            tree = entries
+                .entry(name.to_string())
+                .or_insert_with(|| Box::new(Self::Directory(BTreeMap::new())));
+        }
+    }
+}
+
 impl FileSystem {
-    pub fn new(user: &str) -> Self {
-        let pwd = if user == "root" {
-            PathBuf::from("/root")
-        } else {
-            PathBuf::from("/home").join(user)
-        };
+    pub fn new(user: &str, seed: Option<&Tree>) -> Self {
+        let pwd = home_of(user);
 
         let mut this = Self {
             home: pwd.clone(),
             pwd,
-            data: Tree::Directory(BTreeMap::new()),
+            data: seed
+                .cloned()
+                .unwrap_or_else(|| Tree::Directory(BTreeMap::new())),
+            metadata: BTreeMap::new(),
         };
 
         let _res = this.mkdirall(&this.pwd.clone());
@@ -38,13 +107,14 @@ impl FileSystem {
     }
 
     pub fn mkdirall(&mut self, path: &Path) -> Result<(), LsError> {
+        let canonical = self.canonicalize(path);
         let mut tree = &mut self.data;
 
-        for c in path {
+        for c in names(&canonical) {
             match tree {
                 Tree::Directory(d) => {
                     tree = d
-                        .entry(c.to_str().unwrap().to_string())
+                        .entry(c.to_string())
                         .or_insert_with(|| Box::new(Tree::Directory(BTreeMap::new())));
                 }
                 Tree::File(_) => return Err(LsError::FileExists),
@@ -54,28 +124,106 @@ impl FileSystem {
         Ok(())
     }
 
-    pub fn cd(&mut self, v: Option<&str>) {
-        if let Some(v) = v {
-            self.pwd.push(v);
-        } else {
+    /// Changes the working directory to `v`, or the user's home directory if `None`.
+    pub fn cd(&mut self, v: Option<&Path>) -> Result<(), LsError> {
+        let Some(v) = v else {
             self.pwd = self.home.clone();
+            return Ok(());
+        };
+
+        let canonical = self.canonicalize(v);
+
+        match self.get(&canonical)? {
+            Tree::Directory(_) => {
+                self.pwd = canonical;
+                Ok(())
+            }
+            Tree::File(_) => Err(LsError::NotDirectory),
         }
     }
 
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// Moves to `user`'s home directory as a login shell would, creating it if need be.
+    pub fn login(&mut self, user: &str) {
+        self.home = home_of(user);
+        self.pwd = self.home.clone();
+        let _res = self.mkdirall(&self.pwd.clone());
+    }
+
     pub fn pwd(&self) -> &Path {
         &self.pwd
     }
 
-    pub fn read(&self, path: &Path) -> Result<&[u8], LsError> {
-        let canonical = self.pwd().join(path);
+    pub fn into_tree(self) -> Tree {
+        self.data
+    }
+
+    /// Resolves `path` against the current working directory, or the home directory if it
+    /// starts with `~`, collapsing any `.` and `..` components. The returned path is always
+    /// absolute.
+    pub fn canonicalize(&self, path: &Path) -> PathBuf {
+        let mut out = PathBuf::from("/");
+
+        let path = match path.strip_prefix("~") {
+            Ok(rest) => self.home.join(rest),
+            Err(_) => self.pwd.join(path),
+        };
+
+        for c in path.components() {
+            match c {
+                Component::Prefix(_) | Component::RootDir => out = PathBuf::from("/"),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    out.pop();
+                }
+                Component::Normal(v) => out.push(v),
+            }
+        }
+
+        out
+    }
+
+    /// Looks up the node at `path`, which may be either a file or a directory.
+    pub fn get(&self, path: &Path) -> Result<&Tree, LsError> {
+        let canonical = self.canonicalize(path);
         let mut tree = &self.data;
 
-        for c in &canonical {
+        for c in names(&canonical) {
             match tree {
                 Tree::Directory(d) => {
-                    tree = d
-                        .get(c.to_str().unwrap())
-                        .ok_or(LsError::NoSuchFileOrDirectory)?;
+                    tree = d.get(c).ok_or(LsError::NoSuchFileOrDirectory)?;
+                }
+                Tree::File(_) => {
+                    return Err(LsError::NotDirectory);
+                }
+            }
+        }
+
+        Ok(tree)
+    }
+
+    /// Looks up the directory containing `path`, returning it along with the final component
+    /// of the path.
+    fn parent_mut(
+        &mut self,
+        path: &Path,
+    ) -> Result<(&mut BTreeMap<String, Box<Tree>>, String), LsError> {
+        let canonical = self.canonicalize(path);
+
+        let Some(name) = canonical.file_name().and_then(|v| v.to_str()) else {
+            return Err(LsError::IsADirectory);
+        };
+        let name = name.to_string();
+
+        let mut tree = &mut self.data;
+
+        for c in canonical.parent().into_iter().flat_map(names) {
+            match tree {
+                Tree::Directory(d) => {
+                    tree = d.get_mut(c).ok_or(LsError::NoSuchFileOrDirectory)?;
                 }
                 Tree::File(_) => {
                     return Err(LsError::NotDirectory);
@@ -84,93 +232,291 @@ impl FileSystem {
         }
 
         match tree {
+            Tree::Directory(d) => Ok((d, name)),
+            Tree::File(_) => Err(LsError::NotDirectory),
+        }
+    }
+
+    pub fn read(&self, path: &Path) -> Result<&[u8], LsError> {
+        match self.get(path)? {
             Tree::Directory(_) => Err(LsError::IsADirectory),
             Tree::File(content) => Ok(content),
         }
     }
 
     pub fn write(&mut self, path: &Path, content: Box<[u8]>) -> Result<(), LsError> {
-        let canonical = self.pwd().join(path);
-        let mut tree = &mut self.data;
+        self.protected(path, false)?;
+        self.write_unchecked(path, content)
+    }
 
-        if let Some(parents) = canonical.parent() {
-            for c in parents {
-                match tree {
-                    Tree::Directory(d) => {
-                        tree = d
-                            .get_mut(c.to_str().unwrap())
-                            .ok_or(LsError::NoSuchFileOrDirectory)?;
-                    }
-                    Tree::File(_) => {
-                        return Err(LsError::NotDirectory);
-                    }
-                }
+    fn write_unchecked(&mut self, path: &Path, content: Box<[u8]>) -> Result<(), LsError> {
+        let (parent, name) = self.parent_mut(path)?;
+
+        match parent.entry(name) {
+            Entry::Vacant(v) => {
+                v.insert(Box::new(Tree::File(content)));
+                Ok(())
+            }
+            Entry::Occupied(mut o) if matches!(o.get().as_ref(), Tree::File(_)) => {
+                o.insert(Box::new(Tree::File(content)));
+                Ok(())
             }
+            Entry::Occupied(_) => Err(LsError::IsADirectory),
         }
+    }
 
-        match tree {
-            Tree::Directory(v) => {
-                match v.entry(
-                    canonical
-                        .components()
-                        .next_back()
-                        .unwrap()
-                        .as_os_str()
-                        .to_str()
-                        .unwrap()
-                        .to_string(),
-                ) {
-                    Entry::Vacant(v) => {
-                        v.insert(Box::new(Tree::File(content)));
-                        Ok(())
-                    }
-                    Entry::Occupied(mut o) if matches!(o.get().as_ref(), Tree::File(_)) => {
-                        o.insert(Box::new(Tree::File(content)));
-                        Ok(())
-                    }
-                    Entry::Occupied(_) => Err(LsError::IsADirectory),
-                }
+    /// Removes the file at `path`, directories are left untouched.
+    pub fn remove(&mut self, path: &Path) -> Result<(), LsError> {
+        self.protected(path, false)?;
+        let (parent, name) = self.parent_mut(path)?;
+
+        match parent.entry(name) {
+            Entry::Occupied(o) if matches!(o.get().as_ref(), Tree::File(_)) => {
+                o.remove();
             }
-            Tree::File(_) => Err(LsError::NotDirectory),
+            Entry::Occupied(_) => return Err(LsError::IsADirectory),
+            Entry::Vacant(_) => return Err(LsError::NoSuchFileOrDirectory),
         }
+
+        self.forget(path);
+        Ok(())
     }
 
-    #[allow(clippy::unused_self)]
-    pub fn ls<'a>(&'a self, dir: Option<&'a Path>) -> Result<Vec<&'a str>, LsError> {
-        let canonical = if let Some(dir) = dir {
-            Cow::Owned(self.pwd().join(dir))
+    /// Removes the file or directory at `path`, along with all of its children.
+    pub fn remove_all(&mut self, path: &Path) -> Result<(), LsError> {
+        self.protected(path, false)?;
+        let (parent, name) = self.parent_mut(path)?;
+
+        parent.remove(&name).ok_or(LsError::NoSuchFileOrDirectory)?;
+
+        self.forget(path);
+        Ok(())
+    }
+
+    /// Creates a single directory at `path`, whose parent must already exist.
+    pub fn mkdir(&mut self, path: &Path) -> Result<(), LsError> {
+        let (parent, name) = self.parent_mut(path)?;
+
+        match parent.entry(name) {
+            Entry::Vacant(v) => {
+                v.insert(Box::new(Tree::Directory(BTreeMap::new())));
+                Ok(())
+            }
+            Entry::Occupied(_) => Err(LsError::FileExists),
+        }
+    }
+
+    /// Moves the file or directory at `from` to `to`, replacing any file that's already there.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> Result<(), LsError> {
+        let (from, to) = (self.canonicalize(from), self.canonicalize(to));
+
+        if to == from {
+            return Ok(());
+        } else if to.starts_with(&from) {
+            return Err(LsError::InvalidArgument);
+        }
+
+        self.protected(&from, false)?;
+        self.protected(&to, false)?;
+
+        let tree = self.get(&from)?.clone();
+        self.insert(&to, tree)?;
+        let (parent, name) = self.parent_mut(&from)?;
+        parent.remove(&name);
+
+        // metadata follows the files it belongs to
+        let moved = self
+            .metadata
+            .keys()
+            .filter(|v| v.starts_with(&from))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.forget(&to);
+
+        for path in moved {
+            if let Some(metadata) = self.metadata.remove(&path) {
+                let path = to.join(path.strip_prefix(&from).unwrap_or(&path));
+                self.metadata.insert(path, metadata);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies the file or directory at `from`, along with all of its children, to `to`.
+    pub fn copy(&mut self, from: &Path, to: &Path) -> Result<(), LsError> {
+        self.protected(to, false)?;
+        let tree = self.get(from)?.clone();
+        self.insert(to, tree)
+    }
+
+    fn insert(&mut self, path: &Path, tree: Tree) -> Result<(), LsError> {
+        let (parent, name) = self.parent_mut(path)?;
+
+        match (parent.get(&name).map(|v| &**v), &tree) {
+            (Some(Tree::Directory(_)), Tree::File(_)) => Err(LsError::IsADirectory),
+            (Some(Tree::File(_)), Tree::Directory(_)) => Err(LsError::NotDirectory),
+            _ => {
+                parent.insert(name, Box::new(tree));
+                Ok(())
+            }
+        }
+    }
+
+    /// Permission bits of the file or directory at `path`, which are the same as the default
+    /// `umask` would give unless they've since been changed.
+    pub fn mode(&self, path: &Path) -> Result<u32, LsError> {
+        let default = match self.get(path)? {
+            Tree::Directory(_) => 0o755,
+            Tree::File(_) => 0o644,
+        };
+
+        Ok(self
+            .metadata
+            .get(&self.canonicalize(path))
+            .and_then(|v| v.mode)
+            .unwrap_or(default))
+    }
+
+    pub fn set_mode(&mut self, path: &Path, mode: u32) -> Result<(), LsError> {
+        self.get(path)?;
+        self.protected(path, false)?;
+
+        self.metadata
+            .entry(self.canonicalize(path))
+            .or_default()
+            .mode = Some(mode);
+        Ok(())
+    }
+
+    /// Attributes of the file or directory at `path`, as `chattr` would set them.
+    pub fn attributes(&self, path: &Path) -> Result<BTreeSet<char>, LsError> {
+        self.get(path)?;
+
+        Ok(self
+            .metadata
+            .get(&self.canonicalize(path))
+            .map(|v| v.attributes.clone())
+            .unwrap_or_default())
+    }
+
+    pub fn set_attributes(
+        &mut self,
+        path: &Path,
+        attributes: BTreeSet<char>,
+    ) -> Result<(), LsError> {
+        self.get(path)?;
+
+        self.metadata
+            .entry(self.canonicalize(path))
+            .or_default()
+            .attributes = attributes;
+        Ok(())
+    }
+
+    /// Fails if `path` has been made immutable, or append-only unless it's being `appended` to.
+    fn protected(&self, path: &Path, appending: bool) -> Result<(), LsError> {
+        let Some(metadata) = self.metadata.get(&self.canonicalize(path)) else {
+            return Ok(());
+        };
+
+        if metadata.attributes.contains(&'i') || (!appending && metadata.attributes.contains(&'a'))
+        {
+            Err(LsError::OperationNotPermitted)
         } else {
-            Cow::Borrowed(self.pwd())
+            Ok(())
+        }
+    }
+
+    /// Drops any metadata for `path` and everything beneath it, once it's gone.
+    fn forget(&mut self, path: &Path) {
+        let path = self.canonicalize(path);
+        self.metadata.retain(|k, _| !k.starts_with(&path));
+    }
+
+    /// Creates an empty file at `path` if nothing exists there already.
+    pub fn touch(&mut self, path: &Path) -> Result<(), LsError> {
+        let (parent, name) = self.parent_mut(path)?;
+
+        parent
+            .entry(name)
+            .or_insert_with(|| Box::new(Tree::File(Box::default())));
+
+        Ok(())
+    }
+
+    /// Appends `content` to the file at `path`, creating it if it doesn't already exist.
+    pub fn append(&mut self, path: &Path, content: &[u8]) -> Result<(), LsError> {
+        self.protected(path, true)?;
+
+        let existing = match self.read(path) {
+            Ok(existing) => existing.to_vec(),
+            Err(LsError::NoSuchFileOrDirectory) => Vec::new(),
+            Err(e) => return Err(e),
         };
 
-        let mut tree = &self.data;
+        self.write_unchecked(
+            path,
+            [existing.as_slice(), content].concat().into_boxed_slice(),
+        )
+    }
 
-        for c in canonical.as_ref() {
-            match tree {
-                Tree::Directory(d) => {
-                    tree = d
-                        .get(c.to_str().unwrap())
-                        .ok_or(LsError::NoSuchFileOrDirectory)?;
-                }
-                Tree::File(_) => {
-                    return Err(LsError::NotDirectory);
+    /// Lists `path` and everything beneath it, with paths relative to `path` so it comes first
+    /// as an empty path. Parents always come before their children.
+    pub fn walk(&self, path: &Path) -> Result<Vec<(PathBuf, &Tree)>, LsError> {
+        fn visit<'a>(path: PathBuf, tree: &'a Tree, out: &mut Vec<(PathBuf, &'a Tree)>) {
+            if let Tree::Directory(children) = tree {
+                out.push((path.clone(), tree));
+
+                for (name, child) in children {
+                    visit(path.join(name), child, out);
                 }
+            } else {
+                out.push((path, tree));
             }
         }
 
-        match tree {
+        let mut out = Vec::new();
+        visit(PathBuf::new(), self.get(path)?, &mut out);
+        Ok(out)
+    }
+
+    #[allow(clippy::unused_self)]
+    pub fn ls<'a>(&'a self, dir: Option<&'a Path>) -> Result<Vec<&'a str>, LsError> {
+        match self.get(dir.unwrap_or(self.pwd()))? {
             Tree::Directory(v) => Ok(v.keys().map(String::as_str).collect()),
             Tree::File(_) => Ok(vec![dir.unwrap_or(self.pwd()).to_str().unwrap()]),
         }
     }
 }
 
+/// Names of the entries leading from the root of the tree down to `path`. The tree itself is
+/// the root directory, so it has no entry of its own.
+fn names(path: &Path) -> impl Iterator<Item = &str> {
+    path.components().filter_map(|c| match c {
+        Component::Normal(v) => v.to_str(),
+        _ => None,
+    })
+}
+
+/// Home directory of `user`, where `useradd` would have put it.
+fn home_of(user: &str) -> PathBuf {
+    if user == "root" {
+        PathBuf::from("/root")
+    } else {
+        PathBuf::from("/home").join(user)
+    }
+}
+
 #[derive(Debug)]
 pub enum LsError {
     NotDirectory,
     NoSuchFileOrDirectory,
     IsADirectory,
     FileExists,
+    OperationNotPermitted,
+    InvalidArgument,
 }
 
 impl Display for LsError {
@@ -180,6 +526,8 @@ impl Display for LsError {
             LsError::NotDirectory => "Not a directory",
             LsError::IsADirectory => "Is a directory",
             LsError::FileExists => "File exists",
+            LsError::OperationNotPermitted => "Operation not permitted",
+            LsError::InvalidArgument => "Invalid argument",
         })
     }
 }
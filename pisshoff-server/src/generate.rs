@@ -0,0 +1,176 @@
+//! Writes out the bundled example config to start from, optionally asking the operator a few
+//! questions to fill it in with first.
+
+use std::{
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{BufRead, ErrorKind, Write as _},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use strum::VariantNames;
+
+use crate::{config::Config, persona::Preset};
+
+/// The example config, with every option documented.
+const TEMPLATE: &str = include_str!("../config.toml");
+
+/// Values to fill the template in with, anything left unset keeps the example's value.
+#[derive(Default)]
+pub struct Answers {
+    pub listen_address: Option<SocketAddr>,
+    pub preset: Option<String>,
+    pub audit_output_file: Option<PathBuf>,
+}
+
+/// Writes a config to `output`, or to stdout if it isn't set, asking the questions on stderr
+/// first if `interactive` is set. An existing file is only replaced if `force` is set.
+pub fn run(output: Option<&Path>, interactive: bool, force: bool) -> anyhow::Result<()> {
+    let answers = if interactive {
+        ask(&mut std::io::stdin().lock())?
+    } else {
+        Answers::default()
+    };
+
+    let config = generate(&answers)?;
+
+    let Some(path) = output else {
+        print!("{config}");
+        return Ok(());
+    };
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => {
+                anyhow!(
+                    "{} already exists, pass --force to replace it",
+                    path.display()
+                )
+            }
+            _ => anyhow!(e).context(format!("failed to create {}", path.display())),
+        })?;
+    file.write_all(config.as_bytes())?;
+
+    eprintln!("Wrote config to {}", path.display());
+
+    Ok(())
+}
+
+/// Asks for each answer in turn, reading replies from `input`. Empty replies keep the
+/// example's value.
+fn ask(input: &mut impl BufRead) -> anyhow::Result<Answers> {
+    let port = prompt(input, "Port to listen on", "22", |v| v.parse::<u16>().ok())?;
+    let preset = prompt(
+        input,
+        &format!("Persona to pretend to be ({})", Preset::VARIANTS.join(", ")),
+        "container",
+        |v| Preset::VARIANTS.contains(&v).then(|| v.to_string()),
+    )?;
+    let audit_output_file = prompt(input, "Path to write audit logs to", "audit.jsonl", |v| {
+        Some(PathBuf::from(v))
+    })?;
+
+    Ok(Answers {
+        listen_address: Some(SocketAddr::from(([0, 0, 0, 0], port))),
+        preset: Some(preset),
+        audit_output_file: Some(audit_output_file),
+    })
+}
+
+/// Asks `question` until the reply is accepted by `parse`, using `default` for an empty one.
+fn prompt<T>(
+    input: &mut impl BufRead,
+    question: &str,
+    default: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> anyhow::Result<T> {
+    loop {
+        eprint!("{question} [{default}]: ");
+        std::io::stderr().flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(anyhow!("stdin closed before the config was finished"));
+        }
+
+        let reply = Some(line.trim())
+            .filter(|v| !v.is_empty())
+            .unwrap_or(default);
+
+        match parse(reply) {
+            Some(v) => return Ok(v),
+            None => eprintln!("{reply:?} isn't valid here"),
+        }
+    }
+}
+
+/// Fills the template in with `answers`, making sure the result still parses.
+pub fn generate(answers: &Answers) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(TEMPLATE.len());
+    let mut lines = TEMPLATE.lines();
+
+    while let Some(line) = lines.next() {
+        if let Some(address) = answers
+            .listen_address
+            .filter(|_| line.starts_with("listen-address = "))
+        {
+            writeln!(out, "listen-address = \"{address}\"")?;
+        } else if let Some(path) = answers
+            .audit_output_file
+            .as_ref()
+            .filter(|_| line.starts_with("audit-output-file = "))
+        {
+            let path = toml::Value::String(path.to_string_lossy().into_owned());
+            writeln!(out, "audit-output-file = {path}")?;
+        } else if let Some(preset) = answers.preset.as_ref().filter(|_| line == "# [persona]") {
+            // the example preset follows the table header, and is replaced along with it
+            lines.next();
+            let preset = toml::Value::String(preset.clone());
+            writeln!(out, "[persona]\npreset = {preset}")?;
+        } else {
+            writeln!(out, "{line}")?;
+        }
+    }
+
+    toml::from_str::<Config>(&out).context("generated config is invalid")?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, path::PathBuf};
+
+    use super::{ask, generate, Answers, TEMPLATE};
+    use crate::config::Config;
+
+    #[test]
+    fn example_is_unchanged_by_default() {
+        assert_eq!(generate(&Answers::default()).unwrap(), TEMPLATE);
+    }
+
+    #[test]
+    fn fills_in_answers() {
+        let mut input = "2222\nwindows\nbusybox\n/var/log/pisshoff/audit.jsonl\n".as_bytes();
+        let answers = ask(&mut input).unwrap();
+
+        let config = toml::from_str::<Config>(&generate(&answers).unwrap()).unwrap();
+
+        assert_eq!(
+            config.listen_address[0].address,
+            "0.0.0.0:2222".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(config.server_id(), "SSH-2.0-dropbear_2019.78");
+        assert_eq!(
+            config.audit_output_file,
+            PathBuf::from("/var/log/pisshoff/audit.jsonl")
+        );
+    }
+}
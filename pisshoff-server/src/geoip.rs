@@ -0,0 +1,69 @@
+use std::{io::ErrorKind, net::IpAddr, path::Path};
+
+use maxminddb::{geoip2, Reader};
+use tracing::info;
+
+use crate::{audit::GeoIp, config::GeoIpConfig};
+
+/// MaxMind databases peer addresses are looked up in when they connect.
+#[derive(Default)]
+pub struct GeoIpDatabase {
+    city: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIpDatabase {
+    pub fn open(config: &GeoIpConfig) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            city: config.city_database.as_deref().map(open).transpose()?,
+            asn: config.asn_database.as_deref().map(open).transpose()?,
+        })
+    }
+
+    /// Looks `ip` up in each of the configured databases, returning `None` if none of them
+    /// know anything about it - as is the case for private addresses.
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoIp> {
+        let mut out = GeoIp::default();
+
+        if let Some(city) = self
+            .city
+            .as_ref()
+            .and_then(|v| v.lookup::<geoip2::City<'_>>(ip).ok())
+        {
+            out.country = city.country.and_then(|v| v.iso_code).map(Box::from);
+            out.city = city
+                .city
+                .and_then(|v| v.names)
+                .and_then(|v| v.get("en").copied())
+                .map(Box::from);
+        }
+
+        if let Some(asn) = self
+            .asn
+            .as_ref()
+            .and_then(|v| v.lookup::<geoip2::Asn<'_>>(ip).ok())
+        {
+            out.asn = asn.autonomous_system_number;
+            out.org = asn.autonomous_system_organization.map(Box::from);
+        }
+
+        (out != GeoIp::default()).then_some(out)
+    }
+}
+
+fn open(path: &Path) -> Result<Reader<Vec<u8>>, std::io::Error> {
+    let reader = Reader::open_readfile(path).map_err(|e| {
+        std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("failed to open GeoIP database {}: {e}", path.display()),
+        )
+    })?;
+
+    info!(
+        path = %path.display(),
+        kind = %reader.metadata.database_type,
+        "Loaded GeoIP database"
+    );
+
+    Ok(reader)
+}
@@ -0,0 +1,272 @@
+//! Picks the client's identification string and `SSH_MSG_KEXINIT` out of the start of the
+//! connection before thrussh gets to them, since it doesn't expose either, so clients can be
+//! fingerprinted using [HASSH].
+//!
+//! [HASSH]: https://github.com/salesforce/hassh
+
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context, Poll},
+};
+
+use md5::{Digest, Md5};
+use nom::{
+    bytes::{
+        complete::take,
+        streaming::{tag, take_until},
+    },
+    combinator::{peek, verify},
+    error::{Error, ErrorKind},
+    multi::length_data,
+    number::{complete, streaming::be_u32},
+    sequence::terminated,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::audit::ClientHandshake;
+
+/// Largest `SSH_MSG_KEXINIT` packet we'll wait for, RFC 4253 only requires implementations
+/// to handle packets up to 35000 bytes.
+const MAX_PACKET_LENGTH: u32 = 35000;
+
+/// Largest amount of data we'll buffer whilst waiting for the handshake, to stop clients
+/// that never send one having us buffer indefinitely.
+const MAX_BUFFERED: usize = 64 * 1024;
+
+const SSH_MSG_KEXINIT: u8 = 20;
+
+type IResult<I, O> = nom::IResult<I, O>;
+
+/// Wraps the client's stream, parsing its side of the handshake out of the data as it's read
+/// and passing it through untouched.
+pub struct HandshakeSniffer<S> {
+    inner: S,
+    buffer: Option<Vec<u8>>,
+    handshake: Arc<OnceLock<ClientHandshake>>,
+}
+
+impl<S> HandshakeSniffer<S> {
+    pub fn new(inner: S, handshake: Arc<OnceLock<ClientHandshake>>) -> Self {
+        Self {
+            inner,
+            buffer: Some(Vec::new()),
+            handshake,
+        }
+    }
+
+    fn sniff(&mut self, data: &[u8]) {
+        let Some(buffer) = &mut self.buffer else {
+            return;
+        };
+
+        buffer.extend_from_slice(data);
+
+        let keep_sniffing = match parse_handshake(buffer) {
+            Ok((_, handshake)) => {
+                let _res = self.handshake.set(handshake);
+                false
+            }
+            Err(nom::Err::Incomplete(_)) => buffer.len() < MAX_BUFFERED,
+            Err(_) => false,
+        };
+
+        if !keep_sniffing {
+            self.buffer = None;
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for HandshakeSniffer<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let already_filled = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = res {
+            self.sniff(&buf.filled()[already_filled..]);
+        }
+
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for HandshakeSniffer<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Parses the client's identification string followed by its first binary packet, which
+/// must be a `SSH_MSG_KEXINIT` sent in the clear.
+fn parse_handshake(input: &[u8]) -> IResult<&[u8], ClientHandshake> {
+    let (rest, version) = parse_identification(input)?;
+    let (rest, _) = verify(peek(be_u32), |len: &u32| *len <= MAX_PACKET_LENGTH)(rest)?;
+    let (rest, packet) = length_data(be_u32)(rest)?;
+
+    let (_, lists) = parse_kexinit(packet)
+        .map_err(|_| nom::Err::Failure(Error::new(input, ErrorKind::Verify)))?;
+
+    let [kex, host_key, encryption, _, mac, _, compression, _] = lists.map(parse_name_list);
+
+    Ok((
+        rest,
+        ClientHandshake {
+            version: String::from_utf8_lossy(version).into(),
+            hassh: hassh(&kex, &encryption, &mac, &compression),
+            kex_algorithms: kex,
+            server_host_key_algorithms: host_key,
+            encryption_algorithms: encryption,
+            mac_algorithms: mac,
+            compression_algorithms: compression,
+        },
+    ))
+}
+
+/// Parses the identification string, skipping over any lines that precede it.
+fn parse_identification(mut input: &[u8]) -> IResult<&[u8], &[u8]> {
+    loop {
+        let (rest, line) = terminated(take_until("\n"), tag("\n"))(input)?;
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.starts_with(b"SSH-") {
+            return Ok((rest, line));
+        }
+
+        input = rest;
+    }
+}
+
+/// Parses the name-lists out of a `SSH_MSG_KEXINIT` packet, in order: kex, server host key,
+/// then encryption, MAC and compression algorithms for each direction.
+fn parse_kexinit(packet: &[u8]) -> IResult<&[u8], [&[u8]; 8]> {
+    let (rest, _padding_length) = complete::u8(packet)?;
+    let (rest, _) = verify(complete::u8, |v: &u8| *v == SSH_MSG_KEXINIT)(rest)?;
+    let (mut rest, _cookie) = take(16_usize)(rest)?;
+
+    let mut lists = [&[][..]; 8];
+
+    for list in &mut lists {
+        (rest, *list) = length_data(complete::be_u32)(rest)?;
+    }
+
+    Ok((rest, lists))
+}
+
+fn parse_name_list(list: &[u8]) -> Vec<Box<str>> {
+    if list.is_empty() {
+        return Vec::new();
+    }
+
+    list.split(|&c| c == b',')
+        .map(|name| String::from_utf8_lossy(name).into())
+        .collect()
+}
+
+/// Computes the HASSH of the client's offered algorithms, the MD5 of its kex, encryption, MAC
+/// and compression algorithms joined by `;`.
+fn hassh(
+    kex: &[Box<str>],
+    encryption: &[Box<str>],
+    mac: &[Box<str>],
+    compression: &[Box<str>],
+) -> Box<str> {
+    let input = [kex, encryption, mac, compression]
+        .map(|v| v.join(","))
+        .join(";");
+
+    format!("{:x}", Md5::digest(input.as_bytes())).into_boxed_str()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hassh, parse_handshake};
+
+    fn name_list(out: &mut Vec<u8>, list: &str) {
+        out.extend_from_slice(&u32::try_from(list.len()).unwrap().to_be_bytes());
+        out.extend_from_slice(list.as_bytes());
+    }
+
+    fn handshake() -> Vec<u8> {
+        let mut payload = vec![20];
+        payload.extend_from_slice(&[0; 16]);
+        name_list(
+            &mut payload,
+            "curve25519-sha256,diffie-hellman-group14-sha256",
+        );
+        name_list(&mut payload, "ssh-ed25519");
+        name_list(&mut payload, "aes128-ctr");
+        name_list(&mut payload, "aes128-ctr");
+        name_list(&mut payload, "hmac-sha2-256");
+        name_list(&mut payload, "hmac-sha2-256");
+        name_list(&mut payload, "none");
+        name_list(&mut payload, "none");
+        name_list(&mut payload, "");
+        name_list(&mut payload, "");
+        payload.extend_from_slice(&[0, 0, 0, 0, 0]);
+
+        let mut packet = vec![4];
+        packet.extend_from_slice(&payload);
+        packet.extend_from_slice(&[0; 4]);
+
+        let mut out = b"SSH-2.0-Go\r\n".to_vec();
+        out.extend_from_slice(&u32::try_from(packet.len()).unwrap().to_be_bytes());
+        out.extend_from_slice(&packet);
+        out
+    }
+
+    #[test]
+    fn parses_handshake() {
+        let (rest, handshake) = parse_handshake(&handshake()).unwrap();
+
+        assert!(rest.is_empty());
+        assert_eq!(&*handshake.version, "SSH-2.0-Go");
+        assert_eq!(handshake.kex_algorithms.len(), 2);
+        assert_eq!(&*handshake.server_host_key_algorithms[0], "ssh-ed25519");
+        assert_eq!(&*handshake.compression_algorithms[0], "none");
+    }
+
+    #[test]
+    fn waits_for_more_data() {
+        let data = handshake();
+
+        for len in [0, 5, 12, 20, data.len() - 1] {
+            assert!(
+                matches!(parse_handshake(&data[..len]), Err(nom::Err::Incomplete(_))),
+                "{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn computes_hassh() {
+        // a typical OpenSSH client's offered algorithms
+        let list = |v: &str| v.split(',').map(Box::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            &*hassh(
+                &list("curve25519-sha256@libssh.org,ecdh-sha2-nistp256,ecdh-sha2-nistp384,ecdh-sha2-nistp521,diffie-hellman-group-exchange-sha256,diffie-hellman-group14-sha1"),
+                &list("chacha20-poly1305@openssh.com,aes128-ctr,aes192-ctr,aes256-ctr,aes128-gcm@openssh.com,aes256-gcm@openssh.com,aes128-cbc,aes192-cbc,aes256-cbc"),
+                &list("umac-64-etm@openssh.com,umac-128-etm@openssh.com,hmac-sha2-256-etm@openssh.com,hmac-sha2-512-etm@openssh.com,hmac-sha1-etm@openssh.com,umac-64@openssh.com,umac-128@openssh.com,hmac-sha2-256,hmac-sha2-512,hmac-sha1"),
+                &list("none,zlib@openssh.com,zlib"),
+            ),
+            "1df2196f64fc48573d55dd92a680e4c2"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Output of the server's own operational logs, as opposed to its audit logs. The JSON format
+//! writes each event as a single object with the fields of the spans it happened within, such as
+//! the connection's ID and peer address, flattened into it alongside the event's own, so it can
+//! be parsed by the same pipeline as the audit logs.
+
+use std::fmt::Write as _;
+
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::{
+        format::{JsonFields, Writer},
+        FmtContext, FormatEvent, FormatFields, FormattedFields,
+    },
+    registry::LookupSpan,
+    Layer,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// A JSON object per line.
+    Json,
+}
+
+/// Layer writing logs to stdout in `format`.
+pub fn layer<S>(format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .fmt_fields(JsonFields::new())
+            .event_format(Json)
+            .boxed(),
+    }
+}
+
+/// Formats events as JSON objects with the fields of their spans flattened into them, spans
+/// are expected to have been recorded with [`JsonFields`].
+pub struct Json;
+
+impl<S, N> FormatEvent<S, N> for Json
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let metadata = event.metadata();
+
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default()
+                .into(),
+        );
+        line.insert("level".to_string(), metadata.level().as_str().into());
+        line.insert("target".to_string(), metadata.target().into());
+
+        // outermost first, so fields of inner spans take precedence
+        if let Some(scope) = ctx.event_scope() {
+            let mut spans = Vec::new();
+
+            for span in scope.from_root() {
+                spans.push(Value::from(span.name()));
+
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<N>>() else {
+                    continue;
+                };
+
+                if let Ok(Value::Object(fields)) = serde_json::from_str(fields) {
+                    line.extend(fields);
+                }
+            }
+
+            line.insert("spans".to_string(), spans.into());
+        }
+
+        event.record(&mut Visitor(&mut line));
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Records an event's fields into a JSON object.
+struct Visitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for Visitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0
+            .insert(field.name().to_string(), value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Write, sync::Arc};
+
+    use parking_lot::Mutex;
+    use serde_json::Value;
+    use tracing::{info, info_span};
+    use tracing_subscriber::fmt::{format::JsonFields, MakeWriter};
+
+    use crate::logging::Json;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn flattens_span_fields() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(buffer.clone())
+            .fmt_fields(JsonFields::new())
+            .event_format(Json)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let connection =
+                info_span!("connection", connection_id = "abc", peer_addr = %"127.0.0.1:1234");
+            let _connection = connection.enter();
+            let auth = info_span!("auth_password");
+            let _auth = auth.enter();
+
+            info!(user = "root", attempts = 3, "Rejected login");
+        });
+
+        let output = buffer.0.lock().clone();
+        let line: Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "Rejected login");
+        assert_eq!(line["connection_id"], "abc");
+        assert_eq!(line["peer_addr"], "127.0.0.1:1234");
+        assert_eq!(line["user"], "root");
+        assert_eq!(line["attempts"], 3);
+        assert_eq!(
+            line["spans"],
+            serde_json::json!(["connection", "auth_password"])
+        );
+    }
+}
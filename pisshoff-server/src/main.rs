@@ -1,51 +1,139 @@
 #![deny(clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
 use clap::Parser;
 use futures::FutureExt;
+use nix::unistd::{setgid, setgroups, setuid, Group, Uid, User};
 use thrussh::MethodSet;
 use tokio::{
+    net::TcpListener,
     signal::unix::SignalKind,
     sync::{oneshot, watch},
 };
-use tracing::{error, info};
-use tracing_subscriber::EnvFilter;
+use tracing::{error, info, warn};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-use crate::{config::Args, server::Server};
+use crate::{
+    audit::queue::AuditQueue,
+    config::{Args, Command, Config, ConfigFile, ListenerConfig},
+    server::Server,
+    state::State,
+};
 
+mod admin;
+mod alert;
+mod api;
+mod archive;
 mod audit;
+mod auth;
+mod chain;
+mod check;
+mod classify;
+mod collector;
 mod command;
 mod config;
+mod download;
+mod encrypt;
+mod export;
 mod file_system;
+mod generate;
+mod geoip;
+mod handshake;
+mod logging;
+mod metrics;
+mod migrate;
+mod persistence;
+mod persona;
+mod quarantine;
+mod recording;
+mod replay;
+mod report;
+mod reverse_dns;
+mod sandbox;
 mod server;
 mod state;
 mod subsystem;
+mod systemd;
+mod tarpit;
+mod telemetry;
+mod terminal;
+mod verify;
+mod virustotal;
 
-#[tokio::main]
-async fn main() {
-    if let Err(e) = run().await {
+fn main() {
+    if let Err(e) = run() {
         error!("Failed to run {}: {}", env!("CARGO_CRATE_NAME"), e);
         std::process::exit(1);
     }
 }
 
-async fn run() -> anyhow::Result<()> {
+fn run() -> anyhow::Result<()> {
     let args = Args::parse();
 
     std::env::set_var("RUST_LOG", args.verbosity());
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
+    // the exporter can only be started once the config's been read and the runtime is up
+    let (telemetry_layer, telemetry_handle) = telemetry::layer();
+    tracing_subscriber::registry()
+        .with(telemetry_layer)
+        .with(logging::layer(args.log_format))
+        .with(EnvFilter::from_default_env())
         .init();
 
-    info!(
-        "{} listening on {}",
-        env!("CARGO_CRATE_NAME"),
-        args.config.listen_address
-    );
+    match &args.command {
+        Some(Command::Ctl { args: ctl_args }) => {
+            return tokio::runtime::Runtime::new()?
+                .block_on(admin::ctl(&args.config_file()?.config, ctl_args));
+        }
+        Some(Command::CheckConfig) => return check_config(&args.config_file()?),
+        Some(Command::DumpConfig) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&*args.config_file()?.config)?
+            );
+            return Ok(());
+        }
+        Some(Command::GenerateConfig {
+            output,
+            interactive,
+            force,
+        }) => return generate::run(output.as_deref(), *interactive, *force),
+        Some(Command::Report { paths, top, json }) => return report::run(paths, *top, *json),
+        Some(Command::Export {
+            paths,
+            format,
+            output,
+        }) => return export::run(paths, *format, output.as_deref()),
+        Some(Command::Replay {
+            target,
+            paths,
+            speed,
+            max_idle,
+        }) => {
+            let timing = replay::Timing {
+                speed: *speed,
+                max_idle: *max_idle,
+            };
+            return replay::run(target, paths, timing);
+        }
+        Some(Command::Migrate { paths, output }) => return migrate::run(paths, output.as_deref()),
+        Some(Command::Verify { paths, public_key }) => {
+            return verify::run(paths, public_key.as_deref())
+        }
+        Some(Command::Decrypt {
+            paths,
+            identity,
+            output,
+        }) => return encrypt::run(paths, identity, output.as_deref()),
+        Some(Command::Collector) => return collect(&args.config_file()?, &telemetry_handle),
+        None => {}
+    }
+
+    let config_file = args.config_file()?;
+    let config = &config_file.config;
 
     let hostname = Box::leak(
         nix::unistd::gethostname()?
@@ -53,61 +141,449 @@ async fn run() -> anyhow::Result<()> {
             .map_err(|_| anyhow!("invalid hostname"))?
             .into_boxed_str(),
     );
-    let keys = vec![thrussh_keys::key::KeyPair::generate_ed25519().unwrap()];
 
-    let thrussh_config = Arc::new(thrussh::server::Config {
-        server_id: args.config.server_id.to_string(),
-        methods: MethodSet::PASSWORD | MethodSet::PUBLICKEY | MethodSet::KEYBOARD_INTERACTIVE,
-        keys,
-        auth_rejection_time: std::time::Duration::from_secs(1),
-        ..thrussh::server::Config::default()
-    });
+    // this all happens before the runtime is started, so that each of its threads is spawned
+    // from within the sandbox
+    let listeners = bind_listeners(config)?;
+    let metrics = bind_http(config.metrics.as_ref().map(|v| v.listen_address))?;
+    let api = bind_http(config.api.as_ref().map(|v| v.listen_address))?;
+    drop_privileges(config)?;
+    sandbox::apply(config, &config_file.path)?;
+
+    tokio::runtime::Runtime::new()?.block_on(serve(
+        &config_file,
+        hostname,
+        listeners,
+        metrics,
+        api,
+        &telemetry_handle,
+    ))
+}
+
+fn check_config(config_file: &ConfigFile) -> anyhow::Result<()> {
+    let problems = check::check(&config_file.config);
+
+    if problems.is_empty() {
+        println!("{} is valid", config_file.path.display());
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{problem}");
+    }
+
+    Err(anyhow!(
+        "found {} problems with {}",
+        problems.len(),
+        config_file.path.display()
+    ))
+}
+
+async fn serve(
+    config_file: &ConfigFile,
+    hostname: &'static str,
+    listeners: Vec<Listener>,
+    metrics: Option<std::net::TcpListener>,
+    api: Option<std::net::TcpListener>,
+    telemetry: &telemetry::Handle,
+) -> anyhow::Result<()> {
+    let config = config_file.config.clone();
 
-    let (reload_send, reload_recv) = watch::channel(());
+    telemetry::start(config.otlp.as_ref(), telemetry)?;
+
+    let (reload_send, reload_recv) = watch::channel(config.clone());
     let (shutdown_send, shutdown_recv) = oneshot::channel();
 
-    let (audit_send, audit_handle) =
-        audit::start_audit_writer(args.config.clone(), reload_recv, shutdown_recv);
+    let state = Arc::new(State::new(config.clone())?);
+
+    let archiver = archive::run(reload_recv.clone());
+    let api = serve_api(api, reload_recv.clone());
+    let audit_handle = audit::start_audit_writer(reload_recv, shutdown_recv, state.audit.clone());
     let mut audit_handle = audit_handle.fuse();
 
-    let server = Server::new(hostname, args.config.clone(), audit_send);
-    let listen_address = args.config.listen_address.to_string();
+    let server = Server::new(hostname, state.clone());
+    let fut = futures::future::try_join_all(
+        listeners
+            .into_iter()
+            .map(|(address, socket, thrussh)| {
+                Ok(server
+                    .clone()
+                    .run(thrussh, address, TcpListener::from_std(socket)?))
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?,
+    );
 
-    // TODO: needs clean shutdowns on clients
-    let fut = thrussh::server::run(thrussh_config, &listen_address, server);
+    let shutdown_watcher = watch_for_shutdown();
+    let reload_watcher = watch_for_reloads(config_file, Some(state.clone()), reload_send);
+    let admin = serve_admin(&config, state.clone());
+    let metrics = serve_metrics(&config, metrics, state.clone());
 
-    let shutdown_watcher = watch_for_shutdown(shutdown_send);
-    let reload_watcher = watch_for_reloads(reload_send);
+    systemd::notify("READY=1");
 
     tokio::select! {
-        res = fut => res?,
+        res = fut => { res?; }
+        res = admin => res?,
+        res = metrics => res?,
+        res = api => res?,
+        res = archiver => res?,
         res = &mut audit_handle => res??,
         res = shutdown_watcher => res?,
         res = reload_watcher => res?,
     }
 
+    systemd::notify("STOPPING=1");
+
+    // the listeners were dropped along with the select, so nothing new is being accepted
+    drain_connections(&state, Duration::from_secs(config.shutdown_grace_period)).await;
+
+    let _res = shutdown_send.send(());
+
     info!("Finishing audit log writes");
     audit_handle.await??;
     info!("Audit log writes finished");
 
+    telemetry::shutdown().await;
+
+    Ok(())
+}
+
+/// Runs as a collector, writing logs forwarded by sensors to the configured sinks rather than
+/// serving SSH.
+fn collect(config_file: &ConfigFile, telemetry: &telemetry::Handle) -> anyhow::Result<()> {
+    let config = &config_file.config;
+    let collector = config
+        .collector
+        .as_ref()
+        .ok_or_else(|| anyhow!("{} has no [collector] section", config_file.path.display()))?;
+
+    let listener = std::net::TcpListener::bind(collector.listen_address)
+        .with_context(|| format!("failed to bind {}", collector.listen_address))?;
+    listener.set_nonblocking(true)?;
+
+    // the certificate is read before the sandbox goes up, and isn't read again on reload
+    let tls = collector::tls_acceptor(collector)?;
+    let api = bind_http(config.api.as_ref().map(|v| v.listen_address))?;
+
+    info!(
+        tls = tls.is_some(),
+        "Collector listening on {}",
+        listener.local_addr()?
+    );
+
+    drop_privileges(config)?;
+    sandbox::apply(config, &config_file.path)?;
+
+    tokio::runtime::Runtime::new()?.block_on(serve_collector(
+        config_file,
+        listener,
+        tls,
+        api,
+        telemetry,
+    ))
+}
+
+async fn serve_collector(
+    config_file: &ConfigFile,
+    listener: std::net::TcpListener,
+    tls: Option<tokio_rustls::TlsAcceptor>,
+    api: Option<std::net::TcpListener>,
+    telemetry: &telemetry::Handle,
+) -> anyhow::Result<()> {
+    let config = config_file.config.clone();
+
+    telemetry::start(config.otlp.as_ref(), telemetry)?;
+
+    let (reload_send, reload_recv) = watch::channel(config.clone());
+    let (shutdown_send, shutdown_recv) = oneshot::channel();
+
+    let queue = Arc::new(AuditQueue::new(&config));
+
+    let archiver = archive::run(reload_recv.clone());
+    let collector = collector::serve(
+        TcpListener::from_std(listener)?,
+        tls,
+        reload_recv.clone(),
+        queue.clone(),
+    );
+    let api = serve_api(api, reload_recv.clone());
+    let audit_handle = audit::start_audit_writer(reload_recv, shutdown_recv, queue);
+    let mut audit_handle = audit_handle.fuse();
+
+    let shutdown_watcher = watch_for_shutdown();
+    let reload_watcher = watch_for_reloads(config_file, None, reload_send);
+
+    systemd::notify("READY=1");
+
+    tokio::select! {
+        res = collector => res?,
+        res = api => res?,
+        res = archiver => res?,
+        res = &mut audit_handle => res??,
+        res = shutdown_watcher => res?,
+        res = reload_watcher => res?,
+    }
+
+    systemd::notify("STOPPING=1");
+
+    let _res = shutdown_send.send(());
+
+    info!("Finishing audit log writes");
+    audit_handle.await??;
+    info!("Audit log writes finished");
+
+    telemetry::shutdown().await;
+
+    Ok(())
+}
+
+/// A bound listener, along with the address it was configured with and its SSH config.
+type Listener = (
+    SocketAddr,
+    std::net::TcpListener,
+    Arc<thrussh::server::Config>,
+);
+
+/// Binds a socket for each of the configured listeners, using the sockets passed to us by
+/// systemd where there are any.
+fn bind_listeners(config: &Config) -> anyhow::Result<Vec<Listener>> {
+    let mut inherited = systemd::listen_fds().context("failed to take sockets from systemd")?;
+    let mut listeners = Vec::new();
+
+    for listener in &config.listen_address {
+        let socket = match systemd::take_listener(&mut inherited, listener.address) {
+            Some(socket) => socket,
+            None => {
+                let socket = std::net::TcpListener::bind(listener.address)
+                    .with_context(|| format!("failed to bind {}", listener.address))?;
+                socket.set_nonblocking(true)?;
+                socket
+            }
+        };
+
+        info!(
+            "{} listening on {}",
+            env!("CARGO_CRATE_NAME"),
+            socket.local_addr()?
+        );
+
+        listeners.push((listener.address, socket, thrussh_config(config, listener)?));
+    }
+
+    for socket in inherited {
+        warn!(address = ?socket.local_addr().ok(), "Ignoring socket from systemd that doesn't match any listener");
+    }
+
+    Ok(listeners)
+}
+
+/// Builds the SSH config for a listener, loading its host key or generating one if it hasn't
+/// been given one.
+fn thrussh_config(
+    config: &Config,
+    listener: &ListenerConfig,
+) -> anyhow::Result<Arc<thrussh::server::Config>> {
+    let key = match &listener.host_key {
+        Some(path) => thrussh_keys::load_secret_key(path, None)
+            .with_context(|| format!("failed to load host key from {}", path.display()))?,
+        None => thrussh_keys::key::KeyPair::generate_ed25519().unwrap(),
+    };
+
+    let config = config.for_listener(listener);
+
+    // thrussh only accepts a static banner, but listeners live as long as the process anyway
+    let auth_banner = config
+        .banner(listener)
+        .map(|v| -> &'static str { Box::leak(v.to_string().into_boxed_str()) });
+
+    Ok(Arc::new(thrussh::server::Config {
+        server_id: config.server_id().to_string(),
+        methods: MethodSet::PASSWORD | MethodSet::PUBLICKEY | MethodSet::KEYBOARD_INTERACTIVE,
+        keys: vec![key],
+        auth_rejection_time: std::time::Duration::from_secs(1),
+        auth_banner,
+        max_auth_attempts: config.max_auth_tries,
+        ..thrussh::server::Config::default()
+    }))
+}
+
+/// Switches to the configured user and group, so we aren't left running as root while talking
+/// to attackers. Anything needing root, such as binding port 22 or reading host keys, has to
+/// happen before this.
+fn drop_privileges(config: &Config) -> anyhow::Result<()> {
+    if config.user.is_none() && config.group.is_none() {
+        return Ok(());
+    }
+
+    let user = config
+        .user
+        .as_deref()
+        .map(|name| User::from_name(name)?.ok_or_else(|| anyhow!("unknown user {name}")))
+        .transpose()?;
+
+    let gid = match &config.group {
+        Some(name) => Some(
+            Group::from_name(name)?
+                .ok_or_else(|| anyhow!("unknown group {name}"))?
+                .gid,
+        ),
+        None => user.as_ref().map(|v| v.gid),
+    };
+
+    // the group has to be switched first, we no longer have permission to once we've setuid
+    if let Some(gid) = gid {
+        if Uid::effective().is_root() {
+            setgroups(&[gid]).context("failed to drop supplementary groups")?;
+        }
+
+        setgid(gid).context("failed to switch group")?;
+    }
+
+    if let Some(user) = &user {
+        setuid(user.uid).context("failed to switch user")?;
+
+        if !user.uid.is_root() && setuid(Uid::from_raw(0)).is_ok() {
+            return Err(anyhow!("regained root after switching to {}", user.name));
+        }
+    }
+
+    info!(user = ?config.user, group = ?config.group, "Dropped privileges");
+
     Ok(())
 }
 
-async fn watch_for_shutdown(send: oneshot::Sender<()>) -> Result<(), anyhow::Error> {
-    tokio::signal::ctrl_c().await?;
-    info!("Received ctrl-c, initiating shutdown");
+/// Binds a listener for one of the HTTP interfaces, if it's enabled.
+fn bind_http(address: Option<SocketAddr>) -> anyhow::Result<Option<std::net::TcpListener>> {
+    let Some(address) = address else {
+        return Ok(None);
+    };
+
+    let socket = std::net::TcpListener::bind(address)
+        .with_context(|| format!("failed to bind {address}"))?;
+    socket.set_nonblocking(true)?;
 
-    let _res = send.send(());
+    Ok(Some(socket))
+}
+
+async fn serve_metrics(
+    config: &Config,
+    listener: Option<std::net::TcpListener>,
+    state: Arc<State>,
+) -> Result<(), anyhow::Error> {
+    match (&config.metrics, listener) {
+        (Some(metrics), Some(listener)) => metrics::serve(listener, metrics, state).await,
+        _ => futures::future::pending().await,
+    }
+}
+
+async fn serve_api(
+    listener: Option<std::net::TcpListener>,
+    config: watch::Receiver<Arc<Config>>,
+) -> Result<(), anyhow::Error> {
+    match listener {
+        Some(listener) => api::serve(listener, config).await,
+        None => futures::future::pending().await,
+    }
+}
+
+async fn serve_admin(config: &Config, state: Arc<State>) -> Result<(), anyhow::Error> {
+    match &config.admin_socket {
+        Some(path) => admin::serve(path, state).await,
+        None => futures::future::pending().await,
+    }
+}
+
+async fn watch_for_shutdown() -> Result<(), anyhow::Error> {
+    let mut terminate = tokio::signal::unix::signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => {
+            res?;
+            info!("Received ctrl-c, initiating shutdown");
+        }
+        _ = terminate.recv() => {
+            info!("Received SIGTERM, initiating shutdown");
+        }
+    }
 
     Ok(())
 }
 
-async fn watch_for_reloads(send: watch::Sender<()>) -> Result<(), anyhow::Error> {
+/// Gives open connections up to `grace_period` to finish by themselves, then closes any that
+/// are left so their audit logs are queued before the audit writer shuts down.
+async fn drain_connections(state: &State, grace_period: Duration) {
+    let open = state.connections.list().len();
+
+    if open == 0 {
+        return;
+    }
+
+    info!(
+        open,
+        ?grace_period,
+        "Waiting for open connections to finish"
+    );
+
+    if tokio::time::timeout(grace_period, state.connections.drained())
+        .await
+        .is_ok()
+    {
+        info!("Open connections finished");
+        return;
+    }
+
+    let remaining = state.connections.list();
+    warn!(
+        remaining = remaining.len(),
+        "Grace period elapsed, closing remaining connections"
+    );
+
+    for connection in remaining {
+        connection.kill.notify_one();
+    }
+
+    // closing a connection only drops it, so this shouldn't take long
+    if tokio::time::timeout(Duration::from_secs(5), state.connections.drained())
+        .await
+        .is_err()
+    {
+        warn!("Connections still open after being closed, their audit logs will be lost");
+    }
+}
+
+/// Reads the config again on SIGHUP, applying it to new connections and recreating the audit
+/// sinks. The old config stays in place if the new one can't be loaded, though the sinks are
+/// still reopened so log rotation carries on working. There's no `state` to apply it to when
+/// running as a collector.
+async fn watch_for_reloads(
+    config_file: &ConfigFile,
+    state: Option<Arc<State>>,
+    send: watch::Sender<Arc<Config>>,
+) -> Result<(), anyhow::Error> {
     let mut signal = tokio::signal::unix::signal(SignalKind::hangup())?;
 
     while let Some(()) = signal.recv().await {
-        info!("Received SIGHUP, broadcasting reload");
-        let _res = send.send(());
+        info!("Received SIGHUP, reloading config");
+
+        let reloaded = config_file.reload().and_then(|config| {
+            if let Some(state) = &state {
+                state.reload(config.clone())?;
+            }
+
+            Ok(config)
+        });
+
+        let config = match reloaded {
+            Ok(config) => {
+                info!("Reloaded config");
+                config
+            }
+            Err(error) => {
+                error!(%error, "Failed to reload config, keeping the old one");
+                send.borrow().clone()
+            }
+        };
+
+        let _res = send.send(config);
     }
 
     Ok(())
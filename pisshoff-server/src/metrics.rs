@@ -0,0 +1,260 @@
+//! HTTP listener for monitoring the server, serving `/healthz` for liveness and `/readyz` for
+//! readiness so Kubernetes or an uptime monitor can tell when a honeypot has wedged. The server
+//! is ready while each listener is taking connections, the audit queue has room and the
+//! directories the server writes to have space left.
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tracing::info;
+
+use crate::{config::MetricsConfig, state::State};
+
+/// What each of the SSH and Telnet listeners is currently doing.
+#[derive(Default)]
+pub struct Listeners(Mutex<BTreeMap<SocketAddr, ListenerStatus>>);
+
+impl Listeners {
+    pub fn set(&self, address: SocketAddr, status: ListenerStatus) {
+        self.0.lock().insert(address, status);
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListenerStatus {
+    /// Waiting for the next connection.
+    Accepting,
+    /// Not taking connections until the audit writer catches up, under the blocking overflow
+    /// policy.
+    Blocked,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Readiness {
+    ready: bool,
+    /// Why the server isn't ready, empty if it is.
+    problems: Vec<String>,
+    listeners: BTreeMap<SocketAddr, ListenerStatus>,
+    audit_queue: QueueBacklog,
+    disks: Vec<DiskSpace>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct QueueBacklog {
+    depth: usize,
+    capacity: usize,
+    dropped: u64,
+    spilled: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct DiskSpace {
+    path: PathBuf,
+    /// Bytes available to the server, missing if they couldn't be looked up.
+    free: Option<u64>,
+}
+
+/// Serves health checks on `listener` until the server shuts down.
+pub async fn serve(
+    listener: std::net::TcpListener,
+    config: &MetricsConfig,
+    state: Arc<State>,
+) -> anyhow::Result<()> {
+    let min_free_space = config.min_free_space;
+
+    info!(address = %listener.local_addr()?, "Metrics listening");
+
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let response = respond(&request, &state, min_free_space);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    hyper::Server::from_tcp(listener)?
+        .serve(make_service)
+        .await?;
+
+    Ok(())
+}
+
+fn respond(request: &Request<Body>, state: &State, min_free_space: u64) -> Response<Body> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed\n");
+    }
+
+    match request.uri().path() {
+        // answering at all means the runtime isn't wedged, anything more is left to readiness
+        // so a full disk doesn't get the server restarted in a loop
+        "/healthz" => text(StatusCode::OK, "ok\n"),
+        "/readyz" => {
+            let readiness = readiness(state, min_free_space);
+            let status = if readiness.ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+
+            let mut body = serde_json::to_vec_pretty(&readiness).unwrap_or_default();
+            body.push(b'\n');
+
+            Response::builder()
+                .status(status)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap_or_default()
+        }
+        _ => text(StatusCode::NOT_FOUND, "not found\n"),
+    }
+}
+
+fn text(status: StatusCode, body: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(body))
+        .unwrap_or_default()
+}
+
+fn readiness(state: &State, min_free_space: u64) -> Readiness {
+    let config = state.settings.load().config.clone();
+    let mut problems = Vec::new();
+
+    let listeners = state.listeners.0.lock().clone();
+
+    if listeners.is_empty() {
+        problems.push("no listeners have started yet".to_string());
+    }
+
+    for (address, status) in &listeners {
+        if *status == ListenerStatus::Blocked {
+            problems.push(format!(
+                "listener {address} is blocked waiting for the audit writer"
+            ));
+        }
+    }
+
+    let audit_queue = QueueBacklog {
+        depth: state.audit.depth(),
+        capacity: state.audit.capacity(),
+        dropped: state.audit.dropped(),
+        spilled: state.audit.spilled(),
+    };
+
+    if audit_queue.depth >= audit_queue.capacity {
+        problems.push(format!(
+            "audit queue is full with {} logs waiting",
+            audit_queue.depth
+        ));
+    }
+
+    let disks = [&config.quarantine_directory, &config.state_dir]
+        .into_iter()
+        .flatten()
+        .map(|path| {
+            let free = match free_space(path) {
+                Ok(free) => {
+                    if free < min_free_space {
+                        problems.push(format!("{} has only {free} bytes free", path.display()));
+                    }
+
+                    Some(free)
+                }
+                Err(error) => {
+                    problems.push(format!(
+                        "failed to check free space in {}: {error}",
+                        path.display()
+                    ));
+                    None
+                }
+            };
+
+            DiskSpace {
+                path: path.clone(),
+                free,
+            }
+        })
+        .collect();
+
+    Readiness {
+        ready: problems.is_empty(),
+        problems,
+        listeners,
+        audit_queue,
+        disks,
+    }
+}
+
+/// Bytes available to unprivileged users on the file system holding `path`.
+// the types of the fields differ between platforms
+#[allow(clippy::unnecessary_cast)]
+fn free_space(path: &Path) -> Result<u64, nix::Error> {
+    let stat = nix::sys::statvfs::statvfs(path)?;
+    Ok((stat.blocks_available() as u64).saturating_mul(stat.fragment_size() as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{
+        config::Config,
+        metrics::{readiness, ListenerStatus},
+        state::{Settings, State},
+    };
+
+    #[test]
+    fn reports_readiness() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-metrics-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let state = State::default();
+        state.settings.store(Arc::new(Settings {
+            config: Arc::new(Config {
+                state_dir: Some(directory.clone()),
+                ..Config::default()
+            }),
+            ..Settings::default()
+        }));
+
+        let not_started = readiness(&state, 0);
+        assert!(!not_started.ready);
+        assert_eq!(not_started.disks.len(), 1);
+        assert!(not_started.disks[0].free.is_some());
+
+        let address = "127.0.0.1:2222".parse().unwrap();
+        state.listeners.set(address, ListenerStatus::Accepting);
+        assert!(readiness(&state, 0).ready);
+
+        state.listeners.set(address, ListenerStatus::Blocked);
+        assert!(!readiness(&state, 0).ready);
+
+        state.listeners.set(address, ListenerStatus::Accepting);
+        let full_disk = readiness(&state, u64::MAX);
+        assert!(!full_disk.ready);
+        assert_eq!(full_disk.problems.len(), 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+//! Upgrades audit logs written by older versions to the current schema, so collections kept
+//! over the long term can still be read by `report`, `export` and the sinks' consumers after
+//! events have been renamed or reshaped. Logs are upgraded as plain JSON one version at a time,
+//! as older ones won't necessarily deserialise as the current types.
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fs::File,
+    io::{BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use serde_json::{Map, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    audit::{sha256_hex, AuditLog, SCHEMA_VERSION},
+    report,
+};
+
+/// A step upgrading a log from one schema version to the next.
+type Migration = fn(&mut Map<String, Value>) -> anyhow::Result<()>;
+
+/// Migrations from each schema version to the next, the one at index `n` upgrading a log from
+/// version `n` to `n + 1`.
+const MIGRATIONS: &[Migration] = &[v0_to_v1];
+
+/// Upgrades every audit log in `paths`, each of which can be a file or a directory of them,
+/// writing them out to `output` or stdout if it isn't set. Lines that can't be upgraded are
+/// written out as they were, so nothing is lost.
+pub fn run(paths: &[PathBuf], output: Option<&Path>) -> anyhow::Result<()> {
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).with_context(|| {
+                format!("failed to create {}", path.display())
+            })?))
+        }
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let mut stats = Stats::default();
+
+    for path in paths {
+        for file in report::files(path)? {
+            let reader = report::open(&file)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            migrate(reader, &mut out, &mut stats)
+                .with_context(|| format!("failed to migrate {}", file.display()))?;
+        }
+    }
+
+    out.flush()?;
+
+    eprintln!(
+        "Upgraded {} connections to schema version {SCHEMA_VERSION}, {} were already up to date",
+        stats.upgraded, stats.current
+    );
+
+    if stats.skipped_lines > 0 {
+        eprintln!(
+            "Left {} lines as they were as they couldn't be upgraded",
+            stats.skipped_lines
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Default, Debug)]
+struct Stats {
+    upgraded: usize,
+    current: usize,
+    /// Lines that couldn't be upgraded, such as those that aren't audit logs at all or were
+    /// written by a newer version.
+    skipped_lines: usize,
+}
+
+/// Writes out each audit log in `reader` upgraded to the current schema.
+fn migrate(reader: impl BufRead, out: &mut impl Write, stats: &mut Stats) -> std::io::Result<()> {
+    for line in reader.split(b'\n') {
+        let line = line?;
+
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        out.write_all(&migrate_line(&line, stats))?;
+        out.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+/// Upgrades a single line of an audit log, returning what to write in its place.
+fn migrate_line<'a>(line: &'a [u8], stats: &mut Stats) -> Cow<'a, [u8]> {
+    let upgraded = serde_json::from_slice::<Value>(line)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut log| {
+            if !upgrade(&mut log)? {
+                return Ok(None);
+            }
+
+            // going through the current types checks the upgrade left the log readable
+            let log = serde_json::from_value::<AuditLog>(log)?;
+            Ok(Some(serde_json::to_vec(&log)?))
+        });
+
+    match upgraded {
+        Ok(Some(upgraded)) => {
+            stats.upgraded += 1;
+            Cow::Owned(upgraded)
+        }
+        Ok(None) => {
+            stats.current += 1;
+            Cow::Borrowed(line)
+        }
+        Err(_) => {
+            stats.skipped_lines += 1;
+            Cow::Borrowed(line)
+        }
+    }
+}
+
+/// Runs each migration `log` hasn't had yet, returning whether there were any.
+fn upgrade(log: &mut Value) -> anyhow::Result<bool> {
+    let Value::Object(log) = log else {
+        bail!("expected an object");
+    };
+
+    let version = match log.get("schema_version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow!("invalid schema version {version}"))?,
+        None => 0,
+    };
+
+    match version.cmp(&SCHEMA_VERSION) {
+        Ordering::Greater => bail!("written by a newer version, with schema version {version}"),
+        Ordering::Equal => return Ok(false),
+        Ordering::Less => {}
+    }
+
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(log)?;
+    }
+
+    log.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+
+    Ok(true)
+}
+
+/// Version 1 started recording the schema version, along with each event's wall-clock time and
+/// offset in milliseconds and the hash and size of written files. Version 0 logs only have the
+/// offset as a `Duration` and the file's content, which they're worked out from.
+fn v0_to_v1(log: &mut Map<String, Value>) -> anyhow::Result<()> {
+    let ts = log
+        .get("ts")
+        .and_then(Value::as_str)
+        .and_then(|v| OffsetDateTime::parse(v, &Rfc3339).ok());
+
+    let Some(Value::Array(events)) = log.get_mut("events") else {
+        bail!("missing events");
+    };
+
+    for event in events {
+        let Value::Object(event) = event else {
+            bail!("expected each event to be an object");
+        };
+
+        if let Some(offset) = event.get("start_offset").and_then(duration) {
+            event
+                .entry("offset_ms")
+                .or_insert_with(|| u64::try_from(offset.as_millis()).unwrap_or(u64::MAX).into());
+
+            if let Some(ts) = ts {
+                if event.get("ts").map_or(true, Value::is_null) {
+                    event.insert("ts".to_string(), (ts + offset).format(&Rfc3339)?.into());
+                }
+            }
+        }
+
+        let Some(Value::Object(action)) = event.get_mut("action") else {
+            continue;
+        };
+
+        if action.get("type").and_then(Value::as_str) != Some("write-file") {
+            continue;
+        }
+
+        if let Some(content) = action.get("content").and_then(bytes) {
+            if action
+                .get("sha256")
+                .and_then(Value::as_str)
+                .map_or(true, str::is_empty)
+            {
+                action.insert("sha256".to_string(), (*sha256_hex(&content)).into());
+            }
+
+            action.entry("size").or_insert_with(|| content.len().into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a `Duration` as serde writes it out, ie. `{"secs": 1, "nanos": 500}`.
+fn duration(value: &Value) -> Option<Duration> {
+    let secs = value.get("secs")?.as_u64()?;
+    let nanos = u32::try_from(value.get("nanos")?.as_u64()?).ok()?;
+    Some(Duration::new(secs, nanos))
+}
+
+/// Reads `Bytes` as serde_json writes them out, as an array of numbers.
+fn bytes(value: &Value) -> Option<Vec<u8>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|v| u8::try_from(v.as_u64()?).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+
+    use super::{migrate, Stats, MIGRATIONS};
+    use crate::audit::SCHEMA_VERSION;
+
+    const V0: &str = r#"{"connection_id":"464d87c9-e8fc-4d24-ab6f-34ee67b094f5","ts":"2023-08-10T20:46:09.5Z","peer_address":"127.0.0.1:31732","host":"my-cool-honeypot.dev","events":[{"start_offset":{"secs":1,"nanos":250000000},"action":{"type":"write-file","path":"test","content":[116,101,115,116,10]}}]}"#;
+
+    #[test]
+    fn has_migration_for_each_version() {
+        assert_eq!(MIGRATIONS.len(), SCHEMA_VERSION as usize);
+    }
+
+    #[test]
+    fn upgrades_v0() {
+        let input = format!("{V0}\nnot json\n\n{V0}\n");
+
+        let mut out = Vec::new();
+        let mut stats = Stats::default();
+        migrate(input.as_bytes(), &mut out, &mut stats).unwrap();
+
+        assert_eq!(stats.upgraded, 2);
+        assert_eq!(stats.skipped_lines, 1);
+
+        let lines = std::str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "not json");
+
+        let log: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(log["schema_version"], SCHEMA_VERSION);
+
+        let event = &log["events"][0];
+        assert_eq!(event["offset_ms"], 1250);
+        assert_eq!(event["ts"], "2023-08-10T20:46:10.75Z");
+        assert_eq!(
+            event["action"]["sha256"],
+            "f2ca1bb6c7e907d06dafe4687e579fce76b37e4e93b7605022da52e6ccc26fd2"
+        );
+        assert_eq!(event["action"]["size"], 5);
+
+        // upgrading again leaves the log as it is
+        let mut again = Vec::new();
+        let mut stats = Stats::default();
+        migrate(lines[0].as_bytes(), &mut again, &mut stats).unwrap();
+
+        assert_eq!(stats.current, 1);
+        assert_eq!(std::str::from_utf8(&again).unwrap().trim_end(), lines[0]);
+    }
+
+    #[test]
+    fn leaves_newer_versions() {
+        let input = V0.replacen(
+            '{',
+            &format!("{{\"schema_version\":{},", SCHEMA_VERSION + 1),
+            1,
+        );
+
+        let mut out = Vec::new();
+        let mut stats = Stats::default();
+        migrate(input.as_bytes(), &mut out, &mut stats).unwrap();
+
+        assert_eq!(stats.skipped_lines, 1);
+        assert_eq!(std::str::from_utf8(&out).unwrap().trim_end(), input);
+    }
+}
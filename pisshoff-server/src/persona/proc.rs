@@ -0,0 +1,147 @@
+//! Contents of the files under `/proc` that recon scripts tend to read directly rather than
+//! going through `lscpu` and `free`, generated from the persona so they agree with each other.
+
+use std::fmt::Write;
+
+use crate::persona::Persona;
+
+const X86_FLAGS: &str = "fpu vme de pse tsc msr pae mce cx8 apic sep mtrr pge mca cmov pat \
+pse36 clflush mmx fxsr sse sse2 ss syscall nx pdpe1gb rdtscp lm constant_tsc rep_good nopl \
+xtopology cpuid tsc_known_freq pni pclmulqdq ssse3 fma cx16 pcid sse4_1 sse4_2 x2apic movbe \
+popcnt tsc_deadline_timer aes xsave avx f16c rdrand hypervisor lahf_lm abm 3dnowprefetch \
+invpcid_single pti fsgsbase bmi1 hle avx2 smep bmi2 erms invpcid rtm rdseed adx smap xsaveopt \
+arat md_clear arch_capabilities";
+
+const ARM_FEATURES: &str =
+    "half thumb fastmult vfp edsp neon vfpv3 tls vfpv4 idiva idivt vfpd32 lpae evtstrm";
+
+pub fn cpuinfo(persona: &Persona) -> String {
+    let mut out = String::new();
+
+    for processor in 0..persona.cpu_count {
+        if persona.machine == "x86_64" {
+            write!(
+                out,
+                "processor\t: {processor}
+vendor_id\t: {vendor}
+cpu family\t: 6
+model\t\t: 79
+model name\t: {model}
+stepping\t: 1
+microcode\t: 0x1
+cpu MHz\t\t: {mhz:.3}
+cache size\t: 16384 KB
+physical id\t: 0
+siblings\t: {count}
+core id\t\t: {processor}
+cpu cores\t: {count}
+apicid\t\t: {processor}
+initial apicid\t: {processor}
+fpu\t\t: yes
+fpu_exception\t: yes
+cpuid level\t: 13
+wp\t\t: yes
+flags\t\t: {X86_FLAGS}
+bugs\t\t: cpu_meltdown spectre_v1 spectre_v2 spec_store_bypass l1tf mds swapgs taa itlb_multihit mmio_stale_data retbleed
+bogomips\t: {bogomips:.2}
+clflush size\t: 64
+cache_alignment\t: 64
+address sizes\t: 46 bits physical, 48 bits virtual
+power management:
+
+",
+                vendor = persona.cpu_vendor,
+                model = persona.cpu_model,
+                mhz = persona.cpu_mhz,
+                count = persona.cpu_count,
+                bogomips = persona.cpu_mhz * 2.0,
+            )
+            .unwrap();
+        } else {
+            write!(
+                out,
+                "processor\t: {processor}
+model name\t: ARMv7 Processor rev 5 (v7l)
+BogoMIPS\t: 38.40
+Features\t: {ARM_FEATURES}
+CPU implementer\t: 0x41
+CPU architecture: 7
+CPU variant\t: 0x0
+CPU part\t: 0xc07
+CPU revision\t: 5
+
+"
+            )
+            .unwrap();
+        }
+    }
+
+    if persona.machine != "x86_64" {
+        write!(
+            out,
+            "Hardware\t: Generic DT based system\nRevision\t: 0000\nSerial\t\t: 0000000000000000\n"
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+pub fn meminfo(persona: &Persona) -> String {
+    let usage = persona.memory_usage();
+    let buffers = usage.buff_cache / 10;
+
+    let mut out = String::new();
+    let mut line = |key: &str, value: u64| {
+        writeln!(out, "{:<16}{value:>8} kB", format!("{key}:")).unwrap();
+    };
+
+    line("MemTotal", usage.total);
+    line("MemFree", usage.free);
+    line("MemAvailable", usage.available);
+    line("Buffers", buffers);
+    line("Cached", usage.buff_cache - buffers);
+    line("SwapCached", 0);
+    line("Active", usage.used / 2 + usage.buff_cache / 2);
+    line("Inactive", usage.used / 2 + usage.buff_cache / 2);
+    line("SwapTotal", persona.swap);
+    line("SwapFree", persona.swap);
+    line("Dirty", 48);
+    line("Writeback", 0);
+    line("AnonPages", usage.used * 9 / 10);
+    line("Mapped", usage.used / 5);
+    line("Shmem", usage.shared);
+    line("Slab", usage.total / 50);
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cpuinfo, meminfo};
+    use crate::persona::Persona;
+
+    #[test]
+    fn cpuinfo_lists_every_processor() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let cpuinfo = cpuinfo(&persona);
+
+        assert_eq!(cpuinfo.matches("processor\t:").count(), 2);
+        assert!(cpuinfo.contains("model name\t: DO-Regular\n"));
+    }
+
+    #[test]
+    fn meminfo_agrees_with_free() {
+        let persona = toml::from_str::<Persona>("preset = \"container\"").unwrap();
+        let meminfo = meminfo(&persona);
+
+        assert!(
+            meminfo.starts_with("MemTotal:        8148348 kB\n"),
+            "{meminfo}"
+        );
+        assert!(
+            meminfo.contains("\nSwapTotal:       2097148 kB\n"),
+            "{meminfo}"
+        );
+    }
+}
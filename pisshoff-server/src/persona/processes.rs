@@ -0,0 +1,256 @@
+//! A synthetic process table for `ps` and `top`, made up of the persona's daemons along with
+//! the processes belonging to the client's own session.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use time::OffsetDateTime;
+
+use crate::persona::Persona;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Process {
+    pub pid: u32,
+    pub ppid: u32,
+    pub user: String,
+    /// The controlling terminal, if any.
+    pub tty: Option<String>,
+    pub stat: &'static str,
+    /// Virtual memory size in kibibytes.
+    pub vsz: u64,
+    /// Resident set size in kibibytes.
+    pub rss: u64,
+    /// Shared memory in kibibytes.
+    pub shared: u64,
+    /// CPU time used by the process so far.
+    pub time: Duration,
+    pub start: OffsetDateTime,
+    pub command: String,
+}
+
+impl Process {
+    /// Whether this is a kernel thread, rather than a user space process.
+    pub fn is_kernel_thread(&self) -> bool {
+        self.command.starts_with('[')
+    }
+
+    /// The name of the executable, as shown by `ps` without `-f`.
+    pub fn comm(&self) -> &str {
+        if self.is_kernel_thread() {
+            return self.command.trim_start_matches('[').trim_end_matches(']');
+        }
+
+        let exec = self.command.split(' ').next().unwrap_or_default();
+        exec.rsplit('/')
+            .next()
+            .unwrap_or(exec)
+            .trim_end_matches(':')
+            .trim_start_matches(|c| c == '-' || c == '@')
+    }
+}
+
+/// The session the process table is being generated for.
+#[derive(Debug, Clone, Copy)]
+pub struct Session<'a> {
+    pub username: &'a str,
+    pub tty: Option<&'a str>,
+    /// When the client logged in.
+    pub login: OffsetDateTime,
+    pub now: OffsetDateTime,
+    /// The command being run, which will show up as the last process.
+    pub command: &'a str,
+}
+
+/// Builds the process table of the persona, as seen from `session`. The persona's daemons keep
+/// the same pids on every call, while the session's processes are numbered according to when
+/// the client logged in.
+pub fn processes(persona: &Persona, boot: OffsetDateTime, session: &Session<'_>) -> Vec<Process> {
+    let mut hasher = DefaultHasher::new();
+    persona.hostname.hash(&mut hasher);
+    let rng = fastrand::Rng::with_seed(hasher.finish());
+
+    let uptime = (session.now - boot).whole_seconds().max(0).unsigned_abs();
+    let memory = persona.memory.max(1);
+
+    let mut processes = Vec::with_capacity(persona.daemons.len() + 4);
+    let mut pid = 0;
+    let mut kthreadd = None;
+
+    for (i, daemon) in persona.daemons.iter().enumerate() {
+        let kernel_thread = daemon.command.starts_with('[');
+
+        pid += match (i, kernel_thread) {
+            (0, _) => 1,
+            (_, true) => rng.u32(1..=3),
+            (_, false) => rng.u32(20..=200),
+        };
+
+        if kernel_thread && kthreadd.is_none() {
+            kthreadd = Some(pid);
+        }
+
+        let (ppid, vsz, rss, start, time) = if kernel_thread {
+            let ppid = kthreadd.filter(|v| *v != pid).unwrap_or(0);
+            (ppid, 0, 0, boot, Duration::ZERO)
+        } else {
+            let vsz = rng.u64(4_000..=(memory / 8).max(4_001));
+            let start = boot + Duration::from_secs(u64::from(pid / 100) + rng.u64(1..=5));
+            // somewhere between 0.01% and 0.02% of the CPU
+            let time = Duration::from_millis(uptime * rng.u64(100..=200) / 1_000);
+            (u32::from(i != 0), vsz, vsz / rng.u64(3..=12), start, time)
+        };
+
+        processes.push(Process {
+            pid,
+            ppid,
+            user: daemon.user.clone(),
+            tty: None,
+            stat: if kernel_thread { "I<" } else { "Ss" },
+            vsz,
+            rss,
+            shared: rss / 2,
+            time,
+            start,
+            command: daemon.command.clone(),
+        });
+    }
+
+    let ssh_daemon = processes
+        .iter()
+        .find(|v| v.command.contains("sshd") || v.command.contains("dropbear"))
+        .map(|v| (v.pid, v.command.clone()));
+
+    // plenty of other processes will have come and gone before the client logged in
+    #[allow(clippy::cast_possible_truncation)]
+    let mut pid = pid + 1000 + (session.login.unix_timestamp().unsigned_abs() % 30_000) as u32;
+    let mut ppid = ssh_daemon.as_ref().map_or(1, |(pid, _)| *pid);
+    let tty = session.tty.map(ToString::to_string);
+
+    // each of the session's processes is the child of the one before it
+    let mut spawn = |user: &str, tty: Option<String>, stat, command: String| {
+        pid += rng.u32(1..=12);
+
+        let process = Process {
+            pid,
+            ppid,
+            user: user.to_string(),
+            tty,
+            stat,
+            vsz: rng.u64(7_000..=20_000),
+            rss: rng.u64(3_000..=9_000),
+            shared: rng.u64(1_500..=3_000),
+            time: Duration::ZERO,
+            start: session.login,
+            command,
+        };
+
+        ppid = pid;
+        process
+    };
+
+    let shell = match &ssh_daemon {
+        Some((_, command)) if command.contains("dropbear") => {
+            processes.push(spawn("root", None, "S", command.clone()));
+            "-ash"
+        }
+        _ => {
+            processes.push(spawn(
+                "root",
+                None,
+                "Ss",
+                format!("sshd: {} [priv]", session.username),
+            ));
+            processes.push(spawn(
+                session.username,
+                None,
+                "S",
+                format!(
+                    "sshd: {}@{}",
+                    session.username,
+                    session.tty.unwrap_or("notty")
+                ),
+            ));
+            "-bash"
+        }
+    };
+
+    let shell = if tty.is_some() {
+        shell.to_string()
+    } else {
+        format!("bash -c {}", session.command)
+    };
+    processes.push(spawn(session.username, tty.clone(), "Ss", shell));
+
+    let stat = if tty.is_some() { "R+" } else { "R" };
+    let mut command = spawn(session.username, tty, stat, session.command.to_string());
+    command.start = session.now;
+    processes.push(command);
+
+    processes
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use time::macros::datetime;
+
+    use super::{processes, Session};
+    use crate::persona::Persona;
+
+    #[test]
+    fn lists_daemons_and_session() {
+        let persona = toml::from_str::<Persona>("preset = \"ubuntu-22.04\"").unwrap();
+        let boot = datetime!(2023-10-12 06:08:40 UTC);
+        let session = Session {
+            username: "ubuntu",
+            tty: Some("pts/0"),
+            login: datetime!(2023-10-24 09:10:00 UTC),
+            now: datetime!(2023-10-24 09:12:44 UTC),
+            command: "ps aux",
+        };
+
+        let first = processes(&persona, boot, &session);
+        let later = processes(
+            &persona,
+            boot,
+            &Session {
+                now: session.now + Duration::from_secs(60),
+                ..session
+            },
+        );
+
+        assert_eq!(first[0].pid, 1);
+        assert_eq!(first[0].command, "/sbin/init");
+        assert!(first.windows(2).all(|v| v[0].pid < v[1].pid));
+        assert_eq!(
+            first.iter().map(|v| v.pid).collect::<Vec<_>>(),
+            later.iter().map(|v| v.pid).collect::<Vec<_>>()
+        );
+
+        let session_processes = first
+            .iter()
+            .rev()
+            .take(4)
+            .map(|v| (v.user.as_str(), v.tty.as_deref(), v.command.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            session_processes,
+            [
+                ("ubuntu", Some("pts/0"), "ps aux"),
+                ("ubuntu", Some("pts/0"), "-bash"),
+                ("ubuntu", None, "sshd: ubuntu@pts/0"),
+                ("root", None, "sshd: ubuntu [priv]"),
+            ]
+        );
+
+        let sshd = first
+            .iter()
+            .find(|v| v.command.starts_with("sshd: /usr/sbin/sshd"))
+            .unwrap();
+        assert_eq!(first[first.len() - 4].ppid, sshd.pid);
+    }
+}
@@ -0,0 +1,352 @@
+//! Keeps captured payloads in the quarantine directory for analysis, named by their SHA-256
+//! hash so each is only stored once however many times it's seen. Each payload has a JSON
+//! sidecar alongside it recording where it was seen, and the least recently seen payloads are
+//! evicted once the directory grows past its size limit.
+
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::ErrorKind,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditLogAction, QuarantinedEvent, VirusTotalReport},
+    server::ConnectionState,
+};
+
+/// Most sightings kept in each payload's sidecar, older ones are dropped to make room.
+const MAX_SIGHTINGS: usize = 100;
+
+/// Stores are serialised, so a payload that's being stored can't be evicted by another one
+/// at the same time.
+static LOCK: Mutex<()> = parking_lot::const_mutex(());
+
+/// Where a payload came from.
+pub struct Origin<'a> {
+    /// Command or subsystem the payload came in through, ie. `wget` or `sftp`.
+    pub tool: &'static str,
+    /// Name the payload was given by the client.
+    pub name: Option<&'a str>,
+    /// URL the payload was fetched from.
+    pub url: Option<&'a str>,
+}
+
+/// Details of a payload, written alongside it as `<sha256>.json`.
+#[derive(Serialize, Deserialize, Debug)]
+struct Metadata {
+    sha256: Box<str>,
+    size: usize,
+    #[serde(with = "time::serde::rfc3339")]
+    first_seen: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    last_seen: OffsetDateTime,
+    /// Number of times the payload has been seen, including those no longer in `sightings`.
+    times_seen: u64,
+    /// The most recent times the payload was seen.
+    sightings: Vec<Sighting>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    virustotal: Option<VirusTotalReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Sighting {
+    connection_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    peer_address: Option<SocketAddr>,
+    tool: Cow<'static, str>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    name: Option<Box<str>>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    url: Option<Box<str>>,
+    #[serde(with = "time::serde::rfc3339")]
+    ts: OffsetDateTime,
+}
+
+/// Stores `data` in the quarantine directory in the background, if one has been configured,
+/// recording that it was in the connection's audit log.
+pub fn store(connection: &mut ConnectionState, sha256: &str, data: Bytes, origin: &Origin<'_>) {
+    let Some(directory) = connection.config().quarantine_directory.clone() else {
+        return;
+    };
+    let max_size = connection.config().quarantine_max_size;
+
+    if max_size.map_or(false, |max| data.len() as u64 > max) {
+        warn!(
+            sha256,
+            size = data.len(),
+            "Payload is too large to quarantine"
+        );
+        return;
+    }
+
+    let log = connection.audit_log();
+    let sighting = Sighting {
+        connection_id: log.connection_id,
+        peer_address: log.peer_address,
+        tool: Cow::Borrowed(origin.tool),
+        name: origin.name.map(Box::from),
+        url: origin.url.map(Box::from),
+        ts: OffsetDateTime::now_utc(),
+    };
+
+    let virus_total = connection.virus_total();
+    virus_total.submit(sha256, &directory);
+    let virustotal = virus_total.cached(sha256);
+
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::Quarantined(QuarantinedEvent {
+            sha256: Box::from(sha256),
+            size: data.len(),
+            tool: Cow::Borrowed(origin.tool),
+            virustotal,
+        }));
+
+    let sha256 = sha256.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let _guard = LOCK.lock();
+
+        if let Err(error) = write(&directory, &sha256, &data, sighting) {
+            warn!(%error, sha256, "Failed to write payload to quarantine");
+            return;
+        }
+
+        if let Some(max_size) = max_size {
+            if let Err(error) = evict(&directory, max_size) {
+                warn!(%error, "Failed to evict payloads from quarantine");
+            }
+        }
+    });
+}
+
+/// Writes the payload out if it hasn't been seen before, and adds `sighting` to its sidecar.
+fn write(directory: &Path, sha256: &str, data: &[u8], sighting: Sighting) -> std::io::Result<()> {
+    std::fs::create_dir_all(directory)?;
+
+    let path = directory.join(sha256);
+
+    if path.exists() {
+        // the modification time tracks when the payload was last seen, so it's evicted last
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now())?;
+    } else {
+        // written under a temporary name first, so a half written payload is never mistaken
+        // for the real thing
+        let partial = directory.join(format!("{sha256}.partial"));
+        std::fs::write(&partial, data)?;
+        std::fs::rename(&partial, &path)?;
+        debug!(sha256, size = data.len(), "Quarantined new payload");
+    }
+
+    let sidecar = sidecar_path(&path);
+
+    let mut metadata = match std::fs::read(&sidecar) {
+        Ok(v) => serde_json::from_slice(&v).ok(),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    }
+    .unwrap_or_else(|| Metadata {
+        sha256: Box::from(sha256),
+        size: data.len(),
+        first_seen: sighting.ts,
+        last_seen: sighting.ts,
+        times_seen: 0,
+        sightings: Vec::new(),
+        virustotal: None,
+    });
+
+    metadata.last_seen = sighting.ts;
+    metadata.times_seen += 1;
+    metadata.sightings.push(sighting);
+
+    let excess = metadata.sightings.len().saturating_sub(MAX_SIGHTINGS);
+    metadata.sightings.drain(..excess);
+
+    let json = serde_json::to_vec_pretty(&metadata)?;
+    std::fs::write(sidecar, json)
+}
+
+/// Adds the payload's VirusTotal report to its sidecar in the background, if it's still in
+/// quarantine.
+pub fn annotate(directory: PathBuf, sha256: Box<str>, report: VirusTotalReport) {
+    tokio::task::spawn_blocking(move || {
+        let _guard = LOCK.lock();
+
+        let sidecar = directory.join(format!("{sha256}.json"));
+
+        let res = std::fs::read(&sidecar).and_then(|v| {
+            let mut metadata: Metadata = serde_json::from_slice(&v)?;
+            metadata.virustotal = Some(report);
+            std::fs::write(&sidecar, serde_json::to_vec_pretty(&metadata)?)
+        });
+
+        match res {
+            Ok(()) => {}
+            // the payload was evicted before it was looked up
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(error) => warn!(%error, %sha256, "Failed to add VirusTotal report to quarantine"),
+        }
+    });
+}
+
+/// Removes the least recently seen payloads, along with their sidecars, until those left take
+/// up no more than `max_size` bytes.
+fn evict(directory: &Path, max_size: u64) -> std::io::Result<()> {
+    let mut payloads = Vec::new();
+    let mut total = 0;
+
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+
+        if !is_payload(&entry.path()) {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        total += metadata.len();
+        payloads.push((metadata.modified()?, metadata.len(), entry.path()));
+    }
+
+    payloads.sort();
+
+    for (_, size, path) in payloads {
+        if total <= max_size {
+            break;
+        }
+
+        debug!(?path, "Evicting payload from quarantine");
+        std::fs::remove_file(&path)?;
+        let _res = std::fs::remove_file(sidecar_path(&path));
+        total -= size;
+    }
+
+    Ok(())
+}
+
+/// Payloads in the quarantine directory, leaving out sidecars and those still being written.
+pub fn payloads(directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut payloads = Vec::new();
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if is_payload(&path) {
+            payloads.push(path);
+        }
+    }
+
+    Ok(payloads)
+}
+
+/// Whether `path` is a payload, rather than a sidecar or one still being written.
+fn is_payload(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|v| v.to_str())
+        .map_or(false, |v| {
+            v.len() == 64 && v.bytes().all(|c| c.is_ascii_hexdigit())
+        })
+}
+
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        borrow::Cow,
+        time::{Duration, SystemTime},
+    };
+
+    use time::OffsetDateTime;
+
+    use super::{evict, write, Metadata, Sighting};
+    use crate::audit::sha256_hex;
+
+    fn sighting(tool: &'static str) -> Sighting {
+        Sighting {
+            connection_id: uuid::Uuid::nil(),
+            peer_address: None,
+            tool: Cow::Borrowed(tool),
+            name: None,
+            url: None,
+            ts: OffsetDateTime::now_utc(),
+        }
+    }
+
+    #[test]
+    fn deduplicates() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-quarantine-{}", uuid::Uuid::new_v4()));
+        let sha256 = sha256_hex(b"payload");
+
+        write(&directory, &sha256, b"payload", sighting("wget")).unwrap();
+        write(&directory, &sha256, b"payload", sighting("scp")).unwrap();
+
+        assert_eq!(std::fs::read(directory.join(&*sha256)).unwrap(), b"payload");
+
+        let metadata: Metadata = serde_json::from_slice(
+            &std::fs::read(directory.join(format!("{sha256}.json"))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(metadata.times_seen, 2);
+        assert_eq!(metadata.sightings.len(), 2);
+        assert_eq!(metadata.sightings[1].tool, "scp");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_seen() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-quarantine-{}", uuid::Uuid::new_v4()));
+        let now = SystemTime::now();
+
+        let mut hashes = Vec::new();
+
+        for (i, data) in [b"first", b"again", b"third"].iter().enumerate() {
+            let sha256 = sha256_hex(*data);
+            write(&directory, &sha256, *data, sighting("sftp")).unwrap();
+
+            // make sure the modification times are distinct, however coarse the file system's
+            std::fs::File::options()
+                .write(true)
+                .open(directory.join(&*sha256))
+                .unwrap()
+                .set_modified(now - Duration::from_secs(60 * (3 - i as u64)))
+                .unwrap();
+
+            hashes.push(sha256);
+        }
+
+        evict(&directory, 10).unwrap();
+
+        assert!(!directory.join(&*hashes[0]).exists());
+        assert!(!directory.join(format!("{}.json", hashes[0])).exists());
+        assert!(directory.join(&*hashes[1]).exists());
+        assert!(directory.join(&*hashes[2]).exists());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+//! Records the terminal output of shell sessions in [asciicast v2] format so they can be replayed
+//! with `asciinema play`.
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::audit::AuditLog;
+
+/// Maximum amount of terminal data we'll record for a single connection, anything past this is
+/// dropped.
+const MAX_RECORDING_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Serialize, Clone, Copy)]
+enum EventKind {
+    #[serde(rename = "o")]
+    Output,
+    #[serde(rename = "i")]
+    Input,
+    #[serde(rename = "r")]
+    Resize,
+}
+
+#[derive(Serialize)]
+struct Header<'a> {
+    version: u8,
+    width: u32,
+    height: u32,
+    timestamp: i64,
+    env: HeaderEnv<'a>,
+}
+
+#[derive(Serialize)]
+struct HeaderEnv<'a> {
+    #[serde(rename = "TERM")]
+    term: &'a str,
+    #[serde(rename = "SHELL")]
+    shell: &'a str,
+}
+
+struct Cast {
+    start: Instant,
+    width: u32,
+    height: u32,
+    term: Option<Box<str>>,
+    events: Vec<(Duration, EventKind, String)>,
+    size: usize,
+}
+
+impl Cast {
+    fn push(&mut self, kind: EventKind, data: String) {
+        if self.size + data.len() > MAX_RECORDING_SIZE {
+            return;
+        }
+
+        self.size += data.len();
+        self.events.push((self.start.elapsed(), kind, data));
+    }
+}
+
+/// Handle to a connection's recording, cheaply cloneable so it can be passed down to the
+/// sessions writing to the terminal.
+#[derive(Clone)]
+pub struct Recording(Arc<Mutex<Cast>>);
+
+impl Recording {
+    pub fn new(start: Instant) -> Self {
+        Self(Arc::new(Mutex::new(Cast {
+            start,
+            width: 80,
+            height: 24,
+            term: None,
+            events: Vec::new(),
+            size: 0,
+        })))
+    }
+
+    pub fn output(&self, data: &[u8]) {
+        self.0.lock().push(
+            EventKind::Output,
+            String::from_utf8_lossy(data).into_owned(),
+        );
+    }
+
+    pub fn input(&self, data: &[u8]) {
+        self.0
+            .lock()
+            .push(EventKind::Input, String::from_utf8_lossy(data).into_owned());
+    }
+
+    /// Sets the size of the terminal, the first PTY requested on the connection determines the
+    /// dimensions in the header, any further changes are recorded as resize events.
+    pub fn resize(&self, term: Option<&str>, width: u32, height: u32) {
+        let mut cast = self.0.lock();
+
+        if cast.term.is_none() && cast.events.is_empty() {
+            cast.term = Some(Box::from(term.unwrap_or("xterm")));
+            cast.width = width;
+            cast.height = height;
+        } else {
+            cast.push(EventKind::Resize, format!("{width}x{height}"));
+        }
+    }
+
+    /// Writes the recording out to the given path in the background, returning `false` if
+    /// there was nothing to write.
+    pub fn finish(self, path: PathBuf, log: &AuditLog) -> bool {
+        let cast = self.0.lock();
+
+        if cast.events.is_empty() {
+            return false;
+        }
+
+        let header = Header {
+            version: 2,
+            width: cast.width,
+            height: cast.height,
+            timestamp: log.ts.unix_timestamp(),
+            env: HeaderEnv {
+                term: cast.term.as_deref().unwrap_or("xterm"),
+                shell: "/bin/bash",
+            },
+        };
+
+        let mut out = match serde_json::to_vec(&header) {
+            Ok(v) => v,
+            Err(error) => {
+                warn!(%error, "Failed to serialise recording header");
+                return false;
+            }
+        };
+        out.push(b'\n');
+
+        for (time, kind, data) in &cast.events {
+            if serde_json::to_writer(&mut out, &(time.as_secs_f64(), kind, data)).is_ok() {
+                out.push(b'\n');
+            }
+        }
+
+        drop(cast);
+
+        tokio::spawn(async move {
+            if let Some(parent) = path.parent() {
+                if let Err(error) = tokio::fs::create_dir_all(parent).await {
+                    warn!(%error, ?path, "Failed to create directory for recording");
+                    return;
+                }
+            }
+
+            match tokio::fs::write(&path, out).await {
+                Ok(()) => debug!(?path, "Wrote session recording"),
+                Err(error) => warn!(%error, ?path, "Failed to write session recording"),
+            }
+        });
+
+        true
+    }
+}
+
+/// Expands the `{connection_id}`, `{peer_ip}` and `{timestamp}` placeholders within the
+/// configured recording path.
+pub fn expand_path_template(template: &str, log: &AuditLog) -> PathBuf {
+    let peer_ip = log
+        .peer_address
+        .map_or_else(|| "unknown".to_string(), |v| v.ip().to_string());
+
+    template
+        .replace("{connection_id}", &log.connection_id.to_string())
+        .replace("{peer_ip}", &peer_ip)
+        .replace("{timestamp}", &log.ts.unix_timestamp().to_string())
+        .into()
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use crate::{audit::AuditLog, recording::expand_path_template};
+
+    #[test]
+    fn path_template() {
+        let log = AuditLog {
+            connection_id: uuid::Uuid::from_bytes([
+                1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+            ]),
+            peer_address: Some(SocketAddr::from(([127, 0, 0, 1], 1234))),
+            ..AuditLog::default()
+        };
+
+        assert_eq!(
+            expand_path_template("/casts/{peer_ip}/{connection_id}.cast", &log),
+            std::path::Path::new("/casts/127.0.0.1/01020304-0506-0708-090a-0b0c0d0e0f10.cast"),
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Plays a connection back to the terminal with its original timing, from its asciicast
+//! recording if one was written, or otherwise from the commands in its audit log.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    audit::{AuditLog, AuditLogAction},
+    report,
+};
+
+/// How quickly to play a session back.
+#[derive(Debug, Clone, Copy)]
+pub struct Timing {
+    /// Multiplier for the speed the session originally ran at.
+    pub speed: f64,
+    /// Longest pause between frames, in seconds.
+    pub max_idle: Option<f64>,
+}
+
+/// Output written to the client's terminal, `offset` into the connection.
+#[derive(Debug, PartialEq)]
+struct Frame {
+    offset: Duration,
+    data: String,
+}
+
+/// Replays `target`, which is either the path to an asciicast recording, or the ID of a
+/// connection (or the start of one) to look for in the audit logs in `paths`.
+pub fn run(target: &str, paths: &[PathBuf], timing: Timing) -> anyhow::Result<()> {
+    if timing.speed.is_nan() || timing.speed <= 0.0 {
+        return Err(anyhow!("speed must be greater than zero"));
+    }
+
+    let frames = if Path::new(target).is_file() {
+        read_cast_file(Path::new(target))?
+    } else {
+        let log = find(target, paths)?;
+
+        match log.recording.as_deref().map(Path::new) {
+            Some(path) if path.is_file() => read_cast_file(path)?,
+            recording => {
+                if let Some(path) = recording {
+                    eprintln!("Recording {} is missing", path.display());
+                }
+
+                eprintln!("Replaying commands only, as there's no recording of the terminal");
+                commands(&log)
+            }
+        }
+    };
+
+    play(&frames, timing, &mut std::io::stdout().lock())?;
+
+    Ok(())
+}
+
+/// Looks through the audit logs in `paths` for the connection with an ID starting with `id`.
+fn find(id: &str, paths: &[PathBuf]) -> anyhow::Result<AuditLog> {
+    let id = id.to_ascii_lowercase();
+
+    if id.is_empty() {
+        return Err(anyhow!("no connection ID given"));
+    } else if paths.is_empty() {
+        return Err(anyhow!(
+            "{id} isn't a recording, pass the audit logs to look for it in"
+        ));
+    }
+
+    let mut found = Vec::new();
+
+    for path in paths {
+        for file in report::files(path)? {
+            let reader = report::open(&file)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            find_in(reader, &id, &mut found)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+        }
+    }
+
+    match found.len() {
+        0 => Err(anyhow!("no connection found with ID {id}")),
+        1 => Ok(found.remove(0)),
+        n => Err(anyhow!("{n} connections have IDs starting with {id}")),
+    }
+}
+
+/// Adds each audit log read from `reader` with an ID starting with `id` to `found`.
+fn find_in(reader: impl BufRead, id: &str, found: &mut Vec<AuditLog>) -> std::io::Result<()> {
+    for line in reader.split(b'\n') {
+        let line = line?;
+
+        // the ID is in there somewhere if it's the right line, which saves parsing the rest
+        if !line.windows(id.len()).any(|v| v == id.as_bytes()) {
+            continue;
+        }
+
+        if let Ok(log) = serde_json::from_slice::<AuditLog>(&line) {
+            if log.connection_id.to_string().starts_with(id) {
+                found.push(log);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_cast_file(path: &Path) -> anyhow::Result<Vec<Frame>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    read_cast(BufReader::new(file)).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Reads the output events out of an asciicast v2 recording, skipping its header.
+fn read_cast(reader: impl BufRead) -> anyhow::Result<Vec<Frame>> {
+    let mut frames = Vec::new();
+
+    for line in reader.lines().skip(1) {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (offset, kind, data) = serde_json::from_str::<(f64, String, String)>(&line)?;
+
+        if kind == "o" {
+            frames.push(Frame {
+                offset: Duration::try_from_secs_f64(offset)?,
+                data,
+            });
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Makes up the session from the commands in its audit log, as though each were typed at a
+/// prompt, for connections that weren't recorded.
+fn commands(log: &AuditLog) -> Vec<Frame> {
+    log.events
+        .iter()
+        .filter_map(|event| match &event.action {
+            AuditLogAction::ExecCommand(command) => Some(Frame {
+                offset: Duration::from_millis(event.offset_ms),
+                data: format!("$ {}\r\n", command.args.join(" ")),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn play(frames: &[Frame], timing: Timing, out: &mut impl Write) -> std::io::Result<()> {
+    let mut previous = Duration::ZERO;
+
+    for frame in frames {
+        std::thread::sleep(delay(previous, frame.offset, timing));
+        previous = frame.offset;
+
+        out.write_all(frame.data.as_bytes())?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+/// How long to wait between frames at `previous` and `next`.
+fn delay(previous: Duration, next: Duration, timing: Timing) -> Duration {
+    let delay = next.saturating_sub(previous).as_secs_f64() / timing.speed;
+    let delay = timing.max_idle.map_or(delay, |max| delay.min(max));
+
+    Duration::try_from_secs_f64(delay).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{commands, delay, find_in, read_cast, Frame, Timing};
+    use crate::audit::{AuditLog, AuditLogAction, ExecCommandEvent};
+
+    #[test]
+    fn reads_cast() {
+        let cast = concat!(
+            r#"{"version":2,"width":80,"height":24,"timestamp":0,"env":{"TERM":"xterm","SHELL":"/bin/bash"}}"#,
+            "\n",
+            r#"[0.5,"o","$ "]"#,
+            "\n",
+            r#"[1.0,"i","ls\r"]"#,
+            "\n",
+            r#"[1.25,"o","ls\r\n"]"#,
+            "\n",
+        );
+
+        assert_eq!(
+            read_cast(cast.as_bytes()).unwrap(),
+            [
+                Frame {
+                    offset: Duration::from_millis(500),
+                    data: "$ ".to_string(),
+                },
+                Frame {
+                    offset: Duration::from_millis(1250),
+                    data: "ls\r\n".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_connection() {
+        let mut log = AuditLog {
+            connection_id: uuid::Uuid::from_bytes([
+                1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+            ]),
+            ..AuditLog::default()
+        };
+        log.push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+            args: Box::from(["uname -a".to_string()]),
+        }));
+
+        let input = [
+            serde_json::to_string(&AuditLog::default()).unwrap(),
+            serde_json::to_string(&log).unwrap(),
+        ]
+        .join("\n");
+
+        let mut found = Vec::new();
+        find_in(input.as_bytes(), "0102", &mut found).unwrap();
+        assert_eq!(found.len(), 1);
+
+        let frames = commands(&found[0]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data, "$ uname -a\r\n");
+    }
+
+    #[test]
+    fn timing() {
+        let timing = Timing {
+            speed: 2.0,
+            max_idle: Some(1.5),
+        };
+
+        assert_eq!(
+            delay(Duration::from_secs(1), Duration::from_secs(3), timing),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            delay(Duration::from_secs(1), Duration::from_secs(60), timing),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(
+            delay(Duration::from_secs(3), Duration::from_secs(1), timing),
+            Duration::ZERO
+        );
+    }
+}
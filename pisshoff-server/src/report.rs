@@ -0,0 +1,363 @@
+//! Summarises collected audit logs, giving operators the most common credentials, commands and
+//! source countries without needing anything more than the files the server wrote.
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::Serialize;
+use time::UtcOffset;
+
+use crate::audit::{AuditLog, AuditLogAction, LoginAttemptEvent};
+
+/// Reads every audit log in `paths`, each of which can be a file or a directory of them, and
+/// prints the `top` most common of everything as a table, or as JSON if `json` is set.
+pub fn run(paths: &[PathBuf], top: usize, json: bool) -> anyhow::Result<()> {
+    let mut tally = Tally::default();
+
+    for path in paths {
+        for file in files(path)? {
+            let reader =
+                open(&file).with_context(|| format!("failed to open {}", file.display()))?;
+            tally
+                .read(reader)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+        }
+    }
+
+    let summary = tally.summary(top);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        print!("{summary}");
+    }
+
+    Ok(())
+}
+
+/// Lists the files making up `path`, which is either a single file or a directory holding the
+/// current log along with any rotated ones.
+pub fn files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+
+    for entry in
+        std::fs::read_dir(path).with_context(|| format!("failed to read {}", path.display()))?
+    {
+        let entry = entry?;
+
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Opens an audit log, decompressing it if it's been rotated out and compressed.
+pub fn open(path: &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+
+    let reader: Box<dyn Read> = match path.extension().and_then(|v| v.to_str()) {
+        Some("gz") => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Some("zst") => Box::new(zstd::stream::read::Decoder::new(file)?),
+        _ => Box::new(file),
+    };
+
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+/// Running counts over every connection read so far.
+#[derive(Default)]
+struct Tally {
+    connections: usize,
+    login_attempts: usize,
+    /// Lines that couldn't be read as an audit log, such as the last line of a log that's still
+    /// being written to.
+    skipped_lines: usize,
+    usernames: HashMap<String, usize>,
+    passwords: HashMap<String, usize>,
+    commands: HashMap<String, usize>,
+    countries: HashMap<String, usize>,
+    tags: HashMap<String, usize>,
+    /// Connections by the hour of the day, in UTC, they were opened at.
+    hours: [usize; 24],
+}
+
+impl Tally {
+    /// Adds each line of `reader` to the tally.
+    fn read(&mut self, reader: impl BufRead) -> std::io::Result<()> {
+        for line in reader.split(b'\n') {
+            let line = line?;
+
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            match serde_json::from_slice::<AuditLog>(&line) {
+                Ok(log) => self.add(&log),
+                Err(_) => self.skipped_lines += 1,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add(&mut self, log: &AuditLog) {
+        self.connections += 1;
+        self.hours[usize::from(log.ts.to_offset(UtcOffset::UTC).hour())] += 1;
+
+        if let Some(country) = log.geoip.as_ref().and_then(|v| v.country.as_deref()) {
+            increment(&mut self.countries, country);
+        }
+
+        for tag in &log.tags {
+            increment(&mut self.tags, tag);
+        }
+
+        for event in &log.events {
+            match &event.action {
+                AuditLogAction::LoginAttempt(attempt) => {
+                    self.login_attempts += 1;
+
+                    match attempt {
+                        LoginAttemptEvent::UsernamePassword { username, password } => {
+                            increment(&mut self.usernames, username);
+                            increment(&mut self.passwords, password);
+                        }
+                        LoginAttemptEvent::PublicKey { .. } => {}
+                    }
+                }
+                AuditLogAction::ExecCommand(command) => {
+                    increment(&mut self.commands, &command.args.join(" "));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn summary(self, top: usize) -> Summary {
+        let hours = self
+            .hours
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(hour, count)| (format!("{hour:02}:00"), *count))
+            .collect::<Vec<_>>();
+
+        Summary {
+            connections: self.connections,
+            login_attempts: self.login_attempts,
+            skipped_lines: self.skipped_lines,
+            usernames: most_common(self.usernames, top),
+            passwords: most_common(self.passwords, top),
+            commands: most_common(self.commands, top),
+            countries: most_common(self.countries, top),
+            tags: most_common(self.tags, top),
+            busiest_hours: most_common(hours, top),
+        }
+    }
+}
+
+fn increment(counts: &mut HashMap<String, usize>, value: &str) {
+    if let Some(count) = counts.get_mut(value) {
+        *count += 1;
+    } else {
+        counts.insert(value.to_string(), 1);
+    }
+}
+
+/// Picks the `top` most common values out of `counts`, ties being broken alphabetically so the
+/// report comes out the same each time.
+fn most_common(counts: impl IntoIterator<Item = (String, usize)>, top: usize) -> Vec<Count> {
+    let mut counts = counts
+        .into_iter()
+        .map(|(value, count)| Count { value, count })
+        .collect::<Vec<_>>();
+
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    counts.truncate(top);
+    counts
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+    connections: usize,
+    login_attempts: usize,
+    skipped_lines: usize,
+    usernames: Vec<Count>,
+    passwords: Vec<Count>,
+    commands: Vec<Count>,
+    countries: Vec<Count>,
+    tags: Vec<Count>,
+    /// Hours of the day, in UTC, the most connections were opened in.
+    busiest_hours: Vec<Count>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct Count {
+    value: String,
+    count: usize,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Connections:    {}", self.connections)?;
+        writeln!(f, "Login attempts: {}", self.login_attempts)?;
+
+        if self.skipped_lines > 0 {
+            writeln!(f, "Skipped lines:  {}", self.skipped_lines)?;
+        }
+
+        for (heading, counts) in [
+            ("Username", &self.usernames),
+            ("Password", &self.passwords),
+            ("Command", &self.commands),
+            ("Country", &self.countries),
+            ("Tag", &self.tags),
+            ("Hour (UTC)", &self.busiest_hours),
+        ] {
+            if counts.is_empty() {
+                continue;
+            }
+
+            // overly long commands would push the counts off the side of the screen
+            let values = counts
+                .iter()
+                .map(|v| truncate(&v.value, 60))
+                .collect::<Vec<_>>();
+            let width = values
+                .iter()
+                .map(|v| v.chars().count())
+                .chain([heading.len()])
+                .max()
+                .unwrap_or_default();
+
+            writeln!(f)?;
+            writeln!(f, "{heading:<width$}  Count")?;
+
+            for (value, count) in values.iter().zip(counts) {
+                writeln!(f, "{value:<width$}  {}", count.count)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Cuts `value` down to `max` characters, escaping anything that would mess up the table.
+fn truncate(value: &str, max: usize) -> String {
+    let escaped = value.escape_debug().to_string();
+
+    if escaped.chars().count() <= max {
+        return escaped;
+    }
+
+    let mut out = escaped.chars().take(max - 3).collect::<String>();
+    out.push_str("...");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use time::macros::datetime;
+
+    use super::{Count, Tally};
+    use crate::audit::{AuditLog, AuditLogAction, ExecCommandEvent, GeoIp, LoginAttemptEvent};
+
+    fn log(username: &str, password: &str, command: &str, country: &str) -> String {
+        let mut log = AuditLog {
+            ts: datetime!(2023-06-01 14:30 UTC),
+            host: Cow::Borrowed("test"),
+            geoip: Some(GeoIp {
+                country: Some(Box::from(country)),
+                ..GeoIp::default()
+            }),
+            ..AuditLog::default()
+        };
+
+        log.push_action(AuditLogAction::LoginAttempt(
+            LoginAttemptEvent::UsernamePassword {
+                username: Box::from(username),
+                password: Box::from(password),
+            },
+        ));
+        log.push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+            args: Box::from([command.to_string()]),
+        }));
+
+        serde_json::to_string(&log).unwrap()
+    }
+
+    fn count(value: &str, count: usize) -> Count {
+        Count {
+            value: value.to_string(),
+            count,
+        }
+    }
+
+    #[test]
+    fn summarises() {
+        let input = [
+            log("root", "123456", "uname -a", "CN"),
+            log("root", "admin", "uname -a", "US"),
+            log("admin", "admin", "cat /proc/cpuinfo", "CN"),
+            "{\"connection_id\":".to_string(),
+        ]
+        .join("\n");
+
+        let mut tally = Tally::default();
+        tally.read(input.as_bytes()).unwrap();
+        let summary = tally.summary(1);
+
+        assert_eq!(summary.connections, 3);
+        assert_eq!(summary.login_attempts, 3);
+        assert_eq!(summary.skipped_lines, 1);
+        assert_eq!(summary.usernames, [count("root", 2)]);
+        assert_eq!(summary.passwords, [count("admin", 2)]);
+        assert_eq!(summary.commands, [count("uname -a", 2)]);
+        assert_eq!(summary.countries, [count("CN", 2)]);
+        assert_eq!(summary.busiest_hours, [count("14:00", 3)]);
+    }
+
+    #[test]
+    fn formats_table() {
+        let mut tally = Tally::default();
+        tally
+            .read(log("root", "root", "w", "GB").as_bytes())
+            .unwrap();
+
+        assert_eq!(
+            tally.summary(10).to_string(),
+            "Connections:    1\n\
+             Login attempts: 1\n\
+             \n\
+             Username  Count\n\
+             root      1\n\
+             \n\
+             Password  Count\n\
+             root      1\n\
+             \n\
+             Command  Count\n\
+             w        1\n\
+             \n\
+             Country  Count\n\
+             GB       1\n\
+             \n\
+             Hour (UTC)  Count\n\
+             14:00       1\n"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::{net::IpAddr, num::NonZeroUsize, time::Duration};
+
+use hickory_resolver::TokioAsyncResolver;
+use lru::LruCache;
+use parking_lot::Mutex;
+use tracing::debug;
+
+use crate::config::ReverseDnsConfig;
+
+/// Resolves the PTR records of peer addresses, caching the answers since the same scanners tend
+/// to come back time and time again.
+#[derive(Default)]
+pub struct ReverseDns {
+    inner: Option<Inner>,
+}
+
+struct Inner {
+    resolver: TokioAsyncResolver,
+    timeout: Duration,
+    /// Names we've already resolved, including addresses that don't have one.
+    cache: Mutex<LruCache<IpAddr, Option<Box<str>>>>,
+}
+
+impl ReverseDns {
+    pub fn new(config: &ReverseDnsConfig) -> Result<Self, std::io::Error> {
+        if !config.enabled {
+            return Ok(Self::default());
+        }
+
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+        let cache_size = NonZeroUsize::new(config.cache_size).unwrap_or(NonZeroUsize::MIN);
+
+        Ok(Self {
+            inner: Some(Inner {
+                resolver,
+                timeout: Duration::from_millis(config.timeout_ms),
+                cache: Mutex::new(LruCache::new(cache_size)),
+            }),
+        })
+    }
+
+    /// Whether lookups have been enabled, [`Self::lookup`] always returns `None` otherwise.
+    pub fn enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Returns the name `ip` points back to, if it has one and it could be resolved in time.
+    pub async fn lookup(&self, ip: IpAddr) -> Option<Box<str>> {
+        let inner = self.inner.as_ref()?;
+
+        if let Some(name) = inner.cache.lock().get(&ip) {
+            return name.clone();
+        }
+
+        let name =
+            match tokio::time::timeout(inner.timeout, inner.resolver.reverse_lookup(ip)).await {
+                Ok(Ok(lookup)) => lookup.iter().next().map(|name| {
+                    name.to_utf8()
+                        .trim_end_matches('.')
+                        .to_string()
+                        .into_boxed_str()
+                }),
+                Ok(Err(error)) => {
+                    debug!(%ip, %error, "Failed to resolve PTR record");
+                    None
+                }
+                Err(_) => {
+                    // don't cache timeouts, the resolver might just be having a bad day
+                    debug!(%ip, "Timed out resolving PTR record");
+                    return None;
+                }
+            };
+
+        inner.cache.lock().put(ip, name.clone());
+        name
+    }
+}
@@ -0,0 +1,280 @@
+//! Restricts what the process can do once it has started up, so an attacker who manages to
+//! exploit the server doesn't get much of a foothold. A seccomp filter stops the process from
+//! running programs or meddling with the kernel, and Landlock limits it to the parts of the
+//! file system named in the config.
+
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::config::{AuditSinkConfig, Config};
+
+/// Files read by the libraries we use, such as the resolver, or by the dynamic loader.
+const SYSTEM_READ_PATHS: &[&str] = &[
+    "/etc/gai.conf",
+    "/etc/host.conf",
+    "/etc/hosts",
+    "/etc/localtime",
+    "/etc/nsswitch.conf",
+    "/etc/resolv.conf",
+    "/dev/urandom",
+    "/lib",
+    "/lib64",
+    "/usr/lib",
+    "/usr/lib64",
+    "/nix/store",
+    "/proc/self",
+    "/sys/fs/cgroup",
+];
+
+/// Applies the sandbox to the calling thread and any threads it goes on to spawn. This needs to
+/// happen before the runtime is started, as Landlock can't restrict threads that already exist.
+pub fn apply(config: &Config, config_path: &Path) -> anyhow::Result<()> {
+    if !config.sandbox.enabled {
+        warn!("Sandbox is disabled");
+        return Ok(());
+    }
+
+    imp::apply(&Paths::new(config, config_path))
+}
+
+/// Paths the process needs access to after startup.
+struct Paths {
+    read: Vec<PathBuf>,
+    write: Vec<PathBuf>,
+}
+
+impl Paths {
+    fn new(config: &Config, config_path: &Path) -> Self {
+        let mut read = vec![config_path.to_path_buf()];
+        read.extend(config.file_system_snapshot.clone());
+        read.extend(config.geoip.city_database.clone());
+        read.extend(config.geoip.asn_database.clone());
+        read.extend(config.classifier.signature_files.iter().cloned());
+        read.extend(SYSTEM_READ_PATHS.iter().map(PathBuf::from));
+        read.extend(config.sandbox.read_paths.iter().cloned());
+
+        let mut write = Vec::new();
+
+        for sink in config.audit_sinks().iter() {
+            match sink {
+                // files are written alongside these, such as journals or rotated logs
+                AuditSinkConfig::File(sink) => {
+                    write.extend(parent(&sink.path));
+                    // read again whenever the sinks are reopened on reload
+                    read.extend(sink.checkpoint_key.clone());
+                }
+                AuditSinkConfig::Sqlite(sink) => write.extend(parent(&sink.path)),
+                AuditSinkConfig::Forward(sink) => {
+                    read.extend(sink.ca_file.clone());
+                    read.extend(sink.certificate.clone());
+                    read.extend(sink.private_key.clone());
+                    write.extend(sink.spool_directory.clone());
+                }
+                AuditSinkConfig::Kafka(sink) => read.extend(sink.ca_file.clone()),
+                AuditSinkConfig::Mqtt(sink) => read.extend(sink.ca_file.clone()),
+                AuditSinkConfig::Nats(sink) => read.extend(sink.ca_file.clone()),
+                AuditSinkConfig::Postgres(sink) => read.extend(sink.ca_file.clone()),
+                AuditSinkConfig::Syslog(sink) => read.extend(sink.ca_file.clone()),
+                AuditSinkConfig::Webhook(sink) => write.extend(sink.spool_directory.clone()),
+                AuditSinkConfig::Hpfeeds(_) | AuditSinkConfig::Stdout => {}
+            }
+        }
+
+        write.extend(config.quarantine_directory.clone());
+        write.extend(config.state_dir.clone());
+        write.extend(config.audit_queue.spill_directory.clone());
+        write.extend(
+            config
+                .recording_path
+                .as_deref()
+                .and_then(template_directory),
+        );
+        write.extend(config.admin_socket.as_deref().and_then(parent));
+        // SQLite looks for a journal alongside the database, even when only reading it
+        read.extend(config.api.as_ref().and_then(|v| parent(&v.database)));
+        write.extend(config.sandbox.write_paths.iter().cloned());
+
+        Self { read, write }
+    }
+}
+
+/// The directory `path` is in, which is the working directory for bare file names.
+pub fn parent(path: &Path) -> Option<PathBuf> {
+    path.parent().map(|v| {
+        if v.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            v.to_path_buf()
+        }
+    })
+}
+
+/// The directory a path template such as `/var/lib/pisshoff/{peer_ip}/{connection_id}.cast`
+/// writes beneath, which is everything up to the first substitution.
+pub fn template_directory(template: &str) -> Option<PathBuf> {
+    match template.split_once('{') {
+        Some(("", _)) => Some(PathBuf::from(".")),
+        Some((prefix, _)) if prefix.ends_with('/') => Some(PathBuf::from(prefix)),
+        Some((prefix, _)) => parent(Path::new(prefix)),
+        None => parent(Path::new(template)),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::collections::BTreeMap;
+
+    use anyhow::Context;
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr,
+        RulesetStatus, ABI,
+    };
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, TargetArch};
+    use tracing::{info, warn};
+
+    use super::Paths;
+
+    /// Syscalls nothing in the server has any business making, mostly ones that would let an
+    /// attacker run their own programs, escalate their privileges or poke at the kernel.
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_acct,
+        libc::SYS_add_key,
+        libc::SYS_bpf,
+        libc::SYS_chroot,
+        libc::SYS_clock_settime,
+        libc::SYS_delete_module,
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_finit_module,
+        libc::SYS_init_module,
+        libc::SYS_kexec_load,
+        libc::SYS_keyctl,
+        libc::SYS_mount,
+        libc::SYS_perf_event_open,
+        libc::SYS_personality,
+        libc::SYS_pivot_root,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_ptrace,
+        libc::SYS_reboot,
+        libc::SYS_request_key,
+        libc::SYS_setdomainname,
+        libc::SYS_setgid,
+        libc::SYS_setgroups,
+        libc::SYS_sethostname,
+        libc::SYS_setns,
+        libc::SYS_setregid,
+        libc::SYS_setresgid,
+        libc::SYS_setresuid,
+        libc::SYS_setreuid,
+        libc::SYS_settimeofday,
+        libc::SYS_setuid,
+        libc::SYS_swapoff,
+        libc::SYS_swapon,
+        libc::SYS_umount2,
+        libc::SYS_unshare,
+        libc::SYS_userfaultfd,
+    ];
+
+    pub fn apply(paths: &Paths) -> anyhow::Result<()> {
+        restrict_file_system(paths).context("failed to apply Landlock rules")?;
+        filter_syscalls().context("failed to apply seccomp filter")?;
+
+        Ok(())
+    }
+
+    fn restrict_file_system(paths: &Paths) -> anyhow::Result<()> {
+        // Landlock can only grant access to paths that exist, so create any directories we'll
+        // be writing to ahead of time
+        for path in &paths.write {
+            std::fs::create_dir_all(path)
+                .with_context(|| format!("failed to create {}", path.display()))?;
+        }
+
+        let read = paths.read.iter().filter(|v| v.exists());
+
+        let abi = ABI::V2;
+        let status = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))?
+            .create()?
+            .add_rules(path_beneath_rules(read, AccessFs::from_read(abi)))?
+            .add_rules(path_beneath_rules(&paths.write, AccessFs::from_all(abi)))?
+            .restrict_self()?;
+
+        match status.ruleset {
+            RulesetStatus::FullyEnforced | RulesetStatus::PartiallyEnforced => {
+                info!(write = ?paths.write, "Restricted file system access");
+            }
+            RulesetStatus::NotEnforced => {
+                warn!(
+                    "Landlock isn't supported by this kernel, file system access is unrestricted"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn filter_syscalls() -> anyhow::Result<()> {
+        let Ok(arch) = TargetArch::try_from(std::env::consts::ARCH) else {
+            warn!(
+                arch = std::env::consts::ARCH,
+                "seccomp filters aren't supported on this architecture"
+            );
+            return Ok(());
+        };
+
+        let rules = DENIED_SYSCALLS
+            .iter()
+            .map(|&syscall| (i64::from(syscall), Vec::new()))
+            .collect::<BTreeMap<_, _>>();
+
+        #[allow(clippy::cast_sign_loss)]
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            arch,
+        )?;
+
+        let program: BpfProgram = filter.try_into()?;
+        seccompiler::apply_filter_all_threads(&program)?;
+        info!("Applied seccomp filter");
+
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use tracing::warn;
+
+    use super::Paths;
+
+    pub fn apply(_paths: &Paths) -> anyhow::Result<()> {
+        warn!(
+            "Sandboxing is only supported on Linux, set `sandbox.enabled = false` to silence this"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use test_case::test_case;
+
+    use super::template_directory;
+
+    #[test_case("/var/lib/pisshoff/{connection_id}.cast", Some("/var/lib/pisshoff/"); "substitution in file name")]
+    #[test_case("/var/lib/pisshoff/recording-{connection_id}.cast", Some("/var/lib/pisshoff"); "prefixed substitution")]
+    #[test_case("/var/lib/pisshoff/{peer_ip}/{connection_id}.cast", Some("/var/lib/pisshoff/"); "substitution in directory")]
+    #[test_case("/var/lib/pisshoff/recording.cast", Some("/var/lib/pisshoff"); "no substitution")]
+    #[test_case("{connection_id}.cast", Some("."); "working directory")]
+    #[test_case("recordings/{connection_id}.cast", Some("recordings/"); "relative")]
+    fn finds_template_directory(template: &str, expected: Option<&str>) {
+        assert_eq!(template_directory(template), expected.map(PathBuf::from));
+    }
+}
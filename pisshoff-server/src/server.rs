@@ -1,11 +1,16 @@
+mod telnet;
+
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, HashMap},
     future::Future,
-    net::SocketAddr,
+    hash::{Hash, Hasher},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, OnceLock},
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use futures::{
@@ -17,84 +22,316 @@ use thrussh::{
     ChannelId, CryptoVec, Pty, Sig,
 };
 use thrussh_keys::key::PublicKey;
-use tokio::sync::{mpsc::UnboundedSender, Mutex};
+use time::OffsetDateTime;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    task::JoinHandle,
+};
 use tracing::{debug, error, info, info_span, instrument::Instrumented, Instrument, Span};
 
 use crate::{
     audit::{
-        AuditLog, AuditLogAction, LoginAttemptEvent, OpenDirectTcpIpEvent, OpenX11Event,
-        PtyRequestEvent, SignalEvent, SubsystemRequestEvent, TcpIpForwardEvent,
-        WindowAdjustedEvent, WindowChangeRequestEvent, X11RequestEvent,
+        AuditLog, AuditLogAction, AuthMethod, ClientHandshake, DisconnectReason, DisconnectedEvent,
+        LoginAttemptEvent, OpenDirectTcpIpEvent, OpenX11Event, Protocol, PtyRequestEvent,
+        RateLimit, RateLimitedEvent, RemoteForwardConnectionEvent, SignalEvent,
+        SubsystemRequestEvent, TarpittedEvent, TcpIpForwardEvent, WindowAdjustedEvent,
+        WindowChangeRequestEvent, WriteFileEvent, X11RequestEvent,
     },
-    config::Config,
-    file_system::FileSystem,
-    state::State,
+    auth::{self, Verdict},
+    config::{Config, ForwardingConfig, TarpitConfig},
+    file_system::{FileSystem, Tree},
+    handshake::HandshakeSniffer,
+    metrics::ListenerStatus,
+    persistence,
+    recording::{expand_path_template, Recording},
+    state::{ActiveConnection, ConnectionPermit, ListenerSettings, State, Takeover, Tap, Visitor},
     subsystem::{self, shell::Shell, Subsystem as SubsystemTrait},
+    tarpit,
+    terminal::Pty as PtyRequest,
+    virustotal::VirusTotal,
 };
 
+/// Variables set within a session, keyed by name.
+pub type Environment = HashMap<Cow<'static, [u8]>, Cow<'static, [u8]>>;
+
 pub static KEYBOARD_INTERACTIVE_PROMPT: &[(Cow<'static, str>, bool)] =
     &[(Cow::Borrowed("Password: "), false)];
 
 #[derive(Clone)]
 pub struct Server {
-    config: Arc<Config>,
     state: Arc<State>,
     hostname: &'static str,
-    audit_send: UnboundedSender<AuditLog>,
 }
 
 impl Server {
-    pub fn new(
-        hostname: &'static str,
-        config: Arc<Config>,
-        audit_send: UnboundedSender<AuditLog>,
-    ) -> Self {
-        Self {
-            config,
-            hostname,
-            state: Arc::new(State::default()),
-            audit_send,
+    pub fn new(hostname: &'static str, state: Arc<State>) -> Self {
+        Self { hostname, state }
+    }
+
+    /// Accepts connections on the listener configured for `listen_address`, handing each of them
+    /// off to thrussh, or to [`telnet`] if the listener speaks telnet. We run the accept loop
+    /// ourselves rather than using [`thrussh::server::run`] so the client's side of the
+    /// handshake can be picked out of the stream on the way through.
+    pub async fn run(
+        self,
+        config: Arc<thrussh::server::Config>,
+        listen_address: SocketAddr,
+        listener: TcpListener,
+    ) -> std::io::Result<()> {
+        self.state
+            .listeners
+            .set(listen_address, ListenerStatus::Accepting);
+
+        loop {
+            // with the blocking overflow policy, stop taking on connections until the writer has
+            // caught up
+            if !self.state.audit.has_space() {
+                self.state
+                    .listeners
+                    .set(listen_address, ListenerStatus::Blocked);
+                self.state.audit.wait_for_space().await;
+                self.state
+                    .listeners
+                    .set(listen_address, ListenerStatus::Accepting);
+            }
+
+            let (stream, peer_addr) = listener.accept().await?;
+
+            // connections keep the settings they were accepted with, even if the config is
+            // reloaded
+            let settings = self.state.settings.load().listener(listen_address);
+
+            let permit = match self
+                .state
+                .rate_limiter
+                .admit(peer_addr.ip(), &settings.config.rate_limit)
+            {
+                Ok(permit) => permit,
+                Err(limit) => {
+                    self.rate_limited(peer_addr, settings.protocol, limit);
+                    continue;
+                }
+            };
+
+            if let Some(config) = settings.tarpit {
+                tokio::spawn(self.clone().tarpit(stream, peer_addr, config, permit));
+                continue;
+            }
+
+            if settings.protocol == Protocol::Telnet {
+                let banner = settings.banner.clone();
+                let connection = self.new_connection(settings, peer_addr, Arc::default(), permit);
+                let active = connection.active.clone();
+
+                spawn_connection(active, telnet::run(stream, connection, banner));
+                continue;
+            }
+
+            let handshake = Arc::new(OnceLock::new());
+            let connection = self.new_connection(settings, peer_addr, handshake.clone(), permit);
+            let active = connection.active.clone();
+            let stream = HandshakeSniffer::new(stream, handshake);
+            let fut = thrussh::server::run_stream(config.clone(), stream, connection);
+
+            spawn_connection(active, async move {
+                if let Err(error) = fut.await {
+                    debug!(%error, "Connection closed with error");
+                }
+            });
         }
     }
-}
 
-impl thrussh::server::Server for Server {
-    type Handler = Connection;
+    /// Starts the audit log for a new connection from `peer_addr`.
+    fn audit_log(&self, peer_addr: SocketAddr, protocol: Protocol) -> AuditLog {
+        AuditLog {
+            connection_id: uuid::Uuid::new_v4(),
+            host: Cow::Borrowed(self.hostname),
+            peer_address: Some(peer_addr),
+            protocol,
+            geoip: self.state.geoip.lookup(peer_addr.ip()),
+            ..AuditLog::default()
+        }
+    }
+
+    /// Records a connection that's been dropped for exceeding one of the rate limits.
+    fn rate_limited(&self, peer_addr: SocketAddr, protocol: Protocol, limit: RateLimit) {
+        debug!(%peer_addr, ?limit, "Dropping rate limited connection");
+
+        let mut audit_log = self.audit_log(peer_addr, protocol);
+        audit_log.push_action(AuditLogAction::RateLimited(RateLimitedEvent { limit }));
+        audit_log.finish();
+
+        self.state.audit.push(audit_log);
+    }
+
+    /// Holds the connection in a tarpit rather than letting it get as far as the handshake,
+    /// recording how long the client stuck around for once it gives up.
+    async fn tarpit(
+        self,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+        config: TarpitConfig,
+        _permit: ConnectionPermit,
+    ) {
+        let mut audit_log = self.audit_log(peer_addr, Protocol::Ssh);
+        let span = info_span!("tarpit", %peer_addr, connection_id = %audit_log.connection_id);
+
+        let bytes_sent = tarpit::run(stream, &config).instrument(span).await;
 
-    fn new(&mut self, peer_addr: Option<SocketAddr>) -> Self::Handler {
-        let connection_id = uuid::Uuid::new_v4();
+        audit_log.push_action(AuditLogAction::Tarpitted(TarpittedEvent { bytes_sent }));
+        audit_log.finish();
+
+        self.state.audit.push(audit_log);
+    }
+
+    fn new_connection(
+        &self,
+        settings: ListenerSettings,
+        peer_addr: SocketAddr,
+        handshake: Arc<OnceLock<ClientHandshake>>,
+        permit: ConnectionPermit,
+    ) -> Connection {
+        let audit_log = self.audit_log(peer_addr, settings.protocol);
+        let connection_id = audit_log.connection_id;
+        let active = Arc::new(ActiveConnection::new(
+            connection_id,
+            peer_addr,
+            audit_log.ts,
+            audit_log.start,
+            handshake.clone(),
+        ));
+        self.state.connections.insert(active.clone());
+
+        let recording = settings
+            .config
+            .recording_path
+            .is_some()
+            .then(|| Recording::new(audit_log.start));
+
+        let visitor = self.state.visitors.get(peer_addr.ip()).unwrap_or_default();
+
+        // resolved in the background so slow DNS doesn't hold up the handshake, whatever has
+        // come back by the time the connection closes makes it into the log
+        let reverse_dns = Arc::new(OnceLock::new());
+        if self.state.reverse_dns.enabled() {
+            let state = self.state.clone();
+            let reverse_dns = reverse_dns.clone();
+
+            tokio::spawn(async move {
+                if let Some(name) = state.reverse_dns.lookup(peer_addr.ip()).await {
+                    let _res = reverse_dns.set(name);
+                }
+            });
+        }
+
+        let previous_login = visitor.last_login.and_then(|start| {
+            let start = OffsetDateTime::from_unix_timestamp(i64::try_from(start).ok()?).ok()?;
+            let end = OffsetDateTime::from_unix_timestamp(i64::try_from(visitor.last_seen).ok()?);
+            Some((start, end.ok()?))
+        });
+
+        // returning visitors pick up the file system they left behind
+        let file_system_seed = visitor
+            .file_system
+            .clone()
+            .map(Arc::new)
+            .or_else(|| settings.file_system_seed.clone());
 
         Connection {
-            span: info_span!("connection", ?peer_addr, %connection_id),
+            span: info_span!("connection", %peer_addr, %connection_id),
             server: self.clone(),
             state: ConnectionState {
-                audit_log: AuditLog {
-                    connection_id,
-                    host: Cow::Borrowed(self.hostname),
-                    peer_address: peer_addr,
-                    ..AuditLog::default()
-                },
+                audit_log,
+                recording,
+                config: settings.config.clone(),
                 username: None,
+                switched_user: None,
+                effective_user: None,
+                hide_input: false,
                 file_system: None,
+                file_system_seed,
                 environment: HashMap::new(),
+                tap: active.tap.clone(),
+                takeover: active.takeover.clone(),
+                seed: self.state.peer_seeds.seed(&settings.config, peer_addr.ip()),
+                tty: None,
+                history: Vec::new(),
+                previous_login,
+                exit_status: 0,
+                script_depth: 0,
+                sftp_uploaded: 0,
+                virus_total: self.state.virus_total.clone(),
             },
-            subsystem: HashMap::new(),
+            channels: HashMap::new(),
+            channels_opened: 0,
+            remote_forwards: HashMap::new(),
+            incoming: Arc::default(),
+            x11_auth_cookie: None,
+            visitor,
+            handshake,
+            reverse_dns,
+            active,
+            _permit: permit,
+            auth_failures: 0,
+            password_attempts: 0,
+            auth_method: None,
         }
     }
 }
 
+/// Runs a connection in the background until it closes or is killed. Killing it drops the
+/// connection along with the future, which writes out its audit log as usual. Connections are
+/// killed by operators, or on shutdown once the grace period is up.
+fn spawn_connection(active: Arc<ActiveConnection>, fut: impl Future<Output = ()> + Send + 'static) {
+    tokio::spawn(async move {
+        tokio::select! {
+            () = fut => {}
+            () = active.kill.notified() => {
+                info!(connection_id = %active.id, "Connection killed");
+            }
+        }
+    });
+}
+
 pub struct ConnectionState {
     audit_log: AuditLog,
+    recording: Option<Recording>,
+    config: Arc<Config>,
     username: Option<String>,
+    /// User switched to for the rest of the session by `su`.
+    switched_user: Option<String>,
+    /// User `sudo` is running a command as, only set while the command is being called into.
+    effective_user: Option<String>,
+    /// Whether the shell should stop echoing keystrokes, as it does while a command is
+    /// prompting for a password.
+    hide_input: bool,
     file_system: Option<FileSystem>,
-    environment: HashMap<Cow<'static, [u8]>, Cow<'static, [u8]>>,
+    file_system_seed: Option<Arc<Tree>>,
+    environment: Environment,
+    tap: Tap,
+    takeover: Takeover,
+    /// Seed for the peer's randomness, the same on each of its connections.
+    seed: u64,
+    /// Terminal the shell is attached to, if the client requested a PTY for it.
+    tty: Option<&'static str>,
+    /// Lines kept in the interactive shell's history, starting with `~/.bash_history` as it
+    /// was when the shell started.
+    history: Vec<String>,
+    /// Start and end of the peer's previous session, if they've logged in before.
+    previous_login: Option<(OffsetDateTime, OffsetDateTime)>,
+    /// Exit status of the last command the shell ran, as `$?` expands to.
+    exit_status: u32,
+    /// Number of scripts currently being run within one another.
+    script_depth: u32,
+    /// Bytes written over SFTP, counted towards `sftp.max-upload-bytes`.
+    sftp_uploaded: usize,
+    virus_total: Arc<VirusTotal>,
 }
 
 impl ConnectionState {
     #[cfg(test)]
     pub fn mock() -> Self {
-        use std::net::{IpAddr, Ipv4Addr};
-
         ConnectionState {
             audit_log: AuditLog {
                 connection_id: uuid::Uuid::from_bytes([
@@ -107,21 +344,81 @@ impl ConnectionState {
                 )),
                 ..AuditLog::default()
             },
+            recording: None,
+            config: Arc::new(Config::default()),
             username: None,
+            switched_user: None,
+            effective_user: None,
+            hide_input: false,
             file_system: None,
+            file_system_seed: None,
             environment: HashMap::new(),
+            tap: Tap::default(),
+            takeover: Takeover::default(),
+            seed: 0,
+            tty: None,
+            history: Vec::new(),
+            previous_login: None,
+            exit_status: 0,
+            script_depth: 0,
+            sftp_uploaded: 0,
+            virus_total: Arc::default(),
         }
     }
 }
 
 impl ConnectionState {
+    /// The user commands are running as, which is the one the client logged in as unless
+    /// they've since used `su` or `sudo`.
     pub fn username(&self) -> &str {
-        self.username.as_deref().unwrap_or("root")
+        self.effective_user
+            .as_deref()
+            .or(self.switched_user.as_deref())
+            .or(self.username.as_deref())
+            .unwrap_or("root")
+    }
+
+    /// Switches to `username` for the rest of the session, moving to their home directory if
+    /// `login` is set as `su -` would.
+    pub fn switch_user(&mut self, username: &str, login: bool) {
+        self.switched_user = Some(username.to_string());
+
+        if login {
+            let config = self.config.clone();
+            let file_system = self.file_system();
+            file_system.login(username);
+            config.persona.add_user(file_system, username);
+
+            let home = file_system.home().display().to_string();
+            self.set_variable("HOME", &home);
+            self.set_variable("PWD", &home);
+            self.set_variable("USER", username);
+            self.set_variable("LOGNAME", username);
+        }
+    }
+
+    /// Sets the user to run commands as until it's next changed, returning the previous one so
+    /// it can be restored.
+    pub fn set_effective_user(&mut self, username: Option<String>) -> Option<String> {
+        std::mem::replace(&mut self.effective_user, username)
+    }
+
+    pub fn hides_input(&self) -> bool {
+        self.hide_input
+    }
+
+    pub fn set_hide_input(&mut self, hide: bool) {
+        self.hide_input = hide;
     }
 
     pub fn file_system(&mut self) -> &mut FileSystem {
         if self.file_system.is_none() {
-            self.file_system = Some(FileSystem::new(self.username()));
+            // the file system is always set up for the user that logged in, even if it's first
+            // touched by a command that's run as somebody else
+            let username = self.username.as_deref().unwrap_or("root");
+            let mut file_system = FileSystem::new(username, self.file_system_seed.as_deref());
+            self.config.persona.add_user(&mut file_system, username);
+            self.file_system = Some(file_system);
         }
 
         self.file_system.as_mut().unwrap()
@@ -131,23 +428,339 @@ impl ConnectionState {
         &mut self.audit_log
     }
 
-    pub fn environment(&self) -> &HashMap<Cow<'static, [u8]>, Cow<'static, [u8]>> {
+    pub fn history(&mut self) -> &mut Vec<String> {
+        &mut self.history
+    }
+
+    pub fn virus_total(&self) -> &VirusTotal {
+        &self.virus_total
+    }
+
+    pub fn previous_login(&self) -> Option<(OffsetDateTime, OffsetDateTime)> {
+        self.previous_login
+    }
+
+    pub fn exit_status(&self) -> u32 {
+        self.exit_status
+    }
+
+    /// Records the exit status of a command, which is also kept in the environment as `?` so
+    /// `$?` expands to it like any other variable.
+    pub fn set_exit_status(&mut self, exit_status: u32) {
+        self.exit_status = exit_status;
+        self.environment.insert(
+            Cow::Borrowed(b"?"),
+            Cow::Owned(exit_status.to_string().into_bytes()),
+        );
+    }
+
+    /// Number of scripts currently being run within one another, so scripts that call
+    /// themselves can be stopped before they exhaust the stack.
+    pub fn script_depth(&mut self) -> &mut u32 {
+        &mut self.script_depth
+    }
+
+    pub fn sftp_uploaded(&mut self) -> &mut usize {
+        &mut self.sftp_uploaded
+    }
+
+    /// Records a file written by the client, along with a persistence attempt if it was an
+    /// `authorized_keys` file, crontab or systemd unit.
+    pub fn record_write(&mut self, event: WriteFileEvent) {
+        let path = PathBuf::from(&*event.path);
+        let content = event.content.clone();
+
+        self.audit_log.push_action(AuditLogAction::WriteFile(event));
+        self.detect_persistence(&path, &content);
+    }
+
+    /// Records a persistence attempt if `content` ending up at `path` would give the client a
+    /// way back in, for files put there by moving or copying rather than writing them.
+    pub fn detect_persistence(&mut self, path: &Path, content: &[u8]) {
+        if let Some(persistence) = persistence::detect(path, content) {
+            self.audit_log
+                .push_action(AuditLogAction::PersistenceAttempt(persistence));
+        }
+    }
+
+    pub fn recording(&self) -> Option<Recording> {
+        self.recording.clone()
+    }
+
+    pub fn tap(&self) -> Tap {
+        self.tap.clone()
+    }
+
+    pub fn takeover(&self) -> &Takeover {
+        &self.takeover
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn tty(&self) -> Option<&'static str> {
+        self.tty
+    }
+
+    pub fn set_tty(&mut self, tty: &'static str) {
+        self.tty = Some(tty);
+    }
+
+    /// Returns a generator for something random about the peer, such as whether a login is
+    /// accepted, which gives the same results whenever it's called with the same `key`.
+    pub fn rng(&self, key: impl Hash) -> fastrand::Rng {
+        let mut hasher = DefaultHasher::new();
+        self.seed.hash(&mut hasher);
+        key.hash(&mut hasher);
+        fastrand::Rng::with_seed(hasher.finish())
+    }
+
+    pub fn environment(&self) -> &Environment {
         &self.environment
     }
+
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    /// Sets the variable `name` for the rest of the session.
+    pub fn set_variable(&mut self, name: &str, value: &str) {
+        self.environment.insert(
+            Cow::Owned(name.as_bytes().to_vec()),
+            Cow::Owned(value.as_bytes().to_vec()),
+        );
+    }
 }
 
 pub struct Connection {
     span: Span,
     server: Server,
     state: ConnectionState,
-    subsystem: HashMap<ChannelId, Arc<Mutex<Subsystem>>>,
+    /// Session channels the client has open.
+    channels: HashMap<ChannelId, Channel>,
+    /// Number of channels opened so far, used to number the next one.
+    channels_opened: u32,
+    /// Remote forwards we've claimed to have set up, along with the task making up connections
+    /// to each.
+    remote_forwards: HashMap<(Box<str>, u32), JoinHandle<()>>,
+    /// Channels the remote forward tasks have opened to the client, waiting to be picked up by
+    /// the next handler that runs.
+    incoming: Arc<parking_lot::Mutex<Vec<IncomingConnection>>>,
+    /// Cookie from the last X11 forwarding request we accepted.
+    x11_auth_cookie: Option<Box<str>>,
+    /// State left behind by the peer's previous connections, updated and stored again once
+    /// this connection closes.
+    visitor: Visitor,
+    /// The client's side of the SSH handshake, filled in once it has been read.
+    handshake: Arc<OnceLock<ClientHandshake>>,
+    /// The peer address's PTR record, filled in once it has been resolved.
+    reverse_dns: Arc<OnceLock<Box<str>>>,
+    /// The connection's entry in the list of open connections.
+    active: Arc<ActiveConnection>,
+    /// Counts the connection against the peer's limit on open connections.
+    _permit: ConnectionPermit,
+    /// Number of authentication attempts the client has failed.
+    auth_failures: usize,
+    /// Number of passwords the client has tried.
+    password_attempts: usize,
+    /// Method the client logged in with, once it has.
+    auth_method: Option<AuthMethod>,
+}
+
+/// What a session channel has been asked to do, which can only be decided once per channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChannelMode {
+    /// The client hasn't yet asked for a shell, command or subsystem.
+    Pending,
+    Shell,
+    Exec,
+    Sftp,
+    /// A `direct-tcpip` channel accepted into the sinkhole.
+    Sinkhole,
+    /// A channel we opened for a made up connection to one of the client's remote forwards.
+    Forwarded,
+    /// An X11 channel accepted into the sinkhole.
+    X11,
+}
+
+/// A made up connection to one of the client's remote forwards.
+#[derive(Debug)]
+struct IncomingConnection {
+    channel: ChannelId,
+    address: Box<str>,
+    port: u32,
+    originator: SocketAddr,
+}
+
+/// A session channel, with everything the client has set up on it. Clients are free to open
+/// several at once, each with their own PTY and environment.
+#[derive(Debug)]
+struct Channel {
+    /// Number of the channel in the order the client opened them, which its events are audited
+    /// against.
+    number: u32,
+    mode: ChannelMode,
+    subsystem: Option<Arc<Mutex<Subsystem>>>,
+    /// PTY the client has requested for the channel, if any.
+    pty: Option<PtyRequest>,
+    /// Environment for commands run on the channel, swapped in whenever the channel is handled.
+    environment: Environment,
+}
+
+impl Channel {
+    fn new(number: u32) -> Self {
+        Self {
+            number,
+            mode: ChannelMode::Pending,
+            subsystem: None,
+            pty: None,
+            environment: HashMap::new(),
+        }
+    }
 }
 
 impl Connection {
+    /// Switches the connection over to `channel`, so commands see its environment and events
+    /// are audited against it, until [`Self::leave_channel`] is called.
+    fn enter_channel(&mut self, channel: ChannelId) {
+        if let Some(channel) = self.channels.get_mut(&channel) {
+            std::mem::swap(self.state.environment_mut(), &mut channel.environment);
+            self.state.audit_log.channel = Some(channel.number);
+        }
+    }
+
+    /// Stores any changes made to `channel`'s environment since [`Self::enter_channel`].
+    fn leave_channel(&mut self, channel: ChannelId) {
+        if let Some(channel) = self.channels.get_mut(&channel) {
+            std::mem::swap(self.state.environment_mut(), &mut channel.environment);
+        }
+
+        self.state.audit_log.channel = None;
+    }
+
+    /// Picks up the channels the remote forward tasks have opened since we last looked,
+    /// sinkholing whatever the client sends down them.
+    fn accept_incoming(&mut self) {
+        let incoming = std::mem::take(&mut *self.incoming.lock());
+
+        for connection in incoming {
+            self.channels_opened += 1;
+
+            let mut channel = Channel::new(self.channels_opened);
+            channel.mode = ChannelMode::Forwarded;
+            channel.subsystem = Some(Arc::new(Mutex::new(Subsystem::Sinkhole(
+                subsystem::sinkhole::Sinkhole::new(
+                    &connection.address,
+                    connection.port,
+                    self.state.config.forwarding.max_capture,
+                ),
+            ))));
+            self.channels.insert(connection.channel, channel);
+
+            self.enter_channel(connection.channel);
+            self.state
+                .audit_log
+                .push_action(AuditLogAction::RemoteForwardConnection(
+                    RemoteForwardConnectionEvent {
+                        address: connection.address,
+                        port: connection.port,
+                        originator_address: connection.originator.ip().to_string().into(),
+                        originator_port: u32::from(connection.originator.port()),
+                    },
+                ));
+            self.leave_channel(connection.channel);
+        }
+    }
+
+    /// Decides what `channel` is to be used for, returning `false` if it's unknown or has
+    /// already been used for something.
+    fn start_channel(&mut self, channel: ChannelId, mode: ChannelMode) -> bool {
+        match self.channels.get_mut(&channel) {
+            Some(channel) if channel.mode == ChannelMode::Pending => {
+                channel.mode = mode;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Updates the stats shown for the connection on the admin interface.
+    fn update_stats(&self) {
+        let mut stats = self.active.stats.lock();
+        stats.username.clone_from(&self.state.username);
+        stats.events = self.state.audit_log.events.len();
+        stats.last_activity = Instant::now();
+    }
+
     fn try_login(&mut self, user: &str, password: &str) -> bool {
+        let res = self.check_password(user, password);
+
+        self.state
+            .audit_log
+            .push_action(AuditLogAction::LoginAttempt(
+                LoginAttemptEvent::UsernamePassword {
+                    username: Box::from(user),
+                    password: Box::from(password),
+                },
+            ));
+
+        if res {
+            self.auth_method = Some(AuthMethod::Password);
+        }
+
+        res
+    }
+
+    fn try_key_login(&mut self, user: &str, fingerprint: &str) -> bool {
+        self.state.username = Some(user.to_string());
+
+        if self.server.state.previously_accepted_keys.seen(fingerprint) {
+            info!(
+                user,
+                fingerprint, "Accepted key due to it being used before"
+            );
+            true
+        } else if self.state.rng(("public-key", user, fingerprint)).f64()
+            <= self.state.config.key_access_probability
+        {
+            info!(user, fingerprint, "Accepted key randomly");
+            self.server
+                .state
+                .previously_accepted_keys
+                .store(fingerprint);
+            true
+        } else {
+            info!(?user, ?fingerprint, "Rejected key");
+            false
+        }
+    }
+
+    /// Decides whether the given credentials should be accepted, without recording the attempt.
+    fn check_password(&mut self, user: &str, password: &str) -> bool {
         self.state.username = Some(user.to_string());
+        self.password_attempts += 1;
+
+        // static decisions from the auth policy win out over anything accepted before
+        let probability = match auth::evaluate(
+            &self.state.config.auth,
+            user,
+            password,
+            self.password_attempts,
+        ) {
+            Some(Verdict::Accept) => {
+                info!(user, password, "Accepted login due to auth policy");
+                return true;
+            }
+            Some(Verdict::Reject) => {
+                info!(?user, ?password, "Rejected login due to auth policy");
+                return false;
+            }
+            Some(Verdict::Probability(probability)) => probability,
+            None => self.state.config.access_probability,
+        };
 
-        let res = if self
+        if self
             .server
             .state
             .previously_accepted_passwords
@@ -155,28 +768,30 @@ impl Connection {
         {
             info!(user, password, "Accepted login due to it being used before");
             true
-        } else if fastrand::f64() <= self.server.config.access_probability {
+        } else if self.visitor.accepted(user, password) {
+            info!(
+                user,
+                password, "Accepted login due to it being used by the peer before"
+            );
+            self.server
+                .state
+                .previously_accepted_passwords
+                .store(user, password);
+            true
+        } else if self.state.rng(("password", user, password)).f64() <= probability {
             info!(user, password, "Accepted login randomly");
             self.server
                 .state
                 .previously_accepted_passwords
                 .store(user, password);
+            self.visitor
+                .accepted_passwords
+                .push((user.to_string(), password.to_string()));
             true
         } else {
             info!(?user, ?password, "Rejected login");
             false
-        };
-
-        self.state
-            .audit_log
-            .push_action(AuditLogAction::LoginAttempt(
-                LoginAttemptEvent::UsernamePassword {
-                    username: Box::from(user),
-                    password: Box::from(password),
-                },
-            ));
-
-        res
+        }
     }
 }
 
@@ -187,8 +802,27 @@ impl thrussh::server::Handler for Connection {
     type FutureBool =
         ServerFuture<Self::Error, BoxFuture<'static, Result<(Self, Session, bool), Self::Error>>>;
 
-    fn finished_auth(self, auth: Auth) -> Self::FutureAuth {
+    fn finished_auth(mut self, auth: Auth) -> Self::FutureAuth {
         let span = info_span!(parent: &self.span, "finished_auth");
+
+        if matches!(auth, Auth::Reject) {
+            self.auth_failures += 1;
+
+            // thrussh drops the connection itself once the limit is reached, we just need to
+            // record why
+            if self.auth_failures == self.state.config.max_auth_tries {
+                info!(parent: &span, "Too many authentication failures, disconnecting");
+
+                self.state
+                    .audit_log
+                    .push_action(AuditLogAction::Disconnected(DisconnectedEvent {
+                        reason: DisconnectReason::TooManyAuthenticationFailures,
+                    }));
+            }
+        }
+
+        self.update_stats();
+
         futures::future::ok((self, auth)).boxed().wrap(span)
     }
 
@@ -196,15 +830,20 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "finished_bool");
         let _entered = span.enter();
 
+        self.update_stats();
+
         futures::future::ok((self, session, b))
             .boxed()
             .wrap(Span::current())
     }
 
-    fn finished(self, session: Session) -> Self::FutureUnit {
+    fn finished(mut self, session: Session) -> Self::FutureUnit {
         let span = info_span!(parent: &self.span, "finished");
         let _entered = span.enter();
 
+        self.accept_incoming();
+        self.update_stats();
+
         futures::future::ok((self, session))
             .boxed()
             .wrap(Span::current())
@@ -231,7 +870,7 @@ impl thrussh::server::Handler for Connection {
         self.finished_auth(res)
     }
 
-    fn auth_publickey(mut self, _user: &str, public_key: &PublicKey) -> Self::FutureAuth {
+    fn auth_publickey(mut self, user: &str, public_key: &PublicKey) -> Self::FutureAuth {
         let span = info_span!(parent: &self.span, "auth_publickey");
         let _entered = span.enter();
 
@@ -242,29 +881,49 @@ impl thrussh::server::Handler for Connection {
             .audit_log
             .push_action(AuditLogAction::LoginAttempt(LoginAttemptEvent::PublicKey {
                 kind: Cow::Borrowed(kind),
-                fingerprint: Box::from(fingerprint),
+                fingerprint: Box::from(fingerprint.as_str()),
             }));
 
-        self.finished_auth(Auth::Reject)
-            .boxed()
-            .wrap(Span::current())
+        let res = if self.try_key_login(user, &fingerprint) {
+            self.auth_method = Some(AuthMethod::PublicKey);
+            Auth::Accept
+        } else {
+            Auth::Reject
+        };
+
+        self.finished_auth(res).boxed().wrap(Span::current())
     }
 
     fn auth_keyboard_interactive(
         mut self,
         user: &str,
         _submethods: &str,
-        mut response: Option<Response>,
+        response: Option<Response>,
     ) -> Self::FutureAuth {
         let span = info_span!(parent: &self.span, "auth_keyboard_interactive");
         let _entered = span.enter();
 
-        let result = if let Some(password) = response
-            .as_mut()
-            .and_then(Response::next)
-            .map(String::from_utf8_lossy)
-        {
-            if self.try_login(user, password.as_ref()) {
+        let result = if let Some(response) = response {
+            let responses: Vec<Box<str>> = response
+                .map(|v| Box::from(String::from_utf8_lossy(v)))
+                .collect();
+
+            // we only ever prompt for a password, so the first answer is all we need to check
+            let accepted = responses
+                .first()
+                .map_or(false, |password| self.check_password(user, password));
+
+            self.state
+                .audit_log
+                .push_action(AuditLogAction::LoginAttempt(
+                    LoginAttemptEvent::KeyboardInteractive {
+                        username: Box::from(user),
+                        responses,
+                    },
+                ));
+
+            if accepted {
+                self.auth_method = Some(AuthMethod::KeyboardInteractive);
                 Auth::Accept
             } else {
                 Auth::Reject
@@ -282,10 +941,12 @@ impl thrussh::server::Handler for Connection {
         self.finished_auth(result)
     }
 
-    fn channel_close(self, channel: ChannelId, mut session: Session) -> Self::FutureUnit {
+    fn channel_close(mut self, channel: ChannelId, mut session: Session) -> Self::FutureUnit {
         let span = info_span!(parent: &self.span, "channel_close");
         let _entered = span.enter();
 
+        self.channels.remove(&channel);
+
         session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
     }
@@ -294,22 +955,49 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "channel_eof");
         let _entered = span.enter();
 
-        if self.subsystem.remove(&channel).is_some() {
-            session.exit_status_request(channel, 0);
-            session.channel_success(channel);
-        } else {
+        let Some(subsystem) = self
+            .channels
+            .get_mut(&channel)
+            .and_then(|v| v.subsystem.take())
+        else {
             session.channel_failure(channel);
-        }
+            session.close(channel);
+            return self.finished(session).boxed().wrap(Span::current());
+        };
 
-        session.close(channel);
+        async move {
+            self.enter_channel(channel);
+            let mut subsystem = subsystem.lock().await;
 
-        self.finished(session).boxed().wrap(Span::current())
+            if let Subsystem::Shell(ref mut inner) = &mut *subsystem {
+                // the shell sends its own exit status on the way out
+                inner.eof(&mut self.state, channel, &mut session).await;
+            } else {
+                session.exit_status_request(channel, 0);
+                session.close(channel);
+            }
+
+            drop(subsystem);
+            self.leave_channel(channel);
+            session.channel_success(channel);
+            self.finished(session).await
+        }
+        .boxed()
+        .wrap(Span::current())
     }
 
-    fn channel_open_session(self, channel: ChannelId, mut session: Session) -> Self::FutureUnit {
+    fn channel_open_session(
+        mut self,
+        channel: ChannelId,
+        mut session: Session,
+    ) -> Self::FutureUnit {
         let span = info_span!(parent: &self.span, "channel_open_session");
         let _entered = span.enter();
 
+        self.channels_opened += 1;
+        self.channels
+            .insert(channel, Channel::new(self.channels_opened));
+
         session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
     }
@@ -324,14 +1012,39 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "channel_open_x11");
         let _entered = span.enter();
 
+        let accept = self.state.config.forwarding.accept_x11 && self.x11_auth_cookie.is_some();
+
+        if accept {
+            self.channels_opened += 1;
+
+            let mut channel_state = Channel::new(self.channels_opened);
+            channel_state.mode = ChannelMode::X11;
+            channel_state.subsystem = Some(Arc::new(Mutex::new(Subsystem::Sinkhole(
+                subsystem::sinkhole::Sinkhole::raw(
+                    originator_address,
+                    originator_port,
+                    self.state.config.forwarding.max_capture,
+                ),
+            ))));
+            self.channels.insert(channel, channel_state);
+        }
+
+        self.enter_channel(channel);
         self.state
             .audit_log
             .push_action(AuditLogAction::OpenX11(OpenX11Event {
                 originator_address: Box::from(originator_address),
                 originator_port,
+                x11_auth_cookie: self.x11_auth_cookie.clone().filter(|_| accept),
             }));
+        self.leave_channel(channel);
+
+        if accept {
+            session.channel_success(channel);
+        } else {
+            session.channel_failure(channel);
+        }
 
-        session.channel_failure(channel);
         self.finished(session).boxed().wrap(Span::current())
     }
 
@@ -347,6 +1060,26 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "channel_open_direct_tcpip");
         let _entered = span.enter();
 
+        let forwarding = self.state.config.forwarding.clone();
+        let mut greeting = None;
+
+        if forwarding.sinkhole {
+            self.channels_opened += 1;
+
+            let sinkhole = subsystem::sinkhole::Sinkhole::new(
+                host_to_connect,
+                port_to_connect,
+                forwarding.max_capture,
+            );
+            greeting = sinkhole.greeting();
+
+            let mut channel_state = Channel::new(self.channels_opened);
+            channel_state.mode = ChannelMode::Sinkhole;
+            channel_state.subsystem = Some(Arc::new(Mutex::new(Subsystem::Sinkhole(sinkhole))));
+            self.channels.insert(channel, channel_state);
+        }
+
+        self.enter_channel(channel);
         self.state
             .audit_log
             .push_action(AuditLogAction::OpenDirectTcpIp(OpenDirectTcpIpEvent {
@@ -355,8 +1088,26 @@ impl thrussh::server::Handler for Connection {
                 originator_address: Box::from(originator_address),
                 originator_port,
             }));
+        self.leave_channel(channel);
+
+        if forwarding.sinkhole {
+            // the channel is never left open for longer than this, however slowly the client
+            // trickles data down it
+            let handle = session.handle();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(forwarding.timeout)).await;
+                let _res = handle.close(channel).await;
+            });
+
+            session.channel_success(channel);
+
+            if let Some(greeting) = greeting {
+                session.data(channel, CryptoVec::from_slice(&greeting));
+            }
+        } else {
+            session.channel_failure(channel);
+        }
 
-        session.channel_failure(channel);
         self.finished(session).boxed().wrap(Span::current())
     }
 
@@ -364,11 +1115,19 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "data");
         let _entered = span.enter();
 
-        // TODO: don't unwrap
-        let subsystem = self.subsystem.get(&channel).unwrap().clone();
+        self.accept_incoming();
+
+        let Some(subsystem) = self
+            .channels
+            .get(&channel)
+            .and_then(|v| v.subsystem.clone())
+        else {
+            return self.finished(session).boxed().wrap(Span::current());
+        };
         let data = data.to_vec();
 
         async move {
+            self.enter_channel(channel);
             let mut subsystem = subsystem.lock().await;
 
             match &mut *subsystem {
@@ -382,8 +1141,15 @@ impl thrussh::server::Handler for Connection {
                         .data(&mut self.state, channel, &data, &mut session)
                         .await;
                 }
+                Subsystem::Sinkhole(ref mut inner) => {
+                    inner
+                        .data(&mut self.state, channel, &data, &mut session)
+                        .await;
+                }
             }
 
+            drop(subsystem);
+            self.leave_channel(channel);
             self.finished(session).await
         }
         .boxed()
@@ -442,6 +1208,7 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "pty_request");
         let _entered = span.enter();
 
+        self.enter_channel(channel);
         self.state
             .audit_log
             .push_action(AuditLogAction::PtyRequest(PtyRequestEvent {
@@ -458,8 +1225,20 @@ impl thrussh::server::Handler for Connection {
                         .collect::<Vec<_>>(),
                 ),
             }));
+        self.leave_channel(channel);
 
-        session.channel_failure(channel);
+        let Some(channel_state) = self.channels.get_mut(&channel) else {
+            session.channel_failure(channel);
+            return self.finished(session).boxed().wrap(Span::current());
+        };
+
+        channel_state.pty = Some(PtyRequest::new(term, col_width, row_height, modes));
+
+        if let Some(recording) = &self.state.recording {
+            recording.resize(Some(term), col_width, row_height);
+        }
+
+        session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
     }
 
@@ -475,6 +1254,7 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "x11_request");
         let _entered = span.enter();
 
+        self.enter_channel(channel);
         self.state
             .audit_log
             .push_action(AuditLogAction::X11Request(X11RequestEvent {
@@ -483,8 +1263,14 @@ impl thrussh::server::Handler for Connection {
                 x11_auth_cookie: Box::from(x11_auth_cookie),
                 x11_screen_number,
             }));
+        self.leave_channel(channel);
 
-        session.channel_failure(channel);
+        if self.state.config.forwarding.accept_x11 {
+            self.x11_auth_cookie = Some(Box::from(x11_auth_cookie));
+            session.channel_success(channel);
+        } else {
+            session.channel_failure(channel);
+        }
         self.finished(session).boxed().wrap(Span::current())
     }
 
@@ -503,6 +1289,11 @@ impl thrussh::server::Handler for Connection {
             .environment_variables
             .push((Box::from(variable_name), Box::from(variable_value)));
 
+        // only commands run on this channel get to see it
+        self.enter_channel(channel);
+        self.state.set_variable(variable_name, variable_value);
+        self.leave_channel(channel);
+
         session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
     }
@@ -511,13 +1302,26 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "shell_request");
         let _entered = span.enter();
 
+        if !self.start_channel(channel, ChannelMode::Shell) {
+            session.channel_failure(channel);
+            return self.finished(session).boxed().wrap(Span::current());
+        }
+
+        self.enter_channel(channel);
         self.state
             .audit_log
             .push_action(AuditLogAction::ShellRequested);
 
-        let shell = Shell::new(true, channel, &mut session);
-        self.subsystem
-            .insert(channel, Arc::new(Mutex::new(Subsystem::Shell(shell))));
+        let channel_state = self.channels.get_mut(&channel).unwrap();
+        let shell = Shell::new(
+            true,
+            channel_state.pty.as_ref(),
+            &mut self.state,
+            channel,
+            &mut session,
+        );
+        channel_state.subsystem = Some(Arc::new(Mutex::new(Subsystem::Shell(shell))));
+        self.leave_channel(channel);
 
         session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
@@ -532,17 +1336,27 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "exec_request");
         let _entered = span.enter();
 
+        if !self.start_channel(channel, ChannelMode::Exec) {
+            session.channel_failure(channel);
+            return self.finished(session).boxed().wrap(Span::current());
+        }
+
         let data = data.to_vec();
 
         async move {
-            let mut shell = Shell::new(false, channel, &mut session);
+            self.enter_channel(channel);
+
+            let pty = self.channels.get(&channel).and_then(|v| v.pty.clone());
+            let mut shell = Shell::new(false, pty.as_ref(), &mut self.state, channel, &mut session);
             shell
-                .data(&mut self.state, channel, &data, &mut session)
+                .exec(&mut self.state, channel, &data, &mut session)
                 .await;
 
-            self.subsystem
-                .insert(channel, Arc::new(Mutex::new(Subsystem::Shell(shell))));
+            if let Some(channel_state) = self.channels.get_mut(&channel) {
+                channel_state.subsystem = Some(Arc::new(Mutex::new(Subsystem::Shell(shell))));
+            }
 
+            self.leave_channel(channel);
             session.channel_success(channel);
             self.finished(session).await
         }
@@ -559,20 +1373,26 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "subsystem_request");
         let _entered = span.enter();
 
+        self.enter_channel(channel);
         self.state
             .audit_log
             .push_action(AuditLogAction::SubsystemRequest(SubsystemRequestEvent {
                 name: Box::from(name),
             }));
+        self.leave_channel(channel);
 
         let subsystem = match name {
             subsystem::sftp::Sftp::NAME => Some(Subsystem::Sftp(subsystem::sftp::Sftp::default())),
             _ => None,
         };
 
-        if let Some(subsystem) = subsystem {
-            self.subsystem
-                .insert(channel, Arc::new(Mutex::new(subsystem)));
+        if let Some(subsystem) =
+            subsystem.filter(|_| self.start_channel(channel, ChannelMode::Sftp))
+        {
+            if let Some(channel_state) = self.channels.get_mut(&channel) {
+                channel_state.subsystem = Some(Arc::new(Mutex::new(subsystem)));
+            }
+
             session.channel_success(channel);
         } else {
             session.channel_failure(channel);
@@ -593,6 +1413,7 @@ impl thrussh::server::Handler for Connection {
         let span = info_span!(parent: &self.span, "window_change_request");
         let _entered = span.enter();
 
+        self.enter_channel(channel);
         self.state
             .audit_log
             .push_action(AuditLogAction::WindowChangeRequest(
@@ -603,6 +1424,16 @@ impl thrussh::server::Handler for Connection {
                     pix_height,
                 },
             ));
+        self.leave_channel(channel);
+
+        if let Some(pty) = self.channels.get_mut(&channel).and_then(|v| v.pty.as_mut()) {
+            pty.col_width = col_width;
+            pty.row_height = row_height;
+        }
+
+        if let Some(recording) = &self.state.recording {
+            recording.resize(None, col_width, row_height);
+        }
 
         session.channel_success(channel);
         self.finished(session).boxed().wrap(Span::current())
@@ -610,18 +1441,20 @@ impl thrussh::server::Handler for Connection {
 
     fn signal(
         mut self,
-        _channel: ChannelId,
+        channel: ChannelId,
         signal_name: Sig,
         session: Session,
     ) -> Self::FutureUnit {
         let span = info_span!(parent: &self.span, "signal");
         let _entered = span.enter();
 
+        self.enter_channel(channel);
         self.state
             .audit_log
             .push_action(AuditLogAction::Signal(SignalEvent {
                 name: format!("{signal_name:?}").into(),
             }));
+        self.leave_channel(channel);
 
         self.finished(session).boxed().wrap(Span::current())
     }
@@ -637,7 +1470,34 @@ impl thrussh::server::Handler for Connection {
                 port,
             }));
 
-        self.finished_bool(false, session)
+        let forwarding = &self.state.config.forwarding;
+
+        if !forwarding.accept_remote {
+            return self
+                .finished_bool(false, session)
+                .boxed()
+                .wrap(Span::current());
+        }
+
+        if forwarding.incoming_per_minute > 0.0 {
+            let task = tokio::spawn(simulate_incoming(
+                session.handle(),
+                self.incoming.clone(),
+                Box::from(address),
+                port,
+                self.state.rng(("remote-forward", address, port)),
+                forwarding.clone(),
+            ));
+
+            if let Some(previous) = self
+                .remote_forwards
+                .insert((Box::from(address), port), task)
+            {
+                previous.abort();
+            }
+        }
+
+        self.finished_bool(true, session)
             .boxed()
             .wrap(Span::current())
     }
@@ -658,7 +1518,15 @@ impl thrussh::server::Handler for Connection {
                 port,
             }));
 
-        self.finished_bool(false, session)
+        let cancelled = if let Some(task) = self.remote_forwards.remove(&(Box::from(address), port))
+        {
+            task.abort();
+            true
+        } else {
+            self.state.config.forwarding.accept_remote
+        };
+
+        self.finished_bool(cancelled, session)
             .boxed()
             .wrap(Span::current())
     }
@@ -671,10 +1539,106 @@ impl Drop for Connection {
 
         info!("Connection closed");
 
-        let _res = self
-            .server
-            .audit_send
-            .send(std::mem::take(&mut self.state.audit_log));
+        self.server.state.connections.remove(&self.active.id);
+        self.active.takeover.detach();
+
+        for task in self.remote_forwards.values() {
+            task.abort();
+        }
+
+        self.accept_incoming();
+
+        if let (Some(recording), Some(template)) = (
+            self.state.recording.take(),
+            &self.state.config.recording_path,
+        ) {
+            let path = expand_path_template(template, &self.state.audit_log);
+
+            if recording.finish(path.clone(), &self.state.audit_log) {
+                self.state.audit_log.recording = Some(path.to_string_lossy().into());
+            }
+        }
+
+        if let Some(addr) = self.state.audit_log.peer_address {
+            let mut visitor = std::mem::take(&mut self.visitor);
+
+            if let Some(file_system) = self.state.file_system.take() {
+                visitor.file_system = Some(file_system.into_tree());
+                visitor.last_login = u64::try_from(self.state.audit_log.ts.unix_timestamp()).ok();
+            }
+
+            // don't bother keeping state around for clients that never got a foot in the door
+            if visitor.file_system.is_some() || !visitor.accepted_passwords.is_empty() {
+                self.server.state.visitors.store(addr.ip(), visitor);
+            }
+        }
+
+        self.state.audit_log.client_handshake = self.handshake.get().cloned();
+        self.state.audit_log.reverse_dns = self.reverse_dns.get().cloned();
+        self.state.virus_total.enrich(&mut self.state.audit_log);
+        self.server
+            .state
+            .settings
+            .load()
+            .classifier
+            .classify(&mut self.state.audit_log);
+        self.state.audit_log.finish();
+
+        let summary = self.state.audit_log.summary(self.auth_method);
+        self.state
+            .audit_log
+            .push_action(AuditLogAction::SessionSummary(summary));
+
+        self.server
+            .state
+            .audit
+            .push(std::mem::take(&mut self.state.audit_log));
+    }
+}
+
+/// Opens a channel to the client every so often for a made up connection to its remote forward
+/// on `address` and `port`, up to the configured limit.
+async fn simulate_incoming(
+    mut handle: thrussh::server::Handle,
+    incoming: Arc<parking_lot::Mutex<Vec<IncomingConnection>>>,
+    address: Box<str>,
+    port: u32,
+    rng: fastrand::Rng,
+    config: ForwardingConfig,
+) {
+    for _ in 0..config.max_incoming {
+        // jittered so connections don't arrive like clockwork
+        let delay = 60.0 / config.incoming_per_minute * (0.5 + rng.f64());
+        tokio::time::sleep(Duration::from_secs_f64(delay)).await;
+
+        let originator = SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(
+                rng.u8(1..=223),
+                rng.u8(..),
+                rng.u8(..),
+                rng.u8(1..=254),
+            )),
+            rng.u16(32768..61000),
+        );
+
+        let Ok(channel) = handle
+            .channel_open_forwarded_tcpip(
+                address.to_string(),
+                port,
+                originator.ip().to_string(),
+                u32::from(originator.port()),
+            )
+            .await
+        else {
+            return;
+        };
+
+        incoming.lock().push(IncomingConnection {
+            channel,
+            address: address.clone(),
+            port,
+            originator,
+        });
     }
 }
 
@@ -682,6 +1646,7 @@ impl Drop for Connection {
 pub enum Subsystem {
     Shell(subsystem::shell::Shell),
     Sftp(subsystem::sftp::Sftp),
+    Sinkhole(subsystem::sinkhole::Sinkhole),
 }
 
 #[cfg_attr(test, mockall::automock)]
@@ -699,9 +1664,13 @@ impl ThrusshSession for Session {
     }
 }
 
-impl ThrusshSession for &mut Session {
+impl<T: ThrusshSession + ?Sized> ThrusshSession for &mut T {
     fn data(&mut self, channel: ChannelId, data: CryptoVec) {
-        Session::data(self, channel, data);
+        T::data(self, channel, data);
+    }
+
+    fn redirected(&self) -> bool {
+        T::redirected(self)
     }
 }
 
@@ -793,12 +1762,127 @@ impl<T, E, F: Future<Output = Result<T, E>> + Unpin> Future for ServerFuture<E,
 
 #[cfg(test)]
 pub mod test {
-    use thrussh::ChannelId;
+    use std::{net::SocketAddr, sync::Arc};
+
+    use arc_swap::ArcSwap;
+    use thrussh::{
+        server::{Auth, Handler},
+        ChannelId,
+    };
+
+    use super::{Connection, Server};
+    use crate::{
+        audit::{AuditLogAction, DisconnectReason},
+        config::Config,
+        state::{Settings, State},
+    };
+
+    const LISTEN_ADDRESS: &str = "127.0.0.1:2222";
+    const PEER: &str = "203.0.113.7:50000";
 
     pub fn fake_channel_id() -> ChannelId {
         unsafe { std::mem::transmute(0_u32) }
     }
 
+    fn server(config: Config) -> Server {
+        let state = State {
+            settings: ArcSwap::from_pointee(Settings::new(Arc::new(config)).unwrap()),
+            ..State::default()
+        };
+
+        Server::new("test", Arc::new(state))
+    }
+
+    fn connect(server: &Server) -> Connection {
+        let peer_addr: SocketAddr = PEER.parse().unwrap();
+        let settings = server
+            .state
+            .settings
+            .load()
+            .listener(LISTEN_ADDRESS.parse().unwrap());
+        let permit = server
+            .state
+            .rate_limiter
+            .admit(peer_addr.ip(), &settings.config.rate_limit)
+            .unwrap();
+
+        server.new_connection(settings, peer_addr, Arc::default(), permit)
+    }
+
+    fn disconnects(connection: &Connection) -> Vec<DisconnectReason> {
+        connection
+            .state
+            .audit_log
+            .events
+            .iter()
+            .filter_map(|v| match &v.action {
+                AuditLogAction::Disconnected(event) => Some(event.reason),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn disconnects_after_max_auth_tries() {
+        let server = server(Config {
+            access_probability: 0.0,
+            max_auth_tries: 3,
+            ..Config::default()
+        });
+        let mut connection = connect(&server);
+
+        for attempt in 1..=3 {
+            assert!(disconnects(&connection).is_empty());
+
+            let (next, auth) = connection
+                .auth_password("root", &format!("hunter{attempt}"))
+                .await
+                .unwrap();
+            assert!(matches!(auth, Auth::Reject));
+            connection = next;
+        }
+
+        assert_eq!(
+            disconnects(&connection),
+            [DisconnectReason::TooManyAuthenticationFailures]
+        );
+    }
+
+    #[tokio::test]
+    async fn open_connections_keep_settings_across_reload() {
+        let config = Config {
+            access_probability: 0.0,
+            max_auth_tries: 3,
+            ..Config::default()
+        };
+        let server = server(config.clone());
+        let connection = connect(&server);
+
+        server
+            .state
+            .reload(Arc::new(Config {
+                max_auth_tries: 1,
+                ..config
+            }))
+            .unwrap();
+
+        // the connection opened before the reload still allows three attempts
+        let (connection, _auth) = connection.auth_password("root", "hunter2").await.unwrap();
+        assert_eq!(connection.state.config.max_auth_tries, 3);
+        assert!(disconnects(&connection).is_empty());
+
+        // while new connections pick up the change
+        let (connection, _auth) = connect(&server)
+            .auth_password("root", "hunter2")
+            .await
+            .unwrap();
+        assert_eq!(connection.state.config.max_auth_tries, 1);
+        assert_eq!(
+            disconnects(&connection),
+            [DisconnectReason::TooManyAuthenticationFailures]
+        );
+    }
+
     pub mod predicate {
         use mockall::{predicate, Predicate};
         use thrussh::CryptoVec;
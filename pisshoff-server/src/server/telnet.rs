@@ -0,0 +1,479 @@
+//! Speaks telnet to clients on listeners configured for it, putting a `login:` prompt in front
+//! of the same shell SSH clients get. Login attempts, commands and everything else end up in
+//! the connection's audit log just as they would over SSH.
+
+use std::time::Duration;
+
+use thrussh::{server::Handle, ChannelId, CryptoVec};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::{debug, info, Instrument};
+
+use super::{Connection, ThrusshSession};
+use crate::{
+    audit::{AuditLogAction, DisconnectReason, DisconnectedEvent},
+    subsystem::shell::Shell,
+    terminal::{Pty, ShellSession},
+};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+
+/// How long clients have to log in before they're disconnected.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a failed login takes to be rejected, as `login` makes clients wait.
+const FAILED_LOGIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest username or password read at the login prompt, anything past it is dropped.
+const MAX_LINE_LENGTH: usize = 256;
+
+/// Runs the telnet session for `connection` until the client goes away or exits the shell,
+/// dropping the connection afterwards to write out its audit log.
+pub async fn run(stream: TcpStream, mut connection: Connection, banner: Option<String>) {
+    let span = connection.span.clone();
+    let mut telnet = Telnet::new(stream);
+
+    if let Err(error) = session(&mut telnet, &mut connection, banner)
+        .instrument(span)
+        .await
+    {
+        debug!(%error, "Connection closed with error");
+    }
+}
+
+async fn session(
+    telnet: &mut Telnet,
+    connection: &mut Connection,
+    banner: Option<String>,
+) -> std::io::Result<()> {
+    // we do the echoing ourselves, so passwords can be hidden, and run in character mode so
+    // the shell gets keystrokes as they're typed
+    telnet
+        .write(&[IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD])
+        .await?;
+
+    if let Some(banner) = banner {
+        telnet.write(&crlf(banner.as_bytes())).await?;
+    }
+
+    match tokio::time::timeout(LOGIN_TIMEOUT, login(telnet, connection)).await {
+        Ok(Ok(true)) => {}
+        Ok(Ok(false)) => return Ok(()),
+        Ok(Err(error)) => return Err(error),
+        Err(_) => {
+            info!("Login timed out, disconnecting");
+            connection
+                .state
+                .audit_log
+                .push_action(AuditLogAction::Disconnected(DisconnectedEvent {
+                    reason: DisconnectReason::LoginTimeout,
+                }));
+            telnet
+                .write(b"\r\nLogin timed out after 60 seconds.\r\n")
+                .await?;
+            return Ok(());
+        }
+    }
+
+    shell(telnet, connection).await
+}
+
+/// Prompts for credentials until the client gets some accepted, returning `false` if it gives
+/// up or runs out of attempts first.
+async fn login(telnet: &mut Telnet, connection: &mut Connection) -> std::io::Result<bool> {
+    let prompt = format!("{} login: ", connection.server.hostname);
+
+    loop {
+        telnet.write(prompt.as_bytes()).await?;
+        let Some(username) = telnet.read_line(true).await? else {
+            return Ok(false);
+        };
+
+        if username.is_empty() {
+            continue;
+        }
+
+        telnet.write(b"Password: ").await?;
+        let Some(password) = telnet.read_line(false).await? else {
+            return Ok(false);
+        };
+        telnet.write(b"\r\n").await?;
+
+        let accepted = connection.try_login(&username, &password);
+        connection.update_stats();
+
+        if accepted {
+            return Ok(true);
+        }
+
+        connection.auth_failures += 1;
+        tokio::time::sleep(FAILED_LOGIN_DELAY).await;
+        telnet.write(b"\r\nLogin incorrect\r\n").await?;
+
+        if connection.auth_failures == connection.state.config.max_auth_tries {
+            info!("Too many authentication failures, disconnecting");
+
+            connection
+                .state
+                .audit_log
+                .push_action(AuditLogAction::Disconnected(DisconnectedEvent {
+                    reason: DisconnectReason::TooManyAuthenticationFailures,
+                }));
+            return Ok(false);
+        }
+    }
+}
+
+/// Hands everything the client sends to an interactive shell until either of them closes.
+async fn shell(telnet: &mut Telnet, connection: &mut Connection) -> std::io::Result<()> {
+    connection
+        .state
+        .audit_log
+        .push_action(AuditLogAction::ShellRequested);
+
+    // telnet clients don't say what terminal they are unless asked, so they're assumed to be
+    // the usual size
+    let pty = Pty {
+        term: Box::from("xterm"),
+        col_width: 80,
+        row_height: 24,
+        echo: true,
+    };
+
+    let channel = channel_id();
+    let mut session = TelnetSession::default();
+    let mut shell = Shell::new(
+        true,
+        Some(&pty),
+        &mut connection.state,
+        channel,
+        &mut session,
+    );
+
+    // anything typed ahead of the prompt goes straight to the shell
+    let mut data = std::mem::take(&mut telnet.pending);
+
+    loop {
+        if !data.is_empty() {
+            shell
+                .input(&mut connection.state, channel, &data, &mut session)
+                .await;
+            connection.update_stats();
+        }
+
+        telnet.write(&std::mem::take(&mut session.output)).await?;
+
+        if session.closed {
+            return Ok(());
+        }
+
+        match telnet.read().await? {
+            Some(v) => data = v,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Telnet has no channels, but the shell addresses its output to one regardless. Whatever it
+/// sends ends up in the [`TelnetSession`], which doesn't look at it.
+fn channel_id() -> ChannelId {
+    // SAFETY: `ChannelId` is a plain wrapper around a `u32` that thrussh doesn't let us build
+    unsafe { std::mem::transmute(0_u32) }
+}
+
+/// Translates bare newlines to `\r\n`, as telnet expects.
+fn crlf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+
+        out.push(byte);
+    }
+
+    out
+}
+
+/// A client's telnet stream, with the protocol's commands stripped out of its input.
+struct Telnet {
+    stream: TcpStream,
+    decoder: Decoder,
+    /// Input that's been read, but not yet used.
+    pending: Vec<u8>,
+}
+
+impl Telnet {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            decoder: Decoder::default(),
+            pending: Vec::new(),
+        }
+    }
+
+    async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.stream.write_all(data).await
+    }
+
+    /// Reads the next lot of input from the client, answering any options it asks for along
+    /// the way. Returns `None` once the client has closed the connection.
+    async fn read(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut buf = [0; 4096];
+
+        loop {
+            let n = self.stream.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+
+            let mut out = Vec::with_capacity(n);
+            self.decoder.decode(&buf[..n], &mut out);
+
+            let replies = std::mem::take(&mut self.decoder.replies);
+            self.write(&replies).await?;
+
+            if !out.is_empty() {
+                return Ok(Some(out));
+            }
+        }
+    }
+
+    /// Reads a line at the login prompt, echoing it back if `echo` is set. Returns `None` once
+    /// the client has closed the connection.
+    async fn read_line(&mut self, echo: bool) -> std::io::Result<Option<String>> {
+        let mut line = Vec::new();
+
+        loop {
+            if self.pending.is_empty() {
+                match self.read().await? {
+                    Some(v) => self.pending = v,
+                    None => return Ok(None),
+                }
+            }
+
+            let mut echoed = Vec::new();
+            let mut finished = false;
+            let mut consumed = 0;
+
+            for &byte in &self.pending {
+                consumed += 1;
+
+                match byte {
+                    b'\r' | b'\n' => {
+                        finished = true;
+                        break;
+                    }
+                    // ^D on an empty line logs out
+                    0x04 if line.is_empty() => return Ok(None),
+                    0x08 | 0x7f => {
+                        if line.pop().is_some() {
+                            echoed.extend_from_slice(b"\x08 \x08");
+                        }
+                    }
+                    _ if byte.is_ascii_control() => {}
+                    _ if line.len() < MAX_LINE_LENGTH => {
+                        line.push(byte);
+                        echoed.push(byte);
+                    }
+                    _ => {}
+                }
+            }
+
+            self.pending.drain(..consumed);
+
+            if echo {
+                self.write(&echoed).await?;
+            }
+
+            if finished {
+                if echo {
+                    self.write(b"\r\n").await?;
+                }
+
+                return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+            }
+        }
+    }
+}
+
+/// Strips telnet commands out of the client's input, normalising the line endings telnet sends
+/// for the return key to a lone `\r` as a terminal would.
+#[derive(Default)]
+struct Decoder {
+    state: DecoderState,
+    /// Responses to the options the client has asked for, waiting to be sent.
+    replies: Vec<u8>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    #[default]
+    Data,
+    /// A `\r` has been passed through, which the client follows with a `\n` or NUL.
+    CarriageReturn,
+    Command,
+    /// Waiting on the option a `WILL`, `WONT`, `DO` or `DONT` refers to.
+    Option(u8),
+    Subnegotiation,
+    SubnegotiationCommand,
+}
+
+impl Decoder {
+    fn decode(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        use DecoderState as S;
+
+        for &byte in input {
+            self.state = match (self.state, byte) {
+                (S::Data | S::CarriageReturn, IAC) => S::Command,
+                (S::CarriageReturn, b'\n' | 0) => S::Data,
+                (S::Data | S::CarriageReturn, b'\r') => {
+                    out.push(b'\r');
+                    S::CarriageReturn
+                }
+                (S::Data | S::CarriageReturn, _) => {
+                    out.push(byte);
+                    S::Data
+                }
+                (S::Command, IAC) => {
+                    out.push(IAC);
+                    S::Data
+                }
+                (S::Command, WILL | WONT | DO | DONT) => S::Option(byte),
+                (S::Command, SB) => S::Subnegotiation,
+                // everything else is a single byte command we've no use for, such as NOP
+                (S::Command, _) => S::Data,
+                (S::Option(command), option) => {
+                    self.negotiate(command, option);
+                    S::Data
+                }
+                (S::Subnegotiation, IAC) => S::SubnegotiationCommand,
+                (S::SubnegotiationCommand, SE) => S::Data,
+                (S::Subnegotiation | S::SubnegotiationCommand, _) => S::Subnegotiation,
+            };
+        }
+    }
+
+    /// Refuses any option the client asks for other than the ones we've offered. Refusals
+    /// aren't answered, so the two sides can't get stuck going back and forth.
+    fn negotiate(&mut self, command: u8, option: u8) {
+        match command {
+            DO if !matches!(option, ECHO | SUPPRESS_GO_AHEAD) => {
+                self.replies.extend_from_slice(&[IAC, WONT, option]);
+            }
+            WILL => self.replies.extend_from_slice(&[IAC, DONT, option]),
+            _ => {}
+        }
+    }
+}
+
+/// Collects the shell's output to be written to the client, escaping anything that would be
+/// taken as a telnet command.
+#[derive(Default)]
+struct TelnetSession {
+    output: Vec<u8>,
+    /// Whether the shell has exited.
+    closed: bool,
+}
+
+impl ThrusshSession for TelnetSession {
+    fn data(&mut self, _channel: ChannelId, data: CryptoVec) {
+        for &byte in data.iter() {
+            if byte == IAC {
+                self.output.push(IAC);
+            }
+
+            self.output.push(byte);
+        }
+    }
+}
+
+impl ShellSession for TelnetSession {
+    fn exit_status_request(&mut self, _channel: ChannelId, _exit_status: u32) {}
+
+    fn eof(&mut self, _channel: ChannelId) {}
+
+    fn close(&mut self, _channel: ChannelId) {
+        self.closed = true;
+    }
+
+    fn handle(&self) -> Option<Handle> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crlf, Decoder, DO, DONT, ECHO, IAC, SB, SE, WILL, WONT};
+
+    fn decode(decoder: &mut Decoder, input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        decoder.decode(input, &mut out);
+        out
+    }
+
+    #[test]
+    fn strips_commands() {
+        let mut decoder = Decoder::default();
+
+        let out = decode(
+            &mut decoder,
+            &[
+                b'l', IAC, DO, ECHO, b's', IAC, SB, 24, 0, b'x', IAC, IAC, IAC, SE, IAC, IAC,
+            ],
+        );
+
+        assert_eq!(out, [b'l', b's', IAC]);
+    }
+
+    #[test]
+    fn commands_can_be_split_across_reads() {
+        let mut decoder = Decoder::default();
+
+        assert_eq!(decode(&mut decoder, &[b'a', IAC]), b"a");
+        assert_eq!(decode(&mut decoder, &[WILL]), b"");
+        assert_eq!(decode(&mut decoder, &[31, b'b']), b"b");
+    }
+
+    #[test]
+    fn normalises_return() {
+        let mut decoder = Decoder::default();
+
+        assert_eq!(decode(&mut decoder, b"ls\r\n"), b"ls\r");
+        assert_eq!(decode(&mut decoder, b"id\r"), b"id\r");
+        assert_eq!(decode(&mut decoder, b"\0pwd\r\r\n"), b"pwd\r\r");
+    }
+
+    #[test]
+    fn refuses_options() {
+        let mut decoder = Decoder::default();
+
+        decode(
+            &mut decoder,
+            &[IAC, DO, ECHO, IAC, WILL, 31, IAC, DO, 5, IAC, WONT, 24],
+        );
+
+        assert_eq!(decoder.replies, [IAC, DONT, 31, IAC, WONT, 5]);
+    }
+
+    #[test]
+    fn translates_newlines() {
+        assert_eq!(crlf(b"\nhello\r\nworld\n"), b"\r\nhello\r\nworld\r\n");
+    }
+}
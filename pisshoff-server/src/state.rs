@@ -1,12 +1,177 @@
-use std::{borrow::Cow, collections::HashSet};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use parking_lot::RwLock;
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thrussh::{server::Handle, ChannelId, CryptoVec};
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    audit::{queue::AuditQueue, ClientHandshake, Protocol, RateLimit},
+    classify::Classifier,
+    config::{Config, RateLimitConfig, TarpitConfig},
+    file_system::Tree,
+    geoip::GeoIpDatabase,
+    metrics::Listeners,
+    persona,
+    recording::Recording,
+    reverse_dns::ReverseDns,
+    terminal::translate_newlines,
+    virustotal::VirusTotal,
+};
 
 #[derive(Default)]
 pub struct State {
+    /// Settings that can be changed without a restart, swapped out on SIGHUP.
+    pub settings: ArcSwap<Settings>,
     /// A list of passwords that have previously been accepted, and will forever be accepted
     /// to further attract the bear.
     pub previously_accepted_passwords: StoredPasswords,
+    /// Fingerprints of public keys that have previously been accepted, these will also be
+    /// accepted forever.
+    pub previously_accepted_keys: StoredKeys,
+    /// State kept for each peer address, so returning clients find things as they left them.
+    pub visitors: Visitors,
+    /// Databases to enrich audit logs with the peer's location and network.
+    pub geoip: GeoIpDatabase,
+    /// Cached PTR records of peer addresses.
+    pub reverse_dns: ReverseDns,
+    /// Lookups of quarantined payloads on VirusTotal.
+    pub virus_total: Arc<VirusTotal>,
+    /// Connections that are currently open, for the admin interface.
+    pub connections: ActiveConnections,
+    /// Connections open and recently opened by each peer address.
+    pub rate_limiter: RateLimiter,
+    pub peer_seeds: PeerSeeds,
+    /// Logs waiting to be written out by the audit writer.
+    pub audit: Arc<AuditQueue>,
+    /// What each listener is doing, for the readiness check.
+    pub listeners: Listeners,
+}
+
+impl State {
+    pub fn new(config: Arc<Config>) -> Result<Self, std::io::Error> {
+        // the persona's uptime counts from now, rather than from the first time it's asked for
+        persona::started();
+
+        Ok(Self {
+            settings: ArcSwap::from_pointee(Settings::new(config.clone())?),
+            visitors: Visitors::load(
+                config.state_dir.clone(),
+                Duration::from_secs(config.visitor_ttl),
+                config.max_visitors,
+            )?,
+            geoip: GeoIpDatabase::open(&config.geoip)?,
+            reverse_dns: ReverseDns::new(&config.reverse_dns)?,
+            virus_total: Arc::new(VirusTotal::new(&config.virustotal)?),
+            audit: Arc::new(AuditQueue::new(&config)),
+            ..Self::default()
+        })
+    }
+
+    /// Applies a freshly loaded config. Open connections carry on with the settings they
+    /// started with, only new connections pick up the change.
+    ///
+    /// Options that are only read on startup, such as `listen-address`, need a restart.
+    pub fn reload(&self, config: Arc<Config>) -> Result<(), std::io::Error> {
+        self.settings.store(Arc::new(Settings::new(config)?));
+        Ok(())
+    }
+}
+
+/// The parts of the server's state derived from the config, which can be swapped out on the
+/// fly.
+#[derive(Default)]
+pub struct Settings {
+    pub config: Arc<Config>,
+    /// File system each new session starts off with, built from the persona and snapshot.
+    pub file_system_seed: Option<Arc<Tree>>,
+    /// Settings for each of the configured listeners, which may have their own persona.
+    pub listeners: Vec<ListenerSettings>,
+    /// Tags sessions as they close.
+    pub classifier: Classifier,
+}
+
+#[derive(Clone)]
+pub struct ListenerSettings {
+    pub address: SocketAddr,
+    /// The config with the listener's persona applied.
+    pub config: Arc<Config>,
+    pub file_system_seed: Option<Arc<Tree>>,
+    pub tarpit: Option<TarpitConfig>,
+    pub protocol: Protocol,
+    /// Message shown to clients before they log in, for protocols where it isn't sent as part
+    /// of the handshake.
+    pub banner: Option<String>,
+}
+
+impl Settings {
+    pub fn new(config: Arc<Config>) -> Result<Self, std::io::Error> {
+        let file_system_snapshot = config
+            .file_system_snapshot
+            .as_deref()
+            .map(Tree::load_snapshot)
+            .transpose()?;
+
+        let listeners = config
+            .listen_address
+            .iter()
+            .map(|listener| {
+                let config = config.for_listener(listener);
+                let file_system_seed = config.persona.seed(file_system_snapshot.clone());
+                let banner = config.banner(listener).map(str::to_string);
+
+                ListenerSettings {
+                    address: listener.address,
+                    config: Arc::new(config),
+                    file_system_seed: Some(Arc::new(file_system_seed)),
+                    tarpit: listener.tarpit.clone(),
+                    protocol: listener.protocol,
+                    banner,
+                }
+            })
+            .collect();
+
+        let file_system_seed = Arc::new(config.persona.seed(file_system_snapshot));
+        let classifier = Classifier::new(&config.classifier)?;
+
+        Ok(Self {
+            config,
+            file_system_seed: Some(file_system_seed),
+            listeners,
+            classifier,
+        })
+    }
+
+    /// Settings for connections accepted on `address`. Listeners are only bound on startup, so
+    /// any that have since been removed from the config fall back to the top-level settings.
+    pub fn listener(&self, address: SocketAddr) -> ListenerSettings {
+        self.listeners
+            .iter()
+            .find(|v| v.address == address)
+            .cloned()
+            .unwrap_or_else(|| ListenerSettings {
+                address,
+                config: self.config.clone(),
+                file_system_seed: self.file_system_seed.clone(),
+                tarpit: None,
+                protocol: Protocol::default(),
+                banner: None,
+            })
+    }
 }
 
 #[derive(Default)]
@@ -26,6 +191,624 @@ impl StoredPasswords {
     }
 }
 
+#[derive(Default)]
+pub struct StoredKeys(RwLock<HashSet<Box<str>>>);
+
+impl StoredKeys {
+    pub fn seen(&self, fingerprint: &str) -> bool {
+        self.0.read().contains(fingerprint)
+    }
+
+    pub fn store(&self, fingerprint: &str) -> bool {
+        self.0.write().insert(Box::from(fingerprint))
+    }
+}
+
+#[derive(Default)]
+pub struct ActiveConnections {
+    connections: RwLock<HashMap<Uuid, Arc<ActiveConnection>>>,
+    /// Woken each time a connection closes, for shutdown to wait on.
+    closed: Notify,
+}
+
+impl ActiveConnections {
+    pub fn insert(&self, connection: Arc<ActiveConnection>) {
+        self.connections.write().insert(connection.id, connection);
+    }
+
+    pub fn remove(&self, id: &Uuid) {
+        self.connections.write().remove(id);
+        self.closed.notify_waiters();
+    }
+
+    /// Returns every open connection, oldest first.
+    pub fn list(&self) -> Vec<Arc<ActiveConnection>> {
+        let mut out: Vec<_> = self.connections.read().values().cloned().collect();
+        out.sort_by_key(|v| v.start);
+        out
+    }
+
+    /// Waits until every open connection has closed.
+    pub async fn drained(&self) {
+        loop {
+            let closed = self.closed.notified();
+            tokio::pin!(closed);
+            // registered before checking so a connection closing in between isn't missed
+            closed.as_mut().enable();
+
+            if self.connections.read().is_empty() {
+                return;
+            }
+
+            closed.await;
+        }
+    }
+
+    /// Finds the connection with an ID starting with `prefix`, so operators don't have to type
+    /// out the whole thing.
+    pub fn find(&self, prefix: &str) -> Result<Arc<ActiveConnection>, String> {
+        let connections = self.connections.read();
+        let mut matches = connections
+            .values()
+            .filter(|v| v.id.to_string().starts_with(prefix));
+
+        match (matches.next(), matches.next()) {
+            (Some(connection), None) if !prefix.is_empty() => Ok(connection.clone()),
+            (Some(_), _) => Err(format!("{prefix} matches more than one connection")),
+            (None, _) => Err(format!("no open connection matches {prefix}")),
+        }
+    }
+}
+
+/// Number of peers to track before forgetting about the ones that have gone quiet.
+const RATE_LIMITER_PRUNE_THRESHOLD: usize = 4096;
+
+/// Enforces the limits on connections from a single address, using a token bucket per address
+/// to limit the rate new connections are opened at.
+#[derive(Clone, Default)]
+pub struct RateLimiter(Arc<Mutex<HashMap<IpAddr, Peer>>>);
+
+struct Peer {
+    /// Number of connections the peer has open.
+    open: usize,
+    /// Number of connections the peer can make before being limited.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Peer {
+    fn refill(&mut self, config: &RateLimitConfig, now: Instant) {
+        let rate = config.connections_per_minute.unwrap_or_default() / 60.0;
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(config.burst));
+        self.last_refill = now;
+    }
+}
+
+impl RateLimiter {
+    /// Checks whether `ip` is allowed to open another connection, returning a permit counting
+    /// it as open until it's dropped.
+    pub fn admit(
+        &self,
+        ip: IpAddr,
+        config: &RateLimitConfig,
+    ) -> Result<ConnectionPermit, RateLimit> {
+        self.admit_at(ip, config, Instant::now())
+    }
+
+    fn admit_at(
+        &self,
+        ip: IpAddr,
+        config: &RateLimitConfig,
+        now: Instant,
+    ) -> Result<ConnectionPermit, RateLimit> {
+        let mut peers = self.0.lock();
+
+        if peers.len() >= RATE_LIMITER_PRUNE_THRESHOLD {
+            peers.retain(|_, peer| {
+                peer.refill(config, now);
+                peer.open > 0 || peer.tokens < f64::from(config.burst)
+            });
+        }
+
+        let peer = peers.entry(ip).or_insert_with(|| Peer {
+            open: 0,
+            tokens: f64::from(config.burst),
+            last_refill: now,
+        });
+
+        if config
+            .max_connections_per_ip
+            .map_or(false, |max| peer.open >= max)
+        {
+            return Err(RateLimit::ConcurrentConnections);
+        }
+
+        if config.connections_per_minute.is_some() {
+            peer.refill(config, now);
+
+            if peer.tokens < 1.0 {
+                return Err(RateLimit::ConnectionRate);
+            }
+
+            peer.tokens -= 1.0;
+        }
+
+        peer.open += 1;
+
+        Ok(ConnectionPermit {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+}
+
+/// Counts a connection against its peer's limit until dropped.
+pub struct ConnectionPermit {
+    limiter: RateLimiter,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        if let Some(peer) = self.limiter.0.lock().get_mut(&self.ip) {
+            peer.open = peer.open.saturating_sub(1);
+        }
+    }
+}
+
+/// A connection that's currently open.
+pub struct ActiveConnection {
+    pub id: Uuid,
+    pub peer_address: SocketAddr,
+    pub started_at: OffsetDateTime,
+    pub start: Instant,
+    /// The client's side of the SSH handshake, filled in once it has been read.
+    pub handshake: Arc<OnceLock<ClientHandshake>>,
+    pub stats: Mutex<ConnectionStats>,
+    /// Notified when an operator asks for the connection to be closed.
+    pub kill: Notify,
+    /// Copies of the connection's terminal traffic, for operators watching it.
+    pub tap: Tap,
+    /// Lets an operator answer the client in place of the shell.
+    pub takeover: Takeover,
+}
+
+impl ActiveConnection {
+    pub fn new(
+        id: Uuid,
+        peer_address: SocketAddr,
+        started_at: OffsetDateTime,
+        start: Instant,
+        handshake: Arc<OnceLock<ClientHandshake>>,
+    ) -> Self {
+        Self {
+            id,
+            peer_address,
+            started_at,
+            start,
+            handshake,
+            stats: Mutex::new(ConnectionStats {
+                username: None,
+                events: 0,
+                last_activity: start,
+            }),
+            kill: Notify::new(),
+            tap: Tap::default(),
+            takeover: Takeover::default(),
+        }
+    }
+}
+
+/// Broadcasts a session's terminal traffic to any operators watching it.
+#[derive(Clone)]
+pub struct Tap(broadcast::Sender<TapEvent>);
+
+impl Default for Tap {
+    fn default() -> Self {
+        Self(broadcast::channel(256).0)
+    }
+}
+
+impl Tap {
+    /// Records input from the client that isn't echoed back to it, echoed input is already
+    /// part of the output.
+    pub fn input(&self, data: &[u8]) {
+        self.send(|| TapEvent::Input(Bytes::copy_from_slice(data)));
+    }
+
+    /// Records output sent to the client.
+    pub fn output(&self, data: &[u8]) {
+        self.send(|| TapEvent::Output(Bytes::copy_from_slice(data)));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TapEvent> {
+        self.0.subscribe()
+    }
+
+    fn send(&self, event: impl FnOnce() -> TapEvent) {
+        // don't bother copying the data if nobody's watching
+        if self.0.receiver_count() > 0 {
+            let _res = self.0.send(event());
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TapEvent {
+    Input(Bytes),
+    Output(Bytes),
+}
+
+/// Hands control of a session's interactive shell over to an operator. While an operator is in
+/// control, the shell stops responding to input and the operator's output is sent to the client
+/// instead.
+#[derive(Clone, Default)]
+pub struct Takeover(Arc<Mutex<TakeoverState>>);
+
+#[derive(Default)]
+struct TakeoverState {
+    /// Whether an operator is currently in control.
+    active: bool,
+    /// Where to send the operator's output, set once the client opens an interactive shell.
+    terminal: Option<TakeoverTerminal>,
+}
+
+#[derive(Clone)]
+struct TakeoverTerminal {
+    handle: Handle,
+    channel: ChannelId,
+    translate_newlines: bool,
+    recording: Option<Recording>,
+    tap: Tap,
+}
+
+impl Takeover {
+    /// Registers the channel of the connection's interactive shell, so it can be taken over.
+    pub fn attach(
+        &self,
+        handle: Handle,
+        channel: ChannelId,
+        translate_newlines: bool,
+        recording: Option<Recording>,
+        tap: Tap,
+    ) {
+        self.0.lock().terminal = Some(TakeoverTerminal {
+            handle,
+            channel,
+            translate_newlines,
+            recording,
+            tap,
+        });
+    }
+
+    /// Forgets about the connection's shell once the connection closes, letting go of its tap
+    /// so anyone watching is told about it.
+    pub fn detach(&self) {
+        self.0.lock().terminal = None;
+    }
+
+    /// Whether an operator is in control of the session.
+    pub fn active(&self) -> bool {
+        self.0.lock().active
+    }
+
+    /// Puts the operator in control of the session until the returned guard is dropped.
+    pub fn begin(&self) -> Result<TakeoverGuard, String> {
+        let mut state = self.0.lock();
+
+        if state.terminal.is_none() {
+            return Err("connection doesn't have an interactive shell".to_string());
+        } else if state.active {
+            return Err("connection is already being interacted with".to_string());
+        }
+
+        state.active = true;
+        Ok(TakeoverGuard(self.clone()))
+    }
+}
+
+/// Keeps an operator in control of a session, handing control back to the shell when dropped.
+pub struct TakeoverGuard(Takeover);
+
+impl TakeoverGuard {
+    /// Sends the operator's output to the client, returning `false` if the shell has closed.
+    pub async fn send(&self, data: &[u8]) -> bool {
+        let Some(mut terminal) = (self.0).0.lock().terminal.clone() else {
+            return false;
+        };
+
+        let data = if terminal.translate_newlines {
+            translate_newlines(data)
+        } else {
+            data.to_vec()
+        };
+
+        if let Some(recording) = &terminal.recording {
+            recording.output(&data);
+        }
+
+        terminal.tap.output(&data);
+        terminal
+            .handle
+            .data(terminal.channel, CryptoVec::from_slice(&data))
+            .await
+            .is_ok()
+    }
+}
+
+impl Drop for TakeoverGuard {
+    fn drop(&mut self) {
+        (self.0).0.lock().active = false;
+    }
+}
+
+/// Statistics about an open connection, updated after each request the client makes.
+#[derive(Clone)]
+pub struct ConnectionStats {
+    /// The username the client last tried to log in as.
+    pub username: Option<String>,
+    /// Number of events in the connection's audit log.
+    pub events: usize,
+    pub last_activity: Instant,
+}
+
+/// State left behind by a previous connection from the same peer address.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Visitor {
+    /// Unix timestamp of the end of the peer's last connection.
+    pub last_seen: u64,
+    /// Unix timestamp of the start of the peer's last connection that got into a session.
+    #[serde(default)]
+    pub last_login: Option<u64>,
+    /// The file system as it was at the end of the peer's last session.
+    #[serde(default)]
+    pub file_system: Option<Tree>,
+    /// Username and password pairs that have been accepted for the peer.
+    #[serde(default)]
+    pub accepted_passwords: Vec<(String, String)>,
+}
+
+impl Visitor {
+    pub fn accepted(&self, username: &str, password: &str) -> bool {
+        self.accepted_passwords
+            .iter()
+            .any(|(u, p)| u == username && p == password)
+    }
+}
+
+pub struct Visitors {
+    visitors: RwLock<HashMap<IpAddr, Visitor>>,
+    directory: Option<PathBuf>,
+    ttl: Duration,
+    /// Most visitors to keep, those last seen the longest ago being forgotten first.
+    capacity: usize,
+}
+
+impl Default for Visitors {
+    fn default() -> Self {
+        let config = Config::default();
+
+        Self {
+            visitors: RwLock::default(),
+            directory: None,
+            ttl: Duration::from_secs(config.visitor_ttl),
+            capacity: config.max_visitors,
+        }
+    }
+}
+
+impl Visitors {
+    /// Loads any unexpired visitors persisted to `directory` by a previous instance.
+    pub fn load(
+        directory: Option<PathBuf>,
+        ttl: Duration,
+        capacity: usize,
+    ) -> Result<Self, std::io::Error> {
+        let mut visitors = HashMap::new();
+
+        if let Some(directory) = &directory {
+            std::fs::create_dir_all(directory)?;
+
+            for entry in std::fs::read_dir(directory)? {
+                let path = entry?.path();
+
+                // left behind by a write that never finished
+                if path.extension().is_some_and(|v| v == "partial") {
+                    let _res = std::fs::remove_file(&path);
+                    continue;
+                }
+
+                let Some(ip) = path
+                    .file_stem()
+                    .and_then(|v| v.to_str())
+                    .and_then(|v| v.parse::<IpAddr>().ok())
+                else {
+                    continue;
+                };
+
+                let visitor = std::fs::read(&path).and_then(|v| {
+                    serde_json::from_slice::<Visitor>(&v)
+                        .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))
+                });
+
+                match visitor {
+                    Ok(visitor) if !expired(&visitor, ttl) => {
+                        visitors.insert(ip, visitor);
+                    }
+                    Ok(_) => {
+                        let _res = std::fs::remove_file(&path);
+                    }
+                    Err(error) => warn!(%error, ?path, "Failed to load visitor state"),
+                }
+            }
+
+            for ip in prune(&mut visitors, ttl, capacity) {
+                let _res = std::fs::remove_file(visitor_path(directory, ip));
+            }
+
+            info!("Loaded state for {} visitors", visitors.len());
+        }
+
+        Ok(Self {
+            visitors: RwLock::new(visitors),
+            directory,
+            ttl,
+            capacity,
+        })
+    }
+
+    /// Fetches the state left over from the peer's last connection, if it hasn't expired.
+    pub fn get(&self, ip: IpAddr) -> Option<Visitor> {
+        let visitors = self.visitors.read();
+        let visitor = visitors.get(&ip)?;
+
+        if expired(visitor, self.ttl) {
+            drop(visitors);
+            self.visitors.write().remove(&ip);
+            return None;
+        }
+
+        Some(visitor.clone())
+    }
+
+    /// Stores the state of the peer's connection, persisting it to disk in the background if
+    /// a state directory is configured. Visitors that have expired, or that no longer fit, are
+    /// forgotten to make room.
+    pub fn store(&self, ip: IpAddr, mut visitor: Visitor) {
+        visitor.last_seen = now();
+
+        if let Some(directory) = &self.directory {
+            match serde_json::to_vec(&visitor) {
+                Ok(data) => {
+                    let path = visitor_path(directory, ip);
+
+                    tokio::spawn(async move {
+                        if let Err(error) = write_visitor(&path, &data).await {
+                            warn!(%error, ?path, "Failed to persist visitor state");
+                        }
+                    });
+                }
+                Err(error) => warn!(%error, "Failed to serialise visitor state"),
+            }
+        }
+
+        let forgotten = {
+            let mut visitors = self.visitors.write();
+            visitors.insert(ip, visitor);
+            prune(&mut visitors, self.ttl, self.capacity)
+        };
+
+        if let (Some(directory), false) = (&self.directory, forgotten.is_empty()) {
+            let paths = forgotten
+                .into_iter()
+                .map(|ip| visitor_path(directory, ip))
+                .collect::<Vec<_>>();
+
+            tokio::spawn(async move {
+                for path in paths {
+                    let _res = tokio::fs::remove_file(path).await;
+                }
+            });
+        }
+    }
+}
+
+/// Drops expired visitors, then those last seen the longest ago until no more than `capacity`
+/// are left, returning the addresses of the visitors dropped.
+fn prune(visitors: &mut HashMap<IpAddr, Visitor>, ttl: Duration, capacity: usize) -> Vec<IpAddr> {
+    let mut forgotten = visitors
+        .iter()
+        .filter(|(_, visitor)| expired(visitor, ttl))
+        .map(|(ip, _)| *ip)
+        .collect::<Vec<_>>();
+
+    let excess = (visitors.len() - forgotten.len()).saturating_sub(capacity);
+    if excess > 0 {
+        let mut by_last_seen = visitors
+            .iter()
+            .filter(|(_, visitor)| !expired(visitor, ttl))
+            .map(|(ip, visitor)| (visitor.last_seen, *ip))
+            .collect::<Vec<_>>();
+        by_last_seen.sort_unstable();
+
+        forgotten.extend(by_last_seen.into_iter().take(excess).map(|(_, ip)| ip));
+    }
+
+    for ip in &forgotten {
+        visitors.remove(ip);
+    }
+
+    forgotten
+}
+
+fn visitor_path(directory: &Path, ip: IpAddr) -> PathBuf {
+    directory.join(format!("{ip}.json"))
+}
+
+/// Writes the visitor under a temporary name first, so an interrupted write never leaves a
+/// truncated file in place of the last good one.
+async fn write_visitor(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    let partial = path.with_extension(format!("{}.partial", Uuid::new_v4()));
+
+    let res = match tokio::fs::write(&partial, data).await {
+        Ok(()) => tokio::fs::rename(&partial, path).await,
+        Err(error) => Err(error),
+    };
+
+    if res.is_err() {
+        let _res = tokio::fs::remove_file(&partial).await;
+    }
+
+    res
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn expired(visitor: &Visitor, ttl: Duration) -> bool {
+    visitor.last_seen.saturating_add(ttl.as_secs()) < now()
+}
+
+/// Derives the seed for the decisions made about each peer address, so the same peer gets the
+/// same answers on every connection without us having to remember anything about them.
+pub struct PeerSeeds {
+    /// Secret used when `seed-secret` isn't configured, which only lasts until a restart.
+    generated: [u8; 16],
+}
+
+impl Default for PeerSeeds {
+    fn default() -> Self {
+        Self {
+            generated: fastrand::u128(..).to_le_bytes(),
+        }
+    }
+}
+
+impl PeerSeeds {
+    pub fn seed(&self, config: &Config, ip: IpAddr) -> u64 {
+        let mut hasher = Sha256::new();
+        match &config.seed_secret {
+            Some(secret) => hasher.update(secret.as_bytes()),
+            None => hasher.update(self.generated),
+        }
+        match ip {
+            IpAddr::V4(ip) => hasher.update(ip.octets()),
+            IpAddr::V6(ip) => hasher.update(ip.octets()),
+        }
+
+        let hash = hasher.finalize();
+        u64::from_le_bytes(hash[..8].try_into().unwrap())
+    }
+}
+
 #[derive(Hash, Clone, Debug, PartialEq, Eq)]
 struct UsernamePasswordTuple<'a> {
     pub username: Cow<'a, str>,
@@ -47,3 +830,196 @@ impl<'a> UsernamePasswordTuple<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use super::{now, PeerSeeds, RateLimiter, Visitor, Visitors};
+    use crate::{
+        audit::RateLimit,
+        config::{Config, RateLimitConfig},
+    };
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+    #[test]
+    fn peer_seeds_are_stable() {
+        let config = Config {
+            seed_secret: Some("hunter2".to_string()),
+            ..Config::default()
+        };
+        let other_peer = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 8));
+
+        let seeds = PeerSeeds::default();
+        assert_eq!(seeds.seed(&config, PEER), seeds.seed(&config, PEER));
+        assert_ne!(seeds.seed(&config, PEER), seeds.seed(&config, other_peer));
+
+        // a configured secret gives the same seeds across restarts
+        assert_eq!(
+            seeds.seed(&config, PEER),
+            PeerSeeds::default().seed(&config, PEER)
+        );
+        assert_ne!(
+            seeds.seed(&Config::default(), PEER),
+            PeerSeeds::default().seed(&Config::default(), PEER)
+        );
+    }
+
+    #[test]
+    fn limits_concurrent_connections() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig {
+            max_connections_per_ip: Some(2),
+            ..RateLimitConfig::default()
+        };
+        let now = Instant::now();
+
+        let first = limiter.admit_at(PEER, &config, now).unwrap();
+        let _second = limiter.admit_at(PEER, &config, now).unwrap();
+        assert_eq!(
+            limiter.admit_at(PEER, &config, now).err(),
+            Some(RateLimit::ConcurrentConnections)
+        );
+
+        // other peers have limits of their own
+        assert!(limiter
+            .admit_at(IpAddr::V4(Ipv4Addr::LOCALHOST), &config, now)
+            .is_ok());
+
+        drop(first);
+        assert!(limiter.admit_at(PEER, &config, now).is_ok());
+    }
+
+    #[test]
+    fn limits_connection_rate() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig {
+            connections_per_minute: Some(6.0),
+            burst: 2,
+            ..RateLimitConfig::default()
+        };
+        let now = Instant::now();
+
+        assert!(limiter.admit_at(PEER, &config, now).is_ok());
+        assert!(limiter.admit_at(PEER, &config, now).is_ok());
+        assert_eq!(
+            limiter.admit_at(PEER, &config, now).err(),
+            Some(RateLimit::ConnectionRate)
+        );
+
+        // a token is added every 10 seconds
+        let later = now + Duration::from_secs(10);
+        assert!(limiter.admit_at(PEER, &config, later).is_ok());
+        assert_eq!(
+            limiter.admit_at(PEER, &config, later).err(),
+            Some(RateLimit::ConnectionRate)
+        );
+    }
+
+    #[tokio::test]
+    async fn visitors_persist_across_restarts() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-visitors-{}", uuid::Uuid::new_v4()));
+        let ttl = Duration::from_secs(3600);
+
+        let visitors = Visitors::load(Some(directory.clone()), ttl, 10).unwrap();
+        visitors.store(
+            PEER,
+            Visitor {
+                accepted_passwords: vec![("root".to_string(), "hunter2".to_string())],
+                ..Visitor::default()
+            },
+        );
+
+        // the visitor is written out in the background
+        let mut reloaded = None;
+        for _ in 0..100 {
+            reloaded = Visitors::load(Some(directory.clone()), ttl, 10)
+                .unwrap()
+                .get(PEER);
+            if reloaded.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let visitor = reloaded.expect("visitor wasn't persisted");
+        assert!(visitor.accepted("root", "hunter2"));
+        assert!(!visitor.accepted("root", "password"));
+        assert!(visitor.last_seen > 0);
+
+        // nothing is left behind from writing it out
+        let files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|v| v.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, [format!("{PEER}.json").as_str()]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn visitors_expire() {
+        let directory =
+            std::env::temp_dir().join(format!("pisshoff-visitors-{}", uuid::Uuid::new_v4()));
+        let ttl = Duration::from_secs(3600);
+        let expired = Visitor {
+            last_seen: now() - 2 * ttl.as_secs(),
+            ..Visitor::default()
+        };
+
+        // expired visitors are dropped, and removed from disk, on startup
+        let path = directory.join(format!("{PEER}.json"));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(&path, serde_json::to_vec(&expired).unwrap()).unwrap();
+
+        let visitors = Visitors::load(Some(directory.clone()), ttl, 10).unwrap();
+        assert!(visitors.get(PEER).is_none());
+        assert!(!path.exists());
+
+        // and any that expire while we're running are forgotten once they're next looked up
+        visitors.visitors.write().insert(PEER, expired);
+        assert!(visitors.get(PEER).is_none());
+        assert!(visitors.visitors.read().is_empty());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn visitors_are_pruned_on_store() {
+        let ttl = Duration::from_secs(3600);
+        let visitors = Visitors::load(None, ttl, 2).unwrap();
+        let expired_peer = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+        let stale_peer = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 2));
+        let new_peer = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 3));
+
+        // expired visitors are forgotten without having to be looked up again
+        visitors.visitors.write().insert(
+            expired_peer,
+            Visitor {
+                last_seen: now() - 2 * ttl.as_secs(),
+                ..Visitor::default()
+            },
+        );
+        visitors.store(PEER, Visitor::default());
+        assert!(!visitors.visitors.read().contains_key(&expired_peer));
+
+        // and once full, those last seen the longest ago make way for new ones
+        visitors.visitors.write().insert(
+            stale_peer,
+            Visitor {
+                last_seen: now() - 60,
+                ..Visitor::default()
+            },
+        );
+        visitors.store(new_peer, Visitor::default());
+
+        let mut remaining = visitors.visitors.read().keys().copied().collect::<Vec<_>>();
+        remaining.sort_unstable();
+        assert_eq!(remaining, [new_peer, PEER]);
+    }
+}
@@ -5,6 +5,7 @@ use crate::server::ConnectionState;
 
 pub mod sftp;
 pub mod shell;
+pub mod sinkhole;
 
 #[async_trait]
 pub trait Subsystem {
@@ -1,4 +1,11 @@
-use std::{collections::HashMap, io::Write, mem::size_of, str::FromStr};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::Write,
+    mem::size_of,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -9,26 +16,64 @@ use nom::{
     number::complete::{be_u32, be_u64, be_u8},
     IResult,
 };
-use pisshoff_types::audit::{AuditLogAction, MkdirEvent, WriteFileEvent};
-use strum::FromRepr;
+use pisshoff_types::audit::{AuditLogAction, MkdirEvent, SftpRequestEvent, WriteFileEvent};
+use strum::{FromRepr, IntoStaticStr};
 use thrussh::{server::Session, ChannelId};
 use tracing::{debug, error, trace, warn};
 use uuid::Uuid;
 
-use crate::{server::ConnectionState, subsystem::Subsystem};
+use crate::{
+    audit::sha256_hex,
+    file_system::{LsError, Tree},
+    quarantine::{self, Origin},
+    server::ConnectionState,
+    subsystem::Subsystem,
+};
+
+/// The largest file we're willing to buffer in memory for a single handle.
+const MAX_FILE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The largest chunk we'll return from a single `SSH_FXP_READ`.
+const MAX_READ_LENGTH: usize = 256 * 1024;
+
+/// How far past the end of a file a write can start, as clients write files in order and
+/// anything further would have us zero-fill the gap.
+const MAX_WRITE_GAP: usize = 256 * 1024;
 
 // https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-13
 #[derive(Default, Clone, Debug)]
 pub struct Sftp {
-    open_files: HashMap<Uuid, String>,
+    version: u32,
+    handles: HashMap<Uuid, Handle>,
     pending_data: bytes::BytesMut,
 }
 
+#[derive(Clone, Debug)]
+enum Handle {
+    File {
+        path: PathBuf,
+        content: Vec<u8>,
+        modified: bool,
+        read: bool,
+    },
+    Directory {
+        path: PathBuf,
+        exhausted: bool,
+    },
+}
+
+impl Handle {
+    fn path(&self) -> &Path {
+        match self {
+            Self::File { path, .. } | Self::Directory { path, .. } => path,
+        }
+    }
+}
+
 #[async_trait]
 impl Subsystem for Sftp {
     const NAME: &'static str = "sftp";
 
-    #[allow(clippy::too_many_lines)]
     async fn data(
         &mut self,
         connection: &mut ConnectionState,
@@ -63,193 +108,525 @@ impl Subsystem for Sftp {
                 }
             };
 
-            match packet.typ {
-                PacketType::Init => {
-                    // the version the client sent us is in `request_id`, lets just echo it back
-                    // to them, bounded by the version of the rfc we developed this barebones
-                    // implementation against
-                    session.data(
-                        channel,
-                        WirePacket::new(PacketType::Version, packet.request_id.min(6), &[])
-                            .to_bytes()
-                            .into(),
-                    );
-                }
-                PacketType::Stat | PacketType::Lstat => {
-                    let (_data, stat) = StatPacket::parse(packet.data).unwrap();
-
-                    trace!("SFTP stat packet: {stat:?}");
-
-                    session.data(
-                        channel,
-                        StatusResponse {
-                            code: StatusCode::NoSuchFile,
-                            message: "No such file or directory",
-                        }
-                        .to_packet(packet.request_id)
-                        .into(),
-                    );
-                }
-                PacketType::Open => {
-                    let (_data, open) = OpenPacket::parse(packet.data).unwrap();
+            let response = self.handle_packet(connection, &packet);
+            session.data(channel, response.into());
+        }
 
-                    trace!("SFTP open packet: {open:?}");
+        session.channel_success(channel);
+        session.flush_pending(channel);
+    }
+}
 
-                    let uuid = Uuid::new_v4();
-                    self.open_files.insert(uuid, open.path.to_string());
+impl Sftp {
+    /// Handles a single packet from the client, returning the serialised response.
+    fn handle_packet(
+        &mut self,
+        connection: &mut ConnectionState,
+        packet: &WirePacket<'_>,
+    ) -> Vec<u8> {
+        let request_id = packet.request_id;
+
+        let res = match packet.typ {
+            PacketType::Init => {
+                // the version the client sent us is in `request_id`, lets just echo it back
+                // to them, bounded by the version of the rfc we developed this barebones
+                // implementation against
+                self.version = request_id.min(6);
+                return WirePacket::new(PacketType::Version, self.version, &[]).to_bytes();
+            }
+            PacketType::Open => self.open(connection, request_id, packet.data),
+            PacketType::Close => self.close(connection, request_id, packet.data),
+            PacketType::Read => self.read(connection, request_id, packet.data),
+            PacketType::Write => self.write(connection, request_id, packet.data),
+            PacketType::Stat | PacketType::Lstat => self.stat(connection, request_id, packet.data),
+            PacketType::Fstat => self.fstat(connection, request_id, packet.data),
+            PacketType::OpenDir => self.open_dir(connection, request_id, packet.data),
+            PacketType::ReadDir => self.read_dir(connection, request_id, packet.data),
+            PacketType::Remove => self.remove(connection, request_id, packet.data),
+            PacketType::Mkdir => self.mkdir(connection, request_id, packet.data),
+            PacketType::RealPath => self.real_path(connection, request_id, packet.data),
+            PacketType::FSetStat | PacketType::SetStat => {
+                self.set_stat(connection, request_id, packet.typ, packet.data)
+            }
+            typ => {
+                warn!("Unsupported SFTP packet {packet:?}");
+                audit(connection, typ, None);
+                Err(StatusCode::OpUnsupported)
+            }
+        };
 
-                    session.data(
-                        channel,
-                        HandleResponse(uuid).to_packet(packet.request_id).into(),
-                    );
-                }
-                PacketType::FSetStat | PacketType::SetStat => {
-                    let (_data, set_stat) = FSetStatPacket::parse(packet.data).unwrap();
-
-                    trace!("SFTP fsetstat packet: {set_stat:?}");
-
-                    session.data(
-                        channel,
-                        StatusResponse {
-                            code: StatusCode::Ok,
-                            message: "",
-                        }
-                        .to_packet(packet.request_id)
-                        .into(),
-                    );
-                }
-                PacketType::Write => {
-                    let (_data, write_packet) = WritePacket::parse(packet.data).unwrap();
-
-                    let path = self
-                        .open_files
-                        .get(&Uuid::from_str(write_packet.handle).unwrap())
-                        .unwrap();
-
-                    debug!(
-                        "Received write for {path} at offset {}: {:?}",
-                        write_packet.offset, write_packet.data
-                    );
-
-                    connection
-                        .audit_log()
-                        .push_action(AuditLogAction::WriteFile(WriteFileEvent {
-                            path: path.to_string().into_boxed_str(),
-                            content: Bytes::copy_from_slice(write_packet.data.as_bytes()),
-                        }));
-
-                    session.data(
-                        channel,
-                        StatusResponse {
-                            code: StatusCode::Ok,
-                            message: "",
-                        }
-                        .to_packet(packet.request_id)
-                        .into(),
-                    );
-                }
-                PacketType::Close => {
-                    let (_data, close_packet) = ClosePacket::parse(packet.data).unwrap();
-
-                    trace!("SFTP close packet: {close_packet:?}");
-
-                    self.open_files
-                        .remove(&Uuid::from_str(close_packet.handle).unwrap())
-                        .unwrap();
-
-                    session.data(
-                        channel,
-                        StatusResponse {
-                            code: StatusCode::Ok,
-                            message: "",
-                        }
-                        .to_packet(packet.request_id)
-                        .into(),
-                    );
-                }
-                PacketType::RealPath => {
-                    let (_data, real_path) = RealPathPacket::parse(packet.data).unwrap();
-
-                    trace!("SFTP realpath packet: {real_path:?}");
-
-                    #[allow(clippy::wildcard_in_or_patterns)]
-                    match real_path.control {
-                        // SSH_FXP_REALPATH_STAT_ALWAYS
-                        Some(2) => {
-                            session.data(
-                                channel,
-                                StatusResponse {
-                                    code: StatusCode::NoSuchFile,
-                                    message: "No such file or directory",
-                                }
-                                .to_packet(packet.request_id)
-                                .into(),
-                            );
-                        }
-                        // SSH_FXP_REALPATH_NO_CHECK | SSH_FXP_REALPATH_STAT_IF
-                        Some(0 | 1) | _ => {
-                            session.data(
-                                channel,
-                                NameResponse {
-                                    files: &[NameResponseFile {
-                                        name: real_path.path,
-                                        long_name: real_path.path,
-                                        attrs: FileAttrs {
-                                            typ: FileType::Unknown,
-                                        },
-                                    }],
-                                }
-                                .to_packet(packet.request_id)
-                                .into(),
-                            );
-                        }
-                    }
-                }
-                PacketType::Mkdir => {
-                    let (_data, mkdir) = MkdirPacket::parse(packet.data).unwrap();
-
-                    trace!("SFTP mkdir packet: {mkdir:?}");
-
-                    connection
-                        .audit_log()
-                        .push_action(AuditLogAction::Mkdir(MkdirEvent {
-                            path: mkdir.path.to_string().into_boxed_str(),
-                        }));
-
-                    session.data(
-                        channel,
-                        StatusResponse {
-                            code: StatusCode::Ok,
-                            message: "",
-                        }
-                        .to_packet(packet.request_id)
-                        .into(),
-                    );
-                }
-                _ => {
-                    // TODO: return SSH_FX_OP_UNSUPPORTED
-                    warn!("Unknown SFTP packet {packet:?}");
-                }
+        res.unwrap_or_else(|code| self.status(request_id, code))
+    }
+
+    fn status(&self, request_id: u32, code: StatusCode) -> Vec<u8> {
+        // status codes beyond `SSH_FX_OP_UNSUPPORTED` were introduced after version 3, so older
+        // clients won't know how to display them
+        let code = if self.version < 4 && code as u32 > StatusCode::OpUnsupported as u32 {
+            StatusCode::Failure
+        } else {
+            code
+        };
+
+        StatusResponse {
+            code,
+            message: code.message(),
+        }
+        .to_packet(request_id)
+    }
+
+    fn handle(&mut self, handle: &str) -> Result<&mut Handle, StatusCode> {
+        let uuid = Uuid::from_str(handle).map_err(|_| StatusCode::InvalidHandle)?;
+        self.handles.get_mut(&uuid).ok_or(StatusCode::InvalidHandle)
+    }
+
+    fn open(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, open) = OpenPacket::parse(data, self.version)?;
+
+        trace!("SFTP open packet: {open:?}");
+
+        let path = connection.file_system().canonicalize(Path::new(open.path));
+        audit(connection, PacketType::Open, Some(&path));
+
+        if self.handles.len() >= connection.config().sftp.max_open_handles {
+            return Err(StatusCode::Failure);
+        }
+
+        let (content, modified) = match connection.file_system().read(&path) {
+            Ok(_) if open.truncate => (Vec::new(), true),
+            Ok(content) => (content.to_vec(), false),
+            Err(LsError::NoSuchFileOrDirectory) if open.create => (Vec::new(), true),
+            Err(e) => return Err(e.into()),
+        };
+
+        let uuid = Uuid::new_v4();
+        self.handles.insert(
+            uuid,
+            Handle::File {
+                path,
+                content,
+                modified,
+                read: false,
+            },
+        );
+
+        Ok(HandleResponse(uuid).to_packet(request_id))
+    }
+
+    fn close(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, close_packet) = HandlePacket::parse(data)?;
+
+        trace!("SFTP close packet: {close_packet:?}");
+
+        let handle = self.handle(close_packet.handle)?.path().to_path_buf();
+        audit(connection, PacketType::Close, Some(&handle));
+
+        let uuid = Uuid::from_str(close_packet.handle).map_err(|_| StatusCode::InvalidHandle)?;
+
+        if let Some(Handle::File {
+            path,
+            content,
+            modified: true,
+            ..
+        }) = self.handles.remove(&uuid)
+        {
+            debug!(
+                "Client finished writing {} bytes to {}",
+                content.len(),
+                path.display()
+            );
+
+            // record the upload before attempting to write it, so we capture the payload even
+            // if it'd be rejected by the file system
+            let name = path.to_string_lossy().into_owned().into_boxed_str();
+            let sha256 = sha256_hex(&content);
+            let data = Bytes::copy_from_slice(&content);
+
+            let origin = Origin {
+                tool: "sftp",
+                name: Some(&name),
+                url: None,
+            };
+            quarantine::store(connection, &sha256, data.clone(), &origin);
+
+            connection.record_write(WriteFileEvent {
+                path: name,
+                sha256,
+                size: content.len(),
+                original_name: None,
+                mode: None,
+                content: data,
+            });
+
+            connection
+                .file_system()
+                .write(&path, content.into_boxed_slice())?;
+        }
+
+        Ok(self.status(request_id, StatusCode::Ok))
+    }
+
+    fn read(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, read_packet) = ReadPacket::parse(data)?;
+
+        trace!("SFTP read packet: {read_packet:?}");
+
+        let Handle::File {
+            path,
+            content,
+            read,
+            ..
+        } = self.handle(read_packet.handle)?
+        else {
+            return Err(StatusCode::FileIsADirectory);
+        };
+
+        // only record the first read of a handle to save spamming the log as the client
+        // requests each chunk
+        if !std::mem::replace(read, true) {
+            audit(connection, PacketType::Read, Some(path.as_path()));
+        }
+
+        let start = usize::try_from(read_packet.offset).unwrap_or(usize::MAX);
+        if start >= content.len() {
+            return Err(StatusCode::Eof);
+        }
+
+        let length = usize::try_from(read_packet.length)
+            .unwrap_or(usize::MAX)
+            .min(MAX_READ_LENGTH);
+        let end = start.saturating_add(length).min(content.len());
+
+        Ok(DataResponse(&content[start..end]).to_packet(request_id))
+    }
+
+    fn write(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, write_packet) = WritePacket::parse(data)?;
+
+        let Handle::File {
+            path,
+            content,
+            modified,
+            ..
+        } = self.handle(write_packet.handle)?
+        else {
+            return Err(StatusCode::FileIsADirectory);
+        };
+
+        debug!(
+            "Received write for {} at offset {}: {:?}",
+            path.display(),
+            write_packet.offset,
+            write_packet.data
+        );
+
+        let start = usize::try_from(write_packet.offset).unwrap_or(usize::MAX);
+        if start > content.len().saturating_add(MAX_WRITE_GAP) {
+            return Err(StatusCode::Failure);
+        }
+
+        let end = start
+            .checked_add(write_packet.data.len())
+            .filter(|end| *end <= MAX_FILE_SIZE)
+            .ok_or(StatusCode::NoSpaceOnFilesystem)?;
+
+        if content.len() < end {
+            let uploaded = *connection.sftp_uploaded() + (end - content.len());
+            if uploaded > connection.config().sftp.max_upload_bytes {
+                return Err(StatusCode::NoSpaceOnFilesystem);
             }
+
+            *connection.sftp_uploaded() = uploaded;
+            content.resize(end, 0);
         }
 
-        session.channel_success(channel);
-        session.flush_pending(channel);
+        content[start..end].copy_from_slice(write_packet.data);
+
+        // the full content is recorded once the client closes the handle
+        *modified = true;
+
+        Ok(self.status(request_id, StatusCode::Ok))
+    }
+
+    fn stat(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, stat) = StatPacket::parse(data)?;
+
+        trace!("SFTP stat packet: {stat:?}");
+
+        let path = connection.file_system().canonicalize(Path::new(stat.path));
+        audit(connection, PacketType::Stat, Some(&path));
+
+        let attrs = FileAttrs::from(connection.file_system().get(&path)?);
+
+        Ok(AttrsResponse {
+            attrs,
+            version: self.version,
+        }
+        .to_packet(request_id))
+    }
+
+    fn fstat(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, fstat) = HandlePacket::parse(data)?;
+
+        trace!("SFTP fstat packet: {fstat:?}");
+
+        let version = self.version;
+        let handle = self.handle(fstat.handle)?;
+        audit(connection, PacketType::Fstat, Some(handle.path()));
+
+        let attrs = match handle {
+            // the file might not have been flushed to the file system yet, so we'll read
+            // directly from the handle
+            Handle::File { content, .. } => FileAttrs::file(content.len()),
+            Handle::Directory { .. } => FileAttrs::directory(),
+        };
+
+        Ok(AttrsResponse { attrs, version }.to_packet(request_id))
+    }
+
+    fn open_dir(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, open_dir) = PathPacket::parse(data)?;
+
+        trace!("SFTP opendir packet: {open_dir:?}");
+
+        let path = connection
+            .file_system()
+            .canonicalize(Path::new(open_dir.path));
+        audit(connection, PacketType::OpenDir, Some(&path));
+
+        if self.handles.len() >= connection.config().sftp.max_open_handles {
+            return Err(StatusCode::Failure);
+        }
+
+        if !matches!(connection.file_system().get(&path)?, Tree::Directory(_)) {
+            return Err(StatusCode::NotADirectory);
+        }
+
+        let uuid = Uuid::new_v4();
+        self.handles.insert(
+            uuid,
+            Handle::Directory {
+                path,
+                exhausted: false,
+            },
+        );
+
+        Ok(HandleResponse(uuid).to_packet(request_id))
+    }
+
+    fn read_dir(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, read_dir) = HandlePacket::parse(data)?;
+
+        trace!("SFTP readdir packet: {read_dir:?}");
+
+        let version = self.version;
+        let Handle::Directory { path, exhausted } = self.handle(read_dir.handle)? else {
+            return Err(StatusCode::NotADirectory);
+        };
+
+        // we return every entry in one go, so the next request for the handle is told there's
+        // nothing left
+        if std::mem::replace(exhausted, true) {
+            return Err(StatusCode::Eof);
+        }
+
+        let path = path.clone();
+        audit(connection, PacketType::ReadDir, Some(&path));
+
+        let owner = connection.username().to_string();
+        let Tree::Directory(entries) = connection.file_system().get(&path)? else {
+            return Err(StatusCode::NotADirectory);
+        };
+
+        let files = [
+            (".", FileAttrs::directory()),
+            ("..", FileAttrs::directory()),
+        ]
+        .into_iter()
+        .chain(
+            entries
+                .iter()
+                .map(|(name, tree)| (name.as_str(), FileAttrs::from(tree.as_ref()))),
+        )
+        .map(|(name, attrs)| NameResponseFile {
+            long_name: attrs.long_name(name, &owner),
+            name: name.to_string(),
+            attrs,
+        })
+        .collect();
+
+        Ok(NameResponse { files, version }.to_packet(request_id))
+    }
+
+    fn remove(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, remove) = PathPacket::parse(data)?;
+
+        trace!("SFTP remove packet: {remove:?}");
+
+        let path = connection
+            .file_system()
+            .canonicalize(Path::new(remove.path));
+        audit(connection, PacketType::Remove, Some(&path));
+
+        connection.file_system().remove(&path)?;
+
+        Ok(self.status(request_id, StatusCode::Ok))
+    }
+
+    fn mkdir(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, mkdir) = PathPacket::parse(data)?;
+
+        trace!("SFTP mkdir packet: {mkdir:?}");
+
+        let path = connection.file_system().canonicalize(Path::new(mkdir.path));
+        connection
+            .audit_log()
+            .push_action(AuditLogAction::Mkdir(MkdirEvent {
+                path: path.to_string_lossy().into(),
+            }));
+
+        connection.file_system().mkdirall(&path)?;
+
+        Ok(self.status(request_id, StatusCode::Ok))
+    }
+
+    fn real_path(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, real_path) = RealPathPacket::parse(data)?;
+
+        trace!("SFTP realpath packet: {real_path:?}");
+
+        let path = connection
+            .file_system()
+            .canonicalize(Path::new(real_path.path));
+        audit(connection, PacketType::RealPath, Some(&path));
+
+        #[allow(clippy::wildcard_in_or_patterns)]
+        let attrs = match real_path.control {
+            // SSH_FXP_REALPATH_STAT_ALWAYS
+            Some(2) => FileAttrs::from(connection.file_system().get(&path)?),
+            // SSH_FXP_REALPATH_NO_CHECK | SSH_FXP_REALPATH_STAT_IF
+            Some(0 | 1) | _ => FileAttrs::unknown(),
+        };
+
+        let name = path.to_string_lossy().into_owned();
+
+        Ok(NameResponse {
+            files: vec![NameResponseFile {
+                long_name: name.clone(),
+                name,
+                attrs,
+            }],
+            version: self.version,
+        }
+        .to_packet(request_id))
+    }
+
+    fn set_stat(
+        &mut self,
+        connection: &mut ConnectionState,
+        request_id: u32,
+        typ: PacketType,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StatusCode> {
+        let (_data, set_stat) = PathPacket::parse(data)?;
+
+        trace!("SFTP setstat packet: {set_stat:?}");
+
+        let path = if matches!(typ, PacketType::FSetStat) {
+            self.handle(set_stat.path)?.path().to_path_buf()
+        } else {
+            connection
+                .file_system()
+                .canonicalize(Path::new(set_stat.path))
+        };
+
+        audit(connection, typ, Some(&path));
+
+        Ok(self.status(request_id, StatusCode::Ok))
     }
 }
 
+/// Records an SFTP operation against the connection's audit log.
+fn audit(connection: &mut ConnectionState, typ: PacketType, path: Option<&Path>) {
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::SftpRequest(SftpRequestEvent {
+            operation: Cow::Borrowed(typ.into()),
+            path: path.map(|v| v.to_string_lossy().into_owned().into_boxed_str()),
+        }));
+}
+
 fn take_length_delimited_string(rest: &[u8]) -> IResult<&[u8], &str> {
+    map_res(take_length_delimited_bytes, std::str::from_utf8)(rest)
+}
+
+fn take_length_delimited_bytes(rest: &[u8]) -> IResult<&[u8], &[u8]> {
     let (rest, length) = be_u32(rest)?;
-    map_res(take(length), std::str::from_utf8)(rest)
+    take(length)(rest)
 }
 
+/// Any packet consisting of just a path (or a path followed by attributes we don't care about),
+/// ie. `SSH_FXP_MKDIR`, `SSH_FXP_REMOVE`, `SSH_FXP_OPENDIR` and `SSH_FXP_SETSTAT`.
 #[derive(Debug)]
-struct MkdirPacket<'a> {
+struct PathPacket<'a> {
     path: &'a str,
-    // TODO: fileattrs
 }
 
-impl<'a> MkdirPacket<'a> {
+impl<'a> PathPacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
         let (rest, path) = take_length_delimited_string(rest)?;
 
@@ -272,18 +649,42 @@ impl<'a> RealPathPacket<'a> {
     }
 }
 
+#[derive(Debug)]
+struct ReadPacket<'a> {
+    handle: &'a str,
+    offset: u64,
+    length: u32,
+}
+
+impl<'a> ReadPacket<'a> {
+    fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
+        let (rest, handle) = take_length_delimited_string(rest)?;
+        let (rest, offset) = be_u64(rest)?;
+        let (rest, length) = be_u32(rest)?;
+
+        Ok((
+            rest,
+            Self {
+                handle,
+                offset,
+                length,
+            },
+        ))
+    }
+}
+
 #[derive(Debug)]
 struct WritePacket<'a> {
     handle: &'a str,
     offset: u64,
-    data: &'a str,
+    data: &'a [u8],
 }
 
 impl<'a> WritePacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
         let (rest, handle) = take_length_delimited_string(rest)?;
         let (rest, offset) = be_u64(rest)?;
-        let (rest, data) = take_length_delimited_string(rest)?;
+        let (rest, data) = take_length_delimited_bytes(rest)?;
 
         Ok((
             rest,
@@ -296,12 +697,14 @@ impl<'a> WritePacket<'a> {
     }
 }
 
+/// Any packet consisting of just a handle, ie. `SSH_FXP_CLOSE`, `SSH_FXP_FSTAT` and
+/// `SSH_FXP_READDIR`.
 #[derive(Debug)]
-struct ClosePacket<'a> {
+struct HandlePacket<'a> {
     handle: &'a str,
 }
 
-impl<'a> ClosePacket<'a> {
+impl<'a> HandlePacket<'a> {
     fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
         let (rest, handle) = take_length_delimited_string(rest)?;
 
@@ -310,41 +713,47 @@ impl<'a> ClosePacket<'a> {
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 struct OpenPacket<'a> {
     path: &'a str,
-    desired_access: u32,
-    flags: u32,
+    create: bool,
+    truncate: bool,
 }
 
 impl<'a> OpenPacket<'a> {
-    fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
+    fn parse(rest: &'a [u8], version: u32) -> IResult<&'a [u8], Self> {
         let (rest, path) = take_length_delimited_string(rest)?;
-        let (rest, desired_access) = be_u32(rest)?;
-        let (rest, flags) = be_u32(rest)?;
-
-        Ok((
-            rest,
-            Self {
-                path,
-                desired_access,
-                flags,
-            },
-        ))
-    }
-}
 
-#[derive(Debug)]
-#[allow(dead_code)]
-struct FSetStatPacket<'a> {
-    handle: &'a str,
-}
+        if version >= 5 {
+            let (rest, _desired_access) = be_u32(rest)?;
+            let (rest, flags) = be_u32(rest)?;
 
-impl<'a> FSetStatPacket<'a> {
-    fn parse(rest: &'a [u8]) -> IResult<&'a [u8], Self> {
-        let (rest, handle) = take_length_delimited_string(rest)?;
+            // SSH_FXF_ACCESS_DISPOSITION
+            let disposition = flags & 0x0000_0007;
 
-        Ok((rest, Self { handle }))
+            Ok((
+                rest,
+                Self {
+                    path,
+                    // SSH_FXF_CREATE_NEW | SSH_FXF_CREATE_TRUNCATE | SSH_FXF_OPEN_OR_CREATE
+                    create: matches!(disposition, 0 | 1 | 3),
+                    // SSH_FXF_CREATE_TRUNCATE | SSH_FXF_TRUNCATE_EXISTING
+                    truncate: matches!(disposition, 1 | 4),
+                },
+            ))
+        } else {
+            let (rest, pflags) = be_u32(rest)?;
+
+            Ok((
+                rest,
+                Self {
+                    path,
+                    // SSH_FXF_CREAT
+                    create: pflags & 0x0000_0008 != 0,
+                    // SSH_FXF_TRUNC
+                    truncate: pflags & 0x0000_0010 != 0,
+                },
+            ))
+        }
     }
 }
 
@@ -404,7 +813,7 @@ impl<'a> WirePacket<'a> {
         let (rest, typ) = be_u8(rest)?;
         let (rest, request_id) = be_u32(rest)?;
         let (rest, data) = take(
-            length - u32::try_from(size_of::<u8>() + size_of::<u32>()).unwrap_or(u32::MAX),
+            length.saturating_sub(u32::try_from(size_of::<u8>() + size_of::<u32>()).unwrap()),
         )(rest)?;
 
         let Some(typ) = PacketType::from_repr(typ) else {
@@ -426,8 +835,9 @@ impl<'a> WirePacket<'a> {
     }
 }
 
-#[derive(Copy, Clone, Debug, FromRepr)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromRepr, IntoStaticStr)]
 #[repr(u8)]
+#[strum(serialize_all = "kebab-case")]
 pub enum PacketType {
     Init = 1,
     Version = 2,
@@ -448,6 +858,7 @@ pub enum PacketType {
     Stat = 17,
     Rename = 18,
     ReadLink = 19,
+    Symlink = 20,
     Link = 21,
     Block = 22,
     Unblock = 23,
@@ -463,21 +874,18 @@ pub enum PacketType {
 pub struct StatusResponse<'a> {
     code: StatusCode,
     message: &'a str,
-    // language_tag: &'a str,
 }
 
 impl Response for StatusResponse<'_> {
     const TYPE: PacketType = PacketType::Status;
 
     fn to_bytes(&self) -> Vec<u8> {
-        let mut out = Vec::with_capacity(size_of::<u32>() + size_of::<u32>() + self.message.len());
+        let mut out =
+            Vec::with_capacity(size_of::<u32>() + size_of::<u32>() + self.message.len() + 4);
         out.extend_from_slice(&(self.code as u32).to_be_bytes());
-        out.extend_from_slice(
-            &u32::try_from(self.message.len())
-                .unwrap_or(u32::MAX)
-                .to_be_bytes(),
-        );
-        out.extend_from_slice(self.message.as_bytes());
+        put_string(&mut out, self.message.as_bytes());
+        // language tag
+        put_string(&mut out, b"");
         out
     }
 }
@@ -495,15 +903,40 @@ impl Response for HandleResponse {
     }
 }
 
-pub struct NameResponse<'a> {
-    files: &'a [NameResponseFile<'a>],
+pub struct DataResponse<'a>(&'a [u8]);
+
+impl Response for DataResponse<'_> {
+    const TYPE: PacketType = PacketType::Data;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(size_of::<u32>() + self.0.len());
+        put_string(&mut out, self.0);
+        out
+    }
+}
+
+pub struct AttrsResponse {
+    attrs: FileAttrs,
+    version: u32,
+}
+
+impl Response for AttrsResponse {
+    const TYPE: PacketType = PacketType::Attrs;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.attrs.to_bytes(self.version)
+    }
+}
+
+pub struct NameResponse {
+    files: Vec<NameResponseFile>,
+    version: u32,
 }
 
-impl Response for NameResponse<'_> {
+impl Response for NameResponse {
     const TYPE: PacketType = PacketType::Name;
 
     fn to_bytes(&self) -> Vec<u8> {
-        // TODO: include nameresponsefile size
         let mut out = Vec::with_capacity(size_of::<u32>());
         out.extend_from_slice(
             &u32::try_from(self.files.len())
@@ -511,45 +944,47 @@ impl Response for NameResponse<'_> {
                 .to_be_bytes(),
         );
 
-        for file in self.files {
-            out.extend_from_slice(&file.to_bytes());
+        for file in &self.files {
+            out.extend_from_slice(&file.to_bytes(self.version));
         }
 
-        out.push(1);
+        // end-of-list, only understood by version 6 clients
+        if self.version >= 6 {
+            out.push(1);
+        }
 
         out
     }
 }
 
-pub struct NameResponseFile<'a> {
-    name: &'a str,
-    long_name: &'a str,
+pub struct NameResponseFile {
+    name: String,
+    long_name: String,
     attrs: FileAttrs,
 }
 
-impl NameResponseFile<'_> {
-    fn to_bytes(&self) -> Vec<u8> {
-        // TODO: include FileAttrs size
+impl NameResponseFile {
+    fn to_bytes(&self, version: u32) -> Vec<u8> {
         let mut out = Vec::with_capacity(
             size_of::<u32>() + self.name.len() + size_of::<u32>() + self.long_name.len(),
         );
-        out.extend_from_slice(
-            &u32::try_from(self.name.len())
-                .unwrap_or(u32::MAX)
-                .to_be_bytes(),
-        );
-        out.extend_from_slice(self.name.as_bytes());
-        out.extend_from_slice(
-            &u32::try_from(self.long_name.len())
-                .unwrap_or(u32::MAX)
-                .to_be_bytes(),
-        );
-        out.extend_from_slice(self.long_name.as_bytes());
-        out.extend_from_slice(&self.attrs.to_bytes());
+        put_string(&mut out, self.name.as_bytes());
+
+        // the long name was dropped from the protocol after version 3
+        if version < 4 {
+            put_string(&mut out, self.long_name.as_bytes());
+        }
+
+        out.extend_from_slice(&self.attrs.to_bytes(version));
         out
     }
 }
 
+fn put_string(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend_from_slice(&u32::try_from(value.len()).unwrap_or(u32::MAX).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(u8)]
 #[allow(dead_code)]
@@ -565,20 +1000,95 @@ enum FileType {
     Fifo = 9,
 }
 
+const SSH_FILEXFER_ATTR_SIZE: u32 = 0x0000_0001;
+const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x0000_0004;
+
 #[derive(Copy, Clone, Debug)]
 struct FileAttrs {
     typ: FileType,
+    size: Option<u64>,
+    permissions: Option<u32>,
 }
 
 impl FileAttrs {
-    fn to_bytes(self) -> Vec<u8> {
-        let mut out = Vec::with_capacity(size_of::<u32>() + size_of::<u8>());
-        out.extend_from_slice(&0_u32.to_be_bytes());
-        out.push(self.typ as u8);
+    fn file(size: usize) -> Self {
+        Self {
+            typ: FileType::Regular,
+            size: Some(u64::try_from(size).unwrap_or(u64::MAX)),
+            permissions: Some(0o100_644),
+        }
+    }
+
+    fn directory() -> Self {
+        Self {
+            typ: FileType::Directory,
+            size: Some(4096),
+            permissions: Some(0o040_755),
+        }
+    }
+
+    fn unknown() -> Self {
+        Self {
+            typ: FileType::Unknown,
+            size: None,
+            permissions: None,
+        }
+    }
+
+    /// Renders the entry in the same format as `ls -l`, which is what version 3 clients display
+    /// to the user.
+    fn long_name(&self, name: &str, owner: &str) -> String {
+        let mode = match self.typ {
+            FileType::Directory => "drwxr-xr-x",
+            _ => "-rw-r--r--",
+        };
+
+        format!(
+            "{mode}    1 {owner:<8} {owner:<8} {:>8} Jan  1 00:00 {name}",
+            self.size.unwrap_or(0)
+        )
+    }
+
+    fn to_bytes(self, version: u32) -> Vec<u8> {
+        let mut flags = 0;
+
+        if self.size.is_some() {
+            flags |= SSH_FILEXFER_ATTR_SIZE;
+        }
+
+        if self.permissions.is_some() {
+            flags |= SSH_FILEXFER_ATTR_PERMISSIONS;
+        }
+
+        let mut out = Vec::with_capacity(size_of::<u32>() * 2 + size_of::<u8>() + size_of::<u64>());
+        out.extend_from_slice(&flags.to_be_bytes());
+
+        // the type was only added to the attributes after version 3
+        if version >= 4 {
+            out.push(self.typ as u8);
+        }
+
+        if let Some(size) = self.size {
+            out.extend_from_slice(&size.to_be_bytes());
+        }
+
+        if let Some(permissions) = self.permissions {
+            out.extend_from_slice(&permissions.to_be_bytes());
+        }
+
         out
     }
 }
 
+impl From<&Tree> for FileAttrs {
+    fn from(value: &Tree) -> Self {
+        match value {
+            Tree::Directory(_) => Self::directory(),
+            Tree::File(content) => Self::file(content.len()),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(u32)]
 #[allow(dead_code)]
@@ -617,6 +1127,44 @@ enum StatusCode {
     NoMatchingByteRangeLock = 31,
 }
 
+impl StatusCode {
+    fn message(self) -> &'static str {
+        match self {
+            Self::Ok => "Success",
+            Self::Eof => "End of file",
+            Self::NoSuchFile => "No such file or directory",
+            Self::PermissionDenied => "Permission denied",
+            Self::BadMessage => "Bad message",
+            Self::OpUnsupported => "Operation unsupported",
+            Self::InvalidHandle => "Invalid handle",
+            Self::FileAlreadyExists => "File exists",
+            Self::NoSpaceOnFilesystem => "No space left on device",
+            Self::NotADirectory => "Not a directory",
+            Self::FileIsADirectory => "Is a directory",
+            _ => "Failure",
+        }
+    }
+}
+
+impl From<LsError> for StatusCode {
+    fn from(value: LsError) -> Self {
+        match value {
+            LsError::NotDirectory => Self::NotADirectory,
+            LsError::NoSuchFileOrDirectory => Self::NoSuchFile,
+            LsError::IsADirectory => Self::FileIsADirectory,
+            LsError::FileExists => Self::FileAlreadyExists,
+            LsError::OperationNotPermitted => Self::PermissionDenied,
+            LsError::InvalidArgument => Self::Failure,
+        }
+    }
+}
+
+impl<E> From<nom::Err<E>> for StatusCode {
+    fn from(_value: nom::Err<E>) -> Self {
+        Self::BadMessage
+    }
+}
+
 trait Response {
     const TYPE: PacketType;
 
@@ -626,3 +1174,210 @@ trait Response {
         WirePacket::new(Self::TYPE, request_id, &self.to_bytes()).to_bytes()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::AuditLogAction;
+
+    use crate::{
+        server::ConnectionState,
+        subsystem::sftp::{put_string, PacketType, Sftp, WirePacket},
+    };
+
+    /// Sends a single request to the subsystem, returning the type and body of the response.
+    fn request(
+        sftp: &mut Sftp,
+        state: &mut ConnectionState,
+        typ: PacketType,
+        data: &[u8],
+    ) -> (PacketType, Vec<u8>) {
+        // request id doubles as the version for init packets
+        let bytes = WirePacket::new(typ, 3, data).to_bytes();
+        let (_, packet) = WirePacket::parse(&bytes).unwrap();
+
+        let response = sftp.handle_packet(state, &packet);
+        let (rest, response) = WirePacket::parse(&response).unwrap();
+        assert!(rest.is_empty());
+
+        (response.typ, response.data.to_vec())
+    }
+
+    fn string(value: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_string(&mut out, value);
+        out
+    }
+
+    fn status(body: &[u8]) -> u32 {
+        u32::from_be_bytes(body[..4].try_into().unwrap())
+    }
+
+    fn open(sftp: &mut Sftp, state: &mut ConnectionState, path: &[u8], pflags: u32) -> Vec<u8> {
+        let mut body = string(path);
+        body.extend_from_slice(&pflags.to_be_bytes());
+        body.extend_from_slice(&0_u32.to_be_bytes());
+
+        let (typ, handle) = request(sftp, state, PacketType::Open, &body);
+        assert_eq!(typ, PacketType::Handle);
+
+        handle[4..].to_vec()
+    }
+
+    fn upload(sftp: &mut Sftp, state: &mut ConnectionState, path: &[u8], content: &[u8]) {
+        // SSH_FXF_WRITE | SSH_FXF_CREAT | SSH_FXF_TRUNC
+        let handle = open(sftp, state, path, 0x1a);
+
+        let mut body = string(&handle);
+        body.extend_from_slice(&0_u64.to_be_bytes());
+        body.extend_from_slice(&string(content));
+        let (typ, response) = request(sftp, state, PacketType::Write, &body);
+        assert_eq!((typ, status(&response)), (PacketType::Status, 0));
+
+        let (typ, response) = request(sftp, state, PacketType::Close, &string(&handle));
+        assert_eq!((typ, status(&response)), (PacketType::Status, 0));
+    }
+
+    #[test]
+    fn upload_and_download() {
+        let mut sftp = Sftp::default();
+        let mut state = ConnectionState::mock();
+
+        let (typ, _) = request(&mut sftp, &mut state, PacketType::Init, &[]);
+        assert_eq!(typ, PacketType::Version);
+
+        upload(&mut sftp, &mut state, b"test.txt", b"hello world");
+
+        let written = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|event| match &event.action {
+                AuditLogAction::WriteFile(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*written.path, "/root/test.txt");
+        assert_eq!(written.content.as_ref(), b"hello world");
+        assert_eq!(
+            &*written.sha256,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+
+        // SSH_FXF_READ
+        let handle = open(&mut sftp, &mut state, b"/root/test.txt", 0x01);
+
+        let mut body = string(&handle);
+        body.extend_from_slice(&0_u64.to_be_bytes());
+        body.extend_from_slice(&1024_u32.to_be_bytes());
+        let (typ, response) = request(&mut sftp, &mut state, PacketType::Read, &body);
+        assert_eq!(typ, PacketType::Data);
+        assert_eq!(&response[4..], b"hello world");
+
+        let mut body = string(&handle);
+        body.extend_from_slice(&11_u64.to_be_bytes());
+        body.extend_from_slice(&1024_u32.to_be_bytes());
+        let (typ, response) = request(&mut sftp, &mut state, PacketType::Read, &body);
+        assert_eq!((typ, status(&response)), (PacketType::Status, 1));
+    }
+
+    #[test]
+    fn rejects_sparse_writes() {
+        let mut sftp = Sftp::default();
+        let mut state = ConnectionState::mock();
+
+        request(&mut sftp, &mut state, PacketType::Init, &[]);
+
+        // SSH_FXF_WRITE | SSH_FXF_CREAT | SSH_FXF_TRUNC
+        let handle = open(&mut sftp, &mut state, b"sparse", 0x1a);
+
+        let mut body = string(&handle);
+        body.extend_from_slice(&(64 * 1024 * 1024 - 1_u64).to_be_bytes());
+        body.extend_from_slice(&string(b"x"));
+        let (typ, response) = request(&mut sftp, &mut state, PacketType::Write, &body);
+        assert_eq!((typ, status(&response)), (PacketType::Status, 4));
+        assert_eq!(*state.sftp_uploaded(), 0);
+    }
+
+    #[test]
+    fn limits_open_handles() {
+        let mut sftp = Sftp::default();
+        let mut state = ConnectionState::mock();
+
+        request(&mut sftp, &mut state, PacketType::Init, &[]);
+
+        for i in 0..32 {
+            open(&mut sftp, &mut state, format!("{i}").as_bytes(), 0x1a);
+        }
+
+        let mut body = string(b"one-too-many");
+        body.extend_from_slice(&0x1a_u32.to_be_bytes());
+        body.extend_from_slice(&0_u32.to_be_bytes());
+        let (typ, response) = request(&mut sftp, &mut state, PacketType::Open, &body);
+        assert_eq!((typ, status(&response)), (PacketType::Status, 4));
+    }
+
+    #[test]
+    fn mkdir_logs_canonical_path() {
+        let mut sftp = Sftp::default();
+        let mut state = ConnectionState::mock();
+
+        request(&mut sftp, &mut state, PacketType::Init, &[]);
+
+        let (typ, response) = request(
+            &mut sftp,
+            &mut state,
+            PacketType::Mkdir,
+            &string(b"./.cache/../.x"),
+        );
+        assert_eq!((typ, status(&response)), (PacketType::Status, 0));
+
+        let created = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|event| match &event.action {
+                AuditLogAction::Mkdir(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*created.path, "/root/.x");
+    }
+
+    #[test]
+    fn read_dir_and_remove() {
+        let mut sftp = Sftp::default();
+        let mut state = ConnectionState::mock();
+
+        request(&mut sftp, &mut state, PacketType::Init, &[]);
+        upload(&mut sftp, &mut state, b"dropper.sh", b"#!/bin/sh");
+
+        let (typ, handle) = request(&mut sftp, &mut state, PacketType::OpenDir, &string(b"."));
+        assert_eq!(typ, PacketType::Handle);
+        let handle = string(&handle[4..]);
+
+        let (typ, response) = request(&mut sftp, &mut state, PacketType::ReadDir, &handle);
+        assert_eq!(typ, PacketType::Name);
+        // `.`, `..` and our file
+        assert_eq!(status(&response), 3);
+        assert!(String::from_utf8_lossy(&response).contains("dropper.sh"));
+
+        let (typ, response) = request(&mut sftp, &mut state, PacketType::ReadDir, &handle);
+        assert_eq!((typ, status(&response)), (PacketType::Status, 1));
+
+        let (typ, response) = request(
+            &mut sftp,
+            &mut state,
+            PacketType::Remove,
+            &string(b"dropper.sh"),
+        );
+        assert_eq!((typ, status(&response)), (PacketType::Status, 0));
+
+        let (typ, response) = request(
+            &mut sftp,
+            &mut state,
+            PacketType::Stat,
+            &string(b"dropper.sh"),
+        );
+        assert_eq!((typ, status(&response)), (PacketType::Status, 2));
+    }
+}
@@ -1,20 +1,40 @@
 mod parser;
+mod payload;
+
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    convert::Infallible,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 use async_trait::async_trait;
-use pisshoff_types::audit::{AuditLogAction, ExecCommandEvent};
-use thrussh::{server::Session, ChannelId};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use pisshoff_types::audit::{AuditLogAction, ExecCommandEvent, WriteFileEvent};
+use thrussh::{server::Session, ChannelId, CryptoVec};
+use time::OffsetDateTime;
 use tracing::info;
 
 use crate::{
-    command::{CommandResult, ConcreteCommand},
-    server::{ConnectionState, EitherSession, StdoutCaptureSession},
+    audit::sha256_hex,
+    command::{self, CommandResult, ConcreteCommand},
+    persona::motd,
+    server::{ConnectionState, EitherSession, StdoutCaptureSession, ThrusshSession},
     subsystem::{
-        shell::parser::{tokenize, IterState, ParsedPart},
+        shell::{
+            parser::{parse_command_list, Connector, IterState, ParsedPart, Pipeline},
+            payload::Role,
+        },
         Subsystem,
     },
+    terminal::{Input, Keystrokes, LineBuffer, Pty, ShellSession, Terminal, TerminalSession},
 };
 
-pub const SHELL_PROMPT: &str = "bash-5.1$ ";
+/// How deep scripts can be run within one another, as a script that runs itself otherwise
+/// recurses until the stack overflows.
+const MAX_SCRIPT_DEPTH: u32 = 64;
 
 type IResult<I, O> = nom::IResult<I, O, nom_supreme::error::ErrorTree<I>>;
 
@@ -22,81 +42,154 @@ type IResult<I, O> = nom::IResult<I, O, nom_supreme::error::ErrorTree<I>>;
 pub struct Shell {
     interactive: bool,
     state: State,
+    /// Line discipline for the session, if the client requested a PTY.
+    terminal: Option<Terminal>,
+    /// Input towards the next command, for sessions without a PTY.
+    buffer: LineBuffer,
+    /// Timing of the input towards the next line, if it's being logged.
+    keystrokes: Option<Keystrokes>,
+    /// Whether the shell has exited and closed the channel.
+    closed: bool,
 }
 
 impl Shell {
-    pub fn new(interactive: bool, channel: ChannelId, session: &mut Session) -> Self {
+    pub fn new(
+        interactive: bool,
+        pty: Option<&Pty>,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut dyn ShellSession,
+    ) -> Self {
+        if interactive && pty.is_some() {
+            connection.set_tty("pts/0");
+        }
+
+        populate_environment(connection, pty);
+
         if interactive {
-            session.data(channel, SHELL_PROMPT.to_string().into());
+            // bash reads its history in when it starts, and only writes it back out on exit
+            let path = connection.file_system().home().join(".bash_history");
+            let history = connection.file_system().read(&path).map(|v| {
+                String::from_utf8_lossy(v)
+                    .lines()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            });
+            *connection.history() = history.unwrap_or_default();
+
+            if let Some(handle) = session.handle() {
+                connection.takeover().attach(
+                    handle,
+                    channel,
+                    pty.is_some(),
+                    connection.recording(),
+                    connection.tap(),
+                );
+            }
+
+            let mut session =
+                TerminalSession::new(session, false, connection.recording(), connection.tap());
+            let motd = render_motd(connection);
+
+            if !motd.is_empty() {
+                session.data(channel, motd.into());
+            }
+
+            session.data(channel, prompt(connection).into());
         }
 
         Self {
             interactive,
             state: State::Prompt,
+            terminal: pty.map(Terminal::new),
+            buffer: LineBuffer::default(),
+            keystrokes: (pty.is_some() && connection.config().keystroke_timing)
+                .then(|| Keystrokes::new(Instant::now())),
+            closed: false,
         }
     }
 
-    fn handle_command_result(
-        &self,
-        command_result: CommandResult<ExecutingCommand>,
-    ) -> (State, bool) {
+    /// Runs a command sent to us via an `exec` request, bypassing the line discipline.
+    pub async fn exec(
+        &mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        command: &[u8],
+        session: &mut dyn ShellSession,
+    ) {
+        let mut session = TerminalSession::new(
+            session,
+            self.terminal.is_some(),
+            connection.recording(),
+            connection.tap(),
+        );
+        self.execute(connection, channel, command, &mut session)
+            .await;
+    }
+
+    fn handle_command_result(&self, command_result: CommandResult<ExecutingList>) -> (State, bool) {
         match (command_result, self.interactive) {
             (CommandResult::ReadStdin(cmd), _) => (State::Running(cmd), true),
-            (CommandResult::Exit(exit_status), true) => (State::Exit(exit_status), false),
+            // interactive shells only report an exit status once they exit themselves
+            (CommandResult::Exit(_), true) => (State::Prompt, true),
             (CommandResult::Exit(exit_status), false) | (CommandResult::Close(exit_status), _) => {
                 (State::Quit(exit_status), false)
             }
         }
     }
-}
-
-#[async_trait]
-impl Subsystem for Shell {
-    const NAME: &'static str = "shell";
 
-    async fn data(
+    /// Executes a line of input, returning `false` if the channel has been closed.
+    async fn execute(
         &mut self,
         connection: &mut ConnectionState,
         channel: ChannelId,
         data: &[u8],
-        session: &mut Session,
-    ) {
+        session: &mut TerminalSession<'_>,
+    ) -> bool {
         loop {
             let (next, end) = match std::mem::take(&mut self.state) {
                 State::Prompt => {
-                    connection
-                        .audit_log()
-                        .push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
-                            args: Box::from(vec![String::from_utf8_lossy(data).to_string()]),
-                        }));
-
-                    match tokenize(data) {
-                        Ok((_unparsed, args)) => {
-                            let cmd = parser::Iter::new(
-                                args.into_iter().map(ParsedPart::into_owned).collect(),
-                            );
+                    if self.interactive {
+                        remember(connection, data);
+                    }
+
+                    match parse_command_list(data) {
+                        Ok((rest, list)) if rest.iter().all(u8::is_ascii_whitespace) => {
+                            let pipelines = prepare_pipelines(connection, list);
                             self.handle_command_result(
-                                ExecutingCommand::new(cmd, connection, channel, session).await,
+                                ExecutingList::new(pipelines, connection, channel, session).await,
                             )
                         }
+                        Ok((rest, _)) => {
+                            audit_command(connection, data);
+                            session.data(
+                                channel,
+                                format!(
+                                    "bash: syntax error near unexpected token `{}'\n",
+                                    unexpected_token(rest)
+                                )
+                                .into(),
+                            );
+                            (State::Prompt, true)
+                        }
                         Err(e) => {
-                            // TODO
+                            audit_command(connection, data);
                             info!("Invalid syntax: {e}");
-                            session.data(channel, "bash: syntax error\n".to_string().into());
+                            session.data(
+                                channel,
+                                "bash: syntax error: unexpected end of file\n"
+                                    .to_string()
+                                    .into(),
+                            );
                             (State::Prompt, true)
                         }
                     }
                 }
                 State::Running(command) => self
                     .handle_command_result(command.stdin(connection, channel, data, session).await),
-                State::Exit(exit_status) => {
-                    session.exit_status_request(channel, exit_status);
-                    (State::Prompt, true)
-                }
                 State::Quit(exit_status) => {
-                    session.exit_status_request(channel, exit_status);
-                    session.close(channel);
-                    break;
+                    self.quit(channel, exit_status, session);
+                    return false;
                 }
             };
 
@@ -108,34 +201,732 @@ impl Subsystem for Shell {
         }
 
         if matches!(self.state, State::Prompt) {
-            session.data(channel, SHELL_PROMPT.to_string().into());
+            // a command that was prompting for a password may not have had a chance to
+            // start echoing again
+            connection.set_hide_input(false);
+            session.data(channel, prompt(connection).into());
+        }
+
+        true
+    }
+
+    /// Handles input from a client without a PTY, running each command once its line is
+    /// complete. Anything sent to a running command is passed on as is.
+    async fn buffered_input(
+        &mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut TerminalSession<'_>,
+    ) {
+        if !matches!(self.state, State::Prompt) {
+            self.execute(connection, channel, data, session).await;
+            return;
+        }
+
+        for line in self.buffer.input(data) {
+            if self.taken_over(connection) {
+                if matches!(self.state, State::Prompt) {
+                    audit_command(connection, &line);
+                }
+            } else if !self.execute(connection, channel, &line, session).await {
+                return;
+            }
+        }
+
+        // the rest belongs to whichever command is now reading stdin
+        if matches!(self.state, State::Running(_)) {
+            if let Some(rest) = self.buffer.take() {
+                self.execute(connection, channel, &rest, session).await;
+            }
+        }
+    }
+
+    /// Whether an operator has taken over the session, in which case input is left for them to
+    /// answer.
+    fn taken_over(&self, connection: &ConnectionState) -> bool {
+        self.interactive && connection.takeover().active()
+    }
+
+    /// Abandons the running command, if any, as a result of input from the terminal.
+    fn interrupt(
+        &mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        exit_status: u32,
+        session: &mut TerminalSession<'_>,
+    ) -> bool {
+        self.state = State::Prompt;
+        connection.set_hide_input(false);
+
+        if self.interactive {
+            session.data(channel, prompt(connection).into());
+            true
+        } else {
+            self.quit(channel, exit_status, session);
+            false
+        }
+    }
+
+    /// Exits the shell, sending the client its exit status before closing the channel.
+    fn quit(&mut self, channel: ChannelId, exit_status: u32, session: &mut TerminalSession<'_>) {
+        self.closed = true;
+
+        // sshd starts a login shell, which says goodbye on the way out
+        if self.interactive {
+            session.data(channel, "logout\n".to_string().into());
+        }
+
+        session.exit_status_request(channel, exit_status);
+        session.eof(channel);
+        session.close(channel);
+    }
+
+    /// Handles the client closing its end of the channel, running whatever's left of the last
+    /// line from clients without a PTY, then exiting with the status of the last command as the
+    /// shell would once its input runs out.
+    pub async fn eof(
+        &mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut dyn ShellSession,
+    ) {
+        if self.closed {
+            return;
+        }
+
+        let mut session = TerminalSession::new(
+            session,
+            self.terminal.is_some(),
+            connection.recording(),
+            connection.tap(),
+        );
+
+        // `printf 'uname -a' | ssh host` never sends a newline after its command
+        if let Some(line) = self.buffer.take() {
+            if self.taken_over(connection) {
+                audit_command(connection, &line);
+            } else if !self.execute(connection, channel, &line, &mut session).await {
+                return;
+            }
+        }
+
+        self.quit(channel, connection.exit_status(), &mut session);
+    }
+
+    /// Handles input from the client, through the line discipline if it has a PTY.
+    pub async fn input(
+        &mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut dyn ShellSession,
+    ) {
+        let recording = connection.recording();
+        let tap = connection.tap();
+
+        if let Some(recording) = &recording {
+            recording.input(data);
+        }
+
+        if !self.terminal.as_ref().map_or(false, Terminal::echoes) {
+            tap.input(data);
+        }
+
+        let Some(terminal) = &mut self.terminal else {
+            let mut session = TerminalSession::new(session, false, recording, tap);
+            self.buffered_input(connection, channel, data, &mut session)
+                .await;
+            return;
+        };
+
+        let (echo, input) = terminal.input(data);
+        let mut session = TerminalSession::new(session, true, recording, tap);
+
+        if let Some(keystrokes) = &mut self.keystrokes {
+            keystrokes.input(Instant::now(), data.len());
+        }
+
+        if !echo.is_empty() && !connection.hides_input() {
+            session.data(channel, CryptoVec::from_slice(&echo));
+        }
+
+        for input in input {
+            if matches!(input, Input::Line(_)) {
+                if let Some(event) = self.keystrokes.as_mut().and_then(Keystrokes::take) {
+                    connection
+                        .audit_log()
+                        .push_action(AuditLogAction::Keystrokes(event));
+                }
+            }
+
+            let open = match input {
+                Input::Line(line) if self.taken_over(connection) => {
+                    // still worth knowing what they ran, even if the operator is answering
+                    if matches!(self.state, State::Prompt) {
+                        audit_command(connection, &line);
+                    }
+
+                    true
+                }
+                Input::Interrupt if self.taken_over(connection) => true,
+                Input::Line(line) => self.execute(connection, channel, &line, &mut session).await,
+                Input::Interrupt => self.interrupt(connection, channel, 130, &mut session),
+                Input::Eof if self.interactive && matches!(self.state, State::Prompt) => {
+                    self.quit(channel, connection.exit_status(), &mut session);
+                    false
+                }
+                Input::Eof => self.interrupt(connection, channel, 0, &mut session),
+            };
+
+            if !open {
+                break;
+            }
         }
     }
 }
 
+#[async_trait]
+impl Subsystem for Shell {
+    const NAME: &'static str = "shell";
+
+    async fn data(
+        &mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) {
+        self.input(connection, channel, data, session).await;
+    }
+}
+
+/// Adds a line of input to the shell's history, skipping any starting with a space along with
+/// repeats of the line before as `HISTCONTROL=ignoreboth` does.
+fn remember(connection: &mut ConnectionState, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end();
+
+    if line.trim_start().is_empty() || line.starts_with(' ') {
+        return;
+    }
+
+    let history = connection.history();
+    if history.last().map(String::as_str) != Some(line) {
+        history.push(line.to_string());
+    }
+}
+
+/// Fills in the variables a login shell would start with, leaving any the client sent with an
+/// `env` request as they were.
+fn populate_environment(connection: &mut ConnectionState, pty: Option<&Pty>) {
+    let username = connection.username().to_string();
+    let home = connection.file_system().home().display().to_string();
+    let pwd = connection.file_system().pwd().display().to_string();
+    let shell = connection
+        .file_system()
+        .read(Path::new("/etc/passwd"))
+        .ok()
+        .and_then(|passwd| {
+            String::from_utf8_lossy(passwd)
+                .lines()
+                .map(|v| v.split(':').collect::<Vec<_>>())
+                .find(|v| v.first() == Some(&username.as_str()))
+                .and_then(|v| v.get(6).map(|v| (*v).to_string()))
+        })
+        .unwrap_or_else(|| "/bin/bash".to_string());
+    let path = if username == "root" {
+        "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"
+    } else {
+        "/usr/local/bin:/usr/bin:/bin:/usr/local/games:/usr/games"
+    };
+
+    let mut variables = vec![
+        ("HOME", home),
+        ("USER", username.clone()),
+        ("LOGNAME", username),
+        ("SHELL", shell),
+        ("PATH", path.to_string()),
+        ("PWD", pwd),
+        ("LANG", "C.UTF-8".to_string()),
+    ];
+
+    if let Some(pty) = pty {
+        variables.push(("TERM", pty.term.to_string()));
+    }
+
+    if let Some(peer) = connection.audit_log().peer_address {
+        let local = connection
+            .config()
+            .persona
+            .network
+            .sockets(Some(peer))
+            .pop()
+            .map(|v| v.local);
+        let local_port = local.map_or(22, |v| v.port());
+
+        variables.push((
+            "SSH_CLIENT",
+            format!("{} {} {local_port}", peer.ip(), peer.port()),
+        ));
+
+        if let Some(local) = local {
+            variables.push((
+                "SSH_CONNECTION",
+                format!(
+                    "{} {} {} {}",
+                    peer.ip(),
+                    peer.port(),
+                    local.ip(),
+                    local.port()
+                ),
+            ));
+        }
+    }
+
+    if let Some(tty) = connection.tty() {
+        variables.push(("SSH_TTY", format!("/dev/{tty}")));
+    }
+
+    for (name, value) in variables {
+        connection
+            .environment_mut()
+            .entry(Cow::Borrowed(name.as_bytes()))
+            .or_insert_with(|| Cow::Owned(value.into_bytes()));
+    }
+}
+
+/// Renders the persona's MOTD, reporting the login before the client's own as the last one so it
+/// agrees with `last`.
+fn render_motd(connection: &mut ConnectionState) -> String {
+    if connection.config().persona.motd.is_empty() {
+        return String::new();
+    }
+
+    let logins = command::logins(connection);
+    let persona = &connection.config().persona;
+
+    motd::render(
+        &persona.motd,
+        &motd::Variables {
+            persona,
+            now: OffsetDateTime::now_utc(),
+            last_login: logins.get(1),
+        },
+    )
+}
+
+/// Renders the persona's prompt, expanding the subset of bash's escapes that make sense
+/// for us.
+fn prompt(connection: &mut ConnectionState) -> String {
+    let template = connection.config().persona.prompt.clone();
+    let mut chars = template.chars();
+    let mut out = String::new();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('u') => out.push_str(connection.username()),
+            Some('h') => out.push_str(
+                connection
+                    .config()
+                    .persona
+                    .hostname
+                    .split('.')
+                    .next()
+                    .unwrap_or_default(),
+            ),
+            Some('H') => out.push_str(&connection.config().persona.hostname),
+            Some('w') => {
+                let file_system = connection.file_system();
+                let pwd = file_system.pwd();
+
+                match pwd.strip_prefix(file_system.home()) {
+                    Ok(rest) if rest.as_os_str().is_empty() => out.push('~'),
+                    Ok(rest) => out.push_str(&format!("~/{}", rest.display())),
+                    Err(_) => out.push_str(&pwd.display().to_string()),
+                }
+            }
+            Some('W') => {
+                let file_system = connection.file_system();
+                let pwd = file_system.pwd();
+
+                if pwd == file_system.home() {
+                    out.push('~');
+                } else {
+                    out.push_str(
+                        &pwd.file_name()
+                            .map_or_else(|| "/".into(), |v| v.to_string_lossy()),
+                    );
+                }
+            }
+            Some('$') if connection.username() == "root" => out.push('#'),
+            Some('$') => out.push('$'),
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+fn audit_command(connection: &mut ConnectionState, command: &[u8]) {
+    info!(command = %String::from_utf8_lossy(command), "Ran command");
+
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::ExecCommand(ExecCommandEvent {
+            args: Box::from(vec![String::from_utf8_lossy(command).to_string()]),
+        }));
+}
+
+/// Audits each command within the list separately and takes ownership of them, ready to be
+/// executed.
+fn prepare_pipelines(
+    connection: &mut ConnectionState,
+    list: Vec<(Connector, Pipeline<'_>)>,
+) -> VecDeque<(Connector, Vec<Stage>)> {
+    list.into_iter()
+        .map(|(connector, pipeline)| {
+            let pipeline = pipeline
+                .into_iter()
+                .map(|command| {
+                    audit_command(connection, command.source);
+                    let role = Role::of(command.source);
+                    let iter = parser::Iter::new(
+                        command
+                            .parts
+                            .into_iter()
+                            .map(ParsedPart::into_owned)
+                            .collect(),
+                    );
+
+                    (iter, role)
+                })
+                .collect();
+
+            (connector, pipeline)
+        })
+        .collect()
+}
+
+/// Runs `script` a line at a time as a non-interactive shell would, auditing each command
+/// within it, and returns the exit status of the last one. Errors are prefixed with `name` as
+/// bash does, ie. `x.sh: line 3: ...`.
+///
+/// Commands have no stdin to read from, so any waiting on it are considered to have exited
+/// successfully just as they are in a pipeline. A script that calls `exit` returns `Close`,
+/// which only the shell running the script should act on.
+///
+/// The future is boxed, as scripts can run commands which themselves run scripts. Scripts
+/// nested more than [`MAX_SCRIPT_DEPTH`] deep fail rather than recursing any further.
+pub fn run_script<'a, S: ThrusshSession + Send>(
+    name: &'a str,
+    script: &'a [u8],
+    connection: &'a mut ConnectionState,
+    channel: ChannelId,
+    session: &'a mut S,
+) -> BoxFuture<'a, CommandResult<Infallible>> {
+    Box::pin(async move {
+        if *connection.script_depth() >= MAX_SCRIPT_DEPTH {
+            session.data(
+                channel,
+                format!("{name}: maximum nesting level exceeded ({MAX_SCRIPT_DEPTH})\n").into(),
+            );
+            return CommandResult::Exit(2);
+        }
+
+        *connection.script_depth() += 1;
+        let res = run_script_lines(name, script, connection, channel, session).await;
+        *connection.script_depth() -= 1;
+
+        res
+    })
+}
+
+/// Runs each line of a script, see [`run_script`].
+async fn run_script_lines<S: ThrusshSession + Send>(
+    name: &str,
+    script: &[u8],
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<Infallible> {
+    let mut status = 0;
+
+    for (number, line) in script_lines(script) {
+        let trimmed = &line[line
+            .iter()
+            .position(|c| !c.is_ascii_whitespace())
+            .unwrap_or(line.len())..];
+
+        // comments, including the shebang
+        if trimmed.is_empty() || trimmed.starts_with(b"#") {
+            continue;
+        }
+
+        let error = match parse_command_list(&line) {
+            Ok((rest, list)) if rest.iter().all(u8::is_ascii_whitespace) => {
+                let pipelines = prepare_pipelines(connection, list);
+                let mut res = ExecutingList::new(pipelines, connection, channel, session).await;
+
+                loop {
+                    match res {
+                        CommandResult::ReadStdin(list) => {
+                            res =
+                                ExecutingList::run(list.pipelines, 0, connection, channel, session)
+                                    .await;
+                        }
+                        CommandResult::Exit(v) => {
+                            status = v;
+                            break;
+                        }
+                        CommandResult::Close(v) => return CommandResult::Close(v),
+                    }
+                }
+
+                continue;
+            }
+            Ok((rest, _)) => format!(
+                "syntax error near unexpected token `{}'",
+                unexpected_token(rest)
+            ),
+            Err(_) => "syntax error: unexpected end of file".to_string(),
+        };
+
+        audit_command(connection, &line);
+        session.data(channel, format!("{name}: line {number}: {error}\n").into());
+        status = 2;
+    }
+
+    CommandResult::Exit(status)
+}
+
+/// Splits a script into its lines along with their line numbers, joining lines that end in a
+/// backslash onto the line after them.
+fn script_lines(script: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut out = Vec::new();
+    let mut current: Option<(usize, Vec<u8>)> = None;
+
+    for (i, line) in script.split(|c| *c == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let (number, mut joined) = current.take().unwrap_or((i + 1, Vec::new()));
+        joined.extend_from_slice(line);
+
+        if line.ends_with(b"\\") {
+            joined.pop();
+            current = Some((number, joined));
+        } else {
+            out.push((number, joined));
+        }
+    }
+
+    out.extend(current);
+    out
+}
+
+/// Picks out the token bash would complain about from the input we failed to parse.
+fn unexpected_token(rest: &[u8]) -> Cow<'_, str> {
+    let rest = &rest[rest
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .unwrap_or(rest.len())..];
+
+    let is_operator = |c: u8| b"|&;()<>".contains(&c);
+
+    let len = if rest.first().map_or(false, |&c| is_operator(c)) {
+        rest.iter().take_while(|&&c| is_operator(c)).count()
+    } else {
+        rest.iter()
+            .take_while(|&&c| !c.is_ascii_whitespace() && !is_operator(c))
+            .count()
+    };
+
+    if len == 0 {
+        Cow::Borrowed("newline")
+    } else {
+        String::from_utf8_lossy(&rest[..len])
+    }
+}
+
+/// A command within a pipeline, along with the part it plays in passing a payload through it.
+type Stage = (parser::Iter<'static>, Option<Role>);
+
+/// A list of pipelines joined by `;`, `&&` and `||`, executed one after another whilst
+/// honouring the exit status of the previous pipeline.
+#[derive(Debug)]
+pub struct ExecutingList {
+    pipelines: VecDeque<(Connector, Vec<Stage>)>,
+    current: ExecutingCommand,
+}
+
+impl ExecutingList {
+    async fn new<S: ThrusshSession + Send>(
+        pipelines: VecDeque<(Connector, Vec<Stage>)>,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        Self::run(pipelines, 0, connection, channel, session).await
+    }
+
+    /// Runs pipelines until one of them needs to read from stdin or the list is exhausted.
+    async fn run<S: ThrusshSession + Send>(
+        mut pipelines: VecDeque<(Connector, Vec<Stage>)>,
+        mut status: u32,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        while let Some((connector, mut pipeline)) = pipelines.pop_front() {
+            let skip = match connector {
+                Connector::Always => false,
+                Connector::And => status != 0,
+                Connector::Or => status == 0,
+            };
+
+            if skip {
+                // skipped pipelines leave the exit status untouched, so `false && a || b`
+                // still runs `b`
+                continue;
+            }
+
+            let shell = pipeline
+                .iter()
+                .rposition(|(_, role)| *role == Some(Role::Shell));
+
+            let Some((last, _)) = pipeline.pop() else {
+                continue;
+            };
+
+            if pipeline.is_empty() {
+                match ExecutingCommand::new(last, connection, channel, session).await {
+                    CommandResult::ReadStdin(current) => {
+                        return CommandResult::ReadStdin(Self { pipelines, current })
+                    }
+                    CommandResult::Exit(v) => status = v,
+                    CommandResult::Close(v) => return CommandResult::Close(v),
+                }
+
+                continue;
+            }
+
+            let mut input = Vec::new();
+
+            for (i, (iter, role)) in pipeline.into_iter().enumerate() {
+                let mut output = Vec::new();
+
+                let res = run_piped(
+                    iter,
+                    &input,
+                    connection,
+                    channel,
+                    &mut StdoutCaptureSession::new(&mut output),
+                )
+                .await;
+
+                match res {
+                    CommandResult::Exit(_) => {}
+                    CommandResult::Close(v) => return CommandResult::Close(v),
+                    CommandResult::ReadStdin(v) => match v {},
+                }
+
+                if let Some(Role::Decoder(encoding)) = role.filter(|_| shell > Some(i)) {
+                    payload::record(connection, encoding, &input, &output);
+
+                    if !connection.config().shell.run_decoded_payloads {
+                        output.clear();
+                    }
+                }
+
+                input = output;
+            }
+
+            match run_piped(last, &input, connection, channel, session).await {
+                CommandResult::Exit(v) => status = v,
+                CommandResult::Close(v) => return CommandResult::Close(v),
+                CommandResult::ReadStdin(v) => match v {},
+            }
+        }
+
+        CommandResult::Exit(status)
+    }
+
+    async fn stdin<S: ThrusshSession + Send>(
+        self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut S,
+    ) -> CommandResult<Self> {
+        match self.current.stdin(connection, channel, data, session).await {
+            CommandResult::ReadStdin(current) => CommandResult::ReadStdin(Self {
+                pipelines: self.pipelines,
+                current,
+            }),
+            CommandResult::Exit(status) => {
+                Self::run(self.pipelines, status, connection, channel, session).await
+            }
+            CommandResult::Close(status) => CommandResult::Close(status),
+        }
+    }
+}
+
+/// Runs a command within a multi-command pipeline, feeding it the output of the command before
+/// it as stdin. We've no way of signalling EOF to a command, so one still waiting on stdin
+/// after receiving all of its input is considered to have exited successfully.
+async fn run_piped<S: ThrusshSession + Send>(
+    iter: parser::Iter<'static>,
+    input: &[u8],
+    connection: &mut ConnectionState,
+    channel: ChannelId,
+    session: &mut S,
+) -> CommandResult<Infallible> {
+    let command = match ExecutingCommand::new(iter, connection, channel, session).await {
+        CommandResult::ReadStdin(command) if !input.is_empty() => command,
+        CommandResult::ReadStdin(_) => return CommandResult::Exit(0),
+        CommandResult::Exit(v) => return CommandResult::Exit(v),
+        CommandResult::Close(v) => return CommandResult::Close(v),
+    };
+
+    match command.stdin(connection, channel, input, session).await {
+        CommandResult::ReadStdin(_) => CommandResult::Exit(0),
+        CommandResult::Exit(v) => CommandResult::Exit(v),
+        CommandResult::Close(v) => CommandResult::Close(v),
+    }
+}
+
 #[derive(Debug)]
 pub struct ExecutingCommand {
     iter: parser::Iter<'static>,
     current: ConcreteCommand,
     buf: Option<Vec<u8>>,
+    redirect: Option<Redirect>,
 }
 
 impl ExecutingCommand {
-    async fn new(
+    async fn new<S: ThrusshSession + Send>(
         iter: parser::Iter<'static>,
         connection: &mut ConnectionState,
         channel: ChannelId,
-        session: &mut Session,
+        session: &mut S,
     ) -> CommandResult<Self> {
         Self::new_inner(Vec::new(), iter, connection, channel, session).await
     }
 
-    async fn new_inner(
+    async fn new_inner<S: ThrusshSession + Send>(
         mut buf: Vec<u8>,
         mut iter: parser::Iter<'static>,
         connection: &mut ConnectionState,
         channel: ChannelId,
-        session: &mut Session,
+        session: &mut S,
     ) -> CommandResult<Self> {
         loop {
             let (has_next, current) = match iter.step(
@@ -146,15 +937,19 @@ impl ExecutingCommand {
                 IterState::Ready(cmd) => (false, cmd),
             };
 
-            let mut session = if has_next {
+            let mut redirect = if has_next { None } else { Redirect::new(&iter) };
+
+            let mut sess = if has_next {
                 EitherSession::L(StdoutCaptureSession::new(&mut buf))
+            } else if let Some(redirect) = &mut redirect {
+                EitherSession::L(StdoutCaptureSession::new(&mut redirect.buf))
             } else {
                 EitherSession::R(&mut *session)
             };
 
             match (
                 current
-                    .into_concrete_command(connection, channel, &mut session)
+                    .into_concrete_command(connection, channel, &mut sess)
                     .await,
                 has_next,
             ) {
@@ -163,12 +958,15 @@ impl ExecutingCommand {
                         iter,
                         current: cmd,
                         buf: has_next.then_some(buf),
+                        redirect,
                     })
                 }
                 (CommandResult::Exit(_status), true) => {
                     continue;
                 }
                 (CommandResult::Exit(status), false) => {
+                    let status = Redirect::finish(redirect, status, connection, channel, session);
+                    connection.set_exit_status(status);
                     break CommandResult::Exit(status);
                 }
                 (CommandResult::Close(status), _) => {
@@ -178,15 +976,17 @@ impl ExecutingCommand {
         }
     }
 
-    async fn stdin(
+    async fn stdin<S: ThrusshSession + Send>(
         mut self,
         connection: &mut ConnectionState,
         channel: ChannelId,
         data: &[u8],
-        session: &mut Session,
+        session: &mut S,
     ) -> CommandResult<Self> {
         let mut sess = if let Some(buf) = &mut self.buf {
             EitherSession::L(StdoutCaptureSession::new(buf))
+        } else if let Some(redirect) = &mut self.redirect {
+            EitherSession::L(StdoutCaptureSession::new(&mut redirect.buf))
         } else {
             EitherSession::R(&mut *session)
         };
@@ -200,7 +1000,15 @@ impl ExecutingCommand {
                 iter: self.iter,
                 current: cmd,
                 buf: self.buf,
+                redirect: self.redirect,
             }),
+            CommandResult::Exit(status) if self.buf.is_none() => {
+                // the command that exited was the one we were ultimately executing rather than
+                // a substitution, so there's nothing left to run
+                let status = Redirect::finish(self.redirect, status, connection, channel, session);
+                connection.set_exit_status(status);
+                CommandResult::Exit(status)
+            }
             CommandResult::Exit(_) => {
                 Self::new_inner(
                     self.buf.unwrap_or_default(),
@@ -216,11 +1024,313 @@ impl ExecutingCommand {
     }
 }
 
+/// Captures a command's stdout so it can be written to the file it was redirected to once the
+/// command exits.
+#[derive(Debug)]
+struct Redirect {
+    path: PathBuf,
+    append: bool,
+    buf: Vec<u8>,
+}
+
+impl Redirect {
+    fn new(iter: &parser::Iter<'_>) -> Option<Self> {
+        let (path, append) = iter.stdout_redirection()?;
+
+        Some(Self {
+            path: PathBuf::from(String::from_utf8_lossy(path).into_owned()),
+            append,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Writes the captured output to the file system, returning the exit status the command
+    /// should exit with.
+    fn finish<S: ThrusshSession>(
+        this: Option<Self>,
+        status: u32,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        session: &mut S,
+    ) -> u32 {
+        let Some(this) = this else {
+            return status;
+        };
+
+        if this.path == Path::new("/dev/null") {
+            return status;
+        }
+
+        let path = connection.file_system().canonicalize(&this.path);
+
+        // record the write before attempting it, so we capture the content even if it'd be
+        // rejected by the file system
+        connection.record_write(WriteFileEvent {
+            path: path.to_string_lossy().into_owned().into_boxed_str(),
+            sha256: sha256_hex(&this.buf),
+            size: this.buf.len(),
+            original_name: None,
+            mode: None,
+            content: Bytes::copy_from_slice(&this.buf),
+        });
+
+        let res = if this.append {
+            connection.file_system().append(&path, &this.buf)
+        } else {
+            connection
+                .file_system()
+                .write(&path, this.buf.clone().into_boxed_slice())
+        };
+
+        if let Err(e) = res {
+            session.data(
+                channel,
+                format!("bash: {}: {e}\n", this.path.display()).into(),
+            );
+            return 1;
+        }
+
+        status
+    }
+}
+
 #[derive(Debug, Default)]
 enum State {
     #[default]
     Prompt,
-    Running(ExecutingCommand),
-    Exit(u32),
+    Running(ExecutingList),
     Quit(u32),
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use mockall::{
+        mock,
+        predicate::{always, eq},
+    };
+    use pisshoff_types::audit::{AuditLogAction, PersistenceMechanism};
+    use thrussh::{server::Handle, ChannelId, CryptoVec};
+
+    use crate::{
+        command::CommandResult,
+        server::{
+            test::{fake_channel_id, predicate::eq_string},
+            ConnectionState, MockThrusshSession, ThrusshSession,
+        },
+        subsystem::shell::{parser::parse_command_list, prepare_pipelines, ExecutingList, Shell},
+        terminal::ShellSession,
+    };
+
+    mock! {
+        Session {}
+
+        impl ThrusshSession for Session {
+            fn data(&mut self, channel: ChannelId, data: CryptoVec);
+        }
+
+        impl ShellSession for Session {
+            fn exit_status_request(&mut self, channel: ChannelId, exit_status: u32);
+            fn eof(&mut self, channel: ChannelId);
+            fn close(&mut self, channel: ChannelId);
+            fn handle(&self) -> Option<Handle>;
+        }
+    }
+
+    /// Expects the shell to exit with `exit_status`, allowing for any output along the way.
+    fn expect_exit(session: &mut MockSession, exit_status: u32) {
+        session.expect_handle().returning(|| None);
+        session.expect_data().returning(|_, _| ());
+        session
+            .expect_exit_status_request()
+            .once()
+            .with(always(), eq(exit_status))
+            .returning(|_, _| ());
+        session.expect_eof().once().returning(|_| ());
+        session.expect_close().once().returning(|_| ());
+    }
+
+    #[tokio::test]
+    async fn eof_exits_with_last_status() {
+        let mut session = MockSession::new();
+        let mut state = ConnectionState::mock();
+        expect_exit(&mut session, 127);
+
+        let mut shell = Shell::new(true, None, &mut state, fake_channel_id(), &mut session);
+        shell
+            .input(&mut state, fake_channel_id(), b"nope\n", &mut session)
+            .await;
+        shell.eof(&mut state, fake_channel_id(), &mut session).await;
+
+        // the channel's already closed, so there's nothing more to send
+        shell.eof(&mut state, fake_channel_id(), &mut session).await;
+    }
+
+    #[tokio::test]
+    async fn eof_runs_unterminated_line() {
+        let mut session = MockSession::new();
+        let mut state = ConnectionState::mock();
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("root\n"))
+            .returning(|_, _| ());
+        expect_exit(&mut session, 0);
+
+        let mut shell = Shell::new(true, None, &mut state, fake_channel_id(), &mut session);
+        shell
+            .input(&mut state, fake_channel_id(), b"whoami", &mut session)
+            .await;
+        shell.eof(&mut state, fake_channel_id(), &mut session).await;
+
+        let commands = state
+            .audit_log()
+            .events
+            .iter()
+            .filter(|v| matches!(v.action, AuditLogAction::ExecCommand(_)))
+            .count();
+        assert_eq!(commands, 1);
+    }
+
+    #[tokio::test]
+    async fn short_circuits_and_pipes() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session.expect_redirected().returning(|| false);
+
+        session
+            .expect_data()
+            .times(2)
+            .with(
+                always(),
+                eq_string("ls: /missing: No such file or directory\n"),
+            )
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("fallback\n"))
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("piped\n"))
+            .returning(|_, _| ());
+
+        let (rest, list) = parse_command_list(
+            b"ls /missing && echo skipped || echo fallback; ls /missing | cat && echo piped",
+        )
+        .unwrap();
+        assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+
+        let pipelines = prepare_pipelines(&mut state, list);
+        let out = ExecutingList::new(pipelines, &mut state, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn expands_exit_status() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session.expect_redirected().returning(|| false);
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("bash: nope: command not found\n"))
+            .returning(|_, _| ());
+
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("127\n"))
+            .returning(|_, _| ());
+
+        let (rest, list) = parse_command_list(b"nope; echo $?").unwrap();
+        assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+
+        let pipelines = prepare_pipelines(&mut state, list);
+        let out = ExecutingList::new(pipelines, &mut state, fake_channel_id(), &mut session).await;
+
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+        assert_eq!(state.exit_status(), 0);
+    }
+
+    #[tokio::test]
+    async fn records_authorized_keys_redirect() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+        state
+            .file_system()
+            .mkdirall(Path::new("/root/.ssh"))
+            .unwrap();
+
+        session.expect_redirected().returning(|| false);
+
+        let (rest, list) = parse_command_list(
+            b"echo \"ssh-rsa AAAAB3NzaC1yc2E mdrfckr\" >> ~/.ssh/authorized_keys",
+        )
+        .unwrap();
+        assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+
+        let pipelines = prepare_pipelines(&mut state, list);
+        let out = ExecutingList::new(pipelines, &mut state, fake_channel_id(), &mut session).await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let persistence = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::PersistenceAttempt(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        let PersistenceMechanism::AuthorizedKeys { keys } = &persistence.mechanism else {
+            panic!("expected authorized keys, got {:?}", persistence.mechanism);
+        };
+        assert_eq!(&*persistence.path, "/root/.ssh/authorized_keys");
+        assert_eq!(&*keys[0].kind, "ssh-rsa");
+        assert_eq!(&*keys[0].key, "AAAAB3NzaC1yc2E");
+        assert_eq!(keys[0].comment.as_deref(), Some("mdrfckr"));
+    }
+
+    #[tokio::test]
+    async fn decodes_payloads() {
+        let mut session = MockThrusshSession::default();
+        let mut state = ConnectionState::mock();
+
+        session.expect_redirected().returning(|| false);
+        session
+            .expect_data()
+            .once()
+            .with(always(), eq_string("root\n"))
+            .returning(|_, _| ());
+
+        let (rest, list) = parse_command_list(b"echo d2hvYW1p | base64 -d | sh").unwrap();
+        assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+
+        let pipelines = prepare_pipelines(&mut state, list);
+        let out = ExecutingList::new(pipelines, &mut state, fake_channel_id(), &mut session).await;
+        assert!(matches!(out, CommandResult::Exit(0)), "{out:?}");
+
+        let payload = state
+            .audit_log()
+            .events
+            .iter()
+            .find_map(|v| match &v.action {
+                AuditLogAction::DecodedPayload(v) => Some(v),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(&*payload.encoding, "base64");
+        assert_eq!(&*payload.encoded, "d2hvYW1p");
+        assert_eq!(&*payload.decoded, b"whoami");
+    }
+}
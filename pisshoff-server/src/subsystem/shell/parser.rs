@@ -2,12 +2,12 @@ use std::{borrow::Cow, collections::HashMap};
 
 use nom::{
     branch::alt,
-    bytes::complete::{escaped_transform, is_not, tag, take, take_until, take_while1},
-    character::complete::{alphanumeric1, char, digit0, digit1, multispace1},
-    combinator::{cut, fail, map, map_opt, peek, value},
+    bytes::complete::{escaped_transform, is_not, tag, take, take_until, take_while, take_while1},
+    character::complete::{char, digit0, digit1, multispace0},
+    combinator::{consumed, cut, fail, map, map_opt, not, opt, peek, value, verify},
     error::context,
-    multi::{fold_many0, many_till},
-    sequence::{delimited, preceded},
+    multi::{fold_many0, many0, many_till, separated_list1},
+    sequence::{delimited, pair, preceded, terminated},
     AsChar,
 };
 
@@ -44,6 +44,16 @@ impl<'a> Iter<'a> {
 }
 
 impl<'a> Iter<'a> {
+    /// The file stdout has been redirected to, along with whether it should be appended to
+    /// rather than truncated. Only valid once the iterator has returned `IterState::Ready`.
+    pub fn stdout_redirection(&self) -> Option<(&[u8], bool)> {
+        match &self.stdio_out[0] {
+            RedirectionTo::Stdio(_) => None,
+            RedirectionTo::File(path) => Some((path, false)),
+            RedirectionTo::Append(path) => Some((path, true)),
+        }
+    }
+
     pub fn step(
         &mut self,
         env: &HashMap<Cow<'static, [u8]>, Cow<'static, [u8]>>,
@@ -95,10 +105,14 @@ impl<'a> Iter<'a> {
                         env.get(&variable).cloned().unwrap_or(Cow::Borrowed(b""))
                     }
                     ParsedPart::Redirection(idx, target) => {
-                        // store a stdio redirection
-                        if let Some(out) = self.stdio_out.get_mut(usize::from(idx)) {
-                            *out = target;
-                        }
+                        // store a stdio redirection, an unnamed redirection applies to stdout
+                        let slot = match idx {
+                            0 | 1 => 0,
+                            2 => 1,
+                            _ => continue,
+                        };
+
+                        self.stdio_out[slot] = target;
                         continue;
                     }
                 }
@@ -144,6 +158,7 @@ impl ParsedPart<'_> {
 pub enum RedirectionTo<'a> {
     Stdio(u8),
     File(Cow<'a, [u8]>),
+    Append(Cow<'a, [u8]>),
 }
 
 impl RedirectionTo<'_> {
@@ -151,6 +166,7 @@ impl RedirectionTo<'_> {
         match self {
             RedirectionTo::Stdio(v) => RedirectionTo::Stdio(v),
             RedirectionTo::File(f) => RedirectionTo::File(Cow::Owned(f.into_owned())),
+            RedirectionTo::Append(f) => RedirectionTo::Append(Cow::Owned(f.into_owned())),
         }
     }
 }
@@ -172,6 +188,125 @@ impl Expansion<'_> {
     }
 }
 
+/// Operator joining a pipeline to the one preceding it within a command list.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Connector {
+    /// `;`, `&` or a newline, the pipeline runs regardless of the previous exit status.
+    Always,
+    /// `&&`, the pipeline only runs if the previous one succeeded.
+    And,
+    /// `||`, the pipeline only runs if the previous one failed.
+    Or,
+}
+
+/// A single command within a pipeline, along with the source it was parsed from so it can be
+/// audited separately from the rest of the line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SimpleCommand<'a> {
+    pub source: &'a [u8],
+    pub parts: Vec<ParsedPart<'a>>,
+}
+
+/// Commands joined by `|`, each receiving the output of the command before it as stdin.
+pub type Pipeline<'a> = Vec<SimpleCommand<'a>>;
+
+/// Parses a list of pipelines delimited by `;`, `&&`, `||`, `&` or newlines, ie.
+/// `cd /tmp && wget x || curl x; chmod +x x`. Parsing stops at the first token that can't
+/// be handled, so callers should check the remaining input to detect syntax errors.
+pub fn parse_command_list(s: &[u8]) -> IResult<&[u8], Vec<(Connector, Pipeline<'_>)>> {
+    let (s, _) = multispace0(s)?;
+
+    if s.is_empty() {
+        return Ok((s, Vec::new()));
+    }
+
+    let (s, first) = parse_pipeline(s)?;
+    let (s, rest) = many0(pair(parse_connector, parse_pipeline))(s)?;
+    let (s, _) = opt(verify(parse_connector, |v: &Connector| {
+        *v == Connector::Always
+    }))(s)?;
+
+    let mut out = Vec::with_capacity(rest.len() + 1);
+    out.push((Connector::Always, first));
+    out.extend(rest);
+
+    Ok((s, out))
+}
+
+fn parse_connector(s: &[u8]) -> IResult<&[u8], Connector> {
+    delimited(
+        blank0,
+        alt((
+            value(Connector::And, tag("&&")),
+            value(Connector::Or, tag("||")),
+            value(Connector::Always, alt((tag(";"), tag("&"), tag("\n")))),
+        )),
+        multispace0,
+    )(s)
+}
+
+fn parse_pipeline(s: &[u8]) -> IResult<&[u8], Pipeline<'_>> {
+    separated_list1(
+        delimited(blank0, terminated(char('|'), not(char('|'))), blank0),
+        parse_simple_command,
+    )(s)
+}
+
+fn parse_simple_command(s: &[u8]) -> IResult<&[u8], SimpleCommand<'_>> {
+    map(
+        verify(
+            consumed(tokenize),
+            |(_, parts): &(&[u8], Vec<ParsedPart<'_>>)| {
+                parts.iter().any(|v| *v != ParsedPart::Break)
+            },
+        ),
+        |(source, mut parts)| {
+            // breaks surrounding the command would otherwise be treated as empty arguments
+            while parts.last() == Some(&ParsedPart::Break) {
+                parts.pop();
+            }
+
+            let leading = parts
+                .iter()
+                .take_while(|v| **v == ParsedPart::Break)
+                .count();
+            parts.drain(..leading);
+
+            SimpleCommand {
+                source: trim_blank(source),
+                parts,
+            }
+        },
+    )(s)
+}
+
+/// Whitespace separating arguments within a command, newlines instead delimit commands.
+fn is_blank(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\r')
+}
+
+fn blank0(s: &[u8]) -> IResult<&[u8], &[u8]> {
+    take_while(is_blank)(s)
+}
+
+fn trim_blank(mut s: &[u8]) -> &[u8] {
+    while let [first, rest @ ..] = s {
+        if !is_blank(*first) {
+            break;
+        }
+        s = rest;
+    }
+
+    while let [rest @ .., last] = s {
+        if !is_blank(*last) {
+            break;
+        }
+        s = rest;
+    }
+
+    s
+}
+
 /// Parses a single command (including substitutions), a command is delimited by a `;`, `|` or `>`
 pub fn tokenize(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
     fold_many0(parse_string_part, Vec::new, |mut acc, res| {
@@ -190,7 +325,7 @@ fn parse_string_part(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
         map(
             alt((
                 parse_redirection,
-                map(multispace1, |_| ParsedPart::Break),
+                map(take_while1(is_blank), |_| ParsedPart::Break),
                 map(parse_single_quoted, |r| {
                     ParsedPart::String(Cow::Borrowed(r))
                 }),
@@ -204,13 +339,31 @@ fn parse_string_part(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
 
 fn parse_redirection(s: &[u8]) -> IResult<&[u8], ParsedPart<'_>> {
     let (s, from) = map_opt(digit0, atoi)(s)?;
-    let (s, _) = char('>')(s)?;
+    let (s, append) = alt((value(true, tag(">>")), value(false, tag(">"))))(s)?;
     let (s, to) = alt((
         map(
             preceded(char('&'), map_opt(digit1, atoi)),
             RedirectionTo::Stdio,
         ),
-        map(alphanumeric1, |f| RedirectionTo::File(Cow::Borrowed(f))),
+        map(
+            preceded(
+                blank0,
+                alt((
+                    map(parse_single_quoted, Cow::Borrowed),
+                    map(
+                        verify(parse_unquoted, |v: &Vec<u8>| !v.is_empty()),
+                        Cow::Owned,
+                    ),
+                )),
+            ),
+            |f| {
+                if append {
+                    RedirectionTo::Append(f)
+                } else {
+                    RedirectionTo::File(f)
+                }
+            },
+        ),
     ))(s)?;
 
     Ok((s, ParsedPart::Redirection(from, to)))
@@ -218,7 +371,7 @@ fn parse_redirection(s: &[u8]) -> IResult<&[u8], ParsedPart<'_>> {
 
 fn parse_unquoted(s: &[u8]) -> IResult<&[u8], Vec<u8>> {
     escaped_transform(
-        is_not("\\\n \"'$`|>&();"),
+        is_not("\\\n\t\r \"'$`|>&();"),
         '\\',
         alt((value(b"".as_slice(), char('\n')), take(1_u8))),
     )(s)
@@ -258,7 +411,9 @@ fn parse_double_quoted(s: &[u8]) -> IResult<&[u8], Vec<ParsedPart<'_>>> {
 
 fn parse_expansion(s: &[u8]) -> IResult<&[u8], Expansion<'_>> {
     let dollar_expansion = alt((
-        map(tag("$"), |f| Expansion::Variable(Cow::Borrowed(f))),
+        map(alt((tag("$"), tag("?"))), |f| {
+            Expansion::Variable(Cow::Borrowed(f))
+        }),
         map(
             delimited(
                 char('('),
@@ -438,6 +593,24 @@ mod test {
             );
         }
 
+        #[test]
+        fn parses_file_redirects() {
+            let (rest, s) = tokenize(b"echo hi > /tmp/a.txt 2>>'log file'").unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                s,
+                vec![
+                    ParsedPart::String(Cow::Borrowed(b"echo")),
+                    ParsedPart::Break,
+                    ParsedPart::String(Cow::Borrowed(b"hi")),
+                    ParsedPart::Break,
+                    ParsedPart::Redirection(0, RedirectionTo::File(Cow::Borrowed(b"/tmp/a.txt"))),
+                    ParsedPart::Break,
+                    ParsedPart::Redirection(2, RedirectionTo::Append(Cow::Borrowed(b"log file"))),
+                ]
+            );
+        }
+
         #[test]
         fn parses_unnamed_redirects() {
             let (rest, s) = tokenize(b"hello test >&1").unwrap();
@@ -455,6 +628,108 @@ mod test {
         }
     }
 
+    mod parse_command_list {
+        use std::borrow::Cow;
+
+        use crate::subsystem::shell::parser::{
+            parse_command_list, Connector, ParsedPart, SimpleCommand,
+        };
+
+        fn sources(input: &[u8]) -> (&[u8], Vec<(Connector, Vec<&str>)>) {
+            let (rest, list) = parse_command_list(input).unwrap();
+
+            let list = list
+                .into_iter()
+                .map(|(connector, pipeline)| {
+                    let pipeline = pipeline
+                        .into_iter()
+                        .map(|v| std::str::from_utf8(v.source).unwrap())
+                        .collect();
+                    (connector, pipeline)
+                })
+                .collect();
+
+            (rest, list)
+        }
+
+        #[test]
+        fn compound() {
+            let (rest, list) = sources(b"cd /tmp && wget x || curl x; chmod +x x\n");
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                list,
+                vec![
+                    (Connector::Always, vec!["cd /tmp"]),
+                    (Connector::And, vec!["wget x"]),
+                    (Connector::Or, vec!["curl x"]),
+                    (Connector::Always, vec!["chmod +x x"]),
+                ]
+            );
+        }
+
+        #[test]
+        fn pipes() {
+            let (rest, list) = sources(b"echo aGk= | base64 -d|sh 2>&1 || echo failed");
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                list,
+                vec![
+                    (Connector::Always, vec!["echo aGk=", "base64 -d", "sh 2>&1"]),
+                    (Connector::Or, vec!["echo failed"]),
+                ]
+            );
+        }
+
+        #[test]
+        fn newlines() {
+            let (rest, list) = sources(b"\nuname -a\n\nwhoami &\n");
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                list,
+                vec![
+                    (Connector::Always, vec!["uname -a"]),
+                    (Connector::Always, vec!["whoami"]),
+                ]
+            );
+        }
+
+        #[test]
+        fn quoted_operators() {
+            let (rest, list) = parse_command_list(b"echo 'a && b' \"c | d\"").unwrap();
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert_eq!(
+                list,
+                vec![(
+                    Connector::Always,
+                    vec![SimpleCommand {
+                        source: b"echo 'a && b' \"c | d\"",
+                        parts: vec![
+                            ParsedPart::String(Cow::Borrowed(b"echo")),
+                            ParsedPart::Break,
+                            ParsedPart::String(Cow::Borrowed(b"a && b")),
+                            ParsedPart::Break,
+                            ParsedPart::String(Cow::Borrowed(b"c | d")),
+                        ],
+                    }]
+                )]
+            );
+        }
+
+        #[test]
+        fn empty() {
+            let (rest, list) = sources(b"  \n");
+            assert!(rest.is_empty(), "{}", String::from_utf8_lossy(rest));
+            assert!(list.is_empty());
+        }
+
+        #[test]
+        fn unexpected_token() {
+            let (rest, list) = sources(b"ls && && pwd");
+            assert_eq!(rest, b"&& && pwd");
+            assert_eq!(list, vec![(Connector::Always, vec!["ls"])]);
+        }
+    }
+
     mod parse_expansion {
         use std::borrow::Cow;
 
@@ -467,6 +742,13 @@ mod test {
             assert_eq!(s, Expansion::Variable(Cow::Borrowed(b"$")));
         }
 
+        #[test]
+        fn exit_status() {
+            let (rest, s) = parse_expansion(b"$?;").unwrap();
+            assert_eq!(rest, b";");
+            assert_eq!(s, Expansion::Variable(Cow::Borrowed(b"?")));
+        }
+
         #[test]
         fn variable() {
             let (rest, s) = parse_expansion(b"$HELLO_WORLD").unwrap();
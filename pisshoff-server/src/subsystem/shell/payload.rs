@@ -0,0 +1,84 @@
+//! Spots encoded payloads being piped into a shell, as in `echo <base64> | base64 -d | sh`,
+//! which is how a good deal of droppers hide what they're running from anything that only
+//! looks at the command line.
+
+use std::borrow::Cow;
+
+use bytes::Bytes;
+use pisshoff_types::audit::{AuditLogAction, DecodedPayloadEvent};
+
+use crate::server::ConnectionState;
+
+/// The part a command plays in passing a payload through a pipeline.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Role {
+    /// Decodes its stdin from the given encoding.
+    Decoder(&'static str),
+    /// Runs its stdin as a script.
+    Shell,
+}
+
+impl Role {
+    /// Works out the role of a command from its source, as long as the command isn't hidden
+    /// behind an expansion.
+    pub fn of(source: &[u8]) -> Option<Self> {
+        let words = shlex::split(&String::from_utf8_lossy(source))?;
+        let mut words = words.iter().map(String::as_str);
+
+        let mut program = words.next()?;
+        if program.rsplit('/').next() == Some("sudo") {
+            program = words.find(|v| !v.starts_with('-'))?;
+        }
+
+        let options = words.filter(|v| v.starts_with('-')).collect::<Vec<_>>();
+        let short = |flag| {
+            options
+                .iter()
+                .any(|v| !v.starts_with("--") && v.contains(flag))
+        };
+
+        match program.rsplit('/').next()? {
+            "sh" | "bash" | "dash" | "ash" => Some(Self::Shell),
+            "base64" if short('d') || short('D') || options.contains(&"--decode") => {
+                Some(Self::Decoder("base64"))
+            }
+            "xxd" if short('r') || options.contains(&"--revert") => Some(Self::Decoder("hex")),
+            _ => None,
+        }
+    }
+}
+
+/// Records a payload that was decoded by a command in a pipeline on its way into a shell.
+pub fn record(
+    connection: &mut ConnectionState,
+    encoding: &'static str,
+    encoded: &[u8],
+    decoded: &[u8],
+) {
+    connection
+        .audit_log()
+        .push_action(AuditLogAction::DecodedPayload(DecodedPayloadEvent {
+            encoding: Cow::Borrowed(encoding),
+            encoded: String::from_utf8_lossy(encoded).trim_end().into(),
+            decoded: Bytes::copy_from_slice(decoded),
+        }));
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use crate::subsystem::shell::payload::Role;
+
+    #[test_case("base64 -d", Some(Role::Decoder("base64")); "base64")]
+    #[test_case("base64 --decode -w0", Some(Role::Decoder("base64")); "long option")]
+    #[test_case("base64 -di", Some(Role::Decoder("base64")); "bundled")]
+    #[test_case("base64", None; "encoding")]
+    #[test_case("xxd -r -p", Some(Role::Decoder("hex")); "hex")]
+    #[test_case("/bin/bash", Some(Role::Shell); "shell")]
+    #[test_case("sudo -E sh -s", Some(Role::Shell); "sudo")]
+    #[test_case("$SHELL", None; "expansion")]
+    fn roles(source: &str, expected: Option<Role>) {
+        assert_eq!(Role::of(source.as_bytes()), expected);
+    }
+}
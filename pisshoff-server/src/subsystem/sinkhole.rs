@@ -0,0 +1,270 @@
+//! Accepts connections the client asked us to forward, recording what they send without ever
+//! passing it on. Common protocols are answered just well enough to keep proxy and spam tooling
+//! talking, so we get to see what it was after.
+
+mod http;
+mod smtp;
+mod socks;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pisshoff_types::audit::{
+    AuditLogAction, ForwardedDataEvent, ForwardedRequest, ForwardedRequestEvent,
+};
+use thrussh::{server::Session, ChannelId, CryptoVec};
+
+use crate::{server::ConnectionState, subsystem::Subsystem};
+
+/// Ports SMTP servers listen on without TLS, which are greeted as soon as they're opened.
+const SMTP_PORTS: &[u32] = &[25, 587, 2525];
+
+/// What a responder makes of the data it's been given.
+#[derive(Debug, Default)]
+pub struct Output {
+    /// Data to send back to the client.
+    pub reply: Vec<u8>,
+    /// Requests to record in the audit log.
+    pub requests: Vec<ForwardedRequest>,
+    /// Whether the conversation is over, and the channel should be closed.
+    pub close: bool,
+    /// Set once the client has been told it's connected onwards, along with any data that's
+    /// already arrived for the other end.
+    pub tunnel: Option<Vec<u8>>,
+}
+
+#[derive(Debug)]
+enum Responder {
+    /// Waiting for the first data to see what the client is speaking.
+    Detect,
+    Http(http::Http),
+    Smtp(smtp::Smtp),
+    Socks(socks::Socks),
+    /// Something we don't speak, which is only ever recorded.
+    Raw,
+}
+
+impl Responder {
+    fn detect(data: &[u8], hostname: &str) -> Self {
+        let word = data
+            .iter()
+            .position(|v| !v.is_ascii_alphabetic())
+            .map_or(data, |v| &data[..v]);
+
+        match (data.first(), word.to_ascii_uppercase().as_slice()) {
+            (Some(0x05), _) => Self::Socks(socks::Socks::default()),
+            (
+                _,
+                b"GET" | b"POST" | b"HEAD" | b"PUT" | b"DELETE" | b"OPTIONS" | b"PATCH"
+                | b"CONNECT",
+            ) => Self::Http(http::Http::default()),
+            (_, b"EHLO" | b"HELO") => Self::Smtp(smtp::Smtp::new(hostname)),
+            _ => Self::Raw,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sinkhole {
+    host_to_connect: Box<str>,
+    port_to_connect: u32,
+    /// Number of bytes we're still willing to capture.
+    remaining: usize,
+    responder: Responder,
+}
+
+impl Sinkhole {
+    pub fn new(host_to_connect: &str, port_to_connect: u32, max_capture: usize) -> Self {
+        let responder = if SMTP_PORTS.contains(&port_to_connect) {
+            Responder::Smtp(smtp::Smtp::new(host_to_connect))
+        } else {
+            Responder::Detect
+        };
+
+        Self {
+            host_to_connect: Box::from(host_to_connect),
+            port_to_connect,
+            remaining: max_capture,
+            responder,
+        }
+    }
+
+    /// A sinkhole that only records, for protocols we don't answer.
+    pub fn raw(host_to_connect: &str, port_to_connect: u32, max_capture: usize) -> Self {
+        Self {
+            responder: Responder::Raw,
+            ..Self::new(host_to_connect, port_to_connect, max_capture)
+        }
+    }
+
+    /// Anything the server at the other end would say before the client has said anything.
+    pub fn greeting(&self) -> Option<Vec<u8>> {
+        match &self.responder {
+            Responder::Smtp(smtp) => Some(smtp.greeting()),
+            _ => None,
+        }
+    }
+
+    /// Hands `data` to whichever responder speaks the client's protocol, starting over with
+    /// whatever comes after if the client is told it's been connected onwards.
+    fn respond(&mut self, data: &[u8]) -> Output {
+        let mut output = Output::default();
+        let mut data = data.to_vec();
+
+        while !data.is_empty() {
+            if matches!(self.responder, Responder::Detect) {
+                self.responder = Responder::detect(&data, &self.host_to_connect);
+            }
+
+            match &mut self.responder {
+                Responder::Http(http) => http.input(&data, &mut output),
+                Responder::Smtp(smtp) => smtp.input(&data, &mut output),
+                Responder::Socks(socks) => socks.input(&data, &mut output),
+                Responder::Detect | Responder::Raw => {}
+            }
+
+            let Some(rest) = output.tunnel.take() else {
+                break;
+            };
+
+            // an SMTP server would greet them as soon as they were put through
+            self.responder = match output.requests.last().and_then(tunnel_target) {
+                Some((host, port)) if SMTP_PORTS.contains(&port) => {
+                    let smtp = smtp::Smtp::new(&host);
+                    output.reply.extend_from_slice(&smtp.greeting());
+                    Responder::Smtp(smtp)
+                }
+                _ => Responder::Detect,
+            };
+            data = rest;
+        }
+
+        output
+    }
+
+    /// Captures as much of `data` as the limit allows, returning `false` once the limit has
+    /// been reached.
+    fn capture(&mut self, connection: &mut ConnectionState, data: &[u8]) -> bool {
+        let captured = &data[..data.len().min(self.remaining)];
+        let truncated = captured.len() < data.len();
+        self.remaining -= captured.len();
+
+        if !captured.is_empty() {
+            connection
+                .audit_log()
+                .push_action(AuditLogAction::ForwardedData(ForwardedDataEvent {
+                    host_to_connect: self.host_to_connect.clone(),
+                    port_to_connect: self.port_to_connect,
+                    data: Bytes::copy_from_slice(captured),
+                    truncated,
+                }));
+        }
+
+        !truncated && self.remaining > 0
+    }
+}
+
+/// The host and port a proxy request asked to be connected to.
+fn tunnel_target(request: &ForwardedRequest) -> Option<(String, u32)> {
+    match request {
+        ForwardedRequest::Http { target, .. } => {
+            let (host, port) = target.rsplit_once(':')?;
+            Some((host.to_string(), port.parse().ok()?))
+        }
+        ForwardedRequest::Socks { host, port, .. } => Some((host.to_string(), u32::from(*port))),
+        ForwardedRequest::SmtpLogin { .. } | ForwardedRequest::SmtpMessage { .. } => None,
+    }
+}
+
+#[async_trait]
+impl Subsystem for Sinkhole {
+    const NAME: &'static str = "sinkhole";
+
+    async fn data(
+        &mut self,
+        connection: &mut ConnectionState,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) {
+        let open = self.capture(connection, data);
+        let output = self.respond(data);
+
+        for request in output.requests {
+            connection
+                .audit_log()
+                .push_action(AuditLogAction::ForwardedRequest(ForwardedRequestEvent {
+                    host_to_connect: self.host_to_connect.clone(),
+                    port_to_connect: self.port_to_connect,
+                    request,
+                }));
+        }
+
+        if !output.reply.is_empty() {
+            session.data(channel, CryptoVec::from_slice(&output.reply));
+        }
+
+        if !open || output.close {
+            session.eof(channel);
+            session.close(channel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::{AuditLogAction, ForwardedRequest};
+
+    use crate::{server::ConnectionState, subsystem::sinkhole::Sinkhole};
+
+    #[test]
+    fn greets_smtp() {
+        let sinkhole = Sinkhole::new("mx.example.com", 25, 1024);
+        assert_eq!(
+            sinkhole.greeting().as_deref(),
+            Some(b"220 mx.example.com ESMTP Postfix\r\n".as_slice())
+        );
+        assert!(Sinkhole::new("example.com", 443, 1024).greeting().is_none());
+    }
+
+    #[test]
+    fn detects_through_tunnel() {
+        let mut sinkhole = Sinkhole::new("proxy.example.com", 8080, 1024);
+
+        let output = sinkhole.respond(b"CONNECT mx.example.com:25 HTTP/1.1\r\n\r\nEHLO spam\r\n");
+        assert!(matches!(
+            &output.requests[..],
+            [ForwardedRequest::Http { method, .. }] if &**method == "CONNECT"
+        ));
+
+        let reply = String::from_utf8_lossy(&output.reply);
+        assert!(
+            reply.contains("established\r\n\r\n220 mx.example.com ESMTP Postfix\r\n"),
+            "{reply}"
+        );
+        assert!(reply.ends_with("250 SMTPUTF8\r\n"), "{reply}");
+    }
+
+    #[test]
+    fn truncates() {
+        let mut state = ConnectionState::mock();
+        let mut sinkhole = Sinkhole::new("smtp.example.com", 25, 8);
+
+        assert!(sinkhole.capture(&mut state, b"EHLO"));
+        assert!(!sinkhole.capture(&mut state, b" example.com\r\n"));
+        assert!(!sinkhole.capture(&mut state, b"MAIL FROM:<a@example.com>\r\n"));
+
+        let captured = state
+            .audit_log()
+            .events
+            .iter()
+            .filter_map(|v| match &v.action {
+                AuditLogAction::ForwardedData(v) => Some((v.data.to_vec(), v.truncated)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            captured,
+            [(b"EHLO".to_vec(), false), (b" exa".to_vec(), true)]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Answers HTTP requests, including `CONNECT`s from clients hoping we're an open proxy.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pisshoff_types::audit::ForwardedRequest;
+
+use crate::subsystem::sinkhole::Output;
+
+/// Largest request head we'll buffer while waiting for it to finish.
+const MAX_HEAD: usize = 16 * 1024;
+
+#[derive(Debug, Default)]
+pub struct Http {
+    buffer: Vec<u8>,
+    /// Bytes of the current request's body still to be skipped over.
+    body_remaining: usize,
+}
+
+impl Http {
+    pub fn input(&mut self, data: &[u8], output: &mut Output) {
+        let mut pending = data.to_vec();
+
+        while !pending.is_empty() {
+            if self.body_remaining > 0 {
+                let skipped = pending.len().min(self.body_remaining);
+                self.body_remaining -= skipped;
+                pending.drain(..skipped);
+                continue;
+            }
+
+            self.buffer.append(&mut pending);
+
+            let Some(end) = find_end_of_head(&self.buffer) else {
+                if self.buffer.len() > MAX_HEAD {
+                    output.reply.extend_from_slice(
+                        b"HTTP/1.1 431 Request Header Fields Too Large\r\nConnection: close\r\n\r\n",
+                    );
+                    output.close = true;
+                }
+
+                return;
+            };
+
+            pending = self.buffer.split_off(end);
+            let head = std::mem::take(&mut self.buffer);
+
+            let Some(request) = parse_head(&head) else {
+                output
+                    .reply
+                    .extend_from_slice(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n");
+                output.close = true;
+                return;
+            };
+
+            let connect = request.method.eq_ignore_ascii_case("CONNECT");
+            output.requests.push(ForwardedRequest::Http {
+                method: request.method.into(),
+                target: request.target.into(),
+                host: request.host.map(Box::from),
+                credentials: request.credentials.map(Box::from),
+            });
+
+            if connect {
+                // whatever follows is meant for the host they asked to be connected to
+                output
+                    .reply
+                    .extend_from_slice(b"HTTP/1.1 200 Connection established\r\n\r\n");
+                output.tunnel = Some(pending);
+                return;
+            }
+
+            output.reply.extend_from_slice(
+                b"HTTP/1.1 200 OK\r\nServer: nginx\r\nContent-Type: text/html\r\n\
+                  Content-Length: 0\r\n\r\n",
+            );
+            self.body_remaining = request.content_length;
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    target: String,
+    host: Option<String>,
+    credentials: Option<String>,
+    content_length: usize,
+}
+
+/// Finds the end of the request head, returning the offset of the first byte after it.
+fn find_end_of_head(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(4)
+        .position(|v| v == b"\r\n\r\n")
+        .map(|v| v + 4)
+        .or_else(|| buffer.windows(2).position(|v| v == b"\n\n").map(|v| v + 2))
+}
+
+fn parse_head(head: &[u8]) -> Option<Request> {
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+
+    let method = request_line.next()?;
+    let target = request_line.next()?;
+
+    if !method.bytes().all(|v| v.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        target: target.to_string(),
+        host: None,
+        credentials: None,
+        content_length: 0,
+    };
+
+    for (name, value) in lines.filter_map(|v| v.split_once(':')) {
+        let value = value.trim();
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "host" => request.host = Some(value.to_string()),
+            "authorization" | "proxy-authorization" => {
+                request.credentials = Some(decode_credentials(value));
+            }
+            "content-length" => request.content_length = value.parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+
+    Some(request)
+}
+
+/// Decodes basic auth credentials to `username:password`, leaving any other scheme as it was
+/// sent.
+fn decode_credentials(value: &str) -> String {
+    value
+        .split_once(' ')
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"))
+        .and_then(|(_, encoded)| STANDARD.decode(encoded.trim()).ok())
+        .map_or_else(
+            || value.to_string(),
+            |v| String::from_utf8_lossy(&v).into_owned(),
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::ForwardedRequest;
+
+    use crate::subsystem::sinkhole::{http::Http, Output};
+
+    #[test]
+    fn connect() {
+        let mut http = Http::default();
+        let mut output = Output::default();
+
+        http.input(
+            b"CONNECT 198.51.100.7:443 HTTP/1.1\r\nHost: 198.51.100.7:443\r\n",
+            &mut output,
+        );
+        assert!(output.requests.is_empty());
+
+        http.input(
+            b"Proxy-Authorization: Basic dXNlcjpodW50ZXIy\r\n\r\n\x16\x03\x01",
+            &mut output,
+        );
+        assert_eq!(
+            output.requests,
+            [ForwardedRequest::Http {
+                method: "CONNECT".into(),
+                target: "198.51.100.7:443".into(),
+                host: Some("198.51.100.7:443".into()),
+                credentials: Some("user:hunter2".into()),
+            }]
+        );
+        assert_eq!(output.reply, b"HTTP/1.1 200 Connection established\r\n\r\n");
+        assert_eq!(output.tunnel.as_deref(), Some(b"\x16\x03\x01".as_slice()));
+    }
+
+    #[test]
+    fn skips_body() {
+        let mut http = Http::default();
+        let mut output = Output::default();
+
+        http.input(
+            b"POST /gate.php HTTP/1.1\r\nHost: c2.example\r\nContent-Length: 4\r\n\r\nid=1GET / \
+              HTTP/1.1\r\n\r\n",
+            &mut output,
+        );
+
+        let targets = output
+            .requests
+            .iter()
+            .map(|v| match v {
+                ForwardedRequest::Http { target, .. } => target.to_string(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(targets, ["/gate.php", "/"]);
+        assert!(!output.close);
+    }
+}
@@ -0,0 +1,292 @@
+//! Plays along as an SMTP server that accepts any login and queues any message, which is all a
+//! spammer needs to see to carry on sending.
+
+use std::fmt::Write;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pisshoff_types::audit::ForwardedRequest;
+
+use crate::subsystem::sinkhole::Output;
+
+/// Longest line we'll buffer while waiting for its end, as per RFC 5321 with some leeway.
+const MAX_LINE: usize = 4096;
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Command,
+    /// Waiting for the message, which ends with a line holding a single `.`.
+    Data,
+    /// Waiting for `AUTH PLAIN`'s credentials, which weren't sent along with the command.
+    AuthPlain,
+    AuthLoginUsername,
+    AuthLoginPassword(String),
+}
+
+#[derive(Debug)]
+pub struct Smtp {
+    hostname: String,
+    state: State,
+    line: Vec<u8>,
+    helo: Option<String>,
+    from: Option<String>,
+    recipients: Vec<String>,
+    subject: Option<String>,
+    size: usize,
+}
+
+impl Smtp {
+    pub fn new(hostname: &str) -> Self {
+        Self {
+            hostname: hostname.to_string(),
+            state: State::Command,
+            line: Vec::new(),
+            helo: None,
+            from: None,
+            recipients: Vec::new(),
+            subject: None,
+            size: 0,
+        }
+    }
+
+    /// SMTP servers speak first, so this is sent as soon as the channel is opened.
+    pub fn greeting(&self) -> Vec<u8> {
+        format!("220 {} ESMTP Postfix\r\n", self.hostname).into_bytes()
+    }
+
+    pub fn input(&mut self, data: &[u8], output: &mut Output) {
+        for &c in data {
+            if c != b'\n' {
+                if self.line.len() < MAX_LINE {
+                    self.line.push(c);
+                }
+
+                continue;
+            }
+
+            let line = std::mem::take(&mut self.line);
+            let line = String::from_utf8_lossy(&line);
+            self.line_received(line.trim_end_matches('\r'), output);
+
+            if output.close {
+                return;
+            }
+        }
+    }
+
+    fn line_received(&mut self, line: &str, output: &mut Output) {
+        let reply = match std::mem::take(&mut self.state) {
+            State::Command => self.command(line, output),
+            State::Data => self.data(line, output),
+            State::AuthPlain => self.auth_plain(line, output),
+            State::AuthLoginUsername => {
+                self.state = State::AuthLoginPassword(decode(line));
+                "334 UGFzc3dvcmQ6".to_string()
+            }
+            State::AuthLoginPassword(username) => {
+                output.requests.push(ForwardedRequest::SmtpLogin {
+                    username: username.into(),
+                    password: decode(line).into(),
+                });
+                "235 2.7.0 Authentication successful".to_string()
+            }
+        };
+
+        if !reply.is_empty() {
+            output.reply.extend_from_slice(reply.as_bytes());
+            output.reply.extend_from_slice(b"\r\n");
+        }
+    }
+
+    fn command(&mut self, line: &str, output: &mut Output) -> String {
+        let (verb, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        match verb.to_ascii_uppercase().as_str() {
+            "EHLO" => {
+                self.helo = Some(argument.to_string());
+
+                let mut reply = format!("250-{}\r\n", self.hostname);
+                for extension in [
+                    "PIPELINING",
+                    "SIZE 10240000",
+                    "AUTH PLAIN LOGIN",
+                    "8BITMIME",
+                ] {
+                    writeln!(reply, "250-{extension}\r").unwrap();
+                }
+                reply.push_str("250 SMTPUTF8");
+                reply
+            }
+            "HELO" => {
+                self.helo = Some(argument.to_string());
+                format!("250 {}", self.hostname)
+            }
+            "AUTH" => {
+                let (mechanism, initial) = argument.split_once(' ').unwrap_or((argument, ""));
+
+                match (mechanism.to_ascii_uppercase().as_str(), initial) {
+                    ("PLAIN", "") => {
+                        self.state = State::AuthPlain;
+                        "334 ".to_string()
+                    }
+                    ("PLAIN", credentials) => self.auth_plain(credentials, output),
+                    ("LOGIN", "") => {
+                        self.state = State::AuthLoginUsername;
+                        "334 VXNlcm5hbWU6".to_string()
+                    }
+                    ("LOGIN", username) => {
+                        self.state = State::AuthLoginPassword(decode(username));
+                        "334 UGFzc3dvcmQ6".to_string()
+                    }
+                    _ => "504 5.5.4 Unrecognized authentication type".to_string(),
+                }
+            }
+            "MAIL" => {
+                self.reset();
+                self.from = Some(address(argument).to_string());
+                "250 2.1.0 Ok".to_string()
+            }
+            "RCPT" if self.from.is_none() => "503 5.5.1 Error: need MAIL command".to_string(),
+            "RCPT" => {
+                self.recipients.push(address(argument).to_string());
+                "250 2.1.5 Ok".to_string()
+            }
+            "DATA" if self.recipients.is_empty() => {
+                "554 5.5.1 Error: no valid recipients".to_string()
+            }
+            "DATA" => {
+                self.state = State::Data;
+                "354 End data with <CR><LF>.<CR><LF>".to_string()
+            }
+            "RSET" => {
+                self.reset();
+                "250 2.0.0 Ok".to_string()
+            }
+            "NOOP" => "250 2.0.0 Ok".to_string(),
+            "STARTTLS" => "454 4.7.0 TLS not available due to local problem".to_string(),
+            "QUIT" => {
+                output.close = true;
+                "221 2.0.0 Bye".to_string()
+            }
+            "" => String::new(),
+            _ => "502 5.5.2 Error: command not recognized".to_string(),
+        }
+    }
+
+    fn data(&mut self, line: &str, output: &mut Output) -> String {
+        if line != "." {
+            if self.subject.is_none() {
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("subject") {
+                        self.subject = Some(value.trim().to_string());
+                    }
+                }
+            }
+
+            self.size += line.len() + 2;
+            self.state = State::Data;
+            return String::new();
+        }
+
+        output.requests.push(ForwardedRequest::SmtpMessage {
+            helo: self.helo.clone().map(Box::from),
+            from: self.from.take().unwrap_or_default().into(),
+            recipients: std::mem::take(&mut self.recipients)
+                .into_iter()
+                .map(Box::from)
+                .collect(),
+            subject: self.subject.take().map(Box::from),
+            size: std::mem::take(&mut self.size),
+        });
+
+        format!("250 2.0.0 Ok: queued as {:010X}", fastrand::u64(..) >> 24)
+    }
+
+    fn auth_plain(&mut self, credentials: &str, output: &mut Output) -> String {
+        // authorisation identity, then authentication identity and password, separated by NULs
+        let decoded = decode(credentials);
+        let mut parts = decoded.split('\0').skip(1);
+
+        output.requests.push(ForwardedRequest::SmtpLogin {
+            username: parts.next().unwrap_or_default().into(),
+            password: parts.next().unwrap_or_default().into(),
+        });
+
+        "235 2.7.0 Authentication successful".to_string()
+    }
+
+    fn reset(&mut self) {
+        self.from = None;
+        self.recipients.clear();
+        self.subject = None;
+        self.size = 0;
+    }
+}
+
+/// Pulls the address out of `FROM:<address>` or `TO:<address>`, along with any parameters.
+fn address(argument: &str) -> &str {
+    let address = argument
+        .split_once(':')
+        .map_or(argument, |(_, address)| address)
+        .trim();
+    let address = address.split(' ').next().unwrap_or_default();
+    address.trim_start_matches('<').trim_end_matches('>')
+}
+
+fn decode(value: &str) -> String {
+    STANDARD.decode(value.trim()).map_or_else(
+        |_| value.to_string(),
+        |v| String::from_utf8_lossy(&v).into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::ForwardedRequest;
+
+    use crate::subsystem::sinkhole::{smtp::Smtp, Output};
+
+    #[test]
+    fn login() {
+        let mut smtp = Smtp::new("mail.example.com");
+        let mut output = Output::default();
+
+        smtp.input(b"EHLO spam\r\nAUTH LOGIN\r\nYWRtaW4=\r\n", &mut output);
+        smtp.input(b"aHVudGVyMg==\r\n", &mut output);
+
+        assert_eq!(
+            output.requests,
+            [ForwardedRequest::SmtpLogin {
+                username: "admin".into(),
+                password: "hunter2".into(),
+            }]
+        );
+        assert!(String::from_utf8_lossy(&output.reply)
+            .ends_with("334 UGFzc3dvcmQ6\r\n235 2.7.0 Authentication successful\r\n"));
+    }
+
+    #[test]
+    fn message() {
+        let mut smtp = Smtp::new("mail.example.com");
+        let mut output = Output::default();
+
+        smtp.input(
+            b"HELO spam\r\nMAIL FROM:<a@example.com> SIZE=10\r\nRCPT TO:<b@example.com>\r\n\
+              RCPT TO:<c@example.com>\r\nDATA\r\nSubject: Invoice\r\n\r\nhello\r\n.\r\nQUIT\r\n",
+            &mut output,
+        );
+
+        assert_eq!(
+            output.requests,
+            [ForwardedRequest::SmtpMessage {
+                helo: Some("spam".into()),
+                from: "a@example.com".into(),
+                recipients: vec!["b@example.com".into(), "c@example.com".into()],
+                subject: Some("Invoice".into()),
+                size: 27,
+            }]
+        );
+        assert!(output.close);
+    }
+}
@@ -0,0 +1,190 @@
+//! Answers the SOCKS5 handshake of clients hoping to use us as a proxy, as if we'd gone on to
+//! make the connection for them.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use pisshoff_types::audit::ForwardedRequest;
+
+use crate::subsystem::sinkhole::Output;
+
+const VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Greeting,
+    /// Waiting for the username and password, as per RFC 1929.
+    Authentication,
+    Request,
+}
+
+#[derive(Debug, Default)]
+pub struct Socks {
+    state: State,
+    buffer: Vec<u8>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Socks {
+    pub fn input(&mut self, data: &[u8], output: &mut Output) {
+        self.buffer.extend_from_slice(data);
+
+        loop {
+            let consumed = match self.state {
+                State::Greeting => self.greeting(output),
+                State::Authentication => self.authentication(output),
+                State::Request => self.request(output),
+            };
+
+            match consumed {
+                Some(consumed) => {
+                    self.buffer.drain(..consumed);
+                }
+                None => return,
+            }
+
+            if output.close {
+                return;
+            }
+
+            if output.tunnel.is_some() {
+                output.tunnel = Some(std::mem::take(&mut self.buffer));
+                return;
+            }
+        }
+    }
+
+    /// Handles the client's greeting, returning the number of bytes it took up once it's all
+    /// arrived.
+    fn greeting(&mut self, output: &mut Output) -> Option<usize> {
+        let [version, count, ..] = self.buffer[..] else {
+            return None;
+        };
+        let methods = self.buffer.get(2..2 + usize::from(count))?;
+
+        if version != VERSION {
+            output.close = true;
+            return Some(self.buffer.len());
+        }
+
+        // ask for credentials whenever the client has some to offer
+        let method = if methods.contains(&USERNAME_PASSWORD) {
+            self.state = State::Authentication;
+            USERNAME_PASSWORD
+        } else if methods.contains(&NO_AUTHENTICATION) {
+            self.state = State::Request;
+            NO_AUTHENTICATION
+        } else {
+            output.close = true;
+            NO_ACCEPTABLE_METHODS
+        };
+
+        output.reply.extend_from_slice(&[VERSION, method]);
+        Some(2 + usize::from(count))
+    }
+
+    fn authentication(&mut self, output: &mut Output) -> Option<usize> {
+        let username_len = usize::from(*self.buffer.get(1)?);
+        let username = self.buffer.get(2..2 + username_len)?;
+        let password_len = usize::from(*self.buffer.get(2 + username_len)?);
+        let password = self
+            .buffer
+            .get(3 + username_len..3 + username_len + password_len)?;
+
+        self.username = Some(String::from_utf8_lossy(username).into_owned());
+        self.password = Some(String::from_utf8_lossy(password).into_owned());
+        self.state = State::Request;
+
+        output.reply.extend_from_slice(&[0x01, 0x00]);
+        Some(3 + username_len + password_len)
+    }
+
+    fn request(&mut self, output: &mut Output) -> Option<usize> {
+        let [_version, _command, _reserved, address_type, ..] = self.buffer[..] else {
+            return None;
+        };
+
+        let (host, address_len) = match address_type {
+            0x01 => {
+                let address: [u8; 4] = self.buffer.get(4..8)?.try_into().ok()?;
+                (Ipv4Addr::from(address).to_string(), 4)
+            }
+            0x03 => {
+                let len = usize::from(*self.buffer.get(4)?);
+                let name = self.buffer.get(5..5 + len)?;
+                (String::from_utf8_lossy(name).into_owned(), len + 1)
+            }
+            0x04 => {
+                let address: [u8; 16] = self.buffer.get(4..20)?.try_into().ok()?;
+                (Ipv6Addr::from(address).to_string(), 16)
+            }
+            _ => {
+                // address type not supported
+                output
+                    .reply
+                    .extend_from_slice(&[VERSION, 0x08, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+                output.close = true;
+                return Some(self.buffer.len());
+            }
+        };
+
+        let port = self.buffer.get(4 + address_len..6 + address_len)?;
+        let port = u16::from_be_bytes([port[0], port[1]]);
+
+        output.requests.push(ForwardedRequest::Socks {
+            host: host.into(),
+            port,
+            username: self.username.take().map(Box::from),
+            password: self.password.take().map(Box::from),
+        });
+
+        // succeeded, bound to 0.0.0.0:0
+        output
+            .reply
+            .extend_from_slice(&[VERSION, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+        output.tunnel = Some(Vec::new());
+        Some(6 + address_len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pisshoff_types::audit::ForwardedRequest;
+
+    use crate::subsystem::sinkhole::{socks::Socks, Output};
+
+    #[test]
+    fn connect() {
+        let mut socks = Socks::default();
+        let mut output = Output::default();
+
+        socks.input(b"\x05\x02\x00\x02", &mut output);
+        assert_eq!(output.reply, b"\x05\x02");
+
+        socks.input(
+            b"\x01\x04user\x07hunter2\x05\x01\x00\x03\x0bexample.com",
+            &mut output,
+        );
+        assert!(output.requests.is_empty());
+
+        socks.input(b"\x01\xbbGET", &mut output);
+        assert_eq!(
+            output.requests,
+            [ForwardedRequest::Socks {
+                host: "example.com".into(),
+                port: 443,
+                username: Some("user".into()),
+                password: Some("hunter2".into()),
+            }]
+        );
+        assert_eq!(
+            output.reply,
+            b"\x05\x02\x01\x00\x05\x00\x00\x01\x00\x00\x00\x00\x00\x00"
+        );
+        assert_eq!(output.tunnel.as_deref(), Some(b"GET".as_slice()));
+    }
+}
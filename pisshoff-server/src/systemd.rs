@@ -0,0 +1,121 @@
+//! Integration with systemd, so the server can be run as a socket activated `Type=notify`
+//! service. Socket activation lets systemd bind port 22 on our behalf, meaning the server
+//! itself never needs to run as root.
+
+use std::{
+    net::{SocketAddr, TcpListener},
+    os::{
+        fd::{FromRawFd, RawFd},
+        unix::net::UnixDatagram,
+    },
+};
+
+use tracing::warn;
+
+/// The first file descriptor passed by systemd, as defined by `sd_listen_fds(3)`.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Takes ownership of the sockets passed to us by systemd, if we were socket activated. The
+/// environment variables are cleared so they aren't inherited by anything we spawn.
+pub fn listen_fds() -> Result<Vec<TcpListener>, std::io::Error> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    // the variables are meant for a different process if the pid doesn't match
+    let (Some(pid), Some(fds)) = (pid, fds) else {
+        return Ok(Vec::new());
+    };
+
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(Vec::new());
+    }
+
+    let count = fds.parse::<RawFd>().unwrap_or_default();
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // SAFETY: systemd hands ownership of these descriptors over to us, and nothing
+            // else in the process knows about them
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+            // make sure we've actually been given a TCP socket
+            listener.local_addr()?;
+            listener.set_nonblocking(true)?;
+
+            Ok(listener)
+        })
+        .collect()
+}
+
+/// Finds the socket passed by systemd that should be used for the listener on `address`,
+/// preferring an exact match and falling back to one bound to the same port.
+pub fn take_listener(inherited: &mut Vec<TcpListener>, address: SocketAddr) -> Option<TcpListener> {
+    let local_addr = |v: &TcpListener| v.local_addr().ok();
+
+    let index = inherited
+        .iter()
+        .position(|v| local_addr(v) == Some(address))
+        .or_else(|| {
+            inherited
+                .iter()
+                .position(|v| local_addr(v).map(|v| v.port()) == Some(address.port()))
+        })?;
+
+    Some(inherited.remove(index))
+}
+
+/// Sends a state change such as `READY=1` to the service manager, doing nothing if we weren't
+/// started by one.
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let send = || -> Result<usize, std::io::Error> {
+        let socket = UnixDatagram::unbound()?;
+
+        #[cfg(target_os = "linux")]
+        if let Some(name) = std::os::unix::ffi::OsStrExt::as_bytes(&*path).strip_prefix(b"@") {
+            use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr as UnixSocketAddr};
+
+            let address = UnixSocketAddr::from_abstract_name(name)?;
+            return socket.send_to_addr(state.as_bytes(), &address);
+        }
+
+        socket.send_to(state.as_bytes(), &path)
+    };
+
+    if let Err(error) = send() {
+        warn!(%error, state, "Failed to notify service manager");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{SocketAddr, TcpListener};
+
+    use super::take_listener;
+
+    #[test]
+    fn matches_listeners() {
+        let a = TcpListener::bind("127.0.0.1:0").unwrap();
+        let b = TcpListener::bind("127.0.0.1:0").unwrap();
+        let a_addr = a.local_addr().unwrap();
+        let b_addr = b.local_addr().unwrap();
+        let mut inherited = vec![a, b];
+
+        let any_b = SocketAddr::from(([0, 0, 0, 0], b_addr.port()));
+        let taken = take_listener(&mut inherited, any_b).unwrap();
+        assert_eq!(taken.local_addr().unwrap(), b_addr);
+
+        assert!(take_listener(&mut inherited, any_b).is_none());
+
+        let taken = take_listener(&mut inherited, a_addr).unwrap();
+        assert_eq!(taken.local_addr().unwrap(), a_addr);
+        assert!(inherited.is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+//! An endlessh-style tarpit, which wastes the time of scanners by dripping an endless SSH banner
+//! at them. RFC 4253 lets servers send any number of lines before their version string as long
+//! as they don't start with `SSH-`, so clients keep waiting for one that never comes.
+
+use std::time::Duration;
+
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+use tracing::debug;
+
+use crate::config::TarpitConfig;
+
+/// Shortest line to send, including the trailing CR LF.
+const MIN_LINE_LENGTH: usize = 3;
+
+/// Sends random lines to the client until it disconnects, returning the number of bytes sent.
+pub async fn run(mut stream: TcpStream, config: &TarpitConfig) -> u64 {
+    let delay = Duration::from_millis(config.delay_ms);
+    let mut bytes_sent = 0;
+
+    loop {
+        tokio::time::sleep(delay).await;
+
+        let line = random_line(config.max_line_length);
+
+        if let Err(error) = stream.write_all(&line).await {
+            debug!(%error, bytes_sent, "Client escaped the tarpit");
+            return bytes_sent;
+        }
+
+        bytes_sent += line.len() as u64;
+    }
+}
+
+/// Generates a line of random printable characters, which is never mistaken for a version
+/// string.
+fn random_line(max_length: usize) -> Vec<u8> {
+    let length = fastrand::usize(MIN_LINE_LENGTH..=max_length.max(MIN_LINE_LENGTH));
+
+    let mut line: Vec<u8> = (0..length - 2).map(|_| fastrand::u8(b'!'..=b'~')).collect();
+
+    if line.starts_with(b"SSH-") {
+        line[0] = b'X';
+    }
+
+    line.extend_from_slice(b"\r\n");
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::random_line;
+
+    #[test]
+    fn generates_banner_lines() {
+        for _ in 0..1000 {
+            let line = random_line(32);
+
+            assert!((3..=32).contains(&line.len()), "{line:?}");
+            assert!(line.ends_with(b"\r\n"));
+            assert!(!line.starts_with(b"SSH-"));
+            assert!(line[..line.len() - 2].iter().all(u8::is_ascii_graphic));
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Exports the span each connection is traced under to an OpenTelemetry collector over OTLP, so
+//! sessions can be followed in Jaeger, Tempo or Grafana Cloud. Login attempts and commands are
+//! logged within that span, so they're exported along with it as span events.
+//!
+//! The exporter is only built with the `otlp` feature, without it configuring one is an error.
+
+#[cfg(feature = "otlp")]
+mod otlp;
+
+#[cfg(not(feature = "otlp"))]
+pub use disabled::{layer, shutdown, start, Handle};
+#[cfg(feature = "otlp")]
+pub use otlp::{layer, shutdown, start, Handle};
+
+#[cfg(not(feature = "otlp"))]
+mod disabled {
+    use anyhow::bail;
+    use tracing_subscriber::layer::Identity;
+
+    use crate::config::OtlpConfig;
+
+    /// Stands in for the handle the exporter would be installed with.
+    pub struct Handle;
+
+    pub fn layer() -> (Identity, Handle) {
+        (Identity::new(), Handle)
+    }
+
+    pub fn start(config: Option<&OtlpConfig>, _handle: &Handle) -> anyhow::Result<()> {
+        if config.is_some() {
+            bail!("pisshoff-server was built without the `otlp` feature");
+        }
+
+        Ok(())
+    }
+
+    pub fn shutdown() -> std::future::Ready<()> {
+        std::future::ready(())
+    }
+}
@@ -0,0 +1,105 @@
+//! Exports spans over OTLP, with the tonic exporter for gRPC and reqwest for HTTP.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    runtime,
+    trace::{self, Tracer},
+    Resource,
+};
+use tonic::{
+    metadata::{MetadataKey, MetadataMap},
+    transport::ClientTlsConfig,
+};
+use tracing::info;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::{reload, Registry};
+
+use crate::config::{OtlpConfig, OtlpProtocol};
+
+type Exporter = Option<OpenTelemetryLayer<Registry, Tracer>>;
+
+/// Layer the exporter is installed into once the config has been read, doing nothing until then.
+pub type Layer = reload::Layer<Exporter, Registry>;
+pub type Handle = reload::Handle<Exporter, Registry>;
+
+pub fn layer() -> (Layer, Handle) {
+    reload::Layer::new(None)
+}
+
+/// Starts exporting spans if configured to. Batches are exported from a task of their own, so
+/// this has to be called from within the runtime.
+pub fn start(config: Option<&OtlpConfig>, handle: &Handle) -> anyhow::Result<()> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+
+    let timeout = Duration::from_secs(config.timeout);
+    let pipeline = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_trace_config(trace::config().with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )])));
+
+    let tracer = match config.protocol {
+        OtlpProtocol::Grpc => {
+            let mut exporter = opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&config.endpoint)
+                .with_timeout(timeout)
+                .with_metadata(metadata(config)?);
+
+            if config.endpoint.starts_with("https://") {
+                exporter = exporter.with_tls_config(ClientTlsConfig::new());
+            }
+
+            pipeline
+                .with_exporter(exporter)
+                .install_batch(runtime::Tokio)
+        }
+        OtlpProtocol::HttpProtobuf => pipeline
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(&config.endpoint)
+                    .with_timeout(timeout)
+                    .with_headers(config.headers.clone().into_iter().collect()),
+            )
+            .install_batch(runtime::Tokio),
+    }
+    .context("failed to start OTLP exporter")?;
+
+    handle
+        .reload(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+        .context("failed to install OTLP exporter")?;
+
+    info!(endpoint = %config.endpoint, "Exporting traces over OTLP");
+
+    Ok(())
+}
+
+/// Exports any spans still waiting to be batched up. Shutting the provider down blocks until
+/// the export completes or times out, so it's done off the runtime's worker threads.
+pub async fn shutdown() {
+    let _res = tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await;
+}
+
+/// gRPC metadata to send with each export, built from the configured headers.
+fn metadata(config: &OtlpConfig) -> anyhow::Result<MetadataMap> {
+    let mut metadata = MetadataMap::new();
+
+    for (name, value) in &config.headers {
+        let key = MetadataKey::from_bytes(name.to_ascii_lowercase().as_bytes())
+            .with_context(|| format!("invalid OTLP header name {name:?}"))?;
+        let value = value
+            .parse()
+            .with_context(|| format!("invalid value for OTLP header {name}"))?;
+        metadata.insert(key, value);
+    }
+
+    Ok(metadata)
+}
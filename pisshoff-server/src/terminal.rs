@@ -0,0 +1,479 @@
+//! A minimal line discipline for sessions that have requested a PTY. Clients with a PTY send us
+//! raw keystrokes and expect us to do the echoing and line editing a kernel TTY would usually
+//! handle for them. Those without still need their input splitting into lines.
+
+use std::time::Instant;
+
+use thrussh::{
+    server::{Handle, Session},
+    ChannelId, CryptoVec, Pty as PtyMode,
+};
+
+use crate::{audit::KeystrokesEvent, recording::Recording, server::ThrusshSession, state::Tap};
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+const ESCAPE: u8 = 0x1b;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_U: u8 = 0x15;
+
+/// Most reads timed towards a single line, so a line that never ends can't grow the audit log
+/// without bound.
+const MAX_KEYSTROKES: usize = 1024;
+
+/// Terminal requested by a client via `pty-req`.
+#[derive(Debug, Clone)]
+pub struct Pty {
+    pub term: Box<str>,
+    pub col_width: u32,
+    pub row_height: u32,
+    pub echo: bool,
+}
+
+impl Pty {
+    pub fn new(term: &str, col_width: u32, row_height: u32, modes: &[(PtyMode, u32)]) -> Self {
+        let echo = modes
+            .iter()
+            .find(|(mode, _)| matches!(mode, PtyMode::ECHO))
+            .map_or(true, |(_, value)| *value != 0);
+
+        Self {
+            term: Box::from(term),
+            col_width,
+            row_height,
+            echo,
+        }
+    }
+}
+
+/// Input that has made it through the line discipline, ready to be handed to the shell.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Input {
+    /// A full line, including its trailing newline.
+    Line(Vec<u8>),
+    /// The client pressed Ctrl-C.
+    Interrupt,
+    /// The client pressed Ctrl-D on an empty line.
+    Eof,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    #[default]
+    None,
+    /// Received an `ESC`, waiting to see what kind of sequence follows.
+    Start,
+    /// Within a CSI sequence (`ESC [`), which is terminated by a byte in `0x40..=0x7e`.
+    Csi,
+}
+
+#[derive(Debug)]
+pub struct Terminal {
+    echo: bool,
+    line: Vec<u8>,
+    escape: Escape,
+    last_was_cr: bool,
+}
+
+impl Terminal {
+    pub fn new(pty: &Pty) -> Self {
+        Self {
+            echo: pty.echo,
+            line: Vec::new(),
+            escape: Escape::None,
+            last_was_cr: false,
+        }
+    }
+
+    /// Feeds raw keystrokes from the client through the line discipline, returning the bytes
+    /// that should be echoed back to the client along with any complete input.
+    pub fn input(&mut self, data: &[u8]) -> (Vec<u8>, Vec<Input>) {
+        let mut echo = Vec::new();
+        let mut input = Vec::new();
+
+        for &c in data {
+            let last_was_cr = std::mem::take(&mut self.last_was_cr);
+
+            match (self.escape, c) {
+                (Escape::Start, b'[') => {
+                    self.escape = Escape::Csi;
+                    continue;
+                }
+                (Escape::Start, _) => {
+                    // two character sequence, such as alt + key
+                    self.escape = Escape::None;
+                    continue;
+                }
+                (Escape::Csi, 0x40..=0x7e) => {
+                    // we don't support cursor movement, so arrow keys and friends are dropped
+                    self.escape = Escape::None;
+                    continue;
+                }
+                (Escape::Csi, _) => continue,
+                (Escape::None, _) => {}
+            }
+
+            match c {
+                b'\n' if last_was_cr => {}
+                b'\r' | b'\n' => {
+                    self.last_was_cr = c == b'\r';
+                    echo.extend_from_slice(b"\r\n");
+
+                    let mut line = std::mem::take(&mut self.line);
+                    line.push(b'\n');
+                    input.push(Input::Line(line));
+                }
+                DELETE | BACKSPACE => {
+                    if self.pop_char() {
+                        echo.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+                CTRL_U => {
+                    while self.pop_char() {
+                        echo.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+                CTRL_C => {
+                    echo.extend_from_slice(b"^C\r\n");
+                    self.line.clear();
+                    input.push(Input::Interrupt);
+                }
+                CTRL_D => {
+                    if self.line.is_empty() {
+                        input.push(Input::Eof);
+                    }
+                }
+                ESCAPE => self.escape = Escape::Start,
+                b'\t' => {}
+                c if c < 0x20 => {}
+                c => {
+                    self.line.push(c);
+                    echo.push(c);
+                }
+            }
+        }
+
+        if !self.echo {
+            echo.clear();
+        }
+
+        (echo, input)
+    }
+
+    /// Whether input is echoed back to the client.
+    pub fn echoes(&self) -> bool {
+        self.echo
+    }
+
+    /// Removes the last character from the line buffer, taking care to remove the entirety of
+    /// multibyte UTF-8 characters. Returns `false` if the line was already empty.
+    fn pop_char(&mut self) -> bool {
+        while let Some(c) = self.line.pop() {
+            // continuation bytes take the form 0b10xxxxxx
+            if c & 0xc0 != 0x80 {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Splits input from clients without a PTY into lines. They're free to spread a command across
+/// as many packets as they like, or send several in one, so nothing is run until its newline
+/// arrives. Lines are only ever split on ASCII, so multibyte characters that straddle two
+/// packets are kept whole.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    line: Vec<u8>,
+    last_was_cr: bool,
+}
+
+impl LineBuffer {
+    /// Buffers `data`, returning any lines it completed with their endings normalised to `\n`.
+    pub fn input(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+
+        for &c in data {
+            let last_was_cr = std::mem::take(&mut self.last_was_cr);
+
+            match c {
+                b'\n' if last_was_cr => {}
+                b'\r' | b'\n' => {
+                    self.last_was_cr = c == b'\r';
+
+                    let mut line = std::mem::take(&mut self.line);
+                    line.push(b'\n');
+                    lines.push(line);
+                }
+                c => self.line.push(c),
+            }
+        }
+
+        lines
+    }
+
+    /// Takes whatever has been buffered towards the next line.
+    pub fn take(&mut self) -> Option<Vec<u8>> {
+        Some(std::mem::take(&mut self.line)).filter(|v| !v.is_empty())
+    }
+}
+
+/// Times the reads of input from a client with a PTY, which tells people typing apart from
+/// scripts pasting in whole commands.
+#[derive(Debug)]
+pub struct Keystrokes {
+    last: Instant,
+    intervals_ms: Vec<u32>,
+    sizes: Vec<u32>,
+}
+
+impl Keystrokes {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            last: start,
+            intervals_ms: Vec::new(),
+            sizes: Vec::new(),
+        }
+    }
+
+    /// Records a read of `len` bytes at `at`.
+    pub fn input(&mut self, at: Instant, len: usize) {
+        if self.intervals_ms.len() < MAX_KEYSTROKES {
+            let interval = at.saturating_duration_since(self.last).as_millis();
+            self.intervals_ms
+                .push(u32::try_from(interval).unwrap_or(u32::MAX));
+            self.sizes.push(u32::try_from(len).unwrap_or(u32::MAX));
+        }
+
+        self.last = at;
+    }
+
+    /// Takes the timing of the reads towards the line that's just been entered, if there were
+    /// any.
+    pub fn take(&mut self) -> Option<KeystrokesEvent> {
+        if self.intervals_ms.is_empty() {
+            return None;
+        }
+
+        let intervals_ms = std::mem::take(&mut self.intervals_ms);
+        let mut sizes = std::mem::take(&mut self.sizes);
+
+        if sizes.iter().all(|v| *v == 1) {
+            sizes.clear();
+        }
+
+        Some(KeystrokesEvent {
+            intervals_ms,
+            sizes,
+        })
+    }
+}
+
+/// Where a shell's output goes, which is an SSH channel unless the client came in over telnet.
+pub trait ShellSession: ThrusshSession + Send {
+    fn exit_status_request(&mut self, channel: ChannelId, exit_status: u32);
+
+    fn eof(&mut self, channel: ChannelId);
+
+    fn close(&mut self, channel: ChannelId);
+
+    /// Handle to write to the client outside of a handler, for an operator to take over the
+    /// session with, if the session supports it.
+    fn handle(&self) -> Option<Handle>;
+}
+
+impl ShellSession for Session {
+    fn exit_status_request(&mut self, channel: ChannelId, exit_status: u32) {
+        Session::exit_status_request(self, channel, exit_status);
+    }
+
+    fn eof(&mut self, channel: ChannelId) {
+        Session::eof(self, channel);
+    }
+
+    fn close(&mut self, channel: ChannelId) {
+        Session::close(self, channel);
+    }
+
+    fn handle(&self) -> Option<Handle> {
+        Some(Session::handle(self))
+    }
+}
+
+/// Wraps a session, translating newlines in outgoing data to `\r\n` as a TTY would if the
+/// session has a PTY attached to it, and recording all output if the connection is being
+/// recorded or watched.
+pub struct TerminalSession<'a> {
+    session: &'a mut dyn ShellSession,
+    translate_newlines: bool,
+    recording: Option<Recording>,
+    tap: Tap,
+}
+
+impl<'a> TerminalSession<'a> {
+    pub fn new(
+        session: &'a mut dyn ShellSession,
+        translate_newlines: bool,
+        recording: Option<Recording>,
+        tap: Tap,
+    ) -> Self {
+        Self {
+            session,
+            translate_newlines,
+            recording,
+            tap,
+        }
+    }
+
+    pub fn exit_status_request(&mut self, channel: ChannelId, exit_status: u32) {
+        self.session.exit_status_request(channel, exit_status);
+    }
+
+    pub fn eof(&mut self, channel: ChannelId) {
+        self.session.eof(channel);
+    }
+
+    pub fn close(&mut self, channel: ChannelId) {
+        self.session.close(channel);
+    }
+}
+
+impl ThrusshSession for TerminalSession<'_> {
+    fn data(&mut self, channel: ChannelId, data: CryptoVec) {
+        let data = if self.translate_newlines {
+            CryptoVec::from_slice(&translate_newlines(&data))
+        } else {
+            data
+        };
+
+        if let Some(recording) = &self.recording {
+            recording.output(&data);
+        }
+
+        self.tap.output(&data);
+        self.session.data(channel, data);
+    }
+}
+
+pub fn translate_newlines(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+
+    for (i, &c) in data.iter().enumerate() {
+        if c == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+
+        out.push(c);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use test_case::test_case;
+
+    use super::{translate_newlines, Input, Keystrokes, LineBuffer, Pty, Terminal};
+    use crate::audit::KeystrokesEvent;
+
+    fn terminal(echo: bool) -> Terminal {
+        Terminal::new(&Pty {
+            term: Box::from("xterm"),
+            col_width: 80,
+            row_height: 24,
+            echo,
+        })
+    }
+
+    #[test_case(b"ls\r", b"ls\r\n", &[Input::Line(b"ls\n".to_vec())]; "simple line")]
+    #[test_case(b"ls\r\n", b"ls\r\n", &[Input::Line(b"ls\n".to_vec())]; "crlf")]
+    #[test_case(b"lx\x7fs\r", b"lx\x08 \x08s\r\n", &[Input::Line(b"ls\n".to_vec())]; "backspace")]
+    #[test_case(b"\x7f", b"", &[]; "backspace empty line")]
+    #[test_case(b"\xc3\xa9\x7f\r", b"\xc3\xa9\x08 \x08\r\n", &[Input::Line(b"\n".to_vec())]; "backspace multibyte")]
+    #[test_case(b"ls\x15pwd\r", b"ls\x08 \x08\x08 \x08pwd\r\n", &[Input::Line(b"pwd\n".to_vec())]; "kill line")]
+    #[test_case(b"sleep\x03", b"sleep^C\r\n", &[Input::Interrupt]; "interrupt")]
+    #[test_case(b"\x04", b"", &[Input::Eof]; "eof")]
+    #[test_case(b"a\x04", b"a", &[]; "eof with pending input")]
+    #[test_case(b"\x1b[A\x1b[1;5Dls\r", b"ls\r\n", &[Input::Line(b"ls\n".to_vec())]; "escape sequences")]
+    fn input(data: &[u8], expected_echo: &[u8], expected_input: &[Input]) {
+        let (echo, input) = terminal(true).input(data);
+        assert_eq!(echo, expected_echo);
+        assert_eq!(input, expected_input);
+    }
+
+    #[test]
+    fn split_across_packets() {
+        let mut terminal = terminal(true);
+
+        assert_eq!(terminal.input(b"l").1, &[]);
+        assert_eq!(terminal.input(b"s\r").1, &[Input::Line(b"ls\n".to_vec())]);
+        assert_eq!(terminal.input(b"\n").1, &[]);
+    }
+
+    #[test]
+    fn no_echo() {
+        let (echo, input) = terminal(false).input(b"hunter2\r");
+        assert_eq!(echo, b"");
+        assert_eq!(input, &[Input::Line(b"hunter2\n".to_vec())]);
+    }
+
+    #[test]
+    fn line_buffer() {
+        let mut buffer = LineBuffer::default();
+
+        assert!(buffer.input(b"u").is_empty());
+        assert!(buffer.input(b"name -a").is_empty());
+        assert_eq!(buffer.input(b"\r"), &[b"uname -a\n".to_vec()]);
+        assert_eq!(
+            buffer.input(b"\nid\nw"),
+            &[b"id\n".to_vec()],
+            "crlf split across packets"
+        );
+        assert_eq!(buffer.input(b"\xc3"), Vec::<Vec<u8>>::new());
+        assert_eq!(buffer.input(b"\xa9\n"), &[b"w\xc3\xa9\n".to_vec()]);
+        assert_eq!(buffer.input(b"cat"), Vec::<Vec<u8>>::new());
+        assert_eq!(buffer.take(), Some(b"cat".to_vec()));
+        assert_eq!(buffer.take(), None);
+    }
+
+    #[test_case(b"a\nb", b"a\r\nb"; "bare newline")]
+    #[test_case(b"a\r\nb", b"a\r\nb"; "already translated")]
+    #[test_case(b"\n", b"\r\n"; "leading newline")]
+    fn translate(data: &[u8], expected: &[u8]) {
+        assert_eq!(translate_newlines(data), expected);
+    }
+
+    #[test]
+    fn times_keystrokes() {
+        let start = Instant::now();
+        let mut keystrokes = Keystrokes::new(start);
+
+        assert_eq!(keystrokes.take(), None);
+
+        for (ms, len) in [(1500, 1), (1620, 1), (1700, 1)] {
+            keystrokes.input(start + Duration::from_millis(ms), len);
+        }
+
+        assert_eq!(
+            keystrokes.take(),
+            Some(KeystrokesEvent {
+                intervals_ms: vec![1500, 120, 80],
+                sizes: vec![],
+            })
+        );
+
+        keystrokes.input(start + Duration::from_millis(2000), 9);
+
+        assert_eq!(
+            keystrokes.take(),
+            Some(KeystrokesEvent {
+                intervals_ms: vec![300],
+                sizes: vec![9],
+            })
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! Checks the hash chain running through audit logs written by the file sink, reporting any
+//! lines that have been edited, removed or reordered since they were written, along with any
+//! checkpoints that weren't signed by the expected key.
+
+use std::{
+    collections::HashMap,
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use thrussh_keys::key::PublicKey;
+
+use crate::{
+    audit::sha256_hex,
+    chain::{self, LinkHeader, GENESIS},
+    report,
+};
+
+/// Checks the chain through each audit log in `paths`, each of which can be a file or a
+/// directory of them, verifying checkpoints against the OpenSSH public key in `public_key` if
+/// one's given.
+pub fn run(paths: &[PathBuf], public_key: Option<&Path>) -> anyhow::Result<()> {
+    let key = public_key.map(read_public_key).transpose()?;
+
+    let mut files = Vec::new();
+
+    for path in paths {
+        for file in report::files(path)? {
+            let reader = report::open(&file)
+                .with_context(|| format!("failed to open {}", file.display()))?;
+            let summary = verify(reader, key.as_ref())
+                .with_context(|| format!("failed to read {}", file.display()))?;
+            files.push((file, summary));
+        }
+    }
+
+    let mut problems = 0;
+
+    for (file, summary) in &files {
+        match (&summary.first, &summary.last) {
+            (Some(first), Some(last)) => println!(
+                "{}: chained from {} to {}, {} checkpoints",
+                file.display(),
+                first.seq,
+                last.seq,
+                summary.checkpoints
+            ),
+            _ => println!("{}: not chained", file.display()),
+        }
+
+        for problem in &summary.problems {
+            println!("  {problem}");
+        }
+
+        if let Some(problem) = follows_on(summary, &files) {
+            println!("  {problem}");
+            problems += 1;
+        }
+
+        problems += summary.problems.len();
+    }
+
+    if key.is_none() && files.iter().any(|(_, v)| v.checkpoints > 0) {
+        println!("Checkpoint signatures weren't checked, pass --public-key to check them");
+    }
+
+    if problems > 0 {
+        return Err(anyhow!("found {problems} problems with the chain"));
+    }
+
+    Ok(())
+}
+
+/// Reads the key out of an OpenSSH public key file, ie. `ssh-ed25519 AAAA... comment`.
+fn read_public_key(path: &Path) -> anyhow::Result<PublicKey> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    contents
+        .split_whitespace()
+        .nth(1)
+        .and_then(|key| thrussh_keys::parse_public_key_base64(key).ok())
+        .ok_or_else(|| anyhow!("{} isn't an OpenSSH public key", path.display()))
+}
+
+/// Where a line sits in the chain.
+#[derive(Clone)]
+struct Position {
+    seq: u64,
+    /// Hash of the line the chain says came before it.
+    prev_hash: Box<str>,
+    /// Hash of the line itself.
+    hash: Box<str>,
+}
+
+#[derive(Default)]
+struct Summary {
+    /// The first chained line in the file.
+    first: Option<Position>,
+    /// The last chained line in the file.
+    last: Option<Position>,
+    checkpoints: usize,
+    problems: Vec<String>,
+}
+
+/// Checks each chained line in `reader` links to the line before it and that each checkpoint
+/// was signed by `key`.
+fn verify(reader: impl BufRead, key: Option<&PublicKey>) -> std::io::Result<Summary> {
+    let mut summary = Summary::default();
+    let mut prev_hash = None;
+
+    for (i, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        let number = i + 1;
+
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        let hash = sha256_hex(&line);
+
+        let Ok(link) = serde_json::from_slice::<LinkHeader>(&line) else {
+            if summary.first.is_some() {
+                summary
+                    .problems
+                    .push(format!("line {number} isn't part of the chain"));
+            }
+
+            prev_hash = Some(hash);
+            continue;
+        };
+
+        if let Some(prev_hash) = &prev_hash {
+            if link.prev_hash != *prev_hash {
+                summary.problems.push(format!(
+                    "line {number} doesn't follow on from the line before it"
+                ));
+            }
+        }
+
+        if let Some(last) = &summary.last {
+            // the count starts again when the chain picks up from a line it can't read
+            if link.seq != last.seq + 1 && link.seq != 0 {
+                summary.problems.push(format!(
+                    "line {number} is number {} in the chain, expected {}",
+                    link.seq,
+                    last.seq + 1
+                ));
+            }
+        }
+
+        if let Some(checkpoint) = &link.checkpoint {
+            summary.checkpoints += 1;
+
+            if let Some(key) = key {
+                if !chain::verify(key, link.seq, &link.prev_hash, checkpoint) {
+                    summary.problems.push(format!(
+                        "line {number} is a checkpoint that wasn't signed by the given key"
+                    ));
+                }
+            }
+        }
+
+        let position = Position {
+            seq: link.seq,
+            prev_hash: link.prev_hash,
+            hash: hash.clone(),
+        };
+
+        if summary.first.is_none() {
+            summary.first = Some(position.clone());
+        }
+
+        summary.last = Some(position);
+        prev_hash = Some(hash);
+    }
+
+    Ok(summary)
+}
+
+/// Checks the first line of the file in `summary` follows on from the end of whichever of
+/// `files` came before it, if it was given. Files can be given in any order, as logs are rotated
+/// into files that sort after the one currently being written.
+fn follows_on(summary: &Summary, files: &[(PathBuf, Summary)]) -> Option<String> {
+    let first = summary.first.as_ref()?;
+
+    if first.seq == 0 || &*first.prev_hash == GENESIS {
+        return None;
+    }
+
+    let ends = files
+        .iter()
+        .filter_map(|(file, v)| Some((file, v.last.as_ref()?)))
+        .filter(|(_, last)| last.seq + 1 == first.seq)
+        .map(|(file, last)| (&*last.hash, file))
+        .collect::<HashMap<_, _>>();
+
+    if ends.is_empty() || ends.contains_key(&*first.prev_hash) {
+        // the file before it wasn't given, which is normal once old logs have been pruned
+        return None;
+    }
+
+    Some(format!(
+        "doesn't follow on from the end of {}",
+        itertools::join(ends.values().map(|v| v.display()), " or ")
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::verify;
+    use crate::{audit::AuditLog, chain::Chain};
+
+    fn chain(len: usize) -> Vec<String> {
+        let mut chain = Chain::default();
+
+        (0..len)
+            .map(|_| String::from_utf8(chain.push(&AuditLog::default()).unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_untouched_chain() {
+        let summary = verify(chain(3).concat().as_bytes(), None).unwrap();
+
+        assert!(summary.problems.is_empty(), "{:?}", summary.problems);
+        assert_eq!(summary.first.unwrap().seq, 0);
+        assert_eq!(summary.last.unwrap().seq, 2);
+    }
+
+    #[test]
+    fn finds_tampering() {
+        let mut removed = chain(3);
+        removed.remove(1);
+        let summary = verify(removed.concat().as_bytes(), None).unwrap();
+        assert_eq!(
+            summary.problems,
+            [
+                "line 2 doesn't follow on from the line before it",
+                "line 2 is number 2 in the chain, expected 1"
+            ]
+        );
+
+        let mut edited = chain(3);
+        edited[1] = edited[1].replace("\"host\":\"\"", "\"host\":\"edited\"");
+        let summary = verify(edited.concat().as_bytes(), None).unwrap();
+        assert_eq!(
+            summary.problems,
+            ["line 3 doesn't follow on from the line before it"]
+        );
+
+        let mut inserted = chain(2);
+        inserted.insert(1, "{}\n".to_string());
+        let summary = verify(inserted.concat().as_bytes(), None).unwrap();
+        assert_eq!(
+            summary.problems,
+            [
+                "line 2 isn't part of the chain",
+                "line 3 doesn't follow on from the line before it"
+            ]
+        );
+    }
+}
